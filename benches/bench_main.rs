@@ -5,4 +5,5 @@ criterion_main! {
     benchmarks::smoothing_kernel::smoothing_kernel,
     benchmarks::morton::morton,
     benchmarks::neighborhood_search::neighborhood_search,
+    benchmarks::solver::solver,
 }
@@ -1,3 +1,4 @@
 pub mod morton;
 pub mod neighborhood_search;
 pub mod smoothing_kernel;
+pub mod solver;
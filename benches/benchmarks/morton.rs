@@ -11,6 +11,14 @@ fn bench_morton(c: &mut Criterion) {
         // TODO: How to construct a bad case for this? perf is highly input dependent
         c.bench_function("find_bigmin", |b| b.iter(|| find_bigmin(black_box(cur), black_box(min), black_box(max))));
     }
+    {
+        let min = encode64(2, 2);
+        let max = encode64(3, 6);
+        let cur = encode64(4, 0);
+        c.bench_function("find_bigmin64", |b| {
+            b.iter(|| find_bigmin64(black_box(cur), black_box(min), black_box(max)))
+        });
+    }
     {
         let x: u32 = 123;
         let y: u32 = 321;
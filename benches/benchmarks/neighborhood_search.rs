@@ -1,67 +1,166 @@
 use cgmath::prelude::*;
-use criterion::{black_box, criterion_group, Criterion};
+use criterion::{black_box, criterion_group, BenchmarkId, Criterion};
 use rand::prelude::*;
 
-use yasph2d::sph::neighborhood_search::NeighborhoodSearch;
+use yasph2d::sph::neighborhood_search::{CellOrdering, CellStorage, NeighborhoodSearch};
 use yasph2d::sph::scratch_buffer::ScratchBufferStore;
 use yasph2d::units::*;
 
+const NUM_POSITIONS: [usize; 3] = [1000, 5000, 20000];
+const DENSITY: Real = 10.0;
+
+fn random_positions(num_positions: usize) -> Vec<Point> {
+    let mut rng: rand::rngs::SmallRng = rand::SeedableRng::seed_from_u64(123456789);
+    std::iter::repeat_with(|| Point::from_vec(rng.gen::<Vector>() * (num_positions as Real / DENSITY).sqrt()))
+        .take(num_positions)
+        .collect()
+}
+
 fn bench_neighborhood_search(c: &mut Criterion) {
-    const NUM_POSITIONS: usize = 20000;
-    const DENSITY: Real = 10.0;
     let search_radius = black_box(1.0);
 
-    let mut rng: rand::rngs::SmallRng = rand::SeedableRng::seed_from_u64(123456789);
-    let mut positions: Vec<Point> = std::iter::repeat_with(|| Point::from_vec(rng.gen::<Vector>() * (NUM_POSITIONS as Real / DENSITY).sqrt()))
-        .take(NUM_POSITIONS)
-        .collect();
+    let mut group_update = c.benchmark_group(format!(
+        "neighborhood_search.update (warm), {} density, {} search_radius",
+        DENSITY, search_radius
+    ));
+    for num_positions in NUM_POSITIONS.iter() {
+        let mut positions = random_positions(*num_positions);
+        let mut scratch_buffer_store = ScratchBufferStore::new();
+        let mut searcher = NeighborhoodSearch::new(search_radius);
+        searcher.update_particle_neighbors(&mut scratch_buffer_store, &mut positions, &mut [], &mut [], &mut [], &[]);
 
-    let mut scratch_buffer_store = ScratchBufferStore::new();
-    let mut searcher = NeighborhoodSearch::new(search_radius);
-    searcher.update_particle_neighbors(&mut scratch_buffer_store, &mut positions, &mut [], &mut [], &[]);
+        group_update.bench_function(BenchmarkId::from_parameter(num_positions), |b| {
+            b.iter(|| searcher.update_particle_neighbors(&mut scratch_buffer_store, &mut positions, &mut [], &mut [], &mut [], &[]))
+        });
+    }
+    group_update.finish();
 
-    c.bench_function(
-        &format!(
-            "neighborhood_search.update (warm), {} positions, {} density, {} search_radius",
-            NUM_POSITIONS, DENSITY, search_radius
-        ),
-        |b| b.iter(|| searcher.update_particle_neighbors(&mut scratch_buffer_store, &mut positions, &mut [], &mut [], &[])),
-    );
+    let mut group_queries = c.benchmark_group(format!(
+        "neighborhood_search queries, {} density, {} search_radius",
+        DENSITY, search_radius
+    ));
+    for num_positions in NUM_POSITIONS.iter() {
+        let num_positions = *num_positions;
+        let mut positions = random_positions(num_positions);
+        let mut scratch_buffer_store = ScratchBufferStore::new();
+        let mut searcher = NeighborhoodSearch::new(search_radius);
+        searcher.update_particle_neighbors(&mut scratch_buffer_store, &mut positions, &mut [], &mut [], &mut [], &[]);
 
-    c.bench_function(
-        &format!(
-            "neighborhood_search.foreach_potential_neighbor, {} positions, {} density, {} search_radius",
-            NUM_POSITIONS, DENSITY, search_radius
-        ),
-        |b| {
+        group_queries.bench_function(BenchmarkId::new("foreach_potential_neighbor", num_positions), |b| {
             let mut pindex = 0; // cycle through position for a more balanced result
             b.iter(|| {
                 let mut accum: Vector = Zero::zero();
                 searcher.foreach_potential_neighbor(positions[pindex], |i| {
                     accum += positions[i as usize].to_vec();
                 });
-                pindex = (pindex + 1) % NUM_POSITIONS;
+                pindex = (pindex + 1) % num_positions;
                 accum
             })
-        },
-    );
-    c.bench_function(
-        &format!(
-            "neighborhood_search.foreach_neighbor, {} positions, {} density, {} search_radius",
-            NUM_POSITIONS, DENSITY, search_radius
-        ),
-        |b| {
+        });
+        group_queries.bench_function(BenchmarkId::new("foreach_neighbor", num_positions), |b| {
             let mut pindex = 0; // cycle through position for a more balanced result
             b.iter(|| {
                 let mut accum: Vector = Zero::zero();
                 searcher.foreach_neighbor(pindex, |i| {
                     accum += positions[i as usize].to_vec();
                 });
-                pindex = (pindex + 1) % NUM_POSITIONS as u32;
+                pindex = (pindex + 1) % num_positions as u32;
                 accum
             })
-        },
-    );
+        });
+    }
+    group_queries.finish();
+
+    let mut group_orderings = c.benchmark_group(format!(
+        "neighborhood_search cell orderings, {} density, {} search_radius",
+        DENSITY, search_radius
+    ));
+    for num_positions in NUM_POSITIONS.iter() {
+        let num_positions = *num_positions;
+        for &cell_ordering in [CellOrdering::Morton, CellOrdering::Hilbert].iter() {
+            let mut positions = random_positions(num_positions);
+            let mut scratch_buffer_store = ScratchBufferStore::new();
+            let mut searcher = NeighborhoodSearch::new(search_radius).with_cell_ordering(cell_ordering);
+            searcher.update_particle_neighbors(&mut scratch_buffer_store, &mut positions, &mut [], &mut [], &mut [], &[]);
+
+            group_orderings.bench_function(
+                BenchmarkId::new(format!("{:?} foreach_potential_neighbor", cell_ordering), num_positions),
+                |b| {
+                    let mut pindex = 0; // cycle through position for a more balanced result
+                    b.iter(|| {
+                        let mut accum: Vector = Zero::zero();
+                        searcher.foreach_potential_neighbor(positions[pindex], |i| {
+                            accum += positions[i].to_vec();
+                        });
+                        pindex = (pindex + 1) % num_positions;
+                        accum
+                    })
+                },
+            );
+            group_orderings.bench_function(BenchmarkId::new(format!("{:?} update (warm)", cell_ordering), num_positions), |b| {
+                b.iter(|| searcher.update_particle_neighbors(&mut scratch_buffer_store, &mut positions, &mut [], &mut [], &mut [], &[]))
+            });
+        }
+    }
+    group_orderings.finish();
+
+    // Sorted storage also needs to sort particle attributes, use velocities and masses as a fluid world does.
+    let mut group_storages = c.benchmark_group(format!(
+        "neighborhood_search cell storages, {} density, {} search_radius",
+        DENSITY, search_radius
+    ));
+    let cell_storages = [
+        ("Sorted", CellStorage::Sorted),
+        (
+            "FixedCapacity",
+            CellStorage::FixedCapacity {
+                expected_max_density: NumberDensity(DENSITY),
+            },
+        ),
+    ];
+    for num_positions in NUM_POSITIONS.iter() {
+        let num_positions = *num_positions;
+        for &(name, cell_storage) in cell_storages.iter() {
+            let mut positions = random_positions(num_positions);
+            let mut velocities = vec![Vector::zero(); num_positions];
+            let mut masses = vec![1.0; num_positions];
+            let mut scratch_buffer_store = ScratchBufferStore::new();
+            let mut searcher = NeighborhoodSearch::new_with_cell_storage(search_radius, cell_storage);
+            searcher.update_particle_neighbors(
+                &mut scratch_buffer_store,
+                &mut positions,
+                &mut [&mut velocities],
+                &mut [&mut masses],
+                &mut [],
+                &[],
+            );
+
+            group_storages.bench_function(BenchmarkId::new(format!("{} foreach_potential_neighbor", name), num_positions), |b| {
+                let mut pindex = 0; // cycle through position for a more balanced result
+                b.iter(|| {
+                    let mut accum: Vector = Zero::zero();
+                    searcher.foreach_potential_neighbor(positions[pindex], |i| {
+                        accum += positions[i].to_vec();
+                    });
+                    pindex = (pindex + 1) % num_positions;
+                    accum
+                })
+            });
+            group_storages.bench_function(BenchmarkId::new(format!("{} update (warm)", name), num_positions), |b| {
+                b.iter(|| {
+                    searcher.update_particle_neighbors(
+                        &mut scratch_buffer_store,
+                        &mut positions,
+                        &mut [&mut velocities],
+                        &mut [&mut masses],
+                        &mut [],
+                        &[],
+                    )
+                })
+            });
+        }
+    }
+    group_storages.finish();
 }
 
 fn config() -> Criterion {
@@ -0,0 +1,47 @@
+use criterion::{criterion_group, Criterion};
+
+use yasph2d::sph;
+use yasph2d::sph::scenes::DamBreak;
+use yasph2d::sph::smoothing_kernel::CubicSpline;
+use yasph2d::units::{Density, NumberDensity};
+
+fn dam_break_scene() -> sph::FluidParticleWorld {
+    DamBreak::martin_moyce(0.5).create_world(NumberDensity(5000.0), Density(100.0))
+}
+
+fn wcsph_solver(fluid_world: &sph::FluidParticleWorld) -> sph::WCSPHSolver<sph::XSPHViscosityModel> {
+    sph::WCSPHSolver::new(
+        sph::XSPHViscosityModel::new(fluid_world.properties.smoothing_length()),
+        &fluid_world.properties,
+    )
+}
+
+fn bench_solver(c: &mut Criterion) {
+    let mut fluid_world = dam_break_scene();
+    let num_particles = fluid_world.particles.positions.len();
+    let mut solver = wcsph_solver(&fluid_world);
+    let mut time_manager = sph::TimeManager::new(sph::TimeManagerConfiguration::FixedTimeStep(0.001));
+
+    // first step computes all densities & neighborhoods from scratch
+    sph::Solver::simulation_step(&mut solver, &mut fluid_world, &mut time_manager).unwrap();
+
+    let kernel = CubicSpline::new(fluid_world.properties.smoothing_length());
+    c.bench_function(&format!("solver.update_densities, dam break, {} particles", num_particles), |b| {
+        b.iter(|| fluid_world.update_densities(kernel))
+    });
+
+    // Note that the scene keeps evolving while this benchmark runs, with fixed timestep it stays stable though.
+    c.bench_function(&format!("solver.wcsph step, dam break, {} particles", num_particles), |b| {
+        b.iter(|| sph::Solver::simulation_step(&mut solver, &mut fluid_world, &mut time_manager))
+    });
+}
+
+fn config() -> Criterion {
+    Criterion::default().warm_up_time(core::time::Duration::new(0, 1000)).sample_size(20)
+}
+
+criterion_group!(
+    name = solver;
+    config = config();
+    targets = bench_solver
+);
@@ -0,0 +1,67 @@
+/* C interface of YaSPH2D, see src/ffi.rs for how to build the library and for details on every function.
+ *
+ *   YasphSimulation* simulation = yasph_create(5000.0f, 1000.0f, 2.0f, YASPH_SOLVER_DFSPH, 0.0f);
+ *   yasph_add_fluid_rect(simulation, 0.0f, 0.0f, 0.5f, 1.0f);
+ *   yasph_add_boundary_line(simulation, 0.0f, 0.0f, 2.0f, 0.0f, 3);
+ *   while (engine_running) {
+ *       if (yasph_step_until(simulation, engine_time) != YASPH_OK)
+ *           log(yasph_last_error(simulation));
+ *       size_t num_particles = yasph_read_positions(simulation, positions, capacity);
+ *   }
+ *   yasph_destroy(simulation);
+ */
+#ifndef YASPH2D_H
+#define YASPH2D_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define YASPH_OK 0
+#define YASPH_ERROR_INVALID_ARGUMENT 1
+#define YASPH_ERROR_LENGTH_MISMATCH 2
+#define YASPH_ERROR_NON_FINITE 3
+#define YASPH_ERROR_PANIC 4
+
+#define YASPH_SOLVER_DFSPH 0
+#define YASPH_SOLVER_WCSPH 1
+
+typedef struct YasphSimulation YasphSimulation;
+
+/* Returns NULL for an unknown solver or non-positive densities. A fixed_timestep of 0 or less selects adaptive timesteps. */
+YasphSimulation* yasph_create(float particle_density, float fluid_density, float smoothing_factor, int32_t solver, float fixed_timestep);
+void yasph_destroy(YasphSimulation* simulation);
+/* Empty if the last call succeeded, valid until the next call with the same handle. */
+const char* yasph_last_error(const YasphSimulation* simulation);
+
+int32_t yasph_add_fluid_rect(YasphSimulation* simulation, float x, float y, float width, float height);
+/* Writes the number of added particles to num_added_particles, unless it is NULL. */
+int32_t yasph_add_fluid_circle(YasphSimulation* simulation, float center_x, float center_y, float radius, size_t* num_added_particles);
+int32_t yasph_add_fluid_particle(YasphSimulation* simulation, float x, float y, float velocity_x, float velocity_y);
+/* Layers beyond the first go to the right of the line direction, i.e. lines along walls run counterclockwise around the fluid. */
+int32_t yasph_add_boundary_line(YasphSimulation* simulation, float start_x, float start_y, float end_x, float end_y, uint32_t thickness_in_particles);
+int32_t yasph_add_boundary_circle(YasphSimulation* simulation, float center_x, float center_y, float radius);
+void yasph_set_gravity(YasphSimulation* simulation, float x, float y);
+int32_t yasph_relax_initial_state(YasphSimulation* simulation);
+
+int32_t yasph_step(YasphSimulation* simulation);
+int32_t yasph_step_until(YasphSimulation* simulation, float time);
+float yasph_time(const YasphSimulation* simulation);
+float yasph_timestep(const YasphSimulation* simulation);
+float yasph_particle_radius(const YasphSimulation* simulation);
+
+/* Buffers hold 2 * capacity floats, x0, y0, x1, y1, ... Return the number of available particles, of which at most capacity were written. */
+size_t yasph_num_particles(const YasphSimulation* simulation);
+size_t yasph_num_boundary_particles(const YasphSimulation* simulation);
+size_t yasph_read_positions(const YasphSimulation* simulation, float* buffer, size_t capacity);
+size_t yasph_read_velocities(const YasphSimulation* simulation, float* buffer, size_t capacity);
+size_t yasph_read_boundary_positions(const YasphSimulation* simulation, float* buffer, size_t capacity);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
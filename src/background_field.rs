@@ -0,0 +1,183 @@
+use ggez::graphics::{self, Rect};
+use ggez::{Context, GameResult};
+use yasph2d::sph;
+use yasph2d::sph::interpolation::{self, Normalization, SampleGrid};
+use yasph2d::units::*;
+
+use crate::camera::*;
+use crate::clamp;
+use crate::colormap::ColorMap;
+
+// Field that is shown under the particles.
+#[derive(PartialEq, Clone, Copy)]
+pub enum BackgroundField {
+    None,
+    Density,
+    Pressure,
+}
+
+impl BackgroundField {
+    pub fn next(self) -> BackgroundField {
+        match self {
+            BackgroundField::None => BackgroundField::Density,
+            BackgroundField::Density => BackgroundField::Pressure,
+            BackgroundField::Pressure => BackgroundField::None,
+        }
+    }
+}
+
+// Samples SPH fields on a coarse grid over the visible world rectangle and draws them as a heatmap image.
+//
+// Both are SPH interpolations, see sph::interpolation. Density is the plain SPH sum ρ(x) = Σ m W(x - x_j), pressure is Shepard normalized
+// Σ V_j p_j W(x - x_j) / Σ V_j W(x - x_j) since it's only known at particle positions.
+// Regions where the color field Σ V_j W(x - x_j) is low are considered outside the fluid and are not drawn.
+pub struct BackgroundFieldRenderer {
+    pub field: BackgroundField,
+    pub color_map: ColorMap,
+
+    values: Vec<Real>,
+    color_field: Vec<Real>,
+    pixels: Vec<u8>,
+}
+
+// Grid cell size relative to the smoothing length.
+const CELL_SIZE_FACTOR: Real = 0.5;
+// Color field below which grid cells are considered empty.
+const MIN_COLOR_FIELD: Real = 0.3;
+// Density range (relative to rest density) that is mapped onto the color ramp.
+const DENSITY_RANGE: (Real, Real) = (0.9, 1.05);
+
+impl BackgroundFieldRenderer {
+    pub fn new() -> BackgroundFieldRenderer {
+        BackgroundFieldRenderer {
+            field: BackgroundField::None,
+            color_map: ColorMap::heat(),
+
+            values: Vec::new(),
+            color_field: Vec::new(),
+            pixels: Vec::new(),
+        }
+    }
+
+    // Draws the current field. Expects the camera transformation to be active.
+    pub fn draw(&mut self, ctx: &mut Context, camera: &Camera, fluid_world: &sph::FluidParticleWorld, solver: &dyn sph::Solver) -> GameResult {
+        microprofile::scope!("BackgroundFieldRenderer", "draw");
+
+        if self.field == BackgroundField::None {
+            return Ok(());
+        }
+
+        let pressures = if self.field == BackgroundField::Pressure {
+            let pressures = (0..fluid_world.particles.positions.len())
+                .map(|i| solver.particle_pressure(fluid_world, i as sph::neighborhood_search::ParticleIndex))
+                .collect::<Option<Vec<Real>>>();
+            match pressures {
+                Some(pressures) => Some(pressures),
+                None => return Ok(()), // solver doesn't know about pressure
+            }
+        } else {
+            None
+        };
+
+        let world_rect = camera.visible_world_rect();
+        let cell_size = fluid_world.properties.smoothing_length() * CELL_SIZE_FACTOR;
+        let (width, height) = self.sample(fluid_world, world_rect, cell_size, pressures.as_deref());
+        if width == 0 || height == 0 {
+            return Ok(());
+        }
+        let range = match pressures {
+            Some(pressures) => (0.0, pressures.iter().cloned().fold(0.0, Real::max)),
+            None => (
+                DENSITY_RANGE.0 * fluid_world.properties.fluid_density(),
+                DENSITY_RANGE.1 * fluid_world.properties.fluid_density(),
+            ),
+        };
+        self.shade(range);
+
+        let mut image = graphics::Image::from_rgba8(ctx, width as u16, height as u16, &self.pixels)?;
+        image.set_filter(graphics::FilterMode::Linear);
+        graphics::draw(
+            ctx,
+            &image,
+            graphics::DrawParam::default()
+                .dest(RenderPoint::new(world_rect.x, world_rect.y))
+                .scale(RenderSize::new(cell_size, cell_size)),
+        )
+    }
+
+    // Splats all particles into the grid, sampled at cell centers. Returns the grid size.
+    fn sample(&mut self, fluid_world: &sph::FluidParticleWorld, world_rect: Rect, cell_size: Real, pressures: Option<&[Real]>) -> (usize, usize) {
+        microprofile::scope!("BackgroundFieldRenderer", "sample");
+
+        let width = ((world_rect.w / cell_size).ceil() as usize).min(u16::MAX as usize);
+        let height = ((world_rect.h / cell_size).ceil() as usize).min(u16::MAX as usize);
+        let grid = SampleGrid {
+            origin: Point::new(world_rect.x, world_rect.y) + Vector::new(0.5, 0.5) * cell_size,
+            spacing: cell_size,
+            size: (width, height),
+        };
+        match pressures {
+            Some(pressures) => interpolation::splat_to_grid(
+                fluid_world,
+                &grid,
+                |j| pressures[j],
+                Normalization::Shepard,
+                &mut self.values,
+                &mut self.color_field,
+            ),
+            None => {
+                let particles = &fluid_world.particles;
+                let fluid_density = fluid_world.properties.fluid_density();
+                // densities of particles added since the last step aren't known yet
+                let density = |j| {
+                    particles
+                        .densities
+                        .get(j)
+                        .cloned()
+                        .filter(|density| *density > 0.0)
+                        .unwrap_or(fluid_density)
+                };
+                interpolation::splat_to_grid(fluid_world, &grid, density, Normalization::None, &mut self.values, &mut self.color_field)
+            }
+        }
+
+        (width, height)
+    }
+
+    fn shade(&mut self, range: (Real, Real)) {
+        microprofile::scope!("BackgroundFieldRenderer", "shade");
+
+        self.pixels.clear();
+        self.pixels.reserve(self.values.len() * 4);
+        for (value, color_field) in self.values.iter().zip(self.color_field.iter()) {
+            let color = self.color_map.sample((value - range.0) / (range.1 - range.0).max(f32::EPSILON));
+            let alpha = clamp(color_field / MIN_COLOR_FIELD - 1.0, 0.0, 1.0);
+            self.pixels.push((color.r * 255.0) as u8);
+            self.pixels.push((color.g * 255.0) as u8);
+            self.pixels.push((color.b * 255.0) as u8);
+            self.pixels.push((alpha * 255.0) as u8);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sampled_density_inside_fluid_is_close_to_rest_density() {
+        let mut fluid_world = sph::FluidParticleWorld::new(2.0, NumberDensity(10000.0), Density(100.0));
+        fluid_world.add_fluid_rect(&Rect::new(0.0, 0.0, 1.0, 1.0), 0.0);
+        let mut renderer = BackgroundFieldRenderer::new();
+        let cell_size = 0.05;
+        let (width, height) = renderer.sample(&fluid_world, Rect::new(0.0, 0.0, 2.0, 1.0), cell_size, None);
+        assert_eq!((width, height), (40, 20));
+
+        let center = 10 * width + 10;
+        assert!((renderer.values[center] / fluid_world.properties.fluid_density() - 1.0).abs() < 0.05);
+        assert!(renderer.color_field[center] > MIN_COLOR_FIELD);
+        let outside = 10 * width + 30;
+        assert_eq!(renderer.values[outside], 0.0);
+        assert_eq!(renderer.color_field[outside], 0.0);
+    }
+}
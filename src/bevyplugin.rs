@@ -0,0 +1,200 @@
+// Integration into the Bevy game engine (https://bevyengine.org), only with the "bevy-plugin" feature.
+// See src/bin/bevy_viewer.rs for a complete app.
+//
+//   App::new()
+//       .add_plugins((DefaultPlugins, SphPlugin::default()))
+//       .add_systems(Startup, |mut commands: Commands| {
+//           commands.spawn((SphFluidRect { size: Vec2::new(0.5, 1.0) }, Transform::from_xyz(0.05, 0.05, 0.0)));
+//           commands.spawn((SphBoundary::Line { start: Vec2::ZERO, end: Vec2::new(2.0, 0.0), thickness_in_particles: 3 }, Transform::default()));
+//       })
+//       .run();
+//
+// Bevy units are meters, with y up. Shapes of the components are relative to the translation of the entity's Transform,
+// rotation and scale are ignored. Fluid and boundaries are added to the simulation once when their component is added,
+// despawning them doesn't remove anything. The simulation is a non-send resource, since the step & particle hooks of
+// sph::Simulation aren't Send, e.g. so that they can share state with the app through an Rc:
+//
+//   fn draw(sph: NonSend<SphSimulation>, mut gizmos: Gizmos) { for p in sph.simulation.fluid_world.particles.positions.iter() { ... } }
+
+use crate::sph;
+// not all of units, its Time would clash with Bevy's
+use crate::units::{Density, NumberDensity, Point, Real, Rect, Vector};
+use bevy::prelude::*;
+
+// Simulation stepped along with Bevy's virtual time by SphPlugin.
+pub struct SphSimulation {
+    pub simulation: sph::Simulation,
+    // If the simulation can't keep up, it falls behind by at most this many steps per frame and the remaining time is skipped.
+    pub max_steps_per_frame: usize,
+    pub error: Option<sph::SphError>, // of a failed step, no more steps are taken once there is one
+
+    skipped_time: Real, // in s, time lost because the simulation couldn't keep up
+}
+
+impl SphSimulation {
+    pub fn new(simulation: sph::Simulation) -> SphSimulation {
+        SphSimulation {
+            simulation,
+            max_steps_per_frame: 50,
+            error: None,
+            skipped_time: 0.0,
+        }
+    }
+}
+
+// Fluid particles on a lattice filling a rectangle, whose bottom left corner is at the entity's translation.
+#[derive(Component, Clone, Debug)]
+pub struct SphFluidRect {
+    pub size: Vec2, // in m
+}
+
+// Static boundary.
+#[derive(Component, Clone, Debug)]
+pub enum SphBoundary {
+    // Layers beyond the first go to the right of the line direction, see FluidParticleWorld::add_boundary_thick_line.
+    Line { start: Vec2, end: Vec2, thickness_in_particles: u32 },
+    Circle { radius: Real },
+    // Closed polygon, vertices counterclockwise.
+    Polygon(Vec<Vec2>),
+}
+
+// Inlet that emits fluid into the simulation, see sph::Inlet. Its line is relative to the entity's translation.
+#[derive(Component)]
+pub struct SphEmitter {
+    pub inlet: sph::Inlet,
+    pub enabled: bool,
+}
+
+pub struct SphPlugin {
+    pub smoothing_factor: Real,
+    pub particle_density: NumberDensity,
+    pub fluid_density: Density,
+}
+
+impl Default for SphPlugin {
+    // Same as the default viewer.
+    fn default() -> Self {
+        SphPlugin {
+            smoothing_factor: 2.0,
+            particle_density: NumberDensity(5000.0),
+            fluid_density: Density(1000.0),
+        }
+    }
+}
+
+impl Plugin for SphPlugin {
+    fn build(&self, app: &mut App) {
+        let fluid_world = sph::FluidParticleWorld::new(self.smoothing_factor, self.particle_density, self.fluid_density);
+        let smoothing_length = fluid_world.properties.smoothing_length();
+        let solver = sph::DFSPHSolver::new(sph::XSPHViscosityModel::new(smoothing_length), smoothing_length);
+        let time_manager = sph::TimeManager::new(sph::TimeManagerConfiguration::AdaptiveTimeStep {
+            timestep_max: 1.0 / 240.0,
+            timestep_min: 1.0 / (400.0 * 60.0),
+            timestep_target_frame: sph::AdaptiveTimeStepTarget::None,
+            cfl_factor: 1.0,
+        });
+        app.insert_non_send_resource(SphSimulation::new(sph::Simulation::new(fluid_world, Box::new(solver), time_manager)))
+            .add_systems(Update, (add_fluid, add_boundaries, step_simulation).chain());
+    }
+}
+
+fn point(v: Vec2) -> Point {
+    Point::new(v.x, v.y)
+}
+
+fn origin(transform: &Transform) -> Vec2 {
+    transform.translation.truncate()
+}
+
+fn add_fluid(fluid_rects: Query<(&SphFluidRect, &Transform), Added<SphFluidRect>>, mut sph: NonSendMut<SphSimulation>) {
+    for (fluid_rect, transform) in fluid_rects.iter() {
+        let origin = origin(transform);
+        let rect = Rect::new(origin.x, origin.y, fluid_rect.size.x, fluid_rect.size.y);
+        sph.simulation.fluid_world.add_fluid_rect(&rect, 0.0);
+    }
+}
+
+fn add_boundaries(boundaries: Query<(&SphBoundary, &Transform), Added<SphBoundary>>, mut sph: NonSendMut<SphSimulation>) {
+    let fluid_world = &mut sph.simulation.fluid_world;
+    for (boundary, transform) in boundaries.iter() {
+        let origin = origin(transform);
+        match boundary {
+            SphBoundary::Line {
+                start,
+                end,
+                thickness_in_particles,
+            } => fluid_world.add_boundary_thick_line(point(origin + *start), point(origin + *end), (*thickness_in_particles).max(1)),
+            SphBoundary::Circle { radius } => fluid_world.add_boundary_circle(point(origin), *radius),
+            SphBoundary::Polygon(vertices) => {
+                let vertices: Vec<Point> = vertices.iter().map(|v| point(origin + *v)).collect();
+                fluid_world.add_boundary_polygon(&vertices);
+            }
+        }
+    }
+}
+
+fn step_simulation(time: Res<Time>, emitters: Query<(&SphEmitter, &Transform)>, mut sph: NonSendMut<SphSimulation>) {
+    if sph.error.is_some() {
+        return;
+    }
+    let inlets: Vec<sph::Inlet> = emitters
+        .iter()
+        .filter(|(emitter, _)| emitter.enabled)
+        .map(|(emitter, transform)| {
+            let offset = origin(transform);
+            let offset = Vector::new(offset.x, offset.y);
+            let inlet = &emitter.inlet;
+            sph::Inlet::new(inlet.start + offset, inlet.end + offset, inlet.depth, inlet.profile)
+        })
+        .collect();
+
+    let sph = &mut *sph;
+    let target_time = time.elapsed_seconds() - sph.skipped_time;
+    let mut num_steps = 0;
+    while sph.simulation.time_manager.passed_time() < target_time {
+        if num_steps == sph.max_steps_per_frame {
+            sph.skipped_time += target_time - sph.simulation.time_manager.passed_time();
+            break;
+        }
+        for inlet in inlets.iter() {
+            inlet.update(&mut sph.simulation.fluid_world);
+        }
+        if let Err(err) = sph.simulation.step() {
+            error!("Simulation halted: {}", err);
+            sph.error = Some(err);
+            break;
+        }
+        num_steps += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn plugin_adds_and_steps_the_scene() {
+        let mut app = App::new();
+        app.insert_resource(Time::<()>::default()).add_plugins(SphPlugin::default());
+        app.world
+            .spawn((SphFluidRect { size: Vec2::new(0.2, 0.2) }, Transform::from_xyz(0.02, 0.02, 0.0)));
+        app.world.spawn((
+            SphBoundary::Line {
+                start: Vec2::ZERO,
+                end: Vec2::new(0.4, 0.0),
+                thickness_in_particles: 2,
+            },
+            Transform::default(),
+        ));
+
+        app.world.resource_mut::<Time>().advance_by(Duration::from_millis(50));
+        app.update();
+
+        let sph = app.world.non_send_resource::<SphSimulation>();
+        assert_gt!(sph.simulation.fluid_world.particles.positions.len(), 100);
+        assert_gt!(sph.simulation.fluid_world.particles.boundary_particles.len(), 0);
+        assert_ge!(sph.simulation.time_manager.passed_time(), 0.05);
+        assert!(sph.error.is_none());
+    }
+}
@@ -0,0 +1,75 @@
+// Dam break in a tank with an emitter pouring in from the side, running in Bevy via yasph2d::bevyplugin::SphPlugin.
+// Particles are drawn with gizmos, colored by speed.
+//
+//   cargo run --release --features bevy-plugin --bin bevy_viewer
+
+use bevy::prelude::*;
+use yasph2d::bevyplugin::*;
+use yasph2d::sph;
+use yasph2d::units::Point;
+
+const MAX_SPEED_COLOR: f32 = 3.0; // speed at which particles are drawn entirely white
+
+fn setup(mut commands: Commands) {
+    let mut camera = Camera2dBundle::default();
+    camera.projection.scale = 1.0 / 400.0; // 400 pixels per meter
+    camera.transform.translation.x = 1.0;
+    camera.transform.translation.y = 0.6;
+    commands.spawn(camera);
+
+    commands.spawn((SphFluidRect { size: Vec2::new(0.5, 0.8) }, Transform::from_xyz(0.02, 0.02, 0.0)));
+    // counterclockwise around the fluid, so that the walls grow outwards
+    commands.spawn((
+        SphBoundary::Line {
+            start: Vec2::new(0.0, 1.2),
+            end: Vec2::new(0.0, 0.0),
+            thickness_in_particles: 3,
+        },
+        Transform::default(),
+    ));
+    commands.spawn((
+        SphBoundary::Line {
+            start: Vec2::new(0.0, 0.0),
+            end: Vec2::new(2.0, 0.0),
+            thickness_in_particles: 3,
+        },
+        Transform::default(),
+    ));
+    commands.spawn((
+        SphBoundary::Line {
+            start: Vec2::new(2.0, 0.0),
+            end: Vec2::new(2.0, 1.2),
+            thickness_in_particles: 3,
+        },
+        Transform::default(),
+    ));
+    commands.spawn((SphBoundary::Circle { radius: 0.1 }, Transform::from_xyz(1.3, 0.2, 0.0)));
+    // flows to the left, from the right wall
+    commands.spawn((
+        SphEmitter {
+            inlet: sph::Inlet::new(Point::new(0.0, 0.0), Point::new(0.0, -0.1), 0.05, sph::InflowProfile::Uniform(1.0)),
+            enabled: true,
+        },
+        Transform::from_xyz(1.9, 1.0, 0.0),
+    ));
+}
+
+fn draw_particles(sph: NonSend<SphSimulation>, mut gizmos: Gizmos) {
+    let particles = &sph.simulation.fluid_world.particles;
+    let radius = sph.simulation.fluid_world.properties.particle_radius();
+    for (p, v) in particles.positions.iter().zip(particles.velocities.iter()) {
+        let t = ((v.x * v.x + v.y * v.y).sqrt() / MAX_SPEED_COLOR).min(1.0);
+        gizmos.circle_2d(Vec2::new(p.x, p.y), radius, Color::rgb(0.1 + 0.9 * t, 0.3 + 0.7 * t, 1.0));
+    }
+    for p in particles.boundary_particles.iter() {
+        gizmos.circle_2d(Vec2::new(p.x, p.y), radius, Color::DARK_GRAY);
+    }
+}
+
+fn main() {
+    App::new()
+        .add_plugins((DefaultPlugins, SphPlugin::default()))
+        .add_systems(Startup, setup)
+        .add_systems(Update, draw_particles)
+        .run();
+}
@@ -0,0 +1,70 @@
+// Compares two trajectory recordings of the same scene (see sph::TrajectoryWriter, recorded in the viewer with J),
+// e.g. before and after a change that should only affect performance.
+//
+//   cargo run --release --bin trajectory_diff -- before.ysph after.ysph [--nearest] [--threshold 0.001] > diff.csv
+//
+// Prints per frame position differences as csv and a summary to stderr.
+// Exits with 0 if the runs stayed within the threshold (in m, rms over all particles), 1 if they diverged and 2 on errors.
+
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::process;
+use yasph2d::sph;
+use yasph2d::units::Real;
+
+const USAGE: &str = "usage: trajectory_diff <a.ysph> <b.ysph> [--nearest] [--threshold <m>]";
+
+fn parse_args() -> Result<(String, String, sph::TrajectoryDiffOptions), String> {
+    let mut options = sph::TrajectoryDiffOptions::default();
+    let mut paths = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--nearest" => options.matching = sph::ParticleMatching::Nearest,
+            "--threshold" => {
+                let threshold = args.next().ok_or("--threshold needs a value")?;
+                options.divergence_threshold = threshold.parse().map_err(|_| format!("invalid threshold {}", threshold))?;
+            }
+            _ => paths.push(arg),
+        }
+    }
+    match paths.len() {
+        2 => {
+            let b = paths.pop().unwrap();
+            let a = paths.pop().unwrap();
+            Ok((a, b, options))
+        }
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn open(path: &str) -> io::Result<sph::TrajectoryReader<BufReader<File>>> {
+    sph::TrajectoryReader::new(BufReader::new(File::open(path)?))
+}
+
+fn run() -> Result<bool, String> {
+    let (a, b, options) = parse_args()?;
+    let reader_a = open(&a).map_err(|err| format!("failed to open {}: {}", a, err))?;
+    let reader_b = open(&b).map_err(|err| format!("failed to open {}: {}", b, err))?;
+    let diff = sph::diff_trajectories(reader_a, reader_b, &options).map_err(|err| format!("failed to read trajectories: {}", err))?;
+    diff.write_csv(&mut io::stdout().lock()).map_err(|err| err.to_string())?;
+
+    let max_rms_distance = diff.frames.iter().map(|frame| frame.rms_distance).fold(0.0, Real::max);
+    eprintln!("compared {} frames, max rms distance {}m", diff.frames.len(), max_rms_distance);
+    match diff.divergence_time {
+        Some(time) => eprintln!("diverged at {}s", time),
+        None => eprintln!("no divergence beyond {}m", options.divergence_threshold),
+    }
+    Ok(diff.divergence_time.is_none())
+}
+
+fn main() {
+    match run() {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(2);
+        }
+    }
+}
@@ -0,0 +1,186 @@
+use std::convert::Infallible;
+
+use bytemuck::Zeroable;
+use cgmath::{Matrix4, Vector4};
+use wgpu::util::DeviceExt;
+use yasph2d::units::*;
+
+use crate::camera::Camera;
+use crate::particle_renderer::*;
+
+// Everything needed to record draw calls for the current frame.
+pub struct Frame<'a> {
+    pub device: &'a wgpu::Device,
+    pub queue: &'a wgpu::Queue,
+    pub encoder: &'a mut wgpu::CommandEncoder,
+    pub view: &'a wgpu::TextureView,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct Uniforms {
+    world_to_clip: [[f32; 4]; 4],
+    radius: f32,
+    _padding: [f32; 3],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct Instance {
+    position: [f32; 2],
+    color: Rgba,
+}
+
+// Anti-aliased circles, all particles are drawn with a single instanced draw call.
+pub struct InstancedCircleRenderer {
+    pipeline: wgpu::RenderPipeline,
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    instance_buffer: wgpu::Buffer,
+    instance_capacity: usize,
+}
+
+impl InstancedCircleRenderer {
+    pub fn new(device: &wgpu::Device, target_format: wgpu::TextureFormat) -> InstancedCircleRenderer {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("circles.wgsl"));
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("circle uniforms"),
+            contents: bytemuck::bytes_of(&Uniforms::zeroed()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("circles"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("circles"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("circles"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("circles"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<Instance>() as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Instance,
+                    attributes: &wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x4],
+                }],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: target_format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleStrip,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        InstancedCircleRenderer {
+            pipeline,
+            uniform_buffer,
+            bind_group,
+            instance_buffer: Self::create_instance_buffer(device, 1),
+            instance_capacity: 1,
+        }
+    }
+
+    fn create_instance_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("circle instances"),
+            size: (capacity * std::mem::size_of::<Instance>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+}
+
+impl<'a> ParticleRenderer<Frame<'a>> for InstancedCircleRenderer {
+    type Error = Infallible;
+
+    fn name(&self) -> &'static str {
+        "instanced circles"
+    }
+
+    // Buffers are only written once the queue is submitted, so this may be called only once per frame.
+    fn draw(&mut self, frame: &mut Frame<'a>, camera: &Camera, positions: &[Point], colors: &[Rgba], radius: Real) -> Result<(), Infallible> {
+        if positions.is_empty() {
+            return Ok(());
+        }
+        if positions.len() > self.instance_capacity {
+            self.instance_capacity = positions.len().next_power_of_two();
+            self.instance_buffer = Self::create_instance_buffer(frame.device, self.instance_capacity);
+        }
+        let instances: Vec<Instance> = positions
+            .iter()
+            .zip(colors.iter())
+            .map(|(position, color)| Instance {
+                position: [position.x, position.y],
+                color: *color,
+            })
+            .collect();
+        frame.queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&instances));
+
+        // camera maps to pixels with y down, clip space is [-1, 1]² with y up
+        let screen_to_clip = Matrix4::from_cols(
+            Vector4::new(2.0 / camera.screen.w, 0.0, 0.0, 0.0),
+            Vector4::new(0.0, -2.0 / camera.screen.h, 0.0, 0.0),
+            Vector4::new(0.0, 0.0, 1.0, 0.0),
+            Vector4::new(-1.0, 1.0, 0.0, 1.0),
+        );
+        let uniforms = Uniforms {
+            world_to_clip: (screen_to_clip * camera.transformation_matrix()).into(),
+            radius,
+            _padding: [0.0; 3],
+        };
+        frame.queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniforms));
+
+        let mut pass = frame.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("circles"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: frame.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.set_vertex_buffer(0, self.instance_buffer.slice(..));
+        pass.draw(0..4, 0..instances.len() as u32);
+        Ok(())
+    }
+}
@@ -0,0 +1,35 @@
+// Particles as instanced screen aligned quads, cut to anti-aliased circles in the fragment shader.
+
+struct Uniforms {
+    world_to_clip: mat4x4<f32>,
+    radius: f32,
+}
+
+@group(0) @binding(0) var<uniform> uniforms: Uniforms;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) offset: vec2<f32>, // from the circle center in radii
+    @location(1) color: vec4<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32, @location(0) center: vec2<f32>, @location(1) color: vec4<f32>) -> VertexOutput {
+    // triangle strip
+    let offset = vec2<f32>(f32(vertex_index & 1u), f32(vertex_index >> 1u)) * 2.0 - 1.0;
+    var out: VertexOutput;
+    out.position = uniforms.world_to_clip * vec4<f32>(center + offset * uniforms.radius, 0.0, 1.0);
+    out.offset = offset;
+    out.color = color;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let distance = length(in.offset);
+    let alpha = clamp((1.0 - distance) / fwidth(distance), 0.0, 1.0);
+    if alpha <= 0.0 {
+        discard;
+    }
+    return vec4<f32>(in.color.rgb, in.color.a * alpha);
+}
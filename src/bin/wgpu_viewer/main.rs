@@ -0,0 +1,284 @@
+// Minimal viewer built directly on winit + wgpu, as an alternative to the ggez based default viewer.
+// Shows a Martin & Moyce dam break, colored by speed.
+//
+//   cargo run --release --features wgpu-viewer --bin wgpu_viewer
+//
+// Also runs in the browser on WebGL2, with the simulation on the page's main thread, using wasm-bindgen (https://rustwasm.github.io/wasm-bindgen/):
+//
+//   cargo build --release --target wasm32-unknown-unknown --features web --bin wgpu_viewer
+//   wasm-bindgen --target web --out-dir web/pkg target/wasm32-unknown-unknown/release/wgpu_viewer.wasm
+//   python3 -m http.server --directory web
+//
+// Controls: mouse wheel zooms, right mouse button pans, space restarts, escape quits.
+
+use std::sync::Arc;
+use std::time::Duration;
+// std::time::Instant panics in the browser
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
+
+use cgmath::prelude::*;
+use winit::event::{ElementState, Event, MouseButton, MouseScrollDelta, WindowEvent};
+use winit::event_loop::{EventLoop, EventLoopWindowTarget};
+use winit::keyboard::{Key, NamedKey};
+use winit::window::{Window, WindowBuilder};
+use yasph2d::sph;
+use yasph2d::units::*;
+
+#[path = "../../camera.rs"]
+mod camera;
+mod circles;
+#[path = "../../renderer/particle_renderer.rs"]
+mod particle_renderer;
+
+use camera::*;
+use circles::{Frame, InstancedCircleRenderer};
+use particle_renderer::*;
+
+const BACKGROUND_COLOR: wgpu::Color = wgpu::Color {
+    r: 0.4,
+    g: 0.4,
+    b: 0.45,
+    a: 1.0,
+};
+const BOUNDARY_COLOR: Rgba = [0.2, 0.2, 0.2, 1.0];
+const MAX_SPEED_COLOR: Real = 3.0; // speed at which particles are drawn entirely white
+
+// If we can't simulate in real time, we don't spend more than this per frame and accept that the simulation runs slower.
+const MAX_PROCESSING_TIME_PER_FRAME: Duration = Duration::from_millis(30);
+
+// Steps the simulation along with real time.
+struct RealtimeSimulation {
+    simulation: sph::Simulation,
+    start_time: Instant,
+    realtime_offset: Real, // simulation time lost because we couldn't keep up
+    halted: bool,          // a step failed, the particles stay where they were
+}
+
+impl RealtimeSimulation {
+    fn new() -> RealtimeSimulation {
+        // same 2D slab of 10cm water as in the default viewer
+        let mut fluid_world = sph::scenes::DamBreak::martin_moyce(0.5).create_world(NumberDensity(5000.0), Density(100.0));
+        if let Err(err) = fluid_world.relax_initial_state() {
+            println!("Failed to relax initial state: {}", err);
+        }
+        let smoothing_length = fluid_world.properties.smoothing_length();
+        let solver = sph::DFSPHSolver::new(sph::XSPHViscosityModel::new(smoothing_length), smoothing_length);
+        let time_manager = sph::TimeManager::new(sph::TimeManagerConfiguration::AdaptiveTimeStep {
+            timestep_max: 1.0 / 240.0,
+            timestep_min: 1.0 / (400.0 * 60.0),
+            timestep_target_frame: sph::AdaptiveTimeStepTarget::None,
+            cfl_factor: 1.0,
+        });
+        RealtimeSimulation {
+            simulation: sph::Simulation::new(fluid_world, Box::new(solver), time_manager),
+            start_time: Instant::now(),
+            realtime_offset: 0.0,
+            halted: false,
+        }
+    }
+
+    fn step_to_realtime(&mut self) {
+        let frame_start = Instant::now();
+        let target_time = self.start_time.elapsed().as_secs_f32() - self.realtime_offset;
+        while !self.halted && self.simulation.time_manager.passed_time() < target_time {
+            if frame_start.elapsed() > MAX_PROCESSING_TIME_PER_FRAME {
+                self.realtime_offset += target_time - self.simulation.time_manager.passed_time();
+                break;
+            }
+            if let Err(err) = self.simulation.step() {
+                println!("Simulation halted: {}", err);
+                self.halted = true;
+                break;
+            }
+        }
+    }
+
+    fn particle_colors(&self) -> Vec<Rgba> {
+        let particles = &self.simulation.fluid_world.particles;
+        let mut colors: Vec<Rgba> = particles
+            .velocities
+            .iter()
+            .map(|velocity| {
+                let t = (velocity.magnitude() / MAX_SPEED_COLOR).min(1.0);
+                [0.1 + 0.9 * t, 0.3 + 0.7 * t, 1.0, 1.0]
+            })
+            .collect();
+        colors.resize(colors.len() + particles.boundary_particles.len(), BOUNDARY_COLOR);
+        colors
+    }
+}
+
+struct Graphics {
+    window: Arc<Window>,
+    surface: wgpu::Surface<'static>,
+    surface_config: wgpu::SurfaceConfiguration,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+}
+
+impl Graphics {
+    async fn new(window: Arc<Window>) -> Graphics {
+        let instance = wgpu::Instance::default();
+        let surface = instance.create_surface(window.clone()).expect("Failed to create surface");
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                compatible_surface: Some(&surface),
+                ..Default::default()
+            })
+            .await
+            .expect("No suitable graphics adapter found");
+        // the circle renderer needs nothing beyond WebGL2, whose limits are below wgpu's defaults
+        let device_descriptor = wgpu::DeviceDescriptor {
+            required_limits: wgpu::Limits::downlevel_webgl2_defaults().using_resolution(adapter.limits()),
+            ..Default::default()
+        };
+        let (device, queue) = adapter.request_device(&device_descriptor, None).await.expect("Failed to create device");
+        let size = window.inner_size();
+        let surface_config = surface
+            .get_default_config(&adapter, size.width.max(1), size.height.max(1))
+            .expect("Surface is not supported by the adapter");
+        surface.configure(&device, &surface_config);
+
+        Graphics {
+            window,
+            surface,
+            surface_config,
+            device,
+            queue,
+        }
+    }
+
+    fn resize(&mut self, width: u32, height: u32) {
+        self.surface_config.width = width.max(1);
+        self.surface_config.height = height.max(1);
+        self.surface.configure(&self.device, &self.surface_config);
+    }
+}
+
+fn screen_rect(window: &Window) -> Rect {
+    let size = window.inner_size();
+    Rect::new(0.0, 0.0, size.width.max(1) as f32, size.height.max(1) as f32)
+}
+
+fn draw(graphics: &Graphics, renderer: &mut InstancedCircleRenderer, camera: &Camera, simulation: &RealtimeSimulation) {
+    let surface_texture = match graphics.surface.get_current_texture() {
+        Ok(surface_texture) => surface_texture,
+        Err(wgpu::SurfaceError::Lost) | Err(wgpu::SurfaceError::Outdated) => {
+            graphics.surface.configure(&graphics.device, &graphics.surface_config);
+            return;
+        }
+        Err(error) => {
+            println!("Failed to acquire next frame: {}", error);
+            return;
+        }
+    };
+    let view = surface_texture.texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let mut encoder = graphics.device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("clear"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: &view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(BACKGROUND_COLOR),
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        ..Default::default()
+    });
+
+    // fluid and boundary particles in a single draw, see InstancedCircleRenderer::draw
+    let particles = &simulation.simulation.fluid_world.particles;
+    let positions: Vec<Point> = particles.positions.iter().chain(particles.boundary_particles.iter()).cloned().collect();
+    let mut frame = Frame {
+        device: &graphics.device,
+        queue: &graphics.queue,
+        encoder: &mut encoder,
+        view: &view,
+    };
+    let radius = simulation.simulation.fluid_world.properties.particle_radius();
+    renderer
+        .draw(&mut frame, camera, &positions, &simulation.particle_colors(), radius)
+        .unwrap();
+
+    graphics.queue.submit(Some(encoder.finish()));
+    surface_texture.present();
+}
+
+fn main() {
+    #[cfg(not(target_arch = "wasm32"))]
+    pollster::block_on(run());
+
+    // the browser doesn't allow blocking, run returns once the event loop is set up
+    #[cfg(target_arch = "wasm32")]
+    {
+        std::panic::set_hook(Box::new(console_error_panic_hook::hook));
+        wasm_bindgen_futures::spawn_local(run());
+    }
+}
+
+async fn run() {
+    let event_loop = EventLoop::new().expect("Failed to create event loop");
+    let window_builder = WindowBuilder::new()
+        .with_title("YaSPH2D")
+        .with_inner_size(winit::dpi::LogicalSize::new(1280.0, 720.0));
+    #[cfg(target_arch = "wasm32")]
+    let window_builder = winit::platform::web::WindowBuilderExtWebSys::with_append(window_builder, true);
+    let window = Arc::new(window_builder.build(&event_loop).expect("Failed to create window"));
+    let mut graphics = Graphics::new(window.clone()).await;
+    let mut renderer = InstancedCircleRenderer::new(&graphics.device, graphics.surface_config.format);
+    window.set_title(&format!("YaSPH2D - wgpu, {}", renderer.name()));
+    let mut camera = Camera::center_around_world_rect(screen_rect(&window), Rect::new(-0.1, -0.1, 2.7, 1.7));
+    let mut simulation = RealtimeSimulation::new();
+
+    let mut cursor_position = RenderPoint::new(0.0, 0.0);
+    let mut panning = false;
+    let event_handler = move |event: Event<()>, target: &EventLoopWindowTarget<()>| match event {
+        Event::WindowEvent { event, .. } => match event {
+            WindowEvent::CloseRequested => target.exit(),
+            WindowEvent::KeyboardInput { event, .. } if event.state == ElementState::Pressed => match event.logical_key {
+                Key::Named(NamedKey::Escape) => target.exit(),
+                Key::Named(NamedKey::Space) => simulation = RealtimeSimulation::new(),
+                _ => {}
+            },
+            WindowEvent::Resized(size) => {
+                graphics.resize(size.width, size.height);
+                camera.resize_screen(screen_rect(&window));
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                let new_position = RenderPoint::new(position.x as f32, position.y as f32);
+                if panning {
+                    camera.pan(new_position - cursor_position);
+                }
+                cursor_position = new_position;
+            }
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Right,
+                ..
+            } => panning = state == ElementState::Pressed,
+            WindowEvent::MouseWheel { delta, .. } => {
+                let lines = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 50.0,
+                };
+                camera.zoom_around_screen_point(cursor_position, 1.1_f32.powf(lines));
+            }
+            WindowEvent::RedrawRequested => {
+                simulation.step_to_realtime();
+                draw(&graphics, &mut renderer, &camera, &simulation);
+            }
+            _ => {}
+        },
+        Event::AboutToWait => graphics.window.request_redraw(),
+        _ => {}
+    };
+
+    #[cfg(not(target_arch = "wasm32"))]
+    event_loop.run(event_handler).expect("Event loop failed");
+    #[cfg(target_arch = "wasm32")]
+    winit::platform::web::EventLoopExtWebSys::spawn(event_loop, event_handler);
+}
@@ -1,6 +1,6 @@
 use cgmath::prelude::*;
 use cgmath::{Matrix4, Vector2, Vector4};
-use ggez::graphics::Rect;
+use yasph2d::units::Rect;
 
 pub type RenderPoint = cgmath::Point2<f32>;
 pub type RenderSize = cgmath::Vector2<f32>;
@@ -34,6 +34,18 @@ impl Camera {
         }
     }
 
+    // Changes the screen rectangle while keeping everything that was visible before visible.
+    // Uniform scaling is maintained, i.e. aspect ratio changes of the screen don't distort the world.
+    pub fn resize_screen(&mut self, screen: Rect) {
+        *self = Camera::center_around_world_rect(screen, self.visible_world_rect());
+    }
+
+    // World rectangle that is currently visible on screen.
+    pub fn visible_world_rect(&self) -> Rect {
+        let extent = RenderSize::new(self.screen.w, self.screen.h.abs()) / self.pixel_per_world_unit;
+        Rect::new(self.position.x - extent.x * 0.5, self.position.y - extent.y * 0.5, extent.x, extent.y)
+    }
+
     #[allow(dead_code)]
     pub fn world_unit_scale(&self) -> RenderSize {
         RenderSize::new(self.pixel_per_world_unit, self.pixel_per_world_unit)
@@ -50,6 +62,28 @@ impl Camera {
         )
     }
 
+    pub fn screen_to_world_coords(&self, screen_pos: RenderPoint) -> RenderPoint {
+        let from_screen_center = RenderSize::new(
+            screen_pos.x - self.screen.x - self.screen.w * 0.5,
+            self.screen.y + self.screen.h * 0.5 - screen_pos.y,
+        );
+        self.position + from_screen_center / self.pixel_per_world_unit
+    }
+
+    // Moves the camera by a distance given in screen coordinates, i.e. "drags" the world along.
+    pub fn pan(&mut self, screen_delta: RenderSize) {
+        self.position.x -= screen_delta.x / self.pixel_per_world_unit;
+        self.position.y += screen_delta.y / self.pixel_per_world_unit;
+    }
+
+    // Zooms by a given factor (>1 zooms in) while keeping the world position under screen_pos at the same spot on screen.
+    pub fn zoom_around_screen_point(&mut self, screen_pos: RenderPoint, zoom_factor: f32) {
+        let world_pos_before = self.screen_to_world_coords(screen_pos);
+        self.pixel_per_world_unit *= zoom_factor;
+        let world_pos_after = self.screen_to_world_coords(screen_pos);
+        self.position += world_pos_before - world_pos_after;
+    }
+
     pub fn transformation_matrix(&self) -> Matrix4<f32> {
         let scaling = Vector2::new(self.pixel_per_world_unit, -self.pixel_per_world_unit);
         let translation = Vector2::new(self.screen.x, self.screen.y) + Vector2::new(self.screen.w, self.screen.h) * 0.5
@@ -123,4 +157,63 @@ mod tests {
             assert_eq!(camera.world_to_screen_coords(RenderPoint::new(-1.0, -1.0)), RenderPoint::new(91.0, 62.0));
         }
     }
+
+    #[test]
+    fn screen_to_world_conversion() {
+        let camera = Camera {
+            screen: Rect::new(1.0, 2.0, 200.0, 100.0),
+            pixel_per_world_unit: 10.0,
+            position: RenderPoint::new(1.0, 1.0),
+        };
+        assert_eq!(camera.screen_to_world_coords(RenderPoint::new(101.0, 52.0)), RenderPoint::new(1.0, 1.0));
+        assert_eq!(camera.screen_to_world_coords(RenderPoint::new(111.0, 42.0)), RenderPoint::new(2.0, 2.0));
+
+        for &world_pos in [RenderPoint::origin(), RenderPoint::new(-3.0, 5.0), RenderPoint::new(12.5, -0.25)].iter() {
+            assert_eq!(camera.screen_to_world_coords(camera.world_to_screen_coords(world_pos)), world_pos);
+        }
+    }
+
+    #[test]
+    fn resize_keeps_visible_world_visible() {
+        let mut camera = Camera::center_around_world_rect(Rect::new(0.0, 0.0, 200.0, 100.0), Rect::new(0.0, 0.0, 20.0, 10.0));
+        let visible_before = camera.visible_world_rect();
+
+        // Narrower screen, the formerly visible horizontal range must fit, i.e. we need to zoom out.
+        camera.resize_screen(Rect::new(0.0, 0.0, 100.0, 100.0));
+        let visible_after = camera.visible_world_rect();
+        assert_eq!(camera.screen, Rect::new(0.0, 0.0, 100.0, 100.0));
+        assert_eq!(camera.pixel_per_world_unit, 5.0);
+        assert_eq!(camera.position, RenderPoint::new(10.0, 5.0));
+        assert!(visible_after.x <= visible_before.x && visible_after.y <= visible_before.y);
+        assert!(visible_after.right() >= visible_before.right() && visible_after.bottom() >= visible_before.bottom());
+    }
+
+    #[test]
+    fn pan_moves_world_with_screen_delta() {
+        let mut camera = Camera {
+            screen: Rect::new(0.0, 0.0, 200.0, 100.0),
+            pixel_per_world_unit: 10.0,
+            position: RenderPoint::origin(),
+        };
+        let screen_pos_before = camera.world_to_screen_coords(RenderPoint::new(1.0, 1.0));
+        camera.pan(RenderSize::new(20.0, -10.0));
+        assert_eq!(
+            camera.world_to_screen_coords(RenderPoint::new(1.0, 1.0)),
+            screen_pos_before + RenderSize::new(20.0, -10.0)
+        );
+    }
+
+    #[test]
+    fn zoom_keeps_point_under_cursor() {
+        let mut camera = Camera {
+            screen: Rect::new(0.0, 0.0, 200.0, 100.0),
+            pixel_per_world_unit: 10.0,
+            position: RenderPoint::origin(),
+        };
+        let cursor = RenderPoint::new(150.0, 20.0);
+        let world_pos_under_cursor = camera.screen_to_world_coords(cursor);
+        camera.zoom_around_screen_point(cursor, 2.0);
+        assert_eq!(camera.pixel_per_world_unit, 20.0);
+        assert!(camera.screen_to_world_coords(cursor).distance(world_pos_under_cursor) < 0.0001);
+    }
 }
@@ -0,0 +1,128 @@
+use ggez::graphics::Color;
+
+use crate::clamp;
+
+// Maps values in [0, 1] to colors by linearly interpolating between color stops.
+#[derive(Clone)]
+pub struct ColorMap {
+    pub name: &'static str,
+    stops: Vec<(f32, Color)>, // position in [0, 1] and color, sorted by position
+}
+
+// Color from a 0xRRGGBB hex value.
+fn rgb(hex: u32) -> Color {
+    Color::new(
+        ((hex >> 16) & 0xFF) as f32 / 255.0,
+        ((hex >> 8) & 0xFF) as f32 / 255.0,
+        (hex & 0xFF) as f32 / 255.0,
+        1.0,
+    )
+}
+
+// Evenly spaced stops from a list of hex colors.
+fn even_stops(colors: &[u32]) -> Vec<(f32, Color)> {
+    colors
+        .iter()
+        .enumerate()
+        .map(|(i, hex)| (i as f32 / (colors.len() - 1) as f32, rgb(*hex)))
+        .collect()
+}
+
+impl ColorMap {
+    // User defined ramp. Stops need to be sorted by position, first and last position should be 0 and 1.
+    pub fn piecewise(name: &'static str, stops: Vec<(f32, Color)>) -> ColorMap {
+        assert!(!stops.is_empty(), "Color map needs at least one color stop");
+        assert!(stops.windows(2).all(|w| w[0].0 <= w[1].0), "Color map stops need to be sorted");
+        ColorMap { name, stops }
+    }
+
+    // Black over red and yellow to white.
+    pub fn heat() -> ColorMap {
+        Self::piecewise("Heat", even_stops(&[0x000000, 0xFF0000, 0xFFFF00, 0xFFFFFF]))
+    }
+
+    // Perceptually uniform maps from matplotlib, sampled at 9 points.
+    pub fn viridis() -> ColorMap {
+        Self::piecewise(
+            "Viridis",
+            even_stops(&[0x440154, 0x472D7B, 0x3B528B, 0x2C728E, 0x21918C, 0x28AE80, 0x5EC962, 0xADDC30, 0xFDE725]),
+        )
+    }
+    pub fn plasma() -> ColorMap {
+        Self::piecewise(
+            "Plasma",
+            even_stops(&[0x0D0887, 0x4C02A1, 0x7E03A8, 0xA92395, 0xCC4778, 0xE56B5D, 0xF89540, 0xFDC328, 0xF0F921]),
+        )
+    }
+
+    // Diverging map by Kenneth Moreland, sampled at 5 points. Good for signed values centered around zero.
+    pub fn coolwarm() -> ColorMap {
+        Self::piecewise("CoolWarm", even_stops(&[0x3B4CC0, 0x8DB0FE, 0xDDDDDD, 0xF49A7B, 0xB40426]))
+    }
+
+    pub fn sample(&self, t: f32) -> Color {
+        let t = if t.is_nan() { 0.0 } else { clamp(t, 0.0, 1.0) };
+        let upper = self.stops.iter().position(|(position, _)| *position >= t).unwrap_or(self.stops.len() - 1);
+        if upper == 0 {
+            return self.stops[0].1;
+        }
+        let (position_a, a) = self.stops[upper - 1];
+        let (position_b, b) = self.stops[upper];
+        let s = if position_b > position_a {
+            (t - position_a) / (position_b - position_a)
+        } else {
+            1.0
+        };
+        Color::new(a.r + (b.r - a.r) * s, a.g + (b.g - a.g) * s, a.b + (b.b - a.b) * s, a.a + (b.a - a.a) * s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_color_eq(a: Color, b: Color) {
+        let max_difference = (a.r - b.r).abs().max((a.g - b.g).abs()).max((a.b - b.b).abs()).max((a.a - b.a).abs());
+        assert!(max_difference < 1.0e-5, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn sample_interpolates_between_stops() {
+        let map = ColorMap::piecewise(
+            "test",
+            vec![
+                (0.0, Color::new(0.0, 0.0, 0.0, 1.0)),
+                (0.5, Color::new(1.0, 0.0, 0.0, 1.0)),
+                (1.0, Color::new(1.0, 1.0, 1.0, 0.0)),
+            ],
+        );
+        assert_color_eq(map.sample(0.0), Color::new(0.0, 0.0, 0.0, 1.0));
+        assert_color_eq(map.sample(0.25), Color::new(0.5, 0.0, 0.0, 1.0));
+        assert_color_eq(map.sample(0.5), Color::new(1.0, 0.0, 0.0, 1.0));
+        assert_color_eq(map.sample(0.75), Color::new(1.0, 0.5, 0.5, 0.5));
+        assert_color_eq(map.sample(1.0), Color::new(1.0, 1.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn sample_clamps_out_of_range() {
+        let map = ColorMap::viridis();
+        assert_color_eq(map.sample(-1.0), map.sample(0.0));
+        assert_color_eq(map.sample(2.0), map.sample(1.0));
+        assert_color_eq(map.sample(f32::NAN), map.sample(0.0));
+    }
+
+    #[test]
+    fn heat_matches_former_ramp() {
+        let map = ColorMap::heat();
+        for i in 0..=10 {
+            let t = i as f32 / 10.0;
+            let expected = Color::new(
+                clamp(t * 3.0, 0.0, 1.0),
+                clamp(t * 3.0 - 1.0, 0.0, 1.0),
+                clamp(t * 3.0 - 2.0, 0.0, 1.0),
+                1.0,
+            );
+            assert_color_eq(map.sample(t), expected);
+        }
+    }
+}
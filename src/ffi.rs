@@ -0,0 +1,389 @@
+// C ABI for embedding the solver into game engines and C/C++ applications, only with the "ffi" feature.
+// Declarations are in include/yasph2d.h. Build a shared or static library with
+//
+//   cargo rustc --release --lib --features ffi --crate-type cdylib    (or staticlib)
+//
+// A simulation is an opaque handle that owns a fluid world, its solver and time manager, see sph::Simulation.
+// Functions returning int32_t return YASPH_OK or one of the error codes below, with a message from yasph_last_error.
+// Null handles are ignored, i.e. return zero/YASPH_ERROR_INVALID_ARGUMENT. Handles must not be used from several threads at once.
+
+use crate::sph;
+use crate::units::*;
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+
+pub const YASPH_OK: i32 = 0;
+pub const YASPH_ERROR_INVALID_ARGUMENT: i32 = 1;
+pub const YASPH_ERROR_LENGTH_MISMATCH: i32 = 2;
+pub const YASPH_ERROR_NON_FINITE: i32 = 3;
+// A bug inside the library, the simulation should be destroyed.
+pub const YASPH_ERROR_PANIC: i32 = 4;
+
+pub const YASPH_SOLVER_DFSPH: i32 = 0;
+pub const YASPH_SOLVER_WCSPH: i32 = 1;
+
+pub struct YasphSimulation {
+    simulation: sph::Simulation,
+    last_error: CString,
+}
+
+impl YasphSimulation {
+    fn fail(&mut self, code: i32, message: String) -> i32 {
+        // messages come from Display impls, which don't contain nul bytes
+        self.last_error = CString::new(message).unwrap_or_default();
+        code
+    }
+
+    fn result(&mut self, result: Result<(), sph::SphError>) -> i32 {
+        match result {
+            Ok(()) => {
+                self.last_error = CString::default();
+                YASPH_OK
+            }
+            Err(err) => {
+                let code = match err {
+                    sph::SphError::LengthMismatch { .. } => YASPH_ERROR_LENGTH_MISMATCH,
+                    sph::SphError::NonFinite { .. } => YASPH_ERROR_NON_FINITE,
+                    sph::SphError::InvalidArgument { .. } => YASPH_ERROR_INVALID_ARGUMENT,
+                };
+                self.fail(code, err.to_string())
+            }
+        }
+    }
+
+    // Panics must not unwind into the caller's frames.
+    fn catch_panic(&mut self, f: impl FnOnce(&mut sph::Simulation) -> Result<(), sph::SphError>) -> i32 {
+        match panic::catch_unwind(AssertUnwindSafe(|| f(&mut self.simulation))) {
+            Ok(result) => self.result(result),
+            Err(_) => self.fail(YASPH_ERROR_PANIC, "panic inside yasph2d, see stderr".to_string()),
+        }
+    }
+}
+
+// Writes up to capacity pairs to buffer as x0, y0, x1, y1, ... and returns the number of available pairs.
+unsafe fn write_pairs(pairs: impl ExactSizeIterator<Item = (Real, Real)>, buffer: *mut f32, capacity: usize) -> usize {
+    let num_pairs = pairs.len();
+    if !buffer.is_null() {
+        let buffer = std::slice::from_raw_parts_mut(buffer, capacity * 2);
+        for (target, (x, y)) in buffer.chunks_exact_mut(2).zip(pairs) {
+            target[0] = x;
+            target[1] = y;
+        }
+    }
+    num_pairs
+}
+
+// Returns null for an unknown solver or non-positive densities. A fixed_timestep of 0 or less selects adaptive timesteps.
+#[no_mangle]
+pub extern "C" fn yasph_create(
+    particle_density: f32,
+    fluid_density: f32,
+    smoothing_factor: f32,
+    solver: i32,
+    fixed_timestep: f32,
+) -> *mut YasphSimulation {
+    if !(particle_density > 0.0 && fluid_density > 0.0 && smoothing_factor > 0.0) {
+        return std::ptr::null_mut();
+    }
+    let fluid_world = sph::FluidParticleWorld::new(smoothing_factor, NumberDensity(particle_density), Density(fluid_density));
+    let smoothing_length = fluid_world.properties.smoothing_length();
+    let viscosity_model = sph::XSPHViscosityModel::new(smoothing_length);
+    let (solver, cfl_factor): (Box<dyn sph::Solver>, Real) = match solver {
+        YASPH_SOLVER_DFSPH => (Box::new(sph::DFSPHSolver::new(viscosity_model, smoothing_length)), 1.0),
+        YASPH_SOLVER_WCSPH => (Box::new(sph::WCSPHSolver::new(viscosity_model, &fluid_world.properties)), 0.2),
+        _ => return std::ptr::null_mut(),
+    };
+    let time_manager = sph::TimeManager::new(if fixed_timestep > 0.0 {
+        sph::TimeManagerConfiguration::FixedTimeStep(fixed_timestep)
+    } else {
+        sph::TimeManagerConfiguration::AdaptiveTimeStep {
+            timestep_max: 1.0 / 240.0,
+            timestep_min: 1.0 / (400.0 * 60.0),
+            timestep_target_frame: sph::AdaptiveTimeStepTarget::None,
+            cfl_factor,
+        }
+    });
+    Box::into_raw(Box::new(YasphSimulation {
+        simulation: sph::Simulation::new(fluid_world, solver, time_manager),
+        last_error: CString::default(),
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn yasph_destroy(simulation: *mut YasphSimulation) {
+    if !simulation.is_null() {
+        drop(Box::from_raw(simulation));
+    }
+}
+
+// Message of the last failed call, empty if the last call succeeded. Valid until the next call with the same handle.
+#[no_mangle]
+pub unsafe extern "C" fn yasph_last_error(simulation: *const YasphSimulation) -> *const c_char {
+    match simulation.as_ref() {
+        Some(simulation) => simulation.last_error.as_ptr(),
+        None => b"\0".as_ptr() as *const c_char,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn yasph_add_fluid_rect(simulation: *mut YasphSimulation, x: f32, y: f32, width: f32, height: f32) -> i32 {
+    match simulation.as_mut() {
+        Some(simulation) => simulation.catch_panic(|simulation| {
+            simulation.fluid_world.add_fluid_rect(&Rect::new(x, y, width, height), 0.0);
+            Ok(())
+        }),
+        None => YASPH_ERROR_INVALID_ARGUMENT,
+    }
+}
+
+// Writes the number of added particles to num_added_particles, unless it is null.
+#[no_mangle]
+pub unsafe extern "C" fn yasph_add_fluid_circle(
+    simulation: *mut YasphSimulation,
+    center_x: f32,
+    center_y: f32,
+    radius: f32,
+    num_added_particles: *mut usize,
+) -> i32 {
+    match simulation.as_mut() {
+        Some(simulation) => simulation.catch_panic(|simulation| {
+            let num_added = simulation.fluid_world.add_fluid_circle(Point::new(center_x, center_y), radius);
+            if let Some(num_added_particles) = num_added_particles.as_mut() {
+                *num_added_particles = num_added;
+            }
+            Ok(())
+        }),
+        None => YASPH_ERROR_INVALID_ARGUMENT,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn yasph_add_fluid_particle(simulation: *mut YasphSimulation, x: f32, y: f32, velocity_x: f32, velocity_y: f32) -> i32 {
+    match simulation.as_mut() {
+        Some(simulation) => simulation.catch_panic(|simulation| {
+            simulation
+                .fluid_world
+                .add_fluid_particle(Point::new(x, y), Vector::new(velocity_x, velocity_y));
+            Ok(())
+        }),
+        None => YASPH_ERROR_INVALID_ARGUMENT,
+    }
+}
+
+// Additional layers of a thickness above 1 go to the right of the line direction.
+#[no_mangle]
+pub unsafe extern "C" fn yasph_add_boundary_line(
+    simulation: *mut YasphSimulation,
+    start_x: f32,
+    start_y: f32,
+    end_x: f32,
+    end_y: f32,
+    thickness_in_particles: u32,
+) -> i32 {
+    match simulation.as_mut() {
+        Some(simulation) => simulation.catch_panic(|simulation| {
+            simulation
+                .fluid_world
+                .add_boundary_thick_line(Point::new(start_x, start_y), Point::new(end_x, end_y), thickness_in_particles.max(1));
+            Ok(())
+        }),
+        None => YASPH_ERROR_INVALID_ARGUMENT,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn yasph_add_boundary_circle(simulation: *mut YasphSimulation, center_x: f32, center_y: f32, radius: f32) -> i32 {
+    match simulation.as_mut() {
+        Some(simulation) => simulation.catch_panic(|simulation| {
+            simulation.fluid_world.add_boundary_circle(Point::new(center_x, center_y), radius);
+            Ok(())
+        }),
+        None => YASPH_ERROR_INVALID_ARGUMENT,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn yasph_set_gravity(simulation: *mut YasphSimulation, x: f32, y: f32) {
+    if let Some(simulation) = simulation.as_mut() {
+        simulation.simulation.fluid_world.set_gravity(Vector::new(x, y));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn yasph_relax_initial_state(simulation: *mut YasphSimulation) -> i32 {
+    match simulation.as_mut() {
+        Some(simulation) => simulation.catch_panic(|simulation| {
+            simulation.fluid_world.relax_initial_state()?;
+            // particles were moved and reordered
+            simulation.solver.clear_cached_state();
+            Ok(())
+        }),
+        None => YASPH_ERROR_INVALID_ARGUMENT,
+    }
+}
+
+// A single step of the solver's timestep, see yasph_timestep. Once a step failed, the simulation shouldn't be stepped any further.
+#[no_mangle]
+pub unsafe extern "C" fn yasph_step(simulation: *mut YasphSimulation) -> i32 {
+    match simulation.as_mut() {
+        Some(simulation) => simulation.catch_panic(|simulation| simulation.step()),
+        None => YASPH_ERROR_INVALID_ARGUMENT,
+    }
+}
+
+// Steps until the simulated time reaches the given time, e.g. the engine's clock, stopping at the first failed step.
+#[no_mangle]
+pub unsafe extern "C" fn yasph_step_until(simulation: *mut YasphSimulation, time: f32) -> i32 {
+    match simulation.as_mut() {
+        Some(simulation) => simulation.catch_panic(|simulation| {
+            while simulation.time_manager.passed_time() < time {
+                simulation.step()?;
+            }
+            Ok(())
+        }),
+        None => YASPH_ERROR_INVALID_ARGUMENT,
+    }
+}
+
+// Simulated time in seconds.
+#[no_mangle]
+pub unsafe extern "C" fn yasph_time(simulation: *const YasphSimulation) -> f32 {
+    match simulation.as_ref() {
+        Some(simulation) => simulation.simulation.time_manager.passed_time(),
+        None => 0.0,
+    }
+}
+
+// Of the last step, in seconds.
+#[no_mangle]
+pub unsafe extern "C" fn yasph_timestep(simulation: *const YasphSimulation) -> f32 {
+    match simulation.as_ref() {
+        Some(simulation) => simulation.simulation.time_manager.timestep(),
+        None => 0.0,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn yasph_particle_radius(simulation: *const YasphSimulation) -> f32 {
+    match simulation.as_ref() {
+        Some(simulation) => simulation.simulation.fluid_world.properties.particle_radius(),
+        None => 0.0,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn yasph_num_particles(simulation: *const YasphSimulation) -> usize {
+    match simulation.as_ref() {
+        Some(simulation) => simulation.simulation.fluid_world.particles.positions.len(),
+        None => 0,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn yasph_num_boundary_particles(simulation: *const YasphSimulation) -> usize {
+    match simulation.as_ref() {
+        Some(simulation) => simulation.simulation.fluid_world.particles.boundary_particles.len(),
+        None => 0,
+    }
+}
+
+// Copies fluid particle positions into a caller buffer of 2 * capacity floats (x0, y0, x1, y1, ...) and returns the number of particles.
+// If that is more than capacity, only the first capacity particles were written. A null buffer only queries the number.
+// Particles are reordered by every step, so their index doesn't identify them across steps.
+#[no_mangle]
+pub unsafe extern "C" fn yasph_read_positions(simulation: *const YasphSimulation, buffer: *mut f32, capacity: usize) -> usize {
+    match simulation.as_ref() {
+        Some(simulation) => {
+            let positions = &simulation.simulation.fluid_world.particles.positions;
+            write_pairs(positions.iter().map(|p| (p.x, p.y)), buffer, capacity)
+        }
+        None => 0,
+    }
+}
+
+// Same as yasph_read_positions for velocities in m/s.
+#[no_mangle]
+pub unsafe extern "C" fn yasph_read_velocities(simulation: *const YasphSimulation, buffer: *mut f32, capacity: usize) -> usize {
+    match simulation.as_ref() {
+        Some(simulation) => {
+            let velocities = &simulation.simulation.fluid_world.particles.velocities;
+            write_pairs(velocities.iter().map(|v| (v.x, v.y)), buffer, capacity)
+        }
+        None => 0,
+    }
+}
+
+// Same as yasph_read_positions for boundary particles, which keep their order unless boundaries are added.
+#[no_mangle]
+pub unsafe extern "C" fn yasph_read_boundary_positions(simulation: *const YasphSimulation, buffer: *mut f32, capacity: usize) -> usize {
+    match simulation.as_ref() {
+        Some(simulation) => {
+            let boundary_particles = &simulation.simulation.fluid_world.particles.boundary_particles;
+            write_pairs(boundary_particles.iter().map(|p| (p.x, p.y)), buffer, capacity)
+        }
+        None => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    #[test]
+    fn simulates_through_the_c_abi() {
+        unsafe {
+            let simulation = yasph_create(10000.0, 1000.0, 2.0, YASPH_SOLVER_DFSPH, 0.001);
+            assert!(!simulation.is_null());
+            assert_eq!(yasph_add_fluid_rect(simulation, 0.02, 0.02, 0.2, 0.2), YASPH_OK);
+            assert_eq!(yasph_add_boundary_line(simulation, 0.0, 0.0, 0.4, 0.0, 2), YASPH_OK);
+            let num_particles = yasph_num_particles(simulation);
+            assert_gt!(num_particles, 100);
+            assert_eq!(yasph_read_positions(simulation, std::ptr::null_mut(), 0), num_particles);
+
+            for _ in 0..10 {
+                assert_eq!(yasph_step(simulation), YASPH_OK);
+            }
+            assert_lt!((yasph_time(simulation) - 0.01).abs(), 1.0e-5);
+            assert_eq!(CStr::from_ptr(yasph_last_error(simulation)).to_str().unwrap(), "");
+
+            // only the first particles fit, everything behind them stays untouched
+            let mut buffer = vec![-1.0; 10 * 2 + 1];
+            assert_eq!(yasph_read_velocities(simulation, buffer.as_mut_ptr(), 10), num_particles);
+            assert!(buffer[..20].iter().any(|v| *v != 0.0 && *v != -1.0));
+            assert_eq!(buffer[20], -1.0);
+            let mut positions = vec![0.0; num_particles * 2];
+            yasph_read_positions(simulation, positions.as_mut_ptr(), num_particles);
+            assert!(positions.chunks(2).all(|p| p[1] > 0.0 && p[1] < 0.25));
+
+            yasph_destroy(simulation);
+        }
+    }
+
+    #[test]
+    fn reports_errors() {
+        unsafe {
+            assert!(yasph_create(10000.0, 1000.0, 2.0, 42, 0.0).is_null());
+            assert!(yasph_create(-1.0, 1000.0, 2.0, YASPH_SOLVER_WCSPH, 0.0).is_null());
+            assert_eq!(yasph_step(std::ptr::null_mut()), YASPH_ERROR_INVALID_ARGUMENT);
+            assert_eq!(yasph_num_particles(std::ptr::null()), 0);
+            assert_eq!(
+                yasph_add_fluid_rect(std::ptr::null_mut(), 0.0, 0.0, 1.0, 1.0),
+                YASPH_ERROR_INVALID_ARGUMENT
+            );
+
+            let simulation = yasph_create(10000.0, 1000.0, 2.0, YASPH_SOLVER_WCSPH, 0.0);
+            assert_eq!(yasph_add_fluid_particle(simulation, 0.0, 0.0, 0.0, 0.0), YASPH_OK);
+            let mut num_added_particles = 0;
+            assert_eq!(yasph_add_fluid_circle(simulation, 0.5, 0.5, 0.05, &mut num_added_particles), YASPH_OK);
+            assert_gt!(num_added_particles, 0);
+            assert_eq!(yasph_num_particles(simulation), 1 + num_added_particles);
+            (*simulation).simulation.fluid_world.particles.velocities.clear();
+            assert_eq!(yasph_step(simulation), YASPH_ERROR_LENGTH_MISMATCH);
+            let message = CStr::from_ptr(yasph_last_error(simulation)).to_str().unwrap();
+            assert!(message.contains("velocities"), "{}", message);
+            yasph_destroy(simulation);
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use cgmath::prelude::*;
+use ggez::graphics::{self, Rect};
+use ggez::{Context, GameResult};
+use std::collections::VecDeque;
+use yasph2d::sph;
+use yasph2d::units::*;
+
+use crate::camera::*;
+
+const LINE_WIDTH: f32 = 0.002;
+
+// Streamlines of the current velocity field.
+// Traced from seed points on a regular grid over the visible world rectangle through the SPH-interpolated velocity field.
+pub struct Streamlines {
+    pub enabled: bool,
+    pub seed_spacing: Real, // distance between seed points in m
+    pub max_steps: usize,   // maximum number of integration steps per streamline
+}
+
+impl Streamlines {
+    pub fn new() -> Streamlines {
+        Streamlines {
+            enabled: false,
+            seed_spacing: 0.08,
+            max_steps: 40,
+        }
+    }
+
+    // Expects the camera transformation to be active.
+    pub fn draw(&self, ctx: &mut Context, camera: &Camera, fluid_world: &sph::FluidParticleWorld) -> GameResult {
+        if !self.enabled {
+            return Ok(());
+        }
+        microprofile::scope!("Streamlines", "draw");
+
+        let world_rect = camera.visible_world_rect();
+        let mut mesh_builder = graphics::MeshBuilder::new();
+        let mut num_lines = 0;
+        for seed in self.seed_points(world_rect) {
+            let line = self.trace(fluid_world, seed);
+            if line.len() >= 2 {
+                let line: Vec<RenderPoint> = line.iter().map(|p| RenderPoint::new(p.x, p.y)).collect();
+                mesh_builder.line(&line, LINE_WIDTH, graphics::Color::new(1.0, 1.0, 1.0, 0.6))?;
+                num_lines += 1;
+            }
+        }
+
+        if num_lines > 0 {
+            let mesh = mesh_builder.build(ctx)?;
+            graphics::draw(ctx, &mesh, graphics::DrawParam::default())?;
+        }
+        Ok(())
+    }
+
+    fn seed_points(&self, world_rect: Rect) -> Vec<Point> {
+        // aligned to world origin, so seeds don't swim around when moving the camera
+        let min_x = (world_rect.x / self.seed_spacing).ceil() as i32;
+        let max_x = ((world_rect.x + world_rect.w) / self.seed_spacing).floor() as i32;
+        let min_y = (world_rect.y / self.seed_spacing).ceil() as i32;
+        let max_y = ((world_rect.y + world_rect.h) / self.seed_spacing).floor() as i32;
+
+        let mut seeds = Vec::new();
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                seeds.push(Point::new(x as Real * self.seed_spacing, y as Real * self.seed_spacing));
+            }
+        }
+        seeds
+    }
+
+    // Integrates along the normalized velocity direction with the midpoint method, i.e. with constant step length.
+    // Stops when leaving the fluid or hitting stagnant flow.
+    fn trace(&self, fluid_world: &sph::FluidParticleWorld, seed: Point) -> Vec<Point> {
+        const MIN_SPEED: Real = 1.0e-3;
+        let step_length = self.seed_spacing * 0.25;
+        let direction_at = |position: Point| {
+            fluid_world
+                .interpolate_velocity(position)
+                .filter(|v| v.magnitude2() > MIN_SPEED * MIN_SPEED)
+                .map(|v| v.normalize())
+        };
+
+        let mut line = vec![seed];
+        let mut position = seed;
+        for _ in 0..self.max_steps {
+            let midpoint = match direction_at(position) {
+                Some(direction) => position + direction * (step_length * 0.5),
+                None => break,
+            };
+            position += match direction_at(midpoint) {
+                Some(direction) => direction * step_length,
+                None => break,
+            };
+            line.push(position);
+        }
+        line
+    }
+}
+
+// Massless tracer particles that are advected with the SPH-interpolated velocity field and leave a trail (pathlines).
+// Fluid particles themselves can't be followed since they get reordered by the neighborhood search.
+pub struct TracerTrails {
+    pub enabled: bool,
+    pub trail_length: usize,       // number of recorded positions per tracer
+    pub seed_every_nth: usize,     // one tracer per this many fluid particles
+    tracers: Vec<VecDeque<Point>>, // front is the current tracer position
+}
+
+impl TracerTrails {
+    pub fn new() -> TracerTrails {
+        TracerTrails {
+            enabled: false,
+            trail_length: 60,
+            seed_every_nth: 20,
+            tracers: Vec::new(),
+        }
+    }
+
+    // Replaces all tracers with new ones at the position of a subset of the fluid particles.
+    pub fn seed(&mut self, fluid_world: &sph::FluidParticleWorld) {
+        let trail_length = self.trail_length;
+        self.tracers = fluid_world
+            .particles
+            .positions
+            .iter()
+            .step_by(self.seed_every_nth.max(1))
+            .map(|p| {
+                let mut trail = VecDeque::with_capacity(trail_length);
+                trail.push_front(*p);
+                trail
+            })
+            .collect();
+    }
+
+    // Moves all tracers along the current velocity field (midpoint method).
+    // Tracers that left the fluid stay where they are.
+    pub fn advance(&mut self, fluid_world: &sph::FluidParticleWorld, dt: Real) {
+        if !self.enabled || dt <= 0.0 {
+            return;
+        }
+        microprofile::scope!("TracerTrails", "advance");
+
+        for trail in self.tracers.iter_mut() {
+            let position = trail[0];
+            let new_position = fluid_world
+                .interpolate_velocity(position)
+                .and_then(|v| fluid_world.interpolate_velocity(position + v * (dt * 0.5)))
+                .map(|v| position + v * dt);
+
+            if let Some(new_position) = new_position {
+                if trail.len() == self.trail_length {
+                    trail.pop_back();
+                }
+                trail.push_front(new_position);
+            }
+        }
+    }
+
+    // Expects the camera transformation to be active.
+    pub fn draw(&self, ctx: &mut Context) -> GameResult {
+        if !self.enabled {
+            return Ok(());
+        }
+        microprofile::scope!("TracerTrails", "draw");
+
+        let mut mesh_builder = graphics::MeshBuilder::new();
+        let mut num_lines = 0;
+        for trail in self.tracers.iter() {
+            if trail.len() >= 2 {
+                let line: Vec<RenderPoint> = trail.iter().map(|p| RenderPoint::new(p.x, p.y)).collect();
+                mesh_builder.line(&line, LINE_WIDTH, graphics::Color::new(1.0, 0.9, 0.3, 0.8))?;
+                num_lines += 1;
+            }
+        }
+
+        if num_lines > 0 {
+            let mesh = mesh_builder.build(ctx)?;
+            graphics::draw(ctx, &mesh, graphics::DrawParam::default())?;
+        }
+        Ok(())
+    }
+}
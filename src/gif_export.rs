@@ -0,0 +1,338 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+// Minimal animated GIF writer for sharing short clips of the simulation, see GifRecorder.
+// Frames are quantized to a fixed 6x7x6 color cube without dithering, which is good enough for color ramps and keeps encoding cheap.
+
+const NUM_RED: u32 = 6;
+const NUM_GREEN: u32 = 7;
+const NUM_BLUE: u32 = 6;
+const MAX_CODE_SIZE: u32 = 12; // LZW codes have at most 12 bits in GIF
+
+// Records frames at a fixed rate for a fixed duration, downscaled to a maximum width.
+pub struct GifRecorder {
+    fps: u32,
+    num_frames: usize,
+    max_width: usize,
+    start: Instant,
+    width: usize,
+    height: usize,
+    frames: Vec<Vec<u8>>, // palette indices
+}
+
+impl GifRecorder {
+    pub fn new(fps: u32, duration: Duration, max_width: usize) -> GifRecorder {
+        GifRecorder {
+            fps,
+            num_frames: ((duration.as_secs_f32() * fps as f32).round() as usize).max(1),
+            max_width,
+            start: Instant::now(),
+            width: 0,
+            height: 0,
+            frames: Vec::new(),
+        }
+    }
+
+    // Whether the next frame is due, frames are taken in real time.
+    pub fn wants_frame(&self) -> bool {
+        !self.is_done() && self.start.elapsed() >= Duration::from_secs_f32(self.frames.len() as f32 / self.fps as f32)
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.frames.len() >= self.num_frames
+    }
+
+    // Progress in [0, 1].
+    pub fn progress(&self) -> f32 {
+        self.frames.len() as f32 / self.num_frames as f32
+    }
+
+    // Adds a frame from rgba8 pixels, rows from top to bottom. All frames need to have the same size.
+    pub fn add_frame(&mut self, rgba: &[u8], width: usize, height: usize) {
+        let factor = (width + self.max_width - 1) / self.max_width.max(1);
+        let (rgba, width, height) = downscale(rgba, width, height, factor.max(1));
+        if self.frames.is_empty() {
+            self.width = width;
+            self.height = height;
+        }
+        assert_eq!((width, height), (self.width, self.height), "GIF frames need to have the same size");
+        self.frames.push(quantize(&rgba));
+    }
+
+    pub fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        let delay = (100.0 / self.fps as f32).round() as u16;
+        write_gif(writer, self.width, self.height, &self.frames, delay)
+    }
+}
+
+// Averages blocks of factor x factor pixels, dropping incomplete blocks at the right and bottom.
+fn downscale(rgba: &[u8], width: usize, height: usize, factor: usize) -> (Vec<u8>, usize, usize) {
+    if factor == 1 {
+        return (rgba.to_vec(), width, height);
+    }
+    let (scaled_width, scaled_height) = (width / factor, height / factor);
+    let mut scaled = vec![0; scaled_width * scaled_height * 4];
+    for y in 0..scaled_height {
+        for x in 0..scaled_width {
+            for c in 0..4 {
+                let mut sum = 0;
+                for sy in 0..factor {
+                    let row = (y * factor + sy) * width;
+                    for sx in 0..factor {
+                        sum += rgba[(row + x * factor + sx) * 4 + c] as usize;
+                    }
+                }
+                scaled[(y * scaled_width + x) * 4 + c] = (sum / (factor * factor)) as u8;
+            }
+        }
+    }
+    (scaled, scaled_width, scaled_height)
+}
+
+fn quantize_channel(value: u8, num_levels: u32) -> u32 {
+    (value as u32 * (num_levels - 1) + 127) / 255
+}
+
+// Palette indices of rgba8 pixels, alpha is ignored.
+fn quantize(rgba: &[u8]) -> Vec<u8> {
+    rgba.chunks(4)
+        .map(|pixel| {
+            let r = quantize_channel(pixel[0], NUM_RED);
+            let g = quantize_channel(pixel[1], NUM_GREEN);
+            let b = quantize_channel(pixel[2], NUM_BLUE);
+            ((r * NUM_GREEN + g) * NUM_BLUE + b) as u8
+        })
+        .collect()
+}
+
+// The color cube, padded to 256 entries.
+fn palette() -> Vec<u8> {
+    let level = |i: u32, num_levels: u32| (i * 255 / (num_levels - 1)) as u8;
+    let mut palette = Vec::with_capacity(256 * 3);
+    for r in 0..NUM_RED {
+        for g in 0..NUM_GREEN {
+            for b in 0..NUM_BLUE {
+                palette.extend_from_slice(&[level(r, NUM_RED), level(g, NUM_GREEN), level(b, NUM_BLUE)]);
+            }
+        }
+    }
+    palette.resize(256 * 3, 0);
+    palette
+}
+
+// Writes a looping GIF89a, delay is per frame in 1/100 s.
+pub fn write_gif(writer: &mut impl Write, width: usize, height: usize, frames: &[Vec<u8>], delay: u16) -> io::Result<()> {
+    writer.write_all(b"GIF89a")?;
+    writer.write_all(&(width as u16).to_le_bytes())?;
+    writer.write_all(&(height as u16).to_le_bytes())?;
+    writer.write_all(&[0xF7, 0, 0])?; // global color table with 256 entries
+    writer.write_all(&palette())?;
+    // NETSCAPE2.0 extension, loop forever
+    writer.write_all(&[0x21, 0xFF, 11])?;
+    writer.write_all(b"NETSCAPE2.0")?;
+    writer.write_all(&[3, 1, 0, 0, 0])?;
+
+    for frame in frames {
+        // graphic control extension with the frame's delay
+        writer.write_all(&[0x21, 0xF9, 4, 0])?;
+        writer.write_all(&delay.to_le_bytes())?;
+        writer.write_all(&[0, 0])?;
+        // image descriptor covering the entire screen, without local color table
+        writer.write_all(&[0x2C, 0, 0, 0, 0])?;
+        writer.write_all(&(width as u16).to_le_bytes())?;
+        writer.write_all(&(height as u16).to_le_bytes())?;
+        writer.write_all(&[0, 8])?; // no flags, minimum code size
+        for block in lzw_compress(frame).chunks(255) {
+            writer.write_all(&[block.len() as u8])?;
+            writer.write_all(block)?;
+        }
+        writer.write_all(&[0])?;
+    }
+    writer.write_all(&[0x3B])
+}
+
+// Packs variable length codes least significant bit first.
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    num_bits: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u32, code_size: u32) {
+        self.buffer |= code << self.num_bits;
+        self.num_bits += code_size;
+        while self.num_bits >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.num_bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.num_bits > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+// LZW compression of 8 bit palette indices as GIF expects it, see https://www.w3.org/Graphics/GIF/spec-gif89a.txt appendix F.
+fn lzw_compress(indices: &[u8]) -> Vec<u8> {
+    const CLEAR_CODE: u32 = 256;
+    const END_CODE: u32 = 257;
+    let mut output = BitWriter {
+        bytes: Vec::new(),
+        buffer: 0,
+        num_bits: 0,
+    };
+    let mut codes: HashMap<(u32, u8), u32> = HashMap::new();
+    let mut next_code = END_CODE + 1;
+    let mut code_size = 9;
+    output.write(CLEAR_CODE, code_size);
+
+    let mut current = match indices.first() {
+        Some(index) => *index as u32,
+        None => {
+            output.write(END_CODE, code_size);
+            return output.finish();
+        }
+    };
+    for &index in indices[1..].iter() {
+        if let Some(&code) = codes.get(&(current, index)) {
+            current = code;
+            continue;
+        }
+        output.write(current, code_size);
+        if next_code < 1 << MAX_CODE_SIZE {
+            codes.insert((current, index), next_code);
+            // the decoder lags one code behind, it switches to the next code size once it assigned the last code of the current size
+            if next_code == 1 << code_size {
+                code_size += 1;
+            }
+            next_code += 1;
+        } else {
+            output.write(CLEAR_CODE, code_size);
+            codes.clear();
+            next_code = END_CODE + 1;
+            code_size = 9;
+        }
+        current = index as u32;
+    }
+    output.write(current, code_size);
+    output.write(END_CODE, code_size);
+    output.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Straightforward GIF LZW decoder to check the encoder against.
+    fn lzw_decompress(data: &[u8]) -> Vec<u8> {
+        let mut bit_position = 0;
+        let mut read = |code_size: u32| {
+            let mut code = 0;
+            for i in 0..code_size {
+                let bit = (data[bit_position / 8] >> (bit_position % 8)) & 1;
+                code |= (bit as u32) << i;
+                bit_position += 1;
+            }
+            code
+        };
+        let mut output = Vec::new();
+        let mut dictionary: Vec<Vec<u8>> = Vec::new();
+        let mut code_size = 9;
+        let mut previous: Option<Vec<u8>> = None;
+        loop {
+            let code = read(code_size);
+            if code == 256 {
+                dictionary = (0..=255).map(|i| vec![i as u8]).collect();
+                dictionary.push(Vec::new());
+                dictionary.push(Vec::new());
+                code_size = 9;
+                previous = None;
+                continue;
+            }
+            if code == 257 {
+                return output;
+            }
+            let entry = if (code as usize) < dictionary.len() {
+                dictionary[code as usize].clone()
+            } else {
+                let mut entry = previous.clone().unwrap();
+                entry.push(entry[0]);
+                entry
+            };
+            output.extend_from_slice(&entry);
+            if let Some(mut previous) = previous {
+                previous.push(entry[0]);
+                dictionary.push(previous);
+                if dictionary.len() == 1 << code_size && code_size < MAX_CODE_SIZE {
+                    code_size += 1;
+                }
+            }
+            previous = Some(entry);
+        }
+    }
+
+    #[test]
+    fn lzw_round_trip() {
+        let mut state = 12345u32;
+        let noise: Vec<u8> = (0..20000)
+            .map(|_| {
+                state = state.wrapping_mul(1103515245).wrapping_add(12345);
+                (state >> 16) as u8
+            })
+            .collect();
+        let inputs = vec![
+            Vec::new(),
+            vec![7],
+            vec![0; 100000],
+            (0..50000).map(|i| (i / 300 % 252) as u8).collect(),
+            noise,
+        ];
+        for input in inputs {
+            let compressed = lzw_compress(&input);
+            assert_eq!(lzw_decompress(&compressed), input);
+        }
+        // a uniform frame compresses well
+        assert!(lzw_compress(&[3; 10000]).len() < 300);
+    }
+
+    #[test]
+    fn quantize_and_downscale() {
+        let palette = palette();
+        for (i, rgb) in palette.chunks(3).take((NUM_RED * NUM_GREEN * NUM_BLUE) as usize).enumerate() {
+            assert_eq!(quantize(&[rgb[0], rgb[1], rgb[2], 255]), vec![i as u8]);
+        }
+        assert_eq!(quantize(&[250, 3, 110, 0]), quantize(&[255, 0, 102, 255]));
+
+        // 4x2 pixels to 2x1
+        let rgba: Vec<u8> = [0, 10, 20, 30, 40, 50, 60, 70].iter().flat_map(|v| vec![*v; 4]).collect();
+        let (scaled, width, height) = downscale(&rgba, 4, 2, 2);
+        assert_eq!((width, height), (2, 1));
+        assert_eq!(scaled, vec![25, 25, 25, 25, 45, 45, 45, 45]);
+    }
+
+    #[test]
+    fn recorder_writes_gif() {
+        let mut recorder = GifRecorder::new(10, Duration::from_millis(200), 3);
+        assert!(recorder.wants_frame());
+        let frame = vec![255; 6 * 4 * 4];
+        recorder.add_frame(&frame, 6, 4);
+        recorder.add_frame(&frame, 6, 4);
+        assert!(recorder.is_done());
+        assert!(!recorder.wants_frame());
+
+        let mut gif = Vec::new();
+        recorder.write(&mut gif).unwrap();
+        assert_eq!(&gif[0..6], b"GIF89a");
+        // downscaled to 3x2
+        assert_eq!(&gif[6..10], &[3, 0, 2, 0]);
+        assert_eq!(*gif.last().unwrap(), 0x3B);
+        let num_frames = gif.windows(3).filter(|w| *w == [0x21, 0xF9, 4]).count();
+        assert_eq!(num_frames, 2);
+    }
+}
@@ -0,0 +1,262 @@
+use ggez::event::MouseButton;
+use ggez::graphics::{self, Color, DrawMode, Rect};
+use ggez::input::mouse;
+use ggez::{Context, GameResult};
+
+use crate::camera::RenderPoint;
+use crate::time_series::TimeSeries;
+
+// Minimalistic immediate mode UI.
+//
+// Widgets are declared every frame between begin and end, interaction is evaluated right away.
+// Everything is laid out top to bottom and drawn in screen coordinates.
+// There's no egui or imgui backend for ggez 0.5 and the handful of widgets the panel needs doesn't justify writing one.
+pub struct Gui {
+    pub visible: bool,
+
+    origin: RenderPoint,
+    layout_cursor: RenderPoint,
+    panel_rect: Rect,
+    next_widget_id: usize,
+    active_widget_id: Option<usize>,
+
+    mouse_position: RenderPoint,
+    mouse_down: bool,
+    mouse_down_last_frame: bool,
+
+    mesh_builder: graphics::MeshBuilder,
+    num_shapes: usize,
+    texts: Vec<(graphics::Text, RenderPoint, Color)>,
+}
+
+const ROW_HEIGHT: f32 = 22.0;
+const LABEL_WIDTH: f32 = 200.0;
+const WIDGET_WIDTH: f32 = 220.0;
+const WIDGET_HEIGHT: f32 = 16.0;
+const PANEL_PADDING: f32 = 8.0;
+const PLOT_HEIGHT: f32 = 48.0;
+
+const PANEL_COLOR: Color = Color {
+    r: 0.1,
+    g: 0.1,
+    b: 0.12,
+    a: 0.8,
+};
+const WIDGET_COLOR: Color = Color {
+    r: 0.3,
+    g: 0.3,
+    b: 0.35,
+    a: 1.0,
+};
+const WIDGET_ACTIVE_COLOR: Color = Color {
+    r: 0.4,
+    g: 0.6,
+    b: 0.9,
+    a: 1.0,
+};
+const PLOT_LINE_COLOR: Color = Color {
+    r: 1.0,
+    g: 0.8,
+    b: 0.3,
+    a: 1.0,
+};
+
+impl Gui {
+    pub fn new(origin: RenderPoint) -> Gui {
+        Gui {
+            visible: true,
+
+            origin,
+            layout_cursor: origin,
+            panel_rect: Rect::new(origin.x, origin.y, 0.0, 0.0),
+            next_widget_id: 0,
+            active_widget_id: None,
+
+            mouse_position: RenderPoint::new(0.0, 0.0),
+            mouse_down: false,
+            mouse_down_last_frame: false,
+
+            mesh_builder: graphics::MeshBuilder::new(),
+            num_shapes: 0,
+            texts: Vec::new(),
+        }
+    }
+
+    // Whether the mouse is currently used by the UI, i.e. should not be used for anything else.
+    pub fn wants_mouse(&self) -> bool {
+        self.visible && (self.active_widget_id.is_some() || self.panel_rect.contains(self.mouse_position))
+    }
+
+    pub fn begin(&mut self, ctx: &mut Context) {
+        let mouse_position = mouse::position(ctx);
+        self.mouse_position = RenderPoint::new(mouse_position.x, mouse_position.y);
+        self.mouse_down_last_frame = self.mouse_down;
+        self.mouse_down = mouse::button_pressed(ctx, MouseButton::Left);
+        if !self.mouse_down {
+            self.active_widget_id = None;
+        }
+
+        self.layout_cursor = RenderPoint::new(self.origin.x + PANEL_PADDING, self.origin.y + PANEL_PADDING);
+        self.next_widget_id = 0;
+        self.mesh_builder = graphics::MeshBuilder::new();
+        self.num_shapes = 0;
+        self.texts.clear();
+    }
+
+    pub fn end(&mut self, ctx: &mut Context) -> GameResult {
+        self.panel_rect = Rect::new(
+            self.origin.x,
+            self.origin.y,
+            LABEL_WIDTH + WIDGET_WIDTH + PANEL_PADDING * 2.0,
+            self.layout_cursor.y - self.origin.y + PANEL_PADDING,
+        );
+        if !self.visible {
+            return Ok(());
+        }
+
+        let background = graphics::Mesh::new_rectangle(ctx, DrawMode::fill(), self.panel_rect, PANEL_COLOR)?;
+        graphics::draw(ctx, &background, graphics::DrawParam::default())?;
+        if self.num_shapes > 0 {
+            let widgets = self.mesh_builder.build(ctx)?;
+            graphics::draw(ctx, &widgets, graphics::DrawParam::default())?;
+        }
+        for (text, position, color) in self.texts.iter() {
+            graphics::draw(ctx, text, (*position, *color))?;
+        }
+        Ok(())
+    }
+
+    // Slider with linear mapping. Returns true if the value was changed.
+    pub fn slider(&mut self, label: &str, value: &mut f32, min: f32, max: f32) -> bool {
+        self.slider_internal(label, value, min, max, |t| min + (max - min) * t, |v| (v - min) / (max - min))
+    }
+
+    // Slider with logarithmic mapping, useful for values spanning several orders of magnitude. min must be positive.
+    pub fn slider_log(&mut self, label: &str, value: &mut f32, min: f32, max: f32) -> bool {
+        let (log_min, log_max) = (min.ln(), max.ln());
+        self.slider_internal(
+            label,
+            value,
+            min,
+            max,
+            |t| (log_min + (log_max - log_min) * t).exp(),
+            |v| (v.ln() - log_min) / (log_max - log_min),
+        )
+    }
+
+    // Row of buttons of which exactly one is selected. Returns true if the selection was changed.
+    pub fn selection(&mut self, label: &str, selected: &mut usize, options: &[&str]) -> bool {
+        self.add_text(label.to_string(), self.layout_cursor, graphics::WHITE);
+
+        let mut changed = false;
+        let button_width = WIDGET_WIDTH / options.len() as f32;
+        for (i, option) in options.iter().enumerate() {
+            let id = self.next_widget_id();
+            let rect = Rect::new(
+                self.layout_cursor.x + LABEL_WIDTH + button_width * i as f32,
+                self.layout_cursor.y,
+                button_width - 2.0,
+                WIDGET_HEIGHT,
+            );
+            if self.clicked(id, rect) && *selected != i {
+                *selected = i;
+                changed = true;
+            }
+            let color = if *selected == i { WIDGET_ACTIVE_COLOR } else { WIDGET_COLOR };
+            self.add_rect(rect, color);
+            self.add_text(option.to_string(), RenderPoint::new(rect.x + 4.0, rect.y), graphics::WHITE);
+        }
+
+        self.layout_cursor.y += ROW_HEIGHT;
+        changed
+    }
+
+    // Scrolling line chart of a time series, newest values on the right. The value axis spans the range of the shown values.
+    // Stands in for egui's plot widget (see above why there's no egui), a single line strip is all the statistics plots need.
+    pub fn plot(&mut self, label: &str, series: &TimeSeries) {
+        let rect = Rect::new(self.layout_cursor.x + LABEL_WIDTH, self.layout_cursor.y, WIDGET_WIDTH, PLOT_HEIGHT);
+        self.add_rect(rect, WIDGET_COLOR);
+        match series.latest() {
+            Some(latest) => self.add_text(format!("{}: {:.4}", label, latest), self.layout_cursor, graphics::WHITE),
+            None => self.add_text(label.to_string(), self.layout_cursor, graphics::WHITE),
+        }
+
+        if let Some((min, max)) = series.range() {
+            let extent = (max - min).max(max.abs() * 1.0e-3).max(1.0e-12);
+            let step = rect.w / (series.capacity() - 1).max(1) as f32;
+            let first_x = rect.right() - step * (series.values().count() - 1) as f32;
+            let points: Vec<RenderPoint> = series
+                .values()
+                .enumerate()
+                .filter(|(_, v)| v.is_finite())
+                .map(|(i, v)| RenderPoint::new(first_x + step * i as f32, rect.bottom() - (v - min) / extent * rect.h))
+                .collect();
+            if points.len() > 1 && self.mesh_builder.line(&points, 1.0, PLOT_LINE_COLOR).is_ok() {
+                self.num_shapes += 1;
+            }
+            self.add_text(format!("{:.3}", max), RenderPoint::new(rect.x + 4.0, rect.y), graphics::WHITE);
+            self.add_text(
+                format!("{:.3}", min),
+                RenderPoint::new(rect.x + 4.0, rect.bottom() - WIDGET_HEIGHT),
+                graphics::WHITE,
+            );
+        }
+
+        self.layout_cursor.y += PLOT_HEIGHT + ROW_HEIGHT - WIDGET_HEIGHT;
+    }
+
+    fn slider_internal(
+        &mut self,
+        label: &str,
+        value: &mut f32,
+        min: f32,
+        max: f32,
+        from_normalized: impl Fn(f32) -> f32,
+        to_normalized: impl Fn(f32) -> f32,
+    ) -> bool {
+        let id = self.next_widget_id();
+        let rect = Rect::new(self.layout_cursor.x + LABEL_WIDTH, self.layout_cursor.y, WIDGET_WIDTH, WIDGET_HEIGHT);
+
+        self.clicked(id, rect);
+        let mut changed = false;
+        if self.active_widget_id == Some(id) {
+            let t = ((self.mouse_position.x - rect.x) / rect.w).clamp(0.0, 1.0);
+            let new_value = from_normalized(t).clamp(min, max);
+            changed = new_value != *value;
+            *value = new_value;
+        }
+
+        let t = to_normalized(*value).clamp(0.0, 1.0);
+        self.add_rect(rect, WIDGET_COLOR);
+        self.add_rect(Rect::new(rect.x, rect.y, rect.w * t, rect.h), WIDGET_ACTIVE_COLOR);
+        self.add_text(label.to_string(), self.layout_cursor, graphics::WHITE);
+        self.add_text(format!("{:.4}", value), RenderPoint::new(rect.x + 4.0, rect.y), graphics::WHITE);
+
+        self.layout_cursor.y += ROW_HEIGHT;
+        changed
+    }
+
+    fn next_widget_id(&mut self) -> usize {
+        self.next_widget_id += 1;
+        self.next_widget_id
+    }
+
+    // Activates the widget if the mouse was pressed on it this frame.
+    fn clicked(&mut self, id: usize, rect: Rect) -> bool {
+        if self.visible && self.mouse_down && !self.mouse_down_last_frame && rect.contains(self.mouse_position) {
+            self.active_widget_id = Some(id);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn add_rect(&mut self, rect: Rect, color: Color) {
+        self.mesh_builder.rectangle(DrawMode::fill(), rect, color);
+        self.num_shapes += 1;
+    }
+
+    fn add_text(&mut self, text: String, position: RenderPoint, color: Color) {
+        self.texts.push((graphics::Text::new(text), position, color));
+    }
+}
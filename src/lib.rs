@@ -3,5 +3,11 @@
 #[macro_use]
 extern crate more_asserts;
 
+#[cfg(feature = "bevy-plugin")]
+pub mod bevyplugin;
+#[cfg(feature = "ffi")]
+mod ffi;
+#[cfg(feature = "python")]
+mod python;
 pub mod sph;
 pub mod units;
@@ -1,14 +1,35 @@
 use cgmath::prelude::*;
-use ggez::event::{self, EventHandler, KeyCode, KeyMods};
+use ggez::event::{self, EventHandler, KeyCode, KeyMods, MouseButton};
 use ggez::graphics::Rect;
 use ggez::{conf, graphics, timer, Context, GameResult};
 use microprofile;
 use std::collections::VecDeque;
+use std::io::Write;
 use std::time::{Duration, Instant};
 
+mod background_field;
 mod camera;
+mod colormap;
+mod flow_lines;
+mod gif_export;
+mod gui;
+mod neighborhood_debug;
+mod renderer;
+mod scene_watcher;
+mod time_series;
+mod tools;
 
+use background_field::BackgroundFieldRenderer;
 use camera::*;
+use colormap::ColorMap;
+use flow_lines::{Streamlines, TracerTrails};
+use gif_export::GifRecorder;
+use gui::Gui;
+use neighborhood_debug::NeighborhoodDebugView;
+use renderer::{Renderer, TOOL_COLOR, UNAVAILABLE_VISUALIZATION_COLOR};
+use scene_watcher::SceneWatcher;
+use time_series::StatisticsHistory;
+use tools::*;
 use yasph2d::sph;
 use yasph2d::units::*;
 
@@ -20,7 +41,7 @@ fn main() -> GameResult {
                 //.samples(conf::NumSamples::Eight) // https://github.com/ggez/ggez/issues/751
                 .vsync(false),
         )
-        .window_mode(conf::WindowMode::default().dimensions(1920.0, 1080.0));
+        .window_mode(conf::WindowMode::default().dimensions(1920.0, 1080.0).resizable(true));
     let (ctx, event_loop) = &mut context_builder.build()?;
     let state = &mut MainState::new(ctx);
 
@@ -35,37 +56,288 @@ enum UpdateMode {
     RealTime,
     Recording,
 }
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Copy)]
 enum Solver {
-    #[allow(dead_code)]
     WSCSPH,
-    #[allow(dead_code)]
     DFSPH,
 }
+#[derive(PartialEq, Clone, Copy)]
+enum ViscosityModel {
+    XSPH,
+    Physical,
+}
+#[derive(PartialEq, Clone, Copy)]
+enum Integrator {
+    SymplecticEuler,
+    VelocityVerlet,
+    RungeKutta2,
+}
+
+// Particle property that fluid particles are colored by.
+#[derive(PartialEq, Clone, Copy)]
+enum VisualizationMode {
+    Velocity,
+    DensityError,
+    Pressure,
+    NeighborCount,
+    FreeSurface,
+    ParticleIndex,
+}
+
+impl VisualizationMode {
+    fn next(self) -> VisualizationMode {
+        match self {
+            VisualizationMode::Velocity => VisualizationMode::DensityError,
+            VisualizationMode::DensityError => VisualizationMode::Pressure,
+            VisualizationMode::Pressure => VisualizationMode::NeighborCount,
+            VisualizationMode::NeighborCount => VisualizationMode::FreeSurface,
+            VisualizationMode::FreeSurface => VisualizationMode::ParticleIndex,
+            VisualizationMode::ParticleIndex => VisualizationMode::Velocity,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            VisualizationMode::Velocity => "Velocity (m/s)",
+            VisualizationMode::DensityError => "Density Error (%)",
+            VisualizationMode::Pressure => "Pressure (N/m)",
+            VisualizationMode::NeighborCount => "Neighbor Count",
+            VisualizationMode::FreeSurface => "Free Surface",
+            VisualizationMode::ParticleIndex => "Particle Index",
+        }
+    }
+}
+
+const NUM_VISUALIZATION_MODES: usize = 6;
+
+// Particle count presets. Scenes are described in meters, switching regenerates the current scene at another particle spacing.
+#[derive(PartialEq, Clone, Copy)]
+enum Resolution {
+    Low,
+    Medium,
+    High,
+}
+
+impl Resolution {
+    const ALL: [Resolution; 3] = [Resolution::Low, Resolution::Medium, Resolution::High];
+    const NAMES: [&'static str; 3] = ["Low (50 particles/m)", "Medium (71 particles/m)", "High (100 particles/m)"];
+
+    fn particle_density(self) -> NumberDensity {
+        match self {
+            Resolution::Low => NumberDensity(2500.0),
+            Resolution::Medium => NumberDensity(5000.0),
+            Resolution::High => NumberDensity(10000.0),
+        }
+    }
+}
+
+const BACKGROUND_COLOR: [f32; 4] = [0.4, 0.4, 0.45, 1.0];
+
+// User choices that are kept per visualization mode.
+#[derive(Clone, Copy)]
+struct VisualizationSettings {
+    color_map: usize, // index into the list of available color maps
+    auto_range: bool, // map min/max of the current values onto the color map instead of the mode's fixed range
+}
+
+// Per fluid particle values for the active visualization mode and the range that is mapped onto the color ramp.
+struct VisualizationValues {
+    values: Vec<f32>,
+    min: f32,
+    max: f32,
+}
+
+impl VisualizationValues {
+    // Maps a value to [0, 1] for color map lookup.
+    fn normalize(&self, value: f32) -> f32 {
+        (value - self.min) / (self.max - self.min).max(f32::EPSILON)
+    }
+}
+
+// Physical viscosity model's default is that of water, which has hardly any visible effect at our resolution.
+const DEFAULT_PHYSICAL_VISCOSITY: Real = 0.3; // in Pa*s
+
+// Everything needed to (re-)create a solver. Tunables are carried over from the previous solver, see Solver::parameters.
+#[derive(Clone)]
+struct SolverConfig {
+    solver: Solver,
+    viscosity_model: ViscosityModel,
+    wcsph_boundary_handling: sph::WCSPHBoundaryHandling,
+    wcsph_integrator: Integrator,
+    watchdog: bool, // halt the simulation when it blows up
+    threading: sph::Threading,
+    xsph_smoothing: bool,    // XSPH velocity smoothing on top of the viscosity model
+    velocity_clamping: bool, // clamp particles to CFL limits instead of letting them explode
+}
+
+impl SolverConfig {
+    fn create_solver(&self, fluid_world: &sph::FluidParticleWorld) -> Box<dyn sph::Solver> {
+        let xsph = sph::XSPHViscosityModel::new(fluid_world.properties.smoothing_length());
+        let mut physicalviscosity = sph::PhysicalViscosityModel::new(fluid_world.properties.smoothing_length());
+        physicalviscosity.fluid_viscosity = DEFAULT_PHYSICAL_VISCOSITY;
+
+        let mut solver: Box<dyn sph::Solver> = match (self.solver, self.viscosity_model) {
+            (Solver::WSCSPH, ViscosityModel::XSPH) => {
+                let mut solver = sph::WCSPHSolver::new(xsph, &fluid_world.properties);
+                solver.set_boundary_handling(self.wcsph_boundary_handling);
+                solver.set_integrator(self.wcsph_integrator());
+                Box::new(solver)
+            }
+            (Solver::WSCSPH, ViscosityModel::Physical) => {
+                let mut solver = sph::WCSPHSolver::new(physicalviscosity, &fluid_world.properties);
+                solver.set_boundary_handling(self.wcsph_boundary_handling);
+                solver.set_integrator(self.wcsph_integrator());
+                Box::new(solver)
+            }
+            (Solver::DFSPH, ViscosityModel::XSPH) => Box::new(sph::DFSPHSolver::new(xsph, fluid_world.properties.smoothing_length())),
+            (Solver::DFSPH, ViscosityModel::Physical) => {
+                Box::new(sph::DFSPHSolver::new(physicalviscosity, fluid_world.properties.smoothing_length()))
+            }
+        };
+        if self.watchdog {
+            solver.set_watchdog(Some(sph::Watchdog::new(WATCHDOG_MAX_VELOCITY)));
+        }
+        if let Err(err) = solver.set_threading(self.threading) {
+            println!("Failed to create solver threads: {}", err);
+        }
+        if self.xsph_smoothing {
+            solver.set_xsph_smoothing(Some(sph::XSPHSmoothing::new(fluid_world.properties.smoothing_length())));
+        }
+        if self.velocity_clamping {
+            solver.set_velocity_clamping(Some(sph::VelocityClamping::new(VELOCITY_CLAMPING_CFL_FACTOR)));
+        }
+        solver
+    }
+
+    fn wcsph_integrator(&self) -> Box<dyn sph::Integrator> {
+        match self.wcsph_integrator {
+            Integrator::SymplecticEuler => Box::new(sph::SymplecticEuler),
+            Integrator::VelocityVerlet => Box::new(sph::VelocityVerlet),
+            Integrator::RungeKutta2 => Box::new(sph::RungeKutta2::default()),
+        }
+    }
+
+    fn cfl_factor(&self) -> Real {
+        match self.solver {
+            Solver::WSCSPH => 0.2,
+            Solver::DFSPH => 1.0,
+        }
+    }
+}
+
+// Second fluid world that is simulated in lock-step with the main one and shown right next to it, for side by side comparisons of solvers.
+// Starts out from the same scene as the main world whenever the simulation is reset.
+// Tools, scene scripts and floating boxes only act on the main world, gravity and other force fields are carried over every step.
+struct ComparisonWorld {
+    solver_config: SolverConfig,
+    fluid_world: sph::FluidParticleWorld,
+    sph_solver: Box<dyn sph::Solver>,
+    time_manager: sph::TimeManager,
+    canvas: Option<graphics::Canvas>,    // the comparison is drawn on it first, see MainState::draw_comparison
+    solver_error: Option<sph::SphError>, // no more steps are taken once the solver failed
+}
+
+impl ComparisonWorld {
+    // Steps until the given simulation time of the main world is reached.
+    fn step_to(&mut self, main_world: &sph::FluidParticleWorld, passed_time: Real) {
+        // custom fields can't be copied, they are usually added by scene scripts which don't run on the comparison anyways
+        self.fluid_world.force_fields = main_world.force_fields.iter().filter_map(sph::ForceField::try_clone).collect();
+        while self.time_manager.passed_time() < passed_time && !self.halted() {
+            if let Err(err) = self.sph_solver.simulation_step(&mut self.fluid_world, &mut self.time_manager) {
+                println!("Comparison halted: {}", err);
+                self.solver_error = Some(err);
+            }
+        }
+    }
+
+    fn halted(&self) -> bool {
+        self.sph_solver.watchdog().and_then(|watchdog| watchdog.alarm()).is_some() || self.solver_error.is_some()
+    }
+}
+
+// Simulation running on its own thread, see MainState::start_background_simulation.
+struct BackgroundSimulation {
+    thread: sph::SimulationThread,
+    snapshot: sph::ParticleSnapshot, // last one shown
+}
 
 struct MainState {
     update_mode: UpdateMode,
-    //solver: Solver,
+    resolution: Resolution, // particle count of the current scene, see set_resolution
+    solver_config: SolverConfig,
     fluid_world: sph::FluidParticleWorld,
     time_manager: sph::TimeManager,
     sph_solver: Box<dyn sph::Solver>,
+    statistics: sph::SimulationStatistics,               // updated every frame
+    neighbor_count_warning_logged: bool,                 // logged once whenever neighbor counts become unhealthy
+    statistics_history: StatisticsHistory,               // recorded every frame that advanced the simulation
+    probes: sph::Probes,                                 // recorded every step
+    comparison_solver: Option<Solver>,                   // if set, a comparison world with this solver is shown to the right of the main world
+    comparison: Option<ComparisonWorld>,                 // recreated on every reset, see comparison_solver
+    background_simulation: Option<BackgroundSimulation>, // if set, fluid_world and sph_solver are copies showing its snapshots
 
     camera: Camera,
-    particle_mesh: graphics::Mesh,
+    renderer: Renderer,
+    visualization_mode: VisualizationMode,
+    visualization_settings: [VisualizationSettings; NUM_VISUALIZATION_MODES], // indexed by VisualizationMode
+    color_maps: Vec<ColorMap>,
+    background_field_renderer: BackgroundFieldRenderer,
+    streamlines: Streamlines,
+    tracer_trails: TracerTrails,
+    neighborhood_debug_view: NeighborhoodDebugView,
+    gui: Gui,
+    show_statistics_plots: bool,
+
+    force_tool: ForceTool,
+    force_tool_target: Option<(Point, Real)>, // position and direction if the tool is active
+    fluid_brush_tool: FluidBrushTool,
+    boundary_draw_tool: BoundaryDrawTool,
+    obstacle_tool: ObstacleTool,
+    floating_box_tool: FloatingBoxTool,
+    particle_inspector_tool: ParticleInspectorTool,
+    region_select_tool: RegionSelectTool,
 
     simulation_step_duration_history: VecDeque<Duration>,
     simulation_processing_time_frame: Duration,
+    simulation_pass_timings_frame: sph::StepTimings, // summed up over all steps of the frame
     simulationstep_count_frame: u32,
+    timings_csv: Option<ggez::filesystem::File>,            // if set, per step timings are written to it
+    step_log: Option<sph::StepLog<ggez::filesystem::File>>, // if set, the columns of STEP_LOG_PRESETS[step_log_preset] are logged every step
+    step_log_preset: usize,
+    gif_recorder: Option<GifRecorder>, // if set, the fluid view is captured into recording.gif
+    trajectory: Option<sph::TrajectoryWriter<ggez::filesystem::File>>, // if set, fluid particles are recorded every frame
+    grid_export: Option<(sph::GridFields, sph::GridExportFormat)>, // if set, fluid fields are written to grid_NNNNN.vtk/npy every frame
+    num_grid_exports: usize,
+    particle_export: Option<sph::ParticleExportFormat>, // if set, fluid particles are written to particles_NNNNN.geo/ply every frame
+    num_particle_exports: usize,
+    density_export: Option<(sph::DensityVolume, sph::VolumeImageFormat)>, // if set, the fluid is rasterized into density_NNNNN.pfm/pgm every frame
+    num_density_exports: usize,
+    instability_reported: bool,                 // whether the current watchdog alarm was already reported
+    solver_error: Option<sph::SphError>,        // last failed step, no more steps are taken until the simulation is reset
+    blue_noise_fluid: bool,                     // initial fluid on blue noise positions instead of a jittered lattice
+    image_scene: Option<sph::ImageScene>,       // replaces the default scene if set, see load_image_scene
+    svg_boundaries: Option<sph::SvgBoundaries>, // replaces the default scene's obstacles within the tank if set, see load_svg_boundaries
+    #[cfg(feature = "scripting")]
+    scene_script: Option<(String, sph::SceneScript)>, // source and running instance of scene.rhai if set, see load_scene_script
+    scene_watcher: SceneWatcher,                // notices edits of the loaded scene files, F5 reloads them
 
     simulation_starttime: Instant,
-    simulation_processing_time_total: Duration,
+    clock: sph::SimulationClock,
     simulation_to_realtime_offset: f32, // Starts out with 0 and grows if we spend too much time on processing the simulation
+    time_scale: Real,                   // fast forward (>1) or slow motion (<1) on top of REALTIME_TO_SIMTIME_SCALE, see set_time_scale
 
     frame_counter: usize,
 }
 
 const SIMULATION_STEP_HISTORY_LENGTH: usize = 80;
 
+// Choices for the columns of steps.csv, see MainState::create_step_log. Probe columns cover the probes at the time logging starts.
+const STEP_LOG_PRESETS: [&str; 5] = ["Off", "Energy", "Accuracy", "Probes", "Statistics & probes"];
+
+// Choices for the number of fluid particles drawn at most, see renderer::ParticleDecimation.
+const PARTICLE_BUDGETS: [Option<usize>; 4] = [None, Some(100_000), Some(20_000), Some(5_000)];
+const PARTICLE_BUDGET_NAMES: [&str; 4] = ["All", "At most 100k", "At most 20k", "At most 5k"];
+
 // Application tries to hit this framerate. If it simulates faster, simulation sleeps. If recording this simply *is* the framerate.
 // Todo: Be awesome and make this dependent on what the screen can do.
 const TARGET_FPS: Real = 60.0;
@@ -81,6 +353,29 @@ const REALTIME_TO_SIMTIME_SCALE: f32 = 1.0;
 
 const TARGET_FRAME_SIMDURATION: Real = REALTIME_TO_SIMTIME_SCALE / TARGET_FPS;
 
+// Range of the user controlled time scale and its change per key press.
+// Only affects how much simulation time passes per real second, timesteps stay the same, i.e. fast forward runs more steps per frame.
+const TIME_SCALE_MIN: Real = 1.0 / 16.0;
+const TIME_SCALE_MAX: Real = 16.0;
+const TIME_SCALE_STEP: Real = 2.0;
+
+// Particles faster than this (in m/s) make the watchdog halt the simulation. Way beyond what any of the scenes reaches.
+const WATCHDOG_MAX_VELOCITY: Real = 50.0;
+
+// Velocity clamping allows particles to move at most this many particle diameters per step.
+const VELOCITY_CLAMPING_CFL_FACTOR: Real = 1.0;
+
+// GIF clips (see GifRecorder) are this long in seconds, at this framerate and at most this wide in pixels.
+const GIF_DURATION: f32 = 5.0;
+const GIF_FPS: u32 = 15;
+const GIF_MAX_WIDTH: usize = 480;
+
+// Loaded scene files are checked for changes this often, see SceneWatcher.
+const SCENE_WATCH_INTERVAL_MS: u64 = 500;
+
+// Zoom factor applied per step of the mouse wheel.
+const CAMERA_ZOOM_PER_WHEEL_STEP: f32 = 1.1;
+
 fn clamp(v: f32, min: f32, max: f32) -> f32 {
     if v < min {
         min
@@ -91,182 +386,1139 @@ fn clamp(v: f32, min: f32, max: f32) -> f32 {
     }
 }
 
-fn heatmap_color(t: f32) -> graphics::Color {
+// All color maps the user can choose from.
+fn available_color_maps() -> Vec<ColorMap> {
+    vec![
+        ColorMap::heat(),
+        ColorMap::viridis(),
+        ColorMap::plasma(),
+        ColorMap::coolwarm(),
+        ColorMap::piecewise(
+            "Water",
+            vec![
+                (0.0, graphics::Color::new(0.03, 0.11, 0.35, 1.0)),
+                (0.4, graphics::Color::new(0.13, 0.37, 0.66, 1.0)),
+                (0.7, graphics::Color::new(0.25, 0.71, 0.77, 1.0)),
+                (1.0, graphics::WHITE),
+            ],
+        ),
+    ]
+}
+
+// Particle colors for tags 1 to 4, see tools::REGION_COLORS. Other tags use the visualization colors.
+const PARTICLE_TAG_COLORS: [graphics::Color; 4] = [
     graphics::Color {
-        r: clamp(t * 3.0, 0.0, 1.0),
-        g: clamp(t * 3.0 - 1.0, 0.0, 1.0),
-        b: clamp(t * 3.0 - 2.0, 0.0, 1.0),
+        r: 0.9,
+        g: 0.2,
+        b: 0.2,
         a: 1.0,
-    }
-}
+    },
+    graphics::Color {
+        r: 0.2,
+        g: 0.8,
+        b: 0.3,
+        a: 1.0,
+    },
+    graphics::Color {
+        r: 0.2,
+        g: 0.4,
+        b: 1.0,
+        a: 1.0,
+    },
+    graphics::Color {
+        r: 1.0,
+        g: 0.85,
+        b: 0.1,
+        a: 1.0,
+    },
+];
 
 impl MainState {
     pub fn new(ctx: &mut Context) -> MainState {
-        let mut fluid_world = sph::FluidParticleWorld::new(
-            2.0,    // smoothing factor
-            5000.0, // #particles/m²
-            100.0,  // density of water (? this is 2d, not 3d where it's 1000 kg/m³)
-        );
-        Self::reset_fluid(&mut fluid_world);
-        let solver = Solver::DFSPH; // Solver::WSCSPH;
-
-        let xsph = sph::XSPHViscosityModel::new(fluid_world.properties.smoothing_length());
-        //xsph.epsilon = 0.1;
-        let mut physicalviscosity = sph::PhysicalViscosityModel::new(fluid_world.properties.smoothing_length());
-        physicalviscosity.fluid_viscosity = 0.01;
-
-        let sph_solver: Box<dyn sph::Solver> = match solver {
-            Solver::WSCSPH => Box::new(sph::WCSPHSolver::new(xsph, &fluid_world.properties)),
-            Solver::DFSPH => Box::new(sph::DFSPHSolver::new(xsph, fluid_world.properties.smoothing_length())),
-        };
-
-        let particle_radius = fluid_world.properties.particle_radius();
-        let particle_mesh = graphics::Mesh::new_circle(
-            ctx,
-            graphics::DrawMode::fill(),
-            RenderPoint::origin(),
-            particle_radius as f32,
-            0.0003,
-            graphics::WHITE,
-        )
-        .unwrap();
-
-        let cfl_factor = match solver {
-            Solver::WSCSPH => 0.2,
-            Solver::DFSPH => 1.0,
+        let resolution = Resolution::Medium;
+        let mut fluid_world = Self::create_fluid_world(resolution);
+        Self::reset_fluid(&mut fluid_world, false, None, None);
+        if let Err(err) = fluid_world.relax_initial_state() {
+            println!("Failed to relax initial state: {}", err);
+        }
+        let solver_config = SolverConfig {
+            solver: Solver::DFSPH, // Solver::WSCSPH;
+            viscosity_model: ViscosityModel::XSPH,
+            wcsph_boundary_handling: sph::WCSPHBoundaryHandling::PenaltyForce,
+            wcsph_integrator: Integrator::VelocityVerlet,
+            watchdog: true,
+            threading: sph::Threading::AllCores,
+            xsph_smoothing: false,
+            velocity_clamping: false,
         };
-
-        let time_manager = sph::TimeManager::new(
-            //sph::TimeManagerConfiguration::FixedTimeStep(TARGET_FRAME_SIMDURATION / 20.0));
-            sph::TimeManagerConfiguration::AdaptiveTimeStep {
-                timestep_max: TARGET_FRAME_SIMDURATION / 4.0,
-                timestep_min: REALTIME_TO_SIMTIME_SCALE / (400.0 * 60.0), // Don't do steps that results in more than a 400 steps for an image on a classic 60Hz display
-                timestep_target_frame: sph::AdaptiveTimeStepTarget::None,
-                cfl_factor,
-            },
-        );
+        let sph_solver = solver_config.create_solver(&fluid_world);
+        let time_manager = Self::create_time_manager(solver_config.cfl_factor());
 
         MainState {
             update_mode: UpdateMode::RealTime,
+            resolution,
+            solver_config,
             fluid_world,
             time_manager,
             sph_solver,
+            statistics: Default::default(),
+            neighbor_count_warning_logged: false,
+            statistics_history: StatisticsHistory::new(),
+            probes: sph::Probes::new(),
+            comparison_solver: None,
+            comparison: None,
+            background_simulation: None,
 
             camera: Camera::center_around_world_rect(graphics::screen_coordinates(ctx), Rect::new(-0.1, -0.1, 2.1, 1.6)),
-            particle_mesh,
+            renderer: Renderer::new(ctx).unwrap(),
+            visualization_mode: VisualizationMode::Velocity,
+            visualization_settings: [
+                VisualizationSettings {
+                    color_map: 0, // Heat
+                    auto_range: false,
+                },
+                VisualizationSettings {
+                    color_map: 2, // Plasma
+                    auto_range: false,
+                },
+                VisualizationSettings {
+                    color_map: 1, // Viridis
+                    auto_range: true,
+                },
+                VisualizationSettings {
+                    color_map: 1, // Viridis
+                    auto_range: false,
+                },
+                VisualizationSettings {
+                    color_map: 0, // Heat
+                    auto_range: false,
+                },
+                VisualizationSettings {
+                    color_map: 4, // Water
+                    auto_range: false,
+                },
+            ],
+            color_maps: available_color_maps(),
+            background_field_renderer: BackgroundFieldRenderer::new(),
+            streamlines: Streamlines::new(),
+            tracer_trails: TracerTrails::new(),
+            neighborhood_debug_view: NeighborhoodDebugView::new(),
+            gui: Gui::new(RenderPoint::new(10.0, 180.0)),
+            show_statistics_plots: false,
+
+            force_tool: ForceTool::new(),
+            force_tool_target: None,
+            fluid_brush_tool: FluidBrushTool::new(),
+            boundary_draw_tool: BoundaryDrawTool::new(),
+            obstacle_tool: ObstacleTool::new(),
+            particle_inspector_tool: ParticleInspectorTool::new(),
+            region_select_tool: RegionSelectTool::new(),
+            floating_box_tool: FloatingBoxTool::new(),
 
             simulation_step_duration_history: VecDeque::with_capacity(SIMULATION_STEP_HISTORY_LENGTH),
             simulation_processing_time_frame: Default::default(),
+            simulation_pass_timings_frame: Default::default(),
             simulationstep_count_frame: 0,
+            timings_csv: None,
+            step_log: None,
+            step_log_preset: 0,
+            gif_recorder: None,
+            trajectory: None,
+            grid_export: None,
+            num_grid_exports: 0,
+            particle_export: None,
+            num_particle_exports: 0,
+            density_export: None,
+            num_density_exports: 0,
+            instability_reported: false,
+            solver_error: None,
+            blue_noise_fluid: false,
+            image_scene: None,
+            svg_boundaries: None,
+            #[cfg(feature = "scripting")]
+            scene_script: None,
+            scene_watcher: SceneWatcher::new(Duration::from_millis(SCENE_WATCH_INTERVAL_MS)),
 
             simulation_starttime: Instant::now(),
-            simulation_processing_time_total: Default::default(),
+            clock: sph::SimulationClock::new(),
             simulation_to_realtime_offset: Default::default(),
+            time_scale: 1.0,
 
             frame_counter: 0,
         }
     }
 
-    fn reset_fluid(fluid_world: &mut sph::FluidParticleWorld) {
+    fn create_fluid_world(resolution: Resolution) -> sph::FluidParticleWorld {
+        // 2D slab of 10cm water, i.e. 100 kg/m²
+        sph::FluidProperties::water()
+            .with_slab_thickness(Length(0.1))
+            .with_particle_density(resolution.particle_density())
+            .create_world()
+    }
+
+    // Starts over with the current scene at another particle count. Force fields and solver tunables are kept.
+    fn set_resolution(&mut self, resolution: Resolution) {
+        self.resolution = resolution;
+        let mut fluid_world = Self::create_fluid_world(resolution);
+        fluid_world.force_fields = std::mem::take(&mut self.fluid_world.force_fields);
+        self.fluid_world = fluid_world;
+        let parameters = self.sph_solver.parameters();
+        self.sph_solver = self.solver_config.create_solver(&self.fluid_world);
+        for parameter in parameters {
+            self.sph_solver.set_parameter(parameter.name, parameter.value);
+        }
+        self.reset_simulation();
+    }
+
+    fn create_time_manager(cfl_factor: Real) -> sph::TimeManager {
+        sph::TimeManager::new(
+            //sph::TimeManagerConfiguration::FixedTimeStep(TARGET_FRAME_SIMDURATION / 20.0));
+            sph::TimeManagerConfiguration::AdaptiveTimeStep {
+                timestep_max: TARGET_FRAME_SIMDURATION / 4.0,
+                timestep_min: REALTIME_TO_SIMTIME_SCALE / (400.0 * 60.0), // Don't do steps that results in more than a 400 steps for an image on a classic 60Hz display
+                timestep_target_frame: sph::AdaptiveTimeStepTarget::None,
+                cfl_factor,
+            },
+        )
+    }
+
+    // Comparison world with the same scene and solver settings as the main world, but a different solver.
+    fn create_comparison(&self, solver: Solver) -> ComparisonWorld {
+        let mut fluid_world = Self::create_fluid_world(self.resolution);
+        Self::reset_fluid(
+            &mut fluid_world,
+            self.blue_noise_fluid,
+            self.image_scene.as_ref(),
+            self.svg_boundaries.as_ref(),
+        );
+        self.boundary_draw_tool.restore(&mut fluid_world);
+        self.obstacle_tool.restore(&mut fluid_world);
+        if let Err(err) = fluid_world.relax_initial_state() {
+            println!("Failed to relax initial state: {}", err);
+        }
+        let solver_config = SolverConfig {
+            solver,
+            ..self.solver_config.clone()
+        };
+        ComparisonWorld {
+            sph_solver: solver_config.create_solver(&fluid_world),
+            time_manager: Self::create_time_manager(solver_config.cfl_factor()),
+            solver_config,
+            fluid_world,
+            canvas: None,
+            solver_error: None,
+        }
+    }
+
+    // Part of the screen the main world is shown in, the left half if there is a comparison world.
+    fn main_view_screen(&self, screen: Rect) -> Rect {
+        if self.comparison.is_some() {
+            Rect::new(screen.x, screen.y, screen.w * 0.5, screen.h)
+        } else {
+            screen
+        }
+    }
+
+    fn reset_fluid(
+        fluid_world: &mut sph::FluidParticleWorld,
+        blue_noise: bool,
+        image_scene: Option<&sph::ImageScene>,
+        svg_boundaries: Option<&sph::SvgBoundaries>,
+    ) {
         fluid_world.remove_all_fluid_particles();
         fluid_world.remove_all_boundary_particles();
+        // every reset starts from the very same particles
+        fluid_world.rng = sph::SimulationRng::default();
 
-        fluid_world.add_fluid_rect(&Rect::new(0.1, 0.7, 0.5, 1.0), 0.05);
-        fluid_world.add_boundary_thick_line(Point::new(0.0, 0.0), Point::new(2.0, 0.0), 2);
-        fluid_world.add_boundary_thick_line(Point::new(0.0, 0.0), Point::new(0.0, 2.5), 2);
-        fluid_world.add_boundary_thick_line(Point::new(2.0, 0.0), Point::new(2.0, 2.5), 2);
+        if let Some(image_scene) = image_scene {
+            image_scene.add_to(fluid_world);
+            return;
+        }
+
+        let fluid_rect = Rect::new(0.1, 0.7, 0.5, 1.0);
+        if blue_noise {
+            fluid_world.add_fluid_blue_noise(&fluid_rect, |_| true);
+        } else {
+            fluid_world.add_fluid_rect(&fluid_rect, 0.05);
+        }
+        fluid_world.add_boundary_polyline(
+            &[Point::new(0.0, 2.5), Point::new(0.0, 0.0), Point::new(2.0, 0.0), Point::new(2.0, 2.5)],
+            2,
+            0.0,
+        );
 
-        fluid_world.add_boundary_line(Point::new(0.0, 0.6), Point::new(1.75, 0.5));
+        match svg_boundaries {
+            Some(svg_boundaries) => svg_boundaries.add_to(fluid_world),
+            None => fluid_world.add_boundary_line(Point::new(0.0, 0.6), Point::new(1.75, 0.5)),
+        }
 
         // close of the container - stop gap solution for issues with endlessly falling particles
         // (mostly a problem for adaptive timestep but potentially also for neighborhood search)
         fluid_world.add_boundary_thick_line(Point::new(0.0, 2.5), Point::new(2.0, 2.5), 2);
     }
 
-    fn draw_text(&mut self, ctx: &mut Context) -> GameResult {
-        microprofile::scope!("MainState", "text");
-
+    // Overlay text blocks, drawn from top to bottom.
+    fn overlay_text(&self, ctx: &mut Context) -> Vec<(String, graphics::Color)> {
         let fps = timer::fps(ctx);
         let average_simulation_step_duration =
             self.simulation_step_duration_history.iter().sum::<Duration>() / self.simulation_step_duration_history.len() as u32;
 
         let simulation_info_text = format!(
-            "Frame Processing: {:3.2}ms ({:4} steps)\nSingle Step (averaged over {}): {:.2}ms, last timestep length {:.4}ms\nTotal Simulated {:.2}s\nTotal Processing {:.2}s",
+            "Frame Processing: {:3.2}ms ({:4} steps)\nSingle Step (averaged over {}): {:.2}ms, last timestep length {:.4}ms\nTotal Simulated {:.2}s in {} steps\nTotal Processing {:.2}s ({:.2}x realtime)",
             self.simulation_processing_time_frame.as_secs_f64() * 1000.0,
             self.simulationstep_count_frame,
             self.simulation_step_duration_history.len(),
             average_simulation_step_duration.as_secs_f64() * 1000.0,
             self.time_manager.timestep() * 1000.0,
-            self.time_manager.passed_time(),
-            self.simulation_processing_time_total.as_secs_f64(),
+            self.clock.simulated_time(),
+            self.clock.num_steps(),
+            self.clock.processing_time().as_secs_f64(),
+            self.clock.realtime_ratio().unwrap_or(0.0),
+        );
+        let pass_timings_text = sph::SimulationPass::ALL
+            .iter()
+            .map(|pass| {
+                format!(
+                    "{} {:.2}ms",
+                    pass.name(),
+                    self.simulation_pass_timings_frame.get(*pass).as_secs_f64() * 1000.0
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        let simulation_info_text = format!(
+            "{}
+Passes (frame): {}{}",
+            simulation_info_text,
+            pass_timings_text,
+            if self.timings_csv.is_some() {
+                "
+Writing timings.csv"
+            } else {
+                ""
+            }
         );
+        let simulation_info_text = if self.trajectory.is_some() {
+            format!("{}\nRecording trajectory.ysph", simulation_info_text)
+        } else {
+            simulation_info_text
+        };
+        let simulation_info_text = match &self.grid_export {
+            Some((_, format)) => format!(
+                "{}\nExporting grid_{:05}.{}",
+                simulation_info_text,
+                self.num_grid_exports,
+                format.extension()
+            ),
+            None => simulation_info_text,
+        };
+        let simulation_info_text = match &self.particle_export {
+            Some(format) => format!(
+                "{}\nExporting particles_{:05}.{}",
+                simulation_info_text,
+                self.num_particle_exports,
+                format.extension()
+            ),
+            None => simulation_info_text,
+        };
+        let simulation_info_text = match &self.density_export {
+            Some((_, format)) => format!(
+                "{}\nExporting density_{:05}.{}",
+                simulation_info_text,
+                self.num_density_exports,
+                format.extension()
+            ),
+            None => simulation_info_text,
+        };
+        let changed_scene_files = self.scene_watcher.changed_files();
+        let simulation_info_text = if changed_scene_files.is_empty() {
+            simulation_info_text
+        } else {
+            let changed_scene_files: Vec<&str> = changed_scene_files.iter().map(|path| path.trim_start_matches('/')).collect();
+            format!("{}\n{} changed, F5 reloads", simulation_info_text, changed_scene_files.join(", "))
+        };
+        let simulation_info_text = match &self.gif_recorder {
+            Some(gif_recorder) => format!("{}\nRecording GIF ({:.0}%)", simulation_info_text, gif_recorder.progress() * 100.0),
+            None => simulation_info_text,
+        };
 
-        let fps_display = graphics::Text::new(match self.update_mode {
+        let mut blocks = Vec::new();
+        let fps_text = match self.update_mode {
             UpdateMode::RealTime => format!(
-                "{:3.2}ms, FPS: {:3.2}\ntime since sim start {:.2}s\n\n{}",
+                "{:3.2}ms, FPS: {:3.2}\ntime since sim start {:.2}s, time scale {:.3}x [+/-]\n\n{}",
                 1000.0 / fps,
                 fps,
                 (Instant::now() - self.simulation_starttime).as_secs_f64(),
+                self.time_scale,
                 simulation_info_text,
             ),
 
             UpdateMode::Recording => format!("RECORDING\n{}", simulation_info_text,),
-        });
-        graphics::draw(ctx, &fps_display, (RenderPoint::new(10.0, 10.0), graphics::WHITE))?;
+        };
+        blocks.push((fps_text, graphics::WHITE));
+        if let Some(background_simulation) = &self.background_simulation {
+            let clock = &background_simulation.snapshot.clock;
+            blocks.push((
+                format!(
+                    "BACKGROUND SIMULATION [H] - showing {:.2}s, {} steps on the simulation thread ({:.2}x realtime)\nTools and settings take the simulation back",
+                    background_simulation.snapshot.passed_time,
+                    clock.num_steps(),
+                    clock.realtime_ratio().unwrap_or(0.0)
+                ),
+                graphics::Color::new(0.4, 0.8, 1.0, 1.0),
+            ));
+        }
+
+        let statistics = &self.statistics;
+        let solver_iterations = match statistics.solver_iterations {
+            Some(iterations) => format!("{} density, {} divergence", iterations.density, iterations.divergence),
+            None => "-".to_string(),
+        };
+        let memory_usage = self.fluid_world.memory_usage();
+        let to_megabytes = |bytes: usize| bytes as f64 / (1024.0 * 1024.0);
+        let statistics_text = format!(
+            "Particles: {} fluid ({} at surface), {} boundary, drawn as {} [S]{}
+Density Error: max {:.2}%, avg {:.2}%
+Energy: kinetic {:.3}J, potential {:.3}J, total {:.3}J
+Max Velocity: {:.2}m/s (CFL {:.2})
+Neighbors: min {}, avg {:.1}, max {}
+Solver Iterations: {}
+Memory: {:.2}MB (particles {:.2}MB, neighbor lists {:.2}MB, grid {:.2}MB)",
+            statistics.num_fluid_particles,
+            statistics.num_free_surface_particles,
+            statistics.num_boundary_particles,
+            self.renderer.particle_renderer_name(),
+            if self.renderer.decimation.num_drawn() < statistics.num_fluid_particles {
+                format!(", {} of them", self.renderer.decimation.num_drawn())
+            } else {
+                String::new()
+            },
+            statistics.max_density_error * 100.0,
+            statistics.avg_density_error * 100.0,
+            statistics.kinetic_energy,
+            statistics.potential_energy,
+            statistics.total_energy(),
+            statistics.max_velocity,
+            statistics.cfl_number,
+            statistics.neighbor_counts.min,
+            statistics.neighbor_counts.avg,
+            statistics.neighbor_counts.max,
+            solver_iterations,
+            to_megabytes(memory_usage.total()),
+            to_megabytes(memory_usage.fluid_particles + memory_usage.boundary_particles),
+            to_megabytes(memory_usage.neighbor_lists),
+            to_megabytes(memory_usage.grid_cells),
+        );
+        blocks.push((statistics_text, graphics::WHITE));
+
+        // strongest load on a single boundary object, e.g. the pressure on a dam wall
+        if let Some((object, strongest)) = statistics.boundary_object_forces.iter().enumerate().max_by(|(_, a), (_, b)| {
+            a.force
+                .magnitude2()
+                .partial_cmp(&b.force.magnitude2())
+                .unwrap_or(std::cmp::Ordering::Equal)
+        }) {
+            blocks.push((
+                format!(
+                    "Max Boundary Force: {:.2}N/m ({:.2}, {:.2}), torque {:.3}N on object {} of {}",
+                    strongest.force.magnitude(),
+                    strongest.force.x,
+                    strongest.force.y,
+                    strongest.torque,
+                    object,
+                    statistics.boundary_object_forces.len()
+                ),
+                graphics::WHITE,
+            ));
+        }
+
+        if let Some(warning) = statistics.solver_residuals.as_ref().and_then(|residuals| residuals.warning()) {
+            blocks.push((warning, graphics::Color::new(1.0, 0.6, 0.1, 1.0)));
+        }
+        if statistics.num_clamped_velocities > 0 || statistics.num_clamped_accellerations > 0 {
+            blocks.push((
+                format!(
+                    "Clamped {} velocities and {} accellerations to CFL limits",
+                    statistics.num_clamped_velocities, statistics.num_clamped_accellerations
+                ),
+                graphics::Color::new(1.0, 0.6, 0.1, 1.0),
+            ));
+        }
+
+        if let Some(instability) = self.sph_solver.watchdog().and_then(|watchdog| watchdog.alarm()) {
+            blocks.push((
+                format!(
+                    "SIMULATION HALTED - {}\nParticle state written to instability.csv, press Space to reset",
+                    instability
+                ),
+                graphics::Color::new(1.0, 0.2, 0.2, 1.0),
+            ));
+        }
+        if let Some(err) = &self.solver_error {
+            blocks.push((
+                format!("SIMULATION HALTED - {}\npress Space to reset", err),
+                graphics::Color::new(1.0, 0.2, 0.2, 1.0),
+            ));
+        }
         if self.simulation_processing_time_frame.as_secs_f32() > TARGET_MAX_PROCESSING_TIME && self.update_mode == UpdateMode::RealTime {
-            graphics::draw(
-                ctx,
-                &graphics::Text::new("REALTIME OFF - simulation time can not keep up with real time"),
-                (RenderPoint::new(10.0, 150.0), graphics::Color::new(1.0, 0.2, 0.2, 1.0)),
-            )?;
+            blocks.push((
+                "REALTIME OFF - simulation time can not keep up with real time".to_string(),
+                graphics::Color::new(1.0, 0.2, 0.2, 1.0),
+            ));
+        }
+        if !self.probes.is_empty() {
+            let probes_text = self
+                .probes
+                .probes()
+                .iter()
+                .map(|probe| match probe.last_sample() {
+                    Some(sample) => format!(
+                        "{}: density {:.1}kg/m², velocity {:.2}m/s, pressure {}",
+                        probe.name,
+                        sample.density,
+                        sample.velocity.magnitude(),
+                        sample.pressure.map_or("-".to_string(), |pressure| format!("{:.1}N/m", pressure))
+                    ),
+                    None => format!("{}: no samples", probe.name),
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            blocks.push((probes_text, graphics::Color::new(1.0, 0.9, 0.1, 1.0)));
+        }
+        if self.particle_inspector_tool.active {
+            let inspector_text = self
+                .particle_inspector_tool
+                .description(&self.fluid_world, self.sph_solver.as_ref())
+                .unwrap_or_else(|| "Click a particle to inspect it".to_string());
+            blocks.push((inspector_text, graphics::Color::new(0.3, 1.0, 1.0, 1.0)));
+        }
+        if self.neighborhood_debug_view.enabled {
+            let stats = self.neighborhood_debug_view.last_query;
+            let query_text = format!(
+                "Neighbor query at cursor: {} cells visited, {} potential neighbors, {} neighbors",
+                stats.visited_cells, stats.potential_neighbors, stats.neighbors
+            );
+            blocks.push((query_text, graphics::WHITE));
         }
 
-        Ok(())
+        blocks
+    }
+
+    fn visualization_settings(&mut self) -> &mut VisualizationSettings {
+        &mut self.visualization_settings[self.visualization_mode as usize]
+    }
+
+    fn visualization_color_map(&self) -> &ColorMap {
+        &self.color_maps[self.visualization_settings[self.visualization_mode as usize].color_map]
+    }
+
+    // Returns None if the current mode is not supported by the active solver.
+    fn visualization_values(&self) -> Option<VisualizationValues> {
+        microprofile::scope!("MainState", "visualization values");
+
+        let mut visualization = self.visualization_values_fixed_range(&self.fluid_world, self.sph_solver.as_ref())?;
+        if self.visualization_settings[self.visualization_mode as usize].auto_range && !visualization.values.is_empty() {
+            visualization.min = visualization.values.iter().cloned().fold(f32::INFINITY, f32::min);
+            visualization.max = visualization.values.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+        }
+        Some(visualization)
+    }
+
+    fn visualization_values_fixed_range(&self, fluid_world: &sph::FluidParticleWorld, solver: &dyn sph::Solver) -> Option<VisualizationValues> {
+        let particles = &fluid_world.particles;
+        let num_particles = particles.positions.len();
+        let fluid_density = fluid_world.properties.fluid_density();
+
+        Some(match self.visualization_mode {
+            VisualizationMode::Velocity => VisualizationValues {
+                values: particles.velocities.iter().map(|v| v.magnitude()).collect(),
+                min: 0.0,
+                max: 10.0,
+            },
+            VisualizationMode::DensityError => VisualizationValues {
+                // densities are clamped to the rest density, so there is no negative error
+                values: particles.densities.iter().map(|rho| (rho / fluid_density - 1.0) * 100.0).collect(),
+                min: 0.0,
+                max: 5.0,
+            },
+            VisualizationMode::Pressure => {
+                let values = (0..num_particles)
+                    .map(|i| solver.particle_pressure(fluid_world, i as sph::neighborhood_search::ParticleIndex))
+                    .collect::<Option<Vec<Real>>>()?;
+                // pressure scale depends heavily on solver settings, no point in a fixed upper bound
+                let max = values.iter().cloned().fold(0.0, Real::max);
+                VisualizationValues { values, min: 0.0, max }
+            }
+            VisualizationMode::NeighborCount => {
+                // twice the number of neighbors a particle in resting fluid has, given by the smoothing length in particle spacings
+                let smoothing_factor = fluid_world.properties.smoothing_length() / (fluid_world.properties.particle_radius() * 2.0);
+                let expected_num_neighbors = std::f32::consts::PI * smoothing_factor * smoothing_factor;
+                VisualizationValues {
+                    values: (0..num_particles)
+                        .map(|i| particles.num_neighbors(i as sph::neighborhood_search::ParticleIndex) as f32)
+                        .collect(),
+                    min: 0.0,
+                    max: (expected_num_neighbors * 2.0).round(),
+                }
+            }
+            VisualizationMode::FreeSurface => VisualizationValues {
+                // particles added since the last step are not classified yet
+                values: (0..num_particles)
+                    .map(|i| {
+                        if particles.free_surface.get(i).cloned().unwrap_or(false) {
+                            1.0
+                        } else {
+                            0.0
+                        }
+                    })
+                    .collect(),
+                min: 0.0,
+                max: 1.0,
+            },
+            VisualizationMode::ParticleIndex => VisualizationValues {
+                values: (0..num_particles).map(|i| i as f32).collect(),
+                min: 0.0,
+                max: num_particles as f32,
+            },
+        })
+    }
+
+    // Color per fluid particle, tagged particles are drawn in their tag's color regardless of the visualization.
+    fn particle_colors(&self, fluid_world: &sph::FluidParticleWorld, visualization: &Option<VisualizationValues>) -> Vec<renderer::Rgba> {
+        let color_map = self.visualization_color_map();
+        let tags = &fluid_world.particles.tags;
+        (0..fluid_world.particles.positions.len())
+            .map(|i| {
+                let tag_color = tags
+                    .get(i)
+                    .filter(|tag| **tag > 0)
+                    .and_then(|tag| PARTICLE_TAG_COLORS.get(*tag as usize - 1));
+                let color = match (tag_color, visualization) {
+                    (Some(tag_color), _) => *tag_color,
+                    (None, Some(visualization)) => {
+                        let value = visualization.values.get(i).cloned().unwrap_or(visualization.min);
+                        color_map.sample(visualization.normalize(value))
+                    }
+                    (None, None) => UNAVAILABLE_VISUALIZATION_COLOR,
+                };
+                color.into()
+            })
+            .collect()
+    }
+
+    // Everything in world space: fluid, boundaries, debug views and tool previews.
+    fn draw_scene(&mut self, ctx: &mut Context, visualization: &Option<VisualizationValues>) -> GameResult {
+        microprofile::scope!("MainState", "draw scene");
+
+        let particle_colors = self.particle_colors(&self.fluid_world, visualization);
+        self.renderer.push_camera(ctx, &self.camera)?;
+        self.background_field_renderer
+            .draw(ctx, &self.camera, &self.fluid_world, self.sph_solver.as_ref())?;
+        self.renderer.draw_particles(ctx, &self.camera, &self.fluid_world, &particle_colors)?;
+        self.streamlines.draw(ctx, &self.camera, &self.fluid_world)?;
+        self.tracer_trails.draw(ctx)?;
+        self.renderer.draw_probes(ctx, &self.probes)?;
+        if let Some(selected) = self.particle_inspector_tool.selected() {
+            let neighbors = self.particle_inspector_tool.neighbors(&self.fluid_world);
+            self.renderer.draw_inspected_particle(ctx, &self.fluid_world, selected, &neighbors)?;
+        }
+        if self.neighborhood_debug_view.enabled {
+            let cursor_position = ggez::input::mouse::position(ctx);
+            let query_position = self.camera.screen_to_world_coords(RenderPoint::new(cursor_position.x, cursor_position.y));
+            self.neighborhood_debug_view
+                .draw(ctx, &self.camera, &self.fluid_world, Point::new(query_position.x, query_position.y))?;
+        }
+        self.draw_tool_previews(ctx)?;
+        self.renderer.pop_camera(ctx)
     }
 
-    fn draw_fluid(&mut self, ctx: &mut Context) -> GameResult {
-        microprofile::scope!("MainState", "draw fluid");
+    // Comparison world in the right half of the screen, with the same camera movement and color mapping as the main world.
+    fn draw_comparison(&mut self, ctx: &mut Context, visualization: &Option<VisualizationValues>) -> GameResult {
+        microprofile::scope!("MainState", "draw comparison");
+
+        let comparison = match &self.comparison {
+            Some(comparison) => comparison,
+            None => return Ok(()),
+        };
+        // values of the comparison mapped to the same range, so colors mean the same on both sides
+        let comparison_visualization = self
+            .visualization_values_fixed_range(&comparison.fluid_world, comparison.sph_solver.as_ref())
+            .and_then(|comparison_visualization| {
+                visualization.as_ref().map(|visualization| VisualizationValues {
+                    min: visualization.min,
+                    max: visualization.max,
+                    ..comparison_visualization
+                })
+            });
+        let particle_colors = self.particle_colors(&comparison.fluid_world, &comparison_visualization);
+        let camera = Camera {
+            screen: Rect::new(
+                self.camera.screen.right(),
+                self.camera.screen.y,
+                self.camera.screen.w,
+                self.camera.screen.h,
+            ),
+            pixel_per_world_unit: self.camera.pixel_per_world_unit,
+            position: self.camera.position,
+        };
+
+        // Everything is drawn on a screen sized canvas of which only the right half is shown,
+        // otherwise particles that are out of view on the right would show up over the main world.
+        let screen = graphics::screen_coordinates(ctx);
+        let comparison = self.comparison.as_mut().unwrap();
+        let canvas_size = (screen.w as u16, screen.h as u16);
+        if comparison.canvas.as_ref().map(|canvas| (canvas.image().width(), canvas.image().height())) != Some(canvas_size) {
+            comparison.canvas = Some(graphics::Canvas::new(ctx, canvas_size.0, canvas_size.1, conf::NumSamples::One)?);
+        }
+        let canvas = comparison.canvas.as_ref().unwrap();
+        graphics::set_canvas(ctx, Some(canvas));
+        graphics::clear(ctx, BACKGROUND_COLOR.into());
+        self.renderer.push_camera(ctx, &camera)?;
+        self.renderer.draw_particles(ctx, &camera, &comparison.fluid_world, &particle_colors)?;
+        self.renderer.pop_camera(ctx)?;
+        graphics::set_canvas(ctx, None);
+
+        let right_half = Rect::new(camera.screen.x / screen.w, 0.0, 1.0 - camera.screen.x / screen.w, 1.0);
+        graphics::draw(
+            ctx,
+            canvas,
+            graphics::DrawParam::default()
+                .src(right_half)
+                .dest(RenderPoint::new(camera.screen.x, screen.y)),
+        )?;
+        let divider = graphics::Mesh::new_line(
+            ctx,
+            &[
+                RenderPoint::new(camera.screen.x, screen.y),
+                RenderPoint::new(camera.screen.x, screen.bottom()),
+            ],
+            2.0,
+            graphics::BLACK,
+        )?;
+        graphics::draw(ctx, &divider, graphics::DrawParam::default())?;
 
-        let boundary_color = graphics::Color {
-            r: 0.2,
-            g: 0.2,
-            b: 0.2,
-            a: 1.0,
+        let solver_name = match comparison.solver_config.solver {
+            Solver::WSCSPH => "WCSPH",
+            Solver::DFSPH => "DFSPH",
         };
-        for (p, a) in self
+        let status = if comparison.halted() { ", halted by watchdog" } else { "" };
+        let label = graphics::Text::new(format!("Comparison: {}{}", solver_name, status));
+        graphics::draw(ctx, &label, (RenderPoint::new(camera.screen.x + 10.0, screen.y + 10.0), graphics::WHITE))
+    }
+
+    fn draw_tool_previews(&self, ctx: &mut Context) -> GameResult {
+        let cursor_position = ggez::input::mouse::position(ctx);
+        let cursor_world_position = self.camera.screen_to_world_coords(RenderPoint::new(cursor_position.x, cursor_position.y));
+        let cursor_world_position = Point::new(cursor_world_position.x, cursor_world_position.y);
+
+        if let Some((center, _)) = self.force_tool_target {
+            self.renderer.draw_circle_outline(ctx, center, self.force_tool.radius, graphics::WHITE)?;
+        }
+        if self.boundary_draw_tool.active {
+            let mut preview_line = self.boundary_draw_tool.pending_vertices().to_vec();
+            preview_line.push(cursor_world_position);
+            self.renderer.draw_line_strip(ctx, &preview_line, TOOL_COLOR)?;
+        }
+        if let Some(obstacle) = self.obstacle_tool.obstacle() {
+            let alpha = if self.obstacle_tool.dragging() { 1.0 } else { 0.5 };
+            self.renderer
+                .draw_polygon_outline(ctx, &obstacle.outline(cursor_world_position), graphics::Color { a: alpha, ..TOOL_COLOR })?;
+        }
+        if let Some((min, max)) = self.region_select_tool.region(cursor_world_position) {
+            self.renderer.draw_rect_outline(ctx, min, max, TOOL_COLOR)?;
+        }
+        Ok(())
+    }
+
+    // Color ramp with value range of the current visualization mode.
+    fn draw_legend(&self, ctx: &mut Context, visualization: &Option<VisualizationValues>) -> GameResult {
+        let color_map = self.visualization_color_map();
+        match visualization {
+            Some(visualization) => {
+                let title = format!(
+                    "{} [C]\n{} [M], {} range [A]",
+                    self.visualization_mode.name(),
+                    color_map.name,
+                    if self.visualization_settings[self.visualization_mode as usize].auto_range {
+                        "auto"
+                    } else {
+                        "fixed"
+                    }
+                );
+                self.renderer
+                    .draw_legend(ctx, &title, Some((color_map, visualization.min, visualization.max)))
+            }
+            None => {
+                let title = format!("{} - not available for this solver [C]", self.visualization_mode.name());
+                self.renderer.draw_legend(ctx, &title, None)
+            }
+        }
+    }
+
+    fn draw_gui(&mut self, ctx: &mut Context) -> GameResult {
+        microprofile::scope!("MainState", "gui");
+
+        let gui = &mut self.gui;
+        gui.begin(ctx);
+
+        let mut gravity = self.fluid_world.gravity();
+        gui.slider("Gravity X (m/s²)", &mut gravity.x, -20.0, 20.0);
+        gui.slider("Gravity Y (m/s²)", &mut gravity.y, -20.0, 20.0);
+        if gravity != self.fluid_world.gravity() {
+            self.fluid_world.set_gravity(gravity);
+        }
+
+        let (mut shaking_amplitude, mut shaking_frequency) = self
             .fluid_world
-            .particles
-            .positions
+            .force_fields
             .iter()
-            .zip(self.fluid_world.particles.velocities.iter())
+            .find_map(|field| match field {
+                sph::ForceField::Shaking { amplitude, frequency } => Some((amplitude.x, *frequency)),
+                _ => None,
+            })
+            .unwrap_or((0.0, 1.0));
+        let shaking_changed = gui.slider("Shaking amplitude (m)", &mut shaking_amplitude, 0.0, 0.2)
+            | gui.slider("Shaking frequency (Hz)", &mut shaking_frequency, 0.1, 5.0);
+        if shaking_changed {
+            let force_fields = &mut self.fluid_world.force_fields;
+            force_fields.retain(|field| !matches!(field, sph::ForceField::Shaking { .. }));
+            force_fields.push(sph::ForceField::Shaking {
+                amplitude: Vector::new(shaking_amplitude, 0.0),
+                frequency: shaking_frequency,
+            });
+        }
+
+        let mut time_scale = self.time_scale;
+        let time_scale_changed = gui.slider_log("Time scale", &mut time_scale, TIME_SCALE_MIN, TIME_SCALE_MAX);
+
+        let mut fluid_initialization = self.blue_noise_fluid as usize;
+        let fluid_initialization_changed = gui.selection("Initial fluid", &mut fluid_initialization, &["Jittered lattice", "Blue noise"]);
+        self.blue_noise_fluid = fluid_initialization == 1;
+
+        let resolution = self.resolution;
+        let mut resolution_index = Resolution::ALL.iter().position(|r| *r == resolution).unwrap();
+        let resolution_changed = gui.selection("Resolution", &mut resolution_index, &Resolution::NAMES);
+
+        // Left mouse drags the selected obstacle into the scene.
+        gui.selection("Obstacle", &mut self.obstacle_tool.preset, &OBSTACLE_PRESETS);
+        if self.obstacle_tool.active() {
+            gui.slider("Obstacle size (m)", &mut self.obstacle_tool.size, 0.05, 1.0);
+        }
+        // Left mouse drags a rectangle, the selected action is applied to all fluid particles within once released.
+        gui.selection("Region action", &mut self.region_select_tool.action, &REGION_ACTIONS);
+        match REGION_ACTIONS[self.region_select_tool.action] {
+            "Impulse" => {
+                gui.slider("Impulse X (m/s)", &mut self.region_select_tool.impulse.x, -5.0, 5.0);
+                gui.slider("Impulse Y (m/s)", &mut self.region_select_tool.impulse.y, -5.0, 5.0);
+            }
+            "Color" => {
+                gui.selection("Region color", &mut self.region_select_tool.color, &REGION_COLORS);
+            }
+            _ => {}
+        }
+        // X drops a floating box at the cursor.
+        gui.slider("Floating box density ratio", &mut self.floating_box_tool.density_ratio, 0.1, 0.9);
+        gui.slider("Floating box size (m)", &mut self.floating_box_tool.size, 0.05, 0.5);
+
+        if let sph::TimeManagerConfiguration::AdaptiveTimeStep {
+            timestep_max, cfl_factor, ..
+        } = self.time_manager.config_mut()
         {
-            let c = heatmap_color((a.magnitude() * 0.1) as f32);
-            let rp: RenderPoint = RenderPoint::new(p.x, p.y);
-            graphics::draw(ctx, &self.particle_mesh, ggez::graphics::DrawParam::default().dest(rp).color(c))?;
+            let mut timestep_max_ms = *timestep_max * 1000.0;
+            if gui.slider_log("Max timestep (ms)", &mut timestep_max_ms, 0.05, TARGET_FRAME_SIMDURATION * 1000.0) {
+                *timestep_max = timestep_max_ms / 1000.0;
+            }
+            gui.slider("CFL factor", cfl_factor, 0.05, 2.0);
         }
-        for p in self.fluid_world.particles.boundary_particles.iter() {
-            let rp: RenderPoint = RenderPoint::new(p.x, p.y);
-            graphics::draw(
-                ctx,
-                &self.particle_mesh,
-                ggez::graphics::DrawParam::default().dest(rp).color(boundary_color),
-            )?;
+
+        let config = &mut self.solver_config;
+        let mut solver_changed = false;
+
+        let mut solver_index = match config.solver {
+            Solver::WSCSPH => 0,
+            Solver::DFSPH => 1,
+        };
+        if gui.selection("Solver", &mut solver_index, &["WCSPH", "DFSPH"]) {
+            config.solver = if solver_index == 0 { Solver::WSCSPH } else { Solver::DFSPH };
+            if let sph::TimeManagerConfiguration::AdaptiveTimeStep { cfl_factor, .. } = self.time_manager.config_mut() {
+                *cfl_factor = config.cfl_factor();
+            }
+            solver_changed = true;
+        }
+        if config.solver == Solver::WSCSPH {
+            let mut boundary_handling_index = match config.wcsph_boundary_handling {
+                sph::WCSPHBoundaryHandling::PenaltyForce => 0,
+                sph::WCSPHBoundaryHandling::GhostParticles(sph::WallCondition::FreeSlip) => 1,
+                sph::WCSPHBoundaryHandling::GhostParticles(sph::WallCondition::NoSlip) => 2,
+            };
+            if gui.selection(
+                "Boundary handling",
+                &mut boundary_handling_index,
+                &["Penalty force", "Ghosts (free-slip)", "Ghosts (no-slip)"],
+            ) {
+                config.wcsph_boundary_handling = match boundary_handling_index {
+                    0 => sph::WCSPHBoundaryHandling::PenaltyForce,
+                    1 => sph::WCSPHBoundaryHandling::GhostParticles(sph::WallCondition::FreeSlip),
+                    _ => sph::WCSPHBoundaryHandling::GhostParticles(sph::WallCondition::NoSlip),
+                };
+                solver_changed = true;
+            }
+
+            let mut integrator_index = config.wcsph_integrator as usize;
+            if gui.selection(
+                "Integrator",
+                &mut integrator_index,
+                &["Symplectic Euler", "Velocity Verlet", "Runge-Kutta 2"],
+            ) {
+                config.wcsph_integrator = match integrator_index {
+                    0 => Integrator::SymplecticEuler,
+                    1 => Integrator::VelocityVerlet,
+                    _ => Integrator::RungeKutta2,
+                };
+                solver_changed = true;
+            }
+        }
+
+        let mut viscosity_model_index = match config.viscosity_model {
+            ViscosityModel::XSPH => 0,
+            ViscosityModel::Physical => 1,
+        };
+        if gui.selection("Viscosity model", &mut viscosity_model_index, &["XSPH", "Physical"]) {
+            config.viscosity_model = if viscosity_model_index == 0 {
+                ViscosityModel::XSPH
+            } else {
+                ViscosityModel::Physical
+            };
+            solver_changed = true;
+        }
+
+        // mostly useful along with the physical viscosity model, stabilizes without making the fluid itself more viscous
+        let mut xsph_smoothing_index = config.xsph_smoothing as usize;
+        if gui.selection("Velocity smoothing", &mut xsph_smoothing_index, &["Off", "XSPH"]) {
+            config.xsph_smoothing = xsph_smoothing_index == 1;
+            let xsph_smoothing = if config.xsph_smoothing {
+                Some(sph::XSPHSmoothing::new(self.fluid_world.properties.smoothing_length()))
+            } else {
+                None
+            };
+            self.sph_solver.set_xsph_smoothing(xsph_smoothing);
+        }
+
+        // safety net while exploring parameters, see the clamped particle count in the statistics
+        let mut velocity_clamping_index = config.velocity_clamping as usize;
+        if gui.selection("Velocity clamping", &mut velocity_clamping_index, &["Off", "CFL limit"]) {
+            config.velocity_clamping = velocity_clamping_index == 1;
+            let velocity_clamping = if config.velocity_clamping {
+                Some(sph::VelocityClamping::new(VELOCITY_CLAMPING_CFL_FACTOR))
+            } else {
+                None
+            };
+            self.sph_solver.set_velocity_clamping(velocity_clamping);
+        }
+
+        // fewer threads leave cores to other applications, serial execution makes profiles easier to read
+        let mut threading_index = match config.threading {
+            sph::Threading::AllCores => 0,
+            sph::Threading::Threads(_) => 1,
+            sph::Threading::Serial => 2,
+        };
+        if gui.selection("Threads", &mut threading_index, &["All cores", "Half of the cores", "Serial"]) {
+            config.threading = match threading_index {
+                0 => sph::Threading::AllCores,
+                1 => sph::Threading::Threads((rayon::current_num_threads() / 2).max(1)),
+                _ => sph::Threading::Serial,
+            };
+            if let Err(err) = self.sph_solver.set_threading(config.threading) {
+                println!("Failed to create solver threads: {}", err);
+            }
+        }
+
+        // tunables of the solver and its viscosity model
+        for mut parameter in self.sph_solver.parameters() {
+            let label = if parameter.unit.is_empty() {
+                parameter.name.to_string()
+            } else {
+                format!("{} ({})", parameter.name, parameter.unit)
+            };
+            let changed = if parameter.logarithmic {
+                gui.slider_log(&label, &mut parameter.value, parameter.min, parameter.max)
+            } else {
+                gui.slider(&label, &mut parameter.value, parameter.min, parameter.max)
+            };
+            if changed {
+                self.sph_solver.set_parameter(parameter.name, parameter.value);
+            }
+        }
+
+        // a different solver on the same scene to the right, both start over whenever it is changed
+        let mut comparison_index = match self.comparison_solver {
+            None => 0,
+            Some(Solver::WSCSPH) => 1,
+            Some(Solver::DFSPH) => 2,
+        };
+        let comparison_changed = gui.selection("Compare with", &mut comparison_index, &["Off", "WCSPH", "DFSPH"]);
+        self.comparison_solver = match comparison_index {
+            1 => Some(Solver::WSCSPH),
+            2 => Some(Solver::DFSPH),
+            _ => None,
+        };
+
+        let max_particles = self.renderer.decimation.max_particles;
+        let mut particle_budget_index = PARTICLE_BUDGETS.iter().position(|budget| *budget == max_particles).unwrap_or(0);
+        gui.selection("Draw particles", &mut particle_budget_index, &PARTICLE_BUDGET_NAMES);
+        self.renderer.decimation.max_particles = PARTICLE_BUDGETS[particle_budget_index];
+
+        let mut step_log_preset = self.step_log_preset;
+        let step_log_changed = gui.selection("Log to steps.csv", &mut step_log_preset, &STEP_LOG_PRESETS);
+
+        let mut grid_export_index = match self.grid_export {
+            None => 0,
+            Some((_, sph::GridExportFormat::Vtk)) => 1,
+            Some((_, sph::GridExportFormat::Npy)) => 2,
+        };
+        let grid_export_changed = gui.selection("Export grid", &mut grid_export_index, &["Off", "VTK", "NumPy"]);
+
+        let mut particle_export_index = match self.particle_export {
+            None => 0,
+            Some(sph::ParticleExportFormat::Geo) => 1,
+            Some(sph::ParticleExportFormat::Ply) => 2,
+        };
+        let particle_export_changed = gui.selection("Export particles", &mut particle_export_index, &["Off", "Houdini", "PLY"]);
+
+        let mut density_export_index = match self.density_export {
+            None => 0,
+            Some((_, sph::VolumeImageFormat::Pfm)) => 1,
+            Some((_, sph::VolumeImageFormat::Pgm)) => 2,
+        };
+        let density_export_changed = gui.selection("Export density", &mut density_export_index, &["Off", "PFM", "PGM 16bit"]);
+
+        let mut show_statistics_plots = self.show_statistics_plots as usize;
+        gui.selection("Statistics plots", &mut show_statistics_plots, &["Off", "On"]);
+        self.show_statistics_plots = show_statistics_plots == 1;
+        if self.show_statistics_plots {
+            gui.plot("Kinetic energy (J)", &self.statistics_history.kinetic_energy);
+            gui.plot("Max density error", &self.statistics_history.max_density_error);
+            gui.plot("Step time per frame (ms)", &self.statistics_history.step_time);
+        }
+
+        gui.end(ctx)?;
+
+        // Only for switches that aren't reachable through the Solver trait, tunables are changed in place with Solver::set_parameter.
+        // Solvers are cheap to create, recreating them is easier than switching their type parameters.
+        if solver_changed {
+            let parameters = self.sph_solver.parameters();
+            self.sph_solver = self.solver_config.create_solver(&self.fluid_world);
+            for parameter in parameters {
+                // tunables the new solver doesn't have are dropped
+                self.sph_solver.set_parameter(parameter.name, parameter.value);
+            }
+        }
+        if time_scale_changed {
+            self.set_time_scale(time_scale);
+        }
+        if step_log_changed {
+            // starts over with the new columns
+            self.step_log = match self.create_step_log(ctx, step_log_preset) {
+                Ok(step_log) => step_log,
+                Err(err) => {
+                    println!("Failed to create steps.csv: {}", err);
+                    None
+                }
+            };
+            self.step_log_preset = if self.step_log.is_some() { step_log_preset } else { 0 };
+        }
+        if grid_export_changed {
+            // numbering starts over, possibly overwriting a previous export
+            self.grid_export = match grid_export_index {
+                1 => Self::create_grid_fields(&self.fluid_world).map(|fields| (fields, sph::GridExportFormat::Vtk)),
+                2 => Self::create_grid_fields(&self.fluid_world).map(|fields| (fields, sph::GridExportFormat::Npy)),
+                _ => None,
+            };
+            self.num_grid_exports = 0;
+        }
+        if particle_export_changed {
+            // numbering starts over, possibly overwriting a previous export
+            self.particle_export = match particle_export_index {
+                1 => Some(sph::ParticleExportFormat::Geo),
+                2 => Some(sph::ParticleExportFormat::Ply),
+                _ => None,
+            };
+            self.num_particle_exports = 0;
+        }
+        if density_export_changed {
+            // numbering starts over, possibly overwriting a previous export
+            self.density_export = match density_export_index {
+                1 => Self::create_density_volume(&self.fluid_world).map(|volume| (volume, sph::VolumeImageFormat::Pfm)),
+                2 => Self::create_density_volume(&self.fluid_world).map(|volume| (volume, sph::VolumeImageFormat::Pgm)),
+                _ => None,
+            };
+            self.num_density_exports = 0;
+        }
+        if resolution_changed {
+            self.set_resolution(Resolution::ALL[resolution_index]);
+        } else if fluid_initialization_changed || comparison_changed {
+            self.reset_simulation();
+        }
+        if comparison_changed {
+            let screen = self.main_view_screen(graphics::screen_coordinates(ctx));
+            self.camera.resize_screen(screen);
         }
 
-        graphics::pop_transform(ctx);
-        graphics::apply_transformations(ctx)?;
         Ok(())
     }
 
     fn single_sim_step(&mut self) {
+        if let Some((center, direction)) = self.force_tool_target {
+            self.force_tool
+                .apply(&mut self.fluid_world, center, direction, self.time_manager.timestep());
+        }
+
+        #[cfg(feature = "scripting")]
+        {
+            if let Some((_, scene_script)) = &mut self.scene_script {
+                if let Err(err) = scene_script.update(&mut self.fluid_world, self.time_manager.passed_time()) {
+                    println!("{}", err);
+                    self.scene_script = None;
+                }
+            }
+        }
+
         let time_before = Instant::now();
-        self.sph_solver.simulation_step(&mut self.fluid_world, &mut self.time_manager);
+        if let Err(err) = self.sph_solver.simulation_step(&mut self.fluid_world, &mut self.time_manager) {
+            println!("Simulation halted: {}", err);
+            self.solver_error = Some(err);
+        }
+        self.floating_box_tool.update(&mut self.fluid_world, self.time_manager.timestep());
+        self.particle_inspector_tool.follow(&self.fluid_world, self.time_manager.timestep());
         let time_after = Instant::now();
 
         let step_processing_time = time_after - time_before;
         self.simulation_processing_time_frame += step_processing_time;
-        self.simulation_processing_time_total += step_processing_time;
+        self.clock.record_step(self.time_manager.passed_time(), step_processing_time);
         self.simulationstep_count_frame += 1;
+        self.simulation_pass_timings_frame.accumulate(self.sph_solver.last_step_timings());
+        if let Some(comparison) = &mut self.comparison {
+            let time_before = Instant::now();
+            comparison.step_to(&self.fluid_world, self.time_manager.passed_time());
+            // counts towards the frame's processing budget, but not towards the main world's step timings
+            self.simulation_processing_time_frame += time_before.elapsed();
+        }
+        self.probes
+            .record(&self.fluid_world, self.sph_solver.as_ref(), self.time_manager.passed_time());
+        if let Some(step_log) = &mut self.step_log {
+            if let Err(err) = step_log.log(&self.fluid_world, self.sph_solver.as_ref(), &self.time_manager, &self.probes) {
+                println!("Failed to write steps.csv: {}", err);
+                self.step_log = None;
+                self.step_log_preset = 0;
+            }
+        }
+        if let Some(file) = &mut self.timings_csv {
+            let row = format!("{},{}", self.time_manager.passed_time(), self.sph_solver.last_step_timings().csv_row());
+            if let Err(err) = writeln!(file, "{}", row) {
+                println!("Failed to write timings: {}", err);
+                self.timings_csv = None;
+            }
+        }
 
         if self.simulation_step_duration_history.len() == SIMULATION_STEP_HISTORY_LENGTH {
             self.simulation_step_duration_history.pop_front();
@@ -274,27 +1526,431 @@ impl MainState {
         self.simulation_step_duration_history.push_back(step_processing_time);
     }
 
+    // Bounding box (min, max) of a set of points, None if there are none.
+    fn bounds(points: &[Point]) -> Option<(Point, Point)> {
+        if points.is_empty() {
+            return None;
+        }
+        let min = points
+            .iter()
+            .fold(Point::new(Real::MAX, Real::MAX), |min, p| Point::new(min.x.min(p.x), min.y.min(p.y)));
+        let max = points
+            .iter()
+            .fold(Point::new(Real::MIN, Real::MIN), |max, p| Point::new(max.x.max(p.x), max.y.max(p.y)));
+        Some((min, max))
+    }
+
+    // Trajectory quantized within the bounds of the boundaries, zstd compressed if available.
+    fn create_trajectory(ctx: &mut Context, fluid_world: &sph::FluidParticleWorld) -> GameResult<sph::TrajectoryWriter<ggez::filesystem::File>> {
+        let encoding = match Self::bounds(&fluid_world.particles.boundary_particles) {
+            Some((min, max)) => sph::TrajectoryEncoding::Quantized { min, max },
+            None => sph::TrajectoryEncoding::Half,
+        };
+        #[cfg(feature = "compression")]
+        let compression = sph::TrajectoryCompression::Zstd(3);
+        #[cfg(not(feature = "compression"))]
+        let compression = sph::TrajectoryCompression::None;
+        let file = ggez::filesystem::create(ctx, "/trajectory.ysph")?;
+        Ok(sph::TrajectoryWriter::new(file, encoding, compression)?)
+    }
+
+    // Grid over the boundaries, or the fluid if there are no boundaries. None if there are no particles at all.
+    fn export_sample_grid(fluid_world: &sph::FluidParticleWorld, spacing: Real) -> Option<sph::interpolation::SampleGrid> {
+        let particles = &fluid_world.particles;
+        let (min, max) = Self::bounds(&particles.boundary_particles).or_else(|| Self::bounds(&particles.positions))?;
+        let size = (
+            ((max.x - min.x) / spacing).ceil() as usize + 1,
+            ((max.y - min.y) / spacing).ceil() as usize + 1,
+        );
+        Some(sph::interpolation::SampleGrid { origin: min, spacing, size })
+    }
+
+    // Grid at particle spacing, see export_sample_grid.
+    fn create_grid_fields(fluid_world: &sph::FluidParticleWorld) -> Option<sph::GridFields> {
+        let spacing = fluid_world.properties.particle_radius() * 2.0;
+        Self::export_sample_grid(fluid_world, spacing).map(sph::GridFields::new)
+    }
+
+    // Four pixels per particle diameter, so that the surface comes out smooth. See export_sample_grid.
+    fn create_density_volume(fluid_world: &sph::FluidParticleWorld) -> Option<sph::DensityVolume> {
+        let spacing = fluid_world.properties.particle_radius() * 0.5;
+        Self::export_sample_grid(fluid_world, spacing).map(sph::DensityVolume::new)
+    }
+
+    // Samples the fluid fields and writes them into the next grid_NNNNN file if grid export is active.
+    fn export_grid(&mut self, ctx: &mut Context, simulated_time: Real) -> GameResult {
+        if let Some((fields, format)) = &mut self.grid_export {
+            fields.sample(&self.fluid_world, simulated_time);
+            let mut file = ggez::filesystem::create(ctx, format!("/grid_{:05}.{}", self.num_grid_exports, format.extension()))?;
+            fields.write(&mut file, *format)?;
+            self.num_grid_exports += 1;
+        }
+        Ok(())
+    }
+
+    // Writes the fluid particles into the next particles_NNNNN file if particle export is active.
+    fn export_particles(&mut self, ctx: &mut Context) -> GameResult {
+        if let Some(format) = self.particle_export {
+            let path = format!("/particles_{:05}.{}", self.num_particle_exports, format.extension());
+            // buffered, the .geo format is written value by value
+            let mut file = std::io::BufWriter::new(ggez::filesystem::create(ctx, path)?);
+            sph::write_particles(&mut file, &self.fluid_world, format)?;
+            file.flush()?;
+            self.num_particle_exports += 1;
+        }
+        Ok(())
+    }
+
+    // Rasterizes the fluid into the next density_NNNNN image if density export is active.
+    fn export_density(&mut self, ctx: &mut Context) -> GameResult {
+        if let Some((volume, format)) = &mut self.density_export {
+            volume.sample(&self.fluid_world);
+            let mut file = ggez::filesystem::create(ctx, format!("/density_{:05}.{}", self.num_density_exports, format.extension()))?;
+            volume.write(&mut file, *format)?;
+            self.num_density_exports += 1;
+        }
+        Ok(())
+    }
+
+    // Log of the columns of a STEP_LOG_PRESETS entry into steps.csv, None for the "Off" preset.
+    fn create_step_log(&self, ctx: &mut Context, preset: usize) -> GameResult<Option<sph::StepLog<ggez::filesystem::File>>> {
+        let columns = match preset {
+            1 => vec![
+                sph::LogColumn::Time,
+                sph::LogColumn::KineticEnergy,
+                sph::LogColumn::PotentialEnergy,
+                sph::LogColumn::TotalEnergy,
+            ],
+            2 => vec![
+                sph::LogColumn::Time,
+                sph::LogColumn::Timestep,
+                sph::LogColumn::MaxDensityError,
+                sph::LogColumn::AvgDensityError,
+                sph::LogColumn::CflNumber,
+                sph::LogColumn::DensityIterations,
+                sph::LogColumn::DivergenceIterations,
+            ],
+            3 => std::iter::once(sph::LogColumn::Time)
+                .chain(sph::LogColumn::probe_columns(&self.probes))
+                .collect(),
+            4 => sph::LogColumn::STATISTICS
+                .iter()
+                .cloned()
+                .chain(sph::LogColumn::probe_columns(&self.probes))
+                .collect(),
+            _ => return Ok(None),
+        };
+        let file = ggez::filesystem::create(ctx, "/steps.csv")?;
+        Ok(Some(sph::StepLog::new(file, columns)))
+    }
+
+    fn create_timings_csv(ctx: &mut Context) -> GameResult<ggez::filesystem::File> {
+        let mut file = ggez::filesystem::create(ctx, "/timings.csv")?;
+        writeln!(file, "time_s,{}", sph::StepTimings::csv_header())?;
+        Ok(file)
+    }
+
+    // Whether the solver's watchdog found an instability or a step failed, which stops simulating.
+    fn simulation_halted(&self) -> bool {
+        self.sph_solver.watchdog().and_then(|watchdog| watchdog.alarm()).is_some() || self.solver_error.is_some()
+    }
+
+    // Prints the watchdog alarm and dumps the particle state.
+    fn report_instability(&mut self, ctx: &mut Context) {
+        self.instability_reported = true;
+        let instability = match self.sph_solver.watchdog().and_then(|watchdog| watchdog.alarm()) {
+            Some(instability) => instability,
+            None => return,
+        };
+        println!("Simulation halted: {}", instability);
+        let clock = self.clock;
+        let result = ggez::filesystem::create(ctx, "/instability.csv").and_then(|mut file| {
+            writeln!(file, "# {}", clock)?;
+            instability.write_snapshot(&mut file, &self.fluid_world).map_err(ggez::GameError::from)
+        });
+        match result {
+            Ok(()) => println!("Wrote particle state to instability.csv"),
+            Err(err) => println!("Failed to write instability.csv: {}", err),
+        }
+    }
+
+    // Scene from scene.png in the resource or user data directory, scaled to the width of the default scene's tank.
+    fn load_image_scene(ctx: &mut Context) -> GameResult<sph::ImageScene> {
+        let image = graphics::Image::new(ctx, "/scene.png")?;
+        let rgba = image.to_rgba8(ctx)?;
+        let pixel_size = 2.0 / image.width() as Real;
+        let image_scene = sph::ImageScene::from_rgba8(image.width() as usize, image.height() as usize, rgba, pixel_size);
+        Ok(image_scene.map_err(std::io::Error::from)?)
+    }
+
+    // Boundaries from scene.svg in the resource or user data directory, scaled to fit into the default scene's tank.
+    fn load_svg_boundaries(ctx: &mut Context) -> GameResult<sph::SvgBoundaries> {
+        let mut file = ggez::filesystem::open(ctx, "/scene.svg")?;
+        let mut svg_boundaries = sph::SvgBoundaries::read(&mut file)?;
+        svg_boundaries.fit_to(&Rect::new(0.0, 0.0, 2.0, 2.5));
+        Ok(svg_boundaries)
+    }
+
+    // Scene files the current scene was loaded from.
+    fn scene_files(&self) -> Vec<&'static str> {
+        let mut paths = Vec::new();
+        if self.image_scene.is_some() {
+            paths.push("/scene.png");
+        }
+        if self.svg_boundaries.is_some() {
+            paths.push("/scene.svg");
+        }
+        #[cfg(feature = "scripting")]
+        {
+            if self.scene_script.is_some() {
+                paths.push("/scene.rhai");
+            }
+        }
+        paths
+    }
+
+    fn read_scene_file(ctx: &mut Context, path: &str) -> Option<Vec<u8>> {
+        let mut file = ggez::filesystem::open(ctx, path).ok()?;
+        let mut content = Vec::new();
+        std::io::Read::read_to_end(&mut file, &mut content).ok()?;
+        Some(content)
+    }
+
+    // Starts watching the scene files over with their current contents, after they were (re)loaded.
+    fn watch_scene_files(&mut self, ctx: &mut Context) {
+        let scene_files = self.scene_files();
+        self.scene_watcher.clear();
+        self.scene_watcher.check(&scene_files, |path| Self::read_scene_file(ctx, path));
+    }
+
+    // Loads the files of the current scene again and starts the simulation over with them.
+    // Camera, solver and all other settings stay as they are. Files that fail to load keep their previous version.
+    fn reload_scene_files(&mut self, ctx: &mut Context) {
+        if self.image_scene.is_some() {
+            match Self::load_image_scene(ctx) {
+                Ok(image_scene) => self.image_scene = Some(image_scene),
+                Err(err) => println!("Failed to reload scene.png, keeping the previous version: {}", err),
+            }
+        }
+        if self.svg_boundaries.is_some() {
+            match Self::load_svg_boundaries(ctx) {
+                Ok(svg_boundaries) => self.svg_boundaries = Some(svg_boundaries),
+                Err(err) => println!("Failed to reload scene.svg, keeping the previous version: {}", err),
+            }
+        }
+        #[cfg(feature = "scripting")]
+        {
+            if self.scene_script.is_some() {
+                match Self::load_scene_script(ctx) {
+                    Ok(scene_script) => self.scene_script = Some(scene_script),
+                    Err(err) => println!("Failed to reload scene.rhai, keeping the previous version: {}", err),
+                }
+            }
+        }
+        self.watch_scene_files(ctx);
+        self.reset_simulation();
+    }
+
+    // Scene events from scene.rhai in the resource or user data directory, see sph::SceneScript.
+    #[cfg(feature = "scripting")]
+    fn load_scene_script(ctx: &mut Context) -> GameResult<(String, sph::SceneScript)> {
+        let mut file = ggez::filesystem::open(ctx, "/scene.rhai")?;
+        let mut source = String::new();
+        std::io::Read::read_to_string(&mut file, &mut source)?;
+        let scene_script = sph::SceneScript::new(&source)?;
+        Ok((source, scene_script))
+    }
+
+    fn capture_gif_frame(&mut self, ctx: &mut Context) -> GameResult {
+        let gif_recorder = match &mut self.gif_recorder {
+            Some(gif_recorder) => gif_recorder,
+            None => return Ok(()),
+        };
+        if gif_recorder.wants_frame() {
+            microprofile::scope!("MainState", "gif frame");
+            let image = graphics::screenshot(ctx)?;
+            let rgba = image.to_rgba8(ctx)?;
+            gif_recorder.add_frame(&rgba, image.width() as usize, image.height() as usize);
+        }
+        if gif_recorder.is_done() {
+            let mut file = ggez::filesystem::create(ctx, "/recording.gif")?;
+            gif_recorder.write(&mut file)?;
+            println!("Saved recording.gif");
+            self.gif_recorder = None;
+        }
+        Ok(())
+    }
+
+    fn write_probes_csv(&self, ctx: &mut Context) -> GameResult {
+        let mut file = ggez::filesystem::create(ctx, "/probes.csv")?;
+        writeln!(file, "# {}", self.clock)?;
+        self.probes.write_csv(&mut file)?;
+        Ok(())
+    }
+
+    // Changes the time scale without jumps in simulation time, i.e. only simulation time from now on passes faster or slower.
+    fn set_time_scale(&mut self, time_scale: Real) {
+        let time_scale = time_scale.clamp(TIME_SCALE_MIN, TIME_SCALE_MAX);
+        let real_time = (Instant::now() - self.simulation_starttime).as_secs_f32() * REALTIME_TO_SIMTIME_SCALE;
+        self.simulation_to_realtime_offset += real_time * (time_scale - self.time_scale);
+        self.time_scale = time_scale;
+    }
+
+    // Moves world, solver and time manager to a SimulationThread, so that slow steps don't hold up the window.
+    // fluid_world and sph_solver are replaced by copies that show the latest snapshot of the thread.
+    // Meanwhile tools, scene scripts, probes and the comparison world are paused, solver specific visualizations aren't available.
+    fn start_background_simulation(&mut self) {
+        if self.update_mode != UpdateMode::RealTime {
+            println!("Background simulation is only available in real time mode");
+            return;
+        }
+        let display_world = self.fluid_world.copy_for_display();
+        let display_solver = self.solver_config.create_solver(&display_world);
+        let display_time_manager = Self::create_time_manager(self.solver_config.cfl_factor());
+        let fluid_world = std::mem::replace(&mut self.fluid_world, display_world);
+        let sph_solver = std::mem::replace(&mut self.sph_solver, display_solver);
+        let time_manager = std::mem::replace(&mut self.time_manager, display_time_manager);
+        self.background_simulation = Some(BackgroundSimulation {
+            thread: sph::SimulationThread::spawn(fluid_world, sph_solver, time_manager),
+            snapshot: Default::default(),
+        });
+        // particle indices of the display copy change with every snapshot
+        self.particle_inspector_tool.deselect();
+    }
+
+    // Takes world, solver and time manager back from the simulation thread, if there is one.
+    fn stop_background_simulation(&mut self) {
+        if let Some(background_simulation) = self.background_simulation.take() {
+            let (fluid_world, sph_solver, time_manager) = background_simulation.thread.join();
+            self.fluid_world = fluid_world;
+            self.sph_solver = sph_solver;
+            self.time_manager = time_manager;
+            self.particle_inspector_tool.deselect();
+        }
+    }
+
+    fn update_background_simulation(&mut self) {
+        let target_simulation_time = self.realtime_target_simulation_time();
+        let background_simulation = match &mut self.background_simulation {
+            Some(background_simulation) => background_simulation,
+            None => return,
+        };
+        background_simulation.thread.set_target_time(target_simulation_time);
+        let previous_num_steps = background_simulation.snapshot.clock.num_steps();
+        if !background_simulation.thread.latest_snapshot(&mut background_simulation.snapshot) {
+            return;
+        }
+        let snapshot = &background_simulation.snapshot;
+        snapshot.apply(&mut self.fluid_world);
+        self.simulationstep_count_frame = (snapshot.clock.num_steps() - previous_num_steps) as u32;
+        if self.simulation_step_duration_history.len() == SIMULATION_STEP_HISTORY_LENGTH {
+            self.simulation_step_duration_history.pop_front();
+        }
+        self.simulation_step_duration_history.push_back(snapshot.step_processing_time);
+        // halted simulations are reported from the main thread
+        if let Some(err) = &snapshot.solver_error {
+            println!("Simulation halted: {}", err);
+            self.solver_error = Some(err.clone());
+        }
+        if snapshot.instability.is_some() || snapshot.solver_error.is_some() {
+            self.stop_background_simulation();
+        }
+    }
+
+    // Simulation time that corresponds to the current real time, see UpdateMode::RealTime.
+    fn realtime_target_simulation_time(&self) -> Real {
+        (Instant::now() - self.simulation_starttime).as_secs_f32() * REALTIME_TO_SIMTIME_SCALE * self.time_scale - self.simulation_to_realtime_offset
+    }
+
+    // Passed time of the simulation, wherever it runs.
+    fn simulated_time(&self) -> Real {
+        match &self.background_simulation {
+            Some(background_simulation) => background_simulation.snapshot.passed_time,
+            None => self.time_manager.passed_time(),
+        }
+    }
+
     fn reset_simulation(&mut self) {
-        self.sph_solver.clear_cached_data(); // todo: this is super meh
+        self.stop_background_simulation();
+        self.sph_solver.clear_cached_state();
         self.simulation_starttime = Instant::now();
         self.simulation_to_realtime_offset = 0.0;
-        self.simulation_processing_time_total = Default::default();
+        self.clock.restart();
 
         self.frame_counter = 0;
         self.time_manager.restart();
-        Self::reset_fluid(&mut self.fluid_world);
+        self.probes.clear_samples();
+        self.statistics_history.clear();
+        if let Some(watchdog) = self.sph_solver.watchdog_mut() {
+            watchdog.reset();
+        }
+        self.instability_reported = false;
+        self.solver_error = None;
+        Self::reset_fluid(
+            &mut self.fluid_world,
+            self.blue_noise_fluid,
+            self.image_scene.as_ref(),
+            self.svg_boundaries.as_ref(),
+        );
+        self.particle_inspector_tool.deselect();
+        self.boundary_draw_tool.restore(&mut self.fluid_world);
+        self.obstacle_tool.restore(&mut self.fluid_world);
+        self.floating_box_tool.restore(&mut self.fluid_world);
+        // after everything was added, so fluid gets pushed out of restored obstacles as well
+        if let Err(err) = self.fluid_world.relax_initial_state() {
+            println!("Failed to relax initial state: {}", err);
+        }
+        self.tracer_trails.seed(&self.fluid_world);
+        self.comparison = self.comparison_solver.map(|solver| self.create_comparison(solver));
+        let mut scene_warnings = self.fluid_world.validate();
+        scene_warnings.extend(sph::validate_timestep(&self.fluid_world, self.sph_solver.as_ref(), &self.time_manager));
+        for warning in scene_warnings {
+            println!("Warning: {}", warning);
+        }
+        #[cfg(feature = "scripting")]
+        {
+            // events already fired, the script needs to start over
+            if let Some((source, _)) = &self.scene_script {
+                self.scene_script = match sph::SceneScript::new(source) {
+                    Ok(scene_script) => Some((source.clone(), scene_script)),
+                    Err(err) => {
+                        println!("{}", err);
+                        None
+                    }
+                };
+            }
+        }
     }
 }
 
 impl EventHandler for MainState {
-    fn key_down_event(&mut self, ctx: &mut Context, keycode: KeyCode, _keymods: KeyMods, repeat: bool) {
+    fn key_down_event(&mut self, ctx: &mut Context, keycode: KeyCode, keymods: KeyMods, repeat: bool) {
+        // everything but toggling it needs the simulation back from the background thread
+        if keycode != KeyCode::H {
+            self.stop_background_simulation();
+        }
         match keycode {
             KeyCode::Escape => {
                 ggez::event::quit(ctx);
             }
+            KeyCode::H => {
+                if !repeat {
+                    if self.background_simulation.is_some() {
+                        self.stop_background_simulation();
+                    } else {
+                        self.start_background_simulation();
+                    }
+                }
+            }
             KeyCode::Space => {
                 self.reset_simulation();
             }
+            KeyCode::Add | KeyCode::Equals => {
+                self.set_time_scale(self.time_scale * TIME_SCALE_STEP);
+            }
+            KeyCode::Subtract | KeyCode::Minus => {
+                self.set_time_scale(self.time_scale / TIME_SCALE_STEP);
+            }
             KeyCode::R => {
                 if !repeat {
                     self.update_mode = if self.update_mode == UpdateMode::RealTime {
@@ -305,19 +1961,312 @@ impl EventHandler for MainState {
                     self.reset_simulation();
                 }
             }
+            KeyCode::F => {
+                // fluid brush, continously applied in update
+            }
+            KeyCode::B => {
+                if !repeat {
+                    // leaving the mode keeps what was drawn so far
+                    if self.boundary_draw_tool.active {
+                        self.boundary_draw_tool.commit(&mut self.fluid_world);
+                    }
+                    self.boundary_draw_tool.active = !self.boundary_draw_tool.active;
+                }
+            }
+            KeyCode::C => {
+                self.visualization_mode = self.visualization_mode.next();
+            }
+            KeyCode::M => {
+                let num_color_maps = self.color_maps.len();
+                let settings = self.visualization_settings();
+                settings.color_map = (settings.color_map + 1) % num_color_maps;
+            }
+            KeyCode::A => {
+                let settings = self.visualization_settings();
+                settings.auto_range = !settings.auto_range;
+            }
+            KeyCode::P => {
+                self.renderer.show_boundary_particles = !self.renderer.show_boundary_particles;
+            }
+            KeyCode::V => {
+                self.renderer.show_velocity_glyphs = !self.renderer.show_velocity_glyphs;
+            }
+            KeyCode::S => {
+                self.renderer.next_particle_renderer();
+            }
+            KeyCode::G => {
+                self.background_field_renderer.field = self.background_field_renderer.field.next();
+            }
+            KeyCode::L => {
+                self.streamlines.enabled = !self.streamlines.enabled;
+            }
+            KeyCode::O => {
+                self.timings_csv = match self.timings_csv {
+                    Some(_) => None,
+                    None => match Self::create_timings_csv(ctx) {
+                        Ok(file) => Some(file),
+                        Err(err) => {
+                            println!("Failed to create timings.csv: {}", err);
+                            None
+                        }
+                    },
+                };
+            }
+            KeyCode::J => {
+                self.trajectory = match self.trajectory.take() {
+                    Some(mut trajectory) => {
+                        if let Err(err) = trajectory.flush() {
+                            println!("Failed to write trajectory.ysph: {}", err);
+                        }
+                        None
+                    }
+                    None => match Self::create_trajectory(ctx, &self.fluid_world) {
+                        Ok(trajectory) => Some(trajectory),
+                        Err(err) => {
+                            println!("Failed to create trajectory.ysph: {}", err);
+                            None
+                        }
+                    },
+                };
+            }
+            KeyCode::K => {
+                // K places a probe at the cursor, with shift all recorded probe samples are saved.
+                if keymods.contains(KeyMods::SHIFT) {
+                    match self.write_probes_csv(ctx) {
+                        Ok(()) => println!("Wrote {} probe samples to probes.csv", self.probes.num_samples()),
+                        Err(err) => println!("Failed to write probes.csv: {}", err),
+                    }
+                } else if !repeat {
+                    let cursor_position = ggez::input::mouse::position(ctx);
+                    let position = self.camera.screen_to_world_coords(RenderPoint::new(cursor_position.x, cursor_position.y));
+                    let name = format!("probe{}", self.probes.probes().len());
+                    self.probes.add(&name, Point::new(position.x, position.y));
+                }
+            }
+            KeyCode::W => {
+                self.solver_config.watchdog = !self.solver_config.watchdog;
+                let watchdog = if self.solver_config.watchdog {
+                    Some(sph::Watchdog::new(WATCHDOG_MAX_VELOCITY))
+                } else {
+                    None
+                };
+                self.sph_solver.set_watchdog(watchdog);
+                self.instability_reported = false;
+            }
+            KeyCode::X => {
+                if !repeat {
+                    let cursor_position = ggez::input::mouse::position(ctx);
+                    let position = self.camera.screen_to_world_coords(RenderPoint::new(cursor_position.x, cursor_position.y));
+                    self.floating_box_tool.drop(&mut self.fluid_world, Point::new(position.x, position.y));
+                }
+            }
+            KeyCode::I => {
+                // I switches between the default scene and scene.png, blue pixels are fluid and black ones boundary.
+                // With shift, the default scene's obstacles are switched with the shapes of scene.svg.
+                if keymods.contains(KeyMods::SHIFT) && !repeat {
+                    self.svg_boundaries = match self.svg_boundaries {
+                        Some(_) => None,
+                        None => match Self::load_svg_boundaries(ctx) {
+                            Ok(svg_boundaries) => Some(svg_boundaries),
+                            Err(err) => {
+                                println!("Failed to load scene.svg: {}", err);
+                                None
+                            }
+                        },
+                    };
+                    self.watch_scene_files(ctx);
+                    self.reset_simulation();
+                } else if !repeat {
+                    self.image_scene = match self.image_scene {
+                        Some(_) => None,
+                        None => match Self::load_image_scene(ctx) {
+                            Ok(image_scene) => Some(image_scene),
+                            Err(err) => {
+                                println!("Failed to load scene.png: {}", err);
+                                None
+                            }
+                        },
+                    };
+                    self.watch_scene_files(ctx);
+                    self.reset_simulation();
+                }
+            }
+            #[cfg(feature = "scripting")]
+            KeyCode::E => {
+                // E starts and stops the scene events of scene.rhai, both restart the simulation.
+                if !repeat {
+                    self.scene_script = match self.scene_script {
+                        Some(_) => None,
+                        None => match Self::load_scene_script(ctx) {
+                            Ok(scene_script) => Some(scene_script),
+                            Err(err) => {
+                                println!("Failed to load scene.rhai: {}", err);
+                                None
+                            }
+                        },
+                    };
+                    self.watch_scene_files(ctx);
+                    self.reset_simulation();
+                }
+            }
+            KeyCode::F5 => {
+                // F5 rebuilds the world from the current versions of the loaded scene files, keeping camera and settings.
+                if !repeat {
+                    self.reload_scene_files(ctx);
+                }
+            }
+            KeyCode::F9 => {
+                // F9 records a short GIF of the fluid view in the current visualization mode, pressing it again cancels.
+                if !repeat {
+                    self.gif_recorder = match self.gif_recorder {
+                        Some(_) => None,
+                        None => Some(GifRecorder::new(GIF_FPS, Duration::from_secs_f32(GIF_DURATION), GIF_MAX_WIDTH)),
+                    };
+                }
+            }
+            KeyCode::Q => {
+                // Q toggles inspecting particles by clicking on them.
+                if !repeat {
+                    self.particle_inspector_tool.active = !self.particle_inspector_tool.active;
+                    self.particle_inspector_tool.deselect();
+                }
+            }
+            KeyCode::N => {
+                self.neighborhood_debug_view.enabled = !self.neighborhood_debug_view.enabled;
+            }
+            KeyCode::T => {
+                self.tracer_trails.enabled = !self.tracer_trails.enabled;
+                if self.tracer_trails.enabled {
+                    self.tracer_trails.seed(&self.fluid_world);
+                }
+            }
+            KeyCode::Tab => {
+                self.gui.visible = !self.gui.visible;
+            }
+            KeyCode::Return => {
+                self.boundary_draw_tool.commit(&mut self.fluid_world);
+            }
+            KeyCode::Back => {
+                self.boundary_draw_tool.remove_last_vertex();
+            }
+            KeyCode::Delete => {
+                self.boundary_draw_tool.discard_pending();
+            }
             _ => {
                 self.update_mode = UpdateMode::RealTime;
             }
         }
     }
 
-    fn update(&mut self, _ctx: &mut Context) -> GameResult {
+    fn mouse_button_down_event(&mut self, _ctx: &mut Context, button: MouseButton, x: f32, y: f32) {
+        // tools and gui act on the simulation
+        if button == MouseButton::Left
+            && (self.gui.wants_mouse()
+                || self.boundary_draw_tool.active
+                || self.obstacle_tool.active()
+                || self.particle_inspector_tool.active
+                || self.region_select_tool.active())
+        {
+            self.stop_background_simulation();
+        }
+        if button == MouseButton::Left && self.boundary_draw_tool.active && !self.gui.wants_mouse() {
+            let world_position = self.camera.screen_to_world_coords(RenderPoint::new(x, y));
+            self.boundary_draw_tool.add_vertex(Point::new(world_position.x, world_position.y));
+        } else if button == MouseButton::Left && self.obstacle_tool.active() && !self.gui.wants_mouse() {
+            self.obstacle_tool.start_drag();
+        } else if button == MouseButton::Left && self.particle_inspector_tool.active && !self.gui.wants_mouse() {
+            let world_position = self.camera.screen_to_world_coords(RenderPoint::new(x, y));
+            self.particle_inspector_tool
+                .select(&self.fluid_world, Point::new(world_position.x, world_position.y));
+        } else if button == MouseButton::Left && self.region_select_tool.active() && !self.gui.wants_mouse() {
+            let world_position = self.camera.screen_to_world_coords(RenderPoint::new(x, y));
+            self.region_select_tool.start_drag(Point::new(world_position.x, world_position.y));
+        }
+    }
+
+    fn mouse_button_up_event(&mut self, _ctx: &mut Context, button: MouseButton, x: f32, y: f32) {
+        if button == MouseButton::Left && self.obstacle_tool.dragging() {
+            let world_position = self.camera.screen_to_world_coords(RenderPoint::new(x, y));
+            self.obstacle_tool
+                .drop(&mut self.fluid_world, Point::new(world_position.x, world_position.y));
+        } else if button == MouseButton::Left && self.region_select_tool.dragging() {
+            let world_position = self.camera.screen_to_world_coords(RenderPoint::new(x, y));
+            let num_particles = self.fluid_world.particles.positions.len();
+            self.region_select_tool.drop(
+                &mut self.fluid_world,
+                self.sph_solver.as_mut(),
+                Point::new(world_position.x, world_position.y),
+            );
+            // particle indices changed
+            if self.fluid_world.particles.positions.len() != num_particles {
+                self.particle_inspector_tool.deselect();
+            }
+        }
+    }
+
+    fn mouse_motion_event(&mut self, ctx: &mut Context, _x: f32, _y: f32, dx: f32, dy: f32) {
+        if ggez::input::mouse::button_pressed(ctx, MouseButton::Right) {
+            self.camera.pan(RenderSize::new(dx, dy));
+        }
+    }
+
+    fn mouse_wheel_event(&mut self, ctx: &mut Context, _x: f32, y: f32) {
+        let cursor_position = ggez::input::mouse::position(ctx);
+        self.camera
+            .zoom_around_screen_point(RenderPoint::new(cursor_position.x, cursor_position.y), CAMERA_ZOOM_PER_WHEEL_STEP.powf(y));
+    }
+
+    fn resize_event(&mut self, ctx: &mut Context, width: f32, height: f32) {
+        // By default ggez keeps the old screen coordinates and stretches them over the new window.
+        let screen = Rect::new(0.0, 0.0, width, height);
+        graphics::set_screen_coordinates(ctx, screen).unwrap();
+        let screen = self.main_view_screen(screen);
+        self.camera.resize_screen(screen);
+    }
+
+    fn update(&mut self, ctx: &mut Context) -> GameResult {
         microprofile::scope!("MainState", "update");
 
+        let scene_files = self.scene_files();
+        self.scene_watcher.poll(&scene_files, |path| Self::read_scene_file(ctx, path));
+
+        let cursor_position = ggez::input::mouse::position(ctx);
+        let cursor_world_position = self.camera.screen_to_world_coords(RenderPoint::new(cursor_position.x, cursor_position.y));
+        let cursor_world_position = Point::new(cursor_world_position.x, cursor_world_position.y);
+
+        // Holding F paints fluid.
+        if ggez::input::keyboard::is_key_pressed(ctx, KeyCode::F) {
+            self.fluid_brush_tool.apply(&mut self.fluid_world, cursor_world_position);
+        }
+
+        // Left mouse attracts fluid, with shift it repels. (unless we're placing boundaries or obstacles, inspecting or selecting particles)
+        self.force_tool_target = if ggez::input::mouse::button_pressed(ctx, MouseButton::Left)
+            && self.background_simulation.is_none()
+            && !self.boundary_draw_tool.active
+            && !self.obstacle_tool.active()
+            && !self.particle_inspector_tool.active
+            && !self.region_select_tool.active()
+            && !self.gui.wants_mouse()
+        {
+            let direction = if ggez::input::keyboard::is_mod_active(ctx, KeyMods::SHIFT) {
+                -1.0
+            } else {
+                1.0
+            };
+            Some((cursor_world_position, direction))
+        } else {
+            None
+        };
+
         self.simulationstep_count_frame = 0;
+        self.simulation_pass_timings_frame.clear();
         self.simulation_processing_time_frame = Duration::from_secs(0);
+        let simulation_time_before_frame = self.simulated_time();
 
         match self.update_mode {
+            _ if self.background_simulation.is_some() => {
+                self.update_background_simulation();
+            }
             UpdateMode::RealTime => {
                 // Note that we _could_ influence the simulation timestep target every frame depending on the delta frame time.
                 // However, that would make our simulation dependend on external, non-deterministic factors and we don't want that.
@@ -325,9 +2274,8 @@ impl EventHandler for MainState {
                     *timestep_target_frame = sph::AdaptiveTimeStepTarget::None;
                 }
 
-                let target_simulation_time =
-                    (Instant::now() - self.simulation_starttime).as_secs_f32() * REALTIME_TO_SIMTIME_SCALE - self.simulation_to_realtime_offset;
-                while self.time_manager.passed_time() < target_simulation_time {
+                let target_simulation_time = self.realtime_target_simulation_time();
+                while self.time_manager.passed_time() < target_simulation_time && !self.simulation_halted() {
                     //if self.time_manager.passed_time() > 2.0 {
                     //    break;
                     //}
@@ -356,12 +2304,47 @@ impl EventHandler for MainState {
                     epsilon = 1.0e-9;
                 }
                 let target_simulation_time = self.frame_counter as Real * TARGET_FRAME_SIMDURATION - epsilon;
-                while self.time_manager.passed_time() < target_simulation_time {
+                while self.time_manager.passed_time() < target_simulation_time && !self.simulation_halted() {
                     self.single_sim_step();
                 }
             }
         }
 
+        if self.simulation_halted() && !self.instability_reported {
+            self.report_instability(ctx);
+        }
+
+        self.statistics = sph::SimulationStatistics::gather(&self.fluid_world, self.sph_solver.as_ref(), &self.time_manager);
+        let neighbor_count_warning = self.statistics.neighbor_counts.warning();
+        if let (Some(warning), false) = (&neighbor_count_warning, self.neighbor_count_warning_logged) {
+            println!("Warning: {}", warning);
+        }
+        self.neighbor_count_warning_logged = neighbor_count_warning.is_some();
+        if self.simulationstep_count_frame > 0 {
+            self.statistics_history.record(&self.statistics, self.simulation_processing_time_frame);
+            let simulated_time = self.simulated_time();
+            if let Some(trajectory) = &mut self.trajectory {
+                if let Err(err) = trajectory.record(&self.fluid_world, simulated_time) {
+                    println!("Failed to write trajectory.ysph: {}", err);
+                    self.trajectory = None;
+                }
+            }
+            if let Err(err) = self.export_grid(ctx, simulated_time) {
+                println!("Failed to export grid: {}", err);
+                self.grid_export = None;
+            }
+            if let Err(err) = self.export_particles(ctx) {
+                println!("Failed to export particles: {}", err);
+                self.particle_export = None;
+            }
+            if let Err(err) = self.export_density(ctx) {
+                println!("Failed to export density: {}", err);
+                self.density_export = None;
+            }
+        }
+        self.tracer_trails
+            .advance(&self.fluid_world, self.simulated_time() - simulation_time_before_frame);
+
         microprofile::flip!();
         Ok(())
     }
@@ -369,12 +2352,20 @@ impl EventHandler for MainState {
     fn draw(&mut self, ctx: &mut Context) -> GameResult {
         microprofile::scope!("MainState", "draw");
 
-        graphics::clear(ctx, [0.4, 0.4, 0.45, 1.0].into());
-        graphics::push_transform(ctx, Some(self.camera.transformation_matrix()));
-        graphics::apply_transformations(ctx)?;
+        graphics::clear(ctx, BACKGROUND_COLOR.into());
 
-        self.draw_fluid(ctx)?;
-        self.draw_text(ctx)?;
+        let visualization = self.visualization_values();
+        self.draw_scene(ctx, &visualization)?;
+        self.draw_comparison(ctx, &visualization)?;
+        self.draw_legend(ctx, &visualization)?;
+        // without text and gui on top
+        self.capture_gif_frame(ctx)?;
+        let overlay_text = self.overlay_text(ctx);
+        self.renderer.draw_text_blocks(ctx, &overlay_text)?;
+        if let Some(residuals) = &self.statistics.solver_residuals {
+            self.renderer.draw_solver_residuals(ctx, residuals)?;
+        }
+        self.draw_gui(ctx)?;
 
         {
             microprofile::scope!("MainState", "present");
@@ -0,0 +1,134 @@
+use cgmath::prelude::*;
+use ggez::graphics::{self, Rect};
+use ggez::{Context, GameResult};
+use yasph2d::sph;
+use yasph2d::units::*;
+
+use crate::camera::*;
+
+// Statistics of the last neighbor query.
+#[derive(Default, Clone, Copy)]
+pub struct NeighborQueryStats {
+    pub visited_cells: usize,       // cells looked at, including those skipped over
+    pub potential_neighbors: usize, // particles in the 3x3 cell box
+    pub neighbors: usize,           // potential neighbors within the smoothing length
+}
+
+// Debug overlay for the neighborhood search grid.
+// Shows all grid cells that contain particles and runs a potential neighbor query at a given position,
+// highlighting the cells it visits and the neighbors it finds.
+pub struct NeighborhoodDebugView {
+    pub enabled: bool,
+    pub last_query: NeighborQueryStats,
+}
+
+const LINE_WIDTH: f32 = 0.002;
+
+fn cell_rect(neighborhood: &sph::neighborhood_search::NeighborhoodSearch, cidx: sph::neighborhood_search::MortonCellIndex) -> Rect {
+    let (min, max) = neighborhood.cell_bounds(cidx);
+    Rect::new(min.x, min.y, max.x - min.x, max.y - min.y)
+}
+
+impl NeighborhoodDebugView {
+    pub fn new() -> NeighborhoodDebugView {
+        NeighborhoodDebugView {
+            enabled: false,
+            last_query: Default::default(),
+        }
+    }
+
+    // Expects the camera transformation to be active.
+    pub fn draw(&mut self, ctx: &mut Context, camera: &Camera, fluid_world: &sph::FluidParticleWorld, query_position: Point) -> GameResult {
+        if !self.enabled {
+            return Ok(());
+        }
+        microprofile::scope!("NeighborhoodDebugView", "draw");
+
+        let neighborhood = fluid_world.particles.neighborhood();
+        if neighborhood.num_particles() == 0 {
+            self.last_query = Default::default();
+            return Ok(());
+        }
+        let positions = &fluid_world.particles.positions;
+        let smoothing_length = fluid_world.properties.smoothing_length();
+        let particle_radius = fluid_world.properties.particle_radius();
+        let mut mesh_builder = graphics::MeshBuilder::new();
+
+        // occupied cells
+        let world_rect = camera.visible_world_rect();
+        neighborhood.foreach_particle_cell(|cidx, _| {
+            let rect = cell_rect(neighborhood, cidx);
+            if rect.overlaps(&world_rect) {
+                mesh_builder.rectangle(graphics::DrawMode::stroke(LINE_WIDTH), rect, graphics::Color::new(0.5, 0.5, 0.5, 0.5));
+            }
+        });
+
+        // cells visited by the query, the ones outside the 3x3 box are wasted effort
+        let query_cell = cell_rect(neighborhood, neighborhood.cell_index(query_position));
+        let neighbor_box = Rect::new(
+            query_cell.x - query_cell.w,
+            query_cell.y - query_cell.h,
+            query_cell.w * 3.0,
+            query_cell.h * 3.0,
+        );
+        let mut visited_cells = 0;
+        neighborhood.foreach_cell_visited_by_potential_neighbor_query(query_position, |cidx| {
+            let rect = cell_rect(neighborhood, cidx);
+            let color = if neighbor_box.contains(RenderPoint::new(rect.x + rect.w * 0.5, rect.y + rect.h * 0.5)) {
+                graphics::Color::new(0.2, 0.8, 0.2, 0.25)
+            } else {
+                graphics::Color::new(1.0, 0.3, 0.1, 0.25)
+            };
+            mesh_builder.rectangle(graphics::DrawMode::fill(), rect, color);
+            visited_cells += 1;
+        });
+        mesh_builder.rectangle(
+            graphics::DrawMode::stroke(LINE_WIDTH * 2.0),
+            neighbor_box,
+            graphics::Color::new(1.0, 1.0, 0.2, 1.0),
+        );
+        mesh_builder.circle(
+            graphics::DrawMode::stroke(LINE_WIDTH),
+            RenderPoint::new(query_position.x, query_position.y),
+            smoothing_length,
+            0.001,
+            graphics::WHITE,
+        );
+
+        // potential and actual neighbors
+        let mut potential_neighbors = 0;
+        let mut neighbors = 0;
+        neighborhood.foreach_potential_neighbor(query_position, |j| {
+            let position = match positions.get(j) {
+                Some(position) => *position,
+                None => return, // removed since the last neighborhood update
+            };
+            let is_neighbor = position.distance2(query_position) <= smoothing_length * smoothing_length;
+            let color = if is_neighbor {
+                graphics::Color::new(1.0, 1.0, 0.2, 1.0)
+            } else {
+                graphics::Color::new(0.6, 0.6, 0.6, 1.0)
+            };
+            mesh_builder.circle(
+                graphics::DrawMode::fill(),
+                RenderPoint::new(position.x, position.y),
+                particle_radius * 0.5,
+                0.001,
+                color,
+            );
+            potential_neighbors += 1;
+            if is_neighbor {
+                neighbors += 1;
+            }
+        });
+
+        self.last_query = NeighborQueryStats {
+            visited_cells,
+            potential_neighbors,
+            neighbors,
+        };
+
+        let mesh = mesh_builder.build(ctx)?;
+        graphics::draw(ctx, &mesh, graphics::DrawParam::default())
+    }
+}
@@ -0,0 +1,250 @@
+// Python bindings of the simulation core, only with the "python" feature.
+// Built as extension module with maturin (https://www.maturin.rs), see pyproject.toml:
+//
+//   maturin develop --release
+//
+//   import yasph2d
+//   simulation = yasph2d.Simulation(particle_density=5000.0, fluid_density=1000.0, solver="dfsph")
+//   simulation.add_fluid_rect(0.0, 0.0, 0.5, 1.0)
+//   simulation.add_boundary_thick_line((0.0, 0.0), (2.0, 0.0), 3)
+//   simulation.relax_initial_state()
+//   simulation.step_until(2.0)
+//   positions = simulation.positions()  # float32 array of shape (n, 2)
+//
+// Particle arrays are handed out as NumPy copies, since every step reorders the particles and may reallocate their arrays.
+// Writing goes through the setters, e.g. set_velocities.
+
+use crate::sph;
+use crate::units::*;
+use cgmath::prelude::*;
+use numpy::ndarray::ArrayView2;
+use numpy::{PyArray1, PyArray2, PyReadonlyArray2};
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+
+impl From<sph::SphError> for PyErr {
+    fn from(err: sph::SphError) -> PyErr {
+        match err {
+            sph::SphError::NonFinite { .. } => PyRuntimeError::new_err(err.to_string()),
+            _ => PyValueError::new_err(err.to_string()),
+        }
+    }
+}
+
+fn point((x, y): (Real, Real)) -> Point {
+    Point::new(x, y)
+}
+
+// (n, 2) array with a row per pair.
+fn pairs_to_numpy<'py>(py: Python<'py>, pairs: impl ExactSizeIterator<Item = (Real, Real)>) -> PyResult<&'py PyArray2<Real>> {
+    let num_rows = pairs.len();
+    let mut values = Vec::with_capacity(num_rows * 2);
+    for (x, y) in pairs {
+        values.push(x);
+        values.push(y);
+    }
+    PyArray1::from_vec(py, values).reshape([num_rows, 2])
+}
+
+fn numpy_to_vectors(what: &'static str, array: &ArrayView2<Real>, expected_rows: usize) -> PyResult<Vec<Vector>> {
+    if array.ncols() != 2 {
+        return Err(PyValueError::new_err(format!("{} need 2 columns, got {}", what, array.ncols())));
+    }
+    if array.nrows() != expected_rows {
+        return Err(sph::SphError::LengthMismatch {
+            what,
+            expected: expected_rows,
+            actual: array.nrows(),
+        }
+        .into());
+    }
+    Ok(array.rows().into_iter().map(|row| Vector::new(row[0], row[1])).collect())
+}
+
+// A fluid world together with its solver and time manager, see sph::Simulation.
+#[pyclass(unsendable, name = "Simulation")]
+struct PySimulation {
+    simulation: sph::Simulation,
+}
+
+#[pymethods]
+impl PySimulation {
+    // Solver is "dfsph" or "wcsph". Without a fixed timestep, the timestep adapts to the fluid velocities.
+    #[new]
+    #[pyo3(signature = (particle_density, fluid_density, smoothing_factor = 2.0, solver = "dfsph", timestep = None))]
+    fn new(particle_density: Real, fluid_density: Real, smoothing_factor: Real, solver: &str, timestep: Option<Real>) -> PyResult<Self> {
+        // negated, so that NaN is rejected as well
+        if !(particle_density > 0.0 && fluid_density > 0.0 && smoothing_factor > 0.0) {
+            return Err(PyValueError::new_err(
+                "particle_density, fluid_density and smoothing_factor need to be positive",
+            ));
+        }
+        if matches!(timestep, Some(timestep) if !(timestep > 0.0)) {
+            return Err(PyValueError::new_err("timestep needs to be positive"));
+        }
+        let fluid_world = sph::FluidParticleWorld::new(smoothing_factor, NumberDensity(particle_density), Density(fluid_density));
+        let smoothing_length = fluid_world.properties.smoothing_length();
+        let viscosity_model = sph::XSPHViscosityModel::new(smoothing_length);
+        let (solver, cfl_factor): (Box<dyn sph::Solver>, Real) = match solver {
+            "dfsph" => (Box::new(sph::DFSPHSolver::new(viscosity_model, smoothing_length)), 1.0),
+            "wcsph" => (Box::new(sph::WCSPHSolver::new(viscosity_model, &fluid_world.properties)), 0.2),
+            _ => return Err(PyValueError::new_err(format!("unknown solver {:?}, use dfsph or wcsph", solver))),
+        };
+        let time_manager = sph::TimeManager::new(match timestep {
+            Some(timestep) => sph::TimeManagerConfiguration::FixedTimeStep(timestep),
+            None => sph::TimeManagerConfiguration::AdaptiveTimeStep {
+                timestep_max: 1.0 / 240.0,
+                timestep_min: 1.0 / (400.0 * 60.0),
+                timestep_target_frame: sph::AdaptiveTimeStepTarget::None,
+                cfl_factor,
+            },
+        });
+        Ok(PySimulation {
+            simulation: sph::Simulation::new(fluid_world, solver, time_manager),
+        })
+    }
+
+    #[pyo3(signature = (x, y, width, height, jitter = 0.0))]
+    fn add_fluid_rect(&mut self, x: Real, y: Real, width: Real, height: Real, jitter: Real) {
+        self.simulation.fluid_world.add_fluid_rect(&Rect::new(x, y, width, height), jitter);
+    }
+
+    // Returns the number of added particles.
+    fn add_fluid_circle(&mut self, center: (Real, Real), radius: Real) -> usize {
+        self.simulation.fluid_world.add_fluid_circle(point(center), radius)
+    }
+
+    #[pyo3(signature = (position, velocity = (0.0, 0.0)))]
+    fn add_fluid_particle(&mut self, position: (Real, Real), velocity: (Real, Real)) {
+        self.simulation
+            .fluid_world
+            .add_fluid_particle(point(position), Vector::new(velocity.0, velocity.1));
+    }
+
+    fn add_boundary_line(&mut self, start: (Real, Real), end: (Real, Real)) {
+        self.simulation.fluid_world.add_boundary_line(point(start), point(end));
+    }
+
+    // Additional layers go to the right of the line direction.
+    fn add_boundary_thick_line(&mut self, start: (Real, Real), end: (Real, Real), thickness_in_particles: u32) {
+        self.simulation
+            .fluid_world
+            .add_boundary_thick_line(point(start), point(end), thickness_in_particles);
+    }
+
+    fn add_boundary_polygon(&mut self, vertices: Vec<(Real, Real)>) {
+        let vertices: Vec<Point> = vertices.into_iter().map(point).collect();
+        self.simulation.fluid_world.add_boundary_polygon(&vertices);
+    }
+
+    fn add_boundary_circle(&mut self, center: (Real, Real), radius: Real) {
+        self.simulation.fluid_world.add_boundary_circle(point(center), radius);
+    }
+
+    fn set_gravity(&mut self, gravity: (Real, Real)) {
+        self.simulation.fluid_world.set_gravity(Vector::new(gravity.0, gravity.1));
+    }
+
+    fn relax_initial_state(&mut self) -> PyResult<()> {
+        self.simulation.fluid_world.relax_initial_state()?;
+        // particles were moved and reordered
+        self.simulation.solver.clear_cached_state();
+        Ok(())
+    }
+
+    // Problems with the scene that are likely to make it blow up, as messages. See sph::Simulation::validate.
+    fn validate(&self) -> Vec<String> {
+        self.simulation.validate().iter().map(|warning| warning.to_string()).collect()
+    }
+
+    fn step(&mut self) -> PyResult<()> {
+        Ok(self.simulation.step()?)
+    }
+
+    // Steps until the simulation reached the given time, without going back to Python in between. Returns the number of steps.
+    fn step_until(&mut self, py: Python, time: Real) -> PyResult<usize> {
+        let mut num_steps = 0;
+        while self.simulation.time_manager.passed_time() < time {
+            self.simulation.step()?;
+            num_steps += 1;
+            // lets Ctrl+C through on long runs
+            if num_steps % 100 == 0 {
+                py.check_signals()?;
+            }
+        }
+        Ok(num_steps)
+    }
+
+    #[getter]
+    fn time(&self) -> Real {
+        self.simulation.time_manager.passed_time()
+    }
+
+    // Of the last step.
+    #[getter]
+    fn timestep(&self) -> Real {
+        self.simulation.time_manager.timestep()
+    }
+
+    #[getter]
+    fn num_particles(&self) -> usize {
+        self.simulation.fluid_world.particles.positions.len()
+    }
+
+    #[getter]
+    fn particle_radius(&self) -> Real {
+        self.simulation.fluid_world.properties.particle_radius()
+    }
+
+    #[getter]
+    fn smoothing_length(&self) -> Real {
+        self.simulation.fluid_world.properties.smoothing_length()
+    }
+
+    fn positions<'py>(&self, py: Python<'py>) -> PyResult<&'py PyArray2<Real>> {
+        let positions = &self.simulation.fluid_world.particles.positions;
+        pairs_to_numpy(py, positions.iter().map(|p| (p.x, p.y)))
+    }
+
+    fn velocities<'py>(&self, py: Python<'py>) -> PyResult<&'py PyArray2<Real>> {
+        let velocities = &self.simulation.fluid_world.particles.velocities;
+        pairs_to_numpy(py, velocities.iter().map(|v| (v.x, v.y)))
+    }
+
+    // As of the last step, empty before the first one.
+    fn densities<'py>(&self, py: Python<'py>) -> &'py PyArray1<Real> {
+        PyArray1::from_slice(py, &self.simulation.fluid_world.particles.densities)
+    }
+
+    fn masses<'py>(&self, py: Python<'py>) -> &'py PyArray1<Real> {
+        PyArray1::from_slice(py, &self.simulation.fluid_world.particles.masses)
+    }
+
+    fn boundary_positions<'py>(&self, py: Python<'py>) -> PyResult<&'py PyArray2<Real>> {
+        let boundary_particles = &self.simulation.fluid_world.particles.boundary_particles;
+        pairs_to_numpy(py, boundary_particles.iter().map(|p| (p.x, p.y)))
+    }
+
+    // Takes an (n, 2) array with a row per fluid particle.
+    fn set_positions(&mut self, positions: PyReadonlyArray2<Real>) -> PyResult<()> {
+        let particles = &mut self.simulation.fluid_world.particles;
+        let positions = numpy_to_vectors("positions", &positions.as_array(), particles.positions.len())?;
+        particles.positions = positions.into_iter().map(Point::from_vec).collect();
+        // neighborhoods and everything the solver derived from them are outdated
+        self.simulation.solver.clear_cached_state();
+        Ok(())
+    }
+
+    // Takes an (n, 2) array with a row per fluid particle.
+    fn set_velocities(&mut self, velocities: PyReadonlyArray2<Real>) -> PyResult<()> {
+        let particles = &mut self.simulation.fluid_world.particles;
+        particles.velocities = numpy_to_vectors("velocities", &velocities.as_array(), particles.velocities.len())?;
+        Ok(())
+    }
+}
+
+#[pymodule]
+fn yasph2d(_py: Python, module: &PyModule) -> PyResult<()> {
+    module.add_class::<PySimulation>()?;
+    Ok(())
+}
@@ -0,0 +1,112 @@
+use cgmath::prelude::*;
+use std::collections::HashMap;
+use yasph2d::units::*;
+
+use super::particle_renderer::Rgba;
+
+// Spatially stratified subset of the fluid particles, keeping draw time bounded for huge particle counts.
+//
+// Particles are binned into square cells that hold `stride` particles of resting fluid each, with the stride chosen such that
+// roughly `max_particles` remain. Every cell keeps the particle closest to its center, which is drawn with a radius scaled by sqrt(stride)
+// to still cover the fluid. Sparse regions like splashes keep all their particles, so the budget is only met approximately.
+// Unlike picking every n-th particle, this doesn't flicker when the neighborhood search reorders particles.
+pub struct ParticleDecimation {
+    pub max_particles: Option<usize>, // None draws all particles
+
+    selected: Vec<(usize, Real)>,      // per cell the closest particle and its squared distance to the cell center
+    cells: HashMap<(i32, i32), usize>, // index into selected
+    positions: Vec<Point>,
+    colors: Vec<Rgba>,
+    num_drawn: usize,
+}
+
+impl ParticleDecimation {
+    pub fn new() -> ParticleDecimation {
+        ParticleDecimation {
+            max_particles: None,
+            selected: Vec::new(),
+            cells: HashMap::new(),
+            positions: Vec::new(),
+            colors: Vec::new(),
+            num_drawn: 0,
+        }
+    }
+
+    // Number of particles drawn by the last apply.
+    pub fn num_drawn(&self) -> usize {
+        self.num_drawn
+    }
+
+    // Positions, colors and radius to draw, either the given ones or a subset if there are more particles than max_particles.
+    // `radius` is the particle radius, i.e. half the particle spacing of resting fluid.
+    pub fn apply<'a>(&'a mut self, positions: &'a [Point], colors: &'a [Rgba], radius: Real) -> (&'a [Point], &'a [Rgba], Real) {
+        microprofile::scope!("ParticleDecimation", "apply");
+
+        let max_particles = match self.max_particles {
+            Some(max_particles) if positions.len() > max_particles => max_particles.max(1),
+            _ => {
+                self.num_drawn = positions.len();
+                return (positions, colors, radius);
+            }
+        };
+        let stride = positions.len() as Real / max_particles as Real;
+        let cell_size = 2.0 * radius * stride.sqrt();
+
+        self.selected.clear();
+        self.cells.clear();
+        for (i, p) in positions.iter().enumerate() {
+            let cell = ((p.x / cell_size).floor(), (p.y / cell_size).floor());
+            let center = Point::new(cell.0 + 0.5, cell.1 + 0.5) * cell_size;
+            let distance2 = p.distance2(center);
+            let selected = &mut self.selected;
+            let slot = *self.cells.entry((cell.0 as i32, cell.1 as i32)).or_insert_with(|| {
+                selected.push((i, Real::INFINITY));
+                selected.len() - 1
+            });
+            if distance2 < selected[slot].1 {
+                selected[slot] = (i, distance2);
+            }
+        }
+        // in particle order, so that overlapping particles are drawn on top of each other the same way as without decimation
+        self.selected.sort_unstable_by_key(|(i, _)| *i);
+
+        self.positions.clear();
+        self.colors.clear();
+        for (i, _) in self.selected.iter() {
+            self.positions.push(positions[*i]);
+            self.colors.push(colors[*i]);
+        }
+        self.num_drawn = self.positions.len();
+        (&self.positions, &self.colors, radius * stride.sqrt())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_roughly_the_budget_and_sparse_particles() {
+        // 100x100 lattice with a spacing of 2 radii and a particle far off
+        let radius = 0.005;
+        let mut positions: Vec<Point> = (0..10000)
+            .map(|i| Point::new((i % 100) as Real + 0.5, (i / 100) as Real + 0.5) * 2.0 * radius)
+            .collect();
+        positions.push(Point::new(10.0, 10.0));
+        let colors = vec![[1.0; 4]; positions.len()];
+
+        let mut decimation = ParticleDecimation::new();
+        let (drawn, _, drawn_radius) = decimation.apply(&positions, &colors, radius);
+        assert_eq!(drawn.len(), positions.len());
+        assert_eq!(drawn_radius, radius);
+
+        decimation.max_particles = Some(2500);
+        let (drawn, drawn_colors, drawn_radius) = decimation.apply(&positions, &colors, radius);
+        assert_eq!(drawn.len(), drawn_colors.len());
+        assert!(drawn.len() >= 2400 && drawn.len() <= 2700, "{} particles drawn", drawn.len());
+        assert!((drawn_radius - 2.0 * radius).abs() < 1.0e-3 * radius);
+        assert_eq!(*drawn.last().unwrap(), Point::new(10.0, 10.0));
+        let num_drawn = drawn.len();
+        assert_eq!(decimation.num_drawn(), num_drawn);
+    }
+}
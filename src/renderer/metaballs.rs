@@ -0,0 +1,146 @@
+use cgmath::prelude::*;
+use ggez::graphics::{self, Rect};
+use ggez::{Context, GameResult};
+use yasph2d::units::*;
+
+use crate::camera::*;
+use crate::clamp;
+
+// Renders fluid as a continuous surface instead of discrete particles.
+//
+// Particles are splatted as metaballs into a scalar field on a grid covering the visible world rectangle.
+// The field is then thresholded into an image with a soft edge which is stretched over the visible world rectangle.
+// Everything happens on the CPU, ggez 0.5 doesn't give us render targets with float formats.
+pub struct MetaballRenderer {
+    pub threshold: Real,
+    pub color: graphics::Color,
+
+    field: Vec<Real>,
+    field_width: usize,
+    field_height: usize,
+    pixels: Vec<u8>,
+}
+
+// Highest resolution of the field relative to the particle radius.
+const MIN_CELLS_PER_PARTICLE_RADIUS: Real = 2.0;
+// Lowest resolution of the field relative to the screen. There is no point in having more cells than pixels.
+const MIN_PIXELS_PER_CELL: Real = 2.0;
+// Radius of a metaball relative to the particle radius.
+const METABALL_RADIUS_FACTOR: Real = 4.0;
+// Relative width of the soft transition around the threshold.
+const EDGE_SOFTNESS: Real = 0.2;
+
+impl MetaballRenderer {
+    pub fn new() -> MetaballRenderer {
+        MetaballRenderer {
+            threshold: 0.5,
+            color: graphics::Color::new(0.2, 0.45, 0.9, 1.0),
+
+            field: Vec::new(),
+            field_width: 0,
+            field_height: 0,
+            pixels: Vec::new(),
+        }
+    }
+
+    // Draws the fluid surface. Expects the camera transformation to be active.
+    pub fn draw(&mut self, ctx: &mut Context, camera: &Camera, positions: &[Point], particle_radius: Real) -> GameResult {
+        microprofile::scope!("MetaballRenderer", "draw");
+
+        let world_rect = camera.visible_world_rect();
+        let cell_size = (particle_radius / MIN_CELLS_PER_PARTICLE_RADIUS).max(MIN_PIXELS_PER_CELL / camera.pixel_per_world_unit);
+        self.splat(world_rect, cell_size, positions, particle_radius * METABALL_RADIUS_FACTOR);
+        if self.field_width == 0 || self.field_height == 0 {
+            return Ok(());
+        }
+        self.shade();
+
+        let mut image = graphics::Image::from_rgba8(ctx, self.field_width as u16, self.field_height as u16, &self.pixels)?;
+        image.set_filter(graphics::FilterMode::Linear);
+        graphics::draw(
+            ctx,
+            &image,
+            graphics::DrawParam::default()
+                .dest(RenderPoint::new(world_rect.x, world_rect.y))
+                .scale(RenderSize::new(cell_size, cell_size)),
+        )
+    }
+
+    // Fills the field with the sum of all metaballs, sampled at cell centers.
+    // Metaball falloff is (1 - r²/R²)³, i.e. a single particle reaches 1.0 at its center.
+    fn splat(&mut self, world_rect: Rect, cell_size: Real, positions: &[Point], metaball_radius: Real) {
+        microprofile::scope!("MetaballRenderer", "splat");
+
+        self.field_width = ((world_rect.w / cell_size).ceil() as usize).min(u16::MAX as usize);
+        self.field_height = ((world_rect.h / cell_size).ceil() as usize).min(u16::MAX as usize);
+        self.field.clear();
+        self.field.resize(self.field_width * self.field_height, 0.0);
+
+        let radius_sq = metaball_radius * metaball_radius;
+        let radius_in_cells = (metaball_radius / cell_size).ceil() as isize;
+        let origin = Point::new(world_rect.x, world_rect.y);
+        for position in positions {
+            let center_cell_x = ((position.x - origin.x) / cell_size).floor() as isize;
+            let center_cell_y = ((position.y - origin.y) / cell_size).floor() as isize;
+            let min_x = (center_cell_x - radius_in_cells).max(0);
+            let max_x = (center_cell_x + radius_in_cells + 1).min(self.field_width as isize);
+            let min_y = (center_cell_y - radius_in_cells).max(0);
+            let max_y = (center_cell_y + radius_in_cells + 1).min(self.field_height as isize);
+
+            for y in min_y..max_y {
+                for x in min_x..max_x {
+                    let cell_center = origin + Vector::new((x as Real + 0.5) * cell_size, (y as Real + 0.5) * cell_size);
+                    let r_sq = cell_center.distance2(*position);
+                    if r_sq < radius_sq {
+                        let falloff = 1.0 - r_sq / radius_sq;
+                        self.field[y as usize * self.field_width + x as usize] += falloff * falloff * falloff;
+                    }
+                }
+            }
+        }
+    }
+
+    // Converts the field to pixels with a soft edge around the threshold.
+    // Slightly brightens the fluid towards its surface for a bit of depth.
+    fn shade(&mut self) {
+        microprofile::scope!("MetaballRenderer", "shade");
+
+        let edge_min = self.threshold * (1.0 - EDGE_SOFTNESS);
+        let edge_max = self.threshold * (1.0 + EDGE_SOFTNESS);
+        let color = self.color;
+
+        self.pixels.clear();
+        self.pixels.reserve(self.field.len() * 4);
+        for &value in self.field.iter() {
+            let t = clamp((value - edge_min) / (edge_max - edge_min), 0.0, 1.0);
+            let alpha = t * t * (3.0 - 2.0 * t);
+            let brightness = 1.0 + 0.3 * (1.0 - clamp(value / self.threshold - 1.0, 0.0, 1.0));
+            self.pixels.push(((color.r * brightness).min(1.0) * 255.0) as u8);
+            self.pixels.push(((color.g * brightness).min(1.0) * 255.0) as u8);
+            self.pixels.push(((color.b * brightness).min(1.0) * 255.0) as u8);
+            self.pixels.push((color.a * alpha * 255.0) as u8);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splat_covers_particles_only() {
+        let mut renderer = MetaballRenderer::new();
+        let world_rect = Rect::new(0.0, 0.0, 1.0, 1.0);
+        let cell_size = 0.01;
+        renderer.splat(world_rect, cell_size, &[Point::new(0.255, 0.255)], 0.1);
+
+        assert_eq!(renderer.field_width, 100);
+        assert_eq!(renderer.field_height, 100);
+        let field_at = |x: Real, y: Real| renderer.field[(y / cell_size) as usize * renderer.field_width + (x / cell_size) as usize];
+        assert!((field_at(0.255, 0.255) - 1.0).abs() < 1.0e-4);
+        assert!(field_at(0.255, 0.255) > renderer.threshold);
+        assert!(field_at(0.3, 0.255) < field_at(0.255, 0.255));
+        assert_eq!(field_at(0.5, 0.5), 0.0);
+        assert_eq!(field_at(0.255, 0.4), 0.0);
+    }
+}
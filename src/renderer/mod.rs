@@ -0,0 +1,446 @@
+use cgmath::prelude::*;
+use ggez::graphics::{self, Rect};
+use ggez::{Context, GameError, GameResult};
+use std::collections::HashSet;
+use yasph2d::sph;
+use yasph2d::units::*;
+
+use crate::camera::*;
+use crate::colormap::ColorMap;
+
+mod decimation;
+mod metaballs;
+mod particle_renderer;
+mod particles;
+
+pub use decimation::ParticleDecimation;
+use metaballs::MetaballRenderer;
+pub use particle_renderer::*;
+pub use particles::*;
+
+// Velocity glyphs are drawn for at most one particle per square cell of this size (in m).
+const VELOCITY_GLYPH_SPACING: Real = 0.04;
+// Length of a velocity glyph per m/s of particle velocity (in m).
+const VELOCITY_GLYPH_SCALE: Real = 0.02;
+
+// Particles and legend for visualizations the active solver doesn't support.
+pub const UNAVAILABLE_VISUALIZATION_COLOR: graphics::Color = graphics::Color {
+    r: 0.6,
+    g: 0.6,
+    b: 0.6,
+    a: 1.0,
+};
+pub const BOUNDARY_COLOR: graphics::Color = graphics::Color {
+    r: 0.2,
+    g: 0.2,
+    b: 0.2,
+    a: 1.0,
+};
+pub const TOOL_COLOR: graphics::Color = graphics::Color {
+    r: 1.0,
+    g: 0.8,
+    b: 0.2,
+    a: 1.0,
+};
+
+// Draws the simulation and its overlays, knows nothing about the application state beyond what is passed in.
+// Fluid particles are drawn with one of several interchangeable ParticleRenderer.
+//
+// World space drawing happens between push_camera and pop_camera, screen space drawing (legend, plots, text) after.
+pub struct Renderer {
+    particle_renderers: Vec<Box<dyn ParticleRenderer<Context, Error = GameError>>>,
+    particle_renderer: usize,           // index into particle_renderers used for fluid particles
+    pub decimation: ParticleDecimation, // applied to fluid particles before they are handed to the particle renderer
+    pub show_velocity_glyphs: bool,
+    pub show_boundary_particles: bool,               // instead of the geometry they were created from
+    boundary_particle_renderer: SpriteBatchRenderer, // boundary particles are always sprites, a surface of them doesn't make sense
+    boundary_particle_colors: Vec<Rgba>,
+}
+
+impl Renderer {
+    pub fn new(ctx: &mut Context) -> GameResult<Renderer> {
+        Ok(Renderer {
+            particle_renderers: vec![
+                Box::new(SpriteBatchRenderer::new(ctx)?),
+                Box::new(CircleRenderer),
+                Box::new(SurfaceRenderer {
+                    metaballs: MetaballRenderer::new(),
+                }),
+            ],
+            particle_renderer: 0,
+            decimation: ParticleDecimation::new(),
+            show_velocity_glyphs: false,
+            show_boundary_particles: false,
+            boundary_particle_renderer: SpriteBatchRenderer::new(ctx)?,
+            boundary_particle_colors: Vec::new(),
+        })
+    }
+
+    pub fn next_particle_renderer(&mut self) {
+        self.particle_renderer = (self.particle_renderer + 1) % self.particle_renderers.len();
+    }
+
+    pub fn particle_renderer_name(&self) -> &'static str {
+        self.particle_renderers[self.particle_renderer].name()
+    }
+
+    pub fn push_camera(&self, ctx: &mut Context, camera: &Camera) -> GameResult {
+        graphics::push_transform(ctx, Some(camera.transformation_matrix()));
+        graphics::apply_transformations(ctx)
+    }
+
+    pub fn pop_camera(&self, ctx: &mut Context) -> GameResult {
+        graphics::pop_transform(ctx);
+        graphics::apply_transformations(ctx)
+    }
+
+    // Fluid particles in the given colors (one per particle) and the boundaries.
+    pub fn draw_particles(&mut self, ctx: &mut Context, camera: &Camera, fluid_world: &sph::FluidParticleWorld, fluid_colors: &[Rgba]) -> GameResult {
+        microprofile::scope!("Renderer", "particles");
+
+        let radius = fluid_world.properties.particle_radius();
+        let (positions, colors, fluid_radius) = self.decimation.apply(&fluid_world.particles.positions, fluid_colors, radius);
+        self.particle_renderers[self.particle_renderer].draw(ctx, camera, positions, colors, fluid_radius)?;
+        if self.show_boundary_particles {
+            let boundary_particles = &fluid_world.particles.boundary_particles;
+            self.boundary_particle_colors.resize(boundary_particles.len(), BOUNDARY_COLOR.into());
+            self.boundary_particle_renderer
+                .draw(ctx, camera, boundary_particles, &self.boundary_particle_colors, radius)?;
+        } else {
+            self.draw_boundary_geometry(ctx, fluid_world)?;
+        }
+        if self.show_velocity_glyphs {
+            self.draw_velocity_glyphs(ctx, fluid_world)?;
+        }
+        Ok(())
+    }
+
+    fn draw_boundary_geometry(&self, ctx: &mut Context, fluid_world: &sph::FluidParticleWorld) -> GameResult {
+        microprofile::scope!("Renderer", "boundary geometry");
+
+        let geometry = fluid_world.boundary_geometry();
+        if geometry.is_empty() {
+            return Ok(());
+        }
+
+        let mut mesh_builder = graphics::MeshBuilder::new();
+        for shape in geometry {
+            match shape {
+                sph::BoundaryGeometry::Line { start, end, width } => {
+                    mesh_builder.line(
+                        &[RenderPoint::new(start.x, start.y), RenderPoint::new(end.x, end.y)],
+                        *width,
+                        BOUNDARY_COLOR,
+                    )?;
+                }
+                sph::BoundaryGeometry::Polygon { vertices, width } => {
+                    let vertices: Vec<RenderPoint> = vertices.iter().map(|v| RenderPoint::new(v.x, v.y)).collect();
+                    mesh_builder.polygon(graphics::DrawMode::stroke(*width), &vertices, BOUNDARY_COLOR)?;
+                }
+                sph::BoundaryGeometry::Polyline { vertices, width } => {
+                    let vertices: Vec<RenderPoint> = vertices.iter().map(|v| RenderPoint::new(v.x, v.y)).collect();
+                    mesh_builder.line(&vertices, *width, BOUNDARY_COLOR)?;
+                }
+                sph::BoundaryGeometry::Circle { center, radius, width } => {
+                    mesh_builder.circle(
+                        graphics::DrawMode::stroke(*width),
+                        RenderPoint::new(center.x, center.y),
+                        *radius,
+                        0.0003,
+                        BOUNDARY_COLOR,
+                    );
+                }
+            }
+        }
+        let mesh = mesh_builder.build(ctx)?;
+        graphics::draw(ctx, &mesh, graphics::DrawParam::default())
+    }
+
+    fn draw_velocity_glyphs(&self, ctx: &mut Context, fluid_world: &sph::FluidParticleWorld) -> GameResult {
+        microprofile::scope!("Renderer", "velocity glyphs");
+
+        const MIN_GLYPH_LENGTH: Real = 0.002; // shorter glyphs are not visible anyways
+        const LINE_WIDTH: f32 = 0.002;
+        let color = graphics::Color::new(1.0, 1.0, 1.0, 0.8);
+
+        let mut occupied_cells = HashSet::new();
+        let mut mesh_builder = graphics::MeshBuilder::new();
+        let mut num_glyphs = 0;
+        for (p, v) in fluid_world.particles.positions.iter().zip(fluid_world.particles.velocities.iter()) {
+            let cell = (
+                (p.x / VELOCITY_GLYPH_SPACING).floor() as i32,
+                (p.y / VELOCITY_GLYPH_SPACING).floor() as i32,
+            );
+            if !occupied_cells.insert(cell) {
+                continue;
+            }
+            let glyph = v * VELOCITY_GLYPH_SCALE;
+            let length = glyph.magnitude();
+            if length < MIN_GLYPH_LENGTH {
+                continue;
+            }
+
+            let tip = p + glyph;
+            let head_back = glyph * -0.3;
+            let head_side = Vector::new(-head_back.y, head_back.x) * 0.5;
+            let head_left = tip + head_back + head_side;
+            let head_right = tip + head_back - head_side;
+            mesh_builder.line(&[RenderPoint::new(p.x, p.y), RenderPoint::new(tip.x, tip.y)], LINE_WIDTH, color)?;
+            mesh_builder.line(
+                &[
+                    RenderPoint::new(head_left.x, head_left.y),
+                    RenderPoint::new(tip.x, tip.y),
+                    RenderPoint::new(head_right.x, head_right.y),
+                ],
+                LINE_WIDTH,
+                color,
+            )?;
+            num_glyphs += 1;
+        }
+
+        if num_glyphs > 0 {
+            let glyphs = mesh_builder.build(ctx)?;
+            graphics::draw(ctx, &glyphs, graphics::DrawParam::default())?;
+        }
+        Ok(())
+    }
+
+    pub fn draw_probes(&self, ctx: &mut Context, probes: &sph::Probes) -> GameResult {
+        if probes.is_empty() {
+            return Ok(());
+        }
+        const MARKER_SIZE: f32 = 0.02;
+        const LINE_WIDTH: f32 = 0.004;
+        let color = graphics::Color::new(1.0, 0.9, 0.1, 1.0);
+
+        let mut mesh_builder = graphics::MeshBuilder::new();
+        for probe in probes.probes() {
+            let p = RenderPoint::new(probe.position.x, probe.position.y);
+            mesh_builder.line(
+                &[RenderPoint::new(p.x - MARKER_SIZE, p.y), RenderPoint::new(p.x + MARKER_SIZE, p.y)],
+                LINE_WIDTH,
+                color,
+            )?;
+            mesh_builder.line(
+                &[RenderPoint::new(p.x, p.y - MARKER_SIZE), RenderPoint::new(p.x, p.y + MARKER_SIZE)],
+                LINE_WIDTH,
+                color,
+            )?;
+        }
+        let mesh = mesh_builder.build(ctx)?;
+        graphics::draw(ctx, &mesh, graphics::DrawParam::default())
+    }
+
+    // Outlines a selected particle, its neighbors and its smoothing length.
+    pub fn draw_inspected_particle(
+        &self,
+        ctx: &mut Context,
+        fluid_world: &sph::FluidParticleWorld,
+        selected: usize,
+        neighbors: &[usize],
+    ) -> GameResult {
+        const LINE_WIDTH: f32 = 0.002;
+        let particle_radius = fluid_world.properties.particle_radius();
+        let positions = &fluid_world.particles.positions;
+        let render_point = |i: usize| RenderPoint::new(positions[i].x, positions[i].y);
+
+        let mut mesh_builder = graphics::MeshBuilder::new();
+        for neighbor in neighbors {
+            mesh_builder.circle(
+                graphics::DrawMode::stroke(LINE_WIDTH),
+                render_point(*neighbor),
+                particle_radius,
+                0.001,
+                graphics::Color::new(1.0, 1.0, 0.2, 1.0),
+            );
+        }
+        mesh_builder.circle(
+            graphics::DrawMode::stroke(LINE_WIDTH),
+            render_point(selected),
+            fluid_world.properties.smoothing_length(),
+            0.001,
+            graphics::WHITE,
+        );
+        mesh_builder.circle(
+            graphics::DrawMode::fill(),
+            render_point(selected),
+            particle_radius,
+            0.001,
+            graphics::Color::new(0.3, 1.0, 1.0, 1.0),
+        );
+        let mesh = mesh_builder.build(ctx)?;
+        graphics::draw(ctx, &mesh, graphics::DrawParam::default())
+    }
+
+    // Tool previews, all in world space with a line width of 5mm.
+
+    pub fn draw_circle_outline(&self, ctx: &mut Context, center: Point, radius: Real, color: graphics::Color) -> GameResult {
+        let mesh = graphics::Mesh::new_circle(
+            ctx,
+            graphics::DrawMode::stroke(0.005),
+            RenderPoint::new(center.x, center.y),
+            radius,
+            0.001,
+            color,
+        )?;
+        graphics::draw(ctx, &mesh, graphics::DrawParam::default())
+    }
+
+    pub fn draw_line_strip(&self, ctx: &mut Context, points: &[Point], color: graphics::Color) -> GameResult {
+        if points.len() < 2 {
+            return Ok(());
+        }
+        let points: Vec<RenderPoint> = points.iter().map(|p| RenderPoint::new(p.x, p.y)).collect();
+        let mesh = graphics::Mesh::new_line(ctx, &points, 0.005, color)?;
+        graphics::draw(ctx, &mesh, graphics::DrawParam::default())
+    }
+
+    pub fn draw_polygon_outline(&self, ctx: &mut Context, vertices: &[Point], color: graphics::Color) -> GameResult {
+        let vertices: Vec<RenderPoint> = vertices.iter().map(|v| RenderPoint::new(v.x, v.y)).collect();
+        let mesh = graphics::Mesh::new_polygon(ctx, graphics::DrawMode::stroke(0.005), &vertices, color)?;
+        graphics::draw(ctx, &mesh, graphics::DrawParam::default())
+    }
+
+    pub fn draw_rect_outline(&self, ctx: &mut Context, min: Point, max: Point, color: graphics::Color) -> GameResult {
+        let mesh = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::stroke(0.005),
+            Rect::new(min.x, min.y, max.x - min.x, max.y - min.y),
+            color,
+        )?;
+        graphics::draw(ctx, &mesh, graphics::DrawParam::default())
+    }
+
+    // Color ramp with value range and a title in the bottom right corner, a gray bar if the visualization is not available.
+    pub fn draw_legend(&self, ctx: &mut Context, title: &str, visualization: Option<(&ColorMap, Real, Real)>) -> GameResult {
+        microprofile::scope!("Renderer", "legend");
+
+        const NUM_SEGMENTS: usize = 32;
+        const SEGMENT_WIDTH: f32 = 8.0;
+        const BAR_HEIGHT: f32 = 16.0;
+
+        let screen = graphics::screen_coordinates(ctx);
+        let origin = RenderPoint::new(screen.w - NUM_SEGMENTS as f32 * SEGMENT_WIDTH - 20.0, screen.h - 90.0);
+        graphics::draw(ctx, &graphics::Text::new(title), (origin, graphics::WHITE))?;
+
+        let bar_top = origin.y + 40.0;
+        let mut mesh_builder = graphics::MeshBuilder::new();
+        for i in 0..NUM_SEGMENTS {
+            let color = match visualization {
+                Some((color_map, _, _)) => color_map.sample(i as f32 / (NUM_SEGMENTS - 1) as f32),
+                None => UNAVAILABLE_VISUALIZATION_COLOR,
+            };
+            mesh_builder.rectangle(
+                graphics::DrawMode::fill(),
+                Rect::new(origin.x + i as f32 * SEGMENT_WIDTH, bar_top, SEGMENT_WIDTH, BAR_HEIGHT),
+                color,
+            );
+        }
+        let bar = mesh_builder.build(ctx)?;
+        graphics::draw(ctx, &bar, graphics::DrawParam::default())?;
+
+        if let Some((_, min, max)) = visualization {
+            let label_top = bar_top + BAR_HEIGHT + 4.0;
+            let min_label = graphics::Text::new(format!("{:.2}", min));
+            let max_label = graphics::Text::new(format!("{:.2}", max));
+            let max_label_x = origin.x + NUM_SEGMENTS as f32 * SEGMENT_WIDTH - max_label.width(ctx) as f32;
+            graphics::draw(ctx, &min_label, (RenderPoint::new(origin.x, label_top), graphics::WHITE))?;
+            graphics::draw(ctx, &max_label, (RenderPoint::new(max_label_x, label_top), graphics::WHITE))?;
+        }
+
+        Ok(())
+    }
+
+    // Residual of every pressure solver iteration on a log scale in the bottom left corner, with the targets as horizontal lines.
+    pub fn draw_solver_residuals(&self, ctx: &mut Context, residuals: &sph::SolverResiduals) -> GameResult {
+        microprofile::scope!("Renderer", "solver residuals");
+
+        const PLOT_WIDTH: f32 = 256.0;
+        const PLOT_HEIGHT: f32 = 96.0;
+        const DENSITY_COLOR: graphics::Color = graphics::Color {
+            r: 0.3,
+            g: 0.7,
+            b: 1.0,
+            a: 1.0,
+        };
+        const DIVERGENCE_COLOR: graphics::Color = graphics::Color {
+            r: 1.0,
+            g: 0.6,
+            b: 0.2,
+            a: 1.0,
+        };
+
+        if residuals.density.is_empty() && residuals.divergence.is_empty() {
+            return Ok(());
+        }
+        let curves = [
+            (&residuals.density, residuals.density_target, DENSITY_COLOR),
+            (&residuals.divergence, residuals.divergence_target, DIVERGENCE_COLOR),
+        ];
+
+        // log10 range of all residuals and targets, at least one order of magnitude
+        let log_values = || {
+            curves
+                .iter()
+                .flat_map(|(values, target, _)| values.iter().chain(std::iter::once(target)))
+                .map(|value| value.max(1.0e-12).log10())
+        };
+        let log_min = log_values().fold(f32::INFINITY, f32::min).floor();
+        let log_max = log_values().fold(f32::NEG_INFINITY, f32::max).ceil().max(log_min + 1.0);
+        let num_iterations = residuals.density.len().max(residuals.divergence.len()).max(2);
+
+        let screen = graphics::screen_coordinates(ctx);
+        let origin = RenderPoint::new(10.0, screen.h - PLOT_HEIGHT - 10.0);
+        let to_plot = |iteration: usize, value: Real| {
+            RenderPoint::new(
+                origin.x + iteration as f32 / (num_iterations - 1) as f32 * PLOT_WIDTH,
+                origin.y + (log_max - value.max(1.0e-12).log10()) / (log_max - log_min) * PLOT_HEIGHT,
+            )
+        };
+
+        let mut mesh_builder = graphics::MeshBuilder::new();
+        mesh_builder.rectangle(
+            graphics::DrawMode::fill(),
+            Rect::new(origin.x, origin.y, PLOT_WIDTH, PLOT_HEIGHT),
+            graphics::Color::new(0.0, 0.0, 0.0, 0.5),
+        );
+        for (values, target, color) in curves.iter() {
+            if values.is_empty() {
+                continue;
+            }
+            let target_color = graphics::Color { a: 0.5, ..*color };
+            mesh_builder.line(&[to_plot(0, *target), to_plot(num_iterations - 1, *target)], 1.0, target_color)?;
+            if values.len() == 1 {
+                mesh_builder.circle(graphics::DrawMode::fill(), to_plot(0, values[0]), 2.0, 0.5, *color);
+            } else {
+                let points: Vec<RenderPoint> = values.iter().enumerate().map(|(i, value)| to_plot(i, *value)).collect();
+                mesh_builder.line(&points, 1.5, *color)?;
+            }
+        }
+        let plot = mesh_builder.build(ctx)?;
+        graphics::draw(ctx, &plot, graphics::DrawParam::default())?;
+
+        let title = graphics::Text::new(format!(
+            "Residuals of density (blue) & divergence (orange) solve, 1e{} to 1e{}, {} iterations",
+            log_min,
+            log_max,
+            residuals.density.len().max(residuals.divergence.len())
+        ));
+        graphics::draw(ctx, &title, (RenderPoint::new(origin.x, origin.y - 20.0), graphics::WHITE))?;
+
+        Ok(())
+    }
+
+    // Blocks of text stacked from the top left corner downwards.
+    pub fn draw_text_blocks(&self, ctx: &mut Context, blocks: &[(String, graphics::Color)]) -> GameResult {
+        microprofile::scope!("Renderer", "text");
+
+        let mut text_y = 10.0;
+        for (text, color) in blocks {
+            let text = graphics::Text::new(text.as_str());
+            graphics::draw(ctx, &text, (RenderPoint::new(10.0, text_y), *color))?;
+            text_y += text.height(ctx) as f32 + 10.0;
+        }
+        Ok(())
+    }
+}
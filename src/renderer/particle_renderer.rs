@@ -0,0 +1,20 @@
+use yasph2d::units::*;
+
+use crate::camera::Camera;
+
+// RGBA color with components in [0, 1], independent of any graphics backend.
+pub type Rgba = [f32; 4];
+
+// Draws a set of particles, e.g. all fluid particles, into a frame of some graphics backend.
+// `Frame` is whatever the backend needs to record draw calls, ggez's Context for the default viewer, see also the wgpu viewer.
+//
+// This file is free of any backend specifics, so that all viewers can share it.
+pub trait ParticleRenderer<Frame> {
+    type Error;
+
+    fn name(&self) -> &'static str;
+
+    // Positions are in world space, the camera maps them to the screen.
+    // `colors` has one entry per particle, renderers that don't show individual particles may ignore it.
+    fn draw(&mut self, frame: &mut Frame, camera: &Camera, positions: &[Point], colors: &[Rgba], radius: Real) -> Result<(), Self::Error>;
+}
@@ -0,0 +1,118 @@
+use cgmath::prelude::*;
+use ggez::graphics;
+use ggez::{Context, GameError, GameResult};
+use yasph2d::units::*;
+
+use super::metaballs::MetaballRenderer;
+use super::particle_renderer::*;
+use crate::camera::*;
+use crate::clamp;
+
+// All particles with a single draw call, each a scaled and tinted copy of the same anti-aliased circle image.
+pub struct SpriteBatchRenderer {
+    batch: graphics::spritebatch::SpriteBatch,
+    image_size: f32, // in pixels
+}
+
+impl SpriteBatchRenderer {
+    pub fn new(ctx: &mut Context) -> GameResult<SpriteBatchRenderer> {
+        let image = create_particle_image(ctx)?;
+        Ok(SpriteBatchRenderer {
+            image_size: image.width() as f32,
+            batch: graphics::spritebatch::SpriteBatch::new(image),
+        })
+    }
+}
+
+impl ParticleRenderer<Context> for SpriteBatchRenderer {
+    type Error = GameError;
+
+    fn name(&self) -> &'static str {
+        "Sprites"
+    }
+
+    fn draw(&mut self, ctx: &mut Context, _camera: &Camera, positions: &[Point], colors: &[Rgba], radius: Real) -> GameResult {
+        microprofile::scope!("SpriteBatchRenderer", "draw");
+
+        let scale = radius * 2.0 / self.image_size;
+        for (p, color) in positions.iter().zip(colors.iter()) {
+            self.batch.add(
+                graphics::DrawParam::default()
+                    .dest(RenderPoint::new(p.x, p.y))
+                    .offset(RenderPoint::new(0.5, 0.5))
+                    .scale(RenderSize::new(scale, scale))
+                    .color(graphics::Color::from(*color)),
+            );
+        }
+        graphics::draw(ctx, &self.batch, graphics::DrawParam::default())?;
+        self.batch.clear();
+        Ok(())
+    }
+}
+
+// White circle with anti-aliased edge, tinted and scaled per particle.
+fn create_particle_image(ctx: &mut Context) -> GameResult<graphics::Image> {
+    const SIZE: u16 = 32;
+    let radius = SIZE as f32 * 0.5;
+    let mut pixels = Vec::with_capacity(SIZE as usize * SIZE as usize * 4);
+    for y in 0..SIZE {
+        for x in 0..SIZE {
+            let from_center = RenderSize::new(x as f32 + 0.5 - radius, y as f32 + 0.5 - radius);
+            let alpha = clamp(radius - from_center.magnitude(), 0.0, 1.0);
+            pixels.extend_from_slice(&[255, 255, 255, (alpha * 255.0) as u8]);
+        }
+    }
+    let mut image = graphics::Image::from_rgba8(ctx, SIZE, SIZE, &pixels)?;
+    image.set_filter(graphics::FilterMode::Linear);
+    Ok(image)
+}
+
+// Particles as tessellated circles, stays sharp at any zoom level but is a lot slower than sprites for many particles.
+pub struct CircleRenderer;
+
+impl ParticleRenderer<Context> for CircleRenderer {
+    type Error = GameError;
+
+    fn name(&self) -> &'static str {
+        "Circles"
+    }
+
+    fn draw(&mut self, ctx: &mut Context, camera: &Camera, positions: &[Point], colors: &[Rgba], radius: Real) -> GameResult {
+        microprofile::scope!("CircleRenderer", "draw");
+
+        if positions.is_empty() {
+            return Ok(());
+        }
+        // tolerance of a quarter pixel
+        let tolerance = 0.25 / camera.pixel_per_world_unit;
+        let mut mesh_builder = graphics::MeshBuilder::new();
+        for (p, color) in positions.iter().zip(colors.iter()) {
+            mesh_builder.circle(
+                graphics::DrawMode::fill(),
+                RenderPoint::new(p.x, p.y),
+                radius,
+                tolerance,
+                graphics::Color::from(*color),
+            );
+        }
+        let mesh = mesh_builder.build(ctx)?;
+        graphics::draw(ctx, &mesh, graphics::DrawParam::default())
+    }
+}
+
+// Continuous fluid surface via MetaballRenderer, in a single color.
+pub struct SurfaceRenderer {
+    pub metaballs: MetaballRenderer,
+}
+
+impl ParticleRenderer<Context> for SurfaceRenderer {
+    type Error = GameError;
+
+    fn name(&self) -> &'static str {
+        "Surface"
+    }
+
+    fn draw(&mut self, ctx: &mut Context, camera: &Camera, positions: &[Point], _colors: &[Rgba], radius: Real) -> GameResult {
+        self.metaballs.draw(ctx, camera, positions, radius)
+    }
+}
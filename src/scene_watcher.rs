@@ -0,0 +1,123 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::time::{Duration, Instant};
+
+// Notices edits of the loaded scene files (scene.png, scene.svg, scene.rhai) while the viewer is running.
+// The files are read through ggez's virtual filesystem, which doesn't tell modification times. Scene files are small though,
+// so they are simply read again every now and then and compared by hash.
+pub struct SceneWatcher {
+    interval: Duration,
+    last_check: Instant,
+    files: Vec<WatchedFile>,
+}
+
+struct WatchedFile {
+    path: String,
+    hash: Option<u64>, // of the contents as of when watching started, None if the file couldn't be read
+    changed: bool,
+}
+
+fn content_hash(content: Option<Vec<u8>>) -> Option<u64> {
+    content.map(|content| {
+        let mut hasher = DefaultHasher::new();
+        content.hash(&mut hasher);
+        hasher.finish()
+    })
+}
+
+impl SceneWatcher {
+    pub fn new(interval: Duration) -> SceneWatcher {
+        SceneWatcher {
+            interval,
+            last_check: Instant::now(),
+            files: Vec::new(),
+        }
+    }
+
+    // Checks the given files if the interval passed since the last check, see check.
+    pub fn poll(&mut self, paths: &[&str], read: impl FnMut(&str) -> Option<Vec<u8>>) {
+        if self.last_check.elapsed() >= self.interval {
+            self.check(paths, read);
+        }
+    }
+
+    // Watches exactly the given files, reading them with read (None if they can't be read).
+    // Files that weren't watched before start out unchanged with their current contents.
+    pub fn check(&mut self, paths: &[&str], mut read: impl FnMut(&str) -> Option<Vec<u8>>) {
+        self.last_check = Instant::now();
+        self.files.retain(|file| paths.contains(&file.path.as_str()));
+        for path in paths {
+            let hash = content_hash(read(path));
+            match self.files.iter_mut().find(|file| file.path == *path) {
+                Some(file) => file.changed |= file.hash != hash,
+                None => self.files.push(WatchedFile {
+                    path: path.to_string(),
+                    hash,
+                    changed: false,
+                }),
+            }
+        }
+    }
+
+    // Forgets all files, e.g. after they were reloaded. The next check starts watching them over with their contents at that point.
+    pub fn clear(&mut self) {
+        self.files.clear();
+    }
+
+    // Files whose contents differ from when watching them started.
+    pub fn changed_files(&self) -> Vec<&str> {
+        self.files.iter().filter(|file| file.changed).map(|file| file.path.as_str()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn reports_changed_files_until_cleared() {
+        let mut contents: HashMap<&str, Vec<u8>> = HashMap::new();
+        contents.insert("/scene.svg", b"<svg/>".to_vec());
+        contents.insert("/scene.rhai", b"at(1.0, || {})".to_vec());
+        let mut watcher = SceneWatcher::new(Duration::from_secs(1));
+        let paths = ["/scene.svg", "/scene.rhai", "/scene.png"];
+
+        watcher.check(&paths, |path| contents.get(path).cloned());
+        assert!(watcher.changed_files().is_empty());
+
+        contents.insert("/scene.svg", b"<svg><rect/></svg>".to_vec());
+        contents.insert("/scene.png", vec![0x89, b'P', b'N', b'G']);
+        watcher.check(&paths, |path| contents.get(path).cloned());
+        assert_eq!(watcher.changed_files(), vec!["/scene.svg", "/scene.png"]);
+
+        // stays changed even if it's changed back, the loaded scene is still the old one
+        contents.insert("/scene.svg", b"<svg/>".to_vec());
+        watcher.check(&paths, |path| contents.get(path).cloned());
+        assert_eq!(watcher.changed_files(), vec!["/scene.svg", "/scene.png"]);
+
+        // files that aren't loaded anymore aren't watched either
+        watcher.check(&paths[1..], |path| contents.get(path).cloned());
+        assert_eq!(watcher.changed_files(), vec!["/scene.png"]);
+
+        watcher.clear();
+        watcher.check(&paths, |path| contents.get(path).cloned());
+        assert!(watcher.changed_files().is_empty());
+    }
+
+    #[test]
+    fn polls_only_after_the_interval() {
+        let mut watcher = SceneWatcher::new(Duration::from_secs(3600));
+        let mut num_reads = 0;
+        watcher.poll(&["/scene.svg"], |_| {
+            num_reads += 1;
+            None
+        });
+        assert_eq!(num_reads, 0);
+        watcher.check(&["/scene.svg"], |_| {
+            num_reads += 1;
+            None
+        });
+        assert_eq!(num_reads, 1);
+    }
+}
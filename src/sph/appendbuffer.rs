@@ -33,6 +33,10 @@ impl<T: Copy> AppendBuffer<T> {
     // }
 
     pub fn as_slice(&self) -> &[T] {
+        // from_raw_parts doesn't allow null, even for empty slices
+        if self.data.is_null() {
+            return &[];
+        }
         unsafe { std::slice::from_raw_parts(self.data, self.size.load(Ordering::Relaxed)) }
     }
 
@@ -64,11 +68,13 @@ impl<T: Copy> AppendBuffer<T> {
         if self.capacity >= capacity {
             return;
         }
-        self.capacity = capacity;
         unsafe {
-            alloc::dealloc(self.data.cast(), Self::buffer_layout(self.capacity));
+            if !self.data.is_null() {
+                alloc::dealloc(self.data.cast(), Self::buffer_layout(self.capacity));
+            }
             self.data = alloc::alloc(Self::buffer_layout(capacity)).cast();
         }
+        self.capacity = capacity;
     }
 
     pub fn clear(&mut self) {
@@ -78,8 +84,10 @@ impl<T: Copy> AppendBuffer<T> {
 
 impl<T: Copy> Drop for AppendBuffer<T> {
     fn drop(&mut self) {
-        unsafe {
-            alloc::dealloc(self.data.cast(), Self::buffer_layout(self.capacity));
+        if !self.data.is_null() {
+            unsafe {
+                alloc::dealloc(self.data.cast(), Self::buffer_layout(self.capacity));
+            }
         }
     }
 }
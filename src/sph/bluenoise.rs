@@ -0,0 +1,152 @@
+use super::smoothing_kernel::Kernel;
+use crate::units::*;
+use cgmath::prelude::*;
+use rand::prelude::*;
+
+// Number of candidates tried around an active sample before it is retired, as proposed by Bridson.
+const NUM_CANDIDATES: usize = 30;
+
+// Ratio of the number of Poisson-disk samples per area to that of a square lattice with the same minimum distance.
+// Determined empirically for this sampler, the exact value depends on the number of candidates.
+pub(super) const POISSON_DISK_DENSITY_RATIO: Real = 0.62;
+
+// Poisson-disk sampling within a shape as in "Fast Poisson Disk Sampling in Arbitrary Dimensions", Bridson 2007.
+// All samples are at least min_distance apart, `inside` tells whether a position belongs to the shape and `bounds` needs to contain the entire shape.
+//
+// Instead of a single initial sample, new samples are seeded from a lattice across the bounds whenever the active list ran empty.
+// This reaches disconnected parts of the shape and thin regions the growing front would only reach through a narrow gap.
+pub(super) fn poisson_disk_samples(bounds: &Rect, min_distance: Real, inside: impl Fn(Point) -> bool, rng: &mut impl Rng) -> Vec<Point> {
+    let cell_size = min_distance / std::f32::consts::SQRT_2; // at most one sample per cell
+    let num_cells_x = (bounds.w / cell_size).ceil() as usize + 1;
+    let num_cells_y = (bounds.h / cell_size).ceil() as usize + 1;
+    let mut grid: Vec<Option<usize>> = vec![None; num_cells_x * num_cells_y];
+    let origin = Point::new(bounds.x, bounds.y);
+    let cell_of = |p: Point| {
+        let offset = (p - origin) / cell_size;
+        (offset.x as usize, offset.y as usize)
+    };
+    let in_bounds = |p: Point| p.x >= bounds.x && p.y >= bounds.y && p.x < bounds.x + bounds.w && p.y < bounds.y + bounds.h;
+
+    let mut samples: Vec<Point> = Vec::new();
+    let mut active = Vec::new();
+    let min_distance_sq = min_distance * min_distance;
+    let is_free = |samples: &[Point], grid: &[Option<usize>], p: Point| {
+        let (cx, cy) = cell_of(p);
+        for y in cy.saturating_sub(2)..(cy + 3).min(num_cells_y) {
+            for x in cx.saturating_sub(2)..(cx + 3).min(num_cells_x) {
+                if let Some(j) = grid[x + y * num_cells_x] {
+                    if samples[j].distance2(p) < min_distance_sq {
+                        return false;
+                    }
+                }
+            }
+        }
+        true
+    };
+
+    let num_seeds_x = (bounds.w / min_distance).ceil() as usize;
+    let num_seeds_y = (bounds.h / min_distance).ceil() as usize;
+    for seed_y in 0..num_seeds_y {
+        for seed_x in 0..num_seeds_x {
+            let seed = origin + Vector::new(seed_x as Real + rng.gen::<Real>(), seed_y as Real + rng.gen::<Real>()) * min_distance;
+            if !in_bounds(seed) || !inside(seed) || !is_free(&samples, &grid, seed) {
+                continue;
+            }
+            let (cx, cy) = cell_of(seed);
+            grid[cx + cy * num_cells_x] = Some(samples.len());
+            active.push(samples.len());
+            samples.push(seed);
+
+            while !active.is_empty() {
+                let active_index = (rng.gen::<Real>() * active.len() as Real) as usize % active.len();
+                let center = samples[active[active_index]];
+                let mut found = false;
+                for _ in 0..NUM_CANDIDATES {
+                    // uniform in the annulus between min_distance and twice that
+                    let angle = rng.gen::<Real>() * 2.0 * std::f32::consts::PI;
+                    let radius = min_distance * (1.0 + 3.0 * rng.gen::<Real>()).sqrt();
+                    let candidate = center + Vector::new(angle.cos(), angle.sin()) * radius;
+                    if !in_bounds(candidate) || !inside(candidate) || !is_free(&samples, &grid, candidate) {
+                        continue;
+                    }
+                    let (cx, cy) = cell_of(candidate);
+                    grid[cx + cy * num_cells_x] = Some(samples.len());
+                    active.push(samples.len());
+                    samples.push(candidate);
+                    found = true;
+                    break;
+                }
+                if !found {
+                    active.swap_remove(active_index);
+                }
+            }
+        }
+    }
+    samples
+}
+
+// Median over all samples of Σ W(|xi - xj|) over all samples j including i itself, i.e. the SPH number density.
+// Samples close to the shape's surface lack neighbors, the median ignores them as long as most samples are in the interior.
+pub(super) fn median_kernel_sum(samples: &[Point], kernel: &impl Kernel, smoothing_length: Real) -> Real {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    let min_x = samples.iter().map(|p| p.x).fold(Real::INFINITY, Real::min);
+    let min_y = samples.iter().map(|p| p.y).fold(Real::INFINITY, Real::min);
+    let cell_of = |p: &Point| (((p.x - min_x) / smoothing_length) as usize, ((p.y - min_y) / smoothing_length) as usize);
+    let num_cells_x = samples.iter().map(|p| cell_of(p).0).max().unwrap_or(0) + 1;
+    let num_cells_y = samples.iter().map(|p| cell_of(p).1).max().unwrap_or(0) + 1;
+    let mut cells = vec![Vec::new(); num_cells_x * num_cells_y];
+    for p in samples.iter() {
+        let (x, y) = cell_of(p);
+        cells[x + y * num_cells_x].push(*p);
+    }
+
+    let mut kernel_sums: Vec<Real> = samples
+        .iter()
+        .map(|p| {
+            let (cx, cy) = cell_of(p);
+            let mut sum = 0.0;
+            for y in cy.saturating_sub(1)..(cy + 2).min(num_cells_y) {
+                for x in cx.saturating_sub(1)..(cx + 2).min(num_cells_x) {
+                    for q in cells[x + y * num_cells_x].iter() {
+                        let r_sq = p.distance2(*q);
+                        sum += kernel.evaluate(r_sq, r_sq.sqrt());
+                    }
+                }
+            }
+            sum
+        })
+        .collect();
+    kernel_sums.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    kernel_sums[kernel_sums.len() / 2]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_keep_min_distance() {
+        let mut rng: rand::rngs::SmallRng = rand::SeedableRng::seed_from_u64(0);
+        let bounds = Rect::new(-1.0, -1.0, 2.0, 2.0);
+        let min_distance = 0.02;
+        // ring with a hole, two disconnected halves
+        let inside = |p: Point| (0.5..0.9).contains(&p.to_vec().magnitude()) && p.x.abs() > 0.1;
+        let samples = poisson_disk_samples(&bounds, min_distance, inside, &mut rng);
+
+        assert!(samples.iter().all(|p| inside(*p)));
+        for (i, a) in samples.iter().enumerate() {
+            for b in samples[i + 1..].iter() {
+                assert_ge!(a.distance(*b), min_distance);
+            }
+        }
+        assert!(samples.iter().any(|p| p.x < 0.0));
+        assert!(samples.iter().any(|p| p.x > 0.0));
+
+        // maximal sampling, filling the shape at a consistent density (slightly higher due to the shape's edges)
+        let area = std::f32::consts::PI * (0.9 * 0.9 - 0.5 * 0.5) - 2.0 * 0.2 * 0.4;
+        let lattice_count = area / (min_distance * min_distance);
+        assert_lt!((samples.len() as Real / lattice_count / POISSON_DISK_DENSITY_RATIO - 1.0).abs(), 0.05);
+    }
+}
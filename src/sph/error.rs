@@ -0,0 +1,42 @@
+use crate::units::Real;
+use std::fmt;
+use std::io;
+
+// Failures of the library that an embedding application may want to recover from or report, instead of a panic.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SphError {
+    // Array that needs one entry per particle (or pixel, ...) with a different number of entries.
+    LengthMismatch { what: &'static str, expected: usize, actual: usize },
+    // Quantity of a solver that became NaN or infinite, i.e. the simulation blew up. Particles are left as they were at that point.
+    NonFinite { what: &'static str, time: Real },
+    // Argument a function doesn't accept, or not in the current state.
+    InvalidArgument { what: &'static str, reason: &'static str },
+}
+
+impl fmt::Display for SphError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SphError::LengthMismatch { what, expected, actual } => write!(f, "expected {} {}, got {}", expected, what, actual),
+            SphError::NonFinite { what, time } => write!(f, "{} is not finite at {:.4}s", what, time),
+            SphError::InvalidArgument { what, reason } => write!(f, "invalid {}: {}", what, reason),
+        }
+    }
+}
+
+impl std::error::Error for SphError {}
+
+// For functions that otherwise only fail with io errors, e.g. when writing trajectories.
+impl From<SphError> for io::Error {
+    fn from(err: SphError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidInput, err)
+    }
+}
+
+// Checks that an array has one entry per particle.
+pub(super) fn check_length(what: &'static str, expected: usize, actual: usize) -> Result<(), SphError> {
+    if expected == actual {
+        Ok(())
+    } else {
+        Err(SphError::LengthMismatch { what, expected, actual })
+    }
+}
@@ -1,23 +1,56 @@
 use crate::units::*;
 use cgmath::prelude::*;
-use ggez::graphics::Rect;
 use rand::prelude::*;
 use rayon::prelude::*;
 
+use super::bluenoise;
+use super::error::{check_length, SphError};
+use super::forcefield::ForceField;
+use super::interpolation::{self, Interpolatable, Normalization};
 use super::neighborhood_search::{NeighborhoodSearch, ParticleIndex};
+use super::particleattributes::{AttributeValue, ParticleAttribute, ParticleAttributes};
 use super::scratch_buffer::ScratchBufferStore;
-use super::smoothing_kernel::Kernel;
+use super::simulationrng::SimulationRng;
+use super::smoothing_kernel::{self, Kernel};
+use super::validation::{self, SceneWarning};
 
 pub struct Particles {
     pub positions: Vec<Point>,
     pub velocities: Vec<Vector>,
 
+    // in kg, ConstantFluidProperties::particle_mass unless explicitly set otherwise
+    // Boundary particles all contribute with FluidParticleWorld::boundary_particle_mass.
+    pub masses: Vec<Real>,
+
     // Local densities ρ
     // typically recomputed every frame
     pub densities: Vec<Real>,
 
-    // also called "shadow particles", immovable particles used for boundaries
+    // Whether a particle is at the free surface (or isolated), see FluidParticleWorld::update_free_surface
+    // recomputed every frame after the densities
+    pub free_surface: Vec<bool>,
+
+    // User defined tag per particle that stays with its particle, e.g. to color groups of particles. 0 unless set otherwise.
+    // Particles added since the last neighborhood update may not have an entry yet.
+    pub tags: Vec<u32>,
+
+    // Additional attributes registered via Particles::register_attribute.
+    attributes: ParticleAttributes,
+
+    // also called "shadow particles", particles used for boundaries that are not affected by the fluid
     pub boundary_particles: Vec<Point>,
+    // in m/s, zero unless the boundary particle is part of a moving boundary
+    // Particles added since the last neighborhood update don't have an entry yet.
+    pub boundary_velocities: Vec<Vector>,
+    // in N/m, force the fluid exerted on each boundary particle during the last simulation step, see FluidParticleWorld::update_boundary_forces
+    pub boundary_forces: Vec<Vector>,
+
+    // Per boundary particle, its position in the rest pose of its moving boundary
+    // and the moving boundary it belongs to (index + 1, 0 for static boundaries).
+    boundary_rest_positions: Vec<Vector>,
+    boundary_moving_ids: Vec<u32>,
+    // Per boundary particle, the boundary object it was sampled from, i.e. the index in FluidParticleWorld::boundary_geometry.
+    boundary_object_ids: Vec<u32>,
 
     neighborhood: NeighborhoodSearch,
 }
@@ -46,19 +79,104 @@ impl Particles {
     pub(super) fn num_total_neighbors(&self, pidx: ParticleIndex) -> u32 {
         self.neighborhood.num_neighbors(pidx) + self.neighborhood.num_boundary_neighbors(pidx)
     }
+
+    // Registers an additional per particle attribute, e.g. temperature, set to `default` for all existing and future particles.
+    // The attribute follows its particles through additions, removals, reordering and snapshots.
+    // If an attribute of that name and type already exists, it is returned instead.
+    pub fn register_attribute<T: AttributeValue>(&mut self, name: &str, default: T) -> ParticleAttribute<T> {
+        self.attributes.register(name, default, self.positions.len())
+    }
+
+    pub fn find_attribute<T: AttributeValue>(&self, name: &str) -> Option<ParticleAttribute<T>> {
+        self.attributes.find(name)
+    }
+
+    // Values of a registered attribute per particle.
+    // Particles that were added by pushing to `positions` directly don't have an entry until the next neighborhood update.
+    pub fn attribute<T: AttributeValue>(&self, attribute: ParticleAttribute<T>) -> &[T] {
+        self.attributes.values(attribute)
+    }
+
+    pub fn attribute_mut<T: AttributeValue>(&mut self, attribute: ParticleAttribute<T>) -> &mut [T] {
+        self.attributes.values_mut(attribute)
+    }
+
+    // Csv columns of all registered attributes, each preceded by a comma. Empty if there are none.
+    pub(super) fn attributes_csv_header(&self) -> String {
+        self.attributes.csv_header()
+    }
+
+    pub(super) fn attributes_csv_row(&self, pidx: usize) -> String {
+        self.attributes.csv_row(pidx)
+    }
+
+    pub(super) fn attributes(&self) -> &ParticleAttributes {
+        &self.attributes
+    }
+
+    // Neighborhood datastructure as of the last simulation step.
+    pub fn neighborhood(&self) -> &NeighborhoodSearch {
+        &self.neighborhood
+    }
+
+    // Number of fluid and boundary neighbors as of the last neighborhood update.
+    // Particles added since then are not part of the neighborhood datastructure yet and report zero.
+    pub fn num_neighbors(&self, pidx: ParticleIndex) -> u32 {
+        if pidx as usize >= self.neighborhood.num_particles() {
+            0
+        } else {
+            self.num_total_neighbors(pidx)
+        }
+    }
+
+    // Calls f for every fluid particle within a radius around an arbitrary position.
+    // Uses the neighborhood datastructure as of the last simulation step, i.e. particles added since are not found.
+    pub fn foreach_fluid_particle_in_radius(&self, position: Point, radius: Real, mut f: impl FnMut(ParticleIndex)) {
+        // particles may have been removed since the last update, query_neighbors skips those
+        self.neighborhood
+            .query_neighbors(&self.positions, position, Some(radius), |j| f(j as ParticleIndex));
+    }
+
+    // Whether there is any fluid or boundary particle closer than min_distance to a given position.
+    // Unlike foreach_fluid_particle_in_radius this also considers fluid particles that were added after the last simulation step.
+    pub fn is_occupied(&self, position: Point, min_distance: Real) -> bool {
+        let min_distance_sq = min_distance * min_distance;
+        let mut occupied = false;
+        self.foreach_fluid_particle_in_radius(position, min_distance, |_| occupied = true);
+        if occupied {
+            return true;
+        }
+
+        let boundary_particles = &self.boundary_particles;
+        self.neighborhood
+            .foreach_potential_boundary_neighbor_in_radius(position, min_distance, |j| {
+                if let Some(pos_j) = boundary_particles.get(j) {
+                    occupied |= pos_j.distance2(position) < min_distance_sq;
+                }
+            });
+        if occupied {
+            return true;
+        }
+
+        let num_known_particles = self.neighborhood.num_particles().min(self.positions.len());
+        self.positions[num_known_particles..]
+            .iter()
+            .any(|pos_j| pos_j.distance2(position) < min_distance_sq)
+    }
 }
 
+#[derive(Clone, Copy)]
 pub struct ConstantFluidProperties {
-    smoothing_length: Real, // typically expressed as 'h'
-    particle_density: Real, // #particles/m² for resting fluid
-    fluid_density: Real,    // kg/m² for the resting fluid (ρ, rho)
+    smoothing_length: Real,          // typically expressed as 'h'
+    particle_density: NumberDensity, // #particles/m² for resting fluid
+    fluid_density: Density,          // for the resting fluid (ρ, rho)
 }
 
 impl ConstantFluidProperties {
     fn new(
         smoothing_factor: Real,
-        particle_density: Real, // #particles/m² for resting fluid
-        fluid_density: Real,    // kg/m² for the resting fluid) {
+        particle_density: NumberDensity, // #particles/m² for resting fluid
+        fluid_density: Density,          // for the resting fluid
     ) -> ConstantFluidProperties {
         let smoothing_length = 2.0 * Self::particle_radius_from_particle_density(particle_density) * smoothing_factor;
         ConstantFluidProperties {
@@ -72,21 +190,23 @@ impl ConstantFluidProperties {
         self.smoothing_length
     }
 
+    // In kg/m².
     pub fn fluid_density(&self) -> Real {
-        self.fluid_density
+        self.fluid_density.0
     }
 
+    // Mass of a particle at the rest particle density, used for all newly added particles by default.
     pub fn particle_mass(&self) -> Real {
-        self.fluid_density / self.particle_density
+        (self.fluid_density / self.particle_density).0
     }
 
     fn num_particles_per_meter(&self) -> Real {
-        self.particle_density.sqrt()
+        self.particle_density.0.sqrt()
     }
 
-    fn particle_radius_from_particle_density(particle_density: Real) -> Real {
+    fn particle_radius_from_particle_density(particle_density: NumberDensity) -> Real {
         // density is per m²
-        0.5 / particle_density.sqrt()
+        0.5 / particle_density.0.sqrt()
     }
 
     pub fn particle_radius(&self) -> Real {
@@ -94,51 +214,331 @@ impl ConstantFluidProperties {
     }
 }
 
+// Shape that boundary particles were sampled from.
+// Kept for visualization, the simulation itself only knows about boundary particles.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BoundaryGeometry {
+    // Line covered by boundary particles, `width` is the thickness of the covered area.
+    Line { start: Point, end: Point, width: Real },
+    // Closed polygon outline.
+    Polygon { vertices: Vec<Point>, width: Real },
+    // Open line strip through all vertices.
+    Polyline { vertices: Vec<Point>, width: Real },
+    Circle { center: Point, radius: Real, width: Real },
+}
+
+impl BoundaryGeometry {
+    // Same geometry with all points mapped by a rigid transformation, widths and radii are kept.
+    pub fn transformed(&self, transform: impl Fn(Point) -> Point) -> BoundaryGeometry {
+        match self {
+            BoundaryGeometry::Line { start, end, width } => BoundaryGeometry::Line {
+                start: transform(*start),
+                end: transform(*end),
+                width: *width,
+            },
+            BoundaryGeometry::Polygon { vertices, width } => BoundaryGeometry::Polygon {
+                vertices: vertices.iter().map(|v| transform(*v)).collect(),
+                width: *width,
+            },
+            BoundaryGeometry::Polyline { vertices, width } => BoundaryGeometry::Polyline {
+                vertices: vertices.iter().map(|v| transform(*v)).collect(),
+                width: *width,
+            },
+            BoundaryGeometry::Circle { center, radius, width } => BoundaryGeometry::Circle {
+                center: transform(*center),
+                radius: *radius,
+                width: *width,
+            },
+        }
+    }
+}
+
+// Force in N/m and torque in N (i.e. Nm/m) that the fluid exerted on a boundary object, see FluidParticleWorld::boundary_object_forces.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoundaryObjectForce {
+    pub force: Vector,
+    pub torque: Real,  // around `center`, counter-clockwise positive
+    pub center: Point, // centroid of the object's boundary particles
+}
+
+impl Default for BoundaryObjectForce {
+    fn default() -> Self {
+        BoundaryObjectForce {
+            force: Vector::zero(),
+            torque: 0.0,
+            center: Point::origin(),
+        }
+    }
+}
+
+impl BoundaryObjectForce {
+    // Torque around an arbitrary point, e.g. the base of a wall.
+    pub fn torque_around(&self, point: Point) -> Real {
+        self.torque + (self.center - point).perp_dot(self.force)
+    }
+}
+
+// Heap memory in bytes held by a FluidParticleWorld, see FluidParticleWorld::memory_usage.
+// Doesn't include per particle data of solvers and scratch buffers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    pub fluid_particles: usize,
+    pub boundary_particles: usize,
+    pub neighbor_lists: usize,
+    pub grid_cells: usize,
+}
+
+impl MemoryUsage {
+    pub fn total(&self) -> usize {
+        self.fluid_particles + self.boundary_particles + self.neighbor_lists + self.grid_cells
+    }
+}
+
+// Number of iterations of FluidParticleWorld::relax_initial_state.
+const RELAXATION_ITERATIONS: usize = 20;
+// Relaxation iterations move particles by at most this fraction of the rest spacing.
+const RELAXATION_MAX_SHIFT: Real = 0.1;
+
+// Handle to a group of boundary particles that is moved as a whole, see FluidParticleWorld::add_moving_boundary.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MovingBoundary(pub(super) u32);
+
+// Boundary geometry of a moving boundary in its rest pose, along with the index in FluidParticleWorld::boundary_geometry.
+#[derive(Clone)]
+struct MovingBoundaryGeometry {
+    rest_geometry: Vec<(usize, BoundaryGeometry)>,
+}
+
 pub struct FluidParticleWorld {
     pub particles: Particles,
     pub properties: ConstantFluidProperties,
 
     pub(super) scratch_buffers: ScratchBufferStore,
 
-    pub gravity: Vector, // global gravity force in m/s² (== N/kg)
+    // external forces acting on all fluid particles, by default just earth's gravity
+    pub force_fields: Vec<ForceField>,
+
+    // source of all randomness, e.g. the jitter of add_fluid_rect. Saving and restoring its state along with the particles reproduces a simulation exactly.
+    pub rng: SimulationRng,
 
     // tracks whether boundary particles have been added/moved
     boundary_changed: bool,
+    boundary_sampling_factor: Real,
+    boundary_geometry: Vec<BoundaryGeometry>,
+    moving_boundaries: Vec<MovingBoundaryGeometry>,
 }
 impl FluidParticleWorld {
     pub fn new(
         smoothing_factor: Real,
-        particle_density: Real, // #particles/m² for resting fluid
-        fluid_density: Real,    // kg/m² for the resting fluid
+        particle_density: NumberDensity, // #particles/m² for resting fluid
+        fluid_density: Density,          // for the resting fluid
     ) -> FluidParticleWorld {
         let properties = ConstantFluidProperties::new(smoothing_factor, particle_density, fluid_density);
         FluidParticleWorld {
             particles: Particles {
                 positions: Vec::new(),
                 velocities: Vec::new(),
+                masses: Vec::new(),
                 densities: Vec::new(),
+                free_surface: Vec::new(),
+                tags: Vec::new(),
+                attributes: ParticleAttributes::default(),
 
                 boundary_particles: Vec::new(),
+                boundary_velocities: Vec::new(),
+                boundary_forces: Vec::new(),
+                boundary_rest_positions: Vec::new(),
+                boundary_moving_ids: Vec::new(),
+                boundary_object_ids: Vec::new(),
 
                 neighborhood: NeighborhoodSearch::new(properties.smoothing_length()),
             },
             properties,
             scratch_buffers: ScratchBufferStore::new(),
 
-            gravity: Vector::new(0.0, -9.81),
+            force_fields: vec![ForceField::Gravity(Vector::new(0.0, -9.81))],
+            rng: SimulationRng::default(),
 
             boundary_changed: true,
+            boundary_sampling_factor: 1.0,
+            boundary_geometry: Vec::new(),
+            moving_boundaries: Vec::new(),
+        }
+    }
+
+    // Copy of particles, boundaries and properties, e.g. to look at the world while the original is simulated on a SimulationThread.
+    // Custom force fields can't be copied and are left out.
+    pub fn copy_for_display(&self) -> FluidParticleWorld {
+        let particles = &self.particles;
+        let mut copy = FluidParticleWorld {
+            particles: Particles {
+                positions: particles.positions.clone(),
+                velocities: particles.velocities.clone(),
+                masses: particles.masses.clone(),
+                densities: particles.densities.clone(),
+                free_surface: particles.free_surface.clone(),
+                tags: particles.tags.clone(),
+                attributes: particles.attributes.clone(),
+
+                boundary_particles: particles.boundary_particles.clone(),
+                boundary_velocities: particles.boundary_velocities.clone(),
+                boundary_forces: particles.boundary_forces.clone(),
+                boundary_rest_positions: particles.boundary_rest_positions.clone(),
+                boundary_moving_ids: particles.boundary_moving_ids.clone(),
+                boundary_object_ids: particles.boundary_object_ids.clone(),
+
+                neighborhood: NeighborhoodSearch::new(self.properties.smoothing_length()),
+            },
+            properties: self.properties,
+            scratch_buffers: ScratchBufferStore::new(),
+
+            force_fields: self.force_fields.iter().filter_map(ForceField::try_clone).collect(),
+            rng: self.rng.clone(),
+
+            boundary_changed: true,
+            boundary_sampling_factor: self.boundary_sampling_factor,
+            boundary_geometry: self.boundary_geometry.clone(),
+            moving_boundaries: self.moving_boundaries.clone(),
+        };
+        // densities aren't sorted along with the other attributes
+        let mut densities = std::mem::take(&mut copy.particles.densities);
+        copy.update_neighborhood_datastructure(Vec::new(), vec![&mut densities]);
+        copy.particles.densities = densities;
+        copy.update_free_surface();
+        copy
+    }
+
+    // Sum of all uniform gravity fields in m/s² (== N/kg).
+    pub fn gravity(&self) -> Vector {
+        self.force_fields
+            .iter()
+            .map(|field| match field {
+                ForceField::Gravity(gravity) => *gravity,
+                _ => Vector::zero(),
+            })
+            .sum()
+    }
+
+    // Replaces all uniform gravity fields with a single one, keeping all other force fields.
+    pub fn set_gravity(&mut self, gravity: Vector) {
+        self.force_fields.retain(|field| !matches!(field, ForceField::Gravity(_)));
+        self.force_fields.insert(0, ForceField::Gravity(gravity));
+    }
+
+    // Boundary particles per fluid particle spacing along boundary lines and circles, 1 by default.
+    // Denser boundaries keep particles from slipping through gaps at corners when pressure is high.
+    // Rows of thick lines stay at the fluid spacing, so every boundary particle represents the same share of a fluid particle's mass.
+    pub fn boundary_sampling_factor(&self) -> Real {
+        self.boundary_sampling_factor
+    }
+
+    // Only possible while there are no boundary particles, since those already added keep their spacing.
+    pub fn set_boundary_sampling_factor(&mut self, boundary_sampling_factor: Real) -> Result<(), SphError> {
+        if !self.particles.boundary_particles.is_empty() {
+            return Err(SphError::InvalidArgument {
+                what: "boundary sampling factor",
+                reason: "can't change once boundary particles were added",
+            });
+        }
+        if boundary_sampling_factor <= 0.0 || boundary_sampling_factor.is_nan() {
+            return Err(SphError::InvalidArgument {
+                what: "boundary sampling factor",
+                reason: "needs to be positive",
+            });
+        }
+        self.boundary_sampling_factor = boundary_sampling_factor;
+        Ok(())
+    }
+
+    // Distance between neighboring boundary particles along a boundary.
+    pub fn boundary_particle_spacing(&self) -> Real {
+        self.properties.particle_radius() * 2.0 / self.boundary_sampling_factor
+    }
+
+    // Mass boundary particles contribute to densities and pressure forces in place of fluid.
+    pub fn boundary_particle_mass(&self) -> Real {
+        self.properties.particle_mass() / self.boundary_sampling_factor
+    }
+
+    pub fn boundary_geometry(&self) -> &[BoundaryGeometry] {
+        &self.boundary_geometry
+    }
+
+    // Preallocates particle arrays and the neighborhood search for the given total numbers of fluid and boundary particles,
+    // so that adding particles up to these counts doesn't reallocate, e.g. for scenes that spawn fluid over time.
+    pub fn reserve(&mut self, num_fluid_particles: usize, num_boundary_particles: usize) {
+        let particles = &mut self.particles;
+        reserve_total(&mut particles.positions, num_fluid_particles);
+        reserve_total(&mut particles.velocities, num_fluid_particles);
+        reserve_total(&mut particles.masses, num_fluid_particles);
+        reserve_total(&mut particles.densities, num_fluid_particles);
+        reserve_total(&mut particles.free_surface, num_fluid_particles);
+        reserve_total(&mut particles.tags, num_fluid_particles);
+        particles.attributes.reserve(num_fluid_particles);
+
+        reserve_total(&mut particles.boundary_particles, num_boundary_particles);
+        reserve_total(&mut particles.boundary_velocities, num_boundary_particles);
+        reserve_total(&mut particles.boundary_forces, num_boundary_particles);
+        reserve_total(&mut particles.boundary_rest_positions, num_boundary_particles);
+        reserve_total(&mut particles.boundary_moving_ids, num_boundary_particles);
+        reserve_total(&mut particles.boundary_object_ids, num_boundary_particles);
+
+        particles.neighborhood.reserve(num_fluid_particles, num_boundary_particles);
+    }
+
+    // Heap memory currently held by particle arrays, neighbor lists and grid cells. Counts capacity, not just what is in use.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let particles = &self.particles;
+        MemoryUsage {
+            fluid_particles: heap_size(&particles.positions)
+                + heap_size(&particles.velocities)
+                + heap_size(&particles.masses)
+                + heap_size(&particles.densities)
+                + heap_size(&particles.free_surface)
+                + heap_size(&particles.tags)
+                + particles.attributes.memory_usage(),
+            boundary_particles: heap_size(&particles.boundary_particles)
+                + heap_size(&particles.boundary_velocities)
+                + heap_size(&particles.boundary_forces)
+                + heap_size(&particles.boundary_rest_positions)
+                + heap_size(&particles.boundary_moving_ids)
+                + heap_size(&particles.boundary_object_ids),
+            neighbor_lists: particles.neighborhood.neighbor_lists_memory_usage(),
+            grid_cells: particles.neighborhood.grid_memory_usage(),
         }
     }
 
     pub fn remove_all_fluid_particles(&mut self) {
         self.particles.positions.clear();
         self.particles.velocities.clear();
+        self.particles.masses.clear();
+        self.particles.free_surface.clear();
+        self.particles.tags.clear();
+        self.particles.attributes.resize(0);
+    }
+
+    // Removes all fluid particles whose entry in `keep` is false.
+    // Solvers keep per particle data across steps, so they need to drop theirs via Solver::retain_particle_data as well.
+    pub fn retain_fluid_particles(&mut self, keep: &[bool]) {
+        retain_particle_attribute(&mut self.particles.positions, keep);
+        retain_particle_attribute(&mut self.particles.velocities, keep);
+        retain_particle_attribute(&mut self.particles.masses, keep);
+        retain_particle_attribute(&mut self.particles.densities, keep);
+        retain_particle_attribute(&mut self.particles.free_surface, keep);
+        retain_particle_attribute(&mut self.particles.tags, keep);
+        self.particles.attributes.retain(keep);
     }
 
     pub fn remove_all_boundary_particles(&mut self) {
         self.particles.boundary_particles.clear();
-        self.particles.velocities.clear();
+        self.particles.boundary_velocities.clear();
+        self.particles.boundary_forces.clear();
+        self.particles.boundary_rest_positions.clear();
+        self.particles.boundary_moving_ids.clear();
+        self.particles.boundary_object_ids.clear();
+        self.boundary_geometry.clear();
+        self.moving_boundaries.clear();
+        self.boundary_changed = true;
     }
 
     /// - `jitter`: Amount of jitter. 0 for perfect lattice. >1 and particles are no longer in a strict lattice.
@@ -152,16 +552,16 @@ impl FluidParticleWorld {
         let new_total_particle_count = self.particles.positions.len() + num_particles;
         self.particles.positions.reserve(new_total_particle_count);
         self.particles.velocities.resize(new_total_particle_count, Zero::zero());
+        self.particles.masses.resize(new_total_particle_count, self.properties.particle_mass());
         self.particles.densities.resize(new_total_particle_count, Zero::zero());
-
-        let mut rng: rand::rngs::SmallRng = rand::SeedableRng::seed_from_u64(self.particles.positions.len() as u64);
+        self.particles.attributes.resize(new_total_particle_count);
 
         let bottom_left = Point::new(fluid_rect.x as Real, fluid_rect.y as Real);
         let step = (fluid_rect.w as Real / (num_particles_x as Real)).min(fluid_rect.h as Real / (num_particles_y as Real));
         let jitter_factor = step * jitter_amount;
         for y in 0..num_particles_y {
             for x in 0..num_particles_x {
-                let jitter = (rng.gen::<Vector>() * 0.5 + Vector::new(0.5, 0.5)) * jitter_factor;
+                let jitter = (self.rng.gen::<Vector>() * 0.5 + Vector::new(0.5, 0.5)) * jitter_factor;
                 self.particles
                     .positions
                     .push(bottom_left + jitter + Vector::new(step * (x as Real), step * (y as Real)));
@@ -169,6 +569,90 @@ impl FluidParticleWorld {
         }
     }
 
+    // Adds resting fluid particles at blue noise positions within a shape, an alternative to add_fluid_rect's lattice.
+    // Unlike a lattice, this starts without a preferred direction, which avoids the lattice artifacts during the first moments of a simulation.
+    // `inside` tells whether a position belongs to the shape, `bounds` needs to contain the entire shape.
+    //
+    // The number of particles per area matches the particle density, but some particles are closer than the rest spacing.
+    // Since close particles contribute more to SPH densities, masses are lowered such that the (cubic spline) density in the shape's interior
+    // matches the rest density, i.e. the fluid starts at rest.
+    //
+    // Returns the number of added particles.
+    pub fn add_fluid_blue_noise(&mut self, bounds: &Rect, inside: impl Fn(Point) -> bool) -> usize {
+        let spacing = 1.0 / self.properties.num_particles_per_meter();
+        let min_distance = spacing * bluenoise::POISSON_DISK_DENSITY_RATIO.sqrt();
+        let samples = bluenoise::poisson_disk_samples(bounds, min_distance, inside, &mut self.rng);
+
+        let smoothing_length = self.properties.smoothing_length();
+        let kernel_sum = bluenoise::median_kernel_sum(&samples, &smoothing_kernel::CubicSpline::new(smoothing_length), smoothing_length);
+        let mass = self.properties.fluid_density() / kernel_sum;
+        for position in samples.iter() {
+            self.add_fluid_particle_with_mass(*position, Zero::zero(), mass);
+        }
+        samples.len()
+    }
+
+    pub fn add_fluid_particle(&mut self, position: Point, velocity: Vector) {
+        self.add_fluid_particle_with_mass(position, velocity, self.properties.particle_mass());
+    }
+
+    // Mass in kg. Note that the smoothing length is the same for all particles,
+    // so particles much lighter or heavier than ConstantFluidProperties::particle_mass need to be spaced accordingly.
+    pub fn add_fluid_particle_with_mass(&mut self, position: Point, velocity: Vector, mass: Real) {
+        self.particles.positions.push(position);
+        self.particles.velocities.push(velocity);
+        self.particles.masses.push(mass);
+        self.particles.densities.push(Zero::zero());
+        self.particles.attributes.resize(self.particles.positions.len());
+    }
+
+    /// Adds resting fluid particles on a lattice with rest spacing within a circle.
+    /// Spots that are already occupied by fluid or boundary particles are skipped, so this can be called repeatedly, e.g. for brushes.
+    /// Lattice is aligned to the world origin, making repeated calls at slightly different positions consistent.
+    ///
+    /// Returns the number of added particles.
+    pub fn add_fluid_circle(&mut self, center: Point, radius: Real) -> usize {
+        let num_particles_per_meter = self.properties.num_particles_per_meter();
+        let spacing = 1.0 / num_particles_per_meter;
+        let radius_sq = radius * radius;
+
+        let min_x = ((center.x - radius) * num_particles_per_meter).ceil() as i32;
+        let max_x = ((center.x + radius) * num_particles_per_meter).floor() as i32;
+        let min_y = ((center.y - radius) * num_particles_per_meter).ceil() as i32;
+        let max_y = ((center.y + radius) * num_particles_per_meter).floor() as i32;
+
+        let mut num_added_particles = 0;
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                let position = Point::new(x as Real * spacing, y as Real * spacing);
+                if position.distance2(center) > radius_sq || self.particles.is_occupied(position, spacing * 0.99) {
+                    continue;
+                }
+                self.add_fluid_particle(position, Zero::zero());
+                num_added_particles += 1;
+            }
+        }
+        num_added_particles
+    }
+
+    // Overwrites the velocities of all fluid particles within a region with a velocity field, e.g. to start a block of fluid
+    // with a shear layer, rotation or a vortex instead of at rest. Meant to be called after adding the fluid, before simulating.
+    // Particles exactly on the left or bottom edge are inside, on the right or top edge outside.
+    //
+    // Returns the number of affected particles.
+    pub fn set_initial_velocity(&mut self, region: &Rect, velocity: impl Fn(Point) -> Vector) -> usize {
+        let particles = &mut self.particles;
+        let mut num_affected_particles = 0;
+        for (p, v) in particles.positions.iter().zip(particles.velocities.iter_mut()) {
+            if p.x < region.x || p.y < region.y || p.x >= region.x + region.w || p.y >= region.y + region.h {
+                continue;
+            }
+            *v = velocity(*p);
+            num_affected_particles += 1;
+        }
+        num_affected_particles
+    }
+
     pub fn add_boundary_thick_line(&mut self, start: Point, end: Point, thickness_in_particles: u32) {
         let dir = (end - start).normalize();
         let dir_perpendicular = Vector::new(-dir.y, dir.x);
@@ -177,17 +661,221 @@ impl FluidParticleWorld {
         let mut offset = -dir_perpendicular * thickness_world;
         let step = dir_perpendicular * thickness_world / thickness_in_particles as Real;
         for _ in 0..thickness_in_particles {
-            self.add_boundary_line(start + offset, end + offset + elongation);
+            self.sample_boundary_line(start + offset, end + offset + elongation);
             offset += step;
         }
+
+        // particle rows lie on one side of the line
+        let center_offset = -dir_perpendicular * (thickness_world + step.magnitude()) * 0.5;
+        self.add_boundary_object(BoundaryGeometry::Line {
+            start: start + center_offset,
+            end: end + center_offset + elongation,
+            width: thickness_world,
+        });
     }
 
     pub fn add_boundary_line(&mut self, start: Point, end: Point) {
+        self.sample_boundary_line(start, end);
+        self.add_boundary_object(BoundaryGeometry::Line {
+            start,
+            end,
+            width: self.properties.particle_radius() * 2.0,
+        });
+    }
+
+    // Open line strip through all vertices with `thickness_in_particles` rows of boundary particles on the right side, like add_boundary_thick_line.
+    // Unlike thick lines meeting at a corner, rows follow the corners with mitered joins and have exactly one particle at every vertex,
+    // leaving neither gaps nor overlaps at junctions.
+    // Corners are rounded off by fillets of the given radius, 0 for sharp corners. Fillets shrink where segments are too short for them.
+    pub fn add_boundary_polyline(&mut self, vertices: &[Point], thickness_in_particles: u32, fillet_radius: Real) {
+        let spacing = self.properties.particle_radius() * 2.0;
+        let center_line = fillet_polyline(vertices, fillet_radius, spacing);
+        for row in 1..=thickness_in_particles {
+            self.sample_boundary_polyline(&offset_polyline(&center_line, -spacing * row as Real), false);
+        }
+
+        // particle rows lie on one side of the line
+        let thickness_world = spacing * thickness_in_particles as Real;
+        self.add_boundary_object(BoundaryGeometry::Polyline {
+            vertices: offset_polyline(&center_line, -(thickness_world + spacing) * 0.5),
+            width: thickness_world,
+        });
+    }
+
+    // Closed outline through all vertices.
+    pub fn add_boundary_polygon(&mut self, vertices: &[Point]) {
+        self.sample_boundary_polyline(vertices, true);
+        self.add_boundary_object(BoundaryGeometry::Polygon {
+            vertices: vertices.to_vec(),
+            width: self.properties.particle_radius() * 2.0,
+        });
+    }
+
+    pub fn add_boundary_circle(&mut self, center: Point, radius: Real) {
+        let circumference = 2.0 * std::f32::consts::PI * radius;
+        let num_shadow_particles = std::cmp::max(1, (circumference / self.boundary_particle_spacing()).ceil() as usize);
+        self.particles.boundary_particles.reserve(num_shadow_particles);
+        for i in 0..num_shadow_particles {
+            let angle = i as Real / num_shadow_particles as Real * 2.0 * std::f32::consts::PI;
+            self.particles
+                .boundary_particles
+                .push(center + Vector::new(angle.cos(), angle.sin()) * radius);
+        }
+
+        self.boundary_changed = true;
+        self.add_boundary_object(BoundaryGeometry::Circle {
+            center,
+            radius,
+            width: self.properties.particle_radius() * 2.0,
+        });
+    }
+
+    // Boundary particles and geometry added by `add` become a moving boundary, e.g.
+    //   world.add_moving_boundary(|world| world.add_boundary_line(start, end))
+    // Where they were added is their rest pose, see move_boundary.
+    pub fn add_moving_boundary(&mut self, add: impl FnOnce(&mut FluidParticleWorld)) -> MovingBoundary {
+        self.resize_boundary_attributes();
+        let first_particle = self.particles.boundary_particles.len();
+        let first_geometry = self.boundary_geometry.len();
+        add(self);
+
+        self.moving_boundaries.push(MovingBoundaryGeometry {
+            rest_geometry: self.boundary_geometry[first_geometry..]
+                .iter()
+                .enumerate()
+                .map(|(i, geometry)| (first_geometry + i, geometry.clone()))
+                .collect(),
+        });
+        let id = self.moving_boundaries.len() as u32;
+        for p in self.particles.boundary_particles[first_particle..].iter() {
+            self.particles.boundary_velocities.push(Vector::zero());
+            self.particles.boundary_forces.push(Vector::zero());
+            self.particles.boundary_rest_positions.push(p.to_vec());
+            self.particles.boundary_moving_ids.push(id);
+        }
+        MovingBoundary(id - 1)
+    }
+
+    // Moves all particles and geometry of a moving boundary.
+    // `pose` maps positions from the rest pose to the current one, `velocity` gives the velocity at a current position.
+    // Meant to be called right before a simulation step, boundaries are rigid so both should describe a rigid motion.
+    pub fn move_boundary(&mut self, moving_boundary: MovingBoundary, pose: impl Fn(Point) -> Point, velocity: impl Fn(Point) -> Vector) {
+        self.resize_boundary_attributes();
+        let id = moving_boundary.0 + 1;
+        let particles = &mut self.particles;
+        for (i, position) in particles.boundary_particles.iter_mut().enumerate() {
+            if particles.boundary_moving_ids[i] == id {
+                *position = pose(Point::from_vec(particles.boundary_rest_positions[i]));
+                particles.boundary_velocities[i] = velocity(*position);
+            }
+        }
+        for (i, rest_geometry) in self.moving_boundaries[moving_boundary.0 as usize].rest_geometry.iter() {
+            self.boundary_geometry[*i] = rest_geometry.transformed(&pose);
+        }
+        self.boundary_changed = true;
+    }
+
+    // Overwrites all boundary particle positions, e.g. with those of a ParticleSnapshot. Doesn't touch the boundary geometry.
+    pub(super) fn set_boundary_particle_positions(&mut self, positions: &[Point]) {
+        self.particles.boundary_particles.clear();
+        self.particles.boundary_particles.extend_from_slice(positions);
+        self.boundary_changed = true;
+    }
+
+    // Total force in N/m and torque in N (i.e. Nm/m) around `center` that the fluid exerted on a moving boundary during the last simulation step.
+    pub fn moving_boundary_force(&self, moving_boundary: MovingBoundary, center: Point) -> (Vector, Real) {
+        let id = moving_boundary.0 + 1;
+        let particles = &self.particles;
+        let mut force = Vector::zero();
+        let mut torque = 0.0;
+        for (i, f) in particles.boundary_forces.iter().enumerate() {
+            if particles.boundary_moving_ids[i] == id {
+                force += *f;
+                torque += (particles.boundary_particles[i] - center).perp_dot(*f);
+            }
+        }
+        (force, torque)
+    }
+
+    // Total force and torque that the fluid exerted on each boundary object during the last simulation step, in the order of boundary_geometry.
+    // Every add_boundary_* call adds one boundary object, e.g. the walls of a dam or an obstacle.
+    pub fn boundary_object_forces(&self) -> Vec<BoundaryObjectForce> {
+        let particles = &self.particles;
+        let mut position_sums = vec![(Vector::zero(), 0); self.boundary_geometry.len()];
+        for (p, object) in particles.boundary_particles.iter().zip(particles.boundary_object_ids.iter()) {
+            let (sum, count) = &mut position_sums[*object as usize];
+            *sum += p.to_vec();
+            *count += 1;
+        }
+        let mut forces: Vec<BoundaryObjectForce> = position_sums
+            .iter()
+            .map(|(sum, count)| BoundaryObjectForce {
+                center: Point::from_vec(*sum / (*count).max(1) as Real),
+                ..Default::default()
+            })
+            .collect();
+
+        // forces are only known for boundary particles that existed during the last step
+        for ((p, f), object) in particles
+            .boundary_particles
+            .iter()
+            .zip(particles.boundary_forces.iter())
+            .zip(particles.boundary_object_ids.iter())
+        {
+            let object_force = &mut forces[*object as usize];
+            object_force.force += *f;
+            object_force.torque += (p - object_force.center).perp_dot(*f);
+        }
+        forces
+    }
+
+    // Recomputes boundary_forces as reaction to the accelleration that fluid particles get from their boundary neighbors,
+    // given by `accelleration_from_boundary(particles, fluid particle, boundary particle)`.
+    // Solvers call this at the end of a simulation step with the neighborhood of that step.
+    pub(super) fn update_boundary_forces(&mut self, accelleration_from_boundary: impl Fn(&Particles, ParticleIndex, ParticleIndex) -> Vector) {
+        microprofile::scope!("FluidParticleWorld", "update_boundary_forces");
+        self.resize_boundary_attributes();
+        let mut forces = std::mem::take(&mut self.particles.boundary_forces);
+        for force in forces.iter_mut() {
+            *force = Vector::zero();
+        }
+        let particles = &self.particles;
+        for i in 0..particles.neighborhood.num_particles().min(particles.positions.len()) {
+            let i = i as ParticleIndex;
+            particles.foreach_neighbor_particle_boundary(i, |j| {
+                forces[j as usize] -= accelleration_from_boundary(particles, i, j) * particles.masses[i as usize];
+            });
+        }
+        self.particles.boundary_forces = forces;
+    }
+
+    // Brings per boundary particle attributes in sync with boundary particles that were added as static boundaries.
+    fn resize_boundary_attributes(&mut self) {
+        let particles = &mut self.particles;
+        let num_boundary_particles = particles.boundary_particles.len();
+        particles.boundary_velocities.resize(num_boundary_particles, Vector::zero());
+        particles.boundary_forces.resize(num_boundary_particles, Vector::zero());
+        particles.boundary_moving_ids.resize(num_boundary_particles, 0);
+        let boundary_particles = &particles.boundary_particles;
+        let rest_positions = &mut particles.boundary_rest_positions;
+        let num_known = rest_positions.len();
+        rest_positions.extend(boundary_particles[num_known..].iter().map(|p| p.to_vec()));
+    }
+
+    // Records the geometry of all boundary particles added since the last boundary object as a new boundary object.
+    fn add_boundary_object(&mut self, geometry: BoundaryGeometry) {
+        let object = self.boundary_geometry.len() as u32;
+        let num_boundary_particles = self.particles.boundary_particles.len();
+        self.particles.boundary_object_ids.resize(num_boundary_particles, object);
+        self.boundary_geometry.push(geometry);
+    }
+
+    fn sample_boundary_line(&mut self, start: Point, end: Point) {
         let distance = start.distance(end);
-        let num_particles_per_meter = self.properties.num_particles_per_meter();
-        let num_shadow_particles = std::cmp::max(1, (distance * num_particles_per_meter).ceil() as usize);
+        let spacing = self.boundary_particle_spacing();
+        let num_shadow_particles = std::cmp::max(1, (distance / spacing).ceil() as usize);
         self.particles.boundary_particles.reserve(num_shadow_particles);
-        let step = (end - start) / distance / self.properties.num_particles_per_meter();
+        let step = (end - start) / distance * spacing;
 
         let mut pos = start; //- step * 0.5;
         for _ in 0..num_shadow_particles {
@@ -198,14 +886,77 @@ impl FluidParticleWorld {
         self.boundary_changed = true;
     }
 
-    pub(super) fn update_densities(&mut self, kernel: impl Kernel + std::marker::Sync) {
+    // Samples every segment evenly, such that there is exactly one particle at every vertex.
+    // Spacing varies a bit from segment to segment in turn.
+    fn sample_boundary_polyline(&mut self, vertices: &[Point], closed: bool) {
+        let spacing = self.boundary_particle_spacing();
+        let num_segments = if closed { vertices.len() } else { vertices.len().saturating_sub(1) };
+        for i in 0..num_segments {
+            let start = vertices[i];
+            let end = vertices[(i + 1) % vertices.len()];
+            let num_particles = std::cmp::max(1, (start.distance(end) / spacing).round() as usize);
+            let step = (end - start) / num_particles as Real;
+            self.particles
+                .boundary_particles
+                .extend((0..num_particles).map(|j| start + step * j as Real));
+        }
+        if !closed {
+            self.particles.boundary_particles.extend(vertices.last());
+        }
+
+        self.boundary_changed = true;
+    }
+
+    // Checks that every per particle array has an entry for every fluid particle, e.g. after positions were pushed to `particles` directly.
+    // Solvers check this before every step, since the neighborhood search reorders all arrays along with the positions.
+    pub fn check_particle_arrays(&self) -> Result<(), SphError> {
+        let particles = &self.particles;
+        let num_particles = particles.positions.len();
+        check_length("velocities", num_particles, particles.velocities.len())?;
+        check_length("masses", num_particles, particles.masses.len())
+    }
+
+    // Checks the scene for problems that would otherwise only show once the simulation blew up, e.g. fluid initialized inside walls.
+    // Meant to be run after setting up a scene, solver dependent checks are in validation::validate_timestep.
+    pub fn validate(&self) -> Vec<SceneWarning> {
+        validation::validate_world(self)
+    }
+
+    // SPH interpolation of a per particle quantity at an arbitrary position.
+    // Shepard normalized, so that it doesn't drop off towards the fluid surface. Returns None if there is no fluid particle in range.
+    // See interpolation::interpolate for details and other normalizations.
+    pub fn interpolate<T: Interpolatable>(&self, position: Point, value: impl Fn(ParticleIndex) -> T) -> Option<T> {
+        interpolation::interpolate(self, position, |j| value(j as ParticleIndex), Normalization::Shepard)
+    }
+
+    // SPH interpolation of the velocity field at an arbitrary position, see interpolate.
+    pub fn interpolate_velocity(&self, position: Point) -> Option<Vector> {
+        let velocities = &self.particles.velocities;
+        self.interpolate(position, |j| velocities[j as usize])
+    }
+
+    // Recomputes all densities using the neighborhood datastructure as of the last simulation step.
+    // Public mostly for benchmarking, solvers take care of this as part of a simulation step.
+    pub fn update_densities(&mut self, kernel: impl Kernel + std::marker::Sync) {
+        self.compute_densities(kernel, true);
+    }
+
+    // Same as update_densities, but densities below the rest density are kept as they are,
+    // e.g. for solvers whose equation of state allows negative pressures.
+    pub fn update_densities_unclamped(&mut self, kernel: impl Kernel + std::marker::Sync) {
+        self.compute_densities(kernel, false);
+    }
+
+    fn compute_densities(&mut self, kernel: impl Kernel + std::marker::Sync, clamp_to_rest_density: bool) {
         microprofile::scope!("FluidParticleWorld", "update_densities");
-        assert_eq!(self.particles.positions.len(), self.particles.densities.len());
+        // densities are recomputed from scratch, including those of particles that were added without one
+        self.particles.densities.resize(self.particles.positions.len(), 0.0);
 
-        let mass = self.properties.particle_mass();
-        let fluid_density = self.properties.fluid_density;
+        let boundary_mass = self.boundary_particle_mass();
+        let fluid_density = self.properties.fluid_density();
         let neighborhood = &self.particles.neighborhood;
         let positions = &self.particles.positions;
+        let masses = &self.particles.masses;
         let boundary_positions = &self.particles.boundary_particles;
 
         self.particles
@@ -214,7 +965,7 @@ impl FluidParticleWorld {
             .zip(positions.par_iter())
             .enumerate()
             .for_each(|(i, (density, ri))| {
-                *density = kernel.evaluate(0.0, 0.0) * mass; // self-contribution
+                *density = kernel.evaluate(0.0, 0.0) * masses[i]; // self-contribution
                 let i = i as u32;
                 Particles::foreach_neighbor_particle_internal(
                     &neighborhood,
@@ -222,7 +973,7 @@ impl FluidParticleWorld {
                     #[inline(always)]
                     |j| {
                         let r_sq = ri.distance2(unsafe { *positions.get_unchecked(j as usize) });
-                        let density_contribution = kernel.evaluate(r_sq, r_sq.sqrt()) * mass;
+                        let density_contribution = kernel.evaluate(r_sq, r_sq.sqrt()) * unsafe { *masses.get_unchecked(j as usize) };
                         *density += density_contribution;
                     },
                 );
@@ -232,15 +983,137 @@ impl FluidParticleWorld {
                     #[inline(always)]
                     |j| {
                         let r_sq = ri.distance2(unsafe { *boundary_positions.get_unchecked(j as usize) });
-                        let density_contribution = kernel.evaluate(r_sq, r_sq.sqrt()) * mass;
+                        let density_contribution = kernel.evaluate(r_sq, r_sq.sqrt()) * boundary_mass;
                         *density += density_contribution;
                     },
                 );
 
                 // Pressure clamping to work around particle deficiency problem. Good explanation here:
                 // https://github.com/InteractiveComputerGraphics/SPlisHSPlasH/issues/36#issuecomment-495883932
-                *density = density.max(fluid_density);
+                if clamp_to_rest_density {
+                    *density = density.max(fluid_density);
+                }
+            });
+    }
+
+    // Classifies every fluid particle as free surface or interior, using the neighborhood datastructure as of the last simulation step.
+    // A particle is at the surface if it has only few neighbors or if the center of mass of its neighborhood is noticeably offset from it,
+    // i.e. its neighborhood is only populated on one side. Boundary particles count as neighbors, so fluid resting against walls is interior.
+    // Purely geometric and cheap, meant for surface tension, rendering and statistics. Solvers update it every step after the densities.
+    pub fn update_free_surface(&mut self) {
+        microprofile::scope!("FluidParticleWorld", "update_free_surface");
+        // fewer neighbors than this means the particle is isolated, e.g. in a splash
+        const MIN_NEIGHBORS: usize = 3;
+        // offset of the neighborhood's center of mass relative to the smoothing length
+        // ~0.3 for particles at a flat surface of a resting lattice, ~0.1 for those right below
+        const CENTER_OF_MASS_THRESHOLD: Real = 0.2;
+
+        let num_particles = self.particles.positions.len();
+        self.particles.free_surface.resize(num_particles, false);
+        let threshold_sq = (CENTER_OF_MASS_THRESHOLD * self.properties.smoothing_length()).powi(2);
+        let boundary_mass = self.boundary_particle_mass();
+        let neighborhood = &self.particles.neighborhood;
+        let positions = &self.particles.positions;
+        let masses = &self.particles.masses;
+        let boundary_positions = &self.particles.boundary_particles;
+
+        self.particles.free_surface.par_iter_mut().enumerate().for_each(|(i, is_surface)| {
+            let ri = positions[i];
+            let mut num_neighbors = 0;
+            let mut mass_sum = 0.0;
+            let mut weighted_offset = Vector::zero();
+            Particles::foreach_neighbor_particle_internal(neighborhood, i as u32, |j| {
+                num_neighbors += 1;
+                mass_sum += masses[j as usize];
+                weighted_offset += (positions[j as usize] - ri) * masses[j as usize];
+            });
+            Particles::foreach_neighbor_particle_internal_boundary_new(neighborhood, i as u32, |j| {
+                num_neighbors += 1;
+                mass_sum += boundary_mass;
+                weighted_offset += (boundary_positions[j as usize] - ri) * boundary_mass;
             });
+            *is_surface = num_neighbors < MIN_NEIGHBORS || (weighted_offset / mass_sum).magnitude2() > threshold_sq;
+        });
+    }
+
+    // Moves fluid particles apart wherever they are denser than the rest density, e.g. fluid that was placed too close to boundaries or jittered.
+    // Meant to be run once after setting up a scene, so the simulation doesn't start with pressure spikes.
+    // Particles with too low density, e.g. at the free surface, are left alone. Velocities are not changed.
+    //
+    // Each iteration is a Jacobi step of a position based density constraint ρ/ρ0 - 1 = 0,
+    // using the same factors as DFSPH's pressure solve, but correcting positions instead of velocities.
+    // Sorts particles like any neighborhood update, solvers need to drop per particle data from before, see Solver::clear_cached_state.
+    pub fn relax_initial_state(&mut self) -> Result<(), SphError> {
+        microprofile::scope!("FluidParticleWorld", "relax_initial_state");
+        self.check_particle_arrays()?;
+        const EPSILON: Real = 1e-6;
+        let kernel = smoothing_kernel::CubicSpline::new(self.properties.smoothing_length());
+        let fluid_density = self.properties.fluid_density();
+        let boundary_mass = self.boundary_particle_mass();
+        let max_shift = RELAXATION_MAX_SHIFT * self.properties.particle_radius() * 2.0;
+
+        for _ in 0..RELAXATION_ITERATIONS {
+            self.update_neighborhood_datastructure(Vec::new(), Vec::new());
+            self.update_densities(kernel);
+
+            let particles = &self.particles;
+            let factors: Vec<Real> = (0..particles.positions.len())
+                .into_par_iter()
+                .map(|i| {
+                    // densities are clamped to the rest density, so there's no error where particles are too sparse
+                    let density_error = particles.densities[i] / fluid_density - 1.0;
+                    if density_error <= 0.0 {
+                        return 0.0;
+                    }
+                    let ri = particles.positions[i];
+                    let i = i as ParticleIndex;
+                    let mut gradient_sum = Vector::zero();
+                    let mut gradient_square_sum = 0.0;
+                    particles.foreach_neighbor_particle(i, |j| {
+                        let grad_ij =
+                            kernel.gradient_from_positions(ri, particles.positions[j as usize]) * particles.masses[j as usize] / fluid_density;
+                        gradient_sum += grad_ij;
+                        gradient_square_sum += grad_ij.magnitude2();
+                    });
+                    particles.foreach_neighbor_particle_boundary(i, |j| {
+                        gradient_sum += kernel.gradient_from_positions(ri, particles.boundary_particles[j as usize]) * boundary_mass / fluid_density;
+                    });
+                    density_error / (gradient_sum.magnitude2() + gradient_square_sum + EPSILON)
+                })
+                .collect();
+
+            let shifts: Vec<Vector> = (0..particles.positions.len())
+                .into_par_iter()
+                .map(|i| {
+                    let ri = particles.positions[i];
+                    let ki = factors[i];
+                    let i = i as ParticleIndex;
+                    let mut shift = Vector::zero();
+                    particles.foreach_neighbor_particle(i, |j| {
+                        let grad_ij = kernel.gradient_from_positions(ri, particles.positions[j as usize]);
+                        shift -= (ki + factors[j as usize]) * particles.masses[j as usize] / fluid_density * grad_ij;
+                    });
+                    particles.foreach_neighbor_particle_boundary(i, |j| {
+                        shift -= ki * boundary_mass / fluid_density * kernel.gradient_from_positions(ri, particles.boundary_particles[j as usize]);
+                    });
+                    // Jacobi steps overshoot where many particles move at once
+                    let shift = shift * 0.5;
+                    let length = shift.magnitude();
+                    if length > max_shift {
+                        shift * (max_shift / length)
+                    } else {
+                        shift
+                    }
+                })
+                .collect();
+
+            for (position, shift) in self.particles.positions.iter_mut().zip(shifts.iter()) {
+                *position += *shift;
+            }
+        }
+        self.update_neighborhood_datastructure(Vec::new(), Vec::new());
+        self.update_densities(kernel);
+        Ok(())
     }
 
     // sorts particle attributes internally!
@@ -252,24 +1125,601 @@ impl FluidParticleWorld {
     ) {
         microprofile::scope!("FluidParticleWorld", "update_neighborhood_datastructure");
 
+        if self.boundary_changed {
+            self.resize_boundary_attributes();
+            let particles = &mut self.particles;
+            particles.neighborhood.update_boundary(
+                &mut self.scratch_buffers,
+                &mut particles.boundary_particles,
+                &mut [
+                    &mut particles.boundary_velocities,
+                    &mut particles.boundary_forces,
+                    &mut particles.boundary_rest_positions,
+                ],
+                &mut [&mut particles.boundary_moving_ids, &mut particles.boundary_object_ids],
+            );
+            self.boundary_changed = false;
+        }
+
+        let particles = &mut self.particles;
+        particles.tags.resize(particles.positions.len(), 0);
+        particles.attributes.resize(particles.positions.len());
+        let (registered_real, registered_vector, mut particle_attributes_uint) = particles.attributes.arrays_mut();
+
         let mut additional_particle_attributes_vector = additional_particle_attributes_vector;
-        additional_particle_attributes_vector.push(&mut self.particles.velocities);
+        additional_particle_attributes_vector.push(&mut particles.velocities);
+        additional_particle_attributes_vector.extend(registered_vector);
 
         let mut additional_particle_attributes_real = additional_particle_attributes_real;
+        additional_particle_attributes_real.push(&mut particles.masses);
+        additional_particle_attributes_real.extend(registered_real);
 
-        if self.boundary_changed {
-            self.particles
-                .neighborhood
-                .update_boundary(&mut self.scratch_buffers, &mut self.particles.boundary_particles);
-            self.boundary_changed = false;
-        }
+        particle_attributes_uint.push(&mut particles.tags);
 
-        self.particles.neighborhood.update_particle_neighbors(
+        particles.neighborhood.update_particle_neighbors(
             &mut self.scratch_buffers,
-            &mut self.particles.positions,
+            &mut particles.positions,
             &mut additional_particle_attributes_vector,
             &mut additional_particle_attributes_real,
-            &self.particles.boundary_particles,
+            &mut particle_attributes_uint,
+            &particles.boundary_particles,
         );
     }
 }
+
+// Replaces all inner corners of a line strip by circular arcs with the given radius, sampled at about the given spacing.
+// Radii shrink where a fillet would take up more than half of a segment.
+fn fillet_polyline(vertices: &[Point], radius: Real, spacing: Real) -> Vec<Point> {
+    if radius <= 0.0 || vertices.len() < 3 {
+        return vertices.to_vec();
+    }
+    let mut filleted = vec![vertices[0]];
+    for i in 1..vertices.len() - 1 {
+        let (previous, vertex, next) = (vertices[i - 1], vertices[i], vertices[i + 1]);
+        let incoming = (vertex - previous).normalize();
+        let outgoing = (next - vertex).normalize();
+        let turn_angle = incoming.perp_dot(outgoing).atan2(incoming.dot(outgoing));
+        // distance from the corner to where the arc touches the segments
+        let tangent_length = radius * (turn_angle.abs() * 0.5).tan();
+        let max_tangent_length = previous.distance(vertex).min(vertex.distance(next)) * 0.5;
+        if tangent_length <= 0.0 {
+            filleted.push(vertex);
+            continue;
+        }
+        let scale = (max_tangent_length / tangent_length).min(1.0);
+        let (radius, tangent_length) = (radius * scale, tangent_length * scale);
+
+        let arc_start = vertex - incoming * tangent_length;
+        let center = arc_start + Vector::new(-incoming.y, incoming.x) * radius * turn_angle.signum();
+        let start_angle = (arc_start - center).y.atan2((arc_start - center).x);
+        let num_steps = std::cmp::max(1, (radius * turn_angle.abs() / spacing).ceil() as usize);
+        filleted.extend((0..=num_steps).map(|step| {
+            let angle = start_angle + turn_angle * step as Real / num_steps as Real;
+            center + Vector::new(angle.cos(), angle.sin()) * radius
+        }));
+    }
+    filleted.push(vertices[vertices.len() - 1]);
+    filleted
+}
+
+// Line strip parallel to the given one, positive distances to the left. Corners are mitered.
+fn offset_polyline(vertices: &[Point], distance: Real) -> Vec<Point> {
+    let left = |from: Point, to: Point| {
+        let direction = (to - from).normalize();
+        Vector::new(-direction.y, direction.x)
+    };
+    (0..vertices.len())
+        .map(|i| {
+            let offset = if i == 0 {
+                left(vertices[0], vertices[1])
+            } else if i == vertices.len() - 1 {
+                left(vertices[i - 1], vertices[i])
+            } else {
+                let (incoming, outgoing) = (left(vertices[i - 1], vertices[i]), left(vertices[i], vertices[i + 1]));
+                // reaching the offset lines of both segments, unless they fold back onto each other
+                let denominator = 1.0 + incoming.dot(outgoing);
+                if denominator > 1.0e-3 {
+                    (incoming + outgoing) / denominator
+                } else {
+                    incoming
+                }
+            };
+            vertices[i] + offset * distance
+        })
+        .collect()
+}
+
+// Removes all elements of a per particle attribute whose entry in `keep` is false.
+// Elements beyond the end of `keep` are kept.
+pub(super) fn retain_particle_attribute<T>(attribute: &mut Vec<T>, keep: &[bool]) {
+    let mut i = 0;
+    attribute.retain(|_| {
+        i += 1;
+        keep.get(i - 1).cloned().unwrap_or(true)
+    });
+}
+
+// Reserves capacity for a total number of elements, as opposed to Vec::reserve which counts additional elements.
+fn reserve_total<T>(attribute: &mut Vec<T>, total: usize) {
+    attribute.reserve(total.saturating_sub(attribute.len()));
+}
+
+fn heap_size<T>(attribute: &Vec<T>) -> usize {
+    attribute.capacity() * std::mem::size_of::<T>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_fluid_circle_skips_occupied_spots() {
+        let mut world = FluidParticleWorld::new(2.0, NumberDensity(100.0), Density(1.0));
+        let num_added = world.add_fluid_circle(Point::new(0.0, 0.0), 0.5);
+        assert_gt!(num_added, 0);
+        assert_eq!(world.particles.positions.len(), num_added);
+        assert_eq!(world.particles.velocities.len(), num_added);
+        assert_eq!(world.particles.densities.len(), num_added);
+
+        // same spot again
+        assert_eq!(world.add_fluid_circle(Point::new(0.0, 0.0), 0.5), 0);
+        // overlapping, only the part that isn't covered yet
+        let num_added_overlapping = world.add_fluid_circle(Point::new(0.5, 0.0), 0.5);
+        assert_gt!(num_added_overlapping, 0);
+        assert_lt!(num_added_overlapping, num_added);
+    }
+
+    #[test]
+    fn restored_rng_reproduces_jitter() {
+        let mut world = FluidParticleWorld::new(2.0, NumberDensity(100.0), Density(1.0));
+        world.add_fluid_rect(&Rect::new(0.0, 0.0, 1.0, 1.0), 0.5);
+        let checkpoint = world.rng.state();
+        world.add_fluid_rect(&Rect::new(0.0, 0.0, 1.0, 1.0), 0.5);
+        let positions = world.particles.positions.clone();
+
+        world.remove_all_fluid_particles();
+        world.add_fluid_rect(&Rect::new(0.0, 0.0, 1.0, 1.0), 0.5);
+        // continuing the stream, the jitter differs from the first rect
+        assert_ne!(world.particles.positions[..], positions[..positions.len() / 2]);
+
+        world.remove_all_fluid_particles();
+        world.add_fluid_rect(&Rect::new(0.0, 0.0, 1.0, 1.0), 0.0);
+        world.rng = SimulationRng::from_state(checkpoint);
+        world.add_fluid_rect(&Rect::new(0.0, 0.0, 1.0, 1.0), 0.5);
+        assert_eq!(world.particles.positions[positions.len() / 2..], positions[positions.len() / 2..]);
+    }
+
+    #[test]
+    fn free_surface_of_resting_block() {
+        let mut world = FluidParticleWorld::new(2.0, NumberDensity(10000.0), Density(100.0));
+        world.add_fluid_rect(&Rect::new(0.0, 0.0, 0.5, 0.5), 0.0);
+        // floor right below the lowest row of particles
+        world.add_boundary_thick_line(Point::new(-0.1, 0.0), Point::new(0.6, 0.0), 2);
+        world.update_neighborhood_datastructure(Vec::new(), Vec::new());
+        world.update_free_surface();
+        assert_eq!(world.particles.free_surface.len(), world.particles.positions.len());
+
+        let smoothing_length = world.properties.smoothing_length();
+        let (min, max) = (0.0, 0.49);
+        for (p, is_surface) in world.particles.positions.iter().zip(world.particles.free_surface.iter()) {
+            let on_outer_row = p.x < min + 0.005 || p.x > max - 0.005 || p.y > max - 0.005;
+            let away_from_sides_and_top = p.x > min + smoothing_length && p.x < max - smoothing_length && p.y < max - smoothing_length;
+            if on_outer_row {
+                assert!(*is_surface, "{:?} should be at the surface", p);
+            } else if away_from_sides_and_top {
+                // including the bottom row, which is supported by the floor
+                assert!(!*is_surface, "{:?} should be interior", p);
+            }
+        }
+
+        // a lone particle has no neighbors at all
+        world.add_fluid_particle(Point::new(2.0, 2.0), Vector::zero());
+        world.update_neighborhood_datastructure(Vec::new(), Vec::new());
+        world.update_free_surface();
+        let lone_particle = world.particles.positions.iter().position(|p| p.x > 1.0).unwrap();
+        assert!(world.particles.free_surface[lone_particle]);
+    }
+
+    #[test]
+    fn densities_use_per_particle_masses() {
+        let mut world = FluidParticleWorld::new(2.0, NumberDensity(10000.0), Density(100.0));
+        world.add_fluid_rect(&Rect::new(0.0, 0.0, 0.5, 0.5), 0.0);
+        let center = Point::new(0.25, 0.25);
+        let mass = world.properties.particle_mass();
+        // heavy particles in the center, marked via their mass
+        let num_particles = world.particles.positions.len();
+        for i in 0..num_particles {
+            if world.particles.positions[i].distance(center) < 0.1 {
+                world.particles.masses[i] = mass * 2.0;
+            }
+        }
+
+        // neighborhood update reorders particles, masses need to stay with their particle
+        world.update_neighborhood_datastructure(Vec::new(), Vec::new());
+        assert_eq!(world.particles.masses.len(), num_particles);
+        for (p, m) in world.particles.positions.iter().zip(world.particles.masses.iter()) {
+            let expected_mass = if p.distance(center) < 0.1 { mass * 2.0 } else { mass };
+            assert_eq!(*m, expected_mass);
+        }
+
+        world.update_densities(smoothing_kernel::CubicSpline::new(world.properties.smoothing_length()));
+        let closest_to_center = (0..num_particles)
+            .min_by(|&a, &b| {
+                let distance_a = world.particles.positions[a].distance2(center);
+                let distance_b = world.particles.positions[b].distance2(center);
+                distance_a.partial_cmp(&distance_b).unwrap()
+            })
+            .unwrap();
+        let fluid_density = world.properties.fluid_density();
+        assert_lt!((world.particles.densities[closest_to_center] / fluid_density - 2.0).abs(), 0.05);
+    }
+
+    #[test]
+    fn tags_stay_with_their_particle() {
+        let mut world = FluidParticleWorld::new(2.0, NumberDensity(10000.0), Density(100.0));
+        world.add_fluid_rect(&Rect::new(0.0, 0.0, 0.5, 0.5), 0.0);
+        world.update_neighborhood_datastructure(Vec::new(), Vec::new());
+        let num_particles = world.particles.positions.len();
+        assert!(world.particles.tags.iter().all(|tag| *tag == 0));
+        for (p, tag) in world.particles.positions.iter().zip(world.particles.tags.iter_mut()) {
+            if p.x < 0.25 {
+                *tag = 3;
+            }
+        }
+
+        // particle added after the update, without a tag entry yet
+        world.add_fluid_particle(Point::new(1.0, 1.0), Vector::zero());
+        world.update_neighborhood_datastructure(Vec::new(), Vec::new());
+        assert_eq!(world.particles.tags.len(), num_particles + 1);
+        for (p, tag) in world.particles.positions.iter().zip(world.particles.tags.iter()) {
+            let expected_tag = if p.x < 0.25 { 3 } else { 0 };
+            assert_eq!(*tag, expected_tag);
+        }
+
+        let keep: Vec<bool> = world.particles.tags.iter().map(|tag| *tag == 0).collect();
+        world.retain_fluid_particles(&keep);
+        assert!(world.particles.tags.iter().all(|tag| *tag == 0));
+        assert_eq!(world.particles.tags.len(), world.particles.positions.len());
+    }
+
+    #[test]
+    fn boundary_object_forces_sum_per_object() {
+        let mut world = FluidParticleWorld::new(2.0, NumberDensity(10000.0), Density(100.0));
+        world.add_boundary_line(Point::new(0.0, 0.0), Point::new(1.0, 0.0));
+        world.add_boundary_circle(Point::new(0.5, 0.5), 0.1);
+        world.update_neighborhood_datastructure(Vec::new(), Vec::new());
+        assert!(world
+            .boundary_object_forces()
+            .iter()
+            .all(|f| f.force == Vector::zero() && f.torque == 0.0));
+
+        // unit force downwards on the line's particles and sideways on the circle's, after reordering
+        let particles = &mut world.particles;
+        let mut num_line_particles = 0;
+        for (p, f) in particles.boundary_particles.iter().zip(particles.boundary_forces.iter_mut()) {
+            if p.y < 0.01 {
+                *f = Vector::new(0.0, -1.0);
+                num_line_particles += 1;
+            } else {
+                *f = Vector::new(1.0, 0.0);
+            }
+        }
+        let num_circle_particles = particles.boundary_particles.len() - num_line_particles;
+
+        let forces = world.boundary_object_forces();
+        assert_eq!(forces.len(), 2);
+        assert_eq!(forces[0].force, Vector::new(0.0, -(num_line_particles as Real)));
+        assert_lt!(forces[0].center.distance(Point::new(0.5, 0.0)), 0.01);
+        assert_lt!(forces[0].torque.abs(), 1.0e-3);
+        let expected_torque = -(num_line_particles as Real) * forces[0].center.x;
+        assert_lt!((forces[0].torque_around(Point::origin()) - expected_torque).abs(), 1.0e-3);
+        assert_eq!(forces[1].force, Vector::new(num_circle_particles as Real, 0.0));
+        assert_lt!(forces[1].center.distance(Point::new(0.5, 0.5)), 1.0e-3);
+        assert_lt!(forces[1].torque.abs(), 1.0e-3);
+    }
+
+    #[test]
+    fn num_neighbors_without_boundary() {
+        let mut world = FluidParticleWorld::new(2.0, NumberDensity(100.0), Density(1.0));
+        world.add_fluid_circle(Point::new(0.0, 0.0), 0.5);
+        // not part of the neighborhood datastructure yet
+        assert_eq!(world.particles.num_neighbors(0), 0);
+
+        world.update_neighborhood_datastructure(Vec::new(), Vec::new());
+        let num_particles = world.particles.positions.len();
+        assert!((0..num_particles).all(|i| world.particles.num_neighbors(i as ParticleIndex) > 0));
+        assert!((0..num_particles).all(|i| world.particles.num_neighbors(i as ParticleIndex) < num_particles as u32));
+    }
+
+    #[test]
+    fn boundary_geometry_is_recorded() {
+        let mut world = FluidParticleWorld::new(2.0, NumberDensity(100.0), Density(1.0));
+        world.add_boundary_line(Point::new(0.0, 0.0), Point::new(1.0, 0.0));
+        world.add_boundary_thick_line(Point::new(0.0, 0.0), Point::new(0.0, 1.0), 3);
+        world.add_boundary_polygon(&[Point::new(0.0, 0.0), Point::new(1.0, 0.0), Point::new(1.0, 1.0)]);
+        let num_particles_before_circle = world.particles.boundary_particles.len();
+        world.add_boundary_circle(Point::new(0.5, 0.5), 0.2);
+        assert_eq!(world.boundary_geometry().len(), 4);
+        assert_eq!(
+            world.boundary_geometry()[0],
+            BoundaryGeometry::Line {
+                start: Point::new(0.0, 0.0),
+                end: Point::new(1.0, 0.0),
+                width: 0.1
+            }
+        );
+        if let BoundaryGeometry::Line { width, .. } = world.boundary_geometry()[1] {
+            assert_lt!((width - 0.3).abs(), 1.0e-5);
+        } else {
+            panic!("expected thick line to be recorded as line");
+        }
+
+        // all circle particles are on the circle
+        for p in world.particles.boundary_particles[num_particles_before_circle..].iter() {
+            assert_lt!((p.distance(Point::new(0.5, 0.5)) - 0.2).abs(), 1.0e-5);
+        }
+
+        // the fluid stays as it is
+        world.add_fluid_particle(Point::new(0.5, 0.5), Vector::new(1.0, 0.0));
+        world.remove_all_boundary_particles();
+        assert!(world.boundary_geometry().is_empty());
+        assert!(world.particles.boundary_particles.is_empty());
+        assert_eq!(world.particles.velocities, vec![Vector::new(1.0, 0.0)]);
+    }
+
+    #[test]
+    fn polyline_has_neither_gaps_nor_overlaps_at_corners() {
+        let min_max_neighbor_distance = |boundary_particles: &[Point]| {
+            let mut min_distance = Real::MAX;
+            let mut max_distance: Real = 0.0;
+            for (i, a) in boundary_particles.iter().enumerate() {
+                let closest = boundary_particles
+                    .iter()
+                    .enumerate()
+                    .filter(|(j, _)| *j != i)
+                    .map(|(_, b)| a.distance(*b))
+                    .fold(Real::MAX, Real::min);
+                min_distance = min_distance.min(closest);
+                max_distance = max_distance.max(closest);
+            }
+            (min_distance, max_distance)
+        };
+        let corners = [Point::new(0.0, 1.0), Point::new(0.0, 0.0), Point::new(1.05, 0.0), Point::new(0.5, 0.6)];
+
+        // spacing of 10cm, rows outside of the corner at (0, 0)
+        let mut world = FluidParticleWorld::new(2.0, NumberDensity(100.0), Density(1.0));
+        world.add_boundary_polyline(&corners, 2, 0.0);
+        let boundary_particles = &world.particles.boundary_particles;
+        assert_eq!(
+            boundary_particles.iter().filter(|p| p.distance(Point::new(-0.1, -0.1)) < 1.0e-4).count(),
+            1
+        );
+        assert_eq!(
+            boundary_particles.iter().filter(|p| p.distance(Point::new(-0.2, -0.2)) < 1.0e-4).count(),
+            1
+        );
+        let (min_distance, max_distance) = min_max_neighbor_distance(boundary_particles);
+        assert_gt!(min_distance, 0.07);
+        assert_lt!(max_distance, 0.13);
+        if let BoundaryGeometry::Polyline { vertices, width } = &world.boundary_geometry()[0] {
+            assert_eq!(vertices.len(), corners.len());
+            assert_lt!(vertices[1].distance(Point::new(-0.15, -0.15)), 1.0e-4);
+            assert_lt!((width - 0.2).abs(), 1.0e-5);
+        } else {
+            panic!("expected polyline geometry");
+        }
+
+        // rows of the fillet at (0, 0) are arcs around its center
+        let mut world = FluidParticleWorld::new(2.0, NumberDensity(100.0), Density(1.0));
+        world.add_boundary_polyline(&corners, 2, 0.3);
+        let center = Point::new(0.3, 0.3);
+        for p in world.particles.boundary_particles.iter().filter(|p| p.x < 0.3 && p.y < 0.3) {
+            let distance = p.distance(center);
+            assert!((distance - 0.4).abs() < 0.01 || (distance - 0.5).abs() < 0.01, "{:?} is not on an arc", p);
+        }
+        let (min_distance, max_distance) = min_max_neighbor_distance(&world.particles.boundary_particles);
+        assert_gt!(min_distance, 0.07);
+        assert_lt!(max_distance, 0.13);
+    }
+
+    #[test]
+    fn denser_boundary_keeps_wall_densities() {
+        let bottom_row_density = |boundary_sampling_factor: Real| {
+            let mut world = FluidParticleWorld::new(2.0, NumberDensity(10000.0), Density(100.0));
+            world.set_boundary_sampling_factor(boundary_sampling_factor).unwrap();
+            world.add_fluid_rect(&Rect::new(0.0, 0.0, 0.5, 0.2), 0.0);
+            world.add_boundary_thick_line(Point::new(-0.1, 0.0), Point::new(0.6, 0.0), 2);
+            // two rows along a line of 70cm plus elongation by the thickness
+            let expected_num_boundary_particles = 2.0 * 72.0 * boundary_sampling_factor;
+            assert_lt!(
+                (world.particles.boundary_particles.len() as Real / expected_num_boundary_particles - 1.0).abs(),
+                0.02
+            );
+
+            world.update_neighborhood_datastructure(Vec::new(), Vec::new());
+            world.update_densities_unclamped(smoothing_kernel::CubicSpline::new(world.properties.smoothing_length()));
+            let particles = &world.particles;
+            let bottom_row: Vec<Real> = (0..particles.positions.len())
+                .filter(|i| particles.positions[*i].y < 0.005 && (0.1..0.4).contains(&particles.positions[*i].x))
+                .map(|i| particles.densities[i])
+                .collect();
+            bottom_row.iter().sum::<Real>() / bottom_row.len() as Real
+        };
+        let density = bottom_row_density(1.0);
+        assert_lt!((density / 100.0 - 1.0).abs(), 0.02);
+        assert_lt!((bottom_row_density(2.0) / density - 1.0).abs(), 0.01);
+        assert_lt!((bottom_row_density(3.0) / density - 1.0).abs(), 0.01);
+    }
+
+    #[test]
+    fn initial_velocity_within_region() {
+        let mut world = FluidParticleWorld::new(2.0, NumberDensity(10000.0), Density(1.0));
+        world.add_fluid_rect(&Rect::new(0.0, 0.0, 1.0, 0.5), 0.0);
+        // shear layer in the left half, rigid rotation around (0.75, 0.25) in the right half
+        let num_left = world.set_initial_velocity(&Rect::new(0.0, 0.0, 0.5, 0.5), |p| Vector::new(if p.y < 0.25 { -1.0 } else { 1.0 }, 0.0));
+        let center = Point::new(0.75, 0.25);
+        let num_right = world.set_initial_velocity(&Rect::new(0.5, 0.0, 0.5, 0.5), |p| {
+            let r = p - center;
+            Vector::new(-r.y, r.x) * 2.0
+        });
+        assert_eq!(num_left + num_right, world.particles.positions.len());
+        assert_eq!(num_left, num_right);
+
+        for (p, v) in world.particles.positions.iter().zip(world.particles.velocities.iter()) {
+            if p.x < 0.5 {
+                assert_eq!(v.magnitude(), 1.0);
+                assert_eq!(v.x > 0.0, p.y >= 0.25);
+            } else {
+                assert_lt!((v.magnitude() - p.distance(center) * 2.0).abs(), 1.0e-5);
+            }
+        }
+
+        // nothing outside of the region changes
+        assert_eq!(world.set_initial_velocity(&Rect::new(2.0, 2.0, 1.0, 1.0), |_| Vector::new(5.0, 5.0)), 0);
+        assert!(world.particles.velocities.iter().all(|v| v.x != 5.0));
+    }
+
+    #[test]
+    fn blue_noise_matches_rest_density() {
+        let mut world = FluidParticleWorld::new(2.0, NumberDensity(10000.0), Density(100.0));
+        let center = Point::new(0.5, 0.5);
+        let num_particles = world.add_fluid_blue_noise(&Rect::new(0.0, 0.0, 1.0, 1.0), |p| p.distance(center) < 0.5);
+        assert_eq!(num_particles, world.particles.positions.len());
+        let expected_num_particles = std::f32::consts::PI * 0.25 * 10000.0;
+        assert_lt!((num_particles as Real / expected_num_particles - 1.0).abs(), 0.05);
+        // a bit lighter than particles on a lattice
+        let mass = world.particles.masses[0];
+        assert_lt!(mass, world.properties.particle_mass());
+        assert_gt!(mass, world.properties.particle_mass() * 0.85);
+
+        // away from the surface, SPH density is close to the rest density
+        // (median, since update_densities clamps densities below the rest density)
+        world.update_neighborhood_datastructure(Vec::new(), Vec::new());
+        world.update_densities(smoothing_kernel::CubicSpline::new(world.properties.smoothing_length()));
+        let mut interior_densities: Vec<Real> = world
+            .particles
+            .positions
+            .iter()
+            .zip(world.particles.densities.iter())
+            .filter(|(p, _)| p.distance(center) < 0.3)
+            .map(|(_, density)| *density)
+            .collect();
+        interior_densities.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let median_density = interior_densities[interior_densities.len() / 2];
+        assert_lt!((median_density / world.properties.fluid_density() - 1.0).abs(), 0.01);
+        let num_denser = interior_densities
+            .iter()
+            .filter(|&&d| d > world.properties.fluid_density() * 1.001)
+            .count();
+        assert_lt!((num_denser as Real / interior_densities.len() as Real - 0.5).abs(), 0.1);
+    }
+
+    #[test]
+    fn relaxation_removes_overlaps() {
+        let mut world = FluidParticleWorld::new(2.0, NumberDensity(10000.0), Density(100.0));
+        let spacing = world.properties.particle_radius() * 2.0;
+        world.add_fluid_rect(&Rect::new(spacing, spacing, 0.5, 0.3), 0.8);
+        // fluid right up to the floor
+        world.add_boundary_thick_line(Point::new(0.0, spacing * 0.5), Point::new(1.0, spacing * 0.5), 2);
+        let kernel = smoothing_kernel::CubicSpline::new(world.properties.smoothing_length());
+        let max_density_error = |world: &FluidParticleWorld| {
+            world
+                .particles
+                .densities
+                .iter()
+                .fold(0.0, |max, density| Real::max(max, density / world.properties.fluid_density() - 1.0))
+        };
+        world.update_neighborhood_datastructure(Vec::new(), Vec::new());
+        world.update_densities(kernel);
+        let error_before = max_density_error(&world);
+
+        world.relax_initial_state().unwrap();
+        let error_after = max_density_error(&world);
+        assert_lt!(error_after, error_before * 0.5);
+        assert_lt!(error_after, 0.05);
+        // fluid stays where it was, particles only moved up a bit from the floor
+        for (p, v) in world.particles.positions.iter().zip(world.particles.velocities.iter()) {
+            assert!((0.0..0.5 + spacing * 3.0).contains(&p.x));
+            assert!((spacing * 0.5..0.3 + spacing * 3.0).contains(&p.y));
+            assert_eq!(*v, Vector::zero());
+        }
+    }
+
+    #[test]
+    fn interpolate_uniform_velocity() {
+        let mut world = FluidParticleWorld::new(2.0, NumberDensity(10000.0), Density(1.0));
+        world.add_fluid_rect(&Rect::new(0.0, 0.0, 0.5, 0.5), 0.1);
+        let velocity = Vector::new(1.0, -2.0);
+        for v in world.particles.velocities.iter_mut() {
+            *v = velocity;
+        }
+        world.update_neighborhood_datastructure(Vec::new(), Vec::new());
+
+        let interpolated_center = world.interpolate_velocity(Point::new(0.25, 0.25)).unwrap();
+        assert_lt!((interpolated_center - velocity).magnitude(), 1.0e-4);
+        let interpolated_edge = world.interpolate_velocity(Point::new(0.0, 0.25)).unwrap();
+        assert_lt!((interpolated_edge - velocity).magnitude(), 1.0e-4);
+        assert_eq!(world.interpolate_velocity(Point::new(1.0, 1.0)), None);
+    }
+
+    #[test]
+    fn reserve_avoids_reallocation() {
+        let mut world = FluidParticleWorld::new(2.0, NumberDensity(10000.0), Density(1.0));
+        let empty = world.memory_usage();
+        world.reserve(1000, 200);
+        let reserved = world.memory_usage();
+        assert_gt!(reserved.fluid_particles, empty.fluid_particles);
+        assert_gt!(reserved.boundary_particles, empty.boundary_particles);
+        assert_gt!(reserved.neighbor_lists, empty.neighbor_lists);
+        assert_gt!(reserved.grid_cells, empty.grid_cells);
+        assert_eq!(
+            reserved.total(),
+            reserved.fluid_particles + reserved.boundary_particles + reserved.neighbor_lists + reserved.grid_cells
+        );
+
+        let positions = world.particles.positions.as_ptr();
+        world.add_fluid_rect(&Rect::new(0.0, 0.0, 0.3, 0.3), 0.0); // 30x30 particles
+        world.add_boundary_line(Point::new(0.0, -0.01), Point::new(1.5, -0.01)); // 150 particles
+        world.update_neighborhood_datastructure(Vec::new(), Vec::new());
+        assert_eq!(world.particles.positions.len(), 900);
+        assert_eq!(world.particles.boundary_particles.len(), 150);
+        // sorting swaps the attributes with scratch buffers, but reserved capacity stays with them
+        assert_ne!(world.particles.positions.as_ptr(), positions);
+        assert_eq!(world.memory_usage(), reserved);
+    }
+
+    #[test]
+    fn registered_attributes_follow_their_particles() {
+        let mut world = FluidParticleWorld::new(2.0, NumberDensity(10000.0), Density(1.0));
+        world.add_fluid_rect(&Rect::new(0.0, 0.0, 0.2, 0.2), 0.0);
+        let temperature = world.particles.register_attribute("temperature", 20.0 as Real);
+        let color = world.particles.register_attribute("color", Vector::zero());
+        // particles added later get the default
+        world.add_fluid_rect(&Rect::new(0.3, 0.0, 0.2, 0.2), 0.0);
+        let num_particles = world.particles.positions.len();
+        assert_eq!(world.particles.attribute(temperature).len(), num_particles);
+        for i in 0..num_particles {
+            let p = world.particles.positions[i];
+            world.particles.attribute_mut(color)[i] = p.to_vec();
+            if p.x > 0.25 {
+                world.particles.attribute_mut(temperature)[i] = 80.0;
+            }
+        }
+
+        // neighborhood updates reorder particles
+        let positions_before_update = world.particles.positions.clone();
+        world.update_neighborhood_datastructure(Vec::new(), Vec::new());
+        let particles = &world.particles;
+        assert_ne!(particles.positions, positions_before_update);
+        for (i, p) in particles.positions.iter().enumerate() {
+            assert_eq!(particles.attribute(color)[i], p.to_vec());
+            assert_eq!(particles.attribute(temperature)[i], if p.x > 0.25 { 80.0 } else { 20.0 });
+        }
+
+        let keep: Vec<bool> = world.particles.positions.iter().map(|p| p.x < 0.25).collect();
+        world.retain_fluid_particles(&keep);
+        assert!(world.particles.attribute(temperature).iter().all(|t| *t == 20.0));
+        assert_eq!(world.particles.attribute(color).len(), world.particles.positions.len());
+        assert_eq!(world.particles.find_attribute::<Real>("temperature"), Some(temperature));
+    }
+}
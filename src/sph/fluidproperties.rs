@@ -0,0 +1,171 @@
+use super::fluidparticleworld::FluidParticleWorld;
+use crate::units::*;
+
+// Smoothing length relative to the particle spacing, as used by all scenes.
+const DEFAULT_SMOOTHING_FACTOR: Real = 2.0;
+
+// Derives consistent simulation parameters from real-world (3D) fluid properties.
+//
+// The simulation is 2D, it represents a slab of fluid with a given thickness. Masses are those of the entire slab's depth,
+// so the 2D density in kg/m² is the 3D density times the slab thickness. Kinematic viscosity is the same in 2D and 3D.
+//
+// Instead of the fluid's real (very high) speed of sound, weakly compressible solvers use one that is just high enough to keep density
+// variations below a target for the expected flow speeds, see WCSPHSolver::with_target_compressibility.
+//
+//   let properties = FluidProperties::water().with_particle_spacing(Length(0.01)).with_fall_height(Length(0.5));
+//   let fluid_world = properties.create_world();
+//   let solver = WCSPHSolver::new(viscosity_model, &fluid_world.properties)
+//       .with_target_compressibility(properties.max_density_variation, properties.max_flow_speed);
+//   let dt = properties.recommended_wcsph_timestep();
+#[derive(Clone, Debug, PartialEq)]
+pub struct FluidProperties {
+    pub density: Real,                   // in kg/m³ (3D)
+    pub kinematic_viscosity: Real,       // in m²/s (ν, nu)
+    pub slab_thickness: Length,          // depth of the 3D fluid the 2D simulation represents
+    pub particle_density: NumberDensity, // #particles/m² for resting fluid
+    pub smoothing_factor: Real,          // smoothing length relative to the particle spacing
+    pub gravity: Acceleration,
+    pub max_flow_speed: Velocity,    // expected maximum speed of the fluid
+    pub max_density_variation: Real, // tolerated compression of weakly compressible solvers, denoted as η
+}
+
+impl FluidProperties {
+    // Water at 20 °C in a slab of 1 m thickness, at 1 cm particle spacing and flow speeds up to 1 m/s.
+    pub fn water() -> FluidProperties {
+        FluidProperties {
+            density: 998.2,
+            kinematic_viscosity: 1.004e-6,
+            slab_thickness: Length(1.0),
+            particle_density: NumberDensity(10000.0),
+            smoothing_factor: DEFAULT_SMOOTHING_FACTOR,
+            gravity: Acceleration(9.81),
+            max_flow_speed: Velocity(1.0),
+            max_density_variation: 0.01,
+        }
+    }
+
+    pub fn with_slab_thickness(mut self, slab_thickness: Length) -> FluidProperties {
+        self.slab_thickness = slab_thickness;
+        self
+    }
+
+    pub fn with_particle_density(mut self, particle_density: NumberDensity) -> FluidProperties {
+        self.particle_density = particle_density;
+        self
+    }
+
+    // Distance of neighboring particles on a square lattice.
+    pub fn with_particle_spacing(mut self, spacing: Length) -> FluidProperties {
+        self.particle_density = NumberDensity(1.0 / (spacing.0 * spacing.0));
+        self
+    }
+
+    pub fn with_max_flow_speed(mut self, max_flow_speed: Velocity) -> FluidProperties {
+        self.max_flow_speed = max_flow_speed;
+        self
+    }
+
+    // Expected maximum flow speed from the height fluid may fall from, v = sqrt(2 g h).
+    pub fn with_fall_height(mut self, fall_height: Length) -> FluidProperties {
+        self.max_flow_speed = Velocity((2.0 * self.gravity.0 * fall_height.0).sqrt());
+        self
+    }
+
+    pub fn with_max_density_variation(mut self, max_density_variation: Real) -> FluidProperties {
+        self.max_density_variation = max_density_variation;
+        self
+    }
+
+    // Density of the simulated 2D fluid.
+    pub fn fluid_density(&self) -> Density {
+        Density(self.density * self.slab_thickness.0)
+    }
+
+    // Dynamic viscosity of the simulated 2D fluid in Pa*s*m, i.e. that of the slab.
+    pub fn dynamic_viscosity(&self) -> Real {
+        self.kinematic_viscosity * self.fluid_density().0
+    }
+
+    pub fn particle_spacing(&self) -> Length {
+        Length(1.0 / self.particle_density.0.sqrt())
+    }
+
+    pub fn smoothing_length(&self) -> Length {
+        self.particle_spacing() * self.smoothing_factor
+    }
+
+    // Speed of sound for weakly compressible solvers, the same WCSPHSolver::with_target_compressibility derives.
+    // Density varies with the square of the Mach number, so this keeps the variation at max_density_variation for the maximum flow speed.
+    pub fn speed_of_sound(&self) -> Velocity {
+        self.max_flow_speed / self.max_density_variation.sqrt()
+    }
+
+    // Largest stable timestep for incompressible solvers like DFSPH, following Monaghan 1992 and Becker & Teschner 2007:
+    // the CFL condition at the maximum flow speed (as in TimeManager), the body force condition and the viscous diffusion condition.
+    pub fn recommended_timestep(&self) -> Time {
+        let spacing = self.particle_spacing().0;
+        let smoothing_length = self.smoothing_length().0;
+        let cfl = 0.4 * spacing / self.max_flow_speed.0;
+        let body_force = 0.25 * (smoothing_length / self.gravity.0).sqrt();
+        let viscous = 0.125 * smoothing_length * smoothing_length / self.kinematic_viscosity;
+        Time(cfl.min(body_force).min(viscous))
+    }
+
+    // Largest stable timestep for weakly compressible solvers, whose CFL condition depends on the speed of sound instead.
+    pub fn recommended_wcsph_timestep(&self) -> Time {
+        let acoustic = Time(0.4 * self.particle_spacing().0 / (self.speed_of_sound() + self.max_flow_speed).0);
+        if acoustic < self.recommended_timestep() {
+            acoustic
+        } else {
+            self.recommended_timestep()
+        }
+    }
+
+    // Empty fluid world with these properties and gravity.
+    pub fn create_world(&self) -> FluidParticleWorld {
+        let mut fluid_world = FluidParticleWorld::new(self.smoothing_factor, self.particle_density, self.fluid_density());
+        fluid_world.set_gravity(Vector::new(0.0, -self.gravity.0));
+        fluid_world
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::prelude::*;
+
+    #[test]
+    fn derived_water_properties() {
+        let properties = FluidProperties::water()
+            .with_slab_thickness(Length(0.1))
+            .with_particle_spacing(Length(0.02))
+            .with_fall_height(Length(0.5));
+        assert_lt!((properties.fluid_density().0 - 99.82).abs(), 1.0e-3);
+        assert_lt!((properties.dynamic_viscosity() - 1.0e-4).abs(), 1.0e-6);
+        assert_lt!((properties.particle_spacing().0 - 0.02).abs(), 1.0e-6);
+        assert_lt!((properties.max_flow_speed.0 - (9.81 as Real).sqrt()).abs(), 1.0e-5);
+        // Mach number of 0.1 for 1% density variation
+        assert_lt!((properties.speed_of_sound().0 - 10.0 * properties.max_flow_speed.0).abs(), 1.0e-4);
+
+        // the speed of sound is much higher than the flow speed, so WCSPH needs far shorter steps
+        let timestep = properties.recommended_timestep();
+        let wcsph_timestep = properties.recommended_wcsph_timestep();
+        assert_lt!((timestep.0 - 0.4 * 0.02 / (9.81 as Real).sqrt()).abs(), 1.0e-6);
+        assert_lt!((wcsph_timestep / timestep - 1.0 / 11.0).abs(), 1.0e-4);
+        // viscosity of water is negligible, but not for a much more viscous fluid
+        let honey = FluidProperties {
+            kinematic_viscosity: 0.1,
+            ..properties.clone()
+        };
+        assert_lt!(honey.recommended_timestep(), timestep);
+
+        let fluid_world = properties.create_world();
+        assert_lt!((fluid_world.properties.fluid_density() - 99.82).abs(), 1.0e-3);
+        assert_lt!((fluid_world.properties.particle_radius() - 0.01).abs(), 1.0e-6);
+        assert_lt!(
+            (fluid_world.properties.smoothing_length() - properties.smoothing_length().0).abs(),
+            1.0e-6
+        );
+        assert_lt!((fluid_world.gravity() - Vector::new(0.0, -9.81)).magnitude(), 1.0e-6);
+    }
+}
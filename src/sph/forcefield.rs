@@ -0,0 +1,160 @@
+use crate::units::*;
+use cgmath::prelude::*;
+
+// Closure computing an accelleration in m/s² from particle position, particle velocity and simulation time.
+pub type ForceFieldFn = dyn Fn(Point, Vector, Real) -> Vector + Send + Sync;
+
+// External force acting on all fluid particles, evaluated by the solvers along with the other non-pressure forces.
+// All fields are expressed as accelleration (force per mass): They model body forces like gravity, which scale with a particle's mass,
+// so the same field value applies to particles of any mass and solvers can add it to the other accellerations as is.
+pub enum ForceField {
+    // Uniform accelleration in m/s² (== N/kg).
+    Gravity(Vector),
+    // Pulls particles towards a center, falling off linearly to zero at `radius`. Negative strength pushes particles away.
+    PointAttractor { center: Point, strength: Real, radius: Real },
+    // Swirls particles counter-clockwise around a center, falling off linearly to zero at `radius`. Negative strength for clockwise.
+    Vortex { center: Point, strength: Real, radius: Real },
+    // Frame accelleration of a container shaken with displacement `amplitude * sin(2π frequency t)`, e.g. for sloshing tanks.
+    // The simulation takes place in the container's frame, so particles feel the opposite of the container's accelleration.
+    Shaking { amplitude: Vector, frequency: Real },
+    // Arbitrary user defined field, can also be used for other time dependent accellerations.
+    Custom(Box<ForceFieldFn>),
+}
+
+impl ForceField {
+    pub fn custom(field: impl Fn(Point, Vector, Real) -> Vector + Send + Sync + 'static) -> ForceField {
+        ForceField::Custom(Box::new(field))
+    }
+
+    // Strength in m/s² at the center, linearly going down to zero at the given radius.
+    fn radial_falloff(strength: Real, radius: Real, distance: Real) -> Real {
+        if distance >= radius {
+            0.0
+        } else {
+            strength * (1.0 - distance / radius)
+        }
+    }
+
+    // Copy of the field, None for custom fields.
+    pub fn try_clone(&self) -> Option<ForceField> {
+        match self {
+            ForceField::Gravity(gravity) => Some(ForceField::Gravity(*gravity)),
+            ForceField::PointAttractor { center, strength, radius } => Some(ForceField::PointAttractor {
+                center: *center,
+                strength: *strength,
+                radius: *radius,
+            }),
+            ForceField::Vortex { center, strength, radius } => Some(ForceField::Vortex {
+                center: *center,
+                strength: *strength,
+                radius: *radius,
+            }),
+            ForceField::Shaking { amplitude, frequency } => Some(ForceField::Shaking {
+                amplitude: *amplitude,
+                frequency: *frequency,
+            }),
+            ForceField::Custom(_) => None,
+        }
+    }
+
+    // Accelleration in m/s² the field applies to a particle.
+    pub fn accelleration(&self, position: Point, velocity: Vector, time: Real) -> Vector {
+        match self {
+            ForceField::Gravity(gravity) => *gravity,
+            ForceField::PointAttractor { center, strength, radius } => {
+                let to_center = center - position;
+                let distance = to_center.magnitude();
+                if distance == 0.0 {
+                    return Vector::zero();
+                }
+                to_center * (Self::radial_falloff(*strength, *radius, distance) / distance)
+            }
+            ForceField::Vortex { center, strength, radius } => {
+                let from_center = position - center;
+                let distance = from_center.magnitude();
+                if distance == 0.0 {
+                    return Vector::zero();
+                }
+                let tangent = Vector::new(-from_center.y, from_center.x);
+                tangent * (Self::radial_falloff(*strength, *radius, distance) / distance)
+            }
+            ForceField::Shaking { amplitude, frequency } => {
+                let angular_frequency = 2.0 * std::f64::consts::PI as Real * frequency;
+                amplitude * (angular_frequency * angular_frequency * (angular_frequency * time).sin())
+            }
+            ForceField::Custom(field) => field(position, velocity, time),
+        }
+    }
+
+    // Combined accelleration in m/s² of several fields.
+    pub fn total_accelleration(fields: &[ForceField], position: Point, velocity: Vector, time: Real) -> Vector {
+        fields.iter().map(|field| field.accelleration(position, velocity, time)).sum()
+    }
+}
+
+impl std::fmt::Debug for ForceField {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ForceField::Gravity(gravity) => write!(f, "Gravity({:?})", gravity),
+            ForceField::PointAttractor { center, strength, radius } => {
+                write!(f, "PointAttractor {{ center: {:?}, strength: {}, radius: {} }}", center, strength, radius)
+            }
+            ForceField::Vortex { center, strength, radius } => {
+                write!(f, "Vortex {{ center: {:?}, strength: {}, radius: {} }}", center, strength, radius)
+            }
+            ForceField::Shaking { amplitude, frequency } => {
+                write!(f, "Shaking {{ amplitude: {:?}, frequency: {} }}", amplitude, frequency)
+            }
+            ForceField::Custom(_) => write!(f, "Custom"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn point_attractor_and_vortex_falloff() {
+        let center = Point::new(1.0, 1.0);
+        let attractor = ForceField::PointAttractor {
+            center,
+            strength: 4.0,
+            radius: 2.0,
+        };
+        let a = attractor.accelleration(Point::new(2.0, 1.0), Vector::zero(), 0.0);
+        assert_lt!((a - Vector::new(-2.0, 0.0)).magnitude(), 0.00001);
+        assert_eq!(attractor.accelleration(Point::new(3.0, 1.0), Vector::zero(), 0.0), Vector::zero());
+        assert_eq!(attractor.accelleration(center, Vector::zero(), 0.0), Vector::zero());
+
+        let vortex = ForceField::Vortex {
+            center,
+            strength: 4.0,
+            radius: 2.0,
+        };
+        let a = vortex.accelleration(Point::new(2.0, 1.0), Vector::zero(), 0.0);
+        assert_lt!((a - Vector::new(0.0, 2.0)).magnitude(), 0.00001);
+    }
+
+    #[test]
+    fn shaking_is_opposite_of_container_accelleration() {
+        let shaking = ForceField::Shaking {
+            amplitude: Vector::new(0.1, 0.0),
+            frequency: 0.5,
+        };
+        // container displacement x = A sin(πt) has accelleration -Aπ² sin(πt)
+        let a = shaking.accelleration(Point::new(0.0, 0.0), Vector::zero(), 0.5);
+        let expected = 0.1 * (std::f64::consts::PI as Real).powi(2);
+        assert_lt!((a - Vector::new(expected, 0.0)).magnitude(), 0.00001);
+        assert_lt!(shaking.accelleration(Point::new(0.0, 0.0), Vector::zero(), 1.0).magnitude(), 0.00001);
+    }
+
+    #[test]
+    fn custom_field_gets_particle_state() {
+        let drag = ForceField::custom(|_, velocity, time| -velocity * time);
+        assert_eq!(
+            drag.accelleration(Point::new(0.0, 0.0), Vector::new(1.0, 2.0), 0.5),
+            Vector::new(-0.5, -1.0)
+        );
+    }
+}
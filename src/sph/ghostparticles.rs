@@ -0,0 +1,306 @@
+use super::fluidparticleworld::{BoundaryGeometry, FluidParticleWorld, Particles};
+use super::neighborhood_search::ParticleIndex;
+use super::smoothing_kernel::Kernel;
+use crate::units::*;
+use cgmath::prelude::*;
+use rayon::prelude::*;
+
+// Velocity condition ghost particles enforce at walls.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WallCondition {
+    // Fluid slides along walls without friction, ghosts only mirror the velocity's normal component.
+    FreeSlip,
+    // Fluid sticks to the walls, ghosts move opposite to their fluid particle.
+    NoSlip,
+}
+
+// Tangent plane of a wall surface, used to mirror particles across.
+#[derive(Clone, Copy, Debug)]
+struct MirrorPlane {
+    point: Point,
+    normal: Vector, // pointing towards the fluid
+}
+
+impl MirrorPlane {
+    fn distance(&self, position: Point) -> Real {
+        (position - self.point).dot(self.normal)
+    }
+
+    fn mirror_position(&self, position: Point) -> Point {
+        position - self.normal * (2.0 * self.distance(position))
+    }
+
+    fn mirror_velocity(&self, velocity: Vector, condition: WallCondition) -> Vector {
+        match condition {
+            WallCondition::FreeSlip => velocity - self.normal * (2.0 * velocity.dot(self.normal)),
+            WallCondition::NoSlip => -velocity,
+        }
+    }
+
+    // Plane of a wall with the given thickness around its center point closest to the position.
+    // None if the position is on the center point itself.
+    fn from_closest_point(position: Point, closest_point: Point, width: Real) -> Option<(Real, MirrorPlane)> {
+        let offset = position - closest_point;
+        let distance = offset.magnitude();
+        if distance == 0.0 {
+            return None;
+        }
+        let normal = offset / distance;
+        let plane = MirrorPlane {
+            point: closest_point + normal * (width * 0.5),
+            normal,
+        };
+        Some((distance - width * 0.5, plane))
+    }
+
+    // Distance to the surface of a boundary shape and the plane touching it there.
+    fn closest_on_geometry(position: Point, geometry: &BoundaryGeometry) -> Option<(Real, MirrorPlane)> {
+        match geometry {
+            BoundaryGeometry::Line { start, end, width } => Self::closest_on_segment(position, *start, *end, *width),
+            BoundaryGeometry::Polygon { vertices, width } => (0..vertices.len())
+                .filter_map(|i| Self::closest_on_segment(position, vertices[i], vertices[(i + 1) % vertices.len()], *width))
+                .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal)),
+            BoundaryGeometry::Polyline { vertices, width } => vertices
+                .windows(2)
+                .filter_map(|segment| Self::closest_on_segment(position, segment[0], segment[1], *width))
+                .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal)),
+            BoundaryGeometry::Circle { center, radius, width } => {
+                let to_position = position - center;
+                let distance_to_center = to_position.magnitude();
+                if distance_to_center == 0.0 {
+                    return None;
+                }
+                Self::from_closest_point(position, center + to_position * (radius / distance_to_center), *width)
+            }
+        }
+    }
+
+    fn closest_on_segment(position: Point, start: Point, end: Point, width: Real) -> Option<(Real, MirrorPlane)> {
+        let direction = end - start;
+        let length_sq = direction.magnitude2();
+        // parameter of the projection onto the line, limited to the segment
+        let t = (position - start).dot(direction) / length_sq;
+        let t = if length_sq == 0.0 || t < 0.0 {
+            0.0
+        } else if t > 1.0 {
+            1.0
+        } else {
+            t
+        };
+        Self::from_closest_point(position, start + direction * t, width)
+    }
+}
+
+// Boundary handling with mirrored ghost particles, an alternative to boundary particles.
+//
+// Fluid particles close to a wall see the fluid mirrored at the wall's surface, giving them full kernel support
+// and letting the wall act on them through the regular pressure and viscosity terms.
+// Walls are taken from FluidParticleWorld::boundary_geometry.
+// Ghosts are never stored, they are found on the fly by looking for fluid particles around a particle's own mirror image.
+// For simplicity, every particle is only mirrored at the surface of the closest wall, approximated by its tangent plane.
+// This gets inaccurate in sharp corners.
+pub struct GhostParticles {
+    pub condition: WallCondition,
+    // per fluid particle, plane of the closest wall if it is within the smoothing length
+    planes: Vec<Option<MirrorPlane>>,
+}
+
+impl GhostParticles {
+    pub fn new(condition: WallCondition) -> GhostParticles {
+        GhostParticles {
+            condition,
+            planes: Vec::new(),
+        }
+    }
+
+    // Finds the closest wall for every particle. Needs to be called whenever particles moved.
+    pub fn update(&mut self, fluid_world: &FluidParticleWorld) {
+        microprofile::scope!("GhostParticles", "update");
+        let smoothing_length = fluid_world.properties.smoothing_length();
+        let geometry = fluid_world.boundary_geometry();
+        fluid_world
+            .particles
+            .positions
+            .par_iter()
+            .map(|&position| {
+                geometry
+                    .iter()
+                    .filter_map(|geometry| MirrorPlane::closest_on_geometry(position, geometry))
+                    .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal))
+                    // particles that already penetrated a wall would be mirrored to the wrong side
+                    .and_then(|(distance, plane)| {
+                        if distance >= 0.0 && distance < smoothing_length {
+                            Some(plane)
+                        } else {
+                            None
+                        }
+                    })
+            })
+            .collect_into_vec(&mut self.planes);
+    }
+
+    // Calls f with source particle, position and velocity of every ghost particle within the smoothing length of particle i.
+    // Uses the neighborhood datastructure as of the last update.
+    #[inline]
+    pub(super) fn foreach_ghost_neighbor(
+        &self,
+        particles: &Particles,
+        smoothing_length: Real,
+        i: ParticleIndex,
+        mut f: impl FnMut(ParticleIndex, Point, Vector),
+    ) {
+        let plane = match self.planes.get(i as usize) {
+            Some(Some(plane)) => plane,
+            _ => return,
+        };
+        // |x_i - mirror(x_j)| == |mirror(x_i) - x_j|
+        let mirrored_position = plane.mirror_position(particles.positions[i as usize]);
+        particles.foreach_fluid_particle_in_radius(mirrored_position, smoothing_length, |j| {
+            let position_j = particles.positions[j as usize];
+            if plane.distance(position_j) < 0.0 {
+                return;
+            }
+            f(
+                j,
+                plane.mirror_position(position_j),
+                plane.mirror_velocity(particles.velocities[j as usize], self.condition),
+            );
+        });
+    }
+
+    // Recomputes all densities from fluid and ghost particles, ignoring boundary particles.
+    pub fn update_densities(&self, fluid_world: &mut FluidParticleWorld, kernel: impl Kernel + std::marker::Sync) {
+        self.compute_densities(fluid_world, kernel, true);
+    }
+
+    // Same as update_densities without clamping to the rest density, see FluidParticleWorld::update_densities_unclamped.
+    pub fn update_densities_unclamped(&self, fluid_world: &mut FluidParticleWorld, kernel: impl Kernel + std::marker::Sync) {
+        self.compute_densities(fluid_world, kernel, false);
+    }
+
+    fn compute_densities(&self, fluid_world: &mut FluidParticleWorld, kernel: impl Kernel + std::marker::Sync, clamp_to_rest_density: bool) {
+        microprofile::scope!("GhostParticles", "update_densities");
+        let mut densities = std::mem::take(&mut fluid_world.particles.densities);
+        densities.resize(fluid_world.particles.positions.len(), 0.0);
+        {
+            let particles = &fluid_world.particles;
+            let smoothing_length = fluid_world.properties.smoothing_length();
+            let fluid_density = fluid_world.properties.fluid_density();
+            densities.par_iter_mut().enumerate().for_each(|(i, density)| {
+                let ri = particles.positions[i];
+                *density = kernel.evaluate(0.0, 0.0) * particles.masses[i]; // self-contribution
+                particles.foreach_neighbor_particle(i as ParticleIndex, |j| {
+                    let r_sq = ri.distance2(particles.positions[j as usize]);
+                    *density += kernel.evaluate(r_sq, r_sq.sqrt()) * particles.masses[j as usize];
+                });
+                self.foreach_ghost_neighbor(particles, smoothing_length, i as ParticleIndex, |j, ghost_position, _| {
+                    let r_sq = ri.distance2(ghost_position);
+                    *density += kernel.evaluate(r_sq, r_sq.sqrt()) * particles.masses[j as usize];
+                });
+
+                // same clamping as in FluidParticleWorld::update_densities
+                if clamp_to_rest_density {
+                    *density = density.max(fluid_density);
+                }
+            });
+        }
+        fluid_world.particles.densities = densities;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sph::smoothing_kernel;
+
+    #[test]
+    fn mirror_planes_of_geometry() {
+        let line = BoundaryGeometry::Line {
+            start: Point::new(0.0, 0.0),
+            end: Point::new(1.0, 0.0),
+            width: 0.2,
+        };
+        let (distance, plane) = MirrorPlane::closest_on_geometry(Point::new(0.5, 0.3), &line).unwrap();
+        assert_lt!((distance - 0.2).abs(), 1.0e-5);
+        assert_lt!((plane.mirror_position(Point::new(0.5, 0.3)) - Point::new(0.5, -0.1)).magnitude(), 1.0e-5);
+        let velocity = Vector::new(1.0, 2.0);
+        assert_eq!(plane.mirror_velocity(velocity, WallCondition::FreeSlip), Vector::new(1.0, -2.0));
+        assert_eq!(plane.mirror_velocity(velocity, WallCondition::NoSlip), Vector::new(-1.0, -2.0));
+
+        // fluid inside a circle
+        let circle = BoundaryGeometry::Circle {
+            center: Point::new(0.0, 0.0),
+            radius: 1.0,
+            width: 0.2,
+        };
+        let (distance, plane) = MirrorPlane::closest_on_geometry(Point::new(0.5, 0.0), &circle).unwrap();
+        assert_lt!((distance - 0.4).abs(), 1.0e-5);
+        assert_lt!((plane.normal - Vector::new(-1.0, 0.0)).magnitude(), 1.0e-5);
+    }
+
+    #[test]
+    fn ghosts_complete_density_at_wall() {
+        let mut fluid_world = FluidParticleWorld::new(2.0, NumberDensity(10000.0), Density(100.0));
+        let spacing = fluid_world.properties.particle_radius() * 2.0;
+        // thick lines grow to the right, the floor's surface is half a spacing below the line
+        fluid_world.add_boundary_thick_line(Point::new(0.0, 0.0), Point::new(1.0, 0.0), 2);
+        // so the first row is half a spacing above the surface, making mirrored rows continue the lattice
+        fluid_world.add_fluid_rect(&Rect::new(0.0, 0.0, 1.0, 0.3), 0.0);
+        fluid_world.update_neighborhood_datastructure(Vec::new(), Vec::new());
+
+        let kernel = smoothing_kernel::CubicSpline::new(fluid_world.properties.smoothing_length());
+        let mut ghosts = GhostParticles::new(WallCondition::FreeSlip);
+        ghosts.update(&fluid_world);
+        ghosts.update_densities(&mut fluid_world, kernel);
+
+        let fluid_density = fluid_world.properties.fluid_density();
+        let particles = &fluid_world.particles;
+        let mut num_checked = 0;
+        for (p, density) in particles.positions.iter().zip(particles.densities.iter()) {
+            if p.y < spacing * 0.5 && p.x > 0.2 && p.x < 0.8 {
+                assert_lt!((density / fluid_density - 1.0).abs(), 0.02);
+                num_checked += 1;
+            }
+        }
+        assert_gt!(num_checked, 0);
+    }
+
+    #[test]
+    fn ghost_velocities_follow_wall_condition() {
+        let mut fluid_world = FluidParticleWorld::new(2.0, NumberDensity(10000.0), Density(100.0));
+        fluid_world.add_boundary_thick_line(Point::new(0.0, 0.0), Point::new(1.0, 0.0), 2);
+        fluid_world.add_fluid_rect(&Rect::new(0.0, 0.0, 1.0, 0.3), 0.0);
+        // fluid sliding along and sinking into the floor
+        for v in fluid_world.particles.velocities.iter_mut() {
+            *v = Vector::new(1.0, -0.5);
+        }
+        fluid_world.update_neighborhood_datastructure(Vec::new(), Vec::new());
+        let smoothing_length = fluid_world.properties.smoothing_length();
+
+        // sum of relative velocities to ghosts, which is what drives viscosity
+        let relative_ghost_velocity = |condition: WallCondition| {
+            let mut ghosts = GhostParticles::new(condition);
+            ghosts.update(&fluid_world);
+            let particles = &fluid_world.particles;
+            let i = (0..particles.positions.len())
+                .find(|&i| particles.positions[i].y == 0.0 && particles.positions[i].x > 0.4)
+                .unwrap();
+            let mut num_ghosts = 0;
+            let mut relative_velocity = Vector::zero();
+            ghosts.foreach_ghost_neighbor(particles, smoothing_length, i as ParticleIndex, |_, ghost_position, ghost_velocity| {
+                assert_lt!(ghost_position.y, 0.0);
+                num_ghosts += 1;
+                relative_velocity += ghost_velocity - particles.velocities[i];
+            });
+            assert_gt!(num_ghosts, 0);
+            relative_velocity / num_ghosts as Real
+        };
+
+        // both conditions push back against the wall
+        let free_slip = relative_ghost_velocity(WallCondition::FreeSlip);
+        assert_lt!((free_slip - Vector::new(0.0, 1.0)).magnitude(), 1.0e-5);
+        // but only no-slip walls drag on the fluid moving along them
+        let no_slip = relative_ghost_velocity(WallCondition::NoSlip);
+        assert_lt!((no_slip - Vector::new(-2.0, 1.0)).magnitude(), 1.0e-5);
+    }
+}
@@ -0,0 +1,187 @@
+use super::fluidparticleworld::FluidParticleWorld;
+use super::interpolation::{self, Normalization, SampleGrid};
+use crate::units::*;
+use std::io;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GridExportFormat {
+    // Legacy VTK structured points (ASCII), with a density scalar and a velocity vector field. Opens in ParaView & co.
+    Vtk,
+    // NumPy array of shape (ny, nx, 3) with density, velocity x & y per grid point as little endian f32, rows from the bottom up.
+    Npy,
+}
+
+impl GridExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            GridExportFormat::Vtk => "vtk",
+            GridExportFormat::Npy => "npy",
+        }
+    }
+}
+
+// Density and velocity fields of the fluid sampled on a regular grid, for post-processing with standard CFD tooling.
+// Density is the plain SPH sum, so it falls off to zero outside the fluid, velocity is Shepard normalized and zero outside. See interpolation.
+//
+//   let mut fields = GridFields::new(SampleGrid { origin, spacing: 0.01, size: (200, 100) });
+//   fields.sample(&fluid_world, time);
+//   fields.write(&mut File::create(format!("grid_{:05}.vtk", frame))?, GridExportFormat::Vtk)?;
+pub struct GridFields {
+    pub grid: SampleGrid,
+    pub time: Real,              // simulation time of the last sample
+    pub densities: Vec<Real>,    // in kg/m², per grid point, see SampleGrid::index
+    pub velocities: Vec<Vector>, // in m/s
+
+    color_field: Vec<Real>, // kept around to avoid reallocations
+}
+
+impl GridFields {
+    pub fn new(grid: SampleGrid) -> GridFields {
+        GridFields {
+            grid,
+            time: 0.0,
+            densities: vec![0.0; grid.num_points()],
+            velocities: vec![Vector::new(0.0, 0.0); grid.num_points()],
+            color_field: Vec::new(),
+        }
+    }
+
+    pub fn sample(&mut self, fluid_world: &FluidParticleWorld, time: Real) {
+        microprofile::scope!("GridFields", "sample");
+        let particles = &fluid_world.particles;
+        let fluid_density = fluid_world.properties.fluid_density();
+        // densities of particles added since the last step aren't known yet
+        let density = |j| {
+            particles
+                .densities
+                .get(j)
+                .cloned()
+                .filter(|density| *density > 0.0)
+                .unwrap_or(fluid_density)
+        };
+        interpolation::splat_to_grid(
+            fluid_world,
+            &self.grid,
+            density,
+            Normalization::None,
+            &mut self.densities,
+            &mut self.color_field,
+        );
+        interpolation::splat_to_grid(
+            fluid_world,
+            &self.grid,
+            |j| particles.velocities[j],
+            Normalization::Shepard,
+            &mut self.velocities,
+            &mut self.color_field,
+        );
+        self.time = time;
+    }
+
+    pub fn write(&self, writer: &mut impl io::Write, format: GridExportFormat) -> io::Result<()> {
+        match format {
+            GridExportFormat::Vtk => self.write_vtk(writer),
+            GridExportFormat::Npy => self.write_npy(writer),
+        }
+    }
+
+    pub fn write_vtk(&self, writer: &mut impl io::Write) -> io::Result<()> {
+        let grid = &self.grid;
+        writeln!(writer, "# vtk DataFile Version 3.0")?;
+        writeln!(writer, "YaSPH2D fluid fields at t={}s", self.time)?;
+        writeln!(writer, "ASCII")?;
+        writeln!(writer, "DATASET STRUCTURED_POINTS")?;
+        writeln!(writer, "DIMENSIONS {} {} 1", grid.size.0, grid.size.1)?;
+        writeln!(writer, "ORIGIN {} {} 0", grid.origin.x, grid.origin.y)?;
+        writeln!(writer, "SPACING {} {} 1", grid.spacing, grid.spacing)?;
+        writeln!(writer, "POINT_DATA {}", grid.num_points())?;
+        writeln!(writer, "SCALARS density float 1")?;
+        writeln!(writer, "LOOKUP_TABLE default")?;
+        for density in self.densities.iter() {
+            writeln!(writer, "{}", density)?;
+        }
+        writeln!(writer, "VECTORS velocity float")?;
+        for velocity in self.velocities.iter() {
+            writeln!(writer, "{} {} 0", velocity.x, velocity.y)?;
+        }
+        Ok(())
+    }
+
+    pub fn write_npy(&self, writer: &mut impl io::Write) -> io::Result<()> {
+        // format version 1.0: magic, version, header length, header padded with spaces to a multiple of 64 bytes including a final newline
+        let mut header = format!(
+            "{{'descr': '<f4', 'fortran_order': False, 'shape': ({}, {}, 3), }}",
+            self.grid.size.1, self.grid.size.0
+        );
+        let unpadded_length = 10 + header.len() + 1;
+        header.push_str(&" ".repeat((64 - unpadded_length % 64) % 64));
+        header.push('\n');
+        writer.write_all(b"\x93NUMPY\x01\x00")?;
+        writer.write_all(&(header.len() as u16).to_le_bytes())?;
+        writer.write_all(header.as_bytes())?;
+
+        let mut data = Vec::with_capacity(self.grid.num_points() * 12);
+        for (density, velocity) in self.densities.iter().zip(self.velocities.iter()) {
+            for value in [*density, velocity.x, velocity.y].iter() {
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        writer.write_all(&data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sampled_block() -> GridFields {
+        let mut fluid_world = FluidParticleWorld::new(2.0, NumberDensity(10000.0), Density(100.0));
+        fluid_world.add_fluid_rect(&Rect::new(0.0, 0.0, 0.5, 0.5), 0.0);
+        for v in fluid_world.particles.velocities.iter_mut() {
+            *v = Vector::new(0.5, 0.0);
+        }
+        let mut fields = GridFields::new(SampleGrid {
+            origin: Point::new(-0.2, 0.0),
+            spacing: 0.1,
+            size: (10, 5),
+        });
+        fields.sample(&fluid_world, 1.5);
+        fields
+    }
+
+    #[test]
+    fn samples_inside_and_outside_the_fluid() {
+        let fields = sampled_block();
+        let inside = fields.grid.index(4, 2);
+        assert_lt!((fields.densities[inside] - 100.0).abs(), 5.0);
+        assert_lt!((fields.velocities[inside].x - 0.5).abs(), 1.0e-4);
+        let outside = fields.grid.index(9, 2);
+        assert_eq!(fields.densities[outside], 0.0);
+        assert_eq!(fields.velocities[outside], Vector::new(0.0, 0.0));
+        assert_eq!(fields.time, 1.5);
+    }
+
+    #[test]
+    fn writes_vtk_and_npy() {
+        let fields = sampled_block();
+
+        let mut vtk = Vec::new();
+        fields.write(&mut vtk, GridExportFormat::Vtk).unwrap();
+        let vtk = String::from_utf8(vtk).unwrap();
+        assert!(vtk.contains("DIMENSIONS 10 5 1\n"));
+        // header, 50 densities and a line in between, 50 velocities
+        assert_eq!(vtk.lines().count(), 10 + 50 + 1 + 50);
+
+        let mut npy = Vec::new();
+        fields.write(&mut npy, GridExportFormat::Npy).unwrap();
+        let header_length = u16::from_le_bytes([npy[8], npy[9]]) as usize;
+        assert_eq!((10 + header_length) % 64, 0);
+        let header = std::str::from_utf8(&npy[10..10 + header_length]).unwrap();
+        assert!(header.contains("'shape': (5, 10, 3)"));
+        assert!(header.ends_with('\n'));
+        assert_eq!(npy.len(), 10 + header_length + 50 * 3 * 4);
+        let inside = 10 + header_length + fields.grid.index(4, 2) * 12;
+        let velocity_x = Real::from_le_bytes([npy[inside + 4], npy[inside + 5], npy[inside + 6], npy[inside + 7]]);
+        assert_eq!(velocity_x, fields.velocities[fields.grid.index(4, 2)].x);
+    }
+}
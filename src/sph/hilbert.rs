@@ -0,0 +1,146 @@
+// Hilbert curve index of a cell, an alternative to morton codes for ordering the cells of the neighborhood search.
+//
+// Unlike the morton curve, the hilbert curve never jumps: consecutive indices are always adjacent cells.
+// This gives better locality, but there is no cheap way to tell whether an index is within a rectangle, it needs to be decoded first.
+//
+// via https://en.wikipedia.org/wiki/Hilbert_curve#Applications_and_mapping_algorithms
+
+// Rotates/flips a quadrant of size s appropriately.
+#[inline]
+fn rotate(s: u32, x: &mut u32, y: &mut u32, rx: u32, ry: u32) {
+    if ry == 0 {
+        if rx == 1 {
+            *x = s.wrapping_sub(1).wrapping_sub(*x);
+            *y = s.wrapping_sub(1).wrapping_sub(*y);
+        }
+        std::mem::swap(x, y);
+    }
+}
+
+// Hilbert index of a position on a 2^32 x 2^32 grid.
+#[inline]
+pub fn encode(x: u32, y: u32) -> u64 {
+    encode_bits(x, y, 32, 0)
+}
+
+// Adds the contribution of the lowest num_bits bits of a position to a hilbert index.
+// The position needs to be transformed by the rotations of all higher bits already.
+#[inline]
+fn encode_bits(x: u32, y: u32, num_bits: u32, index: u64) -> u64 {
+    let (mut x, mut y) = (x, y);
+    let mut index = index;
+    for bit in (0..num_bits).rev() {
+        let s = 1 << bit;
+        let rx = ((x & s) != 0) as u32;
+        let ry = ((y & s) != 0) as u32;
+        index += (s as u64) * (s as u64) * ((3 * rx) ^ ry) as u64;
+        // bits above the current one have been handled, flipping them doesn't matter
+        rotate(0, &mut x, &mut y, rx, ry);
+    }
+    index
+}
+
+// Hilbert indices of all positions in a rectangle (inclusive), in row order.
+// The bits above the highest one that differs within the rectangle are the same for all positions and only processed once,
+// which makes this a lot cheaper than encoding each position for small rectangles.
+pub fn encode_rect(min_x: u32, min_y: u32, max_x: u32, max_y: u32, mut f: impl FnMut(u32, u32, u64)) {
+    let num_low_bits = 32 - ((min_x ^ max_x) | (min_y ^ max_y)).leading_zeros();
+
+    // Rotations of the high bits either swap x and y or not and either flip all bits of both or not, in any order.
+    let (mut x, mut y) = (min_x, min_y);
+    let mut prefix = 0;
+    let mut swapped = false;
+    let mut flipped = false;
+    for bit in (num_low_bits..32).rev() {
+        let s = 1 << bit;
+        let rx = ((x & s) != 0) as u32;
+        let ry = ((y & s) != 0) as u32;
+        prefix += (s as u64) * (s as u64) * ((3 * rx) ^ ry) as u64;
+        rotate(0, &mut x, &mut y, rx, ry);
+        if ry == 0 {
+            flipped ^= rx == 1;
+            swapped = !swapped;
+        }
+    }
+
+    for y in min_y..=max_y {
+        for x in min_x..=max_x {
+            let (mut tx, mut ty) = if swapped { (y, x) } else { (x, y) };
+            if flipped {
+                tx = !tx;
+                ty = !ty;
+            }
+            f(x, y, encode_bits(tx, ty, num_low_bits, prefix));
+        }
+    }
+}
+
+// Position (x, y) of a hilbert index, inverse of encode.
+#[inline]
+pub fn decode(index: u64) -> (u32, u32) {
+    let (mut x, mut y) = (0, 0);
+    let mut t = index;
+    for bit in 0..32 {
+        let s = 1 << bit;
+        let rx = (1 & (t / 2)) as u32;
+        let ry = (1 & (t ^ rx as u64)) as u32;
+        rotate(s, &mut x, &mut y, rx, ry);
+        x += s * rx;
+        y += s * ry;
+        t /= 4;
+    }
+    (x, y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::prelude::*;
+
+    #[test]
+    fn decode_inverts_encode() {
+        let mut rng: rand::rngs::SmallRng = rand::SeedableRng::seed_from_u64(123456789);
+        for _ in 0..10000 {
+            let (x, y) = (rng.gen::<u32>(), rng.gen::<u32>());
+            assert_eq!(decode(encode(x, y)), (x, y));
+        }
+        assert_eq!(encode(0, 0), 0);
+        assert_eq!(decode(u64::MAX), (u32::MAX, 0));
+    }
+
+    #[test]
+    fn encode_rect_matches_encode() {
+        let mut rng: rand::rngs::SmallRng = rand::SeedableRng::seed_from_u64(123456789);
+        for i in 0..1000 {
+            let min = if i % 2 == 0 {
+                (rng.gen::<u32>().saturating_sub(8), rng.gen::<u32>().saturating_sub(8))
+            } else {
+                ((1 << 31) - rng.gen::<u32>() % 8, (1 << 30) - rng.gen::<u32>() % 8)
+            };
+            let max = (min.0 + rng.gen::<u32>() % 8, min.1 + rng.gen::<u32>() % 8);
+            let mut num_positions = 0;
+            encode_rect(min.0, min.1, max.0, max.1, |x, y, index| {
+                assert_eq!(index, encode(x, y));
+                num_positions += 1;
+            });
+            assert_eq!(num_positions, (max.0 - min.0 + 1) * (max.1 - min.1 + 1));
+        }
+    }
+
+    #[test]
+    fn consecutive_indices_are_adjacent() {
+        let mut rng: rand::rngs::SmallRng = rand::SeedableRng::seed_from_u64(123456789);
+        let starts = (0..64)
+            .map(|_| rng.gen::<u64>())
+            .chain([0, (1 << 62) - 50, (1 << 63) - 50].iter().cloned());
+        for start in starts {
+            let mut previous = decode(start);
+            for index in start + 1..start.saturating_add(100) {
+                let (x, y) = decode(index);
+                let distance = (x as i64 - previous.0 as i64).abs() + (y as i64 - previous.1 as i64).abs();
+                assert_eq!(distance, 1, "between index {} and {}", index - 1, index);
+                previous = (x, y);
+            }
+        }
+    }
+}
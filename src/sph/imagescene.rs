@@ -0,0 +1,168 @@
+use super::error::{check_length, SphError};
+use super::fluidparticleworld::FluidParticleWorld;
+use crate::units::*;
+use cgmath::prelude::*;
+
+// Boundary particles are only placed within this many lattice steps of a pixel fluid can reach,
+// the interior of large solid areas is never in reach of any fluid particle.
+const BOUNDARY_LAYERS: usize = 2;
+
+// Scene authored as a bitmap, e.g. in any paint program: pixels of the fluid color become fluid, pixels of the boundary color boundary.
+// All other pixels stay empty, as do (mostly) transparent ones.
+//
+// Particles are placed on a lattice with rest spacing across the image, so pixels may be larger or smaller than particles.
+// Image rows go from top to bottom while the world's y axis points up, i.e. the image appears upright in the world.
+pub struct ImageScene {
+    width: usize,
+    height: usize,
+    rgba: Vec<u8>, // 8 bit per channel, rows from top to bottom
+
+    pub pixel_size: Real, // edge length of a pixel in m
+    pub origin: Point,    // world position of the image's bottom left corner
+    pub fluid_color: [u8; 3],
+    pub boundary_color: [u8; 3],
+    pub color_tolerance: u8, // maximum difference per channel, so that anti-aliased or lossy compressed images work as well
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Material {
+    Empty,
+    Fluid,
+    Boundary,
+}
+
+impl ImageScene {
+    // Blue fluid on black boundaries, with the image's bottom left corner at the origin.
+    // `rgba` has 4 bytes per pixel with the first row being the top of the image, as decoded by most image libraries.
+    pub fn from_rgba8(width: usize, height: usize, rgba: Vec<u8>, pixel_size: Real) -> Result<ImageScene, SphError> {
+        check_length("rgba bytes (4 per pixel)", width * height * 4, rgba.len())?;
+        Ok(ImageScene {
+            width,
+            height,
+            rgba,
+            pixel_size,
+            origin: Point::new(0.0, 0.0),
+            fluid_color: [0, 0, 255],
+            boundary_color: [0, 0, 0],
+            color_tolerance: 32,
+        })
+    }
+
+    // Size of the image in the world in m.
+    pub fn extent(&self) -> Vector {
+        Vector::new(self.width as Real, self.height as Real) * self.pixel_size
+    }
+
+    fn matches(&self, pixel: &[u8], color: &[u8; 3]) -> bool {
+        pixel
+            .iter()
+            .zip(color.iter())
+            .all(|(a, b)| (*a as i32 - *b as i32).abs() <= self.color_tolerance as i32)
+    }
+
+    // Material at a world position, None outside of the image.
+    fn material_at(&self, position: Point) -> Option<Material> {
+        let offset = (position - self.origin) / self.pixel_size;
+        if offset.x < 0.0 || offset.y < 0.0 || offset.x >= self.width as Real || offset.y >= self.height as Real {
+            return None;
+        }
+        let row = self.height - 1 - offset.y as usize;
+        let start = (offset.x as usize + row * self.width) * 4;
+        let pixel = &self.rgba[start..start + 4];
+        Some(if pixel[3] < 128 {
+            Material::Empty
+        } else if self.matches(pixel, &self.fluid_color) {
+            Material::Fluid
+        } else if self.matches(pixel, &self.boundary_color) {
+            Material::Boundary
+        } else {
+            Material::Empty
+        })
+    }
+
+    // Adds resting fluid particles and static boundaries for the image.
+    // Boundaries are added as one line per row of boundary particles, only covering the outlines of boundary areas.
+    // Everything outside of the image is regarded as solid, i.e. there are no boundary particles along the image's border.
+    //
+    // Returns the number of added fluid and boundary particles.
+    pub fn add_to(&self, fluid_world: &mut FluidParticleWorld) -> (usize, usize) {
+        let spacing = fluid_world.properties.particle_radius() * 2.0;
+        let extent = self.extent();
+        let num_x = (extent.x / spacing).ceil() as usize;
+        let num_y = (extent.y / spacing).ceil() as usize;
+        let lattice_position = |x: usize, y: usize| self.origin + Vector::new(x as Real + 0.5, y as Real + 0.5) * spacing;
+        let materials: Vec<Option<Material>> = (0..num_y)
+            .flat_map(|y| (0..num_x).map(move |x| (x, y)))
+            .map(|(x, y)| self.material_at(lattice_position(x, y)))
+            .collect();
+        let is_open = |x: usize, y: usize| matches!(materials[x + y * num_x], Some(Material::Empty) | Some(Material::Fluid));
+
+        let mut num_fluid_particles = 0;
+        let mut num_boundary_particles = 0;
+        for y in 0..num_y {
+            let mut run_start = None;
+            for x in 0..=num_x {
+                let is_surface = x < num_x
+                    && materials[x + y * num_x] == Some(Material::Boundary)
+                    && (y.saturating_sub(BOUNDARY_LAYERS)..(y + BOUNDARY_LAYERS + 1).min(num_y))
+                        .any(|ny| (x.saturating_sub(BOUNDARY_LAYERS)..(x + BOUNDARY_LAYERS + 1).min(num_x)).any(|nx| is_open(nx, ny)));
+                match (is_surface, run_start) {
+                    (true, None) => run_start = Some(x),
+                    (false, Some(start)) => {
+                        // ending half a spacing after the last particle, so that exactly one particle per lattice position is sampled
+                        let end = lattice_position(x - 1, y) + Vector::new(spacing * 0.5, 0.0);
+                        fluid_world.add_boundary_line(lattice_position(start, y), end);
+                        num_boundary_particles += x - start;
+                        run_start = None;
+                    }
+                    _ => {}
+                }
+                if x < num_x && materials[x + y * num_x] == Some(Material::Fluid) {
+                    fluid_world.add_fluid_particle(lattice_position(x, y), Vector::zero());
+                    num_fluid_particles += 1;
+                }
+            }
+        }
+        (num_fluid_particles, num_boundary_particles)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn image_to_particles() {
+        const K: [u8; 4] = [0, 0, 0, 255];
+        const F: [u8; 4] = [0, 0, 255, 255];
+        const W: [u8; 4] = [255, 255, 255, 255];
+        // anti-aliased fluid and transparent boundary
+        let f = [10, 10, 240, 255];
+        let k = [0, 0, 0, 0];
+        let rows = [[K, f, W, k], [K, F, F, W], [K, K, K, K]];
+        let rgba: Vec<u8> = rows.iter().flat_map(|row| row.iter()).flat_map(|pixel| pixel.iter().cloned()).collect();
+        let scene = ImageScene::from_rgba8(4, 3, rgba, 0.05).unwrap();
+        assert_lt!((scene.extent() - Vector::new(0.2, 0.15)).magnitude(), 1.0e-6);
+
+        // 5x5 particles per pixel
+        let mut fluid_world = FluidParticleWorld::new(2.0, NumberDensity(10000.0), Density(1000.0));
+        let (num_fluid, num_boundary) = scene.add_to(&mut fluid_world);
+        assert_eq!(num_fluid, 3 * 25);
+        assert_eq!(fluid_world.particles.positions.len(), num_fluid);
+        for p in fluid_world.particles.positions.iter() {
+            // upper pixels are at the top of the world
+            assert!((0.05..0.15).contains(&p.x) && (0.05..0.1).contains(&p.y) || (0.05..0.1).contains(&p.x) && (0.1..0.15).contains(&p.y));
+        }
+
+        // two layers along the inner side of the L shaped wall, i.e. x and y in lattice steps 3..5 for the left and bottom wall
+        assert_eq!(num_boundary, 2 * 12 + 2 * 17 - 4);
+        assert_eq!(fluid_world.particles.boundary_particles.len(), num_boundary);
+        for p in fluid_world.particles.boundary_particles.iter() {
+            let on_left_wall = (0.03..0.05).contains(&p.x) && p.y > 0.03;
+            let on_bottom_wall = (0.03..0.05).contains(&p.y) && p.x > 0.03;
+            assert!(on_left_wall || on_bottom_wall);
+        }
+        // one line each for both rows of the bottom wall, one per row of the left wall above
+        assert_eq!(fluid_world.boundary_geometry().len(), 2 + 10);
+    }
+}
@@ -0,0 +1,160 @@
+use crate::units::*;
+
+// Time integration scheme of WCSPHSolver.
+//
+// Solvers evaluate accellerations once per step: begin_step moves particles to where the next accellerations are evaluated,
+// using the accellerations of the previous step, finish_step completes the step with the freshly evaluated ones.
+// In between, the neighborhood update may reorder particles.
+// DFSPH's pressure solve is built around (semi-implicit) symplectic Euler, so it doesn't offer a choice.
+pub trait Integrator: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn begin_step(&mut self, dt: Real, positions: &mut [Point], velocities: &mut [Vector], accellerations: &[Vector]);
+
+    // Per particle state kept from begin_step to finish_step that needs to follow particles when they are reordered.
+    fn particle_state(&mut self) -> Vec<&mut Vec<Vector>> {
+        Vec::new()
+    }
+
+    // `accellerations` were evaluated at the positions and velocities begin_step left behind.
+    // `next_dt` is the timestep of the next step, adaptive time stepping may have changed it since begin_step.
+    fn finish_step(&mut self, next_dt: Real, positions: &mut [Point], velocities: &mut [Vector], accellerations: &[Vector]);
+}
+
+// Semi-implicit Euler, x_(i+1) = x_i + v_i dt, v_(i+1) = v_i + a(x_(i+1)) dt
+// First order, but symplectic, i.e. energy oscillates instead of drifting off.
+pub struct SymplecticEuler;
+
+impl Integrator for SymplecticEuler {
+    fn name(&self) -> &'static str {
+        "Symplectic Euler"
+    }
+
+    fn begin_step(&mut self, dt: Real, positions: &mut [Point], velocities: &mut [Vector], _accellerations: &[Vector]) {
+        for (pos, v) in positions.iter_mut().zip(velocities.iter()) {
+            *pos += *v * dt;
+        }
+    }
+
+    fn finish_step(&mut self, next_dt: Real, _positions: &mut [Point], velocities: &mut [Vector], accellerations: &[Vector]) {
+        // Kicking with the next timestep keeps kick and drift of the next step paired, like the half kicks of VelocityVerlet.
+        for (v, a) in velocities.iter_mut().zip(accellerations.iter()) {
+            *v += next_dt * a;
+        }
+    }
+}
+
+// Leap frog integration scheme with integer steps, kick-drift-kick.
+// https://en.wikipedia.org/wiki/Leapfrog_integration
+// Second order and symplectic. Default of WCSPHSolver.
+pub struct VelocityVerlet;
+
+impl Integrator for VelocityVerlet {
+    fn name(&self) -> &'static str {
+        "Velocity Verlet"
+    }
+
+    fn begin_step(&mut self, dt: Real, positions: &mut [Point], velocities: &mut [Vector], accellerations: &[Vector]) {
+        // This got actually slower for a parallel for loop when used with 2500 particles (too few? or is rayon doing something silly?)
+        for ((pos, v), a) in positions.iter_mut().zip(velocities.iter_mut()).zip(accellerations.iter()) {
+            *v += 0.5 * dt * a; // v at t_(i+0.5)
+            *pos += *v * dt; // pos at t_(i+1)
+        }
+    }
+
+    fn finish_step(&mut self, next_dt: Real, _positions: &mut [Point], velocities: &mut [Vector], accellerations: &[Vector]) {
+        for (v, a) in velocities.iter_mut().zip(accellerations.iter()) {
+            *v += 0.5 * next_dt * a; // v at t_(i+1)
+        }
+    }
+}
+
+// Heun's method, a two stage Runge-Kutta scheme:
+// predicts x* = x_i + v_i dt, v* = v_i + a_i dt, evaluates a* there and corrects with the average slope of both stages.
+// Second order, but not symplectic, i.e. energy drifts over long simulations.
+// To get by with one evaluation per step, the first stage reuses the accellerations of the previous step.
+// Those were evaluated at the previous prediction rather than the corrected positions, which keeps it second order.
+#[derive(Default)]
+pub struct RungeKutta2 {
+    dt: Real,
+    first_stage_accellerations: Vec<Vector>,
+}
+
+impl Integrator for RungeKutta2 {
+    fn name(&self) -> &'static str {
+        "Runge-Kutta 2 (Heun)"
+    }
+
+    fn begin_step(&mut self, dt: Real, positions: &mut [Point], velocities: &mut [Vector], accellerations: &[Vector]) {
+        self.dt = dt;
+        self.first_stage_accellerations.clear();
+        self.first_stage_accellerations.extend_from_slice(accellerations);
+        for ((pos, v), a) in positions.iter_mut().zip(velocities.iter_mut()).zip(accellerations.iter()) {
+            *pos += *v * dt;
+            *v += a * dt;
+        }
+    }
+
+    fn particle_state(&mut self) -> Vec<&mut Vec<Vector>> {
+        vec![&mut self.first_stage_accellerations]
+    }
+
+    fn finish_step(&mut self, _next_dt: Real, positions: &mut [Point], velocities: &mut [Vector], accellerations: &[Vector]) {
+        // x_(i+1) = x_i + (v_i + v*) dt / 2 = x* + a_i dt² / 2
+        // v_(i+1) = v_i + (a_i + a*) dt / 2 = v* + (a* - a_i) dt / 2
+        let dt = self.dt;
+        for (((pos, v), a), a_first) in positions
+            .iter_mut()
+            .zip(velocities.iter_mut())
+            .zip(accellerations.iter())
+            .zip(self.first_stage_accellerations.iter())
+        {
+            *pos += 0.5 * dt * dt * a_first;
+            *v += 0.5 * dt * (a - a_first);
+        }
+        self.first_stage_accellerations.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::prelude::*;
+
+    // Integrates a harmonic oscillator a = -x from x = 1, v = 0 up to t = 1 and returns the error against x = cos(t), v = -sin(t).
+    fn oscillator_error(integrator: &mut dyn Integrator, num_steps: usize) -> Real {
+        let dt = 1.0 / num_steps as Real;
+        let mut positions = [Point::new(1.0, 0.0)];
+        let mut velocities = [Vector::zero()];
+        let mut accellerations = [-positions[0].to_vec()];
+        for _ in 0..num_steps {
+            integrator.begin_step(dt, &mut positions, &mut velocities, &accellerations);
+            accellerations[0] = -positions[0].to_vec();
+            integrator.finish_step(dt, &mut positions, &mut velocities, &accellerations);
+        }
+        let t: Real = 1.0;
+        (positions[0] - Point::new(t.cos(), 0.0)).magnitude() + (velocities[0] - Vector::new(-t.sin(), 0.0)).magnitude()
+    }
+
+    #[test]
+    fn integrators_converge_with_their_order() {
+        let integrators: Vec<(Box<dyn Integrator>, Real)> = vec![
+            (Box::new(SymplecticEuler), 1.0),
+            (Box::new(VelocityVerlet), 2.0),
+            (Box::new(RungeKutta2::default()), 2.0),
+        ];
+        for (mut integrator, order) in integrators {
+            let coarse = oscillator_error(integrator.as_mut(), 50);
+            let fine = oscillator_error(integrator.as_mut(), 100);
+            let measured_order = (coarse / fine).log2();
+            assert!(
+                (measured_order - order).abs() < 0.2,
+                "{}: error {} -> {}, order {}",
+                integrator.name(),
+                coarse,
+                fine,
+                measured_order
+            );
+        }
+    }
+}
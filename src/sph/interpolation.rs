@@ -0,0 +1,264 @@
+// SPH interpolation of per particle quantities at arbitrary points:
+//
+//   A(x) = Σ V_j A_j W(x - x_j)
+//
+// with V_j = m_j / ρ_j the volume of particle j and W the cubic spline kernel with the fluid's smoothing length.
+// Towards the fluid surface the sum drops off with the color field c(x) = Σ V_j W(x - x_j), which Shepard normalization divides by.
+// Particles whose density isn't known yet (added since the last step) count with their rest volume.
+//
+// Point queries use the neighborhood datastructure as of the last simulation step, i.e. particles added since are ignored.
+// Splatting onto a grid goes over all particles instead, so it doesn't depend on an up to date neighborhood datastructure.
+
+use super::fluidparticleworld::{FluidParticleWorld, Particles};
+use super::smoothing_kernel::{self, Kernel};
+use crate::units::*;
+use cgmath::prelude::*;
+use std::ops::{AddAssign, Div, Mul};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Normalization {
+    // Plain SPH sum, e.g. Σ V_j ρ_j W = Σ m_j W for densities, which drops off towards the surface.
+    None,
+    // Divided by the color field, so that a constant field is reproduced right up to the fluid surface.
+    Shepard,
+}
+
+// Anything that can be interpolated, i.e. scalars and vectors.
+pub trait Interpolatable: Copy + Zero + AddAssign + Mul<Real, Output = Self> + Div<Real, Output = Self> {}
+impl<T: Copy + Zero + AddAssign + Mul<Real, Output = T> + Div<Real, Output = T>> Interpolatable for T {}
+
+fn particle_volume(particles: &Particles, j: usize, fluid_density: Real) -> Real {
+    let density = particles.densities.get(j).cloned().filter(|density| *density > 0.0);
+    particles.masses[j] / density.unwrap_or(fluid_density)
+}
+
+fn normalize<T: Interpolatable>(sum: T, color_field: Real, normalization: Normalization) -> T {
+    match normalization {
+        Normalization::None => sum,
+        Normalization::Shepard => sum / color_field,
+    }
+}
+
+// Interpolates a quantity given per particle index at a point. None if there is no fluid particle in range.
+pub fn interpolate<T: Interpolatable>(
+    fluid_world: &FluidParticleWorld,
+    point: Point,
+    value: impl Fn(usize) -> T,
+    normalization: Normalization,
+) -> Option<T> {
+    let smoothing_length = fluid_world.properties.smoothing_length();
+    let kernel = smoothing_kernel::CubicSpline::new(smoothing_length);
+    let fluid_density = fluid_world.properties.fluid_density();
+    let particles = &fluid_world.particles;
+
+    let mut sum = T::zero();
+    let mut color_field = 0.0;
+    particles.foreach_fluid_particle_in_radius(point, smoothing_length, |j| {
+        let j = j as usize;
+        let r_sq = particles.positions[j].distance2(point);
+        let weight = particle_volume(particles, j, fluid_density) * kernel.evaluate(r_sq, r_sq.sqrt());
+        sum += value(j) * weight;
+        color_field += weight;
+    });
+
+    if color_field > 0.0 {
+        Some(normalize(sum, color_field, normalization))
+    } else {
+        None
+    }
+}
+
+// Interpolates one value per fluid particle, e.g. Particles::densities.
+pub fn interpolate_scalar(fluid_world: &FluidParticleWorld, point: Point, values: &[Real], normalization: Normalization) -> Option<Real> {
+    interpolate(fluid_world, point, |j| values[j], normalization)
+}
+
+// Interpolates one vector per fluid particle, e.g. Particles::velocities.
+pub fn interpolate_vector(fluid_world: &FluidParticleWorld, point: Point, values: &[Vector], normalization: Normalization) -> Option<Vector> {
+    interpolate(fluid_world, point, |j| values[j], normalization)
+}
+
+// c(x) = Σ V_j W(x - x_j), about 1 inside resting fluid and 0 outside.
+pub fn color_field(fluid_world: &FluidParticleWorld, point: Point) -> Real {
+    interpolate(fluid_world, point, |_| 1.0, Normalization::None).unwrap_or(0.0)
+}
+
+// Regular grid of sample points, stored row by row starting at the bottom left.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SampleGrid {
+    pub origin: Point, // first sample point
+    pub spacing: Real,
+    pub size: (usize, usize), // number of sample points in x and y
+}
+
+impl SampleGrid {
+    pub fn num_points(&self) -> usize {
+        self.size.0 * self.size.1
+    }
+
+    pub fn point(&self, x: usize, y: usize) -> Point {
+        self.origin + Vector::new(x as Real, y as Real) * self.spacing
+    }
+
+    pub fn index(&self, x: usize, y: usize) -> usize {
+        y * self.size.0 + x
+    }
+
+    // Calls f for every sample point within the radius around a position, with the point's index and squared distance.
+    fn foreach_point_in_radius(&self, position: Point, radius: Real, mut f: impl FnMut(usize, Real)) {
+        let min = (position - Vector::new(radius, radius) - self.origin) / self.spacing;
+        let max = (position + Vector::new(radius, radius) - self.origin) / self.spacing;
+        if !(min.x.is_finite() && min.y.is_finite() && max.x.is_finite() && max.y.is_finite()) {
+            return;
+        }
+        // float to int conversion saturates, so positions far off end up with empty ranges
+        let min_x = (min.x.ceil() as isize).max(0);
+        let min_y = (min.y.ceil() as isize).max(0);
+        let max_x = (max.x.floor() as isize).min(self.size.0 as isize - 1);
+        let max_y = (max.y.floor() as isize).min(self.size.1 as isize - 1);
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                let (x, y) = (x as usize, y as usize);
+                f(self.index(x, y), self.point(x, y).distance2(position));
+            }
+        }
+    }
+}
+
+// Splats all fluid particles onto a grid, filling values (zero wherever there is no fluid) and the color field for every grid point.
+// Much faster than interpolating at every grid point for grids about as fine as the particle spacing.
+pub fn splat_to_grid<T: Interpolatable>(
+    fluid_world: &FluidParticleWorld,
+    grid: &SampleGrid,
+    value: impl Fn(usize) -> T,
+    normalization: Normalization,
+    values: &mut Vec<T>,
+    color_field: &mut Vec<Real>,
+) {
+    microprofile::scope!("interpolation", "splat_to_grid");
+
+    values.clear();
+    values.resize(grid.num_points(), T::zero());
+    color_field.clear();
+    color_field.resize(grid.num_points(), 0.0);
+
+    let smoothing_length = fluid_world.properties.smoothing_length();
+    let kernel = smoothing_kernel::CubicSpline::new(smoothing_length);
+    let fluid_density = fluid_world.properties.fluid_density();
+    let particles = &fluid_world.particles;
+
+    for (j, position) in particles.positions.iter().enumerate() {
+        let volume = particle_volume(particles, j, fluid_density);
+        let value = value(j);
+        grid.foreach_point_in_radius(*position, smoothing_length, |i, r_sq| {
+            let weight = volume * kernel.evaluate(r_sq, r_sq.sqrt());
+            values[i] += value * weight;
+            color_field[i] += weight;
+        });
+    }
+
+    if normalization != Normalization::None {
+        for (value, color_field) in values.iter_mut().zip(color_field.iter()) {
+            if *color_field > 0.0 {
+                *value = normalize(*value, *color_field, normalization);
+            }
+        }
+    }
+}
+
+// Color field only, see splat_to_grid.
+pub fn splat_color_field_to_grid(fluid_world: &FluidParticleWorld, grid: &SampleGrid, color_field: &mut Vec<Real>) {
+    microprofile::scope!("interpolation", "splat_color_field_to_grid");
+
+    color_field.clear();
+    color_field.resize(grid.num_points(), 0.0);
+
+    let smoothing_length = fluid_world.properties.smoothing_length();
+    let kernel = smoothing_kernel::CubicSpline::new(smoothing_length);
+    let fluid_density = fluid_world.properties.fluid_density();
+    let particles = &fluid_world.particles;
+
+    for (j, position) in particles.positions.iter().enumerate() {
+        let volume = particle_volume(particles, j, fluid_density);
+        grid.foreach_point_in_radius(*position, smoothing_length, |i, r_sq| {
+            color_field[i] += volume * kernel.evaluate(r_sq, r_sq.sqrt());
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resting_block() -> FluidParticleWorld {
+        let mut world = FluidParticleWorld::new(2.0, NumberDensity(10000.0), Density(1000.0));
+        world.add_fluid_rect(&Rect::new(0.0, 0.0, 0.5, 0.5), 0.0);
+        for v in world.particles.velocities.iter_mut() {
+            *v = Vector::new(1.0, -2.0);
+        }
+        world.update_neighborhood_datastructure(Vec::new(), Vec::new());
+        world.update_densities(smoothing_kernel::CubicSpline::new(world.properties.smoothing_length()));
+        world
+    }
+
+    #[test]
+    fn point_queries_reproduce_fields() {
+        let world = resting_block();
+        let particles = &world.particles;
+        let center = Point::new(0.25, 0.25);
+        let edge = Point::new(0.0, 0.25);
+
+        let velocity = interpolate_vector(&world, center, &particles.velocities, Normalization::Shepard).unwrap();
+        assert_lt!((velocity - Vector::new(1.0, -2.0)).magnitude(), 1.0e-4);
+        assert_lt!((color_field(&world, center) - 1.0).abs(), 0.02);
+
+        // at the edge only Shepard normalization keeps the density
+        let density = interpolate_scalar(&world, edge, &particles.densities, Normalization::Shepard).unwrap();
+        assert_lt!((density - 1000.0).abs(), 10.0);
+        let plain_density = interpolate_scalar(&world, edge, &particles.densities, Normalization::None).unwrap();
+        assert_lt!(plain_density, 900.0);
+        assert_lt!((plain_density - color_field(&world, edge) * 1000.0).abs(), 1.0);
+
+        assert_eq!(
+            interpolate_scalar(&world, Point::new(2.0, 2.0), &particles.densities, Normalization::None),
+            None
+        );
+        assert_eq!(color_field(&world, Point::new(2.0, 2.0)), 0.0);
+    }
+
+    #[test]
+    fn splatting_matches_point_queries() {
+        let world = resting_block();
+        let grid = SampleGrid {
+            origin: Point::new(-0.1, -0.1),
+            spacing: 0.013,
+            size: (60, 55),
+        };
+        let mut velocities = Vec::new();
+        let mut color_field = Vec::new();
+        let particles = &world.particles;
+        splat_to_grid(
+            &world,
+            &grid,
+            |j| particles.velocities[j],
+            Normalization::Shepard,
+            &mut velocities,
+            &mut color_field,
+        );
+        let mut color_field_only = Vec::new();
+        splat_color_field_to_grid(&world, &grid, &mut color_field_only);
+        assert_eq!(color_field, color_field_only);
+
+        for y in 0..grid.size.1 {
+            for x in 0..grid.size.0 {
+                let i = grid.index(x, y);
+                let point = grid.point(x, y);
+                assert_lt!((color_field[i] - super::color_field(&world, point)).abs(), 1.0e-4);
+                match interpolate_vector(&world, point, &particles.velocities, Normalization::Shepard) {
+                    Some(velocity) => assert_lt!((velocities[i] - velocity).magnitude(), 1.0e-4),
+                    None => assert_eq!(velocities[i], Vector::zero()),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,273 @@
+use super::fluidparticleworld::FluidParticleWorld;
+use super::smoothing_kernel::{self, Kernel};
+use crate::units::*;
+use cgmath::prelude::*;
+
+// Flow through a line segment, per unit depth since we're in 2D.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LineFlux {
+    pub mass_flow_rate: Real,   // in kg/(m*s)
+    pub volume_flow_rate: Real, // in m²/s
+}
+
+// Line segment measuring the flow of fluid through it.
+// Flow to the right of the line direction (same side thick boundary lines grow to) is positive.
+pub struct FluxLine {
+    pub start: Point,
+    pub end: Point,
+    pub last_flux: LineFlux,
+    pub accumulated_mass: Real, // mass that went through the line over all recorded steps, in kg/m
+}
+
+impl FluxLine {
+    pub fn new(start: Point, end: Point) -> FluxLine {
+        FluxLine {
+            start,
+            end,
+            last_flux: Default::default(),
+            accumulated_mass: 0.0,
+        }
+    }
+
+    // Unit normal pointing to the positive side.
+    pub fn normal(&self) -> Vector {
+        let direction = (self.end - self.start).normalize();
+        Vector::new(direction.y, -direction.x)
+    }
+
+    // Line integral of the SPH interpolated mass & volume flux density ρv·n, resp. v·n.
+    // Unlike a Shepard-normalized interpolation, the plain SPH sum drops off at the fluid surface,
+    // so the integral over a line crossing the entire flow yields the flow of all particles crossing it.
+    pub fn measure(&self, fluid_world: &FluidParticleWorld) -> LineFlux {
+        let smoothing_length = fluid_world.properties.smoothing_length();
+        let kernel = smoothing_kernel::CubicSpline::new(smoothing_length);
+        let particles = &fluid_world.particles;
+        let normal = self.normal();
+
+        // sample with a fraction of the smoothing length, integrating with the midpoint rule
+        let length = self.start.distance(self.end);
+        let num_samples = ((length / smoothing_length * 8.0).ceil() as usize).max(1);
+        let step = (self.end - self.start) / num_samples as Real;
+        let step_length = length / num_samples as Real;
+
+        let mut flux = LineFlux::default();
+        for i in 0..num_samples {
+            let sample_position = self.start + step * (i as Real + 0.5);
+            particles.foreach_fluid_particle_in_radius(sample_position, smoothing_length, |j| {
+                let j = j as usize;
+                let r_sq = particles.positions[j].distance2(sample_position);
+                let weight = kernel.evaluate(r_sq, r_sq.sqrt()) * step_length;
+                let mass = particles.masses[j];
+                let normal_velocity = particles.velocities[j].dot(normal);
+                flux.mass_flow_rate += mass * normal_velocity * weight;
+                if let Some(&density) = particles.densities.get(j) {
+                    if density > 0.0 {
+                        flux.volume_flow_rate += mass / density * normal_velocity * weight;
+                    }
+                }
+            });
+        }
+        flux
+    }
+
+    // Measures and accumulates the mass that went through the line over a timestep of length dt.
+    // Meant to be called after every simulation step.
+    pub fn record(&mut self, fluid_world: &FluidParticleWorld, dt: Real) {
+        self.last_flux = self.measure(fluid_world);
+        self.accumulated_mass += self.last_flux.mass_flow_rate * dt;
+    }
+}
+
+// Averages over the fluid particles within a region.
+#[derive(Clone, Copy, Debug)]
+pub struct RegionAverage {
+    pub num_particles: usize,
+    pub mass: Real,       // in kg/m, total mass in the region
+    pub density: Real,    // in kg/m², average particle density
+    pub velocity: Vector, // in m/s, mass weighted average velocity (i.e. momentum / mass)
+}
+
+impl RegionAverage {
+    // Particles exactly on the left or bottom edge are inside, on the right or top edge outside.
+    pub fn measure(fluid_world: &FluidParticleWorld, region: &Rect) -> RegionAverage {
+        let particles = &fluid_world.particles;
+        let mut average = RegionAverage {
+            num_particles: 0,
+            mass: 0.0,
+            density: 0.0,
+            velocity: Vector::zero(),
+        };
+        let mut momentum = Vector::zero();
+        let mut density_sum = 0.0;
+        for (i, p) in particles.positions.iter().enumerate() {
+            if p.x < region.x || p.y < region.y || p.x >= region.x + region.w || p.y >= region.y + region.h {
+                continue;
+            }
+            average.num_particles += 1;
+            average.mass += particles.masses[i];
+            momentum += particles.velocities[i] * particles.masses[i];
+            // densities of particles added since the last step are not known yet
+            density_sum += particles.densities.get(i).cloned().unwrap_or(0.0);
+        }
+
+        if average.num_particles > 0 {
+            average.density = density_sum / average.num_particles as Real;
+            average.velocity = momentum / average.mass;
+        }
+        average
+    }
+}
+
+// Vertical line measuring the height of the free surface, e.g. to record waves.
+//
+// Integrates the SPH interpolated fluid volume fraction Σ (m / ρ₀) W along the line.
+// This yields the depth of the fluid column above the gauge's bottom, which is less noisy than looking for the highest particle.
+pub struct WaveGauge {
+    pub x: Real,
+    pub bottom: Real,           // in m, usually the tank's bottom
+    pub top: Real,              // in m, should be well above the highest expected surface
+    samples: Vec<(Real, Real)>, // simulation time in s, surface elevation in m
+}
+
+impl WaveGauge {
+    pub fn new(x: Real, bottom: Real, top: Real) -> WaveGauge {
+        WaveGauge {
+            x,
+            bottom,
+            top,
+            samples: Vec::new(),
+        }
+    }
+
+    // Recorded simulation times and surface elevations.
+    pub fn samples(&self) -> &[(Real, Real)] {
+        &self.samples
+    }
+
+    // Height of the free surface, i.e. bottom plus depth of the fluid column.
+    pub fn measure(&self, fluid_world: &FluidParticleWorld) -> Real {
+        let smoothing_length = fluid_world.properties.smoothing_length();
+        let kernel = smoothing_kernel::CubicSpline::new(smoothing_length);
+        let particles = &fluid_world.particles;
+        let rest_density = fluid_world.properties.fluid_density();
+
+        // sample with a fraction of the smoothing length, integrating with the midpoint rule
+        let length = self.top - self.bottom;
+        let num_samples = ((length / smoothing_length * 8.0).ceil() as usize).max(1);
+        let step_length = length / num_samples as Real;
+
+        let mut depth = 0.0;
+        for i in 0..num_samples {
+            let sample_position = Point::new(self.x, self.bottom + step_length * (i as Real + 0.5));
+            particles.foreach_fluid_particle_in_radius(sample_position, smoothing_length, |j| {
+                let j = j as usize;
+                let r_sq = particles.positions[j].distance2(sample_position);
+                depth += particles.masses[j] / rest_density * kernel.evaluate(r_sq, r_sq.sqrt()) * step_length;
+            });
+        }
+        self.bottom + depth
+    }
+
+    // Meant to be called after every simulation step.
+    pub fn record(&mut self, fluid_world: &FluidParticleWorld, time: Real) {
+        let elevation = self.measure(fluid_world);
+        self.samples.push((time, elevation));
+    }
+
+    // Difference between highest and lowest recorded elevation since a given time, the wave height for regular waves.
+    pub fn wave_height(&self, since: Real) -> Real {
+        let elevations = self.samples.iter().filter(|(time, _)| *time >= since).map(|(_, elevation)| *elevation);
+        let (min, max) = elevations.fold((Real::MAX, Real::MIN), |(min, max), e| (min.min(e), max.max(e)));
+        if max >= min {
+            max - min
+        } else {
+            0.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FLOW_VELOCITY: Real = 0.5;
+
+    // Resting block of fluid that is moving uniformly to the right.
+    fn uniform_flow(fluid_rect: &Rect) -> FluidParticleWorld {
+        let mut fluid_world = FluidParticleWorld::new(2.0, NumberDensity(10000.0), Density(100.0));
+        fluid_world.add_fluid_rect(fluid_rect, 0.0);
+        fluid_world.update_neighborhood_datastructure(Vec::new(), Vec::new());
+        fluid_world.update_densities(smoothing_kernel::CubicSpline::new(fluid_world.properties.smoothing_length()));
+        for v in fluid_world.particles.velocities.iter_mut() {
+            *v = Vector::new(FLOW_VELOCITY, 0.0);
+        }
+        fluid_world
+    }
+
+    #[test]
+    fn flux_of_uniform_flow() {
+        let fluid_rect = Rect::new(0.0, 0.0, 1.0, 0.5);
+        let fluid_world = uniform_flow(&fluid_rect);
+        let fluid_density = fluid_world.properties.fluid_density();
+
+        // vertical line crossing the entire flow, going up so that flow to the right is positive
+        let mut line = FluxLine::new(Point::new(0.5, -0.5), Point::new(0.5, 1.0));
+        let flux = line.measure(&fluid_world);
+        let expected_mass_flow = fluid_density * FLOW_VELOCITY * fluid_rect.h;
+        assert_lt!((flux.mass_flow_rate - expected_mass_flow).abs(), expected_mass_flow * 0.02);
+        let expected_volume_flow = FLOW_VELOCITY * fluid_rect.h;
+        // particles at the surface have lower density, overestimating their volume
+        assert_lt!((flux.volume_flow_rate - expected_volume_flow).abs(), expected_volume_flow * 0.1);
+
+        line.record(&fluid_world, 0.1);
+        line.record(&fluid_world, 0.1);
+        assert_lt!((line.accumulated_mass - flux.mass_flow_rate * 0.2).abs(), 0.0001);
+
+        // opposite direction, opposite sign
+        let reversed = FluxLine::new(line.end, line.start);
+        assert_lt!((reversed.measure(&fluid_world).mass_flow_rate + flux.mass_flow_rate).abs(), 0.0001);
+
+        // parallel to the flow, nothing goes through
+        let parallel = FluxLine::new(Point::new(0.2, 0.25), Point::new(0.8, 0.25));
+        assert_lt!(parallel.measure(&fluid_world).mass_flow_rate.abs(), 0.0001);
+    }
+
+    #[test]
+    fn wave_gauge_on_resting_fluid() {
+        let fluid_world = uniform_flow(&Rect::new(0.0, 0.0, 1.0, 0.5));
+        let spacing = fluid_world.properties.particle_radius() * 2.0;
+
+        // lowest particle row is right at the bottom, so the gauge misses about half of its volume
+        let mut gauge = WaveGauge::new(0.5, 0.0, 1.0);
+        assert_lt!((gauge.measure(&fluid_world) - (0.5 - spacing * 0.5)).abs(), spacing * 0.1);
+        assert_eq!(WaveGauge::new(2.0, 0.0, 1.0).measure(&fluid_world), 0.0);
+
+        gauge.record(&fluid_world, 0.0);
+        gauge.samples.push((0.5, 0.6));
+        gauge.samples.push((1.0, 0.4));
+        assert_eq!(gauge.samples().len(), 3);
+        assert_lt!((gauge.wave_height(0.5) - 0.2).abs(), 1.0e-5);
+        assert_eq!(gauge.wave_height(2.0), 0.0);
+    }
+
+    #[test]
+    fn region_average_of_uniform_flow() {
+        let fluid_world = uniform_flow(&Rect::new(0.0, 0.0, 1.0, 0.5));
+        let fluid_density = fluid_world.properties.fluid_density();
+
+        // interior region, away from the surface
+        let region = Rect::new(0.25, 0.1, 0.5, 0.3);
+        let average = RegionAverage::measure(&fluid_world, &region);
+        assert_gt!(average.num_particles, 0);
+        assert_lt!(
+            (average.mass - fluid_density * region.w * region.h).abs(),
+            fluid_density * region.w * region.h * 0.05
+        );
+        assert_lt!((average.density - fluid_density).abs(), fluid_density * 0.02);
+        assert_lt!((average.velocity - Vector::new(FLOW_VELOCITY, 0.0)).magnitude(), 0.0001);
+
+        let empty = RegionAverage::measure(&fluid_world, &Rect::new(2.0, 2.0, 1.0, 1.0));
+        assert_eq!(empty.num_particles, 0);
+        assert_eq!(empty.mass, 0.0);
+    }
+}
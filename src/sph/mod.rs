@@ -1,14 +1,90 @@
-pub use self::fluidparticleworld::FluidParticleWorld;
+pub use self::error::SphError;
+pub use self::fluidparticleworld::{BoundaryGeometry, BoundaryObjectForce, FluidParticleWorld, MemoryUsage, MovingBoundary};
+pub use self::fluidproperties::*;
+pub use self::forcefield::*;
+pub use self::ghostparticles::{GhostParticles, WallCondition};
+pub use self::gridexport::*;
+pub use self::imagescene::*;
+pub use self::integrator::*;
+pub use self::measurements::*;
+pub use self::obstacles::*;
+pub use self::openboundary::*;
+pub use self::particleattributes::{AttributeValue, ParticleAttribute};
+pub use self::particleexport::*;
+pub use self::periodic::*;
+pub use self::probes::*;
+pub use self::rigidbody::*;
+#[cfg(feature = "scripting")]
+pub use self::scenescript::*;
+pub use self::simulation::*;
+pub use self::simulationclock::*;
+pub use self::simulationrng::*;
+// threads can't be spawned in the browser
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::simulationthread::{ParticleSnapshot, SimulationThread};
 pub use self::solver::*;
+pub use self::statistics::*;
+pub use self::steplog::*;
+pub use self::steptimings::*;
+pub use self::svgimport::*;
+pub use self::termination::*;
+pub use self::threading::*;
 pub use self::timemanager::*;
+pub use self::trajectory::*;
+pub use self::trajectorydiff::*;
+pub use self::validation::{validate_timestep, SceneWarning};
+pub use self::velocityclamping::*;
 pub use self::viscositymodel::*;
+pub use self::volumeexport::*;
+pub use self::watchdog::*;
+pub use self::wavemaker::*;
 
 mod appendbuffer;
+mod bluenoise;
+mod error;
 mod fluidparticleworld;
+mod fluidproperties;
+mod forcefield;
+mod ghostparticles;
+mod gridexport;
+pub mod hilbert;
+mod imagescene;
+mod integrator;
+pub mod interpolation;
+mod measurements;
 pub mod morton;
 pub mod neighborhood_search;
+mod obstacles;
+mod openboundary;
+mod particleattributes;
+mod particleexport;
+mod periodic;
+mod probes;
+mod rigidbody;
+pub mod scenes;
+#[cfg(feature = "scripting")]
+mod scenescript;
 pub mod scratch_buffer;
+mod simulation;
+mod simulationclock;
+mod simulationrng;
+#[cfg(not(target_arch = "wasm32"))]
+mod simulationthread;
 pub mod smoothing_kernel;
 mod solver;
+mod statistics;
+mod steplog;
+mod steptimings;
+pub mod surface;
+mod svgimport;
+mod termination;
+mod threading;
 mod timemanager;
+mod trajectory;
+mod trajectorydiff;
+mod validation;
+mod velocityclamping;
 mod viscositymodel;
+mod volumeexport;
+mod watchdog;
+mod wavemaker;
@@ -1,5 +1,7 @@
 pub const MORTON_XBITS: u32 = 0b01010101_01010101_01010101_01010101;
 pub const MORTON_YBITS: u32 = 0b10101010_10101010_10101010_10101010;
+pub const MORTON64_XBITS: u64 = 0x5555_5555_5555_5555;
+pub const MORTON64_YBITS: u64 = 0xaaaa_aaaa_aaaa_aaaa;
 
 // Encodes two 16(!) bit numbers into a single 32bit morton code by interleaving the bits.
 //
@@ -181,6 +183,105 @@ pub fn find_bigmin(m_cur: u32, min_morton: u32, max_morton: u32) -> u32 {
     bigmin
 }
 
+// 64 bit morton codes of two 32 bit numbers.
+// Same algorithms as for 32 bit, used as cell index by the neighborhood search so that its grid can be practically unbounded.
+
+// Encodes two 32 bit numbers into a single 64bit morton code by interleaving the bits.
+// The upper and lower halves of the code are independent, so this is just two 32 bit encodes.
+#[inline]
+pub fn encode64(x: u32, y: u32) -> u64 {
+    (encode((x >> 16) as u16, (y >> 16) as u16) as u64) << 32 | encode(x as u16, y as u16) as u64
+}
+
+// Decodes x part of 64 bit 2d morton code.
+#[inline]
+pub fn decode64_x(morton: u64) -> u32 {
+    decode_x((morton >> 32) as u32) << 16 | decode_x(morton as u32)
+}
+
+// Decodes y part of 64 bit 2d morton code.
+#[inline]
+pub fn decode64_y(morton: u64) -> u32 {
+    decode_y((morton >> 32) as u32) << 16 | decode_y(morton as u32)
+}
+
+// "Insert" a 0 bit after each of the 32 low bits of x, see part_1by1
+#[inline]
+fn part_1by1_64(x: u32) -> u64 {
+    let mut x = x as u64;
+    x = (x ^ (x << 16)) & 0x0000_ffff_0000_ffff;
+    x = (x ^ (x << 8)) & 0x00ff_00ff_00ff_00ff;
+    x = (x ^ (x << 4)) & 0x0f0f_0f0f_0f0f_0f0f;
+    x = (x ^ (x << 2)) & 0x3333_3333_3333_3333;
+    x = (x ^ (x << 1)) & 0x5555_5555_5555_5555;
+    x
+}
+
+// determines if a given 64 bit morton code is within a rectangle given by morton codes
+#[allow(dead_code)]
+pub(super) fn is_in_rect64(m_cur: u64, min_morton: u64, max_morton: u64) -> bool {
+    is_in_rect_presplit64(
+        m_cur,
+        min_morton & MORTON64_XBITS,
+        min_morton & MORTON64_YBITS,
+        max_morton & MORTON64_XBITS,
+        max_morton & MORTON64_YBITS,
+    )
+}
+
+// determines if a given 64 bit morton code is within a rectangle given by pre-split morton codes
+#[inline]
+pub(super) fn is_in_rect_presplit64(m_cur: u64, min_morton_xbits: u64, min_morton_ybits: u64, max_morton_xbits: u64, max_morton_ybits: u64) -> bool {
+    let cur_x = m_cur & MORTON64_XBITS;
+    let cur_y = m_cur & MORTON64_YBITS;
+
+    cur_x >= min_morton_xbits && cur_y >= min_morton_ybits && cur_x <= max_morton_xbits && cur_y <= max_morton_ybits
+}
+
+// 64 bit version of load_bits
+fn load_bits64(pattern: u32, patternlen: u32, value: u64, dim: u32) -> u64 {
+    let wipe_mask = !(part_1by1_64(0xffff_ffff >> (32 - (patternlen / 2 + 1))) << dim); // clears affected bits
+    let pattern = part_1by1_64(pattern) << dim; // spreads pattern
+    (value & wipe_mask) | pattern
+}
+
+// 64 bit version of find_bigmin
+pub fn find_bigmin64(m_cur: u64, min_morton: u64, max_morton: u64) -> u64 {
+    let mut min_morton = min_morton;
+    let mut max_morton = max_morton;
+    let mut bigmin = 0;
+    // Bits above the highest one that differs between min and max are the same for m_cur as well and don't change anything.
+    // Neighborhood queries only cover a few cells, so this skips most of the 64 bits.
+    let num_relevant_bits = 64 - (min_morton ^ max_morton).leading_zeros();
+    for bitpos in (0..num_relevant_bits).rev() {
+        let setbit = 1 << bitpos;
+        let curbit = (m_cur & setbit) != 0;
+        let minbit = (min_morton & setbit) != 0;
+        let maxbit = (max_morton & setbit) != 0;
+
+        match (curbit, minbit, maxbit) {
+            (false, false, false) => (),
+            (false, false, true) => {
+                let dim = bitpos % 2; // dim = 0 for x; dim = 1 for y
+                let mask = 1 << (bitpos / 2);
+                bigmin = load_bits64(mask, bitpos, min_morton, dim);
+                max_morton = load_bits64(mask - 1, bitpos, max_morton, dim);
+            }
+            (false, true, false) => unsafe { std::hint::unreachable_unchecked() },
+            (false, true, true) => return min_morton,
+            (true, false, false) => return bigmin,
+            (true, false, true) => {
+                let dim = bitpos % 2; // dim = 0 for x; dim = 1 for y
+                let mask = 1 << (bitpos / 2);
+                min_morton = load_bits64(mask, bitpos, min_morton, dim)
+            }
+            (true, true, false) => unsafe { std::hint::unreachable_unchecked() },
+            (true, true, true) => (),
+        }
+    }
+    bigmin
+}
+
 #[cfg(test)]
 mod tests {
     mod encode {
@@ -250,4 +351,81 @@ mod tests {
             assert_eq!(find_bigmin(15, 12, 45), 36);
         }
     }
+
+    mod morton64 {
+        use super::super::*;
+        use rand::prelude::*;
+
+        // Straightforward bit by bit interleaving.
+        fn reference_encode(x: u32, y: u32) -> u64 {
+            (0..32).fold(0, |morton, bit| {
+                morton | ((x as u64 >> bit) & 1) << (2 * bit) | ((y as u64 >> bit) & 1) << (2 * bit + 1)
+            })
+        }
+
+        // Smallest morton code bigger than m_cur within the rectangle by trying all its cells.
+        fn reference_bigmin(m_cur: u64, min: (u32, u32), max: (u32, u32)) -> Option<u64> {
+            (min.1..=max.1)
+                .flat_map(|y| (min.0..=max.0).map(move |x| encode64(x, y)))
+                .filter(|&morton| morton > m_cur)
+                .min()
+        }
+
+        #[test]
+        fn encode_works_for_examples() {
+            assert_eq!(encode64(2, 2), 12);
+            assert_eq!(encode64(3, 6), 45);
+            assert_eq!(encode64(0xffff_ffff, 0), 0x5555_5555_5555_5555);
+            assert_eq!(encode64(0, 0xffff_ffff), 0xaaaa_aaaa_aaaa_aaaa);
+            assert_eq!(encode64(1 << 16, 1 << 31), 1 << 32 | 1 << 63);
+        }
+
+        #[test]
+        fn encode_decode_match_reference() {
+            let mut rng: rand::rngs::SmallRng = rand::SeedableRng::seed_from_u64(123456789);
+            for _ in 0..10000 {
+                let (x, y) = (rng.gen::<u32>(), rng.gen::<u32>());
+                let morton = encode64(x, y);
+                assert_eq!(morton, reference_encode(x, y));
+                assert_eq!(decode64_x(morton), x);
+                assert_eq!(decode64_y(morton), y);
+            }
+        }
+
+        #[test]
+        fn find_bigmin_jumps_to_next_pos_in_rect() {
+            // same as for 32 bit
+            assert_eq!(find_bigmin64(16, 12, 45), 36);
+            assert_eq!(find_bigmin64(29, 12, 45), 36);
+            assert_eq!(find_bigmin64(14, 12, 45), 15);
+            assert_eq!(find_bigmin64(15, 12, 45), 36);
+        }
+
+        #[test]
+        fn find_bigmin_matches_reference() {
+            let mut rng: rand::rngs::SmallRng = rand::SeedableRng::seed_from_u64(123456789);
+            for i in 0..2000 {
+                // small rectangles anywhere, also across the highest bits
+                let size = (rng.gen::<u32>() % 8, rng.gen::<u32>() % 8);
+                let min = if i % 2 == 0 {
+                    (rng.gen::<u32>().saturating_sub(size.0), rng.gen::<u32>().saturating_sub(size.1))
+                } else {
+                    ((1 << 31) - rng.gen::<u32>() % 8, (1 << 31) - rng.gen::<u32>() % 8)
+                };
+                let max = (min.0 + size.0, min.1 + size.1);
+                let (min_morton, max_morton) = (encode64(min.0, min.1), encode64(max.0, max.1));
+
+                // outside of the rectangle but between its corners on the morton curve
+                for _ in 0..10 {
+                    let m_cur = min_morton + rng.gen::<u64>() % (max_morton - min_morton + 1);
+                    if is_in_rect64(m_cur, min_morton, max_morton) {
+                        let (x, y) = (decode64_x(m_cur), decode64_y(m_cur));
+                        assert!(x >= min.0 && x <= max.0 && y >= min.1 && y <= max.1);
+                        continue;
+                    }
+                    assert_eq!(Some(find_bigmin64(m_cur, min_morton, max_morton)), reference_bigmin(m_cur, min, max));
+                }
+            }
+        }
+    }
 }
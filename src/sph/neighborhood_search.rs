@@ -7,74 +7,175 @@ use super::scratch_buffer::ScratchBufferStore;
 use crate::units::*;
 
 pub type ParticleIndex = u32;
-pub type MortonCellIndex = u32;
+pub type MortonCellIndex = u64;
 
 #[derive(Copy, Clone)]
 struct MortonCellPos {
-    x: u16,
-    y: u16,
+    x: u32,
+    y: u32,
 }
 impl MortonCellPos {
     #[inline]
     fn to_cidx(self) -> MortonCellIndex {
-        super::morton::encode(self.x, self.y)
+        super::morton::encode64(self.x, self.y)
     }
 
     #[inline]
     fn from_cidx(cidx: MortonCellIndex) -> MortonCellPos {
         MortonCellPos {
-            x: super::morton::decode_x(cidx) as u16,
-            y: super::morton::decode_y(cidx) as u16,
+            x: super::morton::decode64_x(cidx),
+            y: super::morton::decode64_y(cidx),
         }
     }
 }
 
+// Cells are counted from GRID_ORIGIN, somewhere left below of where scenes are usually built, which is cell GRID_ORIGIN_CELL in both dimensions.
+// The grid extends about 1.4 billion cells in every direction from there, so it is practically unbounded.
+// Deliberately not a power of two: There, spatially adjacent cells are far apart on the morton curve, which makes neighbor queries slower.
+// It is a multiple of 2^16 though, so the morton order of the 65536² cells from GRID_ORIGIN on is the same as without offset.
+const GRID_ORIGIN: Point = Point::new(-100.0, -100.0);
+const GRID_ORIGIN_CELL: f64 = 0x5555_0000 as f64;
+
+// Order of the cells of the neighborhood search, which is also the order particles are sorted in.
+//
+// Benchmark run 2026/10/16, "neighborhood_search cell orderings" with 20000 positions
+// Morton:  foreach_potential_neighbor 346 ns, update (warm) 13.3 ms
+// Hilbert: foreach_potential_neighbor 669 ns, update (warm) 17.2 ms
+// I.e. looking up the cells individually costs more than the better locality saves, the same goes for computing hilbert indices.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CellOrdering {
+    // Z-order curve, the cells of a neighbor box are found by jumping along the curve with cheap bit operations.
+    Morton,
+    // Better locality since the curve never jumps, but every cell of a neighbor box needs to be looked up separately.
+    Hilbert,
+}
+
+// How the particles of each cell are stored.
+//
+// Benchmark run 2026/10/16, "neighborhood_search cell storages" with 20000 positions, velocities and masses
+// Sorted:        foreach_potential_neighbor 215 ns, update (warm) 13.7 ms
+// FixedCapacity: foreach_potential_neighbor 815 ns, update (warm) 20.3 ms
+// I.e. skipping the sorting of attributes doesn't make up for going through mostly empty slots and scattered particle positions.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CellStorage {
+    // Particles and all their attributes are sorted by cell, a cell is a range of particle indices.
+    Sorted,
+    // Particles stay where they are, every cell has a fixed number of slots for particle indices, padded to full cache lines.
+    // The number of slots per cell is derived from the expected maximum number of particles per area and doubled whenever a cell overflows.
+    FixedCapacity { expected_max_density: NumberDensity },
+}
+
 #[derive(Copy, Clone)]
 struct MortonCell {
-    first_particle: usize,
+    first_particle: usize, // with CellStorage::FixedCapacity index of the cell's first slot
     cidx: MortonCellIndex,
 }
 
+const SLOTS_PER_CACHELINE: usize = 64 / std::mem::size_of::<ParticleIndex>();
+const EMPTY_SLOT: ParticleIndex = ParticleIndex::MAX;
+
+#[derive(Copy, Clone)]
+#[repr(align(64))]
+struct CacheLine([ParticleIndex; SLOTS_PER_CACHELINE]);
+
+// Particle slots of all cells with CellStorage::FixedCapacity, each cell owns lines_per_cell consecutive cache lines.
+#[derive(Default)]
+struct CellSlots {
+    lines: Vec<CacheLine>,
+    lines_per_cell: usize,
+    // Particle indices sorted by cell as of the last update. Most particles stay in their cell, so this is still almost sorted on the next.
+    sorted_particles: Vec<ParticleIndex>,
+}
+
+impl CellSlots {
+    #[inline]
+    fn as_slice(&self) -> &[ParticleIndex] {
+        unsafe { std::slice::from_raw_parts(self.lines.as_ptr() as *const ParticleIndex, self.lines.len() * SLOTS_PER_CACHELINE) }
+    }
+}
+
 // Runs of particle indices for a MortonCell and its eight neighbors.
 struct MortonCellNeihborhoodRuns {
     // In a 3x3 2D morton box there are at max 5 continous runs (can be less!)
-    particle_index_runs: [(usize, usize); 5],
-    num_runs: usize, // remove? not really needed I guess
+    // With hilbert order, the curve may enter and leave the box more often.
+    particle_index_runs: [(usize, usize); 9],
+    num_runs: usize,
 }
 
 struct GridProperties {
     radius: Real,
     cell_size_inv: Real,
-    grid_min: Point,
+    // world position of the min corner of the cell at origin_cell in both dimensions, GRID_ORIGIN & GRID_ORIGIN_CELL unless changed for testing
+    origin: Point,
+    origin_cell: f64,
+    cell_ordering: CellOrdering,
+    cell_storage: CellStorage,
 }
 impl GridProperties {
+    #[inline]
+    fn cellpos_to_cidx(&self, pos: MortonCellPos) -> MortonCellIndex {
+        match self.cell_ordering {
+            CellOrdering::Morton => pos.to_cidx(),
+            CellOrdering::Hilbert => super::hilbert::encode(pos.x, pos.y),
+        }
+    }
+
+    #[inline]
+    fn cidx_to_cellpos(&self, cidx: MortonCellIndex) -> MortonCellPos {
+        match self.cell_ordering {
+            CellOrdering::Morton => MortonCellPos::from_cidx(cidx),
+            CellOrdering::Hilbert => {
+                let (x, y) = super::hilbert::decode(cidx);
+                MortonCellPos { x, y }
+            }
+        }
+    }
+
+    // Positions outside of the grid (and NaN) end up in its border cells, float to int conversion saturates.
     #[inline]
     fn position_to_mortoncellpos(&self, position: Point) -> MortonCellPos {
-        let cellspace = (position - self.grid_min) * self.cell_size_inv;
+        let cellspace = (position - self.origin) * self.cell_size_inv;
         MortonCellPos {
-            x: cellspace.x as u16,
-            y: cellspace.y as u16,
+            x: (cellspace.x.floor() as f64 + self.origin_cell) as u32,
+            y: (cellspace.y.floor() as f64 + self.origin_cell) as u32,
         }
     }
 
     #[inline]
     fn position_to_cidx(&self, position: Point) -> MortonCellIndex {
-        self.position_to_mortoncellpos(position).to_cidx()
+        self.cellpos_to_cidx(self.position_to_mortoncellpos(position))
+    }
+
+    // World space position of a cell's min corner.
+    fn cell_min(&self, pos: MortonCellPos) -> Point {
+        let cellspace = Vector::new((pos.x as f64 - self.origin_cell) as Real, (pos.y as f64 - self.origin_cell) as Real);
+        self.origin + cellspace / self.cell_size_inv
     }
 }
 
 #[derive(Default)]
 struct CompactMortonCellGrid {
     cells: Vec<MortonCell>,
+    slots: CellSlots, // only used with CellStorage::FixedCapacity
+    num_particles: usize,
 }
 
 impl CompactMortonCellGrid {
+    // Heap memory in bytes.
+    fn memory_usage(&self) -> usize {
+        self.cells.capacity() * std::mem::size_of::<MortonCell>()
+            + self.slots.lines.capacity() * std::mem::size_of::<CacheLine>()
+            + self.slots.sorted_particles.capacity() * std::mem::size_of::<ParticleIndex>()
+    }
+
     fn apply_sorting<T: Copy>(sorting: &[ParticleIndex], scratch_buffer: &mut Vec<T>, buffer_to_sort: &mut Vec<T>) {
         assert_eq!(scratch_buffer.len(), buffer_to_sort.len());
         assert_eq!(sorting.len(), buffer_to_sort.len());
         for (pos, &i) in scratch_buffer.iter_mut().zip(sorting.iter()) {
             *pos = buffer_to_sort[i as usize];
         }
+        // keep capacity reserved via FluidParticleWorld::reserve with the attribute
+        scratch_buffer.reserve_exact(buffer_to_sort.capacity() - scratch_buffer.len());
         std::mem::swap(scratch_buffer, buffer_to_sort);
     }
 
@@ -87,11 +188,18 @@ impl CompactMortonCellGrid {
         positions: &mut Vec<Point>,
         particle_attributes_vector: &mut [&mut Vec<Vector>],
         particle_attributes_real: &mut [&mut Vec<Real>],
+        particle_attributes_uint: &mut [&mut Vec<u32>],
     ) {
         microprofile::scope!("NeighborhoodSearch", "CompactMortonCellGrid::update");
 
+        self.num_particles = positions.len();
+        if let CellStorage::FixedCapacity { expected_max_density } = grid.cell_storage {
+            self.update_slots(scratch_buffers, grid, positions, expected_max_density);
+            return;
+        }
+
         let particle_indices = &mut scratch_buffers.get_buffer_uint(positions.len());
-        let cell_indices = &mut scratch_buffers.get_buffer_uint(positions.len());
+        let cell_indices = &mut scratch_buffers.get_buffer_u64(positions.len());
 
         // we know that most particles have not changed since last frame
         // -> use insertion sort and building permutation array into it as well!
@@ -133,6 +241,12 @@ impl CompactMortonCellGrid {
                     Self::apply_sorting(&particle_indices.buffer, &mut scratch_buffer.buffer, *attribute_buffer);
                 }
             }
+            {
+                let mut scratch_buffer = scratch_buffers.get_buffer_uint(positions.len());
+                for attribute_buffer in particle_attributes_uint.iter_mut() {
+                    Self::apply_sorting(&particle_indices.buffer, &mut scratch_buffer.buffer, *attribute_buffer);
+                }
+            }
         }
 
         // create cells.
@@ -151,6 +265,103 @@ impl CompactMortonCellGrid {
         }); // sentinel cell
     }
 
+    // Sorts particle indices instead of particles into cells, leaving all particle attributes untouched.
+    fn update_slots(
+        &mut self,
+        scratch_buffers: &mut ScratchBufferStore,
+        grid: &GridProperties,
+        positions: &[Point],
+        expected_max_density: NumberDensity,
+    ) {
+        microprofile::scope!("NeighborhoodSearch", "CompactMortonCellGrid::update_slots");
+
+        let slots = &mut self.slots;
+        if slots.lines_per_cell == 0 {
+            let cell_size = 1.0 / grid.cell_size_inv;
+            let num_expected_in_cell = (cell_size * cell_size * expected_max_density.0).ceil() as usize;
+            slots.lines_per_cell = num_expected_in_cell.div_ceil(SLOTS_PER_CACHELINE).max(1);
+        }
+        if slots.sorted_particles.len() != positions.len() {
+            slots.sorted_particles.clear();
+            slots.sorted_particles.extend(0..positions.len() as ParticleIndex);
+        }
+
+        // same insertion sort as for sorted storage
+        let cell_indices = &mut scratch_buffers.get_buffer_u64(positions.len());
+        for i in 0..positions.len() {
+            cell_indices.buffer[i] = grid.position_to_cidx(positions[slots.sorted_particles[i] as usize]);
+            for j in (0..i).rev() {
+                if cell_indices.buffer[j] > cell_indices.buffer[j + 1] {
+                    cell_indices.buffer.swap(j, j + 1);
+                    slots.sorted_particles.swap(j, j + 1);
+                } else {
+                    break;
+                }
+            }
+        }
+
+        while !self.try_fill_slots(&cell_indices.buffer) {
+            let new_lines_per_cell = self.slots.lines_per_cell * 2;
+            println!(
+                "Neighborhood search cell capacity was too small. Was {} particles, trying again with {}",
+                self.slots.lines_per_cell * SLOTS_PER_CACHELINE,
+                new_lines_per_cell * SLOTS_PER_CACHELINE
+            );
+            self.slots.lines_per_cell = new_lines_per_cell;
+        }
+    }
+
+    // Creates cells and fills their slots from particles sorted by cell. Fails if a cell has more particles than slots.
+    fn try_fill_slots(&mut self, sorted_cell_indices: &[MortonCellIndex]) -> bool {
+        let slots_per_cell = self.slots.lines_per_cell * SLOTS_PER_CACHELINE;
+        let num_cells = sorted_cell_indices.windows(2).filter(|pair| pair[0] != pair[1]).count() + (!sorted_cell_indices.is_empty()) as usize;
+        self.slots.lines.clear();
+        self.slots
+            .lines
+            .resize(num_cells * self.slots.lines_per_cell, CacheLine([EMPTY_SLOT; SLOTS_PER_CACHELINE]));
+
+        self.cells.clear();
+        let mut num_in_cell = 0;
+        for (&cidx, &pidx) in sorted_cell_indices.iter().zip(self.slots.sorted_particles.iter()) {
+            if self.cells.last().map(|cell| cell.cidx) != Some(cidx) {
+                self.cells.push(MortonCell {
+                    first_particle: self.cells.len() * slots_per_cell,
+                    cidx,
+                });
+                num_in_cell = 0;
+            } else if num_in_cell == slots_per_cell {
+                return false;
+            }
+            let slot = self.cells.last().unwrap().first_particle + num_in_cell;
+            self.slots.lines[slot / SLOTS_PER_CACHELINE].0[slot % SLOTS_PER_CACHELINE] = pidx;
+            num_in_cell += 1;
+        }
+        self.cells.push(MortonCell {
+            first_particle: num_cells * slots_per_cell,
+            cidx: MortonCellIndex::MAX,
+        }); // sentinel cell
+        true
+    }
+
+    // Calls f for every particle in a range of entries, i.e. of particle indices with sorted storage and of slots with fixed capacity storage.
+    #[inline]
+    fn foreach_particle_in_entries(&self, grid: &GridProperties, entries: (usize, usize), mut f: impl FnMut(usize)) {
+        match grid.cell_storage {
+            CellStorage::Sorted => {
+                for j in entries.0..entries.1 {
+                    f(j);
+                }
+            }
+            CellStorage::FixedCapacity { .. } => {
+                for &j in unsafe { self.slots.as_slice().get_unchecked(entries.0..entries.1) } {
+                    if j != EMPTY_SLOT {
+                        f(j as usize);
+                    }
+                }
+            }
+        }
+    }
+
     // finds cell array index first cell that has an equal or bigger for a given MortonCellIndex
     fn find_next_cell(cells: &[MortonCell], cidx: MortonCellIndex) -> usize {
         const LINEAR_SEARCH_THRESHHOLD: usize = 16;
@@ -174,42 +385,123 @@ impl CompactMortonCellGrid {
         max
     }
 
-    fn get_particle_runs_in_neighborbox(&self, cidx: MortonCellIndex) -> MortonCellNeihborhoodRuns {
+    #[inline]
+    fn get_particle_runs_in_neighborbox(&self, grid: &GridProperties, cidx: MortonCellIndex) -> MortonCellNeihborhoodRuns {
+        self.get_particle_runs_in_neighborbox_tracked(grid, cidx, |_| {})
+    }
+
+    // on_cell_visited is called for every cell that is looked at, regardless of whether it is part of the neighborbox. For debugging.
+    #[inline]
+    fn get_particle_runs_in_neighborbox_tracked(
+        &self,
+        grid: &GridProperties,
+        cidx: MortonCellIndex,
+        on_cell_visited: impl FnMut(MortonCellIndex),
+    ) -> MortonCellNeihborhoodRuns {
+        match grid.cell_ordering {
+            CellOrdering::Morton => self.get_particle_runs_in_neighborbox_morton(cidx, on_cell_visited),
+            CellOrdering::Hilbert => self.get_particle_runs_in_neighborbox_hilbert(cidx, on_cell_visited),
+        }
+    }
+
+    // Looks up each of the (up to) nine cells individually, in order of the curve so that runs of cells that are next to each other can be merged.
+    fn get_particle_runs_in_neighborbox_hilbert(
+        &self,
+        cidx: MortonCellIndex,
+        mut on_cell_visited: impl FnMut(MortonCellIndex),
+    ) -> MortonCellNeihborhoodRuns {
+        let (x, y) = super::hilbert::decode(cidx);
+        let mut box_cidx = [MortonCellIndex::MAX; 9];
+        let mut num_box_cells = 0;
+        super::hilbert::encode_rect(
+            x.saturating_sub(1),
+            y.saturating_sub(1),
+            x.saturating_add(1),
+            y.saturating_add(1),
+            |_, _, neighbor_cidx| {
+                box_cidx[num_box_cells] = neighbor_cidx;
+                num_box_cells += 1;
+            },
+        );
+        let box_cidx = &mut box_cidx[..num_box_cells];
+        box_cidx.sort_unstable();
+
+        let mut runs = MortonCellNeihborhoodRuns {
+            particle_index_runs: [(0, 0); 9],
+            num_runs: 0,
+        };
+        let mut cell_arrayidx = 0;
+        let mut previous_found_arrayidx = usize::MAX;
+        for &neighbor_cidx in box_cidx.iter() {
+            cell_arrayidx += Self::find_next_cell(&self.cells[cell_arrayidx..], neighbor_cidx);
+            let cell = self.cells[cell_arrayidx];
+            on_cell_visited(cell.cidx);
+            if cell.cidx != neighbor_cidx {
+                continue;
+            }
+            let particles_end = self.cells[cell_arrayidx + 1].first_particle;
+            if runs.num_runs > 0 && previous_found_arrayidx + 1 == cell_arrayidx {
+                runs.particle_index_runs[runs.num_runs - 1].1 = particles_end;
+            } else {
+                runs.particle_index_runs[runs.num_runs] = (cell.first_particle, particles_end);
+                runs.num_runs += 1;
+            }
+            previous_found_arrayidx = cell_arrayidx;
+        }
+        runs
+    }
+
+    fn get_particle_runs_in_neighborbox_morton(
+        &self,
+        cidx: MortonCellIndex,
+        mut on_cell_visited: impl FnMut(MortonCellIndex),
+    ) -> MortonCellNeihborhoodRuns {
         let pos = MortonCellPos::from_cidx(cidx);
-        let cidx_min = MortonCellPos { x: pos.x - 1, y: pos.y - 1 }.to_cidx();
-        let cidx_max = MortonCellPos { x: pos.x + 1, y: pos.y + 1 }.to_cidx();
+        // Saturate so the box is cut off at the edges of the grid instead of wrapping around (or overflowing) there.
+        let cidx_min = MortonCellPos {
+            x: pos.x.saturating_sub(1),
+            y: pos.y.saturating_sub(1),
+        }
+        .to_cidx();
+        let cidx_max = MortonCellPos {
+            x: pos.x.saturating_add(1),
+            y: pos.y.saturating_add(1),
+        }
+        .to_cidx();
 
-        let cidx_min_xbits = cidx_min & super::morton::MORTON_XBITS;
-        let cidx_min_ybits = cidx_min & super::morton::MORTON_YBITS;
-        let cidx_max_xbits = cidx_max & super::morton::MORTON_XBITS;
-        let cidx_max_ybits = cidx_max & super::morton::MORTON_YBITS;
+        let cidx_min_xbits = cidx_min & super::morton::MORTON64_XBITS;
+        let cidx_min_ybits = cidx_min & super::morton::MORTON64_YBITS;
+        let cidx_max_xbits = cidx_max & super::morton::MORTON64_XBITS;
+        let cidx_max_ybits = cidx_max & super::morton::MORTON64_YBITS;
 
         const MAX_CONSECUTIVE_CELL_MISSES: u32 = 8;
 
         // Note: Already tried doing this with iterators. it's hard to do and slow!
         let mut cell_arrayidx = Self::find_next_cell(&self.cells, cidx_min);
         let mut cell = self.cells[cell_arrayidx];
+        on_cell_visited(cell.cidx);
 
         let mut runs = MortonCellNeihborhoodRuns {
-            particle_index_runs: [(0, 0); 5],
+            particle_index_runs: [(0, 0); 9],
             num_runs: 0,
         };
 
         while cell.cidx <= cidx_max {
             // skip until cell is in rect
             let mut num_misses = 0;
-            while !super::morton::is_in_rect_presplit(cell.cidx, cidx_min_xbits, cidx_min_ybits, cidx_max_xbits, cidx_max_ybits) {
+            while !super::morton::is_in_rect_presplit64(cell.cidx, cidx_min_xbits, cidx_min_ybits, cidx_max_xbits, cidx_max_ybits) {
                 num_misses += 1;
 
                 // Try next. Prefer to just grind the array, but at some point use bigmin to jump ahead.
                 if num_misses > MAX_CONSECUTIVE_CELL_MISSES {
-                    let expected_next_cidx = super::morton::find_bigmin(cell.cidx, cidx_min, cidx_max);
+                    let expected_next_cidx = super::morton::find_bigmin64(cell.cidx, cidx_min, cidx_max);
                     cell_arrayidx += Self::find_next_cell(&self.cells[cell_arrayidx..], expected_next_cidx);
                     assert!(expected_next_cidx > cell.cidx);
                 } else {
                     cell_arrayidx += 1;
                 }
                 cell = self.cells[cell_arrayidx];
+                on_cell_visited(cell.cidx);
 
                 if cell.cidx > cidx_max {
                     return runs;
@@ -221,14 +513,15 @@ impl CompactMortonCellGrid {
             loop {
                 cell_arrayidx += 1; // we won't be here for long, no point in doing profound skipping.
                 cell = self.cells[cell_arrayidx];
-                if !super::morton::is_in_rect_presplit(cell.cidx, cidx_min_xbits, cidx_min_ybits, cidx_max_xbits, cidx_max_ybits) {
+                on_cell_visited(cell.cidx);
+                if !super::morton::is_in_rect_presplit64(cell.cidx, cidx_min_xbits, cidx_min_ybits, cidx_max_xbits, cidx_max_ybits) {
                     break;
                 }
             }
             runs.particle_index_runs[runs.num_runs].1 = cell.first_particle;
             runs.num_runs += 1;
-            if runs.num_runs == runs.particle_index_runs.len() {
-                break;
+            if runs.num_runs == 5 {
+                break; // can't be more for morton order
             }
 
             assert_ne!(cell.cidx, cidx_max); // it if was equal, then there would be a cell at cidx_max that is not in the rect limited by cidx_max
@@ -239,18 +532,73 @@ impl CompactMortonCellGrid {
                 break;
             }
             cell = self.cells[cell_arrayidx];
+            on_cell_visited(cell.cidx);
         }
 
         runs
     }
 
+    // Calls f for all particles in cells that overlap a given world space rectangle.
+    pub fn foreach_particle_in_rect(&self, grid: &GridProperties, min: Point, max: Point, mut f: impl FnMut(usize)) {
+        if self.cells.is_empty() {
+            return;
+        }
+        if grid.cell_ordering == CellOrdering::Hilbert {
+            let min = grid.position_to_mortoncellpos(min);
+            let max = grid.position_to_mortoncellpos(max);
+            let mut particles_in_cell = |cell_arrayidx: usize| {
+                let entries = (self.cells[cell_arrayidx].first_particle, self.cells[cell_arrayidx + 1].first_particle);
+                self.foreach_particle_in_entries(grid, entries, &mut f);
+            };
+            // look up every cell of the rectangle unless there are fewer non-empty cells in total (last cell is a sentinel)
+            let num_rect_cells = ((max.x - min.x) as u64 + 1) * ((max.y - min.y) as u64 + 1);
+            if num_rect_cells < self.cells.len() as u64 {
+                super::hilbert::encode_rect(min.x, min.y, max.x, max.y, |_, _, cidx| {
+                    let cell_arrayidx = Self::find_next_cell(&self.cells, cidx);
+                    if self.cells[cell_arrayidx].cidx == cidx {
+                        particles_in_cell(cell_arrayidx);
+                    }
+                });
+            } else {
+                for cell_arrayidx in 0..self.cells.len() - 1 {
+                    let (x, y) = super::hilbert::decode(self.cells[cell_arrayidx].cidx);
+                    if x >= min.x && y >= min.y && x <= max.x && y <= max.y {
+                        particles_in_cell(cell_arrayidx);
+                    }
+                }
+            }
+            return;
+        }
+
+        let cidx_min = grid.position_to_mortoncellpos(min).to_cidx();
+        let cidx_max = grid.position_to_mortoncellpos(max).to_cidx();
+        let cidx_min_xbits = cidx_min & super::morton::MORTON64_XBITS;
+        let cidx_min_ybits = cidx_min & super::morton::MORTON64_YBITS;
+        let cidx_max_xbits = cidx_max & super::morton::MORTON64_XBITS;
+        let cidx_max_ybits = cidx_max & super::morton::MORTON64_YBITS;
+
+        // last cell is a sentinel
+        let mut cell_arrayidx = Self::find_next_cell(&self.cells, cidx_min);
+        while cell_arrayidx < self.cells.len() - 1 {
+            let cell = self.cells[cell_arrayidx];
+            if cell.cidx > cidx_max {
+                break;
+            }
+            if super::morton::is_in_rect_presplit64(cell.cidx, cidx_min_xbits, cidx_min_ybits, cidx_max_xbits, cidx_max_ybits) {
+                self.foreach_particle_in_entries(grid, (cell.first_particle, self.cells[cell_arrayidx + 1].first_particle), &mut f);
+                cell_arrayidx += 1;
+            } else {
+                let next_cidx = super::morton::find_bigmin64(cell.cidx, cidx_min, cidx_max);
+                cell_arrayidx += Self::find_next_cell(&self.cells[cell_arrayidx..], next_cidx);
+            }
+        }
+    }
+
     // todo: remove, impl already no longer optimal
     pub fn foreach_potential_neighbor(&self, grid: &GridProperties, position: Point, mut f: impl FnMut(usize) -> ()) {
-        let runs = self.get_particle_runs_in_neighborbox(grid.position_to_cidx(position));
-        for range in runs.particle_index_runs.iter() {
-            for j in range.0..range.1 {
-                f(j);
-            }
+        let runs = self.get_particle_runs_in_neighborbox(grid, grid.position_to_cidx(position));
+        for range in runs.particle_index_runs[..runs.num_runs].iter() {
+            self.foreach_particle_in_entries(grid, *range, &mut f);
         }
     }
 }
@@ -264,6 +612,8 @@ struct NeighborListRanges {
 unsafe impl Sync for NeighborListRanges {}
 unsafe impl Send for NeighborListRanges {}
 
+const MAX_NUM_NEIGHBORS: usize = 64; // todo: At least pretend to be scientific about this value.
+
 pub struct NeighborLists {
     neighborhood_list_ranges: NeighborListRanges,
     neighborhood_lists: AppendBuffer<ParticleIndex>,
@@ -289,8 +639,6 @@ impl NeighborLists {
     ) -> Result<usize, usize> {
         microprofile::scope!("NeighborhoodSearch", "NeighborLists::try_update");
 
-        const MAX_NUM_NEIGHBORS: usize = 64; // todo: At least pretend to be scientific about this value.
-
         unsafe {
             (*self.neighborhood_list_ranges.list.get()).resize(positions.len() + 1, (0, 0));
         }
@@ -306,17 +654,20 @@ impl NeighborLists {
             let mut neighbor_set = [0; MAX_NUM_NEIGHBORS];
 
             // set of all potential neighbors
-            let particle_runs = neighbor_cell_grid.get_particle_runs_in_neighborbox(current_cell.cidx);
+            let particle_runs = neighbor_cell_grid.get_particle_runs_in_neighborbox(grid, current_cell.cidx);
 
             // for each particle in this cell...
-            for i in current_cell.first_particle..next_cell.first_particle {
+            cell_grid.foreach_particle_in_entries(grid, (current_cell.first_particle, next_cell.first_particle), |i| {
                 let posi = unsafe { *positions.get_unchecked(i) };
 
                 // gather real neighbors
                 const MIN_DISTANCE: Real = 1.0e-10; // used to filter for degenerated cases & self intersect
                 let mut num_neighbors = 0;
-                'neighbor_search: for range in particle_runs.particle_index_runs.iter() {
-                    for j in range.0..range.1 {
+                for range in particle_runs.particle_index_runs[..particle_runs.num_runs].iter() {
+                    neighbor_cell_grid.foreach_particle_in_entries(grid, *range, |j| {
+                        if num_neighbors == MAX_NUM_NEIGHBORS {
+                            return;
+                        }
                         let posj = unsafe { *neighbor_positions.get_unchecked(j) };
                         let distsq = posi.distance2(posj);
                         if distsq <= radius_sq && distsq > MIN_DISTANCE {
@@ -324,10 +675,9 @@ impl NeighborLists {
                             num_neighbors += 1;
                             if num_neighbors == MAX_NUM_NEIGHBORS {
                                 println!("particle has too many neighbors");
-                                break 'neighbor_search;
                             }
                         }
-                    }
+                    });
                 }
 
                 // save neighbors
@@ -337,7 +687,7 @@ impl NeighborLists {
                     *(&mut *self.neighborhood_list_ranges.list.get()).get_unchecked_mut(i) =
                         (neighborhood_list_offset as u32, (neighborhood_list_offset + num_neighbors) as u32);
                 }
-            }
+            });
         });
 
         Ok(self.neighborhood_lists.len())
@@ -352,7 +702,7 @@ impl NeighborLists {
         neighbor_cell_grid: &CompactMortonCellGrid,
     ) {
         microprofile::scope!("NeighborhoodSearch", "NeighborLists::update");
-        assert_eq!(cell_grid.cells.last().unwrap().first_particle, positions.len());
+        assert_eq!(cell_grid.num_particles, positions.len());
 
         while self
             .try_update(grid, positions, cell_grid, neighbor_positions, neighbor_cell_grid)
@@ -368,6 +718,26 @@ impl NeighborLists {
         }
     }
 
+    fn reserve(&mut self, num_particles: usize) {
+        let ranges = self.neighborhood_list_ranges.list.get_mut();
+        ranges.reserve((num_particles + 1).saturating_sub(ranges.len()));
+        self.neighborhood_lists.resize(num_particles * MAX_NUM_NEIGHBORS);
+    }
+
+    // Heap memory in bytes.
+    fn memory_usage(&self) -> usize {
+        let ranges = unsafe { &*self.neighborhood_list_ranges.list.get() };
+        ranges.capacity() * std::mem::size_of::<(u32, u32)>() + self.neighborhood_lists.capacity() * std::mem::size_of::<ParticleIndex>()
+    }
+
+    // Resets to an empty neighbor list for every particle.
+    fn clear(&mut self, num_particles: usize) {
+        let ranges = self.neighborhood_list_ranges.list.get_mut();
+        ranges.clear();
+        ranges.resize(num_particles + 1, (0, 0));
+        self.neighborhood_lists.clear();
+    }
+
     #[inline]
     pub fn foreach_neighbor(&self, particle: ParticleIndex, mut f: impl FnMut(ParticleIndex) -> ()) {
         unsafe {
@@ -401,23 +771,24 @@ pub struct NeighborhoodSearch {
 }
 
 impl NeighborhoodSearch {
-    /// * radius:               Radius that determines if a point is a neighbor
-    /// * expected_max_density: Num particles expected per square unit
-    pub fn new(radius: Real, //    , expected_max_density: Real
-    ) -> NeighborhoodSearch {
-        let cell_size = radius;
+    // Neighborhood search with sorted cell storage.
+    // radius determines if a point is a neighbor.
+    pub fn new(radius: Real) -> NeighborhoodSearch {
+        Self::new_with_cell_storage(radius, CellStorage::Sorted)
+    }
 
-        //const particle_INDICES.buffer_PER_CACHELINE: u32 = 64 / std::mem::size_of::<ParticleIndex>() as u32;
-        //let mut num_expected_in_cell = (cell_size * cell_size * expected_max_density + 0.5) as u32;
-        //num_expected_in_cell = (num_expected_in_cell + particle_INDICES.buffer_PER_CACHELINE-1) / particle_INDICES.buffer_PER_CACHELINE * particle_INDICES.buffer_PER_CACHELINE;
+    // See CellStorage.
+    pub fn new_with_cell_storage(radius: Real, cell_storage: CellStorage) -> NeighborhoodSearch {
+        let cell_size = radius;
 
         NeighborhoodSearch {
             grid: GridProperties {
                 radius,
                 cell_size_inv: 1.0 / cell_size,
-                // todo: we can create a huge domain, but still there is a limited domain! should be safe about this and have a max
-                // limit is there because our morton curve wraps around at some point and then things get complicated (aka don't want to deal with this!)
-                grid_min: Point::new(-100.0, -100.0),
+                origin: GRID_ORIGIN,
+                origin_cell: GRID_ORIGIN_CELL,
+                cell_ordering: CellOrdering::Morton,
+                cell_storage,
             },
 
             cellgrid_particles: Default::default(),
@@ -428,10 +799,49 @@ impl NeighborhoodSearch {
         }
     }
 
+    // Orders cells along a hilbert instead of a morton curve, see CellOrdering.
+    // Takes effect with the next update, which sorts particles anew.
+    pub fn with_cell_ordering(mut self, cell_ordering: CellOrdering) -> NeighborhoodSearch {
+        self.grid.cell_ordering = cell_ordering;
+        self
+    }
+
+    // Pre-sizes cells and neighbor lists for the given total numbers of particles, so that updates up to these counts don't reallocate.
+    // Reserves for the worst case of one cell per particle.
+    pub fn reserve(&mut self, num_particles: usize, num_boundary_particles: usize) {
+        for (cells, num_cells) in [
+            (&mut self.cellgrid_particles.cells, num_particles + 1),
+            (&mut self.cellgrid_boundary.cells, num_boundary_particles + 1),
+        ]
+        .iter_mut()
+        {
+            cells.reserve(num_cells.saturating_sub(cells.len()));
+        }
+        self.particle_particle_neighbors.reserve(num_particles);
+        self.particle_boundary_neighbors.reserve(num_particles);
+    }
+
+    // Heap memory of the neighbor lists of particles and particle-boundary pairs in bytes.
+    pub fn neighbor_lists_memory_usage(&self) -> usize {
+        self.particle_particle_neighbors.memory_usage() + self.particle_boundary_neighbors.memory_usage()
+    }
+
+    // Heap memory of the cell grids of particles and boundary particles in bytes.
+    pub fn grid_memory_usage(&self) -> usize {
+        self.cellgrid_particles.memory_usage() + self.cellgrid_boundary.memory_usage()
+    }
+
     // todo: allow boundaries to have properties
-    pub fn update_boundary(&mut self, scratch_buffers: &mut ScratchBufferStore, positions: &mut Vec<Point>) {
+    pub fn update_boundary(
+        &mut self,
+        scratch_buffers: &mut ScratchBufferStore,
+        positions: &mut Vec<Point>,
+        attributes_vector: &mut [&mut Vec<Vector>],
+        attributes_uint: &mut [&mut Vec<u32>],
+    ) {
         microprofile::scope!("NeighborhoodSearch", "update_boundary");
-        self.cellgrid_boundary.update(scratch_buffers, &self.grid, positions, &mut [], &mut []);
+        self.cellgrid_boundary
+            .update(scratch_buffers, &self.grid, positions, attributes_vector, &mut [], attributes_uint);
     }
 
     pub fn update_particle_neighbors(
@@ -440,6 +850,7 @@ impl NeighborhoodSearch {
         particle_positions: &mut Vec<Point>,
         particle_attributes_vector: &mut [&mut Vec<Vector>],
         particle_attributes_real: &mut [&mut Vec<Real>],
+        particle_attributes_uint: &mut [&mut Vec<u32>],
         boundary_positions: &[Point],
     ) {
         microprofile::scope!("NeighborhoodSearch", "update_particle_neighbors");
@@ -449,6 +860,7 @@ impl NeighborhoodSearch {
             particle_positions,
             particle_attributes_vector,
             particle_attributes_real,
+            particle_attributes_uint,
         );
         self.particle_particle_neighbors.update(
             &self.grid,
@@ -465,6 +877,9 @@ impl NeighborhoodSearch {
                 boundary_positions,
                 &self.cellgrid_boundary,
             );
+        } else {
+            // otherwise boundary neighbor lists would refer to a previous particle set
+            self.particle_boundary_neighbors.clear(particle_positions.len());
         }
     }
 
@@ -495,6 +910,129 @@ impl NeighborhoodSearch {
     pub fn foreach_potential_boundary_neighbor(&self, position: Point, f: impl FnMut(usize) -> ()) {
         self.cellgrid_boundary.foreach_potential_neighbor(&self.grid, position, f)
     }
+
+    // Like foreach_potential_neighbor but for an arbitrary radius which may be bigger than the search radius.
+    pub fn foreach_potential_neighbor_in_radius(&self, position: Point, radius: Real, f: impl FnMut(usize)) {
+        let extent = Vector::new(radius, radius);
+        self.cellgrid_particles
+            .foreach_particle_in_rect(&self.grid, position - extent, position + extent, f)
+    }
+
+    // Like foreach_potential_boundary_neighbor but for an arbitrary radius which may be bigger than the search radius.
+    pub fn foreach_potential_boundary_neighbor_in_radius(&self, position: Point, radius: Real, f: impl FnMut(usize)) {
+        let extent = Vector::new(radius, radius);
+        self.cellgrid_boundary
+            .foreach_particle_in_rect(&self.grid, position - extent, position + extent, f)
+    }
+
+    // Calls f for every particle within a radius around an arbitrary point, which doesn't need to be a particle itself.
+    // `positions` are the particle positions as sorted by the last update_particle_neighbors, particles beyond their length are skipped.
+    // Uses the search radius unless overridden, radii up to the search radius only need to look at the 3x3 cells around the point.
+    pub fn query_neighbors(&self, positions: &[Point], point: Point, radius_override: Option<Real>, mut f: impl FnMut(usize)) {
+        if self.cellgrid_particles.cells.is_empty() {
+            return;
+        }
+        let radius = radius_override.unwrap_or(self.grid.radius);
+        let radius_sq = radius * radius;
+        let filter = |j: usize| {
+            if let Some(position) = positions.get(j) {
+                if position.distance2(point) <= radius_sq {
+                    f(j);
+                }
+            }
+        };
+        if radius <= self.grid.radius {
+            self.foreach_potential_neighbor(point, filter);
+        } else {
+            self.foreach_potential_neighbor_in_radius(point, radius, filter);
+        }
+    }
+
+    // The k particles closest to an arbitrary point, nearest first (ties by index). Fewer if there are less than k particles.
+    // `positions` as for query_neighbors. Searches within the search radius first and doubles it until k particles are found,
+    // so this is fast for a k of about the number of neighbors a particle has, but scans all particles for points far away from any.
+    pub fn query_k_nearest_neighbors(&self, positions: &[Point], point: Point, k: usize) -> Vec<usize> {
+        let mut candidates: Vec<(Real, usize)> = Vec::new();
+        if k == 0 || !(point.x.is_finite() && point.y.is_finite()) {
+            return Vec::new();
+        }
+        let mut radius = self.grid.radius;
+        for _ in 0..16 {
+            candidates.clear();
+            self.query_neighbors(positions, point, Some(radius), |j| candidates.push((positions[j].distance2(point), j)));
+            if candidates.len() >= k {
+                break;
+            }
+            radius *= 2.0;
+        }
+        // fewer than k particles or all far away, rather than doubling on until the radius overflows, take all particles
+        if candidates.len() < k {
+            let num_particles = self.num_particles().min(positions.len());
+            candidates.clear();
+            candidates.extend(
+                positions[..num_particles]
+                    .iter()
+                    .enumerate()
+                    .map(|(j, position)| (position.distance2(point), j))
+                    .filter(|(distance2, _)| !distance2.is_nan()),
+            );
+        }
+        candidates.sort_unstable_by(|a, b| a.0.partial_cmp(&b.0).unwrap().then(a.1.cmp(&b.1)));
+        candidates.iter().take(k).map(|(_, j)| *j).collect()
+    }
+
+    // Number of particles known since the last call to update_particle_neighbors.
+    pub fn num_particles(&self) -> usize {
+        self.cellgrid_particles.num_particles
+    }
+
+    // World space bounds (min, max) of the cells of the grid. Positions outside end up in its border cells, where neighbors are missed.
+    pub fn grid_domain(&self) -> (Point, Point) {
+        let cell_size = 1.0 / self.grid.cell_size_inv as f64;
+        let min = |origin: Real| (origin as f64 - self.grid.origin_cell * cell_size) as Real;
+        let max = |origin: Real| (origin as f64 + (u32::MAX as f64 + 1.0 - self.grid.origin_cell) * cell_size) as Real;
+        let origin = self.grid.origin;
+        (Point::new(min(origin.x), min(origin.y)), Point::new(max(origin.x), max(origin.y)))
+    }
+
+    // Grid cell a position falls into.
+    pub fn cell_index(&self, position: Point) -> MortonCellIndex {
+        self.grid.position_to_cidx(position)
+    }
+
+    // World space bounds (min, max) of a grid cell.
+    pub fn cell_bounds(&self, cidx: MortonCellIndex) -> (Point, Point) {
+        let cell_size = 1.0 / self.grid.cell_size_inv;
+        let min = self.grid.cell_min(self.grid.cidx_to_cellpos(cidx));
+        (min, min + Vector::new(cell_size, cell_size))
+    }
+
+    // Calls f for every grid cell that contains fluid particles, with the indices of the particles in that cell.
+    // For debugging & visualization.
+    pub fn foreach_particle_cell(&self, mut f: impl FnMut(MortonCellIndex, &[usize])) {
+        let mut particles = Vec::new();
+        for cells in self.cellgrid_particles.cells.windows(2) {
+            particles.clear();
+            self.cellgrid_particles
+                .foreach_particle_in_entries(&self.grid, (cells[0].first_particle, cells[1].first_particle), |i| particles.push(i));
+            f(cells[0].cidx, &particles);
+        }
+    }
+
+    // Calls f for every cell that foreach_potential_neighbor looks at for a query at the given position, in the order they are visited.
+    // This includes cells outside of the 3x3 neighbor box that are skipped over, so it's a good measure for the cost of a query.
+    // For debugging & profiling.
+    pub fn foreach_cell_visited_by_potential_neighbor_query(&self, position: Point, mut f: impl FnMut(MortonCellIndex)) {
+        if self.cellgrid_particles.cells.is_empty() {
+            return;
+        }
+        self.cellgrid_particles
+            .get_particle_runs_in_neighborbox_tracked(&self.grid, self.grid.position_to_cidx(position), |cidx| {
+                if cidx != MortonCellIndex::MAX {
+                    f(cidx)
+                }
+            });
+    }
 }
 
 #[cfg(test)]
@@ -502,6 +1040,254 @@ mod tests {
     use super::*;
     use rand::prelude::*;
 
+    const CELL_ORDERINGS: [CellOrdering; 2] = [CellOrdering::Morton, CellOrdering::Hilbert];
+    // Fixed capacity for about 10 particles per cell with the search radius of 1 most tests use, i.e. one cache line that quite some cells overflow.
+    const CELL_STORAGES: [CellStorage; 2] = [
+        CellStorage::Sorted,
+        CellStorage::FixedCapacity {
+            expected_max_density: NumberDensity(10.0),
+        },
+    ];
+
+    // Searchers for all combinations of cell ordering and storage.
+    fn all_searchers(radius: Real) -> impl Iterator<Item = NeighborhoodSearch> {
+        CELL_ORDERINGS.iter().flat_map(move |&cell_ordering| {
+            CELL_STORAGES
+                .iter()
+                .map(move |&cell_storage| NeighborhoodSearch::new_with_cell_storage(radius, cell_storage).with_cell_ordering(cell_ordering))
+        })
+    }
+
+    // Naive O(n²) reference: indices of all positions within radius of query, in ascending order.
+    fn brute_force_neighbors(positions: &[Point], query: Point, radius: Real) -> Vec<usize> {
+        (0..positions.len())
+            .filter(|&i| positions[i].distance2(query) <= radius * radius)
+            .collect()
+    }
+
+    // Checks that filtering potential neighbors by radius yields exactly the brute force neighbors, without duplicates.
+    fn assert_potential_neighbors_match_brute_force(searcher: &NeighborhoodSearch, positions: &[Point], query: Point, radius: Real) {
+        let mut neighbors = Vec::new();
+        searcher.foreach_potential_neighbor(query, |j| {
+            if positions[j].distance2(query) <= radius * radius {
+                neighbors.push(j);
+            }
+        });
+        neighbors.sort_unstable();
+        assert_eq!(neighbors, brute_force_neighbors(positions, query, radius), "query at {:?}", query);
+    }
+
+    // Sorts the positions into a new searcher and compares queries at all positions as well as the given extra queries against brute force.
+    fn check_against_brute_force(positions: Vec<Point>, extra_queries: &[Point], radius: Real) {
+        check_against_brute_force_with_origin_cell(positions, extra_queries, radius, GRID_ORIGIN_CELL);
+    }
+
+    // Like check_against_brute_force, but with GRID_ORIGIN at a different cell of the grid.
+    // Checks all cell orderings and storages.
+    fn check_against_brute_force_with_origin_cell(mut positions: Vec<Point>, extra_queries: &[Point], radius: Real, origin_cell: f64) {
+        for mut searcher in all_searchers(radius) {
+            let mut scratch_buffer_store = ScratchBufferStore::new();
+            searcher.grid.origin_cell = origin_cell;
+            searcher.update_particle_neighbors(&mut scratch_buffer_store, &mut positions, &mut [], &mut [], &mut [], &[]);
+
+            for &query in positions.iter().chain(extra_queries.iter()) {
+                assert_potential_neighbors_match_brute_force(&searcher, &positions, query, radius);
+            }
+        }
+    }
+
+    #[test]
+    fn potential_neighbors_match_brute_force() {
+        const NUM_POSITIONS: usize = 2000;
+        const DENSITY: Real = 10.0;
+        const SEARCH_RADIUS: Real = 1.0;
+
+        let mut rng: rand::rngs::SmallRng = rand::SeedableRng::seed_from_u64(123456789);
+        let domain_size = (NUM_POSITIONS as Real / DENSITY).sqrt();
+        let positions: Vec<Point> = std::iter::repeat_with(|| Point::from_vec(rng.gen::<Vector>() * domain_size))
+            .take(NUM_POSITIONS)
+            .collect();
+        let queries: Vec<Point> = std::iter::repeat_with(|| Point::from_vec(rng.gen::<Vector>() * domain_size))
+            .take(200)
+            .collect();
+
+        check_against_brute_force(positions, &queries, SEARCH_RADIUS);
+    }
+
+    #[test]
+    fn potential_neighbors_match_brute_force_at_cell_borders() {
+        const SEARCH_RADIUS: Real = 0.5;
+
+        // Grid points on cell borders (GRID_ORIGIN is at a multiple of the cell size), jittered by a tiny bit to either side
+        // and pairs of points exactly one radius apart.
+        let mut rng: rand::rngs::SmallRng = rand::SeedableRng::seed_from_u64(123456789);
+        let mut positions = Vec::new();
+        for x in 0..16 {
+            for y in 0..16 {
+                let on_border = Point::new(x as Real, y as Real) * SEARCH_RADIUS;
+                let jitter = Vector::new(rng.gen::<Real>() - 0.5, rng.gen::<Real>() - 0.5) * 0.0001;
+                positions.push(on_border);
+                positions.push(on_border + jitter);
+                positions.push(on_border - jitter);
+                positions.push(on_border + Vector::new(SEARCH_RADIUS * 0.5, 0.0));
+                positions.push(on_border + Vector::new(SEARCH_RADIUS * 0.5, SEARCH_RADIUS));
+            }
+        }
+
+        check_against_brute_force(positions, &[], SEARCH_RADIUS);
+    }
+
+    #[test]
+    fn potential_neighbors_match_brute_force_across_morton_jumps() {
+        const SEARCH_RADIUS: Real = 1.0;
+
+        // Cells that are adjacent in space but far apart on the morton curve lie around cell coordinates that are powers of two.
+        // With the grid's corner at GRID_ORIGIN, these are easy to hit.
+        let grid_min = GRID_ORIGIN;
+
+        let mut rng: rand::rngs::SmallRng = rand::SeedableRng::seed_from_u64(123456789);
+        let mut positions = Vec::new();
+        for power in 1..12 {
+            let cell = (1 << power) as Real;
+            for &center in [Vector::new(cell, cell), Vector::new(cell, 3.0), Vector::new(3.0, cell)].iter() {
+                for _ in 0..50 {
+                    let offset = (rng.gen::<Vector>() - Vector::new(0.5, 0.5)) * 4.0;
+                    positions.push(grid_min + (center + offset) * SEARCH_RADIUS);
+                }
+            }
+        }
+
+        check_against_brute_force_with_origin_cell(positions, &[], SEARCH_RADIUS, 0.0);
+    }
+
+    #[test]
+    fn potential_neighbors_match_brute_force_across_highest_morton_jump() {
+        const SEARCH_RADIUS: Real = 1.0;
+
+        // Around the center of the grid, every bit of the cell coordinates changes.
+        let mut rng: rand::rngs::SmallRng = rand::SeedableRng::seed_from_u64(123456789);
+        let positions: Vec<Point> = std::iter::repeat_with(|| GRID_ORIGIN + (rng.gen::<Vector>() - Vector::new(0.5, 0.5)) * 10.0)
+            .take(500)
+            .collect();
+
+        check_against_brute_force_with_origin_cell(positions, &[GRID_ORIGIN], SEARCH_RADIUS, (1u64 << 31) as f64);
+    }
+
+    #[test]
+    fn potential_neighbors_match_brute_force_far_from_origin() {
+        const SEARCH_RADIUS: Real = 0.02;
+
+        // Way beyond what 16 bit cell coordinates could address at this cell size.
+        let mut rng: rand::rngs::SmallRng = rand::SeedableRng::seed_from_u64(123456789);
+        let mut positions = Vec::new();
+        for &center in [Point::new(5000.0, -5000.0), Point::new(-20000.0, 100.0)].iter() {
+            positions.extend(std::iter::repeat_with(|| center + rng.gen::<Vector>() * 0.2).take(300));
+        }
+        let searcher = NeighborhoodSearch::new(SEARCH_RADIUS);
+        assert_ne!(
+            searcher.cell_index(positions[0]),
+            searcher.cell_index(positions[0] + Vector::new(0.0, 0.05))
+        );
+        assert_ne!(
+            searcher.cell_index(positions[0]),
+            searcher.cell_index(positions[0] + Vector::new(-0.05, 0.0))
+        );
+
+        check_against_brute_force(positions, &[], SEARCH_RADIUS);
+    }
+
+    #[test]
+    fn potential_neighbors_match_brute_force_at_grid_min_corner() {
+        const SEARCH_RADIUS: Real = 1.0;
+
+        // corner of the grid at GRID_ORIGIN, the actual one is too far away to place particles with precision
+        let grid_min = GRID_ORIGIN;
+        let mut rng: rand::rngs::SmallRng = rand::SeedableRng::seed_from_u64(123456789);
+        let mut positions: Vec<Point> = std::iter::repeat_with(|| grid_min + rng.gen::<Vector>() * SEARCH_RADIUS * 3.0)
+            .take(200)
+            .collect();
+        positions.push(grid_min);
+        positions.push(grid_min + Vector::new(SEARCH_RADIUS, 0.0));
+        positions.push(grid_min + Vector::new(0.0, SEARCH_RADIUS));
+
+        check_against_brute_force_with_origin_cell(positions, &[grid_min, grid_min + Vector::new(0.0001, 0.0001)], SEARCH_RADIUS, 0.0);
+    }
+
+    #[test]
+    fn queries_at_arbitrary_points_match_brute_force() {
+        const SEARCH_RADIUS: Real = 1.0;
+
+        let mut rng: rand::rngs::SmallRng = rand::SeedableRng::seed_from_u64(123456789);
+        let mut positions: Vec<Point> = std::iter::repeat_with(|| Point::from_vec(rng.gen::<Vector>() * 10.0))
+            .take(1000)
+            .collect();
+        let queries: Vec<Point> = std::iter::repeat_with(|| Point::from_vec(rng.gen::<Vector>() * 14.0 - Vector::new(2.0, 2.0)))
+            .take(50)
+            .chain(std::iter::once(Point::new(100.0, -50.0)))
+            .collect();
+
+        for mut searcher in all_searchers(SEARCH_RADIUS) {
+            let mut scratch_buffer_store = ScratchBufferStore::new();
+            searcher.update_particle_neighbors(&mut scratch_buffer_store, &mut positions, &mut [], &mut [], &mut [], &[]);
+
+            for &query in queries.iter() {
+                for &radius_override in [None, Some(0.3), Some(2.5)].iter() {
+                    let mut neighbors = Vec::new();
+                    searcher.query_neighbors(&positions, query, radius_override, |j| neighbors.push(j));
+                    neighbors.sort_unstable();
+                    let radius = radius_override.unwrap_or(SEARCH_RADIUS);
+                    assert_eq!(neighbors, brute_force_neighbors(&positions, query, radius), "query at {:?}", query);
+                }
+
+                for &k in [1, 10, 50].iter() {
+                    let mut by_distance: Vec<usize> = (0..positions.len()).collect();
+                    by_distance.sort_by(|a, b| positions[*a].distance2(query).partial_cmp(&positions[*b].distance2(query)).unwrap());
+                    let nearest = searcher.query_k_nearest_neighbors(&positions, query, k);
+                    assert_eq!(nearest.len(), k);
+                    // compare distances, ties may come in any order
+                    for (a, b) in nearest.iter().zip(by_distance.iter()) {
+                        assert_eq!(positions[*a].distance2(query), positions[*b].distance2(query), "query at {:?}", query);
+                    }
+                }
+            }
+            assert_eq!(searcher.query_k_nearest_neighbors(&positions, queries[0], 5000).len(), positions.len());
+        }
+    }
+
+    #[test]
+    fn visited_cells_cover_potential_neighbors() {
+        const NUM_POSITIONS: usize = 500;
+        const SEARCH_RADIUS: Real = 1.0;
+
+        let mut rng: rand::rngs::SmallRng = rand::SeedableRng::seed_from_u64(123456789);
+        let mut positions: Vec<Point> = std::iter::repeat_with(|| Point::from_vec(rng.gen::<Vector>() * 10.0))
+            .take(NUM_POSITIONS)
+            .collect();
+
+        for mut searcher in all_searchers(SEARCH_RADIUS) {
+            let mut scratch_buffer_store = ScratchBufferStore::new();
+            searcher.update_particle_neighbors(&mut scratch_buffer_store, &mut positions, &mut [], &mut [], &mut [], &[]);
+
+            let mut num_particles_in_cells = 0;
+            searcher.foreach_particle_cell(|cidx, particles| {
+                let (min, max) = searcher.cell_bounds(cidx);
+                for p in particles.iter().map(|&i| positions[i]) {
+                    assert!(p.x >= min.x && p.y >= min.y && p.x < max.x && p.y < max.y);
+                }
+                num_particles_in_cells += particles.len();
+            });
+            assert_eq!(num_particles_in_cells, NUM_POSITIONS);
+
+            for query in positions.iter() {
+                let mut visited_cells = Vec::new();
+                searcher.foreach_cell_visited_by_potential_neighbor_query(*query, |cidx| visited_cells.push(cidx));
+                searcher.foreach_potential_neighbor(*query, |j| {
+                    assert!(visited_cells.contains(&searcher.cell_index(positions[j])));
+                });
+            }
+        }
+    }
+
     #[test]
     fn potential_neighbors_contains_neighbors() {
         const NUM_POSITIONS: usize = 1000;
@@ -515,7 +1301,7 @@ mod tests {
 
         let mut scratch_buffer_store = ScratchBufferStore::new();
         let mut searcher = NeighborhoodSearch::new(SEARCH_RADIUS);
-        searcher.update_particle_neighbors(&mut scratch_buffer_store, &mut positions, &mut [], &mut [], &[]);
+        searcher.update_particle_neighbors(&mut scratch_buffer_store, &mut positions, &mut [], &mut [], &mut [], &[]);
 
         for &search_pos in positions.iter() {
             let mut potential_neighbors = Vec::new();
@@ -530,6 +1316,38 @@ mod tests {
         }
     }
 
+    #[test]
+    fn potential_neighbors_in_radius_contains_neighbors() {
+        const NUM_POSITIONS: usize = 1000;
+        const DENSITY: Real = 10.0;
+        const SEARCH_RADIUS: Real = 1.0;
+
+        let mut rng: rand::rngs::SmallRng = rand::SeedableRng::seed_from_u64(123456789);
+        let mut positions: Vec<Point> = std::iter::repeat_with(|| Point::from_vec(rng.gen::<Vector>() * (NUM_POSITIONS as Real / DENSITY).sqrt()))
+            .take(NUM_POSITIONS)
+            .collect();
+
+        for mut searcher in all_searchers(SEARCH_RADIUS) {
+            let mut scratch_buffer_store = ScratchBufferStore::new();
+            searcher.update_particle_neighbors(&mut scratch_buffer_store, &mut positions, &mut [], &mut [], &mut [], &[]);
+
+            // small radii look up every cell in the rectangle with hilbert order, large ones go through all cells
+            for &query_radius in [0.5, 2.5, 7.0, 40.0].iter() {
+                for &search_pos in positions.iter().step_by(10) {
+                    let mut potential_neighbors = Vec::new();
+                    searcher.foreach_potential_neighbor_in_radius(search_pos, query_radius, |p| potential_neighbors.push(p));
+
+                    // validate
+                    for (i, &p) in positions.iter().enumerate() {
+                        if p.distance2(search_pos) <= query_radius * query_radius {
+                            assert!(potential_neighbors.contains(&i));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
     #[test]
     fn neighbors_contains_neighbors() {
         const NUM_POSITIONS: usize = 1000;
@@ -541,22 +1359,59 @@ mod tests {
             .take(NUM_POSITIONS)
             .collect();
 
-        let mut scratch_buffer_store = ScratchBufferStore::new();
-        let mut searcher = NeighborhoodSearch::new(SEARCH_RADIUS);
-        searcher.update_particle_neighbors(&mut scratch_buffer_store, &mut positions, &mut [], &mut [], &[]);
+        for mut searcher in all_searchers(SEARCH_RADIUS) {
+            let mut scratch_buffer_store = ScratchBufferStore::new();
+            searcher.update_particle_neighbors(&mut scratch_buffer_store, &mut positions, &mut [], &mut [], &mut [], &[]);
 
-        for (particle, &search_pos) in positions.iter().enumerate() {
-            let mut neighbors = Vec::new();
-            searcher.foreach_neighbor(particle as ParticleIndex, |p| neighbors.push(p));
+            for (particle, &search_pos) in positions.iter().enumerate() {
+                let mut neighbors = Vec::new();
+                searcher.foreach_neighbor(particle as ParticleIndex, |p| neighbors.push(p));
+                // particles aren't sorted with fixed capacity storage, so neither are their neighbors
+                if searcher.grid.cell_storage != CellStorage::Sorted {
+                    neighbors.sort_unstable();
+                }
 
-            // validate
-            let mut neighbors_bruteforce = Vec::new();
-            for (i, &p) in positions.iter().enumerate() {
-                if i != particle && p.distance2(search_pos) <= SEARCH_RADIUS * SEARCH_RADIUS {
-                    neighbors_bruteforce.push(i as ParticleIndex);
+                // validate
+                let mut neighbors_bruteforce = Vec::new();
+                for (i, &p) in positions.iter().enumerate() {
+                    if i != particle && p.distance2(search_pos) <= SEARCH_RADIUS * SEARCH_RADIUS {
+                        neighbors_bruteforce.push(i as ParticleIndex);
+                    }
                 }
+                assert_eq!(neighbors, neighbors_bruteforce);
             }
-            assert_eq!(neighbors, neighbors_bruteforce);
         }
     }
+
+    #[test]
+    fn fixed_capacity_cells_overflow() {
+        const SEARCH_RADIUS: Real = 1.0;
+
+        // a cluster of 50 particles in a single cell and some spread out around it
+        let mut rng: rand::rngs::SmallRng = rand::SeedableRng::seed_from_u64(123456789);
+        let mut positions: Vec<Point> = std::iter::repeat_with(|| Point::new(0.2, 0.2) + rng.gen::<Vector>() * 0.5)
+            .take(50)
+            .collect();
+        for _ in 0..100 {
+            positions.push(Point::from_vec((rng.gen::<Vector>() - Vector::new(0.5, 0.5)) * 6.0));
+        }
+        let original_positions = positions.clone();
+
+        let mut scratch_buffer_store = ScratchBufferStore::new();
+        let mut searcher = NeighborhoodSearch::new_with_cell_storage(
+            SEARCH_RADIUS,
+            CellStorage::FixedCapacity {
+                expected_max_density: NumberDensity(4.0),
+            },
+        );
+        searcher.update_particle_neighbors(&mut scratch_buffer_store, &mut positions, &mut [], &mut [], &mut [], &[]);
+        assert_eq!(positions, original_positions);
+        assert_eq!(searcher.num_particles(), positions.len());
+        assert_eq!(searcher.cellgrid_particles.slots.lines_per_cell, 4);
+
+        for &query in positions.iter() {
+            assert_potential_neighbors_match_brute_force(&searcher, &positions, query, SEARCH_RADIUS);
+        }
+        assert_ge!(searcher.num_neighbors(0), 49);
+    }
 }
@@ -0,0 +1,179 @@
+use super::fluidparticleworld::FluidParticleWorld;
+use crate::units::*;
+
+// Number of polygon edges used to approximate a cylinder's outline.
+const CYLINDER_SEGMENTS: usize = 48;
+// Number of points per side of an airfoil, distributed more densely towards the leading edge.
+const AIRFOIL_POINTS_PER_SIDE: usize = 32;
+
+// Parametric solid obstacles for flow interaction scenes, placed as closed boundary polygons.
+// All shapes are defined relative to a placement position and assume flow from left to right.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Obstacle {
+    // Circle centered at the placement position.
+    Cylinder { radius: Real },
+    // Isosceles triangle with its tip at the placement position, pointing against the flow.
+    Wedge { length: Real, half_angle: Real }, // half angle at the tip in radians
+    // Symmetric NACA 4-digit profile (00xx) with its leading edge at the placement position.
+    // `thickness` is relative to the chord, e.g. 0.12 for NACA 0012. Positive angle of attack lifts the leading edge.
+    Airfoil { chord: Real, thickness: Real, angle_of_attack: Real }, // angle of attack in radians
+    // Staircase going up to the right, its bottom left corner at the placement position.
+    Stairs { num_steps: u32, step_width: Real, step_height: Real },
+}
+
+impl Obstacle {
+    // Closed outline, last vertex connects back to the first.
+    pub fn outline(&self, position: Point) -> Vec<Point> {
+        match *self {
+            Obstacle::Cylinder { radius } => (0..CYLINDER_SEGMENTS)
+                .map(|i| {
+                    let angle = i as Real / CYLINDER_SEGMENTS as Real * 2.0 * std::f32::consts::PI;
+                    position + Vector::new(angle.cos(), angle.sin()) * radius
+                })
+                .collect(),
+            Obstacle::Wedge { length, half_angle } => {
+                let half_width = length * half_angle.tan();
+                vec![
+                    position,
+                    position + Vector::new(length, -half_width),
+                    position + Vector::new(length, half_width),
+                ]
+            }
+            Obstacle::Airfoil {
+                chord,
+                thickness,
+                angle_of_attack,
+            } => {
+                let (sin, cos) = (-angle_of_attack).sin_cos();
+                let rotate = |v: Vector| Vector::new(v.x * cos - v.y * sin, v.x * sin + v.y * cos);
+                // cosine spacing, starting at the trailing edge along the upper side, back along the lower side
+                let x_along_chord = |i: usize| 0.5 * (1.0 + (i as Real / AIRFOIL_POINTS_PER_SIDE as Real * std::f32::consts::PI).cos());
+                let upper = (0..AIRFOIL_POINTS_PER_SIDE).map(|i| (x_along_chord(i), 1.0));
+                let lower = (0..AIRFOIL_POINTS_PER_SIDE).rev().map(|i| (x_along_chord(i), -1.0));
+                upper
+                    .chain(std::iter::once((0.0, 1.0)))
+                    .chain(lower.take(AIRFOIL_POINTS_PER_SIDE - 1))
+                    .map(|(x, side)| position + rotate(Vector::new(x, side * naca_half_thickness(x, thickness)) * chord))
+                    .collect()
+            }
+            Obstacle::Stairs {
+                num_steps,
+                step_width,
+                step_height,
+            } => {
+                let corner = |x: u32, y: u32| position + Vector::new(x as Real * step_width, y as Real * step_height);
+                let mut vertices = vec![corner(0, 0), corner(num_steps, 0)];
+                for step in (0..num_steps).rev() {
+                    vertices.push(corner(step + 1, step + 1));
+                    vertices.push(corner(step, step + 1));
+                }
+                vertices
+            }
+        }
+    }
+
+    // Adds the obstacle's outline as boundary.
+    pub fn add_to(&self, fluid_world: &mut FluidParticleWorld, position: Point) {
+        match *self {
+            // exact circle instead of the outline, sampling many short polygon edges would place boundary particles too densely
+            Obstacle::Cylinder { radius } => fluid_world.add_boundary_circle(position, radius),
+            _ => fluid_world.add_boundary_polygon(&self.outline(position)),
+        }
+    }
+}
+
+// Half thickness relative to the chord of a symmetric NACA 4-digit airfoil at relative position x along the chord.
+// Uses the coefficients for a closed trailing edge.
+fn naca_half_thickness(x: Real, thickness: Real) -> Real {
+    5.0 * thickness * (0.2969 * x.sqrt() - 0.1260 * x - 0.3516 * x.powi(2) + 0.2843 * x.powi(3) - 0.1036 * x.powi(4))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fluidparticleworld::BoundaryGeometry;
+    use super::*;
+
+    fn polygon_area(vertices: &[Point]) -> Real {
+        let mut twice_area = 0.0;
+        for (i, a) in vertices.iter().enumerate() {
+            let b = vertices[(i + 1) % vertices.len()];
+            twice_area += a.x * b.y - b.x * a.y;
+        }
+        twice_area.abs() * 0.5
+    }
+
+    #[test]
+    fn obstacle_areas() {
+        let position = Point::new(1.0, 2.0);
+
+        let cylinder = Obstacle::Cylinder { radius: 0.5 }.outline(position);
+        assert_lt!((polygon_area(&cylinder) / (std::f32::consts::PI * 0.25) - 1.0).abs(), 0.01);
+
+        let half_angle = std::f32::consts::PI / 8.0;
+        let wedge = Obstacle::Wedge { length: 1.0, half_angle }.outline(position);
+        assert_eq!(wedge[0], position);
+        assert_lt!((polygon_area(&wedge) - half_angle.tan()).abs(), 1.0e-5);
+
+        let stairs = Obstacle::Stairs {
+            num_steps: 3,
+            step_width: 0.2,
+            step_height: 0.1,
+        }
+        .outline(position);
+        assert_eq!(stairs.len(), 2 + 3 * 2);
+        assert_lt!((polygon_area(&stairs) - 0.2 * 0.1 * 6.0).abs(), 1.0e-5);
+    }
+
+    #[test]
+    fn airfoil_outline() {
+        let position = Point::new(1.0, 2.0);
+        let airfoil = Obstacle::Airfoil {
+            chord: 2.0,
+            thickness: 0.12,
+            angle_of_attack: 0.0,
+        };
+        let outline = airfoil.outline(position);
+        assert_eq!(outline.len(), AIRFOIL_POINTS_PER_SIDE * 2);
+        // NACA 00xx profiles enclose about 0.685 * thickness * chord²
+        assert_lt!((polygon_area(&outline) / (0.685 * 0.12 * 4.0) - 1.0).abs(), 0.01);
+        // thickest at 30% of the chord
+        let max_y = outline.iter().map(|p| p.y - position.y).fold(0.0, Real::max);
+        assert_lt!((max_y - 0.12).abs(), 0.002);
+        assert!(outline.contains(&position));
+
+        // rotating by the angle of attack moves the trailing edge down
+        let rotated = Obstacle::Airfoil {
+            chord: 2.0,
+            thickness: 0.12,
+            angle_of_attack: 0.1,
+        }
+        .outline(position);
+        assert_lt!(rotated[0].y, position.y);
+        assert_lt!((polygon_area(&rotated) - polygon_area(&outline)).abs(), 1.0e-4);
+    }
+
+    #[test]
+    fn obstacles_become_boundaries() {
+        let mut fluid_world = FluidParticleWorld::new(2.0, NumberDensity(1000.0), Density(1.0));
+        let spacing = fluid_world.properties.particle_radius() * 2.0;
+        let wedge = Obstacle::Wedge {
+            length: 0.5,
+            half_angle: std::f32::consts::PI / 4.0,
+        };
+        wedge.add_to(&mut fluid_world, Point::new(0.0, 0.0));
+        let outline = wedge.outline(Point::new(0.0, 0.0));
+        assert_eq!(
+            fluid_world.boundary_geometry()[0],
+            BoundaryGeometry::Polygon {
+                vertices: outline.clone(),
+                width: spacing
+            }
+        );
+        // one layer of boundary particles along the perimeter
+        let perimeter = 1.0 + 2.0 * 0.5 * std::f32::consts::SQRT_2;
+        assert_lt!((fluid_world.particles.boundary_particles.len() as Real - perimeter / spacing).abs(), 3.5);
+
+        Obstacle::Cylinder { radius: 0.2 }.add_to(&mut fluid_world, Point::new(1.0, 0.0));
+        assert!(matches!(fluid_world.boundary_geometry()[1], BoundaryGeometry::Circle { .. }));
+    }
+}
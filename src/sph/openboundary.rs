@@ -0,0 +1,259 @@
+use super::fluidparticleworld::FluidParticleWorld;
+use super::solver::Solver;
+use crate::units::*;
+use cgmath::prelude::*;
+
+// Open boundaries: Buffer zones along a line segment through which fluid enters or leaves the domain, e.g. for channel flows.
+//
+// As with FluxLine, flow goes to the right of the line direction (same side thick boundary lines grow to).
+// The inlet's buffer lies behind its line, the outlet's buffer in front of it.
+// Particles in the buffers are regular fluid particles that give particles in the domain full kernel support,
+// only their velocities are overwritten before every simulation step.
+// Both inlet and outlet are meant to be updated right before every simulation step, outlet first.
+
+// Velocity distribution across an inlet, given as speed along the inlet's normal.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InflowProfile {
+    // Same speed in m/s everywhere.
+    Uniform(Real),
+    // Poiseuille profile, zero at both ends of the inlet and the given speed in m/s in its center.
+    Parabolic(Real),
+}
+
+impl InflowProfile {
+    // Speed in m/s at a relative position t ∈ [0, 1] along the inlet.
+    pub fn speed(&self, t: Real) -> Real {
+        match self {
+            InflowProfile::Uniform(speed) => *speed,
+            InflowProfile::Parabolic(max_speed) => 4.0 * max_speed * t * (1.0 - t),
+        }
+    }
+
+    // Average speed across the inlet in m/s.
+    pub fn mean_speed(&self) -> Real {
+        match self {
+            InflowProfile::Uniform(speed) => *speed,
+            InflowProfile::Parabolic(max_speed) => max_speed * 2.0 / 3.0,
+        }
+    }
+}
+
+// Unit normal pointing in flow direction.
+fn flow_normal(start: Point, end: Point) -> Vector {
+    let direction = (end - start).normalize();
+    Vector::new(direction.y, -direction.x)
+}
+
+// Relative position along the line and signed distance in flow direction.
+fn line_coordinates(start: Point, end: Point, position: Point) -> (Real, Real) {
+    let direction = end - start;
+    let offset = position - start;
+    (offset.dot(direction) / direction.magnitude2(), offset.dot(flow_normal(start, end)))
+}
+
+// Creates particles with a prescribed velocity profile.
+//
+// The buffer is divided into columns with rest spacing along the line.
+// A column emits a new particle at the back of the buffer whenever the column's particles moved in by a particle spacing,
+// so a buffer that was filled with fill_buffer keeps a regular lattice.
+// This looks at actual positions instead of integrating the prescribed velocity,
+// since the solver's velocity update still affects how far buffer particles move within a step.
+pub struct Inlet {
+    pub start: Point,
+    pub end: Point,
+    pub depth: Real, // in m, should be at least the smoothing length to give particles behind the line full support
+    pub profile: InflowProfile,
+}
+
+impl Inlet {
+    pub fn new(start: Point, end: Point, depth: Real, profile: InflowProfile) -> Inlet {
+        Inlet { start, end, depth, profile }
+    }
+
+    fn num_columns(&self, fluid_world: &FluidParticleWorld) -> usize {
+        let spacing = fluid_world.properties.particle_radius() * 2.0;
+        ((self.start.distance(self.end) / spacing).round() as usize).max(1)
+    }
+
+    // Position at the back of the buffer where a column emits its particles.
+    fn column_origin(&self, column: usize, num_columns: usize) -> (Point, Real) {
+        let t = (column as Real + 0.5) / num_columns as Real;
+        let origin = self.start + (self.end - self.start) * t - flow_normal(self.start, self.end) * self.depth;
+        (origin, t)
+    }
+
+    // Fills the entire buffer with particles on a lattice, typically done once when setting up a scene.
+    // Returns the number of added particles.
+    pub fn fill_buffer(&self, fluid_world: &mut FluidParticleWorld) -> usize {
+        let spacing = fluid_world.properties.particle_radius() * 2.0;
+        let normal = flow_normal(self.start, self.end);
+        let num_columns = self.num_columns(fluid_world);
+        let num_rows = (self.depth / spacing).round() as usize;
+        for column in 0..num_columns {
+            let (origin, t) = self.column_origin(column, num_columns);
+            for row in 0..num_rows {
+                fluid_world.add_fluid_particle(origin + normal * (row as Real * spacing), normal * self.profile.speed(t));
+            }
+        }
+        num_columns * num_rows
+    }
+
+    // Prescribes the velocity of all particles within the buffer and emits new particles where the buffer's content moved in.
+    // Returns the number of added particles.
+    pub fn update(&self, fluid_world: &mut FluidParticleWorld) -> usize {
+        microprofile::scope!("Inlet", "update");
+        let spacing = fluid_world.properties.particle_radius() * 2.0;
+        let normal = flow_normal(self.start, self.end);
+        let num_columns = self.num_columns(fluid_world);
+
+        // per column, distance of the rearmost particle from the back of the buffer
+        let mut gaps = vec![self.depth; num_columns];
+        {
+            let particles = &mut fluid_world.particles;
+            for (p, v) in particles.positions.iter().zip(particles.velocities.iter_mut()) {
+                let (t, distance) = line_coordinates(self.start, self.end, *p);
+                if !(0.0..1.0).contains(&t) || !(-self.depth - spacing * 0.5..0.0).contains(&distance) {
+                    continue;
+                }
+                *v = normal * self.profile.speed(t);
+                let gap = &mut gaps[(t * num_columns as Real) as usize];
+                *gap = gap.min(distance + self.depth);
+            }
+        }
+
+        let mut num_added = 0;
+        for (column, gap) in gaps.into_iter().enumerate() {
+            let (origin, t) = self.column_origin(column, num_columns);
+            let mut offset = gap - spacing;
+            while offset >= 0.0 {
+                fluid_world.add_fluid_particle(origin + normal * offset, normal * self.profile.speed(t));
+                offset -= spacing;
+                num_added += 1;
+            }
+        }
+        num_added
+    }
+}
+
+// Removes particles that went through the back of the buffer.
+//
+// Within the buffer, velocities are extrapolated from the fluid at the outlet line.
+// This way particles leave with the flow, unaffected by the pressure drop from the missing fluid behind the buffer.
+pub struct Outlet {
+    pub start: Point,
+    pub end: Point,
+    pub depth: Real, // in m, should be at least the smoothing length to give particles in front of the line full support
+}
+
+impl Outlet {
+    pub fn new(start: Point, end: Point, depth: Real) -> Outlet {
+        Outlet { start, end, depth }
+    }
+
+    // Returns the number of removed particles. These are removed from the solver's per particle data as well.
+    pub fn update(&self, fluid_world: &mut FluidParticleWorld, solver: &mut dyn Solver) -> usize {
+        microprofile::scope!("Outlet", "update");
+        let normal = flow_normal(self.start, self.end);
+        let particles = &fluid_world.particles;
+        let mut keep = vec![true; particles.positions.len()];
+        let mut extrapolated_velocities = Vec::new();
+        for (i, p) in particles.positions.iter().enumerate() {
+            let (t, distance) = line_coordinates(self.start, self.end, *p);
+            if !(0.0..=1.0).contains(&t) || distance < 0.0 {
+                continue;
+            }
+            if distance >= self.depth {
+                keep[i] = false;
+            } else if i < particles.neighborhood().num_particles() {
+                // particles added since the last step are not in the neighborhood datastructure yet
+                if let Some(velocity) = fluid_world.interpolate_velocity(p - normal * distance) {
+                    extrapolated_velocities.push((i, velocity));
+                }
+            }
+        }
+
+        for (i, velocity) in extrapolated_velocities {
+            fluid_world.particles.velocities[i] = velocity;
+        }
+        let num_removed = keep.iter().filter(|keep| !**keep).count();
+        if num_removed > 0 {
+            fluid_world.retain_fluid_particles(&keep);
+            solver.retain_particle_data(&keep);
+        }
+        num_removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sph::{WCSPHSolver, XSPHViscosityModel};
+
+    #[test]
+    fn inlet_emits_lattice_rows() {
+        let mut fluid_world = FluidParticleWorld::new(2.0, NumberDensity(10000.0), Density(100.0));
+        fluid_world.force_fields.clear();
+        let spacing = fluid_world.properties.particle_radius() * 2.0;
+        // flow to the right, 10 columns
+        let inlet = Inlet::new(
+            Point::new(0.0, 0.0),
+            Point::new(0.0, spacing * 10.0),
+            spacing * 4.0,
+            InflowProfile::Uniform(1.0),
+        );
+        assert_eq!(inlet.fill_buffer(&mut fluid_world), 40);
+        assert!(fluid_world.particles.positions.iter().all(|p| p.x < 0.0 && p.x >= -spacing * 4.0));
+
+        // particles moved by the simulation get their velocity prescribed
+        fluid_world.particles.velocities[0] = Vector::new(0.0, 5.0);
+        let dt = spacing / 4.0;
+        let mut num_added = 0;
+        for _ in 0..10 {
+            num_added += inlet.update(&mut fluid_world);
+            for (p, v) in fluid_world.particles.positions.iter_mut().zip(fluid_world.particles.velocities.iter()) {
+                assert_eq!(*v, Vector::new(1.0, 0.0));
+                *p += v * dt;
+            }
+        }
+        assert_eq!(num_added, 20);
+        // nothing moved since the last emission
+        assert_eq!(inlet.update(&mut fluid_world), 0);
+
+        // parabolic profile is zero at the edges
+        let parabolic = InflowProfile::Parabolic(1.5);
+        assert_eq!(parabolic.speed(0.0), 0.0);
+        assert_eq!(parabolic.speed(0.5), 1.5);
+        assert_eq!(parabolic.mean_speed(), 1.0);
+    }
+
+    #[test]
+    fn outlet_removes_particles_behind_buffer() {
+        let mut fluid_world = FluidParticleWorld::new(2.0, NumberDensity(10000.0), Density(100.0));
+        let spacing = fluid_world.properties.particle_radius() * 2.0;
+        // outlet at x = 1, fluid moving right through it
+        let outlet = Outlet::new(Point::new(1.0, 0.0), Point::new(1.0, 1.0), spacing * 4.0);
+        for x in 0..20 {
+            for y in 0..10 {
+                let position = Point::new(1.0 + (x as Real - 9.5) * spacing, (y as Real + 0.5) * spacing);
+                fluid_world.add_fluid_particle(position, Vector::new(1.0, 0.0));
+            }
+        }
+        fluid_world.update_neighborhood_datastructure(Vec::new(), Vec::new());
+        fluid_world.update_densities(crate::sph::smoothing_kernel::CubicSpline::new(fluid_world.properties.smoothing_length()));
+
+        let mut solver = WCSPHSolver::new(
+            XSPHViscosityModel::new(fluid_world.properties.smoothing_length()),
+            &fluid_world.properties,
+        );
+        let num_particles = fluid_world.particles.positions.len();
+        assert_eq!(outlet.update(&mut fluid_world, &mut solver), 60);
+        let particles = &fluid_world.particles;
+        assert_eq!(particles.positions.len(), num_particles - 60);
+        assert_eq!(particles.masses.len(), particles.positions.len());
+        assert_eq!(particles.densities.len(), particles.positions.len());
+        assert!(particles.positions.iter().all(|p| p.x < 1.0 + spacing * 4.0));
+        for v in particles.velocities.iter() {
+            assert_lt!((v - Vector::new(1.0, 0.0)).magnitude(), 1.0e-4);
+        }
+    }
+}
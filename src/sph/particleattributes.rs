@@ -0,0 +1,338 @@
+use crate::units::*;
+use std::marker::PhantomData;
+
+// Value types of additional per particle attributes, see Particles::register_attribute.
+// Each type has its own storage in ParticleAttributes, matching the kinds of attributes the neighborhood search can sort.
+pub trait AttributeValue: Copy {
+    const NUM_COMPONENTS: usize;
+
+    fn arrays(attributes: &ParticleAttributes) -> &Vec<AttributeArray<Self>>;
+    fn arrays_mut(attributes: &mut ParticleAttributes) -> &mut Vec<AttributeArray<Self>>;
+
+    // Comma separated csv column names for an attribute of this type, one per component.
+    fn csv_header(name: &str) -> String;
+    fn csv_value(&self) -> String;
+
+    // Component type of Houdini geo attributes and PLY properties, see write_particles.
+    const GEO_TYPE: &'static str;
+    const PLY_TYPE: &'static str;
+    // Space separated components.
+    fn geo_value(&self) -> String;
+    fn append_le_bytes(&self, data: &mut Vec<u8>);
+}
+
+impl AttributeValue for Real {
+    const NUM_COMPONENTS: usize = 1;
+    fn arrays(attributes: &ParticleAttributes) -> &Vec<AttributeArray<Self>> {
+        &attributes.real
+    }
+    fn arrays_mut(attributes: &mut ParticleAttributes) -> &mut Vec<AttributeArray<Self>> {
+        &mut attributes.real
+    }
+    fn csv_header(name: &str) -> String {
+        name.to_string()
+    }
+    fn csv_value(&self) -> String {
+        self.to_string()
+    }
+
+    const GEO_TYPE: &'static str = "float";
+    const PLY_TYPE: &'static str = "float";
+    fn geo_value(&self) -> String {
+        self.to_string()
+    }
+    fn append_le_bytes(&self, data: &mut Vec<u8>) {
+        data.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl AttributeValue for Vector {
+    const NUM_COMPONENTS: usize = 2;
+    fn arrays(attributes: &ParticleAttributes) -> &Vec<AttributeArray<Self>> {
+        &attributes.vector
+    }
+    fn arrays_mut(attributes: &mut ParticleAttributes) -> &mut Vec<AttributeArray<Self>> {
+        &mut attributes.vector
+    }
+    fn csv_header(name: &str) -> String {
+        format!("{}_x,{}_y", name, name)
+    }
+    fn csv_value(&self) -> String {
+        format!("{},{}", self.x, self.y)
+    }
+
+    const GEO_TYPE: &'static str = "float";
+    const PLY_TYPE: &'static str = "float";
+    fn geo_value(&self) -> String {
+        format!("{} {}", self.x, self.y)
+    }
+    fn append_le_bytes(&self, data: &mut Vec<u8>) {
+        data.extend_from_slice(&self.x.to_le_bytes());
+        data.extend_from_slice(&self.y.to_le_bytes());
+    }
+}
+
+impl AttributeValue for u32 {
+    const NUM_COMPONENTS: usize = 1;
+    fn arrays(attributes: &ParticleAttributes) -> &Vec<AttributeArray<Self>> {
+        &attributes.uint
+    }
+    fn arrays_mut(attributes: &mut ParticleAttributes) -> &mut Vec<AttributeArray<Self>> {
+        &mut attributes.uint
+    }
+    fn csv_header(name: &str) -> String {
+        name.to_string()
+    }
+    fn csv_value(&self) -> String {
+        self.to_string()
+    }
+
+    const GEO_TYPE: &'static str = "int";
+    const PLY_TYPE: &'static str = "uint";
+    fn geo_value(&self) -> String {
+        self.to_string()
+    }
+    fn append_le_bytes(&self, data: &mut Vec<u8>) {
+        data.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+// Handle to an attribute registered via Particles::register_attribute.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ParticleAttribute<T: AttributeValue> {
+    index: usize,
+    value_type: PhantomData<T>,
+}
+
+#[derive(Clone)]
+pub struct AttributeArray<T: AttributeValue> {
+    name: String,
+    default: T, // value of newly added particles
+    values: Vec<T>,
+}
+
+// Per fluid particle arrays that solvers, models or tools registered in addition to the built-in ones of Particles,
+// e.g. temperature, phase or vorticity.
+// They are kept in sync with the fluid particles: extended when particles are added, filtered when particles are removed
+// and sorted along with all other attributes on neighborhood updates.
+// Mutable references to all arrays of ParticleAttributes, by type.
+pub(super) type ArraysMut<'a> = (Vec<&'a mut Vec<Real>>, Vec<&'a mut Vec<Vector>>, Vec<&'a mut Vec<u32>>);
+
+#[derive(Clone, Default)]
+pub struct ParticleAttributes {
+    real: Vec<AttributeArray<Real>>,
+    vector: Vec<AttributeArray<Vector>>,
+    uint: Vec<AttributeArray<u32>>,
+}
+
+impl ParticleAttributes {
+    // Registers a new attribute with all particles set to `default`.
+    // If there already is an attribute of that name and type it is returned instead, so that several models can share an attribute.
+    pub(super) fn register<T: AttributeValue>(&mut self, name: &str, default: T, num_particles: usize) -> ParticleAttribute<T> {
+        let index = self.find::<T>(name).map(|attribute| attribute.index).unwrap_or_else(|| {
+            let arrays = T::arrays_mut(self);
+            arrays.push(AttributeArray {
+                name: name.to_string(),
+                default,
+                values: vec![default; num_particles],
+            });
+            arrays.len() - 1
+        });
+        ParticleAttribute {
+            index,
+            value_type: PhantomData,
+        }
+    }
+
+    pub(super) fn find<T: AttributeValue>(&self, name: &str) -> Option<ParticleAttribute<T>> {
+        T::arrays(self)
+            .iter()
+            .position(|array| array.name == name)
+            .map(|index| ParticleAttribute {
+                index,
+                value_type: PhantomData,
+            })
+    }
+
+    pub(super) fn values<T: AttributeValue>(&self, attribute: ParticleAttribute<T>) -> &[T] {
+        &T::arrays(self)[attribute.index].values
+    }
+
+    pub(super) fn values_mut<T: AttributeValue>(&mut self, attribute: ParticleAttribute<T>) -> &mut [T] {
+        &mut T::arrays_mut(self)[attribute.index].values
+    }
+
+    // Fills up or cuts off all attributes to the given number of particles.
+    pub(super) fn resize(&mut self, num_particles: usize) {
+        Self::resize_arrays(&mut self.real, num_particles);
+        Self::resize_arrays(&mut self.vector, num_particles);
+        Self::resize_arrays(&mut self.uint, num_particles);
+    }
+
+    fn resize_arrays<T: AttributeValue>(arrays: &mut [AttributeArray<T>], num_particles: usize) {
+        for array in arrays.iter_mut() {
+            array.values.resize(num_particles, array.default);
+        }
+    }
+
+    pub(super) fn retain(&mut self, keep: &[bool]) {
+        Self::retain_arrays(&mut self.real, keep);
+        Self::retain_arrays(&mut self.vector, keep);
+        Self::retain_arrays(&mut self.uint, keep);
+    }
+
+    fn retain_arrays<T: AttributeValue>(arrays: &mut [AttributeArray<T>], keep: &[bool]) {
+        for array in arrays.iter_mut() {
+            super::fluidparticleworld::retain_particle_attribute(&mut array.values, keep);
+        }
+    }
+
+    pub(super) fn reserve(&mut self, num_particles: usize) {
+        Self::reserve_arrays(&mut self.real, num_particles);
+        Self::reserve_arrays(&mut self.vector, num_particles);
+        Self::reserve_arrays(&mut self.uint, num_particles);
+    }
+
+    fn reserve_arrays<T: AttributeValue>(arrays: &mut [AttributeArray<T>], num_particles: usize) {
+        for array in arrays.iter_mut() {
+            array.values.reserve(num_particles.saturating_sub(array.values.len()));
+        }
+    }
+
+    // Heap memory of all attribute arrays in bytes.
+    pub(super) fn memory_usage(&self) -> usize {
+        Self::arrays_memory_usage(&self.real) + Self::arrays_memory_usage(&self.vector) + Self::arrays_memory_usage(&self.uint)
+    }
+
+    fn arrays_memory_usage<T: AttributeValue>(arrays: &[AttributeArray<T>]) -> usize {
+        arrays.iter().map(|array| array.values.capacity() * std::mem::size_of::<T>()).sum()
+    }
+
+    // All arrays by type, e.g. to sort them along with the particles.
+    pub(super) fn arrays_mut(&mut self) -> ArraysMut<'_> {
+        (
+            self.real.iter_mut().map(|array| &mut array.values).collect(),
+            self.vector.iter_mut().map(|array| &mut array.values).collect(),
+            self.uint.iter_mut().map(|array| &mut array.values).collect(),
+        )
+    }
+
+    // Csv header of all attributes in the order of csv_row, every column preceded by a comma to append it to other columns.
+    pub(super) fn csv_header(&self) -> String {
+        let real = self.real.iter().map(|array| Real::csv_header(&array.name));
+        let vector = self.vector.iter().map(|array| Vector::csv_header(&array.name));
+        let uint = self.uint.iter().map(|array| u32::csv_header(&array.name));
+        real.chain(vector).chain(uint).map(|columns| format!(",{}", columns)).collect()
+    }
+
+    // Csv values of a particle like csv_header, empty fields for particles an attribute doesn't cover yet.
+    pub(super) fn csv_row(&self, particle: usize) -> String {
+        fn values<T: AttributeValue>(arrays: &[AttributeArray<T>], particle: usize) -> impl Iterator<Item = String> + '_ {
+            arrays.iter().map(move |array| match array.values.get(particle) {
+                Some(value) => format!(",{}", value.csv_value()),
+                None => ",".repeat(T::NUM_COMPONENTS),
+            })
+        }
+        values(&self.real, particle)
+            .chain(values(&self.vector, particle))
+            .chain(values(&self.uint, particle))
+            .collect()
+    }
+
+    pub(super) fn num_attributes(&self) -> usize {
+        self.real.len() + self.vector.len() + self.uint.len()
+    }
+
+    // Houdini geo point attribute declarations of all attributes in the order of geo_values, one line each.
+    pub(super) fn geo_header(&self) -> String {
+        fn declarations<T: AttributeValue>(arrays: &[AttributeArray<T>]) -> impl Iterator<Item = String> + '_ {
+            arrays.iter().map(|array| {
+                let defaults = " 0".repeat(T::NUM_COMPONENTS);
+                format!("{} {} {}{}\n", array.name, T::NUM_COMPONENTS, T::GEO_TYPE, defaults)
+            })
+        }
+        declarations(&self.real)
+            .chain(declarations(&self.vector))
+            .chain(declarations(&self.uint))
+            .collect()
+    }
+
+    // Geo values of a particle like geo_header, every attribute preceded by a space.
+    // Unlike csv rows there are no empty fields, particles an attribute doesn't cover yet get its default.
+    pub(super) fn geo_values(&self, particle: usize) -> String {
+        fn values<T: AttributeValue>(arrays: &[AttributeArray<T>], particle: usize) -> impl Iterator<Item = String> + '_ {
+            arrays
+                .iter()
+                .map(move |array| format!(" {}", array.values.get(particle).unwrap_or(&array.default).geo_value()))
+        }
+        values(&self.real, particle)
+            .chain(values(&self.vector, particle))
+            .chain(values(&self.uint, particle))
+            .collect()
+    }
+
+    // PLY property declarations of all attributes in the order of append_ply_values, a property per component named like the csv columns.
+    pub(super) fn ply_header(&self) -> String {
+        fn properties<T: AttributeValue>(arrays: &[AttributeArray<T>]) -> impl Iterator<Item = String> + '_ {
+            arrays.iter().flat_map(|array| {
+                T::csv_header(&array.name)
+                    .split(',')
+                    .map(|property| format!("property {} {}\n", T::PLY_TYPE, property))
+                    .collect::<Vec<_>>()
+            })
+        }
+        properties(&self.real)
+            .chain(properties(&self.vector))
+            .chain(properties(&self.uint))
+            .collect()
+    }
+
+    // Binary little endian PLY values of a particle like ply_header, with defaults like geo_values.
+    pub(super) fn append_ply_values(&self, particle: usize, data: &mut Vec<u8>) {
+        fn append<T: AttributeValue>(arrays: &[AttributeArray<T>], particle: usize, data: &mut Vec<u8>) {
+            for array in arrays.iter() {
+                array.values.get(particle).unwrap_or(&array.default).append_le_bytes(data);
+            }
+        }
+        append(&self.real, particle, data);
+        append(&self.vector, particle, data);
+        append(&self.uint, particle, data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn register_shares_attributes_by_name_and_type() {
+        let mut attributes = ParticleAttributes::default();
+        let temperature = attributes.register("temperature", 20.0 as Real, 3);
+        assert_eq!(attributes.values(temperature), &[20.0, 20.0, 20.0]);
+        attributes.values_mut(temperature)[1] = 30.0;
+
+        assert_eq!(attributes.register("temperature", 0.0 as Real, 3), temperature);
+        assert_eq!(attributes.values(temperature), &[20.0, 30.0, 20.0]);
+        // same name but other type is a separate attribute
+        let phase = attributes.register("temperature", 1_u32, 3);
+        assert_eq!(attributes.values(phase), &[1, 1, 1]);
+        assert_eq!(attributes.find::<Vector>("temperature"), None);
+
+        attributes.resize(4);
+        assert_eq!(attributes.values(temperature), &[20.0, 30.0, 20.0, 20.0]);
+        attributes.retain(&[true, false, true, true]);
+        assert_eq!(attributes.values(temperature), &[20.0, 20.0, 20.0]);
+        assert_eq!(attributes.values(phase), &[1, 1, 1]);
+    }
+
+    #[test]
+    fn csv_has_a_column_per_component() {
+        let mut attributes = ParticleAttributes::default();
+        attributes.register("phase", 2_u32, 1);
+        attributes.register("color", Vector::new(0.5, 1.0), 1);
+        attributes.register("temperature", 20.0 as Real, 2);
+        assert_eq!(attributes.csv_header(), ",temperature,color_x,color_y,phase");
+        assert_eq!(attributes.csv_row(0), ",20,0.5,1,2");
+        assert_eq!(attributes.csv_row(1), ",20,,,");
+    }
+}
@@ -0,0 +1,267 @@
+use super::fluidparticleworld::FluidParticleWorld;
+use crate::units::*;
+use std::io;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParticleExportFormat {
+    // Houdini's classic ASCII geometry (as written by partio), a particle system primitive over all points.
+    // Loads in Houdini with a File SOP, and anything else that reads partio formats.
+    Geo,
+    // Binary little endian PLY point cloud. Opens in Blender, MeshLab, Houdini & co.
+    Ply,
+}
+
+impl ParticleExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ParticleExportFormat::Geo => "geo",
+            ParticleExportFormat::Ply => "ply",
+        }
+    }
+}
+
+// Per particle values of an export, in the order of the fluid particles.
+// The simulation is 2D, points end up in the xy plane with z = 0.
+struct ExportedParticle {
+    position: Point,
+    velocity: Vector, // in m/s
+    density: Real,    // in kg/m²
+    tag: u32,
+}
+
+fn exported_particles(fluid_world: &FluidParticleWorld) -> impl Iterator<Item = ExportedParticle> + '_ {
+    let particles = &fluid_world.particles;
+    let fluid_density = fluid_world.properties.fluid_density();
+    particles
+        .positions
+        .iter()
+        .zip(particles.velocities.iter())
+        .enumerate()
+        .map(move |(i, (position, velocity))| ExportedParticle {
+            position: *position,
+            velocity: *velocity,
+            // densities and tags of particles added since the last step aren't known yet
+            density: particles
+                .densities
+                .get(i)
+                .cloned()
+                .filter(|density| *density > 0.0)
+                .unwrap_or(fluid_density),
+            tag: particles.tags.get(i).cloned().unwrap_or(0),
+        })
+}
+
+// Writes the fluid particles of a single frame, for shading and rendering them in DCC tools.
+// Besides the position, every point has the attributes v (velocity), density, pscale (particle radius, Houdini's point size) and tag.
+// Attributes registered via Particles::register_attribute follow, in PLY files with a property per component named like csv columns.
+// Particles are reordered every step, so there is no stable id; v is enough for motion blur.
+//
+//   write_particles(&mut File::create(format!("particles_{:05}.geo", frame))?, &fluid_world, ParticleExportFormat::Geo)?;
+pub fn write_particles(writer: &mut impl io::Write, fluid_world: &FluidParticleWorld, format: ParticleExportFormat) -> io::Result<()> {
+    match format {
+        ParticleExportFormat::Geo => write_particles_geo(writer, fluid_world),
+        ParticleExportFormat::Ply => write_particles_ply(writer, fluid_world),
+    }
+}
+
+pub fn write_particles_geo(writer: &mut impl io::Write, fluid_world: &FluidParticleWorld) -> io::Result<()> {
+    let num_particles = fluid_world.particles.positions.len();
+    let pscale = fluid_world.properties.particle_radius();
+    let attributes = fluid_world.particles.attributes();
+    // an empty particle primitive isn't valid, without particles there are no primitives at all
+    let num_prims = if num_particles > 0 { 1 } else { 0 };
+    writeln!(writer, "PGEOMETRY V5")?;
+    writeln!(writer, "NPoints {} NPrims {}", num_particles, num_prims)?;
+    writeln!(writer, "NPointGroups 0 NPrimGroups 0")?;
+    writeln!(
+        writer,
+        "NPointAttrib {} NVertexAttrib 0 NPrimAttrib 0 NAttrib 0",
+        4 + attributes.num_attributes()
+    )?;
+    writeln!(writer, "PointAttrib")?;
+    writeln!(writer, "v 3 vector 0 0 0")?;
+    writeln!(writer, "density 1 float 0")?;
+    writeln!(writer, "pscale 1 float 0")?;
+    writeln!(writer, "tag 1 int 0")?;
+    write!(writer, "{}", attributes.geo_header())?;
+    // x y z w (attributes), w is the homogeneous coordinate
+    for (i, particle) in exported_particles(fluid_world).enumerate() {
+        writeln!(
+            writer,
+            "{} {} 0 1 ({} {} 0 {} {} {}{})",
+            particle.position.x,
+            particle.position.y,
+            particle.velocity.x,
+            particle.velocity.y,
+            particle.density,
+            pscale,
+            particle.tag,
+            attributes.geo_values(i)
+        )?;
+    }
+    if num_particles > 0 {
+        write!(writer, "Part {}", num_particles)?;
+        for i in 0..num_particles {
+            write!(writer, " {}", i)?;
+        }
+        writeln!(writer)?;
+    }
+    writeln!(writer, "beginExtra")?;
+    writeln!(writer, "endExtra")?;
+    Ok(())
+}
+
+pub fn write_particles_ply(writer: &mut impl io::Write, fluid_world: &FluidParticleWorld) -> io::Result<()> {
+    let num_particles = fluid_world.particles.positions.len();
+    let pscale = fluid_world.properties.particle_radius();
+    let attributes = fluid_world.particles.attributes();
+    writeln!(writer, "ply")?;
+    writeln!(writer, "format binary_little_endian 1.0")?;
+    writeln!(writer, "comment YaSPH2D fluid particles")?;
+    writeln!(writer, "element vertex {}", num_particles)?;
+    for property in ["x", "y", "z", "vx", "vy", "vz", "density", "pscale"].iter() {
+        writeln!(writer, "property float {}", property)?;
+    }
+    writeln!(writer, "property uint tag")?;
+    write!(writer, "{}", attributes.ply_header())?;
+    writeln!(writer, "end_header")?;
+
+    let mut data = Vec::with_capacity(num_particles * 9 * 4);
+    for (i, particle) in exported_particles(fluid_world).enumerate() {
+        let values = [
+            particle.position.x,
+            particle.position.y,
+            0.0,
+            particle.velocity.x,
+            particle.velocity.y,
+            0.0,
+            particle.density,
+            pscale,
+        ];
+        for value in values.iter() {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(&particle.tag.to_le_bytes());
+        attributes.append_ply_values(i, &mut data);
+    }
+    writer.write_all(&data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn moving_block() -> FluidParticleWorld {
+        let mut fluid_world = FluidParticleWorld::new(2.0, NumberDensity(10000.0), Density(100.0));
+        fluid_world.add_fluid_rect(&Rect::new(0.0, 0.0, 0.1, 0.1), 0.0);
+        for v in fluid_world.particles.velocities.iter_mut() {
+            *v = Vector::new(0.5, -0.25);
+        }
+        fluid_world
+    }
+
+    #[test]
+    fn writes_geo() {
+        let fluid_world = moving_block();
+        let num_particles = fluid_world.particles.positions.len();
+        let mut geo = Vec::new();
+        write_particles(&mut geo, &fluid_world, ParticleExportFormat::Geo).unwrap();
+        let geo = String::from_utf8(geo).unwrap();
+        assert!(geo.starts_with("PGEOMETRY V5\n"));
+        assert!(geo.contains(&format!("NPoints {} NPrims 1\n", num_particles)));
+        // header, a line per point, particle primitive and extra section
+        assert_eq!(geo.lines().count(), 9 + num_particles + 1 + 2);
+        let p = fluid_world.particles.positions[0];
+        let first_point = geo.lines().nth(9).unwrap();
+        assert_eq!(
+            first_point,
+            format!("{} {} 0 1 (0.5 -0.25 0 100 {} 0)", p.x, p.y, fluid_world.properties.particle_radius())
+        );
+    }
+
+    #[test]
+    fn writes_empty_geo() {
+        let fluid_world = FluidParticleWorld::new(2.0, NumberDensity(10000.0), Density(100.0));
+        let mut geo = Vec::new();
+        write_particles(&mut geo, &fluid_world, ParticleExportFormat::Geo).unwrap();
+        let geo = String::from_utf8(geo).unwrap();
+        assert!(geo.contains("NPoints 0 NPrims 0\n"));
+        assert!(!geo.contains("Part"));
+    }
+
+    #[test]
+    fn writes_ply() {
+        let mut fluid_world = moving_block();
+        let num_particles = fluid_world.particles.positions.len();
+        fluid_world.particles.tags = vec![7; num_particles];
+        let mut ply = Vec::new();
+        write_particles(&mut ply, &fluid_world, ParticleExportFormat::Ply).unwrap();
+        let header_end = ply.windows(11).position(|window| window == b"end_header\n").unwrap() + 11;
+        let header = std::str::from_utf8(&ply[..header_end]).unwrap();
+        assert!(header.contains(&format!("element vertex {}\n", num_particles)));
+        assert_eq!(ply.len(), header_end + num_particles * 9 * 4);
+
+        let value = |particle: usize, property: usize| {
+            let offset = header_end + particle * 36 + property * 4;
+            [ply[offset], ply[offset + 1], ply[offset + 2], ply[offset + 3]]
+        };
+        assert_eq!(Real::from_le_bytes(value(1, 0)), fluid_world.particles.positions[1].x);
+        assert_eq!(Real::from_le_bytes(value(1, 4)), -0.25);
+        assert_eq!(Real::from_le_bytes(value(1, 6)), 100.0);
+        assert_eq!(u32::from_le_bytes(value(1, 8)), 7);
+    }
+
+    #[test]
+    fn registered_attributes_round_trip() {
+        let mut fluid_world = moving_block();
+        let num_particles = fluid_world.particles.positions.len();
+        let temperature = fluid_world.particles.register_attribute("temperature", 0.0 as Real);
+        let color = fluid_world.particles.register_attribute("color", Vector::new(0.0, 0.0));
+        let phase = fluid_world.particles.register_attribute("phase", 0_u32);
+        for i in 0..num_particles {
+            fluid_world.particles.attribute_mut(temperature)[i] = 20.0 + i as Real * 0.5;
+            fluid_world.particles.attribute_mut(color)[i] = Vector::new(i as Real * 0.25, -1.0);
+            fluid_world.particles.attribute_mut(phase)[i] = i as u32 % 3;
+        }
+
+        // geo: temperature, color and phase after the built-in attributes, both in the declarations and in every point
+        let mut geo = Vec::new();
+        write_particles(&mut geo, &fluid_world, ParticleExportFormat::Geo).unwrap();
+        let geo = String::from_utf8(geo).unwrap();
+        let lines: Vec<&str> = geo.lines().collect();
+        assert_eq!(lines[3], "NPointAttrib 7 NVertexAttrib 0 NPrimAttrib 0 NAttrib 0");
+        assert_eq!(&lines[9..12], &["temperature 1 float 0", "color 2 float 0 0", "phase 1 int 0"]);
+        for (i, line) in lines[12..12 + num_particles].iter().enumerate() {
+            let point_attributes = &line[line.find('(').unwrap() + 1..line.len() - 1];
+            let values: Vec<Real> = point_attributes.split(' ').map(|value| value.parse().unwrap()).collect();
+            assert_eq!(values.len(), 6 + 4);
+            assert_eq!(values[6], fluid_world.particles.attribute(temperature)[i]);
+            assert_eq!(Vector::new(values[7], values[8]), fluid_world.particles.attribute(color)[i]);
+            assert_eq!(values[9] as u32, fluid_world.particles.attribute(phase)[i]);
+        }
+
+        // ply: a property per component, read back by looking up their offsets in the header
+        let mut ply = Vec::new();
+        write_particles(&mut ply, &fluid_world, ParticleExportFormat::Ply).unwrap();
+        let header_end = ply.windows(11).position(|window| window == b"end_header\n").unwrap() + 11;
+        let header = std::str::from_utf8(&ply[..header_end]).unwrap();
+        let properties: Vec<&str> = header.lines().filter_map(|line| line.strip_prefix("property ")).collect();
+        assert_eq!(&properties[9..], &["float temperature", "float color_x", "float color_y", "uint phase"]);
+        let stride = properties.len() * 4;
+        assert_eq!(ply.len(), header_end + num_particles * stride);
+        let bytes = |particle: usize, property: &str| {
+            let index = properties.iter().position(|p| p.ends_with(&format!(" {}", property))).unwrap();
+            let offset = header_end + particle * stride + index * 4;
+            [ply[offset], ply[offset + 1], ply[offset + 2], ply[offset + 3]]
+        };
+        for i in 0..num_particles {
+            assert_eq!(
+                Real::from_le_bytes(bytes(i, "temperature")),
+                fluid_world.particles.attribute(temperature)[i]
+            );
+            let color_value = Vector::new(Real::from_le_bytes(bytes(i, "color_x")), Real::from_le_bytes(bytes(i, "color_y")));
+            assert_eq!(color_value, fluid_world.particles.attribute(color)[i]);
+            assert_eq!(u32::from_le_bytes(bytes(i, "phase")), fluid_world.particles.attribute(phase)[i]);
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use super::fluidparticleworld::FluidParticleWorld;
+use super::solver::Solver;
+use crate::units::*;
+
+// Tag of the particles PeriodicDomain::update creates as copies, see Particles::tags.
+pub const PERIODIC_COPY_TAG: u32 = u32::MAX;
+
+// Periodic boundary along x, optionally along y as well: fluid leaving the domain [min_x, max_x) on one side re-enters on the other.
+//
+// Instead of looking for neighbors across the domain's ends, copies of the fluid within the smoothing length of either end
+// are placed beyond the opposite end, giving particles close to the ends full kernel support.
+// As with the buffers of open boundaries, copies are regular fluid particles during a step.
+// They are tagged with PERIODIC_COPY_TAG and replaced before every step, so they don't carry any state of their own.
+// Meant to be updated right before every simulation step. Measurements should only look at fluid within the domain.
+pub struct PeriodicDomain {
+    pub min_x: Real,
+    pub max_x: Real,
+    // [min_y, max_y) if the domain is periodic along y as well, see with_periodic_y
+    pub y_range: Option<(Real, Real)>,
+}
+
+// Coordinate wrapped into [min, max).
+fn wrap(value: Real, min: Real, max: Real) -> Real {
+    let wrapped = min + (value - min).rem_euclid(max - min);
+    // rem_euclid may round up to the length itself
+    if wrapped >= max {
+        min
+    } else {
+        wrapped
+    }
+}
+
+// Offsets of the copies of a coordinate within the support of either end of [min, max), 0 for the original.
+fn copy_offsets(value: Real, min: Real, max: Real, support: Real) -> [Option<Real>; 2] {
+    let length = max - min;
+    if value < min + support {
+        [Some(0.0), Some(length)]
+    } else if value >= max - support {
+        [Some(0.0), Some(-length)]
+    } else {
+        [Some(0.0), None]
+    }
+}
+
+impl PeriodicDomain {
+    pub fn new(min_x: Real, max_x: Real) -> PeriodicDomain {
+        PeriodicDomain { min_x, max_x, y_range: None }
+    }
+
+    // Makes the domain periodic along y within [min_y, max_y) as well, copying the fluid across corners.
+    pub fn with_periodic_y(mut self, min_y: Real, max_y: Real) -> PeriodicDomain {
+        self.y_range = Some((min_y, max_y));
+        self
+    }
+
+    pub fn length(&self) -> Real {
+        self.max_x - self.min_x
+    }
+
+    pub fn contains(&self, position: Point) -> bool {
+        let contains_y = match self.y_range {
+            Some((min_y, max_y)) => position.y >= min_y && position.y < max_y,
+            None => true,
+        };
+        position.x >= self.min_x && position.x < self.max_x && contains_y
+    }
+
+    // Removes the copies of the last update, wraps particles that left the domain back into it and copies the fluid at both ends.
+    // Removed copies are removed from the solver's per particle data as well. Returns the number of new copies.
+    pub fn update(&self, fluid_world: &mut FluidParticleWorld, solver: &mut dyn Solver) -> usize {
+        microprofile::scope!("PeriodicDomain", "update");
+        let num_particles = fluid_world.particles.positions.len();
+        fluid_world.particles.tags.resize(num_particles, 0);
+        let keep: Vec<bool> = fluid_world.particles.tags.iter().map(|tag| *tag != PERIODIC_COPY_TAG).collect();
+        if keep.iter().any(|keep| !*keep) {
+            fluid_world.retain_fluid_particles(&keep);
+            solver.retain_particle_data(&keep);
+        }
+
+        for p in fluid_world.particles.positions.iter_mut() {
+            p.x = wrap(p.x, self.min_x, self.max_x);
+            if let Some((min_y, max_y)) = self.y_range {
+                p.y = wrap(p.y, min_y, max_y);
+            }
+        }
+
+        let support = fluid_world.properties.smoothing_length();
+        let particles = &fluid_world.particles;
+        let mut copies: Vec<(Point, Vector, Real)> = Vec::new();
+        for ((p, v), m) in particles.positions.iter().zip(particles.velocities.iter()).zip(particles.masses.iter()) {
+            let offsets_y = match self.y_range {
+                Some((min_y, max_y)) => copy_offsets(p.y, min_y, max_y, support),
+                None => [Some(0.0), None],
+            };
+            // particles close to a corner are copied across both ends and the corner
+            for offset_x in copy_offsets(p.x, self.min_x, self.max_x, support).iter().flatten() {
+                for offset_y in offsets_y.iter().flatten() {
+                    if *offset_x != 0.0 || *offset_y != 0.0 {
+                        copies.push((p + Vector::new(*offset_x, *offset_y), *v, *m));
+                    }
+                }
+            }
+        }
+
+        let num_particles = fluid_world.particles.positions.len();
+        for (position, velocity, mass) in copies.iter() {
+            fluid_world.add_fluid_particle_with_mass(*position, *velocity, *mass);
+        }
+        let tags = &mut fluid_world.particles.tags;
+        tags.resize(num_particles, 0);
+        tags.resize(num_particles + copies.len(), PERIODIC_COPY_TAG);
+        copies.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sph::{WCSPHSolver, XSPHViscosityModel};
+
+    #[test]
+    fn copies_wrap_around_the_domain() {
+        let mut fluid_world = FluidParticleWorld::new(2.0, NumberDensity(10000.0), Density(100.0));
+        fluid_world.add_fluid_rect(&Rect::new(0.0, 0.0, 0.5, 0.1), 0.0);
+        let num_particles = fluid_world.particles.positions.len();
+        let mut solver = WCSPHSolver::new(
+            XSPHViscosityModel::new(fluid_world.properties.smoothing_length()),
+            &fluid_world.properties,
+        );
+        let domain = PeriodicDomain::new(0.0, 0.5);
+
+        // two columns within the smoothing length of each end
+        let num_copies = domain.update(&mut fluid_world, &mut solver);
+        assert_eq!(num_copies, 40);
+        assert_eq!(fluid_world.particles.positions.len(), num_particles + num_copies);
+        let particles = &fluid_world.particles;
+        for (p, tag) in particles.positions.iter().zip(particles.tags.iter()) {
+            assert_eq!(domain.contains(*p), *tag != PERIODIC_COPY_TAG);
+        }
+
+        // particle leaving on the right re-enters on the left, replacing the copies doesn't change the count
+        let particles = &mut fluid_world.particles;
+        let i = (0..num_particles).find(|&i| particles.positions[i].x > 0.48).unwrap();
+        particles.positions[i].x += 0.03;
+        assert_eq!(domain.update(&mut fluid_world, &mut solver), num_copies);
+        assert_eq!(fluid_world.particles.positions.len(), num_particles + num_copies);
+        assert!((fluid_world.particles.positions[i].x - 0.02).abs() < 1.0e-4);
+    }
+
+    #[test]
+    fn copies_wrap_around_corners() {
+        let mut fluid_world = FluidParticleWorld::new(2.0, NumberDensity(10000.0), Density(100.0));
+        fluid_world.add_fluid_rect(&Rect::new(0.0, 0.0, 0.5, 0.5), 0.0);
+        let num_particles = fluid_world.particles.positions.len();
+        let mut solver = WCSPHSolver::new(
+            XSPHViscosityModel::new(fluid_world.properties.smoothing_length()),
+            &fluid_world.properties,
+        );
+        let domain = PeriodicDomain::new(0.0, 0.5).with_periodic_y(0.0, 0.5);
+
+        // two columns & rows within the smoothing length of each end, corners are copied thrice
+        let num_copies = domain.update(&mut fluid_world, &mut solver);
+        assert_eq!(num_copies, 4 * 2 * 50 + 4 * 2 * 2);
+        let particles = &fluid_world.particles;
+        for (p, tag) in particles.positions.iter().zip(particles.tags.iter()) {
+            assert_eq!(domain.contains(*p), *tag != PERIODIC_COPY_TAG);
+        }
+
+        // particle leaving at the top re-enters at the bottom
+        let particles = &mut fluid_world.particles;
+        let i = (0..num_particles).find(|&i| particles.positions[i].y > 0.48).unwrap();
+        particles.positions[i].y += 0.03;
+        assert_eq!(domain.update(&mut fluid_world, &mut solver), num_copies);
+        assert!((fluid_world.particles.positions[i].y - 0.02).abs() < 1.0e-4);
+    }
+}
@@ -0,0 +1,192 @@
+use super::fluidparticleworld::FluidParticleWorld;
+use super::interpolation::{self, Normalization};
+use super::neighborhood_search::ParticleIndex;
+use super::solver::Solver;
+use crate::units::*;
+use cgmath::prelude::*;
+
+// Values measured by a probe at one point in time.
+// All values are Shepard normalized SPH interpolations of the surrounding fluid particles, see interpolation.
+// If there is no fluid around the probe, density, velocity and pressure are zero.
+#[derive(Clone, Copy, Debug)]
+pub struct ProbeSample {
+    pub time: Real,             // simulation time in s
+    pub density: Real,          // in kg/m²
+    pub velocity: Vector,       // in m/s
+    pub pressure: Option<Real>, // None if the solver has no notion of per-particle pressure
+}
+
+// Virtual sensor at a fixed world position, recording a time series of the fluid state.
+// Useful for comparisons with experimental gauge data, e.g. wall pressure in a dam break.
+pub struct Probe {
+    pub name: String,
+    pub position: Point,
+    samples: Vec<ProbeSample>,
+}
+
+impl Probe {
+    pub fn samples(&self) -> &[ProbeSample] {
+        &self.samples
+    }
+
+    pub fn last_sample(&self) -> Option<&ProbeSample> {
+        self.samples.last()
+    }
+
+    fn measure(&self, fluid_world: &FluidParticleWorld, solver: &dyn Solver, time: Real) -> ProbeSample {
+        let particles = &fluid_world.particles;
+        let density = interpolation::interpolate_scalar(fluid_world, self.position, &particles.densities, Normalization::Shepard);
+        let velocity = interpolation::interpolate_vector(fluid_world, self.position, &particles.velocities, Normalization::Shepard);
+
+        // only interpolate pressure if the solver knows about it at all
+        let pressure = solver.particle_pressure(fluid_world, 0).map(|_| {
+            let pressure = |j: usize| solver.particle_pressure(fluid_world, j as ParticleIndex).unwrap_or(0.0);
+            interpolation::interpolate(fluid_world, self.position, pressure, Normalization::Shepard).unwrap_or(0.0)
+        });
+
+        ProbeSample {
+            time,
+            density: density.unwrap_or(0.0),
+            velocity: velocity.unwrap_or_else(Vector::zero),
+            pressure,
+        }
+    }
+}
+
+// Set of probes that are all sampled at the same time.
+#[derive(Default)]
+pub struct Probes {
+    probes: Vec<Probe>,
+}
+
+impl Probes {
+    pub fn new() -> Probes {
+        Default::default()
+    }
+
+    // Adds a probe and returns its index.
+    // Names are used for the csv header, so they should be unique and not contain commas.
+    pub fn add(&mut self, name: &str, position: Point) -> usize {
+        // a probe added later is missing the earlier samples, which would throw off the rows in the csv
+        self.clear_samples();
+        self.probes.push(Probe {
+            name: name.to_string(),
+            position,
+            samples: Vec::new(),
+        });
+        self.probes.len() - 1
+    }
+
+    pub fn remove_all(&mut self) {
+        self.probes.clear();
+    }
+
+    pub fn clear_samples(&mut self) {
+        for probe in self.probes.iter_mut() {
+            probe.samples.clear();
+        }
+    }
+
+    pub fn probes(&self) -> &[Probe] {
+        &self.probes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.probes.is_empty()
+    }
+
+    // Number of times the probes were sampled.
+    pub fn num_samples(&self) -> usize {
+        self.probes.first().map_or(0, |probe| probe.samples.len())
+    }
+
+    // Takes a sample for every probe. Meant to be called after every simulation step.
+    pub fn record(&mut self, fluid_world: &FluidParticleWorld, solver: &dyn Solver, time: Real) {
+        microprofile::scope!("Probes", "record");
+        for probe in self.probes.iter_mut() {
+            let sample = probe.measure(fluid_world, solver, time);
+            probe.samples.push(sample);
+        }
+    }
+
+    // Writes all samples as csv, one row per sample time and four columns (density, velocity x & y, pressure) per probe.
+    // Pressure is left empty if the solver didn't provide it.
+    pub fn write_csv(&self, writer: &mut impl std::io::Write) -> std::io::Result<()> {
+        let header = self
+            .probes
+            .iter()
+            .map(|probe| format!("{0}_density,{0}_velocity_x,{0}_velocity_y,{0}_pressure", probe.name))
+            .collect::<Vec<_>>()
+            .join(",");
+        writeln!(writer, "time_s,{}", header)?;
+
+        for i in 0..self.num_samples() {
+            let row = self
+                .probes
+                .iter()
+                .map(|probe| {
+                    let sample = &probe.samples[i];
+                    format!(
+                        "{},{},{},{}",
+                        sample.density,
+                        sample.velocity.x,
+                        sample.velocity.y,
+                        sample.pressure.map_or(String::new(), |pressure| pressure.to_string())
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(",");
+            writeln!(writer, "{},{}", self.probes[0].samples[i].time, row)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sph::{WCSPHSolver, XSPHViscosityModel};
+
+    #[test]
+    fn record_and_write_csv() {
+        let mut fluid_world = FluidParticleWorld::new(2.0, NumberDensity(5000.0), Density(100.0));
+        fluid_world.force_fields.clear();
+        fluid_world.add_fluid_rect(&Rect::new(0.0, 0.0, 0.5, 0.5), 0.0);
+        let mut solver = WCSPHSolver::new(
+            XSPHViscosityModel::new(fluid_world.properties.smoothing_length()),
+            &fluid_world.properties,
+        );
+        let mut time_manager = crate::sph::TimeManager::new(crate::sph::TimeManagerConfiguration::FixedTimeStep(0.001));
+
+        let mut probes = Probes::new();
+        probes.add("inside", Point::new(0.25, 0.25));
+        probes.add("outside", Point::new(2.0, 2.0));
+        for _ in 0..3 {
+            solver.simulation_step(&mut fluid_world, &mut time_manager).unwrap();
+            probes.record(&fluid_world, &solver, time_manager.passed_time());
+        }
+        assert_eq!(probes.num_samples(), 3);
+
+        let inside = probes.probes()[0].last_sample().unwrap();
+        assert_lt!(
+            (inside.density - fluid_world.properties.fluid_density()).abs(),
+            fluid_world.properties.fluid_density() * 0.05
+        );
+        assert_lt!(inside.velocity.magnitude(), 0.01);
+        assert!(inside.pressure.is_some());
+        let outside = probes.probes()[1].last_sample().unwrap();
+        assert_eq!(outside.density, 0.0);
+        assert_eq!(outside.pressure, Some(0.0));
+
+        let mut csv = Vec::new();
+        probes.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            lines[0],
+            "time_s,inside_density,inside_velocity_x,inside_velocity_y,inside_pressure,outside_density,outside_velocity_x,outside_velocity_y,outside_pressure"
+        );
+        assert!(lines.iter().all(|line| line.split(',').count() == 9));
+    }
+}
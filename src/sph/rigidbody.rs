@@ -0,0 +1,165 @@
+use super::fluidparticleworld::{FluidParticleWorld, MovingBoundary};
+use crate::units::*;
+use cgmath::prelude::*;
+
+// Solid polygon that is moved by the forces the fluid exerts on its boundary particles (two-way coupling).
+//
+// The body is a moving boundary with a single layer of boundary particles along its outline.
+// Fluid forces are those of the last simulation step, so bodies are integrated explicitly right after every step.
+// Like the fluid, bodies are 2D: masses are in kg/m, moments of inertia in kg m²/m.
+pub struct RigidBody {
+    pub mass: Real,
+    pub inertia: Real,   // around the center of mass
+    pub position: Point, // center of mass
+    pub angle: Real,     // in radians, counter clockwise from the rest pose
+    pub velocity: Vector,
+    pub angular_velocity: Real, // in radians per second, counter clockwise
+    rest_position: Point,
+    outline: Vec<Vector>, // relative to the center of mass at rest
+    boundary: MovingBoundary,
+}
+
+// Area, centroid and polar second moment of area around the centroid of a simple polygon.
+fn polygon_mass_properties(vertices: &[Point]) -> (Real, Point, Real) {
+    let mut twice_area = 0.0;
+    let mut centroid = Vector::zero();
+    let mut second_moment = 0.0; // around the origin, times 12
+    for (i, a) in vertices.iter().enumerate() {
+        let (a, b) = (a.to_vec(), vertices[(i + 1) % vertices.len()].to_vec());
+        let cross = a.perp_dot(b);
+        twice_area += cross;
+        centroid += (a + b) * cross;
+        second_moment += cross * (a.dot(a) + a.dot(b) + b.dot(b));
+    }
+    let area = twice_area * 0.5;
+    let centroid = centroid / (6.0 * area);
+    let second_moment = second_moment / 12.0 - area * centroid.magnitude2();
+    // vertices in clockwise order give negative area and moment
+    (area.abs(), Point::from_vec(centroid), second_moment.abs())
+}
+
+impl RigidBody {
+    // Adds a body with the given closed outline. `density_ratio` is the body's density relative to the fluid's rest density.
+    pub fn new(fluid_world: &mut FluidParticleWorld, vertices: &[Point], density_ratio: Real) -> RigidBody {
+        let (area, center, second_moment) = polygon_mass_properties(vertices);
+        let density = density_ratio * fluid_world.properties.fluid_density();
+        let boundary = fluid_world.add_moving_boundary(|fluid_world| fluid_world.add_boundary_polygon(vertices));
+        RigidBody {
+            mass: density * area,
+            inertia: density * second_moment,
+            position: center,
+            angle: 0.0,
+            velocity: Vector::zero(),
+            angular_velocity: 0.0,
+            rest_position: center,
+            outline: vertices.iter().map(|v| v - center).collect(),
+            boundary,
+        }
+    }
+
+    // Axis aligned box around a center.
+    pub fn new_box(fluid_world: &mut FluidParticleWorld, center: Point, width: Real, height: Real, density_ratio: Real) -> RigidBody {
+        let half_extent = Vector::new(width, height) * 0.5;
+        let vertices = [
+            center - half_extent,
+            center + Vector::new(half_extent.x, -half_extent.y),
+            center + half_extent,
+            center + Vector::new(-half_extent.x, half_extent.y),
+        ];
+        RigidBody::new(fluid_world, &vertices, density_ratio)
+    }
+
+    fn rotate(&self, v: Vector) -> Vector {
+        let (sin, cos) = self.angle.sin_cos();
+        Vector::new(v.x * cos - v.y * sin, v.x * sin + v.y * cos)
+    }
+
+    // Current outline in world space.
+    pub fn outline(&self) -> Vec<Point> {
+        self.outline.iter().map(|v| self.position + self.rotate(*v)).collect()
+    }
+
+    // Depth in m of the body's lowest point below a water level.
+    // Note that the fluid keeps about a particle radius away from the outline, the displaced volume is slightly larger than the outline suggests.
+    pub fn draught(&self, water_level: Real) -> Real {
+        let lowest = self.outline().iter().map(|p| p.y).fold(Real::INFINITY, Real::min);
+        water_level - lowest
+    }
+
+    // Applies the fluid forces of the last simulation step and gravity, then moves the body's boundary accordingly.
+    // Meant to be called right after every simulation step with that step's timestep.
+    pub fn update(&mut self, fluid_world: &mut FluidParticleWorld, dt: Real) {
+        let (force, torque) = fluid_world.moving_boundary_force(self.boundary, self.position);
+        let force = force + fluid_world.gravity() * self.mass;
+
+        // symplectic Euler
+        self.velocity += force / self.mass * dt;
+        self.angular_velocity += torque / self.inertia * dt;
+        self.position += self.velocity * dt;
+        self.angle += self.angular_velocity * dt;
+
+        let (sin, cos) = self.angle.sin_cos();
+        let (rest_position, position, velocity, angular_velocity) = (self.rest_position, self.position, self.velocity, self.angular_velocity);
+        fluid_world.move_boundary(
+            self.boundary,
+            |p| {
+                let r = p - rest_position;
+                position + Vector::new(r.x * cos - r.y * sin, r.x * sin + r.y * cos)
+            },
+            |p| {
+                let r = p - position;
+                velocity + Vector::new(-r.y, r.x) * angular_velocity
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn box_mass_properties() {
+        let mut fluid_world = FluidParticleWorld::new(2.0, NumberDensity(1000.0), Density(1000.0));
+        let body = RigidBody::new_box(&mut fluid_world, Point::new(1.0, 2.0), 0.4, 0.2, 0.5);
+        assert_lt!((body.mass - 0.5 * 1000.0 * 0.08).abs(), 1.0e-3);
+        // I = m (w² + h²) / 12
+        assert_lt!((body.inertia - body.mass * (0.16 + 0.04) / 12.0).abs(), 1.0e-4);
+        assert_lt!(body.position.distance(Point::new(1.0, 2.0)), 1.0e-5);
+        assert_lt!((body.draught(2.0) - 0.1).abs(), 1.0e-5);
+
+        // same properties for clockwise vertices
+        let (area, centroid, second_moment) =
+            polygon_mass_properties(&[Point::new(0.0, 0.0), Point::new(0.0, 1.0), Point::new(2.0, 1.0), Point::new(2.0, 0.0)]);
+        assert_lt!((area - 2.0).abs(), 1.0e-5);
+        assert_lt!(centroid.distance(Point::new(1.0, 0.5)), 1.0e-5);
+        assert_lt!((second_moment - 2.0 * 5.0 / 12.0).abs(), 1.0e-5);
+    }
+
+    #[test]
+    fn free_fall_moves_boundary() {
+        let mut fluid_world = FluidParticleWorld::new(2.0, NumberDensity(1000.0), Density(1.0));
+        let mut body = RigidBody::new_box(&mut fluid_world, Point::new(0.0, 0.0), 0.4, 0.2, 0.5);
+        let rest_positions = fluid_world.particles.boundary_particles.clone();
+        body.angular_velocity = 1.0;
+        for _ in 0..10 {
+            body.update(&mut fluid_world, 0.01);
+        }
+        assert_lt!((body.velocity - fluid_world.gravity() * 0.1).magnitude(), 1.0e-4);
+        assert_lt!((body.angle - 0.1).abs(), 1.0e-5);
+
+        let offset = body.position - Point::new(0.0, 0.0);
+        for (p, (v, rest)) in fluid_world
+            .particles
+            .boundary_particles
+            .iter()
+            .zip(fluid_world.particles.boundary_velocities.iter().zip(rest_positions.iter()))
+        {
+            // rigid motion keeps distances to the center, velocities follow the rotation
+            assert_lt!((p.distance(body.position) - rest.distance(Point::new(0.0, 0.0))).abs(), 1.0e-5);
+            let r = p - body.position;
+            assert_lt!((v - body.velocity - Vector::new(-r.y, r.x)).magnitude(), 1.0e-5);
+            assert_lt!((p - rest).magnitude(), offset.magnitude() + rest.to_vec().magnitude() * 0.1 + 1.0e-5);
+        }
+    }
+}
@@ -0,0 +1,725 @@
+use super::fluidparticleworld::{BoundaryGeometry, FluidParticleWorld};
+use super::forcefield::ForceField;
+use super::openboundary::{InflowProfile, Inlet, Outlet};
+use super::periodic::PeriodicDomain;
+use super::probes::Probes;
+use super::rigidbody::RigidBody;
+use super::wavemaker::{WaveMaker, WaveMakerMotion};
+use crate::units::*;
+use cgmath::prelude::*;
+
+// Creates a world with still fluid in the bottom left corner of a tank whose bottom left corner is at the origin.
+// The fluid keeps a particle spacing of distance to the tank walls, particles too close to boundaries get pushed away violently.
+// With a right wall, the fluid keeps that distance to x = width as well, otherwise it extends up to width (like the dam break's column).
+fn fill_tank(particle_density: NumberDensity, fluid_density: Density, width: Real, height: Real, right_wall: bool) -> FluidParticleWorld {
+    let mut fluid_world = FluidParticleWorld::new(2.0, particle_density, fluid_density);
+    let spacing = fluid_world.properties.particle_radius() * 2.0;
+    let right_clearance = if right_wall { spacing } else { 0.0 };
+    fluid_world.add_fluid_rect(&Rect::new(spacing, spacing, width - spacing - right_clearance, height - spacing), 0.0);
+    fluid_world
+}
+
+// Dam break: A rectangular column of fluid in the left corner of a tank collapses under gravity.
+// Tank walls are made of boundary particles outside of the tank rectangle, the tank's bottom left corner is at the origin.
+pub struct DamBreak {
+    pub column_width: Real, // denoted as a in Martin & Moyce
+    pub column_height: Real,
+    pub tank_width: Real,
+    pub tank_height: Real,
+}
+
+// Surge front position over time for a column with height 2a (n² = 2) from
+// Martin & Moyce 1952, "An experimental study of the collapse of liquid columns on a rigid horizontal plane".
+// Dimensionless time T = t sqrt(2g / a) and front position Z = z / a.
+// Values are digitized from the plot, as commonly reproduced in SPH literature (e.g. Koshizuka & Oka 1996).
+pub const MARTIN_MOYCE_SURGE_FRONT: [(Real, Real); 15] = [
+    (0.00, 1.00),
+    (0.41, 1.11),
+    (0.84, 1.22),
+    (1.19, 1.44),
+    (1.43, 1.67),
+    (1.63, 1.89),
+    (1.83, 2.11),
+    (1.98, 2.33),
+    (2.20, 2.56),
+    (2.32, 2.78),
+    (2.51, 3.00),
+    (2.65, 3.22),
+    (2.83, 3.44),
+    (2.98, 3.67),
+    (3.11, 3.89),
+];
+
+impl DamBreak {
+    // Setup of the Martin & Moyce experiment with n² = 2, i.e. a column twice as high as wide.
+    pub fn martin_moyce(column_width: Real) -> DamBreak {
+        DamBreak {
+            column_width,
+            column_height: column_width * 2.0,
+            tank_width: column_width * 5.0,
+            tank_height: column_width * 3.0,
+        }
+    }
+
+    pub fn create_world(&self, particle_density: NumberDensity, fluid_density: Density) -> FluidParticleWorld {
+        let mut fluid_world = fill_tank(particle_density, fluid_density, self.column_width, self.column_height, false);
+
+        // thick lines grow to the right of the line direction, so all walls are oriented clockwise
+        let (w, h) = (self.tank_width, self.tank_height);
+        fluid_world.add_boundary_thick_line(Point::new(0.0, 0.0), Point::new(w, 0.0), 2);
+        fluid_world.add_boundary_thick_line(Point::new(0.0, h), Point::new(0.0, 0.0), 2);
+        fluid_world.add_boundary_thick_line(Point::new(w, 0.0), Point::new(w, h), 2);
+        fluid_world.add_boundary_thick_line(Point::new(w, h), Point::new(0.0, h), 2);
+        fluid_world
+    }
+
+    // Distance of the right most fluid particle's edge from the left tank wall.
+    pub fn surge_front(&self, fluid_world: &FluidParticleWorld) -> Real {
+        let particle_radius = fluid_world.properties.particle_radius();
+        fluid_world.particles.positions.iter().map(|p| p.x).fold(0.0, Real::max) + particle_radius
+    }
+
+    // Dimensionless time T = t sqrt(2g / a) as used by Martin & Moyce.
+    pub fn dimensionless_time(&self, time: Real, gravity: Real) -> Real {
+        time * (2.0 * gravity / self.column_width).sqrt()
+    }
+
+    // Interpolated front position Z = z / a from MARTIN_MOYCE_SURGE_FRONT, None outside of the measured time range.
+    pub fn reference_surge_front(dimensionless_time: Real) -> Option<Real> {
+        MARTIN_MOYCE_SURGE_FRONT.windows(2).find_map(|w| {
+            let ((t0, z0), (t1, z1)) = (w[0], w[1]);
+            if dimensionless_time >= t0 && dimensionless_time <= t1 {
+                Some(z0 + (z1 - z0) * (dimensionless_time - t0) / (t1 - t0))
+            } else {
+                None
+            }
+        })
+    }
+}
+
+// Sloshing tank: A partially filled rectangular tank is shaken horizontally.
+// Shaking close to the tank's natural frequency makes the free surface slosh up and down the side walls with growing amplitude.
+// Like the dam break, the tank's bottom left corner is at the origin and its walls are outside of the tank rectangle.
+pub struct SloshingTank {
+    pub tank_width: Real, // denoted as L
+    pub tank_height: Real,
+    pub fill_height: Real,       // denoted as h
+    pub shaking_amplitude: Real, // horizontal displacement of the tank in m
+    pub shaking_frequency: Real, // in Hz
+}
+
+impl SloshingTank {
+    pub fn create_world(&self, particle_density: NumberDensity, fluid_density: Density) -> FluidParticleWorld {
+        let mut fluid_world = fill_tank(particle_density, fluid_density, self.tank_width, self.fill_height, true);
+        fluid_world.force_fields.push(ForceField::Shaking {
+            amplitude: Vector::new(self.shaking_amplitude, 0.0),
+            frequency: self.shaking_frequency,
+        });
+
+        // thick lines grow to the right of the line direction, so all walls are oriented clockwise
+        let (w, h) = (self.tank_width, self.tank_height);
+        fluid_world.add_boundary_thick_line(Point::new(0.0, 0.0), Point::new(w, 0.0), 2);
+        fluid_world.add_boundary_thick_line(Point::new(0.0, h), Point::new(0.0, 0.0), 2);
+        fluid_world.add_boundary_thick_line(Point::new(w, 0.0), Point::new(w, h), 2);
+        fluid_world.add_boundary_thick_line(Point::new(w, h), Point::new(0.0, h), 2);
+        fluid_world
+    }
+
+    // Frequency in Hz of the first sloshing mode from linear wave theory, ω² = g k tanh(k h) with k = π / L.
+    pub fn natural_frequency(&self, gravity: Real) -> Real {
+        let k = std::f64::consts::PI as Real / self.tank_width;
+        (gravity * k * (k * self.fill_height).tanh()).sqrt() / (2.0 * std::f64::consts::PI as Real)
+    }
+
+    // Height of the free surface at the left and right tank wall, i.e. the highest fluid particle's edge within a band along the wall.
+    pub fn wall_elevations(&self, fluid_world: &FluidParticleWorld) -> (Real, Real) {
+        let particle_radius = fluid_world.properties.particle_radius();
+        let band_width = fluid_world.properties.smoothing_length();
+        let mut elevations: (Real, Real) = (0.0, 0.0);
+        for p in fluid_world.particles.positions.iter() {
+            if p.x < band_width {
+                elevations.0 = elevations.0.max(p.y + particle_radius);
+            } else if p.x > self.tank_width - band_width {
+                elevations.1 = elevations.1.max(p.y + particle_radius);
+            }
+        }
+        elevations
+    }
+}
+
+// Channel flow: Fluid enters a straight channel through an inlet on the left and leaves it through an outlet on the right.
+// The channel's walls run along y = 0 and y = channel_height, the inlet is at x = 0 and the outlet at x = channel_length.
+// There is no gravity, the flow is driven by the inlet alone.
+pub struct ChannelFlow {
+    pub channel_length: Real,
+    pub channel_height: Real,
+    pub inflow: InflowProfile,
+}
+
+impl ChannelFlow {
+    // Fluid filling the channel and both buffer zones, moving with the mean inflow speed.
+    pub fn create_world(&self, particle_density: NumberDensity, fluid_density: Density) -> (FluidParticleWorld, Inlet, Outlet) {
+        let mut fluid_world = FluidParticleWorld::new(2.0, particle_density, fluid_density);
+        fluid_world.force_fields.clear();
+        let spacing = fluid_world.properties.particle_radius() * 2.0;
+        let buffer_depth = (fluid_world.properties.smoothing_length() / spacing).ceil() * spacing;
+        let (l, h) = (self.channel_length, self.channel_height);
+
+        let inlet = Inlet::new(Point::new(0.0, 0.0), Point::new(0.0, h), buffer_depth, self.inflow);
+        inlet.fill_buffer(&mut fluid_world);
+        let num_rows = (h / spacing).round() as usize;
+        let num_columns = ((l + buffer_depth) / spacing).round() as usize;
+        let row_spacing = h / num_rows as Real;
+        let velocity = Vector::new(self.inflow.mean_speed(), 0.0);
+        for x in 0..num_columns {
+            for y in 0..num_rows {
+                // cell centered like the inlet's columns, so that the fluid continues the inlet's lattice
+                let position = Point::new(x as Real * spacing, (y as Real + 0.5) * row_spacing);
+                fluid_world.add_fluid_particle(position, velocity);
+            }
+        }
+        let outlet = Outlet::new(Point::new(l, 0.0), Point::new(l, h), buffer_depth);
+
+        // thick lines grow to the right of the line direction, walls extend one spacing beyond both buffers
+        // so that buffer particles never see the end of a wall
+        let (start, end) = (-buffer_depth - spacing, l + buffer_depth + spacing);
+        fluid_world.add_boundary_thick_line(Point::new(start, -spacing * 0.5), Point::new(end, -spacing * 0.5), 2);
+        fluid_world.add_boundary_thick_line(Point::new(end, h + spacing * 0.5), Point::new(start, h + spacing * 0.5), 2);
+        (fluid_world, inlet, outlet)
+    }
+}
+
+// Flow past a cylinder: Uniform flow through a channel (see ChannelFlow) around a cylinder, the classic drag benchmark.
+// At Reynolds numbers Re = U D / ν above about 47 the wake turns unsteady and sheds vortices (Kármán vortex street),
+// with the lift oscillating around zero at the shedding frequency.
+// The reference values are for an unbounded flow, channel walls raise the drag noticeably unless the channel is much higher than the cylinder.
+pub struct FlowPastCylinder {
+    pub channel_length: Real,
+    pub channel_height: Real,
+    pub cylinder_center: Point,
+    pub cylinder_radius: Real,
+    pub inflow_speed: Real, // denoted as U
+}
+
+// Drag coefficient of a circular cylinder in unbounded flow over the Reynolds number.
+// Steady flow (Re <= 40) from Dennis & Chang 1970, "Numerical solutions for steady flow past a circular cylinder at Reynolds numbers up to 100",
+// time averages of the unsteady flow from Park, Kwon & Choi 1998, "Numerical solutions of flow past a circular cylinder at Reynolds numbers up to 160".
+pub const CYLINDER_DRAG_COEFFICIENTS: [(Real, Real); 8] = [
+    (5.0, 4.116),
+    (10.0, 2.846),
+    (20.0, 2.045),
+    (40.0, 1.522),
+    (60.0, 1.39),
+    (80.0, 1.35),
+    (100.0, 1.33),
+    (160.0, 1.32),
+];
+
+// Dimensionless drag (in flow direction) and lift (perpendicular to it) of a body, C = 2 F / (ρ U² D).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ForceCoefficients {
+    pub drag: Real,
+    pub lift: Real,
+}
+
+impl FlowPastCylinder {
+    // Channel filled with fluid moving at the inflow speed, except for the cylinder.
+    pub fn create_world(&self, particle_density: NumberDensity, fluid_density: Density) -> (FluidParticleWorld, Inlet, Outlet) {
+        let channel = ChannelFlow {
+            channel_length: self.channel_length,
+            channel_height: self.channel_height,
+            inflow: InflowProfile::Uniform(self.inflow_speed),
+        };
+        let (mut fluid_world, inlet, outlet) = channel.create_world(particle_density, fluid_density);
+
+        // keep the same distance to the cylinder as fill_tank keeps to tank walls
+        let spacing = fluid_world.properties.particle_radius() * 2.0;
+        let clearance_sq = (self.cylinder_radius + spacing) * (self.cylinder_radius + spacing);
+        let keep: Vec<bool> = fluid_world
+            .particles
+            .positions
+            .iter()
+            .map(|p| p.distance2(self.cylinder_center) >= clearance_sq)
+            .collect();
+        fluid_world.retain_fluid_particles(&keep);
+        fluid_world.add_boundary_circle(self.cylinder_center, self.cylinder_radius);
+        (fluid_world, inlet, outlet)
+    }
+
+    pub fn reynolds_number(&self, kinematic_viscosity: Real) -> Real {
+        self.inflow_speed * self.cylinder_radius * 2.0 / kinematic_viscosity
+    }
+
+    // Kinematic viscosity in m²/s that gives the flow a Reynolds number.
+    // Viscosity models take a dynamic viscosity, which is this times the fluid density.
+    pub fn kinematic_viscosity(&self, reynolds_number: Real) -> Real {
+        self.inflow_speed * self.cylinder_radius * 2.0 / reynolds_number
+    }
+
+    // Drag and lift from the force the fluid exerted on the cylinder during the last simulation step.
+    pub fn force_coefficients(&self, fluid_world: &FluidParticleWorld) -> ForceCoefficients {
+        let cylinder = fluid_world.boundary_geometry().iter().position(|geometry| match geometry {
+            BoundaryGeometry::Circle { center, radius, .. } => *center == self.cylinder_center && *radius == self.cylinder_radius,
+            _ => false,
+        });
+        let force = match cylinder {
+            Some(cylinder) => fluid_world.boundary_object_forces()[cylinder].force,
+            None => return Default::default(),
+        };
+        let dynamic_pressure = 0.5 * fluid_world.properties.fluid_density() * self.inflow_speed * self.inflow_speed;
+        let reference_force = dynamic_pressure * self.cylinder_radius * 2.0;
+        ForceCoefficients {
+            drag: force.x / reference_force,
+            lift: force.y / reference_force,
+        }
+    }
+
+    // Probes upstream of the cylinder, where the flow is still undisturbed, and in its wake, off the center line to pick up the shedding vortices.
+    pub fn wake_probes(&self) -> Probes {
+        let diameter = self.cylinder_radius * 2.0;
+        let mut probes = Probes::new();
+        probes.add("upstream", self.cylinder_center - Vector::new(diameter * 1.5, 0.0));
+        probes.add("wake", self.cylinder_center + Vector::new(diameter * 2.0, self.cylinder_radius * 0.5));
+        probes
+    }
+
+    // Interpolated drag coefficient from CYLINDER_DRAG_COEFFICIENTS, None outside of the tabulated Reynolds numbers.
+    pub fn reference_drag_coefficient(reynolds_number: Real) -> Option<Real> {
+        CYLINDER_DRAG_COEFFICIENTS.windows(2).find_map(|w| {
+            let ((re0, cd0), (re1, cd1)) = (w[0], w[1]);
+            if reynolds_number >= re0 && reynolds_number <= re1 {
+                Some(cd0 + (cd1 - cd0) * (reynolds_number - re0) / (re1 - re0))
+            } else {
+                None
+            }
+        })
+    }
+}
+
+// Poiseuille flow: Fluid between two parallel no-slip walls, driven along them by a uniform body force g, in a channel that is periodic along x.
+// Starting from rest, the flow develops into the parabolic profile u(y) = g y (H - y) / (2 ν), getting within 1% of it after about 0.5 H² / ν.
+// The wall surfaces are at y = 0 and y = channel_height, as seen by ghost particles (see WCSPHBoundaryHandling::GhostParticles),
+// which are needed for no-slip walls. The periodic domain spans [0, channel_length).
+pub struct PoiseuilleFlow {
+    pub channel_length: Real,
+    pub channel_height: Real, // denoted as H
+    pub body_force: Real,     // accelleration along the channel in m/s², denoted as g
+}
+
+impl PoiseuilleFlow {
+    // Channel filled with resting fluid. Needs to be updated with PeriodicDomain::update before every step.
+    pub fn create_world(&self, particle_density: NumberDensity, fluid_density: Density) -> (FluidParticleWorld, PeriodicDomain) {
+        let mut fluid_world = FluidParticleWorld::new(2.0, particle_density, fluid_density);
+        fluid_world.set_gravity(Vector::new(self.body_force, 0.0));
+        let spacing = fluid_world.properties.particle_radius() * 2.0;
+        let (l, h) = (self.channel_length, self.channel_height);
+
+        // Lattice needs to fit the channel exactly to continue seamlessly across the periodic ends and mirror seamlessly at the walls.
+        let num_rows = (h / spacing).round() as usize;
+        let num_columns = (l / spacing).round() as usize;
+        let (row_spacing, column_spacing) = (h / num_rows as Real, l / num_columns as Real);
+        for x in 0..num_columns {
+            for y in 0..num_rows {
+                let position = Point::new((x as Real + 0.5) * column_spacing, (y as Real + 0.5) * row_spacing);
+                fluid_world.add_fluid_particle(position, Vector::zero());
+            }
+        }
+
+        // thick lines grow to the right of the line direction, their surface is half a spacing from the line
+        // walls extend beyond the periodic ends, where the domain places copies of the fluid
+        let (start, end) = (
+            -fluid_world.properties.smoothing_length() * 2.0,
+            l + fluid_world.properties.smoothing_length() * 2.0,
+        );
+        fluid_world.add_boundary_thick_line(Point::new(start, spacing * 0.5), Point::new(end, spacing * 0.5), 2);
+        fluid_world.add_boundary_thick_line(Point::new(end, h - spacing * 0.5), Point::new(start, h - spacing * 0.5), 2);
+        (fluid_world, PeriodicDomain::new(0.0, l))
+    }
+
+    // Steady state velocity along the channel at height y.
+    pub fn analytic_velocity(&self, y: Real, kinematic_viscosity: Real) -> Real {
+        self.body_force * y * (self.channel_height - y) / (2.0 * kinematic_viscosity)
+    }
+
+    // Average velocity along the channel of the particles within each of `num_layers` horizontal layers of the channel,
+    // as pairs of layer center height and velocity. Ignores fluid outside of the periodic domain. Layers without particles are skipped.
+    pub fn velocity_profile(&self, fluid_world: &FluidParticleWorld, num_layers: usize) -> Vec<(Real, Real)> {
+        let layer_height = self.channel_height / num_layers as Real;
+        let mut sums = vec![(0.0, 0); num_layers];
+        let particles = &fluid_world.particles;
+        for (p, v) in particles.positions.iter().zip(particles.velocities.iter()) {
+            if p.x < 0.0 || p.x >= self.channel_length || p.y < 0.0 || p.y >= self.channel_height {
+                continue;
+            }
+            let (sum, count) = &mut sums[((p.y / layer_height) as usize).min(num_layers - 1)];
+            *sum += v.x;
+            *count += 1;
+        }
+        sums.iter()
+            .enumerate()
+            .filter(|(_, (_, count))| *count > 0)
+            .map(|(layer, (sum, count))| ((layer as Real + 0.5) * layer_height, sum / *count as Real))
+            .collect()
+    }
+}
+
+// Wave tank: A wave maker at the left end of a tank with still water generates regular waves traveling to the right.
+// The paddle's bottom is at the origin, the tank's floor and right wall are outside of the tank rectangle.
+// Waves reflect off the right wall, so measurements are only meaningful until the first reflection comes back.
+pub struct WaveTank {
+    pub tank_length: Real,
+    pub tank_height: Real,
+    pub water_depth: Real, // still water depth, denoted as h
+    pub motion: WaveMakerMotion,
+    pub frequency: Real, // of the wave maker in Hz
+}
+
+impl WaveTank {
+    pub fn create_world(&self, particle_density: NumberDensity, fluid_density: Density) -> (FluidParticleWorld, WaveMaker) {
+        let mut fluid_world = fill_tank(particle_density, fluid_density, self.tank_length, self.water_depth, true);
+
+        // thick lines grow to the right of the line direction, so all walls are oriented clockwise
+        let (l, h) = (self.tank_length, self.tank_height);
+        fluid_world.add_boundary_thick_line(Point::new(0.0, 0.0), Point::new(l, 0.0), 2);
+        fluid_world.add_boundary_thick_line(Point::new(l, 0.0), Point::new(l, h), 2);
+        let wave_maker = WaveMaker::new(&mut fluid_world, Point::new(0.0, 0.0), h, self.motion, self.frequency);
+        (fluid_world, wave_maker)
+    }
+}
+
+// Floating box: A rigid box is released at the surface of still water in the middle of a tank and settles at its equilibrium draught.
+// Tank walls are made of boundary particles outside of the tank rectangle, the tank's bottom left corner is at the origin.
+// By Archimedes' principle, the box displaces its own weight of fluid. In equilibrium it floats upright as long as it is wide enough.
+pub struct FloatingBox {
+    pub tank_width: Real,
+    pub tank_height: Real,
+    pub water_depth: Real,
+    pub box_width: Real,
+    pub box_height: Real,
+    pub density_ratio: Real, // of the box relative to the fluid
+}
+
+impl FloatingBox {
+    pub fn create_world(&self, particle_density: NumberDensity, fluid_density: Density) -> (FluidParticleWorld, RigidBody) {
+        let mut fluid_world = fill_tank(particle_density, fluid_density, self.tank_width, self.water_depth, true);
+        let spacing = fluid_world.properties.particle_radius() * 2.0;
+
+        // thick lines grow to the right of the line direction, so all walls are oriented clockwise
+        let (w, h) = (self.tank_width, self.tank_height);
+        fluid_world.add_boundary_thick_line(Point::new(0.0, 0.0), Point::new(w, 0.0), 2);
+        fluid_world.add_boundary_thick_line(Point::new(0.0, h), Point::new(0.0, 0.0), 2);
+        fluid_world.add_boundary_thick_line(Point::new(w, 0.0), Point::new(w, h), 2);
+
+        // box bottom right above the surface
+        let center = Point::new(w * 0.5, self.water_depth + spacing * 0.5 + self.box_height * 0.5);
+        let body = RigidBody::new_box(&mut fluid_world, center, self.box_width, self.box_height, self.density_ratio);
+        (fluid_world, body)
+    }
+
+    // Depth of the box's bottom below the surface in equilibrium.
+    pub fn expected_draught(&self) -> Real {
+        self.density_ratio * self.box_height
+    }
+}
+
+// Oscillating droplet: A droplet in zero gravity starts out as an ellipse at rest and oscillates around its circular equilibrium,
+// driven by surface tension alone. The droplet is centered at the origin.
+// For small deformations, the period of this second mode follows Rayleigh's formula, which makes the scene a benchmark for surface tension models.
+// Without surface tension there is no restoring force and the droplet just stays elliptic.
+pub struct OscillatingDroplet {
+    pub radius: Real,      // of the circle with the same area, denoted as R
+    pub deformation: Real, // ε, the initial semi-axes are R (1 + ε) along x and R / (1 + ε) along y
+}
+
+impl OscillatingDroplet {
+    // Blue noise instead of a lattice, a lattice's corners make the droplet's outline ragged.
+    pub fn create_world(&self, particle_density: NumberDensity, fluid_density: Density) -> FluidParticleWorld {
+        let mut fluid_world = FluidParticleWorld::new(2.0, particle_density, fluid_density);
+        fluid_world.force_fields.clear();
+        let (a, b) = (self.radius * (1.0 + self.deformation), self.radius / (1.0 + self.deformation));
+        fluid_world.add_fluid_blue_noise(&Rect::new(-a, -b, a * 2.0, b * 2.0), |p| {
+            (p.x / a) * (p.x / a) + (p.y / b) * (p.y / b) <= 1.0
+        });
+        fluid_world
+    }
+
+    // Analytic period in s of the second oscillation mode of a 2D droplet (i.e. a liquid cylinder) from Rayleigh's formula,
+    // ω² = n (n² - 1) σ / (ρ R³) with n = 2. Surface tension σ in N/m and the density in kg/m², same as in the simulation.
+    pub fn rayleigh_period(&self, surface_tension: Real, fluid_density: Real) -> Real {
+        let angular_frequency = (6.0 * surface_tension / (fluid_density * self.radius.powi(3))).sqrt();
+        2.0 * std::f64::consts::PI as Real / angular_frequency
+    }
+
+    // Elongation along x relative to y from the second moments of the droplet's mass distribution around its center of mass,
+    // (Ixx - Iyy) / (Ixx + Iyy). Zero for a circle, positive if wider than high, ((1+ε)⁴ - 1) / ((1+ε)⁴ + 1) for the initial ellipse.
+    pub fn measure_deformation(fluid_world: &FluidParticleWorld) -> Real {
+        let particles = &fluid_world.particles;
+        let total_mass: Real = particles.masses.iter().sum();
+        if total_mass <= 0.0 {
+            return 0.0;
+        }
+        let center_of_mass = particles
+            .positions
+            .iter()
+            .zip(particles.masses.iter())
+            .map(|(p, m)| p.to_vec() * *m)
+            .sum::<Vector>()
+            / total_mass;
+        let (mut ixx, mut iyy) = (0.0, 0.0);
+        for (p, m) in particles.positions.iter().zip(particles.masses.iter()) {
+            let r = p.to_vec() - center_of_mass;
+            ixx += m * r.x * r.x;
+            iyy += m * r.y * r.y;
+        }
+        if ixx + iyy > 0.0 {
+            (ixx - iyy) / (ixx + iyy)
+        } else {
+            0.0
+        }
+    }
+}
+
+// Time series of an oscillating droplet's deformation, see OscillatingDroplet::measure_deformation.
+#[derive(Default)]
+pub struct DropletOscillation {
+    samples: Vec<(Real, Real)>, // simulation time in s, deformation
+}
+
+impl DropletOscillation {
+    pub fn new() -> DropletOscillation {
+        Default::default()
+    }
+
+    // Recorded simulation times and deformations.
+    pub fn samples(&self) -> &[(Real, Real)] {
+        &self.samples
+    }
+
+    // Meant to be called after every simulation step.
+    pub fn record(&mut self, fluid_world: &FluidParticleWorld, time: Real) {
+        self.samples.push((time, OscillatingDroplet::measure_deformation(fluid_world)));
+    }
+
+    // Oscillation period in s, twice the average time between zero crossings of the deformation.
+    // Needs at least two crossings, i.e. more than half a period of recording.
+    pub fn period(&self) -> Option<Real> {
+        let crossings: Vec<Real> = self
+            .samples
+            .windows(2)
+            .filter_map(|w| {
+                let ((t0, d0), (t1, d1)) = (w[0], w[1]);
+                if (d0 > 0.0) != (d1 > 0.0) {
+                    Some(t0 + (t1 - t0) * d0 / (d0 - d1))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        if crossings.len() < 2 {
+            return None;
+        }
+        Some(2.0 * (crossings[crossings.len() - 1] - crossings[0]) / (crossings.len() - 1) as Real)
+    }
+}
+
+// Taylor–Green vortex: Decaying grid of counter-rotating vortices in a square domain with side length L.
+// u = -U cos(kx) sin(ky), v = U sin(kx) cos(ky) with k = 2π / L
+// Velocities decay with exp(-2 ν k² t), kinetic energy with exp(-4 ν k² t) (ν being the kinematic viscosity).
+// The analytic solution assumes a domain that is periodic along both axes, see PeriodicDomain::with_periodic_y.
+pub struct TaylorGreenVortex {
+    pub domain_size: Real,  // L
+    pub max_velocity: Real, // U
+}
+
+impl TaylorGreenVortex {
+    fn wave_number(&self) -> Real {
+        2.0 * std::f64::consts::PI as Real / self.domain_size
+    }
+
+    // Fluid filling the domain [0, L)², no gravity and no boundaries. Needs to be updated with PeriodicDomain::update before every step.
+    pub fn create_world(&self, particle_density: NumberDensity, fluid_density: Density) -> (FluidParticleWorld, PeriodicDomain) {
+        let mut fluid_world = FluidParticleWorld::new(2.0, particle_density, fluid_density);
+        fluid_world.force_fields.clear();
+        // Lattice needs to fit the domain exactly, so the rest spacing is only matched if L is a multiple of it.
+        let num_particles_per_side = (self.domain_size / (fluid_world.properties.particle_radius() * 2.0)).round() as usize;
+        let spacing = self.domain_size / num_particles_per_side as Real;
+        for y in 0..num_particles_per_side {
+            for x in 0..num_particles_per_side {
+                // cell centered, so the lattice continues seamlessly across periodic edges
+                let position = Point::new((x as Real + 0.5) * spacing, (y as Real + 0.5) * spacing);
+                fluid_world.add_fluid_particle(position, self.velocity(position, 0.0, 0.0));
+            }
+        }
+        let domain = PeriodicDomain::new(0.0, self.domain_size).with_periodic_y(0.0, self.domain_size);
+        (fluid_world, domain)
+    }
+
+    // Analytic velocity field.
+    pub fn velocity(&self, position: Point, kinematic_viscosity: Real, time: Real) -> Vector {
+        let k = self.wave_number();
+        let decay = (-2.0 * kinematic_viscosity * k * k * time).exp();
+        Vector::new(
+            -(k * position.x).cos() * (k * position.y).sin(),
+            (k * position.x).sin() * (k * position.y).cos(),
+        ) * (self.max_velocity * decay)
+    }
+
+    // Analytic kinetic energy of the entire domain, ½ ρ ∫ |u|² = ρ L² U² / 4 at t = 0
+    pub fn kinetic_energy(&self, fluid_density: Real, kinematic_viscosity: Real, time: Real) -> Real {
+        let k = self.wave_number();
+        let decay = (-4.0 * kinematic_viscosity * k * k * time).exp();
+        0.25 * fluid_density * self.domain_size * self.domain_size * self.max_velocity * self.max_velocity * decay
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dam_break_setup() {
+        let scene = DamBreak::martin_moyce(0.2);
+        let world = scene.create_world(NumberDensity(5000.0), Density(100.0));
+        let spacing = world.properties.particle_radius() * 2.0;
+        for p in world.particles.positions.iter() {
+            assert!(p.x > 0.0 && p.x < scene.column_width);
+            assert!(p.y > 0.0 && p.y < scene.column_height);
+        }
+        for p in world.particles.boundary_particles.iter() {
+            let inside_tank = p.x > 0.0 && p.x < scene.tank_width && p.y > 0.0 && p.y < scene.tank_height;
+            assert!(!inside_tank, "boundary particle {:?} inside the tank", p);
+        }
+        assert!((scene.surge_front(&world) - scene.column_width).abs() < spacing);
+    }
+
+    #[test]
+    fn sloshing_tank_natural_frequency() {
+        let mut scene = SloshingTank {
+            tank_width: 1.0,
+            tank_height: 1.0,
+            fill_height: 0.5,
+            shaking_amplitude: 0.01,
+            shaking_frequency: 1.0,
+        };
+        // ω² = g π/L tanh(π h/L)
+        let expected = (9.81 * std::f64::consts::PI as Real * (std::f64::consts::PI as Real * 0.5).tanh()).sqrt();
+        assert!((scene.natural_frequency(9.81) * 2.0 * std::f64::consts::PI as Real - expected).abs() < 1.0e-4);
+
+        // deep water limit ω² = g k
+        scene.fill_height = 100.0;
+        let expected = (9.81 * std::f64::consts::PI as Real).sqrt();
+        assert!((scene.natural_frequency(9.81) * 2.0 * std::f64::consts::PI as Real - expected).abs() < 1.0e-4);
+    }
+
+    #[test]
+    fn taylor_green_initial_kinetic_energy() {
+        let scene = TaylorGreenVortex {
+            domain_size: 0.5,
+            max_velocity: 0.1,
+        };
+        let (world, _) = scene.create_world(NumberDensity(10000.0), Density(100.0)); // 1cm spacing
+        let mass = world.properties.particle_mass();
+        let kinetic_energy: Real = world.particles.velocities.iter().map(|v| 0.5 * mass * v.magnitude2()).sum();
+        let expected = scene.kinetic_energy(world.properties.fluid_density(), 0.0, 0.0);
+        assert!((kinetic_energy / expected - 1.0).abs() < 0.02, "{} != {}", kinetic_energy, expected);
+
+        // decays with exp(-4 ν k² t)
+        let k = 2.0 * std::f64::consts::PI as Real / scene.domain_size;
+        let decayed = scene.kinetic_energy(world.properties.fluid_density(), 0.01, 1.0 / (4.0 * 0.01 * k * k));
+        assert!((decayed / expected - (-1.0 as Real).exp()).abs() < 1.0e-5);
+    }
+
+    #[test]
+    fn flow_past_cylinder_setup() {
+        let scene = FlowPastCylinder {
+            channel_length: 1.0,
+            channel_height: 0.4,
+            cylinder_center: Point::new(0.3, 0.2),
+            cylinder_radius: 0.05,
+            inflow_speed: 0.5,
+        };
+        let (world, _, _) = scene.create_world(NumberDensity(5000.0), Density(100.0));
+        for p in world.particles.positions.iter() {
+            assert!(p.distance(scene.cylinder_center) > scene.cylinder_radius);
+        }
+        assert!(matches!(world.boundary_geometry().last(), Some(BoundaryGeometry::Circle { .. })));
+        // nothing simulated yet
+        assert_eq!(scene.force_coefficients(&world), ForceCoefficients::default());
+
+        assert!((scene.reynolds_number(scene.kinematic_viscosity(40.0)) - 40.0).abs() < 1.0e-3);
+        assert!((FlowPastCylinder::reference_drag_coefficient(30.0).unwrap() - 1.7835).abs() < 1.0e-4);
+        assert_eq!(FlowPastCylinder::reference_drag_coefficient(1000.0), None);
+    }
+
+    #[test]
+    fn oscillating_droplet_period() {
+        let scene = OscillatingDroplet {
+            radius: 0.1,
+            deformation: 0.1,
+        };
+        let mut world = scene.create_world(NumberDensity(10000.0), Density(100.0));
+        let total_mass: Real = world.particles.masses.iter().sum();
+        let expected_mass = world.properties.fluid_density() * std::f64::consts::PI as Real * scene.radius * scene.radius;
+        assert!((total_mass / expected_mass - 1.0).abs() < 0.05, "{} != {}", total_mass, expected_mass);
+        let expected_deformation = (1.1_f32.powi(4) - 1.0) / (1.1_f32.powi(4) + 1.0);
+        let initial_deformation = OscillatingDroplet::measure_deformation(&world);
+        assert!((initial_deformation - expected_deformation).abs() < 0.02);
+
+        // ω² = 6 σ / (ρ R³)
+        let period = scene.rayleigh_period(0.5, 100.0);
+        assert!((period - 2.0 * std::f64::consts::PI as Real / (6.0 * 0.5 / (100.0 * 0.001) as Real).sqrt()).abs() < 1.0e-4);
+
+        // stretch the droplet back and forth with a known period instead of simulating a surface tension model
+        let mut oscillation = DropletOscillation::new();
+        let rest_positions = world.particles.positions.clone();
+        let initial_stretch = 1.0 + scene.deformation;
+        let stretch_period = 0.4;
+        for step in 0..100 {
+            let time = step as Real * 0.01;
+            let stretch = 1.0 + scene.deformation * (2.0 * std::f64::consts::PI as Real * time / stretch_period).cos();
+            for (p, rest) in world.particles.positions.iter_mut().zip(rest_positions.iter()) {
+                // from the initial ellipse back to a circle, then to the current ellipse
+                *p = Point::new(rest.x / initial_stretch * stretch, rest.y * initial_stretch / stretch);
+            }
+            oscillation.record(&world, time);
+        }
+        assert_eq!(oscillation.samples().len(), 100);
+        assert!((oscillation.period().unwrap() - stretch_period).abs() < 0.01);
+        assert_eq!(DropletOscillation::new().period(), None);
+    }
+
+    #[test]
+    fn poiseuille_flow_setup() {
+        let scene = PoiseuilleFlow {
+            channel_length: 0.1,
+            channel_height: 0.1,
+            body_force: 0.8,
+        };
+        let (mut world, domain) = scene.create_world(NumberDensity(40000.0), Density(100.0));
+        assert_eq!(world.particles.positions.len(), 400);
+        assert!(world.particles.positions.iter().all(|p| domain.contains(*p)));
+        assert_eq!(world.gravity(), Vector::new(0.8, 0.0));
+
+        // maximum in the middle, g H² / (8 ν)
+        assert!((scene.analytic_velocity(0.05, 0.02) - 0.05).abs() < 1.0e-6);
+        assert_eq!(scene.analytic_velocity(0.0, 0.02), 0.0);
+
+        for (p, v) in world.particles.positions.iter().zip(world.particles.velocities.iter_mut()) {
+            v.x = scene.analytic_velocity(p.y, 0.02);
+        }
+        let profile = scene.velocity_profile(&world, 10);
+        assert_eq!(profile.len(), 10);
+        for (y, velocity) in profile {
+            // two rows per layer, the average of the parabola within a layer is slightly below its value at the center
+            assert!((velocity - scene.analytic_velocity(y, 0.02)).abs() < 1.0e-3);
+        }
+    }
+
+    #[test]
+    fn reference_surge_front_interpolation() {
+        assert_eq!(DamBreak::reference_surge_front(0.0), Some(1.0));
+        assert!((DamBreak::reference_surge_front(2.51).unwrap() - 3.0).abs() < 1.0e-5);
+        assert_eq!(DamBreak::reference_surge_front(10.0), None);
+        assert_eq!(DamBreak::reference_surge_front(-1.0), None);
+    }
+}
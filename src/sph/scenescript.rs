@@ -0,0 +1,357 @@
+use super::fluidparticleworld::{FluidParticleWorld, MovingBoundary};
+use super::openboundary::Inlet;
+use super::simulation::Simulation;
+use crate::units::*;
+use cgmath::prelude::*;
+use std::cell::{Cell, RefCell};
+use std::io;
+use std::rc::Rc;
+
+// Time-dependent scene events written in rhai (https://rhai.rs), for authoring demos without recompiling.
+// Only available with the "scripting" feature.
+//
+// The script's top level runs once when it is loaded. It schedules actions with `at(time, || ...)`
+// and may define `fn update(time)`, which is called before every simulation step.
+//
+//   at(0.5, || start_emitter("inlet"));
+//   at(2.0, || move_boundary("gate", 0.0, 0.6, 0.25));  // slides the gate up by 60cm within 250ms
+//   at(4.0, || set_gravity(0.0, -1.62));
+//   fn update(time) { if time > 6.0 { stop_emitter("inlet"); } }
+//
+// Functions available to scripts, all numbers are floats in SI units:
+// - set_gravity(x, y)
+// - move_boundary(name, dx, dy, duration): moves a moving boundary to an offset from its rest pose at constant velocity, 0 duration jumps
+// - start_emitter(name) / stop_emitter(name)
+// - add_fluid_rect(x, y, width, height)
+// Moving boundaries and emitters are made known to the script by name via add_moving_boundary and add_emitter.
+pub struct SceneScript {
+    engine: rhai::Engine,
+    ast: rhai::AST,
+    has_update: bool,
+    events: Vec<(Real, rhai::FnPtr)>, // sorted by time
+    num_fired_events: usize,
+    commands: Rc<RefCell<Vec<SceneCommand>>>,
+
+    moving_boundaries: Vec<ScriptedBoundary>,
+    emitters: Vec<(String, Inlet, bool)>, // with whether it is running
+    time: Real,
+}
+
+enum SceneCommand {
+    SetGravity(Vector),
+    MoveBoundary { name: String, offset: Vector, duration: Real },
+    StartEmitter(String),
+    StopEmitter(String),
+    AddFluidRect(Rect),
+}
+
+struct ScriptedBoundary {
+    name: String,
+    boundary: MovingBoundary,
+    offset: Vector, // where the current motion started
+    target_offset: Vector,
+    start_time: Real,
+    duration: Real,
+    moving: bool,
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl SceneScript {
+    pub fn new(source: &str) -> io::Result<SceneScript> {
+        let mut engine = rhai::Engine::new();
+        let commands = Rc::new(RefCell::new(Vec::new()));
+        let events = Rc::new(RefCell::new(Vec::new()));
+
+        let scheduled = events.clone();
+        engine.register_fn("at", move |time: rhai::FLOAT, action: rhai::FnPtr| {
+            scheduled.borrow_mut().push((time as Real, action));
+        });
+        let queue = commands.clone();
+        engine.register_fn("set_gravity", move |x: rhai::FLOAT, y: rhai::FLOAT| {
+            queue.borrow_mut().push(SceneCommand::SetGravity(Vector::new(x as Real, y as Real)));
+        });
+        let queue = commands.clone();
+        engine.register_fn(
+            "move_boundary",
+            move |name: &str, dx: rhai::FLOAT, dy: rhai::FLOAT, duration: rhai::FLOAT| {
+                queue.borrow_mut().push(SceneCommand::MoveBoundary {
+                    name: name.to_string(),
+                    offset: Vector::new(dx as Real, dy as Real),
+                    duration: duration as Real,
+                });
+            },
+        );
+        let queue = commands.clone();
+        engine.register_fn("start_emitter", move |name: &str| {
+            queue.borrow_mut().push(SceneCommand::StartEmitter(name.to_string()));
+        });
+        let queue = commands.clone();
+        engine.register_fn("stop_emitter", move |name: &str| {
+            queue.borrow_mut().push(SceneCommand::StopEmitter(name.to_string()));
+        });
+        let queue = commands.clone();
+        engine.register_fn("add_fluid_rect", move |x: rhai::FLOAT, y: rhai::FLOAT, w: rhai::FLOAT, h: rhai::FLOAT| {
+            queue
+                .borrow_mut()
+                .push(SceneCommand::AddFluidRect(Rect::new(x as f32, y as f32, w as f32, h as f32)));
+        });
+
+        let ast = engine
+            .compile(source)
+            .map_err(|err| invalid_data(format!("Failed to parse scene script: {}", err)))?;
+        engine
+            .run_ast(&ast)
+            .map_err(|err| invalid_data(format!("Failed to run scene script: {}", err)))?;
+        let has_update = ast.iter_functions().any(|f| f.name == "update" && f.params.len() == 1);
+
+        let mut events = events.take();
+        events.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+        Ok(SceneScript {
+            engine,
+            ast,
+            has_update,
+            events,
+            num_fired_events: 0,
+            commands,
+            moving_boundaries: Vec::new(),
+            emitters: Vec::new(),
+            time: 0.0,
+        })
+    }
+
+    pub fn read(reader: &mut impl io::Read) -> io::Result<SceneScript> {
+        let mut source = String::new();
+        reader.read_to_string(&mut source)?;
+        Self::new(&source)
+    }
+
+    // Makes a moving boundary known to move_boundary under a name. It is expected to be at its rest pose.
+    pub fn add_moving_boundary(&mut self, name: &str, boundary: MovingBoundary) {
+        self.moving_boundaries.push(ScriptedBoundary {
+            name: name.to_string(),
+            boundary,
+            offset: Vector::zero(),
+            target_offset: Vector::zero(),
+            start_time: 0.0,
+            duration: 0.0,
+            moving: false,
+        });
+    }
+
+    // Makes an inlet known to start_emitter under a name. Emitters don't run until the script starts them.
+    pub fn add_emitter(&mut self, name: &str, inlet: Inlet) {
+        self.emitters.push((name.to_string(), inlet, false));
+    }
+
+    // Simulated time of the last update.
+    pub fn time(&self) -> Real {
+        self.time
+    }
+
+    // Number of events scheduled with `at` that haven't fired yet.
+    pub fn num_pending_events(&self) -> usize {
+        self.events.len() - self.num_fired_events
+    }
+
+    // Fires all events that are due at the given simulated time, runs the script's update function and applies everything the script requested.
+    // Meant to be called right before every simulation step, see install.
+    pub fn update(&mut self, fluid_world: &mut FluidParticleWorld, time: Real) -> io::Result<()> {
+        microprofile::scope!("SceneScript", "update");
+        self.time = time;
+        while self.num_fired_events < self.events.len() && self.events[self.num_fired_events].0 <= self.time {
+            let action = self.events[self.num_fired_events].1.clone();
+            self.num_fired_events += 1;
+            action
+                .call::<()>(&self.engine, &self.ast, ())
+                .map_err(|err| invalid_data(format!("Scene script event failed: {}", err)))?;
+        }
+        if self.has_update {
+            self.engine
+                .call_fn::<()>(&mut rhai::Scope::new(), &self.ast, "update", (self.time as rhai::FLOAT,))
+                .map_err(|err| invalid_data(format!("Scene script update failed: {}", err)))?;
+        }
+
+        let commands = self.commands.take();
+        for command in commands {
+            self.apply(command, fluid_world)?;
+        }
+
+        for scripted in self.moving_boundaries.iter_mut().filter(|scripted| scripted.moving) {
+            let progress = if scripted.duration > 0.0 {
+                ((self.time - scripted.start_time) / scripted.duration).min(1.0)
+            } else {
+                1.0
+            };
+            let offset = scripted.offset + (scripted.target_offset - scripted.offset) * progress;
+            let velocity = if progress < 1.0 {
+                (scripted.target_offset - scripted.offset) / scripted.duration
+            } else {
+                scripted.offset = scripted.target_offset;
+                scripted.moving = false;
+                Vector::zero()
+            };
+            fluid_world.move_boundary(scripted.boundary, |p| p + offset, |_| velocity);
+        }
+        for (_, inlet, running) in self.emitters.iter() {
+            if *running {
+                inlet.update(fluid_world);
+            }
+        }
+        Ok(())
+    }
+
+    fn apply(&mut self, command: SceneCommand, fluid_world: &mut FluidParticleWorld) -> io::Result<()> {
+        match command {
+            SceneCommand::SetGravity(gravity) => fluid_world.set_gravity(gravity),
+            SceneCommand::MoveBoundary { name, offset, duration } => {
+                let time = self.time;
+                let scripted = self
+                    .moving_boundaries
+                    .iter_mut()
+                    .find(|scripted| scripted.name == name)
+                    .ok_or_else(|| invalid_data(format!("Scene script moves unknown boundary \"{}\"", name)))?;
+                // a motion that is interrupted continues from where it got to
+                if scripted.moving && scripted.duration > 0.0 {
+                    let progress = ((time - scripted.start_time) / scripted.duration).min(1.0);
+                    scripted.offset += (scripted.target_offset - scripted.offset) * progress;
+                }
+                scripted.target_offset = offset;
+                scripted.start_time = time;
+                scripted.duration = duration.max(0.0);
+                scripted.moving = true;
+            }
+            SceneCommand::StartEmitter(name) => *self.emitter_running(&name)? = true,
+            SceneCommand::StopEmitter(name) => *self.emitter_running(&name)? = false,
+            SceneCommand::AddFluidRect(rect) => fluid_world.add_fluid_rect(&rect, 0.0),
+        }
+        Ok(())
+    }
+
+    fn emitter_running(&mut self, name: &str) -> io::Result<&mut bool> {
+        self.emitters
+            .iter_mut()
+            .find(|(emitter_name, _, _)| emitter_name == name)
+            .map(|(_, _, running)| running)
+            .ok_or_else(|| invalid_data(format!("Scene script uses unknown emitter \"{}\"", name)))
+    }
+
+    // Runs the script before every step of a simulation, keeping track of time via the simulation's timesteps.
+    // A script error stops the script, the returned cell holds the error from then on.
+    pub fn install(self, simulation: &mut Simulation) -> Rc<RefCell<Option<io::Error>>> {
+        let mut script = Some(self);
+        let error = Rc::new(RefCell::new(None));
+        let reported_error = error.clone();
+        let time = Rc::new(Cell::new(0.0));
+        let passed_time = time.clone();
+        simulation.on_pre_step(move |fluid_world, _| {
+            if let Some(Err(err)) = script.as_mut().map(|script| script.update(fluid_world, time.get())) {
+                *reported_error.borrow_mut() = Some(err);
+                script = None;
+            }
+        });
+        simulation.on_post_step(move |_, dt| passed_time.set(passed_time.get() + dt));
+        error
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sph::{InflowProfile, TimeManager, TimeManagerConfiguration, WCSPHSolver, XSPHViscosityModel};
+
+    #[test]
+    fn script_events_change_scene() {
+        let mut fluid_world = FluidParticleWorld::new(2.0, NumberDensity(5000.0), Density(100.0));
+        let gate = fluid_world.add_moving_boundary(|world| world.add_boundary_line(Point::new(1.0, 0.0), Point::new(1.0, 0.5)));
+        let mut script = SceneScript::new(
+            r#"
+            at(0.002, || set_gravity(0.0, -1.62));
+            at(0.0, || start_emitter("inlet"));
+            at(0.004, || move_boundary("gate", 0.0, 1.0, 0.004));
+            fn update(time) {
+                if time > 0.0095 { stop_emitter("inlet"); }
+            }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(script.num_pending_events(), 3);
+        script.add_moving_boundary("gate", gate);
+        script.add_emitter(
+            "inlet",
+            Inlet::new(Point::new(0.0, 0.0), Point::new(0.0, 0.2), 0.06, InflowProfile::Uniform(1.0)),
+        );
+
+        let solver = WCSPHSolver::new(
+            XSPHViscosityModel::new(fluid_world.properties.smoothing_length()),
+            &fluid_world.properties,
+        );
+        let mut simulation = Simulation::new(
+            fluid_world,
+            Box::new(solver),
+            TimeManager::new(TimeManagerConfiguration::FixedTimeStep(0.001)),
+        );
+        let error = script.install(&mut simulation);
+
+        let gate_height = |simulation: &Simulation| {
+            simulation
+                .fluid_world
+                .particles
+                .boundary_particles
+                .iter()
+                .map(|p| p.y)
+                .fold(0.0, Real::max)
+        };
+        simulation.step().unwrap();
+        let num_emitted = simulation.fluid_world.particles.positions.len();
+        assert_gt!(num_emitted, 0);
+        assert_lt!((simulation.fluid_world.gravity().y + 9.81).abs(), 1.0e-6);
+        for _ in 0..4 {
+            simulation.step().unwrap();
+        }
+        assert_lt!((simulation.fluid_world.gravity().y + 1.62).abs(), 1.0e-6);
+        // halfway up
+        simulation.step().unwrap();
+        simulation.step().unwrap();
+        assert_lt!((gate_height(&simulation) - 1.0).abs(), 0.01);
+        for _ in 0..5 {
+            simulation.step().unwrap();
+        }
+        assert_lt!((gate_height(&simulation) - 1.5).abs(), 0.01);
+
+        // the emitter stopped, its particles move on and leave room that isn't filled anymore
+        let num_particles = simulation.fluid_world.particles.positions.len();
+        for _ in 0..100 {
+            simulation.step().unwrap();
+        }
+        assert_eq!(simulation.fluid_world.particles.positions.len(), num_particles);
+        assert!(error.borrow().is_none());
+    }
+
+    #[test]
+    fn script_errors() {
+        assert!(SceneScript::new("at(1.0, || ").is_err());
+        assert!(SceneScript::new("undefined_function();").is_err());
+
+        let mut fluid_world = FluidParticleWorld::new(2.0, NumberDensity(5000.0), Density(100.0));
+        let mut script = SceneScript::new(r#"at(0.0, || start_emitter("missing"));"#).unwrap();
+        assert!(script.update(&mut fluid_world, 0.0).is_err());
+
+        // installed scripts hand their error to the caller
+        let script = SceneScript::new(r#"at(0.0, || start_emitter("missing"));"#).unwrap();
+        let solver = WCSPHSolver::new(
+            XSPHViscosityModel::new(fluid_world.properties.smoothing_length()),
+            &fluid_world.properties,
+        );
+        let mut simulation = Simulation::new(
+            fluid_world,
+            Box::new(solver),
+            TimeManager::new(TimeManagerConfiguration::FixedTimeStep(0.001)),
+        );
+        let error = script.install(&mut simulation);
+        simulation.step().unwrap();
+        assert!(error.borrow().as_ref().unwrap().to_string().contains("missing"));
+    }
+}
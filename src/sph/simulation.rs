@@ -0,0 +1,307 @@
+use super::error::SphError;
+use super::fluidparticleworld::FluidParticleWorld;
+use super::simulationclock::SimulationClock;
+use super::solver::Solver;
+use super::steptimings::Instant;
+use super::termination::{self, RunSummary, TerminationCriteria, TerminationReason};
+use super::timemanager::TimeManager;
+use super::validation::{self, SceneWarning};
+use crate::units::*;
+use std::ops::Range;
+
+type StepHook = Box<dyn FnMut(&mut FluidParticleWorld, Real)>;
+type ParticlesAddedHook = Box<dyn FnMut(&mut FluidParticleWorld, Range<usize>)>;
+type ParticlesRemovedHook = Box<dyn FnMut(&mut FluidParticleWorld, &[bool])>;
+
+// Fluid world, solver and time manager stepped together, with hooks for custom forces, measurements or interventions
+// that would otherwise require changes to the solvers.
+//
+//   let mut simulation = Simulation::new(fluid_world, Box::new(solver), time_manager);
+//   simulation.on_pre_step(|fluid_world, dt| wave_maker.update(fluid_world, ...));
+//   simulation.on_post_step(move |fluid_world, dt| probes.record(...));
+//   simulation.step()?;
+//
+// Hooks run in the order they were registered. They may add fluid particles, but removing them has to go through
+// Simulation::retain_fluid_particles so the solver can drop its per particle data.
+pub struct Simulation {
+    pub fluid_world: FluidParticleWorld,
+    pub solver: Box<dyn Solver>,
+    pub time_manager: TimeManager,
+    pub clock: SimulationClock, // advanced by every step, processing time only includes the solver, not the hooks
+
+    pre_step_hooks: Vec<StepHook>,
+    post_step_hooks: Vec<StepHook>,
+    particles_added_hooks: Vec<ParticlesAddedHook>,
+    particles_removed_hooks: Vec<ParticlesRemovedHook>,
+    num_known_particles: usize, // number of fluid particles the added hooks were told about
+}
+
+impl Simulation {
+    pub fn new(fluid_world: FluidParticleWorld, solver: Box<dyn Solver>, time_manager: TimeManager) -> Simulation {
+        let num_known_particles = fluid_world.particles.positions.len();
+        Simulation {
+            fluid_world,
+            solver,
+            time_manager,
+            clock: SimulationClock::new(),
+            pre_step_hooks: Vec::new(),
+            post_step_hooks: Vec::new(),
+            particles_added_hooks: Vec::new(),
+            particles_removed_hooks: Vec::new(),
+            num_known_particles,
+        }
+    }
+
+    // Called before every step with the timestep of the previous one, adaptive timestepping may still change it for the upcoming step.
+    pub fn on_pre_step(&mut self, hook: impl FnMut(&mut FluidParticleWorld, Real) + 'static) {
+        self.pre_step_hooks.push(Box::new(hook));
+    }
+
+    // Called after every step with the timestep the step advanced by.
+    pub fn on_post_step(&mut self, hook: impl FnMut(&mut FluidParticleWorld, Real) + 'static) {
+        self.post_step_hooks.push(Box::new(hook));
+    }
+
+    // Called right before a step with the indices of the fluid particles added since the last step, including those added by pre step hooks.
+    // New particles are appended, the indices are only valid until the step reorders all particles.
+    pub fn on_particles_added(&mut self, hook: impl FnMut(&mut FluidParticleWorld, Range<usize>) + 'static) {
+        self.particles_added_hooks.push(Box::new(hook));
+    }
+
+    // Called by retain_fluid_particles before the particles are removed, with the same mask.
+    pub fn on_particles_removed(&mut self, hook: impl FnMut(&mut FluidParticleWorld, &[bool]) + 'static) {
+        self.particles_removed_hooks.push(Box::new(hook));
+    }
+
+    pub fn remove_all_hooks(&mut self) {
+        self.pre_step_hooks.clear();
+        self.post_step_hooks.clear();
+        self.particles_added_hooks.clear();
+        self.particles_removed_hooks.clear();
+    }
+
+    // Removes all fluid particles whose entry in `keep` is false, from the fluid world as well as from the solver.
+    pub fn retain_fluid_particles(&mut self, keep: &[bool]) {
+        self.notify_particles_added();
+        for hook in self.particles_removed_hooks.iter_mut() {
+            hook(&mut self.fluid_world, keep);
+        }
+        self.fluid_world.retain_fluid_particles(keep);
+        self.solver.retain_particle_data(keep);
+        self.num_known_particles = self.fluid_world.particles.positions.len();
+    }
+
+    // Runs all pre step hooks, a single solver step and all post step hooks.
+    // If the solver step fails, post step hooks are skipped, see Solver::simulation_step.
+    pub fn step(&mut self) -> Result<(), SphError> {
+        microprofile::scope!("Simulation", "step");
+        let dt = self.time_manager.timestep();
+        for hook in self.pre_step_hooks.iter_mut() {
+            hook(&mut self.fluid_world, dt);
+        }
+        self.notify_particles_added();
+
+        let step_start = Instant::now();
+        self.solver.simulation_step(&mut self.fluid_world, &mut self.time_manager)?;
+        self.clock.record_step(self.time_manager.passed_time(), step_start.elapsed());
+
+        let dt = self.time_manager.timestep();
+        for hook in self.post_step_hooks.iter_mut() {
+            hook(&mut self.fluid_world, dt);
+        }
+        // particles added by post step hooks are reported before the next step
+        Ok(())
+    }
+
+    // Checks scene and timestep configuration before running, see FluidParticleWorld::validate and validation::validate_timestep.
+    pub fn validate(&self) -> Vec<SceneWarning> {
+        let mut warnings = self.fluid_world.validate();
+        warnings.extend(validation::validate_timestep(&self.fluid_world, self.solver.as_ref(), &self.time_manager));
+        warnings
+    }
+
+    // Steps until one of the criteria applies or the solver's watchdog raised an alarm, for runs without a viewer:
+    //
+    //   let summary = simulation.run_until(&TerminationCriteria { max_time: Some(10.0), ..Default::default() });
+    //   println!("{}", summary);
+    //   std::process::exit(summary.exit_code());
+    //
+    // Criteria are checked before the first step as well, a simulation that already meets them isn't stepped at all.
+    pub fn run_until(&mut self, criteria: &TerminationCriteria) -> RunSummary {
+        microprofile::scope!("Simulation", "run_until");
+        let mut num_steps = 0;
+        let reason = loop {
+            if let Some(instability) = self.solver.watchdog().and_then(|watchdog| watchdog.alarm()) {
+                break TerminationReason::Instability(instability.clone());
+            }
+            let kinetic_energy = termination::kinetic_energy(&self.fluid_world);
+            if let Some(reason) = criteria.check(self.time_manager.passed_time(), num_steps, kinetic_energy) {
+                break reason;
+            }
+            if let Err(err) = self.step() {
+                break TerminationReason::Error(err);
+            }
+            num_steps += 1;
+        };
+        RunSummary {
+            reason,
+            clock: self.clock,
+            num_fluid_particles: self.fluid_world.particles.positions.len(),
+            kinetic_energy: termination::kinetic_energy(&self.fluid_world),
+        }
+    }
+
+    fn notify_particles_added(&mut self) {
+        let num_particles = self.fluid_world.particles.positions.len();
+        // particles that were removed without retain_fluid_particles can't be reported, fewer particles are taken as is
+        if num_particles > self.num_known_particles {
+            for hook in self.particles_added_hooks.iter_mut() {
+                hook(&mut self.fluid_world, self.num_known_particles..num_particles);
+            }
+        }
+        self.num_known_particles = num_particles;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sph::{RestCriterion, TimeManagerConfiguration, WCSPHSolver, Watchdog, XSPHViscosityModel};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn hooks_are_called_around_steps() {
+        let mut fluid_world = FluidParticleWorld::new(2.0, NumberDensity(5000.0), Density(100.0));
+        fluid_world.add_fluid_rect(&Rect::new(0.0, 0.0, 0.2, 0.2), 0.0);
+        let num_initial_particles = fluid_world.particles.positions.len();
+        let solver = WCSPHSolver::new(
+            XSPHViscosityModel::new(fluid_world.properties.smoothing_length()),
+            &fluid_world.properties,
+        );
+        let mut simulation = Simulation::new(
+            fluid_world,
+            Box::new(solver),
+            TimeManager::new(TimeManagerConfiguration::FixedTimeStep(0.0005)),
+        );
+
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let log = events.clone();
+        simulation.on_pre_step(move |fluid_world, _| {
+            log.borrow_mut().push("pre");
+            // custom force: cancel gravity
+            for velocity in fluid_world.particles.velocities.iter_mut() {
+                velocity.y += 9.81 * 0.0005;
+            }
+            if fluid_world.particles.positions.len() < num_initial_particles + 2 {
+                fluid_world.add_fluid_particle(Point::new(1.0, 1.0), Vector::new(0.0, 0.0));
+            }
+        });
+        let log = events.clone();
+        let passed_time = Rc::new(RefCell::new(0.0));
+        let post_step_time = passed_time.clone();
+        simulation.on_post_step(move |_, dt| {
+            log.borrow_mut().push("post");
+            *post_step_time.borrow_mut() += dt;
+        });
+        let added = Rc::new(RefCell::new(Vec::new()));
+        let log = added.clone();
+        simulation.on_particles_added(move |_, range| log.borrow_mut().push(range));
+        let removed = Rc::new(RefCell::new(0));
+        let num_removed = removed.clone();
+        simulation.on_particles_removed(move |fluid_world, keep| {
+            assert_eq!(fluid_world.particles.positions.len(), keep.len());
+            *num_removed.borrow_mut() += keep.iter().filter(|keep| !**keep).count();
+        });
+
+        simulation.step().unwrap();
+        simulation.step().unwrap();
+        assert_eq!(*events.borrow(), vec!["pre", "post", "pre", "post"]);
+        assert_lt!((*passed_time.borrow() - simulation.time_manager.passed_time()).abs(), 1.0e-6);
+        assert_eq!(simulation.clock.num_steps(), 2);
+        assert_eq!(simulation.clock.simulated_time(), simulation.time_manager.passed_time());
+        assert_eq!(
+            *added.borrow(),
+            vec![
+                num_initial_particles..num_initial_particles + 1,
+                num_initial_particles + 1..num_initial_particles + 2
+            ]
+        );
+        // gravity is cancelled out, the resting fluid stays at rest
+        let max_speed = simulation.fluid_world.particles.velocities.iter().map(|v| v.y.abs()).fold(0.0, Real::max);
+        assert_lt!(max_speed, 0.01);
+
+        let mut keep = vec![true; simulation.fluid_world.particles.positions.len()];
+        keep[0] = false;
+        keep[3] = false;
+        simulation.retain_fluid_particles(&keep);
+        assert_eq!(*removed.borrow(), 2);
+        assert_eq!(simulation.fluid_world.particles.positions.len(), num_initial_particles);
+        simulation.step().unwrap();
+        assert_eq!(added.borrow().len(), 3);
+
+        simulation.remove_all_hooks();
+        simulation.step().unwrap();
+        assert_eq!(events.borrow().len(), 6);
+    }
+
+    #[test]
+    fn run_until_stops_at_first_criterion() {
+        // free falling block of fluid without any boundaries
+        let new_simulation = || {
+            let mut fluid_world = FluidParticleWorld::new(2.0, NumberDensity(5000.0), Density(100.0));
+            fluid_world.add_fluid_rect(&Rect::new(0.0, 0.0, 0.1, 0.1), 0.0);
+            let solver = WCSPHSolver::new(
+                XSPHViscosityModel::new(fluid_world.properties.smoothing_length()),
+                &fluid_world.properties,
+            );
+            Simulation::new(
+                fluid_world,
+                Box::new(solver),
+                TimeManager::new(TimeManagerConfiguration::FixedTimeStep(0.001)),
+            )
+        };
+
+        let mut simulation = new_simulation();
+        let summary = simulation.run_until(&TerminationCriteria {
+            max_time: Some(0.0095),
+            ..Default::default()
+        });
+        assert!(matches!(summary.reason, TerminationReason::TimeLimit));
+        assert_eq!(summary.exit_code(), 0);
+        assert_eq!(summary.clock.num_steps(), 10);
+        assert_gt!(summary.kinetic_energy, 0.0);
+
+        // at rest right at the start, unless rest is only checked later
+        let rest = RestCriterion {
+            max_kinetic_energy: 1.0e-6,
+            not_before: 0.0,
+        };
+        let summary = new_simulation().run_until(&TerminationCriteria {
+            rest: Some(rest),
+            max_steps: Some(5),
+            ..Default::default()
+        });
+        assert!(matches!(summary.reason, TerminationReason::AtRest));
+        assert_eq!(summary.clock.num_steps(), 0);
+        let summary = new_simulation().run_until(&TerminationCriteria {
+            rest: Some(RestCriterion { not_before: 1.0, ..rest }),
+            max_steps: Some(5),
+            ..Default::default()
+        });
+        assert!(matches!(summary.reason, TerminationReason::StepLimit));
+        assert_eq!(summary.exit_code(), 2);
+        assert_eq!(summary.clock.num_steps(), 5);
+
+        // falling faster than the watchdog allows
+        let mut simulation = new_simulation();
+        simulation.solver.set_watchdog(Some(Watchdog::new(0.05)));
+        let summary = simulation.run_until(&TerminationCriteria {
+            max_time: Some(1.0),
+            ..Default::default()
+        });
+        assert!(matches!(summary.reason, TerminationReason::Instability(_)));
+        assert_eq!(summary.exit_code(), 1);
+        assert_lt!(summary.clock.simulated_time(), 0.1);
+    }
+}
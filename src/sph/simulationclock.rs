@@ -0,0 +1,90 @@
+use crate::units::*;
+use std::fmt;
+use std::time::Duration;
+
+// Progress of a simulation run: simulated time, number of steps and the wall clock time spent on them.
+// Unlike frame counters of a viewer, this only depends on the simulation itself, so it can be embedded into exports.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SimulationClock {
+    simulated_time: Real, // in s
+    num_steps: u64,
+    processing_time: Duration,
+}
+
+impl SimulationClock {
+    pub fn new() -> SimulationClock {
+        Default::default()
+    }
+
+    pub fn restart(&mut self) {
+        *self = Default::default();
+    }
+
+    // To be called after every step with the total simulated time afterwards (see TimeManager::passed_time) and the wall clock time the step took.
+    pub fn record_step(&mut self, simulated_time: Real, processing_time: Duration) {
+        self.simulated_time = simulated_time;
+        self.num_steps += 1;
+        self.processing_time += processing_time;
+    }
+
+    pub fn simulated_time(&self) -> Real {
+        self.simulated_time
+    }
+
+    pub fn num_steps(&self) -> u64 {
+        self.num_steps
+    }
+
+    pub fn processing_time(&self) -> Duration {
+        self.processing_time
+    }
+
+    // Simulated seconds per second of processing, above 1 if the simulation runs faster than real time. None before the first step.
+    pub fn realtime_ratio(&self) -> Option<Real> {
+        let processing_time = self.processing_time.as_secs_f32();
+        if self.num_steps == 0 || processing_time <= 0.0 {
+            None
+        } else {
+            Some(self.simulated_time / processing_time)
+        }
+    }
+}
+
+impl fmt::Display for SimulationClock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "simulated {:.4}s in {} steps, processing {:.3}s",
+            self.simulated_time,
+            self.num_steps,
+            self.processing_time.as_secs_f64()
+        )?;
+        match self.realtime_ratio() {
+            Some(ratio) => write!(f, " ({:.3}x realtime)", ratio),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clock_accumulates_steps() {
+        let mut clock = SimulationClock::new();
+        assert_eq!(clock.realtime_ratio(), None);
+        assert_eq!(clock.to_string(), "simulated 0.0000s in 0 steps, processing 0.000s");
+
+        clock.record_step(0.01, Duration::from_millis(5));
+        clock.record_step(0.025, Duration::from_millis(15));
+        assert_eq!(clock.num_steps(), 2);
+        assert_eq!(clock.simulated_time(), 0.025);
+        assert_eq!(clock.processing_time(), Duration::from_millis(20));
+        assert_lt!((clock.realtime_ratio().unwrap() - 1.25).abs(), 1.0e-5);
+        assert_eq!(clock.to_string(), "simulated 0.0250s in 2 steps, processing 0.020s (1.250x realtime)");
+
+        clock.restart();
+        assert_eq!(clock, SimulationClock::new());
+    }
+}
@@ -0,0 +1,85 @@
+use rand::RngCore;
+
+// Random number generator for everything stochastic in a simulation, e.g. the jitter of FluidParticleWorld::add_fluid_rect.
+// Stored in the fluid world, so that the same seed and the same sequence of operations always give the same simulation.
+//
+// The entire state is a single u64 (SplitMix64, see http://prng.di.unimi.it/splitmix64.c), which can be saved along with
+// the particles and restored via from_state to continue the exact same random stream.
+// Implements rand's RngCore, so all of rand's distributions work with it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SimulationRng {
+    state: u64,
+}
+
+impl SimulationRng {
+    pub fn new(seed: u64) -> SimulationRng {
+        SimulationRng { state: seed }
+    }
+
+    // Current state, restoring it with from_state continues with the same numbers this generator would yield next.
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    pub fn from_state(state: u64) -> SimulationRng {
+        SimulationRng { state }
+    }
+}
+
+impl Default for SimulationRng {
+    fn default() -> SimulationRng {
+        SimulationRng::new(0)
+    }
+}
+
+impl RngCore for SimulationRng {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn restored_state_continues_stream() {
+        let mut rng = SimulationRng::new(1234);
+        let _: Vec<u64> = (0..10).map(|_| rng.next_u64()).collect();
+        let mut restored = SimulationRng::from_state(rng.state());
+        for _ in 0..100 {
+            assert_eq!(rng.gen::<f32>(), restored.gen::<f32>());
+        }
+        assert_eq!(rng, restored);
+
+        // reference value of SplitMix64 for seed 0
+        let mut rng = SimulationRng::new(0);
+        assert_eq!(rng.next_u64(), 0xe220_a839_7b1d_cdaf);
+        assert_ne!(SimulationRng::new(1).next_u64(), SimulationRng::new(2).next_u64());
+
+        let mut bytes = [0u8; 11];
+        SimulationRng::new(0).fill_bytes(&mut bytes);
+        assert_eq!(bytes[..8], 0xe220_a839_7b1d_cdafu64.to_le_bytes());
+    }
+}
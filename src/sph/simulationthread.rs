@@ -0,0 +1,240 @@
+use super::error::SphError;
+use super::fluidparticleworld::FluidParticleWorld;
+use super::simulationclock::SimulationClock;
+use super::solver::Solver;
+use super::steptimings::Instant;
+use super::timemanager::TimeManager;
+use super::watchdog::Instability;
+use crate::units::*;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+// Particle data a SimulationThread publishes after every step.
+#[derive(Clone, Default)]
+pub struct ParticleSnapshot {
+    pub positions: Vec<Point>,
+    pub velocities: Vec<Vector>,
+    pub masses: Vec<Real>,
+    pub densities: Vec<Real>,
+    pub tags: Vec<u32>,
+    pub boundary_particles: Vec<Point>,
+
+    pub passed_time: Real,
+    pub clock: SimulationClock,           // steps and processing time since the thread was spawned
+    pub step_processing_time: Duration,   // of the last step
+    pub instability: Option<Instability>, // the solver's watchdog alarm, no more steps are taken once there is one
+    pub solver_error: Option<SphError>,   // error of a failed step, no more steps are taken once there is one
+}
+
+impl ParticleSnapshot {
+    // Reuses the snapshot's allocations.
+    fn capture(&mut self, fluid_world: &FluidParticleWorld, solver: &dyn Solver, time_manager: &TimeManager) {
+        let particles = &fluid_world.particles;
+        copy_into(&mut self.positions, &particles.positions);
+        copy_into(&mut self.velocities, &particles.velocities);
+        copy_into(&mut self.masses, &particles.masses);
+        copy_into(&mut self.densities, &particles.densities);
+        copy_into(&mut self.tags, &particles.tags);
+        copy_into(&mut self.boundary_particles, &particles.boundary_particles);
+        self.passed_time = time_manager.passed_time();
+        self.instability = solver.watchdog().and_then(|watchdog| watchdog.alarm()).cloned();
+    }
+
+    // Overwrites the fluid particles of a world with those of the snapshot and updates its neighborhood,
+    // e.g. of a copy made with FluidParticleWorld::copy_for_display.
+    // Boundary particles are only taken over as long as their number didn't change, e.g. to follow moving boundaries.
+    pub fn apply(&self, fluid_world: &mut FluidParticleWorld) {
+        let particles = &mut fluid_world.particles;
+        copy_into(&mut particles.positions, &self.positions);
+        copy_into(&mut particles.velocities, &self.velocities);
+        copy_into(&mut particles.masses, &self.masses);
+        copy_into(&mut particles.tags, &self.tags);
+        if particles.boundary_particles.len() == self.boundary_particles.len() && particles.boundary_particles != self.boundary_particles {
+            fluid_world.set_boundary_particle_positions(&self.boundary_particles);
+        }
+        // densities aren't sorted along with the other attributes
+        let mut densities = std::mem::take(&mut fluid_world.particles.densities);
+        copy_into(&mut densities, &self.densities);
+        fluid_world.update_neighborhood_datastructure(Vec::new(), vec![&mut densities]);
+        fluid_world.particles.densities = densities;
+        fluid_world.update_free_surface();
+    }
+}
+
+fn copy_into<T: Copy>(destination: &mut Vec<T>, source: &[T]) {
+    destination.clear();
+    destination.extend_from_slice(source);
+}
+
+// Everything the simulation thread owns while it runs.
+type SimulatedState = (FluidParticleWorld, Box<dyn Solver>, TimeManager);
+
+struct Control {
+    target_time: Real,
+    stop: bool,
+}
+
+struct Snapshots {
+    front: ParticleSnapshot, // newest snapshot, or the last one the viewer handed back
+    fresh: bool,             // whether front hasn't been taken yet
+}
+
+struct Shared {
+    control: Mutex<Control>,
+    control_changed: Condvar,
+    snapshots: Mutex<Snapshots>,
+}
+
+// Simulates a fluid world on a dedicated thread, so that heavy steps don't block the thread that looks at it, e.g. a window's event loop.
+// The thread steps until the simulation reaches a target time and then waits for the next one.
+// After every step it publishes a ParticleSnapshot, which is double-buffered: the simulation fills one buffer while the other is handed out.
+//
+// The world and solver are owned by the thread until it is joined, changes to the scene have to wait until then.
+pub struct SimulationThread {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<SimulatedState>>,
+}
+
+impl SimulationThread {
+    pub fn spawn(fluid_world: FluidParticleWorld, solver: Box<dyn Solver>, time_manager: TimeManager) -> SimulationThread {
+        let shared = Arc::new(Shared {
+            control: Mutex::new(Control {
+                target_time: time_manager.passed_time(),
+                stop: false,
+            }),
+            control_changed: Condvar::new(),
+            snapshots: Mutex::new(Snapshots {
+                front: ParticleSnapshot::default(),
+                fresh: false,
+            }),
+        });
+        let thread_shared = Arc::clone(&shared);
+        let thread = std::thread::Builder::new()
+            .name("simulation".to_string())
+            .spawn(move || Self::run(&thread_shared, fluid_world, solver, time_manager))
+            .expect("failed to spawn simulation thread");
+        SimulationThread {
+            shared,
+            thread: Some(thread),
+        }
+    }
+
+    fn run(shared: &Shared, mut fluid_world: FluidParticleWorld, mut solver: Box<dyn Solver>, mut time_manager: TimeManager) -> SimulatedState {
+        let mut snapshot = ParticleSnapshot::default();
+        let mut clock = SimulationClock::new();
+        let mut solver_error = None;
+        loop {
+            {
+                let mut control = shared.control.lock().unwrap();
+                loop {
+                    if control.stop {
+                        return (fluid_world, solver, time_manager);
+                    }
+                    let halted = solver.watchdog().and_then(|watchdog| watchdog.alarm()).is_some() || solver_error.is_some();
+                    if time_manager.passed_time() < control.target_time && !halted {
+                        break;
+                    }
+                    control = shared.control_changed.wait(control).unwrap();
+                }
+            }
+
+            let step_start = Instant::now();
+            if let Err(err) = solver.simulation_step(&mut fluid_world, &mut time_manager) {
+                solver_error = Some(err);
+            }
+            let step_processing_time = step_start.elapsed();
+            clock.record_step(time_manager.passed_time(), step_processing_time);
+
+            snapshot.capture(&fluid_world, solver.as_ref(), &time_manager);
+            snapshot.clock = clock;
+            snapshot.step_processing_time = step_processing_time;
+            snapshot.solver_error = solver_error.clone();
+            let mut snapshots = shared.snapshots.lock().unwrap();
+            std::mem::swap(&mut snapshots.front, &mut snapshot);
+            snapshots.fresh = true;
+        }
+    }
+
+    // Simulation time the thread steps up to. Can be moved backwards to pause, but the simulation never goes back in time.
+    pub fn set_target_time(&self, target_time: Real) {
+        self.shared.control.lock().unwrap().target_time = target_time;
+        self.shared.control_changed.notify_one();
+    }
+
+    // Swaps the newest snapshot into `snapshot` if there is one that wasn't taken yet. The previous content is recycled for later snapshots.
+    pub fn latest_snapshot(&self, snapshot: &mut ParticleSnapshot) -> bool {
+        let mut snapshots = self.shared.snapshots.lock().unwrap();
+        if !snapshots.fresh {
+            return false;
+        }
+        std::mem::swap(&mut snapshots.front, snapshot);
+        snapshots.fresh = false;
+        true
+    }
+
+    // Waits for the current step to finish and hands back fluid world, solver and time manager.
+    pub fn join(mut self) -> (FluidParticleWorld, Box<dyn Solver>, TimeManager) {
+        self.stop();
+        self.thread.take().unwrap().join().expect("simulation thread panicked")
+    }
+
+    fn stop(&self) {
+        self.shared.control.lock().unwrap().stop = true;
+        self.shared.control_changed.notify_one();
+    }
+}
+
+impl Drop for SimulationThread {
+    fn drop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            self.stop();
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sph::{TimeManagerConfiguration, WCSPHSolver, XSPHViscosityModel};
+
+    #[test]
+    fn steps_up_to_target_time_and_publishes_snapshots() {
+        let mut fluid_world = FluidParticleWorld::new(2.0, NumberDensity(5000.0), Density(100.0));
+        fluid_world.add_fluid_rect(&Rect::new(0.0, 0.5, 0.2, 0.2), 0.0);
+        let num_particles = fluid_world.particles.positions.len();
+        let solver = WCSPHSolver::new(
+            XSPHViscosityModel::new(fluid_world.properties.smoothing_length()),
+            &fluid_world.properties,
+        );
+        let time_manager = TimeManager::new(TimeManagerConfiguration::FixedTimeStep(0.001));
+        let thread = SimulationThread::spawn(fluid_world, Box::new(solver), time_manager);
+
+        let mut snapshot = ParticleSnapshot::default();
+        assert!(!thread.latest_snapshot(&mut snapshot));
+        thread.set_target_time(0.02);
+        let wait_start = Instant::now();
+        while snapshot.passed_time < 0.02 {
+            assert_lt!(wait_start.elapsed().as_secs(), 10, "simulation thread didn't reach the target time");
+            if !thread.latest_snapshot(&mut snapshot) {
+                std::thread::yield_now();
+            }
+        }
+        assert_eq!(snapshot.positions.len(), num_particles);
+        assert_eq!(snapshot.clock.num_steps(), 20);
+        // free fall
+        assert!(snapshot.velocities.iter().all(|v| v.y < 0.0));
+
+        // waits at the target time
+        std::thread::sleep(Duration::from_millis(10));
+        assert!(!thread.latest_snapshot(&mut snapshot));
+        let (fluid_world, _, time_manager) = thread.join();
+        assert_eq!(time_manager.passed_time(), snapshot.passed_time);
+
+        let mut display_world = fluid_world.copy_for_display();
+        snapshot.apply(&mut display_world);
+        assert_eq!(display_world.particles.positions.len(), num_particles);
+        assert_eq!(display_world.particles.densities.len(), num_particles);
+    }
+}
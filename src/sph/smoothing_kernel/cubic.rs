@@ -7,6 +7,7 @@ use crate::units::{Real, Vector};
 /// Normalization factors from https://pysph.readthedocs.io/en/latest/reference/kernels.html#monaghan1992
 #[derive(Copy, Clone)]
 pub struct CubicSpline {
+    h: Real,
     h_inv: Real,
     normalizer: Real,
     normalizer_grad: Real,
@@ -15,6 +16,7 @@ pub struct CubicSpline {
 impl CubicSpline {
     pub fn new(smoothing_length: Real) -> CubicSpline {
         CubicSpline {
+            h: smoothing_length,
             h_inv: 1.0 / smoothing_length,
             normalizer: 6.0 * 40.0 / (7.0 * std::f64::consts::PI as Real * smoothing_length * smoothing_length),
             normalizer_grad: 6.0 * 40.0 / (7.0 * std::f64::consts::PI as Real * smoothing_length * smoothing_length * smoothing_length),
@@ -25,35 +27,33 @@ impl CubicSpline {
 impl Kernel for CubicSpline {
     #[inline]
     fn evaluate(&self, _r_sq: Real, r: Real) -> Real {
+        // Check support on r directly, r * h_inv may round to slightly less than one for r == h.
+        if r >= self.h {
+            return 0.0;
+        }
         let q = r * self.h_inv;
         if q <= 0.5 {
             let q_sq = q * q;
             self.normalizer * ((1.0 / 6.0) + q_sq * q - q_sq)
-        } else if q <= 1.0 {
+        } else {
             let one_minus_q = 1.0 - q;
             self.normalizer * one_minus_q * one_minus_q * one_minus_q * (2.0 / 6.0)
-        } else {
-            0.0
         }
     }
 
     #[inline]
     fn gradient(&self, ri_to_rj: Vector, _r_sq: Real, r: Real) -> Vector {
+        if r >= self.h {
+            return cgmath::Zero::zero();
+        }
         let q = r * self.h_inv;
         if q <= 0.5 {
             self.normalizer_grad * q * (2.0 - q * 3.0) / r * ri_to_rj
-        } else if q < 1.0 {
+        } else {
             let factor = 1.0 - q;
             self.normalizer_grad * factor * factor / r * ri_to_rj
-        } else {
-            cgmath::Zero::zero()
         }
     }
-
-    #[inline]
-    fn laplacian(&self, _r_sq: Real, _r: Real) -> Real {
-        unimplemented!();
-    }
 }
 
 generate_kernel_tests!(CubicSpline);
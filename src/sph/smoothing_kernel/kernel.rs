@@ -26,16 +26,10 @@ pub trait Kernel {
         let r = r_sq.sqrt();
         self.gradient(ri_to_rj, r_sq, r)
     }
-
-    /// Evaluates the laplacian of the kernel, i.e. the second derivative.
-    /// `r_sq`:     Squared length of ri_to_rj
-    /// `r`:        Length of ri_to_rj
-    fn laplacian(&self, r_sq: Real, r: Real) -> Real;
 }
 
 // TODO:
 // * Try WendlandQuintic: https://pysph.readthedocs.io/en/latest/reference/kernels.html#pysph.base.kernels.WendlandQuintic
-// * consider removing laplacian alltogether
 
 macro_rules! generate_kernel_tests {
     ($kernel_type:ident) => {
@@ -43,6 +37,7 @@ macro_rules! generate_kernel_tests {
         mod tests {
             use super::*;
             use cgmath::prelude::*;
+            use rand::prelude::*;
 
             pub static TEST_SMOOTHING_LENGTHS: [Real; 3] = [0.5, 1.0, 123.0];
 
@@ -73,6 +68,19 @@ macro_rules! generate_kernel_tests {
                 });
             }
 
+            // Random offsets with lengths in [min_radius, max_radius), uniformly distributed in angle.
+            // Seeded, so failures are reproducible.
+            fn random_offsets(min_radius: Real, max_radius: Real) -> impl Iterator<Item = Vector> {
+                const NUM_SAMPLES: usize = 1000;
+                let mut rng: rand::rngs::SmallRng = rand::SeedableRng::seed_from_u64(123456789);
+                std::iter::repeat_with(move || {
+                    let r = min_radius + rng.gen::<Real>() * (max_radius - min_radius);
+                    let angle = rng.gen::<Real>() * 2.0 * std::f64::consts::PI as Real;
+                    Vector::new(angle.cos(), angle.sin()) * r
+                })
+                .take(NUM_SAMPLES)
+            }
+
             #[test]
             fn is_positive_within_smoothing_length() {
                 run_for_different_kernel_sizes(|kernel, smoothing_length| {
@@ -159,6 +167,99 @@ macro_rules! generate_kernel_tests {
                     });
                 });
             }
+
+            #[test]
+            fn evaluate_is_nonnegative_at_random_points() {
+                run_for_different_kernel_sizes(|kernel, smoothing_length| {
+                    for p in random_offsets(0.0, smoothing_length * 2.0) {
+                        assert_ge!(kernel.evaluate(p.magnitude2(), p.magnitude()), 0.0, "negative at {:?}", p);
+                    }
+                });
+            }
+
+            #[test]
+            fn has_compact_support_at_smoothing_length() {
+                run_for_different_kernel_sizes(|kernel, smoothing_length| {
+                    let h = smoothing_length;
+                    assert_eq!(kernel.evaluate(h * h, h), 0.0);
+                    for direction in random_offsets(1.0, 2.0) {
+                        let direction = direction.normalize();
+                        assert_eq!(kernel.gradient(direction * h, h * h, h), Vector::zero());
+                    }
+                    for p in random_offsets(h, h * 3.0) {
+                        assert_eq!(kernel.evaluate(p.magnitude2(), p.magnitude()), 0.0, "non zero at {:?}", p);
+                        assert_eq!(
+                            kernel.gradient(p, p.magnitude2(), p.magnitude()),
+                            Vector::zero(),
+                            "non zero gradient at {:?}",
+                            p
+                        );
+                    }
+                });
+            }
+
+            #[test]
+            fn integrates_to_one_radially() {
+                // Since kernels are radially symmetric the 2D integral is 2π ∫ W(r) r dr, which we can do with Simpson's rule to high precision.
+                run_for_different_kernel_sizes(|kernel, smoothing_length| {
+                    const NUM_INTERVALS: usize = 1000;
+                    let dr = smoothing_length / NUM_INTERVALS as Real;
+                    let integrand = |r: Real| kernel.evaluate(r * r, r) * r;
+                    let mut integral = integrand(0.0) + integrand(smoothing_length);
+                    for i in 1..NUM_INTERVALS {
+                        integral += integrand(i as Real * dr) * if i % 2 == 0 { 2.0 } else { 4.0 };
+                    }
+                    integral *= dr / 3.0 * 2.0 * std::f64::consts::PI as Real;
+                    assert_lt!((1.0 - integral).abs(), 0.001, "smoothing_length {}", smoothing_length);
+                });
+            }
+
+            #[test]
+            fn gradient_points_along_ri_to_rj_at_random_points() {
+                // The kernel falls off with distance, so its gradient with respect to ri points toward rj, the kernel's origin.
+                run_for_different_kernel_sizes(|kernel, smoothing_length| {
+                    for p in random_offsets(smoothing_length * 0.01, smoothing_length * 0.99) {
+                        let gradient = kernel.gradient(p, p.magnitude2(), p.magnitude());
+                        let direction = p.normalize();
+                        assert_gt!(gradient.dot(direction), 0.0, "gradient {:?} at {:?}", gradient, p);
+                        assert_lt!(
+                            (gradient.x * direction.y - gradient.y * direction.x).abs(),
+                            gradient.magnitude() * 0.0001,
+                            "gradient {:?} at {:?}",
+                            gradient,
+                            p
+                        );
+                    }
+                });
+            }
+
+            #[test]
+            fn gradient_matches_finite_differences_at_random_points() {
+                run_for_different_kernel_sizes(|kernel, smoothing_length| {
+                    let step = smoothing_length * 0.001;
+                    let evaluate = |p: Vector| kernel.evaluate(p.magnitude2(), p.magnitude());
+                    // Scale of the gradient, used to allow for some absolute error where the gradient vanishes.
+                    let gradient_scale = evaluate(Vector::zero()) / smoothing_length;
+
+                    // Stay away from the origin, where Spiky's gradient is discontinuous.
+                    for p in random_offsets(smoothing_length * 0.05, smoothing_length * 1.5) {
+                        let analytical_gradient = kernel.gradient(p, p.magnitude2(), p.magnitude());
+                        // gradient is with respect to ri while p is rj - ri, hence the sign flip.
+                        let numerical_gradient = Vector::new(
+                            evaluate(p - Vector::new(step, 0.0)) - evaluate(p + Vector::new(step, 0.0)),
+                            evaluate(p - Vector::new(0.0, step)) - evaluate(p + Vector::new(0.0, step)),
+                        ) / (2.0 * step);
+                        assert_lt!(
+                            (analytical_gradient - numerical_gradient).magnitude(),
+                            analytical_gradient.magnitude() * 0.01 + gradient_scale * 0.001,
+                            "analytical_gradient {:?}, numerical_gradient {:?} at {:?}",
+                            analytical_gradient,
+                            numerical_gradient,
+                            p
+                        );
+                    }
+                });
+            }
         }
     };
 }
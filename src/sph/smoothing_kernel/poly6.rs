@@ -35,11 +35,6 @@ impl Kernel for Poly6 {
         let hsq_sub_rsq = (self.hsq - r_sq).max(0.0);
         self.normalizer_grad * hsq_sub_rsq * hsq_sub_rsq * ri_to_rj
     }
-
-    #[inline]
-    fn laplacian(&self, _r_sq: Real, _r: Real) -> Real {
-        unimplemented!();
-    }
 }
 
 generate_kernel_tests!(Poly6);
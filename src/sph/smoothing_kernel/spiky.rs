@@ -35,11 +35,6 @@ impl Kernel for Spiky {
         let hsubr = (self.h - r).max(0.0);
         (self.normalizer_grad * hsubr * hsubr / (r + Self::DIVISION_EPSILON)) * ri_to_rj
     }
-
-    #[inline]
-    fn laplacian(&self, _r_sq: Real, _r: Real) -> Real {
-        unimplemented!();
-    }
 }
 
 generate_kernel_tests!(Spiky);
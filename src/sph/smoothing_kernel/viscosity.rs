@@ -21,9 +21,19 @@ impl Viscosity {
             h: smoothing_length,
             hsq: smoothing_length * smoothing_length,
             normalizer: 90.0 / (29.0 * std::f64::consts::PI as Real * smoothing_length * smoothing_length),
-            normalizer_laplacian: 360.0 / (29.0 * std::f64::consts::PI as Real * smoothing_length.powi(5)),
+            // Not the laplacian of the above, but scaled such that Σ_j V_j (v_j - v_i) ∇²W_ij approximates ∇²v,
+            // i.e. ∫ x²/2 ∇²W dA = 1 (the 2D counterpart of Müller et al.'s 45 / (π h⁶)).
+            normalizer_laplacian: 40.0 / (std::f64::consts::PI as Real * smoothing_length.powi(5)),
         }
     }
+
+    /// Evaluates the laplacian of the kernel, i.e. the second derivative. Only this kernel has one, see normalizer_laplacian.
+    /// `r_sq`:     Squared length of ri_to_rj
+    /// `r`:        Length of ri_to_rj
+    #[inline]
+    pub fn laplacian(&self, _r_sq: Real, r: Real) -> Real {
+        self.normalizer_laplacian * (self.h - r)
+    }
 }
 
 impl Kernel for Viscosity {
@@ -37,16 +47,63 @@ impl Kernel for Viscosity {
     }
 
     #[inline]
-    fn gradient(&self, _ri_to_rj: Vector, _r_sq: Real, _r: Real) -> Vector {
-        unimplemented!();
-    }
-
-    #[inline]
-    fn laplacian(&self, _r_sq: Real, r: Real) -> Real {
-        self.normalizer_laplacian * (self.h - r)
+    fn gradient(&self, ri_to_rj: Vector, _r_sq: Real, r: Real) -> Vector {
+        // dW/dr divided by r, since ri_to_rj isn't normalized
+        if r < self.h {
+            (self.normalizer * (4.0 * r / (3.0 * self.h) + 2.0) / self.hsq) * ri_to_rj
+        } else {
+            Vector::new(0.0, 0.0)
+        }
     }
 }
 
-// Viscosity kernel doesn't implement gradient and integral over domain doesn't seem to be quite right.
+// Integral of the viscosity kernel over its domain doesn't seem to be quite right.
 // Wrong normalization factor?
 //generate_kernel_tests!(Viscosity);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::prelude::*;
+
+    #[test]
+    fn gradient_matches_finite_differences() {
+        let kernel = Viscosity::new(1.0);
+        let epsilon = 1.0e-3;
+        for &r in [0.1, 0.5, 0.9].iter() {
+            let ri_to_rj = Vector::new(r * 0.6, r * 0.8);
+            let derivative =
+                (kernel.evaluate((r + epsilon).powi(2), r + epsilon) - kernel.evaluate((r - epsilon).powi(2), r - epsilon)) / (2.0 * epsilon);
+            let expected = ri_to_rj / r * derivative;
+            assert!((kernel.gradient(ri_to_rj, r * r, r) - expected).magnitude() < 1.0e-3 * expected.magnitude());
+        }
+        assert_eq!(kernel.gradient(Vector::new(1.5, 0.0), 2.25, 1.5), Vector::zero());
+    }
+
+    #[test]
+    fn laplacian_reproduces_second_derivative() {
+        // v(x) = x²/2 has ∇²v = 1, the kernel should reproduce that from the differences v(x) - v(0)
+        for &smoothing_length in [0.5, 1.0, 123.0].iter() {
+            let kernel = Viscosity::new(smoothing_length);
+            const SAMPLES_PER_AXIS: usize = 400;
+            let cell_size = 2.0 * smoothing_length / SAMPLES_PER_AXIS as Real;
+            let mut laplacian = 0.0;
+            for x in 0..SAMPLES_PER_AXIS {
+                for y in 0..SAMPLES_PER_AXIS {
+                    let p = (Vector::new(x as Real, y as Real) + Vector::new(0.5, 0.5)) * cell_size - Vector::new(smoothing_length, smoothing_length);
+                    let r_sq = p.x * p.x + p.y * p.y;
+                    let r = r_sq.sqrt();
+                    if r < smoothing_length {
+                        laplacian += p.x * p.x * 0.5 * kernel.laplacian(r_sq, r) * cell_size * cell_size;
+                    }
+                }
+            }
+            assert!(
+                (laplacian - 1.0).abs() < 0.01,
+                "laplacian of x²/2 is {} for smoothing length {}",
+                laplacian,
+                smoothing_length
+            );
+        }
+    }
+}
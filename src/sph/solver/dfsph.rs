@@ -1,9 +1,12 @@
-use super::super::fluidparticleworld::FluidParticleWorld;
+use super::super::error::SphError;
+use super::super::fluidparticleworld::{retain_particle_attribute, FluidParticleWorld};
+use super::super::forcefield::ForceField;
 use super::super::smoothing_kernel;
 use super::super::smoothing_kernel::Kernel;
+use super::super::steptimings::{Instant, SimulationPass, StepTimings};
 use super::super::timemanager::TimeManager;
 use super::super::viscositymodel::ViscosityModel;
-use super::Solver;
+use super::{Solver, SolverIterations, SolverOptions, SolverParameter, SolverResiduals};
 use crate::units::*;
 use cgmath::prelude::*;
 use rayon::prelude::*;
@@ -29,6 +32,8 @@ pub struct DFSPHSolver<TViscosityModel: ViscosityModel> {
     max_num_divergence_correction_iterations: usize,
     // Number of divergence minimizer iterations on the last round
     num_divergence_correction_iterations: usize,
+    // Errors after each density and divergence minimizer iteration on the last round
+    residuals: SolverResiduals,
 
     // Recomputed every simulation frame, but needs to be up to date at start of simulation step.
     alpha_values: Vec<Real>,
@@ -36,6 +41,13 @@ pub struct DFSPHSolver<TViscosityModel: ViscosityModel> {
     // Stiffness sum from last simulation frame to improve convergence.
     warmstart_stiffness: Vec<Real>,
     warmstart_kappa: Vec<Real>,
+
+    // Per particle sum of all stiffness values applied against boundaries during the current step, in velocity change per boundary mass and kernel gradient.
+    // Used to compute the forces on boundaries.
+    boundary_stiffness: Vec<Real>,
+
+    timings: StepTimings,
+    options: SolverOptions,
 }
 impl<TViscosityModel: ViscosityModel + std::marker::Sync> DFSPHSolver<TViscosityModel> {
     pub fn new(viscosity_model: TViscosityModel, smoothing_length: Real) -> DFSPHSolver<TViscosityModel> {
@@ -51,13 +63,25 @@ impl<TViscosityModel: ViscosityModel + std::marker::Sync> DFSPHSolver<TViscosity
             max_divergence_error: 0.1 / 100.0, // 1.0% deviation per second.
             max_num_divergence_correction_iterations: 400,
             num_divergence_correction_iterations: 0,
+            residuals: Default::default(),
 
             alpha_values: vec![],
             warmstart_kappa: vec![],
             warmstart_stiffness: vec![],
+            boundary_stiffness: vec![],
+
+            timings: Default::default(),
+            options: Default::default(),
         }
     }
 
+    // Adds the time since timer to a pass and returns the timer for the next pass.
+    fn record_pass(&mut self, pass: SimulationPass, timer: Instant) -> Instant {
+        let now = Instant::now();
+        self.timings.add(pass, now - timer);
+        now
+    }
+
     // computes alpha factors.
     // Note that in the paper the alpha factors contained density as well (== density / thing-we-compute-here)
     // (Note that the newer Eurographics SPH Tutorial from 2019 https://interactivecomputergraphics.github.io/SPH-Tutorial/pdf/SPH_Tutorial.pdf actually works with density-squared!)
@@ -66,7 +90,8 @@ impl<TViscosityModel: ViscosityModel + std::marker::Sync> DFSPHSolver<TViscosity
     fn compute_alpha_factors(alpha_values: &mut Vec<Real>, fluid_world: &FluidParticleWorld, kernel: impl Kernel + std::marker::Sync) {
         microprofile::scope!("DFSPHSolver", "compute_alpha_factors");
         const EPSILON: Real = 1e-6;
-        let particle_mass = fluid_world.properties.particle_mass();
+        // boundary particles contribute like fluid particles at rest
+        let boundary_mass = fluid_world.boundary_particle_mass();
         let particles = &fluid_world.particles;
         alpha_values
             .par_iter_mut()
@@ -82,7 +107,7 @@ impl<TViscosityModel: ViscosityModel + std::marker::Sync> DFSPHSolver<TViscosity
                     #[inline(always)]
                     |j| {
                         let pos_j = particles.positions[j as usize];
-                        let grad_ij = kernel.gradient_from_positions(ri, pos_j) * particle_mass;
+                        let grad_ij = kernel.gradient_from_positions(ri, pos_j) * particles.masses[j as usize];
                         gradient_sum += grad_ij;
                         gradient_square_sum += grad_ij.magnitude2();
                     },
@@ -92,7 +117,7 @@ impl<TViscosityModel: ViscosityModel + std::marker::Sync> DFSPHSolver<TViscosity
                     #[inline(always)]
                     |j| {
                         let pos_j = particles.boundary_particles[j as usize];
-                        let grad_ij = kernel.gradient_from_positions(ri, pos_j) * particle_mass;
+                        let grad_ij = kernel.gradient_from_positions(ri, pos_j) * boundary_mass;
                         gradient_sum += grad_ij;
                         gradient_square_sum += grad_ij.magnitude2();
                     },
@@ -105,7 +130,7 @@ impl<TViscosityModel: ViscosityModel + std::marker::Sync> DFSPHSolver<TViscosity
 
     fn compute_density_error(&self, dt: Real, fluid_world: &FluidParticleWorld, velocities: &[Vector], density_error: &mut [Real]) {
         microprofile::scope!("DFSPHSolver", "compute_density_error");
-        let particle_mass = fluid_world.properties.particle_mass();
+        let boundary_mass = fluid_world.boundary_particle_mass();
         let particles = &fluid_world.particles;
         let reference_density = fluid_world.properties.fluid_density();
         density_error
@@ -121,7 +146,7 @@ impl<TViscosityModel: ViscosityModel + std::marker::Sync> DFSPHSolver<TViscosity
                     |j| {
                         let pos_j = particles.positions[j as usize];
                         let delta_v = velocity_vi - velocities[j as usize];
-                        delta += delta_v.dot(self.kernel.gradient_from_positions(pos_i, pos_j));
+                        delta += delta_v.dot(self.kernel.gradient_from_positions(pos_i, pos_j)) * particles.masses[j as usize];
                     },
                 );
                 particles.foreach_neighbor_particle_boundary(
@@ -129,11 +154,11 @@ impl<TViscosityModel: ViscosityModel + std::marker::Sync> DFSPHSolver<TViscosity
                     #[inline(always)]
                     |j| {
                         let pos_j = particles.boundary_particles[j as usize];
-                        let delta_v = velocity_vi;
-                        delta += delta_v.dot(self.kernel.gradient_from_positions(pos_i, pos_j));
+                        let delta_v = velocity_vi - particles.boundary_velocities[j as usize];
+                        delta += delta_v.dot(self.kernel.gradient_from_positions(pos_i, pos_j)) * boundary_mass;
                     },
                 );
-                *density_error_i = original_density + delta * particle_mass * dt;
+                *density_error_i = original_density + delta * dt;
 
                 // ignore loss of density
                 *density_error_i = reference_density.max(*density_error_i) - reference_density;
@@ -142,7 +167,7 @@ impl<TViscosityModel: ViscosityModel + std::marker::Sync> DFSPHSolver<TViscosity
 
     fn correct_velocity_with_density_error(&mut self, dt: Real, fluid_world: &FluidParticleWorld, velocities: &mut [Vector], density_error: &[Real]) {
         microprofile::scope!("DFSPHSolver", "correct_velocity_with_density_error");
-        let particle_mass = fluid_world.properties.particle_mass();
+        let boundary_mass = fluid_world.boundary_particle_mass();
         let particles = &fluid_world.particles;
         let inv_dt = 1.0 / dt;
         let kernel = &self.kernel;
@@ -168,7 +193,7 @@ impl<TViscosityModel: ViscosityModel + std::marker::Sync> DFSPHSolver<TViscosity
                     |j| {
                         let pos_j = particles.positions[j as usize];
                         let kj = density_error[j as usize] * alpha_values[j as usize];
-                        delta += (ki + kj) * kernel.gradient_from_positions(ri, pos_j);
+                        delta += (ki + kj) * particles.masses[j as usize] * kernel.gradient_from_positions(ri, pos_j);
                     },
                 );
                 particles.foreach_neighbor_particle_boundary(
@@ -177,17 +202,17 @@ impl<TViscosityModel: ViscosityModel + std::marker::Sync> DFSPHSolver<TViscosity
                     |j| {
                         // compared to k values in paper already divided with density and multiplied with dt²!
                         let pos_j = particles.boundary_particles[j as usize];
-                        delta += ki * kernel.gradient_from_positions(ri, pos_j);
+                        delta += ki * boundary_mass * kernel.gradient_from_positions(ri, pos_j);
                     },
                 );
 
-                *predicted_velocity -= inv_dt * delta * particle_mass;
+                *predicted_velocity -= inv_dt * delta;
             });
     }
 
     fn correct_density_error_warmstart(&self, dt: Real, fluid_world: &FluidParticleWorld, velocities: &mut [Vector]) {
         microprofile::scope!("DFSPHSolver", "correct_density_error_warmstart");
-        let particle_mass = fluid_world.properties.particle_mass();
+        let boundary_mass = fluid_world.boundary_particle_mass();
         let particles = &fluid_world.particles;
         let inv_dt = 1.0 / dt;
         let kernel = &self.kernel;
@@ -210,7 +235,7 @@ impl<TViscosityModel: ViscosityModel + std::marker::Sync> DFSPHSolver<TViscosity
                     |j| {
                         let pos_j = particles.positions[j as usize];
                         let kj = self.warmstart_kappa[j as usize];
-                        delta += (ki + kj) * kernel.gradient_from_positions(ri, pos_j);
+                        delta += (ki + kj) * particles.masses[j as usize] * kernel.gradient_from_positions(ri, pos_j);
                     },
                 );
                 particles.foreach_neighbor_particle_boundary(
@@ -218,15 +243,21 @@ impl<TViscosityModel: ViscosityModel + std::marker::Sync> DFSPHSolver<TViscosity
                     #[inline(always)]
                     |j| {
                         let pos_j = particles.boundary_particles[j as usize];
-                        delta += ki * kernel.gradient_from_positions(ri, pos_j);
+                        delta += ki * boundary_mass * kernel.gradient_from_positions(ri, pos_j);
                     },
                 );
 
-                *predicted_velocity -= inv_dt * delta * particle_mass;
+                *predicted_velocity -= inv_dt * delta;
             });
     }
 
-    fn correct_density_error(&mut self, dt: Real, fluid_world: &mut FluidParticleWorld, velocities: &mut [Vector]) {
+    fn correct_density_error(
+        &mut self,
+        time: Real,
+        dt: Real,
+        fluid_world: &mut FluidParticleWorld,
+        velocities: &mut [Vector],
+    ) -> Result<(), SphError> {
         microprofile::scope!("DFSPHSolver", "correct_density_error");
 
         // Warm start just wastes compute if there's no turbulence at all.
@@ -237,7 +268,9 @@ impl<TViscosityModel: ViscosityModel + std::marker::Sync> DFSPHSolver<TViscosity
             }
             self.correct_density_error_warmstart(dt, fluid_world, velocities);
         }
-        for k in &mut self.warmstart_kappa {
+        for (boundary_stiffness, k) in self.boundary_stiffness.iter_mut().zip(self.warmstart_kappa.iter_mut()) {
+            // velocity correction of the density solve divides by dt, unlike the one of the divergence solve
+            *boundary_stiffness = if self.num_density_correction_iterations > 1 { *k / dt } else { 0.0 };
             *k = 0.0;
         }
 
@@ -245,6 +278,8 @@ impl<TViscosityModel: ViscosityModel + std::marker::Sync> DFSPHSolver<TViscosity
         let density_error = &mut _density_error.buffer;
 
         self.num_density_correction_iterations = 0;
+        self.residuals.density.clear();
+        self.residuals.density_target = self.max_avg_density_error;
         loop {
             microprofile::scope!("DFSPHSolver", "density_iteration");
 
@@ -254,7 +289,13 @@ impl<TViscosityModel: ViscosityModel + std::marker::Sync> DFSPHSolver<TViscosity
 
             let avg_density_error: Real = density_error.par_iter().sum::<Real>() / density_error.len() as Real;
             let relative_density_error = avg_density_error / fluid_world.properties.fluid_density();
-            assert!(avg_density_error.is_finite());
+            if !avg_density_error.is_finite() {
+                return Err(SphError::NonFinite {
+                    what: "average density error",
+                    time,
+                });
+            }
+            self.residuals.density.push(relative_density_error * dt);
 
             // error is expressed relative to fluid density and time!
             if relative_density_error * dt < self.max_avg_density_error {
@@ -278,11 +319,15 @@ impl<TViscosityModel: ViscosityModel + std::marker::Sync> DFSPHSolver<TViscosity
                 break;
             }
         }
+        for (boundary_stiffness, k) in self.boundary_stiffness.iter_mut().zip(self.warmstart_kappa.iter()) {
+            *boundary_stiffness += k / dt;
+        }
+        Ok(())
     }
 
     fn compute_density_change(&self, fluid_world: &FluidParticleWorld, velocities: &[Vector], density_change: &mut [Real]) {
         microprofile::scope!("DFSPHSolver", "compute_density_change");
-        let particle_mass = fluid_world.properties.particle_mass();
+        let boundary_mass = fluid_world.boundary_particle_mass();
         let particles = &fluid_world.particles;
         density_change
             .par_iter_mut()
@@ -304,7 +349,7 @@ impl<TViscosityModel: ViscosityModel + std::marker::Sync> DFSPHSolver<TViscosity
                     |j| {
                         let pos_j = particles.positions[j as usize];
                         let delta_v = velocity_vi - velocities[j as usize];
-                        delta += delta_v.dot(self.kernel.gradient_from_positions(ri, pos_j));
+                        delta += delta_v.dot(self.kernel.gradient_from_positions(ri, pos_j)) * particles.masses[j as usize];
                     },
                 );
                 particles.foreach_neighbor_particle_boundary(
@@ -312,18 +357,18 @@ impl<TViscosityModel: ViscosityModel + std::marker::Sync> DFSPHSolver<TViscosity
                     #[inline(always)]
                     |j| {
                         let pos_j = particles.boundary_particles[j as usize];
-                        let delta_v = velocity_vi;
-                        delta += delta_v.dot(self.kernel.gradient_from_positions(ri, pos_j));
+                        let delta_v = velocity_vi - particles.boundary_velocities[j as usize];
+                        delta += delta_v.dot(self.kernel.gradient_from_positions(ri, pos_j)) * boundary_mass;
                     },
                 );
-                *density_change_i = delta * particle_mass;
+                *density_change_i = delta;
                 *density_change_i = density_change_i.max(0.0); // clamp density loss
             });
     }
 
     fn correct_velocity_with_divergence_error(&mut self, fluid_world: &FluidParticleWorld, velocities: &mut [Vector], density_change: &[Real]) {
         microprofile::scope!("DFSPHSolver", "correct_velocity_with_divergence_error");
-        let particle_mass = fluid_world.properties.particle_mass();
+        let boundary_mass = fluid_world.boundary_particle_mass();
         let particles = &fluid_world.particles;
         let kernel = &self.kernel;
         let alpha_values = &self.alpha_values;
@@ -348,7 +393,7 @@ impl<TViscosityModel: ViscosityModel + std::marker::Sync> DFSPHSolver<TViscosity
                     |j| {
                         let pos_j = particles.positions[j as usize];
                         let kj = density_change[j as usize] * alpha_values[j as usize];
-                        delta += (ki + kj) * kernel.gradient_from_positions(ri, pos_j);
+                        delta += (ki + kj) * particles.masses[j as usize] * kernel.gradient_from_positions(ri, pos_j);
                     },
                 );
                 particles.foreach_neighbor_particle_boundary(
@@ -356,17 +401,17 @@ impl<TViscosityModel: ViscosityModel + std::marker::Sync> DFSPHSolver<TViscosity
                     #[inline(always)]
                     |j| {
                         let pos_j = particles.boundary_particles[j as usize];
-                        delta += ki * kernel.gradient_from_positions(ri, pos_j);
+                        delta += ki * boundary_mass * kernel.gradient_from_positions(ri, pos_j);
                     },
                 );
 
-                *predicted_velocity -= delta * particle_mass;
+                *predicted_velocity -= delta;
             });
     }
 
     fn correct_divergence_error_warmstart(&self, fluid_world: &FluidParticleWorld, velocities: &mut [Vector]) {
         microprofile::scope!("DFSPHSolver", "correct_divergence_error_warmstart");
-        let particle_mass = fluid_world.properties.particle_mass();
+        let boundary_mass = fluid_world.boundary_particle_mass();
         let particles = &fluid_world.particles;
         let kernel = &self.kernel;
 
@@ -388,7 +433,7 @@ impl<TViscosityModel: ViscosityModel + std::marker::Sync> DFSPHSolver<TViscosity
                     |j| {
                         let pos_j = particles.positions[j as usize];
                         let kj = self.warmstart_stiffness[j as usize];
-                        delta += (ki + kj) * kernel.gradient_from_positions(ri, pos_j);
+                        delta += (ki + kj) * particles.masses[j as usize] * kernel.gradient_from_positions(ri, pos_j);
                     },
                 );
                 particles.foreach_neighbor_particle_boundary(
@@ -396,15 +441,21 @@ impl<TViscosityModel: ViscosityModel + std::marker::Sync> DFSPHSolver<TViscosity
                     #[inline(always)]
                     |j| {
                         let pos_j = particles.boundary_particles[j as usize];
-                        delta += ki * kernel.gradient_from_positions(ri, pos_j);
+                        delta += ki * boundary_mass * kernel.gradient_from_positions(ri, pos_j);
                     },
                 );
 
-                *predicted_velocity -= delta * particle_mass;
+                *predicted_velocity -= delta;
             });
     }
 
-    fn correct_divergence_error(&mut self, dt: Real, fluid_world: &mut FluidParticleWorld, velocities: &mut [Vector]) {
+    fn correct_divergence_error(
+        &mut self,
+        time: Real,
+        dt: Real,
+        fluid_world: &mut FluidParticleWorld,
+        velocities: &mut [Vector],
+    ) -> Result<(), SphError> {
         microprofile::scope!("DFSPHSolver", "correct_divergence_error");
 
         // Relationship between density change and divergence:
@@ -418,6 +469,9 @@ impl<TViscosityModel: ViscosityModel + std::marker::Sync> DFSPHSolver<TViscosity
                 *s = 0.5 * s.max(-0.5 * fluid_world.properties.fluid_density() * fluid_world.properties.fluid_density());
             }
             self.correct_divergence_error_warmstart(fluid_world, velocities);
+            for (boundary_stiffness, s) in self.boundary_stiffness.iter_mut().zip(self.warmstart_stiffness.iter()) {
+                *boundary_stiffness += s;
+            }
         }
         for s in &mut self.warmstart_stiffness {
             *s = 0.0;
@@ -427,6 +481,8 @@ impl<TViscosityModel: ViscosityModel + std::marker::Sync> DFSPHSolver<TViscosity
         let density_change = &mut _density_change.buffer;
 
         self.num_divergence_correction_iterations = 0;
+        self.residuals.divergence.clear();
+        self.residuals.divergence_target = self.max_divergence_error;
         loop {
             microprofile::scope!("DFSPHSolver", "density_change_iteration");
 
@@ -436,7 +492,13 @@ impl<TViscosityModel: ViscosityModel + std::marker::Sync> DFSPHSolver<TViscosity
 
             let avg_divergence: Real =
                 density_change.par_iter().sum::<Real>() / density_change.len() as Real / fluid_world.properties.fluid_density();
-            assert!(avg_divergence.is_finite());
+            if !avg_divergence.is_finite() {
+                return Err(SphError::NonFinite {
+                    what: "average divergence",
+                    time,
+                });
+            }
+            self.residuals.divergence.push(avg_divergence * dt);
 
             // error is expressed relative to time
             if avg_divergence * dt < self.max_divergence_error {
@@ -460,33 +522,51 @@ impl<TViscosityModel: ViscosityModel + std::marker::Sync> DFSPHSolver<TViscosity
                 break;
             }
         }
-    }
-}
-
-impl<TViscosityModel: ViscosityModel + std::marker::Sync> Solver for DFSPHSolver<TViscosityModel> {
-    fn clear_cached_data(&mut self) {
-        self.alpha_values.clear();
-        self.warmstart_stiffness.clear();
-        self.warmstart_kappa.clear();
-        self.num_divergence_correction_iterations = 0;
-        self.num_density_correction_iterations = 0;
+        for (boundary_stiffness, s) in self.boundary_stiffness.iter_mut().zip(self.warmstart_stiffness.iter()) {
+            *boundary_stiffness += s;
+        }
+        Ok(())
     }
 
-    fn simulation_step(&mut self, fluid_world: &mut FluidParticleWorld, time_manager: &mut TimeManager) {
+    // simulation_step on the threads of the options' thread pool.
+    fn step(&mut self, fluid_world: &mut FluidParticleWorld, time_manager: &mut TimeManager) -> Result<(), SphError> {
         microprofile::scope!("DFSPHSolver", "simulation_step");
+        self.timings.clear();
+        if let Some(velocity_clamping) = &mut self.options.velocity_clamping {
+            velocity_clamping.reset_counters();
+        }
+        if self.options.watchdog_check(|watchdog| watchdog.alarm().is_some()) {
+            return Ok(());
+        }
+        fluid_world.check_particle_arrays()?;
+        let time = time_manager.passed_time();
 
         // ensure densities and alpha factors were initialized previously ("warmup")
-        // Todo: Not happy about the way added particles are handled here. This sort of works for adding, but removing this way is impossible with this design!
+        // Todo: Not happy about the way added particles are handled here. This sort of works for adding, removed particles need to go through retain_particle_data.
         if self.alpha_values.len() != fluid_world.particles.positions.len() {
             self.alpha_values.resize(fluid_world.particles.positions.len(), 0.0 as Real);
             self.warmstart_stiffness.resize(fluid_world.particles.positions.len(), 0.0 as Real);
             self.warmstart_kappa.resize(fluid_world.particles.positions.len(), 0.0 as Real);
+            self.boundary_stiffness.resize(fluid_world.particles.positions.len(), 0.0 as Real);
 
             // todo: Update only new particles.. HOW? better would be to only effectively add later
-            fluid_world.update_neighborhood_datastructure(Vec::new(), vec![&mut self.alpha_values]);
+            let timer = Instant::now();
+            // warm start values and boundary stiffness carry over from the last step, they have to follow their particles
+            fluid_world.update_neighborhood_datastructure(
+                Vec::new(),
+                vec![
+                    &mut self.alpha_values,
+                    &mut self.warmstart_kappa,
+                    &mut self.warmstart_stiffness,
+                    &mut self.boundary_stiffness,
+                ],
+            );
+            let timer = self.record_pass(SimulationPass::Neighborhood, timer);
             fluid_world.update_densities(self.kernel);
             Self::compute_alpha_factors(&mut self.alpha_values, fluid_world, self.kernel);
+            self.record_pass(SimulationPass::Density, timer);
         }
+        let timer = Instant::now();
 
         let mut _predicted_velocities = fluid_world.scratch_buffers.get_buffer_vector(fluid_world.particles.positions.len());
         let predicted_velocities = &mut _predicted_velocities.buffer;
@@ -498,22 +578,21 @@ impl<TViscosityModel: ViscosityModel + std::marker::Sync> Solver for DFSPHSolver
             {
                 microprofile::scope!("DFSPHSolver", "non-pressure forces");
 
-                let non_pressure_forces: Vector = fluid_world.gravity * fluid_world.properties.particle_mass();
-                let particle_mass = fluid_world.properties.particle_mass();
-                let non_pressure_accelleration = non_pressure_forces / particle_mass;
                 let dt = time_manager.timestep();
                 let particles = &fluid_world.particles;
                 let viscosity_model = &self.viscosity_model;
+                let force_fields = &fluid_world.force_fields;
                 accellerations
                     .buffer
                     .par_iter_mut()
                     .zip((&particles.positions, &particles.velocities).into_par_iter())
                     .enumerate()
                     .for_each(|(i, (a, (&ri, &vi)))| {
-                        // forces
-                        *a = non_pressure_accelleration;
+                        // external forces
+                        *a = ForceField::total_accelleration(force_fields, ri, vi, time);
 
                         // viscosity
+                        let rhoi = particles.densities[i];
                         particles.foreach_neighbor_particle(
                             i as u32,
                             #[inline(always)]
@@ -524,7 +603,8 @@ impl<TViscosityModel: ViscosityModel + std::marker::Sync> Solver for DFSPHSolver
                                     dt,
                                     r_sq,
                                     r_sq.sqrt(),
-                                    particle_mass,
+                                    particles.masses[j],
+                                    rhoi,
                                     particles.densities[j],
                                     particles.velocities[j] - vi,
                                 );
@@ -532,6 +612,17 @@ impl<TViscosityModel: ViscosityModel + std::marker::Sync> Solver for DFSPHSolver
                         );
                     });
             }
+            let timer = self.record_pass(SimulationPass::Viscosity, timer);
+            if self
+                .options
+                .watchdog_check(|watchdog| watchdog.check_accellerations(SimulationPass::Viscosity, time, &accellerations.buffer))
+            {
+                return Ok(());
+            }
+            if let Some(velocity_clamping) = &mut self.options.velocity_clamping {
+                let particle_diameter = fluid_world.properties.particle_radius() * 2.0;
+                velocity_clamping.clamp_accellerations(&mut accellerations.buffer, particle_diameter, time_manager.timestep());
+            }
 
             // update timestep
             {
@@ -555,11 +646,30 @@ impl<TViscosityModel: ViscosityModel + std::marker::Sync> Solver for DFSPHSolver
                     *predicted_velocity = v + a * dt;
                 }
             }
+            self.record_pass(SimulationPass::Integration, timer);
+        }
+        if self
+            .options
+            .watchdog_check(|watchdog| watchdog.check_velocities(SimulationPass::Integration, time, predicted_velocities))
+        {
+            return Ok(());
         }
         let dt = time_manager.timestep();
 
         // density correction loop
-        self.correct_density_error(dt, fluid_world, predicted_velocities);
+        let timer = Instant::now();
+        self.correct_density_error(time, dt, fluid_world, predicted_velocities)?;
+        let timer = self.record_pass(SimulationPass::Pressure, timer);
+        if self
+            .options
+            .watchdog_check(|watchdog| watchdog.check_velocities(SimulationPass::Pressure, time, predicted_velocities))
+        {
+            return Ok(());
+        }
+        // advection with clamped velocities moves no particle further than the cfl factor allows
+        if let Some(velocity_clamping) = &mut self.options.velocity_clamping {
+            velocity_clamping.clamp_velocities(predicted_velocities, fluid_world.properties.particle_radius() * 2.0, dt);
+        }
 
         // advect particles
         {
@@ -575,19 +685,177 @@ impl<TViscosityModel: ViscosityModel + std::marker::Sync> Solver for DFSPHSolver
                 });
             time_manager.update_time();
         }
-        // only attribute other than position that we need going forward is predicted velocities!
-        fluid_world.update_neighborhood_datastructure(vec![predicted_velocities], Vec::new());
+        let timer = self.record_pass(SimulationPass::Integration, timer);
+        let positions = &fluid_world.particles.positions;
+        if self
+            .options
+            .watchdog_check(|watchdog| watchdog.check_positions(SimulationPass::Integration, time, positions))
+        {
+            return Ok(());
+        }
+        // only attributes other than position that we need going forward are predicted velocities, what was applied against boundaries
+        // and the warm start values for the next solves
+        fluid_world.update_neighborhood_datastructure(
+            vec![predicted_velocities],
+            vec![&mut self.boundary_stiffness, &mut self.warmstart_kappa, &mut self.warmstart_stiffness],
+        );
+        let timer = self.record_pass(SimulationPass::Neighborhood, timer);
 
         // todo: fuse density & alpha factor computation?
         // recompute densities
         fluid_world.update_densities(self.kernel);
+        fluid_world.update_free_surface();
         // recompute alpha factors
         Self::compute_alpha_factors(&mut self.alpha_values, fluid_world, self.kernel);
+        let timer = self.record_pass(SimulationPass::Density, timer);
+        let densities = &fluid_world.particles.densities;
+        if self
+            .options
+            .watchdog_check(|watchdog| watchdog.check_densities(SimulationPass::Density, time, densities))
+        {
+            return Ok(());
+        }
 
         // divergence error loop
-        self.correct_divergence_error(dt, fluid_world, predicted_velocities);
+        self.correct_divergence_error(time, dt, fluid_world, predicted_velocities)?;
+        self.record_pass(SimulationPass::Pressure, timer);
+        if self
+            .options
+            .watchdog_check(|watchdog| watchdog.check_velocities(SimulationPass::Pressure, time, predicted_velocities))
+        {
+            return Ok(());
+        }
+
+        // reaction to all pressure impulses against boundaries in this step
+        {
+            microprofile::scope!("DFSPHSolver", "boundary forces");
+            let boundary_mass = fluid_world.boundary_particle_mass();
+            let boundary_stiffness = &self.boundary_stiffness;
+            let kernel = &self.kernel;
+            fluid_world.update_boundary_forces(|particles, i, j| {
+                let gradient = kernel.gradient_from_positions(particles.positions[i as usize], particles.boundary_particles[j as usize]);
+                -boundary_stiffness[i as usize] * boundary_mass * gradient / dt
+            });
+        }
 
         // update velocities
         std::mem::swap(&mut fluid_world.particles.velocities, predicted_velocities);
+
+        if let Some(xsph_smoothing) = &self.options.xsph_smoothing {
+            let timer = Instant::now();
+            xsph_smoothing.apply(fluid_world);
+            self.record_pass(SimulationPass::Viscosity, timer);
+        }
+        Ok(())
+    }
+}
+
+impl<TViscosityModel: ViscosityModel + Send + std::marker::Sync> Solver for DFSPHSolver<TViscosityModel> {
+    fn initialize(&mut self, fluid_world: &FluidParticleWorld) {
+        self.kernel = smoothing_kernel::CubicSpline::new(fluid_world.properties.smoothing_length());
+        self.clear_cached_state();
+    }
+
+    fn clear_cached_state(&mut self) {
+        self.alpha_values.clear();
+        self.warmstart_stiffness.clear();
+        self.warmstart_kappa.clear();
+        self.boundary_stiffness.clear();
+        self.num_divergence_correction_iterations = 0;
+        self.num_density_correction_iterations = 0;
+        self.residuals = Default::default();
+    }
+
+    fn retain_particle_data(&mut self, keep: &[bool]) {
+        // The neighborhood datastructure still refers to the removed particles.
+        // Dropping all alpha values makes the next step start with a neighborhood update ("warmup").
+        self.alpha_values.clear();
+        retain_particle_attribute(&mut self.warmstart_stiffness, keep);
+        retain_particle_attribute(&mut self.warmstart_kappa, keep);
+        retain_particle_attribute(&mut self.boundary_stiffness, keep);
+    }
+
+    fn simulation_step(&mut self, fluid_world: &mut FluidParticleWorld, time_manager: &mut TimeManager) -> Result<(), SphError> {
+        let thread_pool = self.options.thread_pool().clone();
+        thread_pool.install(|| self.step(fluid_world, time_manager))
+    }
+
+    fn last_step_timings(&self) -> &StepTimings {
+        &self.timings
+    }
+
+    fn last_step_iterations(&self) -> Option<SolverIterations> {
+        Some(SolverIterations {
+            density: self.num_density_correction_iterations,
+            divergence: self.num_divergence_correction_iterations,
+        })
+    }
+
+    fn last_step_residuals(&self) -> Option<&SolverResiduals> {
+        Some(&self.residuals)
+    }
+
+    fn options(&self) -> &SolverOptions {
+        &self.options
+    }
+
+    fn options_mut(&mut self) -> &mut SolverOptions {
+        &mut self.options
+    }
+
+    fn parameters(&self) -> Vec<SolverParameter> {
+        let mut parameters = vec![
+            SolverParameter {
+                name: "max_density_error",
+                unit: "1/s",
+                value: self.max_avg_density_error,
+                min: 1.0e-6,
+                max: 1.0e-2,
+                logarithmic: true,
+            },
+            SolverParameter {
+                name: "max_density_iterations",
+                unit: "",
+                value: self.max_num_density_correction_iterations as Real,
+                min: 1.0,
+                max: 1000.0,
+                logarithmic: true,
+            },
+            SolverParameter {
+                name: "max_divergence_error",
+                unit: "1/s",
+                value: self.max_divergence_error,
+                min: 1.0e-5,
+                max: 1.0e-1,
+                logarithmic: true,
+            },
+            SolverParameter {
+                name: "max_divergence_iterations",
+                unit: "",
+                value: self.max_num_divergence_correction_iterations as Real,
+                min: 1.0,
+                max: 1000.0,
+                logarithmic: true,
+            },
+        ];
+        parameters.extend(self.viscosity_model.parameters());
+        parameters.extend(self.options.parameters());
+        parameters
+    }
+
+    fn set_parameter(&mut self, name: &str, value: Real) -> bool {
+        match name {
+            "max_density_error" => self.max_avg_density_error = value,
+            "max_density_iterations" => self.max_num_density_correction_iterations = value.round() as usize,
+            "max_divergence_error" => self.max_divergence_error = value,
+            "max_divergence_iterations" => self.max_num_divergence_correction_iterations = value.round() as usize,
+            _ => {
+                return self
+                    .options
+                    .set_parameter(name, value)
+                    .unwrap_or_else(|| self.viscosity_model.set_parameter(name, value))
+            }
+        }
+        true
     }
 }
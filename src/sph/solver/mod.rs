@@ -1,18 +1,652 @@
 pub use dfsph::DFSPHSolver;
-pub use wscsph::WCSPHSolver;
+pub use wscsph::{ArtificialPressure, DensityEvolution, WCSPHBoundaryHandling, WCSPHSolver};
 
 mod dfsph;
 mod wscsph;
 
 // ------------------------------------------------------
 
+use super::error::SphError;
 use super::fluidparticleworld::FluidParticleWorld;
+use super::neighborhood_search::ParticleIndex;
+use super::steptimings::StepTimings;
+use super::threading::{SolverThreadPool, Threading};
 use super::timemanager::TimeManager;
+use super::velocityclamping::VelocityClamping;
+use super::viscositymodel::XSPHSmoothing;
+use super::watchdog::Watchdog;
+use crate::units::Real;
+use rayon::prelude::*;
 
-pub trait Solver {
-    // todo: this is not elegant, should be done automatically
-    fn clear_cached_data(&mut self);
+// Number of iterations the pressure solve of the last simulation step needed.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SolverIterations {
+    pub density: usize,    // iterations for correcting density error
+    pub divergence: usize, // iterations for correcting velocity divergence, 0 if not applicable
+}
+
+// Residual of the pressure solve after every iteration of the last simulation step, with the targets it was iterated to.
+// Residuals are in the solver's own error measure, for DFSPH the average relative density deviation per step.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SolverResiduals {
+    pub density: Vec<Real>,
+    pub divergence: Vec<Real>, // empty if not applicable
+    pub density_target: Real,
+    pub divergence_target: Real,
+}
+
+impl SolverResiduals {
+    // Describes which solve stopped at the iteration limit before reaching its target, None if all converged.
+    pub fn warning(&self) -> Option<String> {
+        let not_converged = |name: &str, residuals: &[Real], target: Real| match residuals.last() {
+            Some(&residual) if residual >= target => Some(format!(
+                "{} solve did not converge within {} iterations, residual {:.2e} (target {:.2e})",
+                name,
+                residuals.len(),
+                residual,
+                target
+            )),
+            _ => None,
+        };
+        not_converged("Density", &self.density, self.density_target).or_else(|| not_converged("Divergence", &self.divergence, self.divergence_target))
+    }
+}
+
+// Fails with SphError::NonFinite if any of the values is NaN or infinite.
+fn check_finite<T: Sync>(what: &'static str, time: Real, values: &[T], is_finite: impl Fn(&T) -> bool + Sync + Send) -> Result<(), SphError> {
+    if values.par_iter().all(is_finite) {
+        Ok(())
+    } else {
+        Err(SphError::NonFinite { what, time })
+    }
+}
+
+// Named tunable of a solver or viscosity model, see Solver::parameters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SolverParameter {
+    pub name: &'static str, // unique per solver, e.g. for scene files
+    pub unit: &'static str, // empty for dimensionless values
+    pub value: Real,
+    // Sensible range, e.g. for gui sliders. Values outside of it are still accepted.
+    pub min: Real,
+    pub max: Real,
+    pub logarithmic: bool, // whether the range spans several orders of magnitude
+}
+
+// Optional behavior that all solvers share, embedded in every solver and accessed via Solver::options.
+// The option accessors of Solver are implemented on top of it, so a new option only needs to be added here.
+#[derive(Default)]
+pub struct SolverOptions {
+    // see Solver::watchdog
+    pub watchdog: Option<Watchdog>,
+    // see Solver::xsph_smoothing
+    pub xsph_smoothing: Option<XSPHSmoothing>,
+    // see Solver::velocity_clamping
+    pub velocity_clamping: Option<VelocityClamping>,
+    thread_pool: SolverThreadPool, // see Solver::threading
+}
+
+impl SolverOptions {
+    pub fn thread_pool(&self) -> &SolverThreadPool {
+        &self.thread_pool
+    }
+
+    pub fn threading(&self) -> Threading {
+        self.thread_pool.threading()
+    }
+
+    // Fails if the threads can't be created, keeping the previous threading.
+    pub fn set_threading(&mut self, threading: Threading) -> Result<(), rayon::ThreadPoolBuildError> {
+        self.thread_pool = SolverThreadPool::new(threading)?;
+        Ok(())
+    }
+
+    // Runs a check if there is a watchdog. Returns true if the simulation step should be aborted.
+    pub fn watchdog_check(&mut self, check: impl FnOnce(&mut Watchdog) -> bool) -> bool {
+        match &mut self.watchdog {
+            Some(watchdog) => check(watchdog),
+            None => false,
+        }
+    }
+
+    // Tunables of the enabled options, for Solver::parameters.
+    pub fn parameters(&self) -> Vec<SolverParameter> {
+        let mut parameters = Vec::new();
+        if let Some(xsph_smoothing) = &self.xsph_smoothing {
+            parameters.push(SolverParameter {
+                name: "xsph_smoothing_epsilon",
+                unit: "",
+                value: xsph_smoothing.epsilon,
+                min: 0.0,
+                max: 0.5,
+                logarithmic: false,
+            });
+        }
+        if let Some(velocity_clamping) = &self.velocity_clamping {
+            parameters.push(SolverParameter {
+                name: "clamping_cfl_factor",
+                unit: "",
+                value: velocity_clamping.cfl_factor,
+                min: 0.1,
+                max: 4.0,
+                logarithmic: false,
+            });
+        }
+        parameters
+    }
+
+    // Changes a tunable of an option, for Solver::set_parameter.
+    // None if no option has a tunable with that name, false if the option it belongs to is disabled.
+    pub fn set_parameter(&mut self, name: &str, value: Real) -> Option<bool> {
+        match name {
+            "xsph_smoothing_epsilon" => Some(match &mut self.xsph_smoothing {
+                Some(xsph_smoothing) => {
+                    xsph_smoothing.epsilon = value;
+                    true
+                }
+                None => false,
+            }),
+            "clamping_cfl_factor" => Some(match &mut self.velocity_clamping {
+                Some(velocity_clamping) => {
+                    velocity_clamping.cfl_factor = value;
+                    true
+                }
+                None => false,
+            }),
+            _ => None,
+        }
+    }
+}
+
+// Send, so that solvers can be moved to a SimulationThread.
+pub trait Solver: Send {
+    // Adapts the solver to a fluid world before simulating it, e.g. to its smoothing length and fluid density.
+    // Drops all cached state, so it is fine to call this for a world that was simulated with another solver before.
+    fn initialize(&mut self, fluid_world: &FluidParticleWorld);
+
+    // Drops all state that was carried over from previous simulation steps, e.g. after the simulation was reset.
+    fn clear_cached_state(&mut self);
+
+    // Drops per particle data of fluid particles that were removed via FluidParticleWorld::retain_fluid_particles.
+    fn retain_particle_data(&mut self, keep: &[bool]);
 
     // performs a single simulation step.
-    fn simulation_step(&mut self, fluid_world: &mut FluidParticleWorld, time_manager: &mut TimeManager);
+    // Fails if the particle arrays of the fluid world are inconsistent or if the solver ran into non-finite values, which would
+    // otherwise propagate through all particles. Particles are left mid step then, as with an alarm of the watchdog.
+    fn simulation_step(&mut self, fluid_world: &mut FluidParticleWorld, time_manager: &mut TimeManager) -> Result<(), SphError>;
+
+    // Time spent in the individual passes of the last simulation step.
+    fn last_step_timings(&self) -> &StepTimings;
+
+    // Pressure of a fluid particle as of the last simulation step.
+    // None if the solver has no notion of per-particle pressure.
+    fn particle_pressure(&self, _fluid_world: &FluidParticleWorld, _particle: ParticleIndex) -> Option<Real> {
+        None
+    }
+
+    // Iteration counts of the last simulation step.
+    // None for non-iterative solvers.
+    fn last_step_iterations(&self) -> Option<SolverIterations> {
+        None
+    }
+
+    // Residual histories of the last simulation step, for spotting convergence problems.
+    // None for non-iterative solvers.
+    fn last_step_residuals(&self) -> Option<&SolverResiduals> {
+        None
+    }
+
+    // Largest timestep the solver's stiffness allows for a fluid world, regardless of flow velocities.
+    // None for solvers that are only limited by the CFL condition.
+    fn max_stable_timestep(&self, _fluid_world: &FluidParticleWorld) -> Option<Real> {
+        None
+    }
+
+    // Options shared by all solvers, see SolverOptions.
+    fn options(&self) -> &SolverOptions;
+    fn options_mut(&mut self) -> &mut SolverOptions;
+
+    // Optional watchdog checking particle data after the passes of every step, disabled by default.
+    // Once it raised an alarm, simulation_step does nothing until the alarm is reset.
+    fn watchdog(&self) -> Option<&Watchdog> {
+        self.options().watchdog.as_ref()
+    }
+    fn watchdog_mut(&mut self) -> Option<&mut Watchdog> {
+        self.options_mut().watchdog.as_mut()
+    }
+    fn set_watchdog(&mut self, watchdog: Option<Watchdog>) {
+        self.options_mut().watchdog = watchdog;
+    }
+
+    // Threads the passes of simulation_step are parallelized on, all cores by default.
+    fn threading(&self) -> Threading {
+        self.options().threading()
+    }
+    // Fails if the threads can't be created, keeping the previous threading.
+    fn set_threading(&mut self, threading: Threading) -> Result<(), rayon::ThreadPoolBuildError> {
+        self.options_mut().set_threading(threading)
+    }
+
+    // Optional XSPH velocity smoothing at the end of every step, on top of the viscosity model. Disabled by default.
+    // While enabled, its epsilon is listed among the parameters as "xsph_smoothing_epsilon".
+    fn xsph_smoothing(&self) -> Option<&XSPHSmoothing> {
+        self.options().xsph_smoothing.as_ref()
+    }
+    fn set_xsph_smoothing(&mut self, xsph_smoothing: Option<XSPHSmoothing>) {
+        self.options_mut().xsph_smoothing = xsph_smoothing;
+    }
+
+    // Optional clamping of particle velocities and accellerations to CFL limits, disabled by default.
+    // While enabled, its cfl factor is listed among the parameters as "clamping_cfl_factor".
+    fn velocity_clamping(&self) -> Option<&VelocityClamping> {
+        self.options().velocity_clamping.as_ref()
+    }
+    fn set_velocity_clamping(&mut self, velocity_clamping: Option<VelocityClamping>) {
+        self.options_mut().velocity_clamping = velocity_clamping;
+    }
+
+    // All tunables of the solver including those of its viscosity model, with their current values.
+    fn parameters(&self) -> Vec<SolverParameter>;
+
+    // Changes a tunable by name, see parameters. Returns false if the solver has no tunable with that name.
+    // Integer tunables like iteration counts are rounded, switches are parameters from 0 (off) to 1 (on).
+    fn set_parameter(&mut self, name: &str, value: Real) -> bool;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sph::{
+        ArtificialPressure, DensityEvolution, PhysicalViscosityModel, RungeKutta2, SymplecticEuler, TimeManagerConfiguration, XSPHSmoothing,
+        XSPHViscosityModel,
+    };
+    use crate::units::{Density, NumberDensity, Point, Rect, Vector};
+    use cgmath::prelude::*;
+    use rand::prelude::*;
+
+    #[test]
+    fn parameters_round_trip() {
+        let fluid_world = FluidParticleWorld::new(2.0, NumberDensity(1000.0), Density(100.0));
+        let smoothing_length = fluid_world.properties.smoothing_length();
+        let solvers: Vec<Box<dyn Solver>> = vec![
+            Box::new(WCSPHSolver::new(XSPHViscosityModel::new(smoothing_length), &fluid_world.properties)),
+            Box::new(DFSPHSolver::new(PhysicalViscosityModel::new(smoothing_length), smoothing_length)),
+        ];
+        for mut solver in solvers {
+            solver.initialize(&fluid_world);
+            let parameters = solver.parameters();
+            // viscosity model's tunables come along
+            assert!(parameters.iter().any(|p| p.name == "xsph_epsilon" || p.name == "viscosity"));
+            for parameter in parameters.iter() {
+                assert!(parameter.min <= parameter.max);
+                assert!(!parameter.logarithmic || parameter.min > 0.0);
+                let value = (parameter.min + parameter.max) * 0.5;
+                assert!(solver.set_parameter(parameter.name, value));
+                let changed = solver.parameters().into_iter().find(|p| p.name == parameter.name).unwrap();
+                assert_lt!((changed.value - value).abs(), 0.5 + value * 1.0e-6);
+                // integer tunables are rounded
+            }
+            assert!(!solver.set_parameter("no_such_parameter", 1.0));
+        }
+    }
+
+    #[test]
+    fn serial_steps_match_parallel_steps() {
+        let create_world = || {
+            let mut fluid_world = FluidParticleWorld::new(2.0, NumberDensity(5000.0), Density(100.0));
+            fluid_world.add_fluid_rect(&Rect::new(0.0, 0.0, 0.2, 0.2), 0.1);
+            fluid_world
+        };
+        let fluid_world = create_world();
+        let smoothing_length = fluid_world.properties.smoothing_length();
+        let create_solvers = || -> Vec<Box<dyn Solver>> {
+            vec![
+                Box::new(WCSPHSolver::new(XSPHViscosityModel::new(smoothing_length), &fluid_world.properties)),
+                Box::new(DFSPHSolver::new(XSPHViscosityModel::new(smoothing_length), smoothing_length)),
+            ]
+        };
+        for (mut parallel_solver, mut serial_solver) in create_solvers().into_iter().zip(create_solvers()) {
+            assert_eq!(parallel_solver.threading(), Threading::AllCores);
+            serial_solver.set_threading(Threading::Serial).unwrap();
+            assert_eq!(serial_solver.threading(), Threading::Serial);
+
+            let mut positions = Vec::new();
+            for solver in [&mut parallel_solver, &mut serial_solver].iter_mut() {
+                let mut fluid_world = create_world();
+                let mut time_manager = TimeManager::new(TimeManagerConfiguration::FixedTimeStep(0.001));
+                solver.initialize(&fluid_world);
+                for _ in 0..10 {
+                    solver.simulation_step(&mut fluid_world, &mut time_manager).unwrap();
+                }
+                positions.push(fluid_world.particles.positions);
+            }
+            // summation order of parallel reductions may differ
+            for (a, b) in positions[0].iter().zip(positions[1].iter()) {
+                assert_lt!(a.distance(*b), 1.0e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn xsph_smoothing_evens_out_velocities() {
+        let create_world = || {
+            let mut fluid_world = FluidParticleWorld::new(2.0, NumberDensity(5000.0), Density(100.0));
+            fluid_world.add_fluid_rect(&Rect::new(0.0, 0.0, 0.2, 0.2), 0.0);
+            fluid_world.set_initial_velocity(&Rect::new(0.0, 0.0, 0.2, 0.2), |p| {
+                Vector::new((p.x * 300.0).sin(), (p.y * 300.0).cos()) * 0.1
+            });
+            fluid_world
+        };
+        let fluid_world = create_world();
+        let smoothing_length = fluid_world.properties.smoothing_length();
+        let create_solvers = || -> Vec<Box<dyn Solver>> {
+            let inviscid = || {
+                let mut viscosity_model = PhysicalViscosityModel::new(smoothing_length);
+                viscosity_model.fluid_viscosity = 0.0;
+                viscosity_model
+            };
+            vec![
+                Box::new(WCSPHSolver::new(inviscid(), &fluid_world.properties)),
+                Box::new(DFSPHSolver::new(inviscid(), smoothing_length)),
+            ]
+        };
+        for (mut solver, mut smoothing_solver) in create_solvers().into_iter().zip(create_solvers()) {
+            assert!(solver.xsph_smoothing().is_none());
+            assert!(!solver.parameters().iter().any(|p| p.name == "xsph_smoothing_epsilon"));
+            assert!(!solver.set_parameter("xsph_smoothing_epsilon", 0.1));
+            smoothing_solver.set_xsph_smoothing(Some(XSPHSmoothing::new(smoothing_length)));
+            assert!(smoothing_solver.set_parameter("xsph_smoothing_epsilon", 0.5));
+            assert_eq!(smoothing_solver.xsph_smoothing().unwrap().epsilon, 0.5);
+
+            let mut velocity_deviations = Vec::new();
+            for solver in [&mut solver, &mut smoothing_solver].iter_mut() {
+                let mut fluid_world = create_world();
+                let mut time_manager = TimeManager::new(TimeManagerConfiguration::FixedTimeStep(0.0001));
+                solver.initialize(&fluid_world);
+                for _ in 0..5 {
+                    solver.simulation_step(&mut fluid_world, &mut time_manager).unwrap();
+                }
+                let velocities = &fluid_world.particles.velocities;
+                let average = velocities.iter().sum::<Vector>() / velocities.len() as Real;
+                velocity_deviations.push(velocities.iter().map(|v| (v - average).magnitude2()).sum::<Real>());
+            }
+            assert_lt!(velocity_deviations[1], velocity_deviations[0] * 0.5);
+        }
+    }
+
+    #[test]
+    fn wcsph_pressure_clamping_and_background_pressure() {
+        // a lone particle is far below rest density
+        let mut fluid_world = FluidParticleWorld::new(2.0, NumberDensity(5000.0), Density(100.0));
+        fluid_world.add_fluid_particle(Point::new(0.0, 0.0), Vector::zero());
+        let smoothing_length = fluid_world.properties.smoothing_length();
+        let mut solver = WCSPHSolver::new(XSPHViscosityModel::new(smoothing_length), &fluid_world.properties);
+        let mut time_manager = TimeManager::new(TimeManagerConfiguration::FixedTimeStep(0.0001));
+        let mut pressure_after_step = |solver: &mut WCSPHSolver<XSPHViscosityModel>| {
+            solver.simulation_step(&mut fluid_world, &mut time_manager).unwrap();
+            solver.particle_pressure(&fluid_world, 0).unwrap()
+        };
+        assert_eq!(pressure_after_step(&mut solver), 0.0);
+
+        assert!(solver.set_parameter("clamp_negative_pressure", 0.0));
+        assert!(!solver.negative_pressure_clamping());
+        assert_lt!(pressure_after_step(&mut solver), 0.0);
+
+        // B = ρ0 c² / γ with the default speed of sound of 10m/s
+        assert!(solver.set_parameter("clamp_negative_pressure", 1.0));
+        assert!(solver.set_parameter("background_pressure_factor", 0.05));
+        assert_lt!((pressure_after_step(&mut solver) - 0.05 * 100.0 * 10.0 * 10.0 / 7.0).abs(), 1.0e-2);
+    }
+
+    #[test]
+    fn wcsph_reports_non_finite_values() {
+        let mut fluid_world = FluidParticleWorld::new(2.0, NumberDensity(5000.0), Density(100.0));
+        fluid_world.add_fluid_rect(&Rect::new(0.0, 0.0, 0.2, 0.2), 0.0);
+        let smoothing_length = fluid_world.properties.smoothing_length();
+        let mut solver = WCSPHSolver::new(XSPHViscosityModel::new(smoothing_length), &fluid_world.properties);
+        let mut time_manager = TimeManager::new(TimeManagerConfiguration::FixedTimeStep(0.0001));
+        solver.simulation_step(&mut fluid_world, &mut time_manager).unwrap();
+
+        fluid_world.particles.velocities[10].x = Real::NAN;
+        match solver.simulation_step(&mut fluid_world, &mut time_manager) {
+            Err(SphError::NonFinite { .. }) => {}
+            result => panic!("expected non-finite error, got {:?}", result),
+        }
+    }
+
+    // Smallest distance between any two particles.
+    fn min_particle_distance(positions: &[Point]) -> Real {
+        let mut min_distance = Real::MAX;
+        for (i, a) in positions.iter().enumerate() {
+            for b in positions[i + 1..].iter() {
+                min_distance = min_distance.min(a.distance(*b));
+            }
+        }
+        min_distance
+    }
+
+    // Square block of fluid at rest without gravity and a WCSPH solver with the given artificial pressure.
+    fn weightless_block(size: Real, artificial_pressure: Option<ArtificialPressure>) -> (FluidParticleWorld, WCSPHSolver<XSPHViscosityModel>) {
+        let mut fluid_world = FluidParticleWorld::new(2.0, NumberDensity(5000.0), Density(100.0));
+        fluid_world.set_gravity(Vector::zero());
+        fluid_world.add_fluid_rect(&Rect::new(0.0, 0.0, size, size), 0.0);
+        let smoothing_length = fluid_world.properties.smoothing_length();
+        let mut solver = WCSPHSolver::new(XSPHViscosityModel::new(smoothing_length), &fluid_world.properties);
+        solver.set_artificial_pressure(artificial_pressure);
+        (fluid_world, solver)
+    }
+
+    fn simulate_steps(solver: &mut dyn Solver, fluid_world: &mut FluidParticleWorld, num_steps: usize) {
+        let mut time_manager = TimeManager::new(TimeManagerConfiguration::FixedTimeStep(0.0001));
+        for _ in 0..num_steps {
+            solver.simulation_step(fluid_world, &mut time_manager).unwrap();
+        }
+    }
+
+    #[test]
+    fn artificial_pressure_separates_clumped_particles() {
+        let min_distance_after_steps = |artificial_pressure: Option<ArtificialPressure>| {
+            let (mut fluid_world, mut solver) = weightless_block(0.2, artificial_pressure);
+            assert_eq!(
+                solver.parameters().iter().any(|p| p.name == "artificial_pressure_strength"),
+                artificial_pressure.is_some()
+            );
+            solver.set_background_pressure_factor(0.05);
+            // move the particle closest to the block's center up to a third of the rest spacing to its right neighbor
+            let spacing = fluid_world.properties.particle_radius() * 2.0;
+            let center = Point::new(0.1, 0.1);
+            let positions = &mut fluid_world.particles.positions;
+            let i = (0..positions.len())
+                .min_by(|&a, &b| positions[a].distance2(center).partial_cmp(&positions[b].distance2(center)).unwrap())
+                .unwrap();
+            positions[i].x += spacing * 2.0 / 3.0;
+            assert_lt!(min_particle_distance(positions), spacing * 0.4);
+
+            simulate_steps(&mut solver, &mut fluid_world, 20);
+            min_particle_distance(&fluid_world.particles.positions)
+        };
+        let min_distance_corrected = min_distance_after_steps(Some(ArtificialPressure::default()));
+        let min_distance_uncorrected = min_distance_after_steps(None);
+        assert_gt!(min_distance_corrected, min_distance_uncorrected);
+    }
+
+    #[test]
+    fn artificial_pressure_counters_tensile_clumping() {
+        let min_distance_after_steps = |artificial_pressure: Option<ArtificialPressure>| {
+            let (mut fluid_world, mut solver) = weightless_block(0.3, artificial_pressure);
+            solver.set_negative_pressure_clamping(false);
+            // stretched block, all particles are below rest density and under tension
+            for position in fluid_world.particles.positions.iter_mut() {
+                *position = Point::from_vec(position.to_vec() * 1.2);
+            }
+            simulate_steps(&mut solver, &mut fluid_world, 50);
+            min_particle_distance(&fluid_world.particles.positions)
+        };
+        let min_distance_corrected = min_distance_after_steps(Some(ArtificialPressure::default()));
+        let min_distance_uncorrected = min_distance_after_steps(None);
+        assert_gt!(min_distance_corrected, min_distance_uncorrected);
+    }
+
+    #[test]
+    fn density_diffusion_smooths_densities() {
+        let density_deviation_after_steps = |density_diffusion: Real| {
+            let mut fluid_world = FluidParticleWorld::new(2.0, NumberDensity(5000.0), Density(100.0));
+            fluid_world.set_gravity(Vector::zero());
+            fluid_world.add_fluid_rect(&Rect::new(0.0, 0.0, 0.3, 0.3), 0.5);
+            let smoothing_length = fluid_world.properties.smoothing_length();
+            let mut solver = WCSPHSolver::new(XSPHViscosityModel::new(smoothing_length), &fluid_world.properties);
+            assert!(solver.set_parameter("density_diffusion", density_diffusion));
+            let mut time_manager = TimeManager::new(TimeManagerConfiguration::FixedTimeStep(0.0002));
+            for _ in 0..5 {
+                solver.simulation_step(&mut fluid_world, &mut time_manager).unwrap();
+            }
+            // interior particles only, densities drop off towards the surface
+            let particles = &fluid_world.particles;
+            let interior_densities: Vec<Real> = (0..particles.positions.len())
+                .filter(|i| (particles.positions[*i] - Point::new(0.15, 0.15)).magnitude() < 0.08)
+                .map(|i| particles.densities[i])
+                .collect();
+            let average = interior_densities.iter().sum::<Real>() / interior_densities.len() as Real;
+            interior_densities.iter().map(|density| (density - average).abs()).sum::<Real>() / interior_densities.len() as Real
+        };
+        let deviation_diffused = density_deviation_after_steps(0.5);
+        let deviation = density_deviation_after_steps(0.0);
+        assert_lt!(deviation_diffused, deviation * 0.75);
+    }
+
+    #[test]
+    fn continuity_densities_follow_summation() {
+        let interior_densities_after_steps = |density_evolution: DensityEvolution| {
+            let mut fluid_world = FluidParticleWorld::new(2.0, NumberDensity(5000.0), Density(100.0));
+            fluid_world.add_fluid_rect(&Rect::new(0.0, 0.0, 0.3, 0.2), 0.1);
+            fluid_world.add_boundary_thick_line(Point::new(-0.3, -0.015), Point::new(0.6, -0.015), 2);
+            let smoothing_length = fluid_world.properties.smoothing_length();
+            let mut solver = WCSPHSolver::new(XSPHViscosityModel::new(smoothing_length), &fluid_world.properties);
+            solver.set_density_evolution(density_evolution);
+            let mut time_manager = TimeManager::new(TimeManagerConfiguration::FixedTimeStep(0.0005));
+            for _ in 0..100 {
+                solver.simulation_step(&mut fluid_world, &mut time_manager).unwrap();
+            }
+            // particles added in between start at rest density
+            fluid_world.add_fluid_particle(Point::new(0.15, 0.5), Vector::zero());
+            solver.simulation_step(&mut fluid_world, &mut time_manager).unwrap();
+
+            let particles = &fluid_world.particles;
+            let rest_density = fluid_world.properties.fluid_density();
+            let added = (0..particles.positions.len()).find(|i| particles.positions[*i].y > 0.4).unwrap();
+            assert_lt!((particles.densities[added] / rest_density - 1.0).abs(), 1.0e-3);
+            let interior_densities: Vec<Real> = (0..particles.positions.len())
+                .filter(|i| (0.1..0.2).contains(&particles.positions[*i].x) && (0.03..0.08).contains(&particles.positions[*i].y))
+                .map(|i| particles.densities[i] / rest_density)
+                .collect();
+            interior_densities.iter().sum::<Real>() / interior_densities.len() as Real
+        };
+        let summation = interior_densities_after_steps(DensityEvolution::Summation);
+        let continuity = interior_densities_after_steps(DensityEvolution::Continuity);
+        assert_lt!((continuity / summation - 1.0).abs(), 0.02);
+    }
+
+    // Asserts that the total momentum doesn't change during one step, up to rounding of the particles' individual changes.
+    // Without gravity and boundaries only pair forces act, which cancel out if the force on i is minus the force on j.
+    fn assert_step_conserves_momentum(solver: &mut dyn Solver, fluid_world: &mut FluidParticleWorld) {
+        // particles may be reordered during the step, tags stay with them
+        let num_particles = fluid_world.particles.positions.len();
+        fluid_world.particles.tags = (0..num_particles as u32).collect();
+        let momentum = |fluid_world: &FluidParticleWorld, i: usize| fluid_world.particles.velocities[i] * fluid_world.particles.masses[i];
+        let mut momenta_before = vec![Vector::zero(); num_particles];
+        for (i, tag) in fluid_world.particles.tags.iter().enumerate() {
+            momenta_before[*tag as usize] = momentum(fluid_world, i);
+        }
+
+        let mut time_manager = TimeManager::new(TimeManagerConfiguration::FixedTimeStep(0.0001));
+        solver.initialize(fluid_world);
+        solver.simulation_step(fluid_world, &mut time_manager).unwrap();
+
+        let mut total_change = Vector::zero();
+        let mut individual_changes = 0.0;
+        for (i, tag) in fluid_world.particles.tags.iter().enumerate() {
+            let change = momentum(fluid_world, i) - momenta_before[*tag as usize];
+            total_change += change;
+            individual_changes += change.magnitude();
+        }
+        let momenta = momenta_before.iter().map(|p| p.magnitude()).sum::<Real>();
+        assert_lt!(total_change.magnitude(), individual_changes * 1.0e-4 + momenta * 1.0e-6);
+    }
+
+    fn all_solvers(fluid_world: &FluidParticleWorld) -> Vec<Box<dyn Solver>> {
+        let smoothing_length = fluid_world.properties.smoothing_length();
+        let mut unclamped = WCSPHSolver::new(PhysicalViscosityModel::new(smoothing_length), &fluid_world.properties);
+        unclamped.set_negative_pressure_clamping(false);
+        let mut artificial_pressure = WCSPHSolver::new(PhysicalViscosityModel::new(smoothing_length), &fluid_world.properties);
+        artificial_pressure.set_negative_pressure_clamping(false);
+        artificial_pressure.set_artificial_pressure(Some(ArtificialPressure::default()));
+        let mut continuity = WCSPHSolver::new(PhysicalViscosityModel::new(smoothing_length), &fluid_world.properties);
+        continuity.set_density_evolution(DensityEvolution::Continuity);
+        let mut symplectic_euler = WCSPHSolver::new(PhysicalViscosityModel::new(smoothing_length), &fluid_world.properties);
+        symplectic_euler.set_integrator(Box::new(SymplecticEuler));
+        let mut runge_kutta = WCSPHSolver::new(PhysicalViscosityModel::new(smoothing_length), &fluid_world.properties);
+        runge_kutta.set_integrator(Box::new(RungeKutta2::default()));
+        vec![
+            Box::new(WCSPHSolver::new(PhysicalViscosityModel::new(smoothing_length), &fluid_world.properties)),
+            Box::new(unclamped),
+            Box::new(artificial_pressure),
+            Box::new(continuity),
+            Box::new(symplectic_euler),
+            Box::new(runge_kutta),
+            Box::new(DFSPHSolver::new(PhysicalViscosityModel::new(smoothing_length), smoothing_length)),
+        ]
+    }
+
+    #[test]
+    fn pair_forces_are_opposite() {
+        let mut rng: rand::rngs::SmallRng = rand::SeedableRng::seed_from_u64(123456789);
+        let mut random_vector = move || Vector::new(rng.gen::<Real>() - 0.5, rng.gen::<Real>() - 0.5);
+        for _ in 0..20 {
+            let particles = [
+                (Point::new(0.0, 0.0), random_vector()),
+                (Point::new(0.0, 0.0) + random_vector() * 0.02, random_vector()),
+            ];
+            let create_world = || {
+                let mut fluid_world = FluidParticleWorld::new(2.0, NumberDensity(5000.0), Density(100.0));
+                fluid_world.set_gravity(Vector::zero());
+                for (position, velocity) in particles.iter() {
+                    fluid_world.add_fluid_particle(*position, *velocity);
+                }
+                fluid_world
+            };
+
+            for mut solver in all_solvers(&create_world()) {
+                let mut fluid_world = create_world();
+                assert_step_conserves_momentum(solver.as_mut(), &mut fluid_world);
+            }
+        }
+    }
+
+    #[test]
+    fn compressed_clusters_conserve_momentum() {
+        let mut rng: rand::rngs::SmallRng = rand::SeedableRng::seed_from_u64(987654321);
+        let mut random_vector = move || Vector::new(rng.gen::<Real>() - 0.5, rng.gen::<Real>() - 0.5);
+        for _ in 0..5 {
+            // 50 particles within a 4cm square, packed densely enough for pressure to push them apart
+            let particles: Vec<(Point, Vector)> = (0..50)
+                .map(|_| (Point::new(0.0, 0.0) + random_vector() * 0.04, random_vector()))
+                .collect();
+            let create_world = || {
+                let mut fluid_world = FluidParticleWorld::new(2.0, NumberDensity(5000.0), Density(100.0));
+                fluid_world.set_gravity(Vector::zero());
+                for (position, velocity) in particles.iter() {
+                    fluid_world.add_fluid_particle(*position, *velocity);
+                }
+                fluid_world
+            };
+
+            for mut solver in all_solvers(&create_world()) {
+                for &threading in [Threading::Serial, Threading::AllCores].iter() {
+                    solver.set_threading(threading).unwrap();
+                    let mut fluid_world = create_world();
+                    assert_step_conserves_momentum(solver.as_mut(), &mut fluid_world);
+                }
+            }
+        }
+    }
 }
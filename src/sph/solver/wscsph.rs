@@ -1,13 +1,67 @@
-use super::super::fluidparticleworld::{ConstantFluidProperties, FluidParticleWorld};
+use super::super::error::SphError;
+use super::super::fluidparticleworld::{retain_particle_attribute, ConstantFluidProperties, FluidParticleWorld};
+use super::super::forcefield::ForceField;
+use super::super::ghostparticles::{GhostParticles, WallCondition};
+use super::super::integrator::{Integrator, VelocityVerlet};
+use super::super::neighborhood_search::ParticleIndex;
 use super::super::smoothing_kernel;
 use super::super::smoothing_kernel::Kernel;
+use super::super::steptimings::{Instant, SimulationPass, StepTimings};
 use super::super::timemanager::TimeManager;
 use super::super::viscositymodel::ViscosityModel;
-use super::Solver;
+use super::{check_finite, Solver, SolverOptions, SolverParameter};
 use crate::units::*;
 use cgmath::prelude::*;
 use rayon::prelude::*;
 
+// How walls act on the fluid.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WCSPHBoundaryHandling {
+    // Radial repulsion from boundary particles, which can't hold the fluid back tangentially.
+    PenaltyForce,
+    // Fluid mirrored at the boundary geometry's surfaces, see GhostParticles.
+    // Boundary particles no longer contribute to density, their penalty force only keeps particles without pressure from penetrating walls.
+    GhostParticles(WallCondition),
+}
+
+// How WCSPHSolver gets the densities of its particles.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DensityEvolution {
+    // Summed up over the neighborhood from scratch every step.
+    // Densities drop off wherever neighbors are missing, i.e. at free surfaces and open boundaries.
+    Summation,
+    // Evolved via the continuity equation dρ_i/dt = Σ_j m_j (v_i - v_j) · ∇W_ij, starting out with summed up densities.
+    // The rate is gathered along with the forces, so there is no separate density pass. It lags a step behind,
+    // which is fine for the small timesteps of WCSPH. Particles added in between start at rest density, e.g. at inflows.
+    // Densities may drift over long simulations, δ-SPH density diffusion counters that.
+    Continuity,
+}
+
+// Artificial pressure against the tensile instability, as in "SPH without a tensile instability" (Monaghan 2000).
+// Same idea as s_corr in "Position Based Fluids" (Macklin & Müller 2013): particles closer than the reference distance
+// repel each other with a force growing with (W(r) / W(Δp))^n, which breaks up the pairs and strings particles tend to clump into.
+// Scales with the particles' pressure, so it has no effect at the free surface unless there is a background pressure.
+// The tensile instability comes from negative pressures, which only occur with negative pressure clamping disabled.
+// Otherwise only the much weaker factor for positive pressures acts.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ArtificialPressure {
+    pub strength: Real,           // ε for negative pressures, fraction of the particles' pressure term, Monaghan proposes 0.2
+    pub positive_strength: Real,  // ε for positive pressures, Monaghan proposes 0.01
+    pub exponent: i32,            // n, 4 as proposed by Monaghan
+    pub reference_distance: Real, // Δp, relative to the particle spacing
+}
+
+impl Default for ArtificialPressure {
+    fn default() -> ArtificialPressure {
+        ArtificialPressure {
+            strength: 0.2,
+            positive_strength: 0.01,
+            exponent: 4,
+            reference_distance: 1.0,
+        }
+    }
+}
+
 // Solver based on Becker & Teschner 2007 WCSPH07
 // No surface tension implemented
 // https://cg.informatik.uni-freiburg.de/publications/2007_SCA_SPH.pdf
@@ -16,15 +70,52 @@ pub struct WCSPHSolver<TViscosityModel: ViscosityModel> {
     density_kernel: smoothing_kernel::Poly6,
     pressure_kernel: smoothing_kernel::Spiky,
     boundary_force_factor: Real,
-    stiffness: Real, // denoted as B. B = density0 * speed_of_sound * speed_of_sound / γ.
+    speed_of_sound: Velocity, // determines the stiffness of the equation of state, see stiffness
+    negative_pressure_clamping: bool,
+    background_pressure_factor: Real, // relative to the stiffness
+    artificial_pressure: Option<ArtificialPressure>,
+    density_diffusion: Real, // δ of δ-SPH, 0 disables density diffusion
+    density_evolution: DensityEvolution,
+    // dρ/dt of the last step for DensityEvolution::Continuity, empty otherwise
+    density_rates: Vec<Real>,
 
-    // recomputed every frame, but need previous frame due to leap frog iteration scheme
+    // recomputed every frame, but integrators need those of the previous frame
     accellerations: Vec<Vector>,
+    integrator: Box<dyn Integrator>,
+
+    // None for WCSPHBoundaryHandling::PenaltyForce
+    ghost_particles: Option<GhostParticles>,
+
+    timings: StepTimings,
+    options: SolverOptions,
 }
 
 // γ is hardcoded to 7 as propsed in the paper
 const TAIT_EQUATION_GAMMA: i32 = 7;
 
+// Equation of State (EOS), Tait equation as in Becker & Teschner 2007 WCSPH07
+#[derive(Clone, Copy)]
+struct EquationOfState {
+    stiffness: Real,
+    fluid_density: Real,
+    clamp_negative_pressure: bool,
+    background_pressure: Real,
+}
+
+impl EquationOfState {
+    fn pressure(&self, local_density: Real) -> Real {
+        let density_ratio = local_density / self.fluid_density;
+        // The max on pressure ratio is due to pressure clamping to work around particle deficiency problem. Good explanation here:
+        // https://github.com/InteractiveComputerGraphics/SPlisHSPlasH/issues/36#issuecomment-495883932
+        let density_ratio = if self.clamp_negative_pressure {
+            density_ratio.max(1.0)
+        } else {
+            density_ratio
+        };
+        self.stiffness * (density_ratio.powi(TAIT_EQUATION_GAMMA) - 1.0) + self.background_pressure
+    }
+}
+
 impl<TViscosityModel: ViscosityModel + std::marker::Sync> WCSPHSolver<TViscosityModel> {
     pub fn new(viscosity_model: TViscosityModel, fluid_properties: &ConstantFluidProperties) -> WCSPHSolver<TViscosityModel> {
         let mut solver = WCSPHSolver {
@@ -32,48 +123,192 @@ impl<TViscosityModel: ViscosityModel + std::marker::Sync> WCSPHSolver<TViscosity
             density_kernel: smoothing_kernel::Poly6::new(fluid_properties.smoothing_length()),
             pressure_kernel: smoothing_kernel::Spiky::new(fluid_properties.smoothing_length()),
             boundary_force_factor: 1.0, // (expected accelleration * initial water depth) / (spacing ratio of boundary / normal particles). Arbitrary value right now.
-            stiffness: 0.0,             // set in set_compressibility below
+            speed_of_sound: Velocity(0.0), // set in set_compressibility below
+            negative_pressure_clamping: true,
+            background_pressure_factor: 0.0,
+            artificial_pressure: None,
+            density_diffusion: 0.0,
+            density_evolution: DensityEvolution::Summation,
+            density_rates: Vec::new(),
             accellerations: Vec::new(),
+            integrator: Box::new(VelocityVerlet),
+            ghost_particles: None,
+            timings: Default::default(),
+            options: Default::default(),
         };
         // set a good default for compressibility
-        solver.set_compressibility(fluid_properties, 0.01, 1.0);
+        solver.set_compressibility(0.01, Velocity(1.0));
         solver
     }
 
     // target_density_variation:    density variation, denoted as η in the paper. defaults to 1%==0.01
-    // expected_max_flow_speed:     expected speed of the fluid in m/s. possible estimate is sqrt(2 * gravity * falling_height)
-    pub fn set_compressibility(&mut self, fluid_properties: &ConstantFluidProperties, target_density_variation: Real, expected_max_flow_speed: Real) {
+    // expected_max_flow_speed:     expected speed of the fluid. possible estimate is sqrt(2 * gravity * falling_height)
+    pub fn set_compressibility(&mut self, target_density_variation: Real, expected_max_flow_speed: Velocity) {
         // real speed of sound of the fluid is usually higher, but this makes our timesteps way too small
-        let speed_of_sound = expected_max_flow_speed / target_density_variation.sqrt();
-        self.stiffness = fluid_properties.fluid_density() * speed_of_sound * speed_of_sound / TAIT_EQUATION_GAMMA as Real;
+        self.speed_of_sound = expected_max_flow_speed / target_density_variation.sqrt();
     }
 
-    // Equation of State (EOS)
-    fn pressure(stiffness: Real, fluid_density: Real, local_density: Real) -> Real {
-        // Tait equation as in Becker & Teschner 2007 WCSPH07
-        // The max on pressure ratio is due to pressure clamping to work around particle deficiency problem. Good explanation here:
-        // https://github.com/InteractiveComputerGraphics/SPlisHSPlasH/issues/36#issuecomment-495883932
-        stiffness * ((local_density / fluid_density).max(1.0).powi(TAIT_EQUATION_GAMMA) - 1.0)
+    // Same as set_compressibility for creating solvers in a single expression. The standard choice is a density variation of 1%,
+    // which puts the speed of sound at ten times the expected maximum flow speed, e.g. for fluid falling from a height h:
+    //   WCSPHSolver::new(viscosity_model, &properties).with_target_compressibility(0.01, Velocity((2.0 * g * h).sqrt()))
+    pub fn with_target_compressibility(mut self, target_density_variation: Real, expected_max_flow_speed: Velocity) -> Self {
+        self.set_compressibility(target_density_variation, expected_max_flow_speed);
+        self
+    }
+
+    // Speed of sound of the equation of state, as derived in set_compressibility.
+    pub fn speed_of_sound(&self) -> Velocity {
+        self.speed_of_sound
+    }
+
+    // Denoted as B. B = density0 * speed_of_sound * speed_of_sound / γ, i.e. the bulk modulus over γ.
+    fn stiffness(&self, fluid_density: Real) -> Real {
+        (Density(fluid_density).bulk_modulus(self.speed_of_sound) / TAIT_EQUATION_GAMMA as Real).0
+    }
+
+    // Whether pressures below zero, i.e. below rest density, are clamped to zero. On by default.
+    // Negative pressures pull particles together, which makes them clump at free surfaces.
+    pub fn negative_pressure_clamping(&self) -> bool {
+        self.negative_pressure_clamping
+    }
+
+    pub fn set_negative_pressure_clamping(&mut self, negative_pressure_clamping: bool) {
+        self.negative_pressure_clamping = negative_pressure_clamping;
+    }
+
+    // Pressure added to every particle, relative to the stiffness B. Zero by default.
+    // A small background pressure (a few percent) pushes particles apart where they are unevenly spaced and so counters clumping.
+    pub fn background_pressure_factor(&self) -> Real {
+        self.background_pressure_factor
+    }
+
+    pub fn set_background_pressure_factor(&mut self, background_pressure_factor: Real) {
+        self.background_pressure_factor = background_pressure_factor;
+    }
+
+    // Optional tensile instability correction in the pressure force, disabled by default.
+    pub fn artificial_pressure(&self) -> Option<ArtificialPressure> {
+        self.artificial_pressure
+    }
+
+    pub fn set_artificial_pressure(&mut self, artificial_pressure: Option<ArtificialPressure>) {
+        self.artificial_pressure = artificial_pressure;
+    }
+
+    // Coefficient δ of the δ-SPH density diffusion term (Molteni & Colagrossi 2009), 0 (disabled) by default.
+    // Diffusing densities between neighbors removes the high frequency noise of WCSPH's pressure field, 0.1 is the usual choice.
+    // With DensityEvolution::Summation densities are summed up from scratch every step, so the diffusion only smooths within a step.
+    // Evolving densities via the continuity equation, it accumulates over time.
+    pub fn density_diffusion(&self) -> Real {
+        self.density_diffusion
+    }
+
+    pub fn set_density_diffusion(&mut self, density_diffusion: Real) {
+        self.density_diffusion = density_diffusion;
     }
 
-    fn update_accellerations(&mut self, fluid_world: &FluidParticleWorld, dt: Real) {
+    pub fn density_evolution(&self) -> DensityEvolution {
+        self.density_evolution
+    }
+
+    pub fn set_density_evolution(&mut self, density_evolution: DensityEvolution) {
+        self.density_evolution = density_evolution;
+    }
+
+    fn equation_of_state(&self, fluid_density: Real) -> EquationOfState {
+        let stiffness = self.stiffness(fluid_density);
+        EquationOfState {
+            stiffness,
+            fluid_density,
+            clamp_negative_pressure: self.negative_pressure_clamping,
+            background_pressure: self.background_pressure_factor * stiffness,
+        }
+    }
+
+    pub fn integrator(&self) -> &dyn Integrator {
+        self.integrator.as_ref()
+    }
+
+    // Takes effect with the next step.
+    pub fn set_integrator(&mut self, integrator: Box<dyn Integrator>) {
+        self.integrator = integrator;
+    }
+
+    pub fn boundary_handling(&self) -> WCSPHBoundaryHandling {
+        match &self.ghost_particles {
+            Some(ghost_particles) => WCSPHBoundaryHandling::GhostParticles(ghost_particles.condition),
+            None => WCSPHBoundaryHandling::PenaltyForce,
+        }
+    }
+
+    pub fn set_boundary_handling(&mut self, boundary_handling: WCSPHBoundaryHandling) {
+        self.ghost_particles = match boundary_handling {
+            WCSPHBoundaryHandling::PenaltyForce => None,
+            WCSPHBoundaryHandling::GhostParticles(condition) => Some(GhostParticles::new(condition)),
+        };
+    }
+
+    // Adds the time since timer to a pass and returns the timer for the next pass.
+    fn record_pass(&mut self, pass: SimulationPass, timer: Instant) -> Instant {
+        let now = Instant::now();
+        self.timings.add(pass, now - timer);
+        now
+    }
+
+    fn update_accellerations(&mut self, fluid_world: &FluidParticleWorld, dt: Real, time: Real) {
         microprofile::scope!("WCSPHSolver", "update_accellerations");
-        let mass = fluid_world.properties.particle_mass();
 
         // pressure & viscosity forces
-        // According to https://www8.cs.umu.se/kurser/TDBD24/VT06/lectures/sphsurvivalkit.pdf
-        // the "good way" to do symmetric forces in SPH is -m (pi + pj) / (2 * rhoj * rhoi)
+        // Symmetric pressure force -mj (pi / rhoi² + pj / rhoj²) as in Monaghan 2005 "Smoothed Particle Hydrodynamics" (eq. 3.15)
+        // Note that the also common -m (pi + pj) / (2 * rhoj * rhoi) (e.g. https://www8.cs.umu.se/kurser/TDBD24/VT06/lectures/sphsurvivalkit.pdf)
+        // only yields half the pressure gradient, making resting fluid settle at twice the hydrostatic pressure. (see tests/hydrostatic.rs)
 
         let fluid_density = fluid_world.properties.fluid_density();
         let particles = &fluid_world.particles;
         let pressure_kernel = self.pressure_kernel;
-        let boundary_force_factor = self.boundary_force_factor;
+        // denser boundaries push with more particles
+        let boundary_force_factor = self.boundary_force_factor / fluid_world.boundary_sampling_factor();
         let viscosity_model = &self.viscosity_model;
-        let gravity = fluid_world.gravity;
-        let stiffness = self.stiffness;
+        let equation_of_state = self.equation_of_state(fluid_density);
+        let force_fields = &fluid_world.force_fields;
+        let ghost_particles = &self.ghost_particles;
+        let smoothing_length = fluid_world.properties.smoothing_length();
+        // strengths for negative & positive pressures, exponent and 1 / W(Δp)
+        let artificial_pressure = self.artificial_pressure.map(|artificial_pressure| {
+            let reference_distance = artificial_pressure.reference_distance * fluid_world.properties.particle_radius() * 2.0;
+            (
+                artificial_pressure.strength,
+                artificial_pressure.positive_strength,
+                artificial_pressure.exponent,
+                1.0 / pressure_kernel.evaluate(reference_distance * reference_distance, reference_distance),
+            )
+        });
+        // pressure term of the symmetric pressure force, i.e. the factor for -mj ∇W
+        let pressure_term = |pi: Real, rhoi: Real, pj: Real, rhoj: Real, r_sq: Real, r: Real| {
+            let pressure_term = pi / (rhoi * rhoi) + pj / (rhoj * rhoj);
+            match artificial_pressure {
+                Some((strength, positive_strength, exponent, inv_reference_weight)) => {
+                    // R of Monaghan 2000, always repulsive
+                    let correction = |p: Real, rho: Real| {
+                        let epsilon = if p < 0.0 { strength } else { positive_strength };
+                        epsilon * p.abs() / (rho * rho)
+                    };
+                    let f = pressure_kernel.evaluate(r_sq, r) * inv_reference_weight;
+                    pressure_term + (correction(pi, rhoi) + correction(pj, rhoj)) * f.powi(exponent)
+                }
+                None => pressure_term,
+            }
+        };
+
+        // continuity equation, boundary particles only count towards the density of the fluid if there are no ghost particles
+        let gather_density_rates = self.density_evolution == DensityEvolution::Continuity;
+        let boundary_mass = fluid_world.boundary_particle_mass();
+        let boundary_velocities = &particles.boundary_velocities;
+        self.density_rates.resize(particles.positions.len(), 0.0);
 
         self.accellerations
             .par_iter_mut()
+            .zip(self.density_rates.par_iter_mut())
             .zip(
                 (
                     &fluid_world.particles.velocities,
@@ -83,10 +318,11 @@ impl<TViscosityModel: ViscosityModel + std::marker::Sync> WCSPHSolver<TViscosity
                     .into_par_iter(),
             )
             .enumerate()
-            .for_each(|(i, (accelleration, (&vi, &ri, &rhoi)))| {
-                *accelleration = gravity;
+            .for_each(|(i, ((accelleration, density_rate), (&vi, &ri, &rhoi)))| {
+                *accelleration = ForceField::total_accelleration(force_fields, ri, vi, time);
+                *density_rate = 0.0;
 
-                let pi = Self::pressure(stiffness, fluid_density, rhoi);
+                let pi = equation_of_state.pressure(rhoi);
                 let i = i as u32;
 
                 // no self-contribution since vector to particle is zero (-> no pressure) and velocity difference is zero as well (-> no viscosity)
@@ -95,22 +331,52 @@ impl<TViscosityModel: ViscosityModel + std::marker::Sync> WCSPHSolver<TViscosity
                     #[inline(always)]
                     |j| {
                         let j = j as usize;
+                        let mj = particles.masses[j];
                         let rhoj = particles.densities[j];
-                        let pj = Self::pressure(stiffness, fluid_density, rhoj);
+                        let pj = equation_of_state.pressure(rhoj);
                         let ri_to_rj = particles.positions[j] - ri;
                         let r_sq = ri_to_rj.magnitude2();
                         let r = r_sq.sqrt();
 
                         // accelleration from pressure force
-                        // As in "Particle-Based Fluid Simulation for Interactive Applications", Müller et al.
                         // This is a weakly compressible model (WCSPH)
-                        let pressure_unsmoothed = -mass * (pi + pj) / (2.0 * rhoi * rhoj);
-                        *accelleration += pressure_unsmoothed * pressure_kernel.gradient(ri_to_rj, r_sq, r);
+                        let pressure_unsmoothed = -mj * pressure_term(pi, rhoi, pj, rhoj, r_sq, r);
+                        let gradient = pressure_kernel.gradient(ri_to_rj, r_sq, r);
+                        *accelleration += pressure_unsmoothed * gradient;
 
-                        *accelleration += viscosity_model.compute_viscous_accelleration(dt, r_sq, r, mass, rhoj, particles.velocities[j] - vi);
+                        let vj = particles.velocities[j];
+                        *accelleration += viscosity_model.compute_viscous_accelleration(dt, r_sq, r, mj, rhoi, rhoj, vj - vi);
+                        if gather_density_rates {
+                            *density_rate += mj * (vi - vj).dot(gradient);
+                        }
                     },
                 );
 
+                // Ghost particles have the same pressure & density as the particle they mirror.
+                // The penalty force below still applies: Particles with zero pressure, e.g. at the fluid's edges, would otherwise slide through walls.
+                if let Some(ghost_particles) = ghost_particles {
+                    ghost_particles.foreach_ghost_neighbor(particles, smoothing_length, i, |j, ghost_position, ghost_velocity| {
+                        let j = j as usize;
+                        let mj = particles.masses[j];
+                        let rhoj = particles.densities[j];
+                        let pj = equation_of_state.pressure(rhoj);
+                        let ri_to_rj = ghost_position - ri;
+                        let r_sq = ri_to_rj.magnitude2();
+                        let r = r_sq.sqrt();
+                        if r == 0.0 {
+                            return;
+                        }
+
+                        let pressure_unsmoothed = -mj * pressure_term(pi, rhoi, pj, rhoj, r_sq, r);
+                        let gradient = pressure_kernel.gradient(ri_to_rj, r_sq, r);
+                        *accelleration += pressure_unsmoothed * gradient;
+                        *accelleration += viscosity_model.compute_viscous_accelleration(dt, r_sq, r, mj, rhoi, rhoj, ghost_velocity - vi);
+                        if gather_density_rates {
+                            *density_rate += mj * (vi - ghost_velocity).dot(gradient);
+                        }
+                    });
+                }
+
                 // Boundary forces as described by
                 // "SPH particle boundary forces for arbitrary boundaries" by Monaghan and Kajtar 2009
                 // Simple formulation found in http://www.unige.ch/math/folks/sutti/SPH_2019.pdf under 2.3.4 Radial force
@@ -121,48 +387,153 @@ impl<TViscosityModel: ViscosityModel + std::marker::Sync> WCSPHSolver<TViscosity
                     |j| {
                         let ri_to_rj = particles.boundary_particles[j as usize] - ri;
                         let r_sq = ri_to_rj.magnitude2();
-                        *accelleration -= boundary_force_factor * pressure_kernel.evaluate(r_sq, r_sq.sqrt()) / r_sq * ri_to_rj;
+                        let r = r_sq.sqrt();
+                        *accelleration -= boundary_force_factor * pressure_kernel.evaluate(r_sq, r) / r_sq * ri_to_rj;
+                        if gather_density_rates && ghost_particles.is_none() {
+                            let vj = boundary_velocities.get(j as usize).cloned().unwrap_or_else(Vector::zero);
+                            *density_rate += boundary_mass * (vi - vj).dot(pressure_kernel.gradient(ri_to_rj, r_sq, r));
+                        }
                     },
                 );
             });
     }
-}
 
-impl<TViscosityModel: ViscosityModel + std::marker::Sync> Solver for WCSPHSolver<TViscosityModel> {
-    fn clear_cached_data(&mut self) {
-        self.accellerations.clear();
+    // δ-SPH density diffusion, adding dt * δ h c Σ_j V_j ψ_ij · ∇W_ij with ψ_ij = 2 (ρ_j - ρ_i) (r_j - r_i) / |r_j - r_i|² to every density.
+    // Only fluid neighbors take part, densities near walls are diffused among the fluid particles alone.
+    fn diffuse_densities(&self, fluid_world: &mut FluidParticleWorld, dt: Real) {
+        microprofile::scope!("WCSPHSolver", "diffuse_densities");
+        let factor = dt * self.density_diffusion * fluid_world.properties.smoothing_length() * self.speed_of_sound.0;
+        let pressure_kernel = self.pressure_kernel;
+        let mut diffused_densities = fluid_world.scratch_buffers.get_buffer_real(fluid_world.particles.densities.len());
+        let particles = &fluid_world.particles;
+        diffused_densities
+            .buffer
+            .par_iter_mut()
+            .zip((&particles.positions, &particles.densities).into_par_iter())
+            .enumerate()
+            .for_each(|(i, (diffused_density, (&ri, &rhoi)))| {
+                let mut diffusion = 0.0;
+                particles.foreach_neighbor_particle(i as ParticleIndex, |j| {
+                    let j = j as usize;
+                    let rhoj = particles.densities[j];
+                    let ri_to_rj = particles.positions[j] - ri;
+                    let r_sq = ri_to_rj.magnitude2();
+                    if r_sq == 0.0 {
+                        return;
+                    }
+                    let gradient = pressure_kernel.gradient(ri_to_rj, r_sq, r_sq.sqrt());
+                    diffusion += particles.masses[j] / rhoj * 2.0 * (rhoj - rhoi) * ri_to_rj.dot(gradient) / r_sq;
+                });
+                *diffused_density = rhoi + factor * diffusion;
+            });
+        std::mem::swap(&mut fluid_world.particles.densities, &mut diffused_densities.buffer);
     }
 
-    fn simulation_step(&mut self, fluid_world: &mut FluidParticleWorld, time_manager: &mut TimeManager) {
+    // simulation_step on the threads of the options' thread pool.
+    fn step(&mut self, fluid_world: &mut FluidParticleWorld, time_manager: &mut TimeManager) -> Result<(), SphError> {
         microprofile::scope!("WCSPHSolver", "simulation_step");
+        self.timings.clear();
+        if let Some(velocity_clamping) = &mut self.options.velocity_clamping {
+            velocity_clamping.reset_counters();
+        }
+        if self.options.watchdog_check(|watchdog| watchdog.alarm().is_some()) {
+            return Ok(());
+        }
+        fluid_world.check_particle_arrays()?;
+        let time = time_manager.passed_time();
         self.accellerations.resize(fluid_world.particles.positions.len(), cgmath::Zero::zero());
 
-        // leap frog integration scheme with integer steps
-        // https://en.wikipedia.org/wiki/Leapfrog_integration
-
         let mut dt = time_manager.timestep();
 
+        let timer = Instant::now();
         {
-            microprofile::scope!("WCSPHSolver", "leap frog 1");
-
-            // This got actually slower for a parallel for loop when used with 2500 particles (too few? or is rayon doing something silly?)
-            // fluid_world.particles.positions.par_iter_mut().zip(fluid_world.particles.velocities.par_iter_mut()).zip(self.accellerations.par_iter()).for_each(|((pos, v), a)| {
+            microprofile::scope!("WCSPHSolver", "integration 1");
+            let particles = &mut fluid_world.particles;
+            self.integrator
+                .begin_step(dt, &mut particles.positions, &mut particles.velocities, &self.accellerations);
+            time_manager.update_time();
+        }
 
-            for ((pos, v), a) in fluid_world
+        let timer = self.record_pass(SimulationPass::Integration, timer);
+        let particles = &fluid_world.particles;
+        if self.options.watchdog_check(|watchdog| {
+            watchdog.check_positions(SimulationPass::Integration, time, &particles.positions)
+                || watchdog.check_velocities(SimulationPass::Integration, time, &particles.velocities)
+        }) {
+            return Ok(());
+        }
+        // Evolved densities are carried over from the previous step, unless there is none, e.g. after a reset.
+        let evolve_densities = self.density_evolution == DensityEvolution::Continuity && !self.density_rates.is_empty();
+        if evolve_densities {
+            // particles added since the last step start at rest density
+            let num_particles = fluid_world.particles.positions.len();
+            let fluid_density = fluid_world.properties.fluid_density();
+            let mut densities = std::mem::take(&mut fluid_world.particles.densities);
+            densities.truncate(self.density_rates.len().min(num_particles));
+            densities.resize(num_particles, fluid_density);
+            self.density_rates.resize(num_particles, 0.0);
+            // densities need to follow the particles when they are reordered
+            fluid_world.update_neighborhood_datastructure(self.integrator.particle_state(), vec![&mut densities, &mut self.density_rates]);
+            fluid_world.particles.densities = densities;
+        } else {
+            fluid_world.update_neighborhood_datastructure(self.integrator.particle_state(), Vec::new());
+        }
+        if let Some(ghost_particles) = &mut self.ghost_particles {
+            ghost_particles.update(fluid_world);
+        }
+        let timer = self.record_pass(SimulationPass::Neighborhood, timer);
+        if evolve_densities {
+            let density_rates = &self.density_rates;
+            fluid_world
                 .particles
-                .positions
-                .iter_mut()
-                .zip(fluid_world.particles.velocities.iter_mut())
-                .zip(self.accellerations.iter())
-            {
-                *v += 0.5 * dt * a; // v at t_(i+0.5)
-                *pos += *v * dt; // pos at t_(i+1)
+                .densities
+                .par_iter_mut()
+                .zip(density_rates.par_iter())
+                .for_each(|(density, density_rate)| *density += density_rate * dt);
+        } else {
+            match &self.ghost_particles {
+                Some(ghost_particles) if self.negative_pressure_clamping => ghost_particles.update_densities(fluid_world, self.density_kernel),
+                Some(ghost_particles) => ghost_particles.update_densities_unclamped(fluid_world, self.density_kernel),
+                None if self.negative_pressure_clamping => fluid_world.update_densities(self.density_kernel),
+                None => fluid_world.update_densities_unclamped(self.density_kernel),
             }
         }
-
-        fluid_world.update_neighborhood_datastructure(Vec::new(), Vec::new());
-        fluid_world.update_densities(self.density_kernel);
-        self.update_accellerations(fluid_world, dt);
+        if self.density_diffusion > 0.0 {
+            self.diffuse_densities(fluid_world, dt);
+        }
+        fluid_world.update_free_surface();
+        let timer = self.record_pass(SimulationPass::Density, timer);
+        let densities = &fluid_world.particles.densities;
+        if self
+            .options
+            .watchdog_check(|watchdog| watchdog.check_densities(SimulationPass::Density, time, densities))
+        {
+            return Ok(());
+        }
+        check_finite("density", time, densities, |density| density.is_finite())?;
+        // viscosity and external forces are computed in the same loop
+        self.update_accellerations(fluid_world, dt, time_manager.passed_time());
+        if self.density_evolution == DensityEvolution::Summation {
+            self.density_rates.clear();
+        }
+        // reaction to the penalty force, ghost particles' pressure is not accounted for
+        let boundary_force_factor = self.boundary_force_factor / fluid_world.boundary_sampling_factor();
+        let pressure_kernel = self.pressure_kernel;
+        fluid_world.update_boundary_forces(|particles, i, j| {
+            let ri_to_rj = particles.boundary_particles[j as usize] - particles.positions[i as usize];
+            let r_sq = ri_to_rj.magnitude2();
+            -boundary_force_factor * pressure_kernel.evaluate(r_sq, r_sq.sqrt()) / r_sq * ri_to_rj
+        });
+        let timer = self.record_pass(SimulationPass::Pressure, timer);
+        if let Some(watchdog) = &mut self.options.watchdog {
+            if watchdog.check_accellerations(SimulationPass::Pressure, time, &self.accellerations) {
+                return Ok(());
+            }
+        }
+        check_finite("accelleration", time, &self.accellerations, |a| a.x.is_finite() && a.y.is_finite())?;
+        if let Some(velocity_clamping) = &mut self.options.velocity_clamping {
+            velocity_clamping.clamp_accellerations(&mut self.accellerations, fluid_world.properties.particle_radius() * 2.0, dt);
+        }
 
         // update timestep
         {
@@ -175,15 +546,197 @@ impl<TViscosityModel: ViscosityModel + std::marker::Sync> Solver for WCSPHSolver
             dt = time_manager.timestep();
         }
 
-        // part 2 of leap frog integration. Finish updating velocity.
         {
-            // This got actually slower for a parallel for loop when used with 2500 particles (too few? or is rayon doing something silly?)
-            // fluid_world.particles.velocities.par_iter_mut().zip(self.accellerations.par_iter()).for_each(|(v, a)|
+            microprofile::scope!("WCSPHSolver", "integration 2");
+            let particles = &mut fluid_world.particles;
+            self.integrator
+                .finish_step(dt, &mut particles.positions, &mut particles.velocities, &self.accellerations);
+        }
+        if let Some(velocity_clamping) = &mut self.options.velocity_clamping {
+            velocity_clamping.clamp_velocities(&mut fluid_world.particles.velocities, fluid_world.properties.particle_radius() * 2.0, dt);
+        }
+        let timer = self.record_pass(SimulationPass::Integration, timer);
+        let velocities = &fluid_world.particles.velocities;
+        if self
+            .options
+            .watchdog_check(|watchdog| watchdog.check_velocities(SimulationPass::Integration, time, velocities))
+        {
+            return Ok(());
+        }
+        check_finite("velocity", time, velocities, |v| v.x.is_finite() && v.y.is_finite())?;
+
+        if let Some(xsph_smoothing) = &self.options.xsph_smoothing {
+            xsph_smoothing.apply(fluid_world);
+            self.record_pass(SimulationPass::Viscosity, timer);
+        }
+        Ok(())
+    }
+}
+
+impl<TViscosityModel: ViscosityModel + Send + std::marker::Sync> Solver for WCSPHSolver<TViscosityModel> {
+    fn initialize(&mut self, fluid_world: &FluidParticleWorld) {
+        self.density_kernel = smoothing_kernel::Poly6::new(fluid_world.properties.smoothing_length());
+        self.pressure_kernel = smoothing_kernel::Spiky::new(fluid_world.properties.smoothing_length());
+        self.clear_cached_state();
+    }
+
+    fn clear_cached_state(&mut self) {
+        self.accellerations.clear();
+        self.density_rates.clear();
+    }
+
+    fn retain_particle_data(&mut self, keep: &[bool]) {
+        retain_particle_attribute(&mut self.accellerations, keep);
+        retain_particle_attribute(&mut self.density_rates, keep);
+    }
+
+    fn simulation_step(&mut self, fluid_world: &mut FluidParticleWorld, time_manager: &mut TimeManager) -> Result<(), SphError> {
+        let thread_pool = self.options.thread_pool().clone();
+        thread_pool.install(|| self.step(fluid_world, time_manager))
+    }
+
+    fn last_step_timings(&self) -> &StepTimings {
+        &self.timings
+    }
 
-            microprofile::scope!("WCSPHSolver", "leap frog 2");
-            for (v, a) in fluid_world.particles.velocities.iter_mut().zip(self.accellerations.iter()) {
-                *v += 0.5 * dt * a; // v at t_(i+1)
+    fn particle_pressure(&self, fluid_world: &FluidParticleWorld, particle: ParticleIndex) -> Option<Real> {
+        let density = *fluid_world.particles.densities.get(particle as usize)?;
+        let fluid_density = fluid_world.properties.fluid_density();
+        Some(self.equation_of_state(fluid_density).pressure(density))
+    }
+
+    fn max_stable_timestep(&self, fluid_world: &FluidParticleWorld) -> Option<Real> {
+        // acoustic CFL condition, pressure waves may not travel further than 0.4 particle spacings per step
+        Some(0.4 * fluid_world.properties.particle_radius() * 2.0 / self.speed_of_sound.0)
+    }
+
+    fn options(&self) -> &SolverOptions {
+        &self.options
+    }
+
+    fn options_mut(&mut self) -> &mut SolverOptions {
+        &mut self.options
+    }
+
+    fn parameters(&self) -> Vec<SolverParameter> {
+        let mut parameters = vec![
+            SolverParameter {
+                name: "speed_of_sound",
+                unit: Velocity::UNIT,
+                value: self.speed_of_sound.0,
+                min: 1.0,
+                max: 100.0,
+                logarithmic: false,
+            },
+            SolverParameter {
+                name: "boundary_force_factor",
+                unit: "",
+                value: self.boundary_force_factor,
+                min: 0.0,
+                max: 10.0,
+                logarithmic: false,
+            },
+            SolverParameter {
+                name: "clamp_negative_pressure",
+                unit: "",
+                value: self.negative_pressure_clamping as i32 as Real,
+                min: 0.0,
+                max: 1.0,
+                logarithmic: false,
+            },
+            SolverParameter {
+                name: "background_pressure_factor",
+                unit: "",
+                value: self.background_pressure_factor,
+                min: 0.0,
+                max: 0.1,
+                logarithmic: false,
+            },
+            SolverParameter {
+                name: "continuity_density",
+                unit: "",
+                value: (self.density_evolution == DensityEvolution::Continuity) as i32 as Real,
+                min: 0.0,
+                max: 1.0,
+                logarithmic: false,
+            },
+            SolverParameter {
+                name: "density_diffusion",
+                unit: "",
+                value: self.density_diffusion,
+                min: 0.0,
+                max: 0.5,
+                logarithmic: false,
+            },
+            // ahead of its strengths, so that they are carried over after it when copying parameters to another solver
+            SolverParameter {
+                name: "artificial_pressure",
+                unit: "",
+                value: self.artificial_pressure.is_some() as i32 as Real,
+                min: 0.0,
+                max: 1.0,
+                logarithmic: false,
+            },
+        ];
+        if let Some(artificial_pressure) = &self.artificial_pressure {
+            parameters.push(SolverParameter {
+                name: "artificial_pressure_strength",
+                unit: "",
+                value: artificial_pressure.strength,
+                min: 0.0,
+                max: 0.5,
+                logarithmic: false,
+            });
+            parameters.push(SolverParameter {
+                name: "artificial_pressure_positive_strength",
+                unit: "",
+                value: artificial_pressure.positive_strength,
+                min: 0.0,
+                max: 0.1,
+                logarithmic: false,
+            });
+        }
+        parameters.extend(self.viscosity_model.parameters());
+        parameters.extend(self.options.parameters());
+        parameters
+    }
+
+    fn set_parameter(&mut self, name: &str, value: Real) -> bool {
+        match name {
+            "speed_of_sound" => self.speed_of_sound = Velocity(value),
+            "boundary_force_factor" => self.boundary_force_factor = value,
+            "clamp_negative_pressure" => self.negative_pressure_clamping = value.round() != 0.0,
+            "background_pressure_factor" => self.background_pressure_factor = value,
+            "density_diffusion" => self.density_diffusion = value,
+            "continuity_density" => {
+                self.density_evolution = if value.round() != 0.0 {
+                    DensityEvolution::Continuity
+                } else {
+                    DensityEvolution::Summation
+                }
+            }
+            "artificial_pressure" => {
+                self.artificial_pressure = if value.round() != 0.0 {
+                    self.artificial_pressure.or_else(|| Some(ArtificialPressure::default()))
+                } else {
+                    None
+                }
+            }
+            "artificial_pressure_strength" => match &mut self.artificial_pressure {
+                Some(artificial_pressure) => artificial_pressure.strength = value,
+                None => return false,
+            },
+            "artificial_pressure_positive_strength" => match &mut self.artificial_pressure {
+                Some(artificial_pressure) => artificial_pressure.positive_strength = value,
+                None => return false,
+            },
+            _ => {
+                return self
+                    .options
+                    .set_parameter(name, value)
+                    .unwrap_or_else(|| self.viscosity_model.set_parameter(name, value))
             }
         }
+        true
     }
 }
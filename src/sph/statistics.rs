@@ -0,0 +1,321 @@
+use super::fluidparticleworld::{BoundaryObjectForce, FluidParticleWorld, Particles};
+use super::neighborhood_search::ParticleIndex;
+use super::solver::{Solver, SolverIterations, SolverResiduals};
+use super::timemanager::TimeManager;
+use crate::units::*;
+use cgmath::prelude::*;
+
+// Range of neighbor counts (fluid and boundary) of particles in the fluid's interior that give reliable SPH estimates in 2D.
+// A resting lattice has about π·f² neighbors for a smoothing factor f, for the default of 2 that is 12 or 8,
+// depending on whether rounding puts the particles exactly two spacings away in reach.
+// Fewer make kernel sums noisy and the fluid prone to clumping, more cost time and smear out detail.
+// Particles at the free surface naturally have fewer neighbors and are exempt from the minimum.
+pub const MIN_HEALTHY_NEIGHBORS: u32 = 8;
+pub const MAX_HEALTHY_NEIGHBORS: u32 = 50;
+
+// Distribution of per particle neighbor counts as of the last neighborhood update, see Particles::num_neighbors.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NeighborCountStatistics {
+    pub min: u32,
+    pub max: u32,
+    pub avg: Real,
+    pub num_too_few: usize,  // interior particles below MIN_HEALTHY_NEIGHBORS
+    pub num_too_many: usize, // particles above MAX_HEALTHY_NEIGHBORS
+}
+
+impl NeighborCountStatistics {
+    pub fn gather(particles: &Particles) -> NeighborCountStatistics {
+        // particles added since the last step are not part of the neighborhood yet
+        let num_particles = particles.positions.len().min(particles.neighborhood().num_particles());
+        if num_particles == 0 {
+            return Default::default();
+        }
+
+        let mut statistics = NeighborCountStatistics {
+            min: u32::MAX,
+            ..Default::default()
+        };
+        let mut sum = 0;
+        for i in 0..num_particles {
+            let num_neighbors = particles.num_neighbors(i as ParticleIndex);
+            statistics.min = statistics.min.min(num_neighbors);
+            statistics.max = statistics.max.max(num_neighbors);
+            sum += num_neighbors as usize;
+            let is_interior = !particles.free_surface.get(i).cloned().unwrap_or(true);
+            if is_interior && num_neighbors < MIN_HEALTHY_NEIGHBORS {
+                statistics.num_too_few += 1;
+            }
+            if num_neighbors > MAX_HEALTHY_NEIGHBORS {
+                statistics.num_too_many += 1;
+            }
+        }
+        statistics.avg = sum as Real / num_particles as Real;
+        statistics
+    }
+
+    // Describes what is wrong with the neighbor counts and how to fix it, None if all are within the healthy range.
+    pub fn warning(&self) -> Option<String> {
+        if self.num_too_few > 0 {
+            Some(format!(
+                "{} interior particles have fewer than {} neighbors (min {}), consider a larger smoothing factor",
+                self.num_too_few, MIN_HEALTHY_NEIGHBORS, self.min
+            ))
+        } else if self.num_too_many > 0 {
+            Some(format!(
+                "{} particles have more than {} neighbors (max {}), consider a smaller smoothing factor",
+                self.num_too_many, MAX_HEALTHY_NEIGHBORS, self.max
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+// Aggregated measures of the current simulation state, for monitoring stability and accuracy.
+// All energies are per unit depth since we're in 2D.
+#[derive(Clone, Debug, Default)]
+pub struct SimulationStatistics {
+    pub num_fluid_particles: usize,
+    pub num_boundary_particles: usize,
+    pub num_free_surface_particles: usize, // as classified during the last simulation step
+    pub neighbor_counts: NeighborCountStatistics,
+
+    // Compression relative to rest density, (ρ - ρ0) / ρ0.
+    // Particles with lower than rest density (typically at the surface) count as zero error.
+    pub max_density_error: Real,
+    pub avg_density_error: Real,
+
+    pub kinetic_energy: Real,   // in J, Σ ½ m v²
+    pub potential_energy: Real, // in J, Σ -m g·x, i.e. relative to the world origin
+
+    pub max_velocity: Real, // in m/s
+    pub cfl_number: Real,   // max_velocity * timestep / particle diameter
+
+    pub solver_iterations: Option<SolverIterations>,
+    pub solver_residuals: Option<SolverResiduals>,
+
+    // Particles the solver's velocity clamping had to slow down in the last step, zero if it has none.
+    pub num_clamped_velocities: usize,
+    pub num_clamped_accellerations: usize,
+
+    // Force and torque the fluid exerted on each boundary object during the last step, see FluidParticleWorld::boundary_object_forces.
+    pub boundary_object_forces: Vec<BoundaryObjectForce>,
+}
+
+impl SimulationStatistics {
+    pub fn gather(fluid_world: &FluidParticleWorld, solver: &dyn Solver, time_manager: &TimeManager) -> SimulationStatistics {
+        microprofile::scope!("SimulationStatistics", "gather");
+
+        let particles = &fluid_world.particles;
+        let fluid_density = fluid_world.properties.fluid_density();
+
+        // densities of particles added since the last step are not known yet
+        let mut max_density_error: Real = 0.0;
+        let mut sum_density_error = 0.0;
+        for density in particles.densities.iter().take(particles.positions.len()) {
+            let density_error = (density / fluid_density - 1.0).max(0.0);
+            max_density_error = max_density_error.max(density_error);
+            sum_density_error += density_error;
+        }
+        let num_densities = particles.densities.len().min(particles.positions.len());
+
+        let mut max_velocity_sq: Real = 0.0;
+        let mut kinetic_energy = 0.0;
+        for (v, mass) in particles.velocities.iter().zip(particles.masses.iter()) {
+            let velocity_sq = v.magnitude2();
+            max_velocity_sq = max_velocity_sq.max(velocity_sq);
+            kinetic_energy += 0.5 * mass * velocity_sq;
+        }
+        // only uniform gravity has a well defined potential energy
+        let gravity = fluid_world.gravity();
+        let potential_energy = -particles
+            .positions
+            .iter()
+            .zip(particles.masses.iter())
+            .map(|(p, mass)| mass * p.to_vec().dot(gravity))
+            .sum::<Real>();
+        let max_velocity = max_velocity_sq.sqrt();
+
+        SimulationStatistics {
+            num_fluid_particles: particles.positions.len(),
+            num_boundary_particles: particles.boundary_particles.len(),
+            num_free_surface_particles: particles.free_surface.iter().filter(|is_surface| **is_surface).count(),
+            neighbor_counts: NeighborCountStatistics::gather(particles),
+
+            max_density_error,
+            avg_density_error: if num_densities > 0 {
+                sum_density_error / num_densities as Real
+            } else {
+                0.0
+            },
+
+            kinetic_energy,
+            potential_energy,
+
+            max_velocity,
+            cfl_number: max_velocity * time_manager.timestep() / (fluid_world.properties.particle_radius() * 2.0),
+
+            solver_iterations: solver.last_step_iterations(),
+            solver_residuals: solver.last_step_residuals().cloned(),
+
+            num_clamped_velocities: solver.velocity_clamping().map_or(0, |clamping| clamping.num_clamped_velocities()),
+            num_clamped_accellerations: solver.velocity_clamping().map_or(0, |clamping| clamping.num_clamped_accellerations()),
+
+            boundary_object_forces: fluid_world.boundary_object_forces(),
+        }
+    }
+
+    pub fn total_energy(&self) -> Real {
+        self.kinetic_energy + self.potential_energy
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use super::*;
+
+    #[test]
+    fn energy_of_moving_particles() {
+        let mut world = FluidParticleWorld::new(2.0, NumberDensity(100.0), Density(1000.0));
+        world.add_fluid_particle(Point::new(0.0, 1.0), Vector::new(2.0, 0.0));
+        world.add_fluid_particle(Point::new(1.0, 2.0), Vector::new(0.0, -1.0));
+        let solver = DFSPHSolver::new(
+            XSPHViscosityModel::new(world.properties.smoothing_length()),
+            world.properties.smoothing_length(),
+        );
+        let time_manager = TimeManager::new(TimeManagerConfiguration::FixedTimeStep(0.01));
+
+        let statistics = SimulationStatistics::gather(&world, &solver, &time_manager);
+        let mass = world.properties.particle_mass();
+        assert_eq!(statistics.num_fluid_particles, 2);
+        assert_lt!((statistics.kinetic_energy - 0.5 * mass * 5.0).abs(), 1.0e-4);
+        assert_lt!((statistics.potential_energy - mass * 9.81 * 3.0).abs(), 1.0e-3);
+        assert_lt!((statistics.max_velocity - 2.0).abs(), 1.0e-6);
+        assert_lt!(
+            (statistics.cfl_number - 2.0 * 0.01 / (world.properties.particle_radius() * 2.0)).abs(),
+            1.0e-6
+        );
+        // no densities computed yet
+        assert_eq!(statistics.max_density_error, 0.0);
+    }
+
+    #[test]
+    fn neighbor_counts_depend_on_smoothing_factor() {
+        let count_neighbors = |smoothing_factor: Real| {
+            let mut world = FluidParticleWorld::new(smoothing_factor, NumberDensity(10000.0), Density(100.0));
+            world.add_fluid_rect(&Rect::new(0.0, 0.0, 0.3, 0.3), 0.0);
+            world.update_neighborhood_datastructure(Vec::new(), Vec::new());
+            world.update_free_surface();
+            NeighborCountStatistics::gather(&world.particles)
+        };
+
+        // resting lattice with the default smoothing factor
+        let statistics = count_neighbors(2.0);
+        assert_le!(statistics.max, 12);
+        assert_lt!(statistics.min, MIN_HEALTHY_NEIGHBORS); // corners
+        assert_eq!(statistics.num_too_few, 0);
+        assert!(statistics.warning().is_none());
+
+        let statistics = count_neighbors(1.3);
+        assert_gt!(statistics.num_too_few, 0);
+        assert!(statistics.warning().unwrap().contains("larger smoothing factor"));
+
+        let statistics = count_neighbors(4.2);
+        assert_gt!(statistics.num_too_many, 0);
+        assert!(statistics.warning().unwrap().contains("smaller smoothing factor"));
+    }
+
+    #[test]
+    fn solver_residuals_follow_iterations() {
+        let mut world = FluidParticleWorld::new(2.0, NumberDensity(10000.0), Density(100.0));
+        world.add_fluid_rect(&Rect::new(0.0, 0.0, 0.2, 0.2), 0.0);
+        world.add_boundary_thick_line(Point::new(-0.1, -0.01), Point::new(0.3, -0.01), 2);
+        let mut solver = DFSPHSolver::new(
+            XSPHViscosityModel::new(world.properties.smoothing_length()),
+            world.properties.smoothing_length(),
+        );
+        let mut time_manager = TimeManager::new(TimeManagerConfiguration::FixedTimeStep(0.001));
+        for _ in 0..3 {
+            solver.simulation_step(&mut world, &mut time_manager).unwrap();
+        }
+
+        let statistics = SimulationStatistics::gather(&world, &solver, &time_manager);
+        let iterations = statistics.solver_iterations.unwrap();
+        let residuals = statistics.solver_residuals.unwrap();
+        assert_eq!(residuals.density.len(), iterations.density);
+        assert_eq!(residuals.divergence.len(), iterations.divergence);
+        assert_lt!(*residuals.density.last().unwrap(), residuals.density_target);
+        assert!(residuals.warning().is_none());
+
+        // an unreachable target stops at the iteration limit
+        assert!(solver.set_parameter("max_density_error", 1.0e-12));
+        assert!(solver.set_parameter("max_density_iterations", 3.0));
+        solver.simulation_step(&mut world, &mut time_manager).unwrap();
+        let residuals = solver.last_step_residuals().unwrap();
+        assert_eq!(residuals.density.len(), 4);
+        assert!(residuals
+            .warning()
+            .unwrap()
+            .starts_with("Density solve did not converge within 4 iterations"));
+    }
+
+    #[test]
+    fn boundary_objects_carry_fluid_weight() {
+        let mut world = FluidParticleWorld::new(2.0, NumberDensity(10000.0), Density(100.0));
+        world.add_fluid_rect(&Rect::new(0.0, 0.0, 0.2, 0.2), 0.0);
+        world.add_boundary_thick_line(Point::new(-0.1, -0.01), Point::new(0.3, -0.01), 2);
+        world.add_boundary_thick_line(Point::new(-0.01, 0.4), Point::new(-0.01, -0.01), 2);
+        world.add_boundary_thick_line(Point::new(0.2, -0.01), Point::new(0.2, 0.4), 2);
+        let mut solver = DFSPHSolver::new(
+            XSPHViscosityModel::new(world.properties.smoothing_length()),
+            world.properties.smoothing_length(),
+        );
+        let mut time_manager = TimeManager::new(TimeManagerConfiguration::FixedTimeStep(0.001));
+        for _ in 0..200 {
+            solver.simulation_step(&mut world, &mut time_manager).unwrap();
+        }
+
+        let statistics = SimulationStatistics::gather(&world, &solver, &time_manager);
+        let forces = &statistics.boundary_object_forces;
+        assert_eq!(forces.len(), 3);
+        // the floor carries the weight of the resting fluid (only roughly, solvers approximate boundary forces), the walls hold it together
+        let weight = world.particles.masses.iter().sum::<Real>() * 9.81;
+        assert_lt!((forces[0].force.y + weight).abs(), weight * 0.5);
+        assert_lt!(forces[0].force.x.abs(), weight * 0.01);
+        assert_lt!(forces[1].force.x, 0.0);
+        assert_gt!(forces[2].force.x, 0.0);
+        assert_lt!((forces[1].force.x + forces[2].force.x).abs(), -forces[1].force.x * 0.2);
+        // pressure tips the left wall outwards around its base, i.e. counter-clockwise
+        assert_gt!(forces[1].torque_around(Point::new(-0.02, 0.0)), 0.0);
+    }
+
+    #[test]
+    fn clamped_particles_are_counted() {
+        let mut world = FluidParticleWorld::new(2.0, NumberDensity(10000.0), Density(100.0));
+        world.add_fluid_rect(&Rect::new(0.0, 0.0, 0.2, 0.2), 0.0);
+        // a single particle way too fast for the timestep
+        world.particles.velocities[0] = Vector::new(1000.0, 0.0);
+        let smoothing_length = world.properties.smoothing_length();
+        let mut solver = DFSPHSolver::new(XSPHViscosityModel::new(smoothing_length), smoothing_length);
+        solver.set_velocity_clamping(Some(VelocityClamping::new(0.5)));
+        let mut time_manager = TimeManager::new(TimeManagerConfiguration::FixedTimeStep(0.001));
+        let start_position = world.particles.positions[0];
+        solver.simulation_step(&mut world, &mut time_manager).unwrap();
+
+        let statistics = SimulationStatistics::gather(&world, &solver, &time_manager);
+        assert_ge!(statistics.num_clamped_velocities, 1);
+        // moved by at most half a particle diameter
+        let max_velocity = solver
+            .velocity_clamping()
+            .unwrap()
+            .max_velocity(world.properties.particle_radius() * 2.0, 0.001);
+        assert_lt!(world.particles.positions[0].distance(start_position), max_velocity * 0.001 + 1.0e-6);
+
+        // no clamping without the option
+        solver.set_velocity_clamping(None);
+        let statistics = SimulationStatistics::gather(&world, &solver, &time_manager);
+        assert_eq!(statistics.num_clamped_velocities, 0);
+        assert_eq!(statistics.num_clamped_accellerations, 0);
+    }
+}
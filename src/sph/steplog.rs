@@ -0,0 +1,257 @@
+use super::fluidparticleworld::FluidParticleWorld;
+use super::probes::{ProbeSample, Probes};
+use super::solver::Solver;
+use super::statistics::SimulationStatistics;
+use super::timemanager::TimeManager;
+use std::io;
+
+// Value that can be logged every step, see StepLog.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogColumn {
+    Time,     // in s
+    Timestep, // in s
+    NumFluidParticles,
+    MaxDensityError,
+    AvgDensityError,
+    KineticEnergy,   // in J
+    PotentialEnergy, // in J
+    TotalEnergy,     // in J
+    MaxVelocity,     // in m/s
+    CflNumber,
+    DensityIterations,    // empty for solvers without a pressure solve
+    DivergenceIterations, // empty for solvers without a pressure solve
+    // Last sample of a probe, by index into Probes. Empty if there is no such probe or it wasn't sampled yet.
+    ProbeDensity(usize),
+    ProbeVelocityX(usize),
+    ProbeVelocityY(usize),
+    ProbePressure(usize), // also empty if the solver doesn't provide pressures
+}
+
+impl LogColumn {
+    // All columns that don't refer to a probe.
+    pub const STATISTICS: [LogColumn; 12] = [
+        LogColumn::Time,
+        LogColumn::Timestep,
+        LogColumn::NumFluidParticles,
+        LogColumn::MaxDensityError,
+        LogColumn::AvgDensityError,
+        LogColumn::KineticEnergy,
+        LogColumn::PotentialEnergy,
+        LogColumn::TotalEnergy,
+        LogColumn::MaxVelocity,
+        LogColumn::CflNumber,
+        LogColumn::DensityIterations,
+        LogColumn::DivergenceIterations,
+    ];
+
+    // All columns of all probes.
+    pub fn probe_columns(probes: &Probes) -> Vec<LogColumn> {
+        (0..probes.probes().len())
+            .flat_map(|i| {
+                vec![
+                    LogColumn::ProbeDensity(i),
+                    LogColumn::ProbeVelocityX(i),
+                    LogColumn::ProbeVelocityY(i),
+                    LogColumn::ProbePressure(i),
+                ]
+            })
+            .collect()
+    }
+
+    fn needs_statistics(&self) -> bool {
+        !matches!(
+            self,
+            LogColumn::Time
+                | LogColumn::Timestep
+                | LogColumn::ProbeDensity(_)
+                | LogColumn::ProbeVelocityX(_)
+                | LogColumn::ProbeVelocityY(_)
+                | LogColumn::ProbePressure(_)
+        )
+    }
+
+    // Column name with unit, probes by their name.
+    pub fn header(&self, probes: &Probes) -> String {
+        let probe_name = |i: usize| probes.probes().get(i).map_or(format!("probe{}", i), |probe| probe.name.clone());
+        match self {
+            LogColumn::Time => "time_s".to_string(),
+            LogColumn::Timestep => "timestep_s".to_string(),
+            LogColumn::NumFluidParticles => "num_fluid_particles".to_string(),
+            LogColumn::MaxDensityError => "max_density_error".to_string(),
+            LogColumn::AvgDensityError => "avg_density_error".to_string(),
+            LogColumn::KineticEnergy => "kinetic_energy_J".to_string(),
+            LogColumn::PotentialEnergy => "potential_energy_J".to_string(),
+            LogColumn::TotalEnergy => "total_energy_J".to_string(),
+            LogColumn::MaxVelocity => "max_velocity_m/s".to_string(),
+            LogColumn::CflNumber => "cfl_number".to_string(),
+            LogColumn::DensityIterations => "density_iterations".to_string(),
+            LogColumn::DivergenceIterations => "divergence_iterations".to_string(),
+            // same names as Probes::write_csv
+            LogColumn::ProbeDensity(i) => format!("{}_density", probe_name(*i)),
+            LogColumn::ProbeVelocityX(i) => format!("{}_velocity_x", probe_name(*i)),
+            LogColumn::ProbeVelocityY(i) => format!("{}_velocity_y", probe_name(*i)),
+            LogColumn::ProbePressure(i) => format!("{}_pressure", probe_name(*i)),
+        }
+    }
+
+    fn value(&self, statistics: Option<&SimulationStatistics>, time_manager: &TimeManager, probes: &Probes) -> String {
+        let probe_sample = |i: usize| -> Option<&ProbeSample> { probes.probes().get(i).and_then(|probe| probe.last_sample()) };
+        let statistic = |value: fn(&SimulationStatistics) -> String| statistics.map_or(String::new(), value);
+        match self {
+            LogColumn::Time => time_manager.passed_time().to_string(),
+            LogColumn::Timestep => time_manager.timestep().to_string(),
+            LogColumn::NumFluidParticles => statistic(|s| s.num_fluid_particles.to_string()),
+            LogColumn::MaxDensityError => statistic(|s| s.max_density_error.to_string()),
+            LogColumn::AvgDensityError => statistic(|s| s.avg_density_error.to_string()),
+            LogColumn::KineticEnergy => statistic(|s| s.kinetic_energy.to_string()),
+            LogColumn::PotentialEnergy => statistic(|s| s.potential_energy.to_string()),
+            LogColumn::TotalEnergy => statistic(|s| s.total_energy().to_string()),
+            LogColumn::MaxVelocity => statistic(|s| s.max_velocity.to_string()),
+            LogColumn::CflNumber => statistic(|s| s.cfl_number.to_string()),
+            LogColumn::DensityIterations => statistic(|s| s.solver_iterations.map_or(String::new(), |it| it.density.to_string())),
+            LogColumn::DivergenceIterations => statistic(|s| s.solver_iterations.map_or(String::new(), |it| it.divergence.to_string())),
+            LogColumn::ProbeDensity(i) => probe_sample(*i).map_or(String::new(), |sample| sample.density.to_string()),
+            LogColumn::ProbeVelocityX(i) => probe_sample(*i).map_or(String::new(), |sample| sample.velocity.x.to_string()),
+            LogColumn::ProbeVelocityY(i) => probe_sample(*i).map_or(String::new(), |sample| sample.velocity.y.to_string()),
+            LogColumn::ProbePressure(i) => probe_sample(*i)
+                .and_then(|sample| sample.pressure)
+                .map_or(String::new(), |pressure| pressure.to_string()),
+        }
+    }
+}
+
+// Writes a row of user selected values per simulation step as csv, as the simulation goes.
+// Unlike Probes::write_csv at the end of a run, a crash late in a run only loses the rows since the last flush.
+//
+//   let mut log = StepLog::new(File::create("steps.csv")?, vec![LogColumn::Time, LogColumn::KineticEnergy]);
+//   loop {
+//       solver.simulation_step(&mut fluid_world, &mut time_manager)?;
+//       log.log(&fluid_world, &solver, &time_manager, &probes)?;
+//   }
+pub struct StepLog<W: io::Write> {
+    writer: W,
+    columns: Vec<LogColumn>,
+    // Rows after which the writer is flushed, 0 leaves flushing to the writer. Defaults to every row.
+    pub flush_interval: usize,
+    num_rows: usize,
+    num_unflushed_rows: usize,
+}
+
+impl<W: io::Write> StepLog<W> {
+    // The header is written along with the first row, so that it can use the names of probes added in the meantime.
+    pub fn new(writer: W, columns: Vec<LogColumn>) -> StepLog<W> {
+        StepLog {
+            writer,
+            columns,
+            flush_interval: 1,
+            num_rows: 0,
+            num_unflushed_rows: 0,
+        }
+    }
+
+    pub fn columns(&self) -> &[LogColumn] {
+        &self.columns
+    }
+
+    pub fn num_rows(&self) -> usize {
+        self.num_rows
+    }
+
+    // Writes a row for the state after a step. Probes are expected to be recorded already.
+    // Statistics are only gathered if any column needs them.
+    pub fn log(&mut self, fluid_world: &FluidParticleWorld, solver: &dyn Solver, time_manager: &TimeManager, probes: &Probes) -> io::Result<()> {
+        microprofile::scope!("StepLog", "log");
+
+        if self.num_rows == 0 {
+            let header: Vec<String> = self.columns.iter().map(|column| column.header(probes)).collect();
+            writeln!(self.writer, "{}", header.join(","))?;
+        }
+        let statistics = if self.columns.iter().any(LogColumn::needs_statistics) {
+            Some(SimulationStatistics::gather(fluid_world, solver, time_manager))
+        } else {
+            None
+        };
+        let row: Vec<String> = self
+            .columns
+            .iter()
+            .map(|column| column.value(statistics.as_ref(), time_manager, probes))
+            .collect();
+        writeln!(self.writer, "{}", row.join(","))?;
+
+        self.num_rows += 1;
+        self.num_unflushed_rows += 1;
+        if self.flush_interval > 0 && self.num_unflushed_rows >= self.flush_interval {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.num_unflushed_rows = 0;
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sph::{TimeManagerConfiguration, WCSPHSolver, XSPHViscosityModel};
+    use crate::units::*;
+
+    // Counts flushes to check the flush interval.
+    #[derive(Default)]
+    struct CountingWriter {
+        bytes: Vec<u8>,
+        num_flushes: usize,
+    }
+
+    impl io::Write for CountingWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.bytes.write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            self.num_flushes += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn logs_selected_columns_every_step() {
+        let mut fluid_world = FluidParticleWorld::new(2.0, NumberDensity(5000.0), Density(100.0));
+        fluid_world.add_fluid_rect(&Rect::new(0.0, 0.5, 0.1, 0.1), 0.0);
+        let mut solver = WCSPHSolver::new(
+            XSPHViscosityModel::new(fluid_world.properties.smoothing_length()),
+            &fluid_world.properties,
+        );
+        let mut time_manager = TimeManager::new(TimeManagerConfiguration::FixedTimeStep(0.001));
+        let mut probes = Probes::new();
+        probes.add("center", Point::new(0.05, 0.55));
+
+        let mut columns = vec![LogColumn::Time, LogColumn::KineticEnergy, LogColumn::DensityIterations];
+        columns.extend(LogColumn::probe_columns(&probes));
+        let mut log = StepLog::new(CountingWriter::default(), columns);
+        log.flush_interval = 2;
+        for _ in 0..3 {
+            solver.simulation_step(&mut fluid_world, &mut time_manager).unwrap();
+            probes.record(&fluid_world, &solver, time_manager.passed_time());
+            log.log(&fluid_world, &solver, &time_manager, &probes).unwrap();
+        }
+        assert_eq!(log.num_rows(), 3);
+        assert_eq!(log.writer.num_flushes, 1);
+
+        let csv = String::from_utf8(log.writer.bytes.clone()).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            lines[0],
+            "time_s,kinetic_energy_J,density_iterations,center_density,center_velocity_x,center_velocity_y,center_pressure"
+        );
+        let last_row: Vec<&str> = lines[3].split(',').collect();
+        assert_eq!(last_row.len(), 7);
+        assert_eq!(last_row[0], time_manager.passed_time().to_string());
+        assert_gt!(last_row[1].parse::<Real>().unwrap(), 0.0);
+        // WCSPH doesn't iterate
+        assert_eq!(last_row[2], "");
+        assert_gt!(last_row[3].parse::<Real>().unwrap(), 0.0);
+    }
+}
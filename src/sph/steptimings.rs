@@ -0,0 +1,118 @@
+use std::time::Duration;
+
+// std::time::Instant panics in the browser, web_time measures with performance.now() there instead.
+#[cfg(not(target_arch = "wasm32"))]
+pub(super) use std::time::Instant;
+#[cfg(target_arch = "wasm32")]
+pub(super) use web_time::Instant;
+
+// Parts of a simulation step that are timed separately.
+// Not every solver has all of them as distinct passes, e.g. WCSPH computes viscosity together with pressure.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SimulationPass {
+    Neighborhood,
+    Density,
+    Pressure,
+    Viscosity,
+    Integration,
+}
+
+pub const NUM_SIMULATION_PASSES: usize = 5;
+
+impl SimulationPass {
+    pub const ALL: [SimulationPass; NUM_SIMULATION_PASSES] = [
+        SimulationPass::Neighborhood,
+        SimulationPass::Density,
+        SimulationPass::Pressure,
+        SimulationPass::Viscosity,
+        SimulationPass::Integration,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SimulationPass::Neighborhood => "neighborhood",
+            SimulationPass::Density => "density",
+            SimulationPass::Pressure => "pressure",
+            SimulationPass::Viscosity => "viscosity",
+            SimulationPass::Integration => "integration",
+        }
+    }
+}
+
+// Wall clock time spent in each pass.
+// Complements microprofile which needs an external viewer: these are cheap enough to always record.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StepTimings {
+    durations: [Duration; NUM_SIMULATION_PASSES],
+}
+
+impl StepTimings {
+    pub fn clear(&mut self) {
+        *self = Default::default();
+    }
+
+    pub fn add(&mut self, pass: SimulationPass, duration: Duration) {
+        self.durations[pass as usize] += duration;
+    }
+
+    // Adds up all passes of another set of timings, e.g. to sum up several steps.
+    pub fn accumulate(&mut self, other: &StepTimings) {
+        for (duration, other) in self.durations.iter_mut().zip(other.durations.iter()) {
+            *duration += *other;
+        }
+    }
+
+    pub fn get(&self, pass: SimulationPass) -> Duration {
+        self.durations[pass as usize]
+    }
+
+    pub fn total(&self) -> Duration {
+        self.durations.iter().sum()
+    }
+
+    pub fn csv_header() -> String {
+        SimulationPass::ALL
+            .iter()
+            .map(|pass| format!("{}_ms", pass.name()))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    // Durations of all passes in milliseconds, in the same order as csv_header.
+    pub fn csv_row(&self) -> String {
+        self.durations
+            .iter()
+            .map(|duration| format!("{:.4}", duration.as_secs_f64() * 1000.0))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accumulate_and_csv() {
+        let mut timings = StepTimings::default();
+        timings.add(SimulationPass::Density, Duration::from_millis(2));
+        timings.add(SimulationPass::Density, Duration::from_millis(1));
+        timings.add(SimulationPass::Integration, Duration::from_micros(500));
+
+        let mut sum = StepTimings::default();
+        sum.accumulate(&timings);
+        sum.accumulate(&timings);
+        assert_eq!(sum.get(SimulationPass::Density), Duration::from_millis(6));
+        assert_eq!(sum.get(SimulationPass::Pressure), Duration::from_millis(0));
+        assert_eq!(sum.total(), Duration::from_millis(7));
+
+        assert_eq!(
+            StepTimings::csv_header(),
+            "neighborhood_ms,density_ms,pressure_ms,viscosity_ms,integration_ms"
+        );
+        assert_eq!(timings.csv_row(), "0.0000,3.0000,0.0000,0.0000,0.5000");
+
+        sum.clear();
+        assert_eq!(sum.total(), Duration::from_millis(0));
+    }
+}
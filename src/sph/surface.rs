@@ -0,0 +1,180 @@
+use super::fluidparticleworld::FluidParticleWorld;
+use super::interpolation::{self, SampleGrid};
+use crate::units::*;
+
+// Piece of the reconstructed fluid surface.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LineSegment {
+    pub start: Point,
+    pub end: Point,
+}
+
+// Reconstructs the fluid surface as line segments using marching squares.
+//
+// Samples the SPH color field c(x) = Σ V_j W(x - x_j) on a regular grid (see interpolation) and extracts the iso line at the given threshold.
+// Inside resting fluid the color field is ~1, outside it is 0.
+// Only fluid particles contribute, so the surface also runs along walls where the fluid is in contact with boundaries.
+pub struct SurfaceExtractor {
+    pub cell_size: Real,
+    pub threshold: Real,
+
+    // color field at grid nodes, kept around to avoid reallocations
+    color_field: Vec<Real>,
+}
+
+impl SurfaceExtractor {
+    pub fn new(cell_size: Real) -> SurfaceExtractor {
+        SurfaceExtractor {
+            cell_size,
+            threshold: 0.5,
+            color_field: Vec::new(),
+        }
+    }
+
+    // Extracts the fluid surface within a region of the world.
+    pub fn extract(&mut self, fluid_world: &FluidParticleWorld, region: &Rect) -> Vec<LineSegment> {
+        microprofile::scope!("SurfaceExtractor", "extract");
+
+        let num_cells_x = (region.w as Real / self.cell_size).ceil().max(1.0) as usize;
+        let num_cells_y = (region.h as Real / self.cell_size).ceil().max(1.0) as usize;
+        let num_nodes_x = num_cells_x + 1;
+        let origin = Point::new(region.x as Real, region.y as Real);
+
+        self.update_color_field(fluid_world, origin, num_nodes_x, num_cells_y + 1);
+
+        let mut segments = Vec::new();
+        for y in 0..num_cells_y {
+            for x in 0..num_cells_x {
+                self.march_cell(origin, num_nodes_x, x, y, &mut segments);
+            }
+        }
+        segments
+    }
+
+    fn update_color_field(&mut self, fluid_world: &FluidParticleWorld, origin: Point, num_nodes_x: usize, num_nodes_y: usize) {
+        microprofile::scope!("SurfaceExtractor", "update_color_field");
+        // splatting particles to the grid, this way we don't depend on an up to date neighborhood datastructure
+        let grid = SampleGrid {
+            origin,
+            spacing: self.cell_size,
+            size: (num_nodes_x, num_nodes_y),
+        };
+        interpolation::splat_color_field_to_grid(fluid_world, &grid, &mut self.color_field);
+    }
+
+    fn node_position(&self, origin: Point, x: usize, y: usize) -> Point {
+        origin + Vector::new(x as Real * self.cell_size, y as Real * self.cell_size)
+    }
+
+    // Adds the segments of a single grid cell. Corners are numbered counter-clockwise starting bottom left.
+    fn march_cell(&self, origin: Point, num_nodes_x: usize, x: usize, y: usize, segments: &mut Vec<LineSegment>) {
+        let corner_nodes = [(x, y), (x + 1, y), (x + 1, y + 1), (x, y + 1)];
+        let mut values = [0.0; 4];
+        let mut positions = [origin; 4];
+        for (i, &(x, y)) in corner_nodes.iter().enumerate() {
+            values[i] = self.color_field[y * num_nodes_x + x];
+            positions[i] = self.node_position(origin, x, y);
+        }
+
+        let mut case = 0;
+        for (i, value) in values.iter().enumerate() {
+            if *value >= self.threshold {
+                case |= 1 << i;
+            }
+        }
+
+        // point on edge i, which goes from corner i to corner i+1
+        let edge_point = |edge: usize| {
+            let (a, b) = (edge, (edge + 1) % 4);
+            let t = (self.threshold - values[a]) / (values[b] - values[a]);
+            positions[a] + (positions[b] - positions[a]) * t
+        };
+        let mut add_segment = |edge_a: usize, edge_b: usize| {
+            segments.push(LineSegment {
+                start: edge_point(edge_a),
+                end: edge_point(edge_b),
+            })
+        };
+
+        match case {
+            0 | 15 => {}
+            1 | 14 => add_segment(3, 0),
+            2 | 13 => add_segment(0, 1),
+            3 | 12 => add_segment(3, 1),
+            4 | 11 => add_segment(1, 2),
+            6 | 9 => add_segment(0, 2),
+            7 | 8 => add_segment(3, 2),
+            // saddles, resolved by the average of all corners
+            5 | 10 => {
+                let center_inside = values.iter().sum::<Real>() * 0.25 >= self.threshold;
+                if (case == 5) == center_inside {
+                    add_segment(3, 2);
+                    add_segment(0, 1);
+                } else {
+                    add_segment(3, 0);
+                    add_segment(1, 2);
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+// Highest point where the surface crosses a vertical line at x, None if there is no crossing.
+// Useful for measuring wave heights.
+pub fn surface_height_at(segments: &[LineSegment], x: Real) -> Option<Real> {
+    segments
+        .iter()
+        .filter_map(|segment| {
+            let (min_x, max_x) = (segment.start.x.min(segment.end.x), segment.start.x.max(segment.end.x));
+            if x < min_x || x > max_x {
+                return None;
+            }
+            let dx = segment.end.x - segment.start.x;
+            if dx.abs() < 1.0e-10 {
+                Some(segment.start.y.max(segment.end.y))
+            } else {
+                Some(segment.start.y + (segment.end.y - segment.start.y) * (x - segment.start.x) / dx)
+            }
+        })
+        .fold(None, |height: Option<Real>, y| Some(height.map_or(y, |height| height.max(y))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_world_has_no_surface() {
+        let world = FluidParticleWorld::new(2.0, NumberDensity(10000.0), Density(1.0));
+        let mut extractor = SurfaceExtractor::new(world.properties.particle_radius());
+        assert!(extractor.extract(&world, &Rect::new(0.0, 0.0, 1.0, 1.0)).is_empty());
+    }
+
+    #[test]
+    fn fluid_rect_surface_height() {
+        let mut world = FluidParticleWorld::new(2.0, NumberDensity(10000.0), Density(1.0));
+        world.add_fluid_rect(&Rect::new(0.1, 0.1, 0.6, 0.3), 0.0);
+        let particle_spacing = world.properties.particle_radius() * 2.0;
+
+        let mut extractor = SurfaceExtractor::new(world.properties.particle_radius());
+        let segments = extractor.extract(&world, &Rect::new(0.0, 0.0, 1.0, 1.0));
+        assert!(!segments.is_empty());
+
+        // top most particle row is one spacing below the rect's top
+        let top_particle_row = 0.4 - particle_spacing;
+        let height = surface_height_at(&segments, 0.4).unwrap();
+        assert!(
+            (height - top_particle_row).abs() < particle_spacing,
+            "surface at {}, expected ~{}",
+            height,
+            top_particle_row
+        );
+
+        // closed contour, no surface outside of the fluid
+        assert_eq!(surface_height_at(&segments, 0.9), None);
+        assert!(segments
+            .iter()
+            .all(|s| s.start.y > 0.0 && s.start.y < 0.5 && s.end.x > 0.0 && s.end.x < 0.8));
+    }
+}
@@ -0,0 +1,645 @@
+use super::fluidparticleworld::FluidParticleWorld;
+use crate::units::*;
+use cgmath::prelude::*;
+use std::io;
+
+// Curves are approximated by straight segments of about this many particle spacings.
+// Shorter segments don't make a difference for the fluid but sample boundary particles more densely at every segment's start.
+const CURVE_SEGMENT_LENGTH_IN_PARTICLES: Real = 2.0;
+// Number of segments per curve when determining the drawing's bounds.
+const BOUNDS_CURVE_SEGMENTS: usize = 16;
+// Everything within these elements is not drawn directly.
+const SKIPPED_ELEMENTS: [&str; 6] = ["defs", "clipPath", "mask", "marker", "pattern", "symbol"];
+
+#[derive(Clone, Debug, PartialEq)]
+enum Segment {
+    Line(Point),
+    Cubic(Point, Point, Point), // two control points and end point
+    Arc {
+        center: Point,
+        radii: Vector,
+        rotation: Real, // of the x axis in radians
+        start_angle: Real,
+        delta_angle: Real,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Shape {
+    Path { start: Point, segments: Vec<Segment>, closed: bool },
+    Circle { center: Point, radius: Real },
+}
+
+// Boundaries from the shapes of an SVG file, so that geometry from vector graphics or CAD programs can be used as simulation domain.
+//
+// Supports path (including curves and arcs), polygon, polyline, line, rect and circle elements.
+// Closed shapes become boundary polygons, open ones boundary lines, circles boundary circles.
+// Only the geometry is read: styles don't matter, transform attributes are ignored and nothing is read from defs and similar elements.
+//
+// SVG's y axis points down, positions in the world are `origin + (x, -y) * scale`, i.e. the drawing appears upright.
+pub struct SvgBoundaries {
+    shapes: Vec<Shape>,
+    pub scale: Real,   // m per SVG user unit
+    pub origin: Point, // world position of the SVG's origin
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn rotate(v: Vector, angle: Real) -> Vector {
+    let (sin, cos) = angle.sin_cos();
+    Vector::new(v.x * cos - v.y * sin, v.x * sin + v.y * cos)
+}
+
+impl Segment {
+    // Length of the control polygon, an upper bound of the curve's length.
+    fn approximate_length(&self, start: Point) -> Real {
+        match self {
+            Segment::Line(end) => start.distance(*end),
+            Segment::Cubic(c1, c2, end) => start.distance(*c1) + c1.distance(*c2) + c2.distance(*end),
+            Segment::Arc { radii, delta_angle, .. } => radii.x.max(radii.y) * delta_angle.abs(),
+        }
+    }
+
+    fn point_at(&self, start: Point, t: Real) -> Point {
+        match self {
+            Segment::Line(end) => start + (end - start) * t,
+            Segment::Cubic(c1, c2, end) => {
+                let s = 1.0 - t;
+                Point::from_vec(
+                    start.to_vec() * (s * s * s) + c1.to_vec() * (3.0 * s * s * t) + c2.to_vec() * (3.0 * s * t * t) + end.to_vec() * (t * t * t),
+                )
+            }
+            Segment::Arc {
+                center,
+                radii,
+                rotation,
+                start_angle,
+                delta_angle,
+            } => {
+                let angle = start_angle + delta_angle * t;
+                center + rotate(Vector::new(radii.x * angle.cos(), radii.y * angle.sin()), *rotation)
+            }
+        }
+    }
+
+    fn end(&self, start: Point) -> Point {
+        match self {
+            Segment::Line(end) | Segment::Cubic(_, _, end) => *end,
+            Segment::Arc { .. } => self.point_at(start, 1.0),
+        }
+    }
+
+    // Appends points along the segment excluding the start point.
+    fn flatten(&self, start: Point, num_curve_segments: impl Fn(Real) -> usize, points: &mut Vec<Point>) {
+        let num_segments = match self {
+            Segment::Line(_) => 1,
+            _ => num_curve_segments(self.approximate_length(start)).max(1),
+        };
+        for i in 1..=num_segments {
+            points.push(self.point_at(start, i as Real / num_segments as Real));
+        }
+    }
+}
+
+// Arc from `start` to `end` in SVG's endpoint parameterization, see "Elliptical arc implementation notes" in the SVG specification.
+fn arc_segment(start: Point, radii: Vector, rotation_in_degrees: Real, large_arc: bool, sweep: bool, end: Point) -> Segment {
+    let (mut rx, mut ry) = (radii.x.abs(), radii.y.abs());
+    if rx == 0.0 || ry == 0.0 || start == end {
+        return Segment::Line(end);
+    }
+    let rotation = rotation_in_degrees.to_radians();
+    let p = rotate((start - end) * 0.5, -rotation);
+    // scale up radii that are too small to connect both points
+    let lambda = (p.x * p.x) / (rx * rx) + (p.y * p.y) / (ry * ry);
+    if lambda > 1.0 {
+        rx *= lambda.sqrt();
+        ry *= lambda.sqrt();
+    }
+    let numerator = rx * rx * ry * ry - rx * rx * p.y * p.y - ry * ry * p.x * p.x;
+    let denominator = rx * rx * p.y * p.y + ry * ry * p.x * p.x;
+    let sign = if large_arc == sweep { -1.0 } else { 1.0 };
+    let coefficient = sign * (numerator / denominator).max(0.0).sqrt();
+    let center_rotated = Vector::new(coefficient * rx * p.y / ry, -coefficient * ry * p.x / rx);
+    let center = Point::from_vec((start.to_vec() + end.to_vec()) * 0.5) + rotate(center_rotated, rotation);
+
+    let start_angle = ((p.y - center_rotated.y) / ry).atan2((p.x - center_rotated.x) / rx);
+    let end_angle = ((-p.y - center_rotated.y) / ry).atan2((-p.x - center_rotated.x) / rx);
+    let mut delta_angle = end_angle - start_angle;
+    if sweep && delta_angle < 0.0 {
+        delta_angle += 2.0 * std::f32::consts::PI;
+    } else if !sweep && delta_angle > 0.0 {
+        delta_angle -= 2.0 * std::f32::consts::PI;
+    }
+    Segment::Arc {
+        center,
+        radii: Vector::new(rx, ry),
+        rotation,
+        start_angle,
+        delta_angle,
+    }
+}
+
+// Reads numbers, flags and commands of path data and point lists.
+struct Tokenizer<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Tokenizer<'a> {
+    fn new(text: &'a str) -> Self {
+        Tokenizer {
+            bytes: text.as_bytes(),
+            position: 0,
+        }
+    }
+
+    fn skip_separators(&mut self) {
+        while self.position < self.bytes.len() && (self.bytes[self.position].is_ascii_whitespace() || self.bytes[self.position] == b',') {
+            self.position += 1;
+        }
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.skip_separators();
+        self.position >= self.bytes.len()
+    }
+
+    fn command(&mut self) -> Option<u8> {
+        self.skip_separators();
+        match self.bytes.get(self.position) {
+            Some(c) if c.is_ascii_alphabetic() => {
+                self.position += 1;
+                Some(*c)
+            }
+            _ => None,
+        }
+    }
+
+    // Numbers may follow each other without separators, e.g. "1-2" or "0.5.5".
+    fn number(&mut self) -> io::Result<Real> {
+        self.skip_separators();
+        let start = self.position;
+        let bytes = self.bytes;
+        let digits = |mut position: usize| {
+            while position < bytes.len() && bytes[position].is_ascii_digit() {
+                position += 1;
+            }
+            position
+        };
+        let mut end = start;
+        if end < bytes.len() && (bytes[end] == b'-' || bytes[end] == b'+') {
+            end += 1;
+        }
+        end = digits(end);
+        if end < bytes.len() && bytes[end] == b'.' {
+            end = digits(end + 1);
+        }
+        if end < bytes.len() && (bytes[end] == b'e' || bytes[end] == b'E') {
+            let mut exponent_end = end + 1;
+            if exponent_end < bytes.len() && (bytes[exponent_end] == b'-' || bytes[exponent_end] == b'+') {
+                exponent_end += 1;
+            }
+            let digits_end = digits(exponent_end);
+            if digits_end > exponent_end {
+                end = digits_end;
+            }
+        }
+        let text = std::str::from_utf8(&bytes[start..end]).unwrap_or("");
+        let number = text
+            .parse()
+            .map_err(|_| invalid_data(format!("expected number at \"{}\"", String::from_utf8_lossy(&bytes[start..]))))?;
+        self.position = end;
+        Ok(number)
+    }
+
+    // Arc flags are single digits that may be written without separators.
+    fn flag(&mut self) -> io::Result<bool> {
+        self.skip_separators();
+        match self.bytes.get(self.position) {
+            Some(b'0') | Some(b'1') => {
+                self.position += 1;
+                Ok(self.bytes[self.position - 1] == b'1')
+            }
+            _ => Err(invalid_data("expected arc flag".to_owned())),
+        }
+    }
+
+    fn point(&mut self) -> io::Result<Point> {
+        Ok(Point::new(self.number()?, self.number()?))
+    }
+}
+
+fn parse_points(text: &str, closed: bool) -> io::Result<Option<Shape>> {
+    let mut tokenizer = Tokenizer::new(text);
+    let mut points = Vec::new();
+    while !tokenizer.at_end() {
+        points.push(tokenizer.point()?);
+    }
+    if points.len() < 2 {
+        return Ok(None);
+    }
+    Ok(Some(Shape::Path {
+        start: points[0],
+        segments: points[1..].iter().map(|p| Segment::Line(*p)).collect(),
+        closed,
+    }))
+}
+
+fn parse_path_data(data: &str, shapes: &mut Vec<Shape>) -> io::Result<()> {
+    let mut tokenizer = Tokenizer::new(data);
+    let mut current = Point::new(0.0, 0.0);
+    let mut subpath_start = current;
+    let mut segments = Vec::new();
+    let mut last_command = None;
+    // control points for smooth continuations of curves
+    let mut last_cubic_control: Option<Point> = None;
+    let mut last_quadratic_control: Option<Point> = None;
+
+    let mut finish_subpath = |start: Point, segments: &mut Vec<Segment>, closed: bool| {
+        if !segments.is_empty() {
+            shapes.push(Shape::Path {
+                start,
+                segments: std::mem::take(segments),
+                closed,
+            });
+        }
+    };
+
+    while !tokenizer.at_end() {
+        let command = match tokenizer.command() {
+            Some(command) => command,
+            // repeated command, numbers following a move are lines
+            None => match last_command {
+                Some(b'M') => b'L',
+                Some(b'm') => b'l',
+                Some(command) if command != b'Z' && command != b'z' => command,
+                _ => return Err(invalid_data("path data doesn't start with a command".to_owned())),
+            },
+        };
+        let relative = command.is_ascii_lowercase();
+        let offset = if relative { current.to_vec() } else { Vector::zero() };
+        let cubic_control = last_cubic_control.take();
+        let quadratic_control = last_quadratic_control.take();
+
+        let segment = match command.to_ascii_uppercase() {
+            b'M' => {
+                finish_subpath(subpath_start, &mut segments, false);
+                current = tokenizer.point()? + offset;
+                subpath_start = current;
+                None
+            }
+            b'Z' => {
+                finish_subpath(subpath_start, &mut segments, true);
+                current = subpath_start;
+                None
+            }
+            b'L' => Some(Segment::Line(tokenizer.point()? + offset)),
+            b'H' => Some(Segment::Line(Point::new(tokenizer.number()? + offset.x, current.y))),
+            b'V' => Some(Segment::Line(Point::new(current.x, tokenizer.number()? + offset.y))),
+            b'C' | b'S' => {
+                let c1 = if command.eq_ignore_ascii_case(&b'C') {
+                    tokenizer.point()? + offset
+                } else {
+                    cubic_control.map_or(current, |c| current + (current - c))
+                };
+                let c2 = tokenizer.point()? + offset;
+                last_cubic_control = Some(c2);
+                Some(Segment::Cubic(c1, c2, tokenizer.point()? + offset))
+            }
+            b'Q' | b'T' => {
+                let control = if command.eq_ignore_ascii_case(&b'Q') {
+                    tokenizer.point()? + offset
+                } else {
+                    quadratic_control.map_or(current, |c| current + (current - c))
+                };
+                let end = tokenizer.point()? + offset;
+                last_quadratic_control = Some(control);
+                // exact conversion to a cubic curve
+                Some(Segment::Cubic(
+                    current + (control - current) * (2.0 / 3.0),
+                    end + (control - end) * (2.0 / 3.0),
+                    end,
+                ))
+            }
+            b'A' => {
+                let radii = Vector::new(tokenizer.number()?, tokenizer.number()?);
+                let rotation = tokenizer.number()?;
+                let large_arc = tokenizer.flag()?;
+                let sweep = tokenizer.flag()?;
+                Some(arc_segment(current, radii, rotation, large_arc, sweep, tokenizer.point()? + offset))
+            }
+            _ => return Err(invalid_data(format!("unknown path command '{}'", command as char))),
+        };
+        if let Some(segment) = segment {
+            // drawing right after closing a subpath continues from its start
+            if segments.is_empty() {
+                subpath_start = current;
+            }
+            current = segment.end(current);
+            segments.push(segment);
+        }
+        last_command = Some(command);
+    }
+    finish_subpath(subpath_start, &mut segments, false);
+    Ok(())
+}
+
+// Attributes of a start tag's content after the element name, e.g. ` d="M 0 0 L 1 1" fill='none'`.
+fn parse_attributes(text: &str) -> Vec<(&str, &str)> {
+    let mut attributes = Vec::new();
+    let mut rest = text;
+    while let Some(equals) = rest.find('=') {
+        let name = rest[..equals].trim();
+        let value = rest[equals + 1..].trim_start();
+        let quote = match value.chars().next() {
+            Some(quote) if quote == '"' || quote == '\'' => quote,
+            _ => break,
+        };
+        let value = &value[1..];
+        let end = match value.find(quote) {
+            Some(end) => end,
+            None => break,
+        };
+        attributes.push((name, &value[..end]));
+        rest = &value[end + 1..];
+    }
+    attributes
+}
+
+// Leading number of an attribute, ignoring units like "px".
+fn number_attribute(attributes: &[(&str, &str)], name: &str) -> io::Result<Real> {
+    match attributes.iter().find(|(n, _)| *n == name) {
+        Some((_, value)) => Tokenizer::new(value).number(),
+        None => Ok(0.0),
+    }
+}
+
+impl SvgBoundaries {
+    pub fn read(reader: &mut impl io::Read) -> io::Result<SvgBoundaries> {
+        let mut svg = String::new();
+        reader.read_to_string(&mut svg)?;
+        SvgBoundaries::parse(&svg)
+    }
+
+    // Shapes of an SVG document with a scale of one meter per user unit.
+    pub fn parse(svg: &str) -> io::Result<SvgBoundaries> {
+        let mut shapes = Vec::new();
+        let mut rest = svg;
+        let mut skipped_element: Option<&str> = None;
+        while let Some(tag_start) = rest.find('<') {
+            rest = &rest[tag_start + 1..];
+            if rest.starts_with("!--") {
+                rest = rest.find("-->").map_or("", |end| &rest[end + 3..]);
+                continue;
+            }
+            let tag_end = rest.find('>').ok_or_else(|| invalid_data("unterminated tag".to_owned()))?;
+            let is_empty_element = rest[..tag_end].ends_with('/');
+            let tag = rest[..tag_end].trim_end_matches('/');
+            rest = &rest[tag_end + 1..];
+
+            let name_end = tag.find(|c: char| c.is_whitespace()).unwrap_or(tag.len());
+            let (name, attributes) = (&tag[..name_end], &tag[name_end..]);
+            let is_end_tag = name.starts_with('/');
+            // namespace prefixes as in "svg:path"
+            let name = name.trim_start_matches('/').rsplit(':').next().unwrap_or(name);
+            if let Some(skipped) = skipped_element {
+                if is_end_tag && name == skipped {
+                    skipped_element = None;
+                }
+                continue;
+            }
+            if is_end_tag {
+                continue;
+            }
+            if !is_empty_element && SKIPPED_ELEMENTS.contains(&name) {
+                skipped_element = Some(name);
+                continue;
+            }
+
+            let attributes = parse_attributes(attributes);
+            let attribute = |name: &str| attributes.iter().find(|(n, _)| *n == name).map(|(_, value)| *value);
+            let number = |name: &str| number_attribute(&attributes, name);
+            match name {
+                "path" => parse_path_data(attribute("d").unwrap_or(""), &mut shapes)?,
+                "polygon" | "polyline" => {
+                    if let Some(shape) = parse_points(attribute("points").unwrap_or(""), name == "polygon")? {
+                        shapes.push(shape);
+                    }
+                }
+                "line" => shapes.push(Shape::Path {
+                    start: Point::new(number("x1")?, number("y1")?),
+                    segments: vec![Segment::Line(Point::new(number("x2")?, number("y2")?))],
+                    closed: false,
+                }),
+                "rect" => {
+                    let (x, y, w, h) = (number("x")?, number("y")?, number("width")?, number("height")?);
+                    shapes.push(Shape::Path {
+                        start: Point::new(x, y),
+                        segments: vec![
+                            Segment::Line(Point::new(x + w, y)),
+                            Segment::Line(Point::new(x + w, y + h)),
+                            Segment::Line(Point::new(x, y + h)),
+                        ],
+                        closed: true,
+                    });
+                }
+                "circle" => shapes.push(Shape::Circle {
+                    center: Point::new(number("cx")?, number("cy")?),
+                    radius: number("r")?,
+                }),
+                _ => {}
+            }
+        }
+
+        Ok(SvgBoundaries {
+            shapes,
+            scale: 1.0,
+            origin: Point::new(0.0, 0.0),
+        })
+    }
+
+    pub fn num_shapes(&self) -> usize {
+        self.shapes.len()
+    }
+
+    // Points along all paths in SVG units, curves split into the given number of segments depending on their length.
+    // Closed paths don't repeat their first point.
+    fn paths(&self, num_curve_segments: impl Fn(Real) -> usize) -> Vec<(Vec<Point>, bool)> {
+        let mut paths = Vec::new();
+        for shape in self.shapes.iter() {
+            if let Shape::Path { start, segments, closed } = shape {
+                let mut points = vec![*start];
+                for segment in segments.iter() {
+                    let segment_start = *points.last().unwrap();
+                    segment.flatten(segment_start, &num_curve_segments, &mut points);
+                }
+                if *closed && points.len() > 1 && points.last() == points.first() {
+                    points.pop();
+                }
+                paths.push((points, *closed));
+            }
+        }
+        paths
+    }
+
+    // Bounding rectangle of all shapes in SVG units, None if there are no shapes.
+    pub fn bounds(&self) -> Option<Rect> {
+        let mut points: Vec<Point> = self.paths(|_| BOUNDS_CURVE_SEGMENTS).into_iter().flat_map(|(points, _)| points).collect();
+        for shape in self.shapes.iter() {
+            if let Shape::Circle { center, radius } = shape {
+                points.push(center - Vector::new(*radius, *radius));
+                points.push(center + Vector::new(*radius, *radius));
+            }
+        }
+        if points.is_empty() {
+            return None;
+        }
+        let min = points.iter().fold(Point::new(Real::INFINITY, Real::INFINITY), |min, p| {
+            Point::new(min.x.min(p.x), min.y.min(p.y))
+        });
+        let max = points.iter().fold(Point::new(-Real::INFINITY, -Real::INFINITY), |max, p| {
+            Point::new(max.x.max(p.x), max.y.max(p.y))
+        });
+        Some(Rect::new(min.x, min.y, max.x - min.x, max.y - min.y))
+    }
+
+    // Sets scale and origin such that the drawing fits into a world rectangle, keeping its aspect ratio.
+    // The drawing's bottom left corner ends up at the rectangle's bottom left corner.
+    pub fn fit_to(&mut self, world_rect: &Rect) {
+        let bounds = match self.bounds() {
+            Some(bounds) => bounds,
+            None => return,
+        };
+        self.scale = (world_rect.w / bounds.w).min(world_rect.h / bounds.h);
+        self.origin = Point::new(world_rect.x - bounds.x * self.scale, world_rect.y + (bounds.y + bounds.h) * self.scale);
+    }
+
+    // World position of a point in SVG units.
+    pub fn to_world(&self, svg_position: Point) -> Point {
+        self.origin + Vector::new(svg_position.x, -svg_position.y) * self.scale
+    }
+
+    // Adds all shapes as static boundaries.
+    pub fn add_to(&self, fluid_world: &mut FluidParticleWorld) {
+        let spacing = fluid_world.properties.particle_radius() * 2.0;
+        let curve_segment_length = CURVE_SEGMENT_LENGTH_IN_PARTICLES * spacing / self.scale;
+        for (points, closed) in self.paths(|length| (length / curve_segment_length).round() as usize) {
+            let mut vertices: Vec<Point> = Vec::with_capacity(points.len());
+            for p in points.iter().map(|p| self.to_world(*p)) {
+                // zero length segments would place a boundary particle on top of another
+                if !matches!(vertices.last(), Some(last) if last.distance(p) < spacing * 1.0e-3) {
+                    vertices.push(p);
+                }
+            }
+            if closed && vertices.len() > 2 {
+                fluid_world.add_boundary_polygon(&vertices);
+            } else {
+                for segment in vertices.windows(2) {
+                    fluid_world.add_boundary_line(segment[0], segment[1]);
+                }
+            }
+        }
+        for shape in self.shapes.iter() {
+            if let Shape::Circle { center, radius } = shape {
+                fluid_world.add_boundary_circle(self.to_world(*center), radius * self.scale);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fluidparticleworld::BoundaryGeometry;
+    use super::*;
+
+    const SVG: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<svg xmlns="http://www.w3.org/2000/svg" width="200mm" height="100mm" viewBox="0 0 200 100">
+  <!-- <path d="M 0 0 L 1000 1000"/> -->
+  <defs><path id="unused" d="M 0 0 L 1000 1000"/></defs>
+  <path style="fill:none" d="M10,90 h180 v-80 M 20 20 l 10-10 10.5.5z"/>
+  <path d="M 100 50 A 20 20 0 1 0 140 50 Q 150 40 160 50 T 180 50" />
+  <polygon points="40,60 60,60 50,40"/>
+  <circle cx="150" cy="20" r="5px"/>
+</svg>"#;
+
+    #[test]
+    fn parse_shapes() {
+        let svg = SvgBoundaries::parse(SVG).unwrap();
+        // path with two subpaths
+        assert_eq!(svg.num_shapes(), 5);
+        let paths = svg.paths(|_| 8);
+        assert_eq!(
+            paths[0],
+            (vec![Point::new(10.0, 90.0), Point::new(190.0, 90.0), Point::new(190.0, 10.0)], false)
+        );
+        assert_eq!(
+            paths[1],
+            (vec![Point::new(20.0, 20.0), Point::new(30.0, 10.0), Point::new(40.5, 10.5)], true)
+        );
+        assert_eq!(paths[3].0.len(), 3);
+        assert!(paths[3].1);
+
+        // half circle, sweeping through the lower half in SVG coordinates (y pointing down)
+        let (arc_and_curves, closed) = &paths[2];
+        assert!(!closed);
+        assert_eq!(arc_and_curves.len(), 1 + 3 * 8);
+        let center = Point::new(120.0, 50.0);
+        for p in arc_and_curves[..9].iter() {
+            assert_lt!((p.distance(center) - 20.0).abs(), 1.0e-3);
+            assert_ge!(p.y, 50.0 - 1.0e-3);
+        }
+        assert_lt!(arc_and_curves[4].distance(Point::new(120.0, 70.0)), 1.0e-3);
+        // smooth quadratic continuation mirrors the control point
+        assert_lt!(arc_and_curves[12].distance(Point::new(150.0, 45.0)), 1.0e-3);
+        assert_lt!(arc_and_curves[20].distance(Point::new(170.0, 55.0)), 1.0e-3);
+        assert_eq!(*arc_and_curves.last().unwrap(), Point::new(180.0, 50.0));
+
+        let bounds = svg.bounds().unwrap();
+        assert_lt!((bounds.x - 10.0).abs(), 1.0e-3);
+        assert_lt!((bounds.y - 10.0).abs(), 1.0e-3);
+        assert_lt!((bounds.w - 180.0).abs(), 1.0e-3);
+        assert_lt!((bounds.h - 80.0).abs(), 1.0e-3);
+
+        assert!(SvgBoundaries::parse("<svg><path d=\"M 0 0 L 1 x\"/></svg>").is_err());
+        assert!(SvgBoundaries::parse("<svg><path d=\"10 10\"/></svg>").is_err());
+    }
+
+    #[test]
+    fn shapes_become_boundaries() {
+        let mut svg = SvgBoundaries::parse(SVG).unwrap();
+        svg.fit_to(&Rect::new(1.0, 2.0, 0.9, 1.0));
+        assert_lt!((svg.scale - 0.005).abs(), 1.0e-6);
+        // bottom left of the drawing is the lower end of the first path's vertical line
+        assert_lt!(svg.to_world(Point::new(10.0, 90.0)).distance(Point::new(1.0, 2.0)), 1.0e-5);
+        assert_lt!(svg.to_world(Point::new(190.0, 10.0)).distance(Point::new(1.9, 2.4)), 1.0e-5);
+
+        let mut fluid_world = FluidParticleWorld::new(2.0, NumberDensity(10000.0), Density(1000.0));
+        svg.add_to(&mut fluid_world);
+        let geometry = fluid_world.boundary_geometry();
+        // open paths are split into lines
+        assert_eq!(geometry.iter().filter(|g| matches!(g, BoundaryGeometry::Polygon { .. })).count(), 2);
+        assert_eq!(geometry.iter().filter(|g| matches!(g, BoundaryGeometry::Circle { .. })).count(), 1);
+        if let BoundaryGeometry::Circle { center, radius, .. } = geometry.last().unwrap() {
+            assert_lt!(center.distance(Point::new(1.7, 2.35)), 1.0e-5);
+            assert_lt!((radius - 0.025).abs(), 1.0e-6);
+        }
+
+        // curves are split into segments of a few particles, so there are about as many boundary particles as the length of all lines
+        let spacing = fluid_world.properties.particle_radius() * 2.0;
+        let paths = svg.paths(|_| 64);
+        let mut length = 0.0;
+        for (points, closed) in paths.iter() {
+            let points: Vec<Point> = points.iter().map(|p| svg.to_world(*p)).collect();
+            length += points.windows(2).map(|s| s[0].distance(s[1])).sum::<Real>();
+            if *closed {
+                length += points[0].distance(*points.last().unwrap());
+            }
+        }
+        length += 2.0 * std::f32::consts::PI * 0.025;
+        let num_boundary_particles = fluid_world.particles.boundary_particles.len() as Real;
+        assert_gt!(num_boundary_particles, length / spacing);
+        assert_lt!(num_boundary_particles, length / spacing * 1.25);
+    }
+}
@@ -0,0 +1,111 @@
+use super::error::SphError;
+use super::fluidparticleworld::FluidParticleWorld;
+use super::simulationclock::SimulationClock;
+use super::watchdog::Instability;
+use crate::units::*;
+use cgmath::prelude::*;
+use std::fmt;
+
+// Fluid at rest: kinetic energy below a threshold.
+#[derive(Clone, Copy, Debug)]
+pub struct RestCriterion {
+    pub max_kinetic_energy: Real, // in J
+    // Simulation time before which rest isn't checked, for scenes that start at rest like a dam break before the fluid got going.
+    pub not_before: Real, // in s
+}
+
+// When Simulation::run_until stops a run without a viewer, so batch sweeps don't need to guess step counts.
+// Criteria that are None never apply, an alarm of the solver's watchdog always ends a run since the solver doesn't step anymore.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TerminationCriteria {
+    pub max_time: Option<Real>, // in s of simulation time
+    pub rest: Option<RestCriterion>,
+    pub max_steps: Option<u64>, // steps of a single run, safety net for runs whose timestep collapsed
+}
+
+#[derive(Clone, Debug)]
+pub enum TerminationReason {
+    AtRest,
+    TimeLimit,
+    StepLimit,
+    Instability(Instability),
+    Error(SphError), // the solver failed, see Solver::simulation_step
+}
+
+impl TerminationReason {
+    // Process exit code for scripts: 0 if the run finished as planned, 1 if it blew up and 2 if it ran out of steps.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            TerminationReason::AtRest | TerminationReason::TimeLimit => 0,
+            TerminationReason::Instability(_) | TerminationReason::Error(_) => 1,
+            TerminationReason::StepLimit => 2,
+        }
+    }
+}
+
+impl fmt::Display for TerminationReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TerminationReason::AtRest => write!(f, "fluid at rest"),
+            TerminationReason::TimeLimit => write!(f, "time limit reached"),
+            TerminationReason::StepLimit => write!(f, "step limit reached"),
+            TerminationReason::Instability(instability) => write!(f, "halted by watchdog, {}", instability),
+            TerminationReason::Error(err) => write!(f, "solver failed, {}", err),
+        }
+    }
+}
+
+// Outcome of Simulation::run_until.
+#[derive(Clone, Debug)]
+pub struct RunSummary {
+    pub reason: TerminationReason,
+    pub clock: SimulationClock, // of the whole simulation, not just this run
+    pub num_fluid_particles: usize,
+    pub kinetic_energy: Real, // in J, at the end of the run
+}
+
+impl RunSummary {
+    pub fn exit_code(&self) -> i32 {
+        self.reason.exit_code()
+    }
+}
+
+impl fmt::Display for RunSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: {}, {} fluid particles, kinetic energy {:.6}J",
+            self.reason, self.clock, self.num_fluid_particles, self.kinetic_energy
+        )
+    }
+}
+
+impl TerminationCriteria {
+    // Reason to stop after the given state, checked in the order of TerminationReason.
+    // `num_steps` counts the steps of the current run only.
+    pub fn check(&self, passed_time: Real, num_steps: u64, kinetic_energy: Real) -> Option<TerminationReason> {
+        if let Some(rest) = self.rest {
+            if passed_time >= rest.not_before && kinetic_energy < rest.max_kinetic_energy {
+                return Some(TerminationReason::AtRest);
+            }
+        }
+        if matches!(self.max_time, Some(max_time) if passed_time >= max_time) {
+            return Some(TerminationReason::TimeLimit);
+        }
+        if matches!(self.max_steps, Some(max_steps) if num_steps >= max_steps) {
+            return Some(TerminationReason::StepLimit);
+        }
+        None
+    }
+}
+
+// Σ ½ m v² of all fluid particles, in J. Same as SimulationStatistics::kinetic_energy without gathering everything else.
+pub(super) fn kinetic_energy(fluid_world: &FluidParticleWorld) -> Real {
+    let particles = &fluid_world.particles;
+    particles
+        .velocities
+        .iter()
+        .zip(particles.masses.iter())
+        .map(|(v, mass)| 0.5 * mass * v.magnitude2())
+        .sum()
+}
@@ -0,0 +1,86 @@
+use std::sync::Arc;
+
+// Threads the passes of a simulation step are parallelized on, see Solver::set_threading.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Threading {
+    // rayon's global thread pool, i.e. one thread per logical core unless limited via the RAYON_NUM_THREADS environment variable.
+    #[default]
+    AllCores,
+    // A dedicated pool with this many threads, e.g. to leave cores to other applications.
+    Threads(usize),
+    // Everything runs on a single thread, e.g. for profiling or comparing against parallel execution.
+    Serial,
+}
+
+// Thread pool for a Threading, owned by a solver.
+// Cheap to clone, clones share the pool.
+#[derive(Clone, Default)]
+pub struct SolverThreadPool {
+    threading: Threading,
+    pool: Option<Arc<rayon::ThreadPool>>, // None for Threading::AllCores
+}
+
+impl SolverThreadPool {
+    pub fn new(threading: Threading) -> Result<SolverThreadPool, rayon::ThreadPoolBuildError> {
+        let num_threads = match threading {
+            Threading::AllCores => None,
+            Threading::Threads(num_threads) => Some(num_threads.max(1)),
+            Threading::Serial => Some(1),
+        };
+        let pool = match num_threads {
+            Some(num_threads) => Some(Arc::new(
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(num_threads)
+                    .thread_name(|i| format!("sph solver {}", i))
+                    .build()?,
+            )),
+            None => None,
+        };
+        Ok(SolverThreadPool { threading, pool })
+    }
+
+    pub fn threading(&self) -> Threading {
+        self.threading
+    }
+
+    // Number of threads parallel iterators within install are spread across.
+    pub fn num_threads(&self) -> usize {
+        match &self.pool {
+            Some(pool) => pool.current_num_threads(),
+            None => rayon::current_num_threads(),
+        }
+    }
+
+    // Runs op with all parallel iterators in it using this pool's threads. op itself runs on one of them.
+    pub fn install<R: Send>(&self, op: impl FnOnce() -> R + Send) -> R {
+        match &self.pool {
+            Some(pool) => pool.install(op),
+            None => op(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rayon::prelude::*;
+
+    #[test]
+    fn threads_of_pool() {
+        let serial = SolverThreadPool::new(Threading::Serial).unwrap();
+        assert_eq!(serial.num_threads(), 1);
+        let caller = std::thread::current().id();
+        let thread_ids: Vec<std::thread::ThreadId> = serial.install(|| (0..1000).into_par_iter().map(|_| std::thread::current().id()).collect());
+        assert!(thread_ids.iter().all(|id| *id == thread_ids[0] && *id != caller));
+
+        let two_threads = SolverThreadPool::new(Threading::Threads(2)).unwrap();
+        assert_eq!(two_threads.threading(), Threading::Threads(2));
+        assert_eq!(two_threads.install(rayon::current_num_threads), 2);
+        assert_eq!(two_threads.clone().num_threads(), 2);
+
+        let all_cores = SolverThreadPool::default();
+        assert_eq!(all_cores.threading(), Threading::AllCores);
+        assert_eq!(all_cores.num_threads(), rayon::current_num_threads());
+        assert_eq!(all_cores.install(|| std::thread::current().id()), caller);
+    }
+}
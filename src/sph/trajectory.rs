@@ -0,0 +1,426 @@
+use super::error::check_length;
+use super::fluidparticleworld::FluidParticleWorld;
+use crate::units::*;
+use half::f16;
+use std::io;
+
+// Binary recording of fluid particle positions and velocities over time, e.g. to replay or compare simulation runs.
+//
+// A header with the encoding is followed by one frame per recorded point in time, each with its own particle count:
+//   header: "YSPHTRAJ", version u32, encoding u8, compression u8, 2 reserved bytes, domain min & max as 4 f32 (zero unless quantized)
+//   frame:  time f32, number of particles u32, payload size u32, payload (all positions, then all velocities, possibly compressed)
+// All numbers are little endian. Frames are compressed individually, so recordings can be written and read as a stream.
+//
+// Full precision takes 16 bytes per particle and frame, i.e. 100k particles recorded at 60Hz fill almost 6GB per minute.
+// Quantized encodings halve that, zstd compression (needs the "compression" feature) typically halves it again.
+
+const MAGIC: &[u8; 8] = b"YSPHTRAJ";
+const VERSION: u32 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TrajectoryEncoding {
+    // f32 for everything, lossless.
+    Full,
+    // f16 for everything, 11 significant bits: about 1mm resolution for positions around 1m.
+    Half,
+    // Positions as 16 bit fixed point within the given domain, velocities as f16.
+    // Resolution is the domain size / 65535 everywhere, e.g. 30µm for a 2m tank. Positions outside the domain are clamped to it.
+    Quantized { min: Point, max: Point },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TrajectoryCompression {
+    None,
+    // zstd with the given level (1 to 22, 3 is zstd's default).
+    #[cfg(feature = "compression")]
+    Zstd(i32),
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TrajectoryFrame {
+    pub time: Real,
+    pub positions: Vec<Point>,
+    pub velocities: Vec<Vector>,
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl TrajectoryEncoding {
+    fn tag(&self) -> u8 {
+        match self {
+            TrajectoryEncoding::Full => 0,
+            TrajectoryEncoding::Half => 1,
+            TrajectoryEncoding::Quantized { .. } => 2,
+        }
+    }
+
+    fn bytes_per_component(&self) -> usize {
+        match self {
+            TrajectoryEncoding::Full => 4,
+            _ => 2,
+        }
+    }
+
+    fn encode_position(&self, position: Point, bytes: &mut Vec<u8>) {
+        match self {
+            TrajectoryEncoding::Full => {
+                bytes.extend_from_slice(&position.x.to_le_bytes());
+                bytes.extend_from_slice(&position.y.to_le_bytes());
+            }
+            TrajectoryEncoding::Half => {
+                bytes.extend_from_slice(&f16::from_f32(position.x).to_le_bytes());
+                bytes.extend_from_slice(&f16::from_f32(position.y).to_le_bytes());
+            }
+            TrajectoryEncoding::Quantized { min, max } => {
+                let quantize = |value: Real, min: Real, max: Real| {
+                    let relative = ((value - min) / (max - min)).clamp(0.0, 1.0);
+                    ((relative * u16::MAX as Real).round() as u16).to_le_bytes()
+                };
+                bytes.extend_from_slice(&quantize(position.x, min.x, max.x));
+                bytes.extend_from_slice(&quantize(position.y, min.y, max.y));
+            }
+        }
+    }
+
+    fn encode_velocity(&self, velocity: Vector, bytes: &mut Vec<u8>) {
+        match self {
+            TrajectoryEncoding::Full => {
+                bytes.extend_from_slice(&velocity.x.to_le_bytes());
+                bytes.extend_from_slice(&velocity.y.to_le_bytes());
+            }
+            _ => {
+                bytes.extend_from_slice(&f16::from_f32(velocity.x).to_le_bytes());
+                bytes.extend_from_slice(&f16::from_f32(velocity.y).to_le_bytes());
+            }
+        }
+    }
+
+    // Decodes the two components of a position or velocity.
+    fn decode(&self, bytes: &[u8], position: bool) -> (Real, Real) {
+        let component = |i: usize| match self {
+            TrajectoryEncoding::Full => f32::from_le_bytes([bytes[i * 4], bytes[i * 4 + 1], bytes[i * 4 + 2], bytes[i * 4 + 3]]),
+            TrajectoryEncoding::Quantized { min, max } if position => {
+                let (min, max) = if i == 0 { (min.x, max.x) } else { (min.y, max.y) };
+                min + u16::from_le_bytes([bytes[i * 2], bytes[i * 2 + 1]]) as Real / u16::MAX as Real * (max - min)
+            }
+            _ => f16::from_le_bytes([bytes[i * 2], bytes[i * 2 + 1]]).to_f32(),
+        };
+        (component(0), component(1))
+    }
+}
+
+// Writes frames to a trajectory recording, see TrajectoryEncoding.
+pub struct TrajectoryWriter<W: io::Write> {
+    writer: W,
+    encoding: TrajectoryEncoding,
+    compression: TrajectoryCompression,
+    payload: Vec<u8>, // reused between frames
+}
+
+impl<W: io::Write> TrajectoryWriter<W> {
+    // Writes the header.
+    pub fn new(mut writer: W, encoding: TrajectoryEncoding, compression: TrajectoryCompression) -> io::Result<TrajectoryWriter<W>> {
+        let compression_tag: u8 = match compression {
+            TrajectoryCompression::None => 0,
+            #[cfg(feature = "compression")]
+            TrajectoryCompression::Zstd(_) => 1,
+        };
+        let (min, max) = match encoding {
+            TrajectoryEncoding::Quantized { min, max } => {
+                if !(min.x < max.x && min.y < max.y) {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, "quantization domain is empty"));
+                }
+                (min, max)
+            }
+            _ => (Point::new(0.0, 0.0), Point::new(0.0, 0.0)),
+        };
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&[encoding.tag(), compression_tag, 0, 0])?;
+        for value in [min.x, min.y, max.x, max.y].iter() {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        Ok(TrajectoryWriter {
+            writer,
+            encoding,
+            compression,
+            payload: Vec::new(),
+        })
+    }
+
+    pub fn write_frame(&mut self, time: Real, positions: &[Point], velocities: &[Vector]) -> io::Result<()> {
+        microprofile::scope!("TrajectoryWriter", "write_frame");
+        check_length("velocities", positions.len(), velocities.len())?;
+
+        self.payload.clear();
+        for position in positions {
+            self.encoding.encode_position(*position, &mut self.payload);
+        }
+        for velocity in velocities {
+            self.encoding.encode_velocity(*velocity, &mut self.payload);
+        }
+        let payload = match self.compression {
+            TrajectoryCompression::None => std::borrow::Cow::Borrowed(&self.payload),
+            #[cfg(feature = "compression")]
+            TrajectoryCompression::Zstd(level) => std::borrow::Cow::Owned(zstd::stream::encode_all(&self.payload[..], level)?),
+        };
+
+        self.writer.write_all(&time.to_le_bytes())?;
+        self.writer.write_all(&(positions.len() as u32).to_le_bytes())?;
+        self.writer.write_all(&(payload.len() as u32).to_le_bytes())?;
+        self.writer.write_all(&payload)
+    }
+
+    // Records the fluid particles of a world.
+    pub fn record(&mut self, fluid_world: &FluidParticleWorld, time: Real) -> io::Result<()> {
+        self.write_frame(time, &fluid_world.particles.positions, &fluid_world.particles.velocities)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+// Reads the frames of a trajectory recording one by one, see TrajectoryWriter.
+pub struct TrajectoryReader<R: io::Read> {
+    reader: R,
+    encoding: TrajectoryEncoding,
+    compressed: bool,
+    payload: Vec<u8>, // reused between frames
+}
+
+impl<R: io::Read> TrajectoryReader<R> {
+    // Reads the header.
+    pub fn new(mut reader: R) -> io::Result<TrajectoryReader<R>> {
+        let mut header = [0_u8; 32];
+        reader.read_exact(&mut header)?;
+        if &header[0..8] != MAGIC {
+            return Err(invalid_data("not a trajectory recording".to_string()));
+        }
+        let version = u32::from_le_bytes([header[8], header[9], header[10], header[11]]);
+        if version != VERSION {
+            return Err(invalid_data(format!("unsupported trajectory version {}", version)));
+        }
+        let float = |offset: usize| f32::from_le_bytes([header[offset], header[offset + 1], header[offset + 2], header[offset + 3]]);
+        let encoding = match header[12] {
+            0 => TrajectoryEncoding::Full,
+            1 => TrajectoryEncoding::Half,
+            2 => TrajectoryEncoding::Quantized {
+                min: Point::new(float(16), float(20)),
+                max: Point::new(float(24), float(28)),
+            },
+            tag => return Err(invalid_data(format!("unknown trajectory encoding {}", tag))),
+        };
+        let compressed = match header[13] {
+            0 => false,
+            1 if cfg!(feature = "compression") => true,
+            1 => {
+                return Err(invalid_data(
+                    "trajectory is zstd compressed, which needs the \"compression\" feature".to_string(),
+                ))
+            }
+            tag => return Err(invalid_data(format!("unknown trajectory compression {}", tag))),
+        };
+        Ok(TrajectoryReader {
+            reader,
+            encoding,
+            compressed,
+            payload: Vec::new(),
+        })
+    }
+
+    pub fn encoding(&self) -> TrajectoryEncoding {
+        self.encoding
+    }
+
+    // Next frame, None at the end of the recording.
+    pub fn read_frame(&mut self) -> io::Result<Option<TrajectoryFrame>> {
+        // only a recording ending right before a frame ends cleanly, a partial frame header means the file is truncated
+        let mut frame_header = [0_u8; 12];
+        let mut num_header_bytes = 0;
+        while num_header_bytes < frame_header.len() {
+            match self.reader.read(&mut frame_header[num_header_bytes..]) {
+                Ok(0) if num_header_bytes == 0 => return Ok(None),
+                Ok(0) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        format!("frame header is truncated after {} bytes", num_header_bytes),
+                    ))
+                }
+                Ok(num_bytes) => num_header_bytes += num_bytes,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        let word = |i: usize| {
+            [
+                frame_header[i * 4],
+                frame_header[i * 4 + 1],
+                frame_header[i * 4 + 2],
+                frame_header[i * 4 + 3],
+            ]
+        };
+        let time = f32::from_le_bytes(word(0));
+        let num_particles = u32::from_le_bytes(word(1)) as usize;
+        let payload_size = u32::from_le_bytes(word(2)) as usize;
+        let bytes_per_vector = 2 * self.encoding.bytes_per_component();
+        let decoded_size = num_particles * bytes_per_vector * 2;
+        // don't allocate whatever a corrupted header claims
+        if !self.compressed && payload_size != decoded_size {
+            return Err(invalid_data(format!(
+                "frame at {}s has {} bytes for {} particles, expected {}",
+                time, payload_size, num_particles, decoded_size
+            )));
+        }
+
+        self.payload.resize(payload_size, 0);
+        self.reader.read_exact(&mut self.payload)?;
+        #[cfg(feature = "compression")]
+        {
+            if self.compressed {
+                self.payload = zstd::stream::decode_all(&self.payload[..])?;
+            }
+        }
+        debug_assert!(cfg!(feature = "compression") || !self.compressed);
+
+        if self.payload.len() != decoded_size {
+            return Err(invalid_data(format!("frame at {}s is truncated", time)));
+        }
+        let (position_bytes, velocity_bytes) = self.payload.split_at(num_particles * bytes_per_vector);
+        let encoding = self.encoding;
+        Ok(Some(TrajectoryFrame {
+            time,
+            positions: position_bytes
+                .chunks_exact(bytes_per_vector)
+                .map(|bytes| {
+                    let (x, y) = encoding.decode(bytes, true);
+                    Point::new(x, y)
+                })
+                .collect(),
+            velocities: velocity_bytes
+                .chunks_exact(bytes_per_vector)
+                .map(|bytes| {
+                    let (x, y) = encoding.decode(bytes, false);
+                    Vector::new(x, y)
+                })
+                .collect(),
+        }))
+    }
+}
+
+impl<R: io::Read> Iterator for TrajectoryReader<R> {
+    type Item = io::Result<TrajectoryFrame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_frame().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::prelude::*;
+
+    fn record(encoding: TrajectoryEncoding, compression: TrajectoryCompression) -> (Vec<TrajectoryFrame>, Vec<u8>) {
+        let frames: Vec<TrajectoryFrame> = (0..3)
+            .map(|frame| TrajectoryFrame {
+                time: frame as Real * 0.1,
+                positions: (0..100 + frame)
+                    .map(|i| Point::new(i as Real * 0.0137, 1.5 - i as Real * 0.0071))
+                    .collect(),
+                velocities: (0..100 + frame).map(|i| Vector::new(-2.0 + i as Real * 0.031, 0.5)).collect(),
+            })
+            .collect();
+        let mut writer = TrajectoryWriter::new(Vec::new(), encoding, compression).unwrap();
+        for frame in frames.iter() {
+            writer.write_frame(frame.time, &frame.positions, &frame.velocities).unwrap();
+        }
+        (frames, writer.writer)
+    }
+
+    fn max_errors(recorded: &[TrajectoryFrame], bytes: &[u8]) -> (Real, Real) {
+        let read: Vec<TrajectoryFrame> = TrajectoryReader::new(bytes).unwrap().collect::<io::Result<_>>().unwrap();
+        assert_eq!(read.len(), recorded.len());
+        let mut max_position_error: Real = 0.0;
+        let mut max_velocity_error: Real = 0.0;
+        for (read, recorded) in read.iter().zip(recorded.iter()) {
+            assert_eq!(read.time, recorded.time);
+            assert_eq!(read.positions.len(), recorded.positions.len());
+            for (a, b) in read.positions.iter().zip(recorded.positions.iter()) {
+                max_position_error = max_position_error.max(a.distance(*b));
+            }
+            for (a, b) in read.velocities.iter().zip(recorded.velocities.iter()) {
+                max_velocity_error = max_velocity_error.max((a - b).magnitude());
+            }
+        }
+        (max_position_error, max_velocity_error)
+    }
+
+    #[test]
+    fn encodings_roundtrip_within_their_resolution() {
+        let (frames, full) = record(TrajectoryEncoding::Full, TrajectoryCompression::None);
+        assert_eq!(max_errors(&frames, &full), (0.0, 0.0));
+
+        let (_, half) = record(TrajectoryEncoding::Half, TrajectoryCompression::None);
+        let (position_error, velocity_error) = max_errors(&frames, &half);
+        assert_lt!(position_error, 1.0e-3);
+        assert_lt!(velocity_error, 2.0e-3);
+
+        let domain = TrajectoryEncoding::Quantized {
+            min: Point::new(0.0, 0.0),
+            max: Point::new(2.0, 2.0),
+        };
+        let (_, quantized) = record(domain, TrajectoryCompression::None);
+        let (position_error, velocity_error) = max_errors(&frames, &quantized);
+        assert_lt!(position_error, 3.0e-5);
+        assert_lt!(velocity_error, 2.0e-3);
+
+        // header and frame headers aside, half the size
+        let overhead = 32 + 3 * 12;
+        assert_eq!((full.len() - overhead) / 2, half.len() - overhead);
+        assert_eq!(half.len(), quantized.len());
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn compressed_roundtrip() {
+        let domain = TrajectoryEncoding::Quantized {
+            min: Point::new(0.0, 0.0),
+            max: Point::new(2.0, 2.0),
+        };
+        let (frames, uncompressed) = record(domain, TrajectoryCompression::None);
+        let (_, compressed) = record(domain, TrajectoryCompression::Zstd(3));
+        assert_lt!(compressed.len(), uncompressed.len());
+        assert_eq!(max_errors(&frames, &compressed), max_errors(&frames, &uncompressed));
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(TrajectoryReader::new(&b"P3\n2 2\n255\n0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0"[..]).is_err());
+        let (_, mut bytes) = record(TrajectoryEncoding::Full, TrajectoryCompression::None);
+        bytes.truncate(bytes.len() - 1);
+        let frames: Vec<io::Result<TrajectoryFrame>> = TrajectoryReader::new(&bytes[..]).unwrap().collect();
+        assert_eq!(frames.len(), 3);
+        assert!(frames[2].is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_or_corrupted_frame_headers() {
+        let (_, bytes) = record(TrajectoryEncoding::Full, TrajectoryCompression::None);
+        let first_frame_end = 32 + 12 + 100 * 16;
+
+        // end of file within the header of the second frame
+        let frames: Vec<io::Result<TrajectoryFrame>> = TrajectoryReader::new(&bytes[..first_frame_end + 5]).unwrap().collect();
+        assert_eq!(frames.len(), 2);
+        assert!(frames[0].is_ok());
+        assert_eq!(frames[1].as_ref().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+
+        // payload size that doesn't match the particle count
+        let mut corrupted = bytes.clone();
+        corrupted[32 + 8..32 + 12].copy_from_slice(&u32::MAX.to_le_bytes());
+        let frame = TrajectoryReader::new(&corrupted[..]).unwrap().read_frame();
+        assert_eq!(frame.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,284 @@
+use super::trajectory::{TrajectoryFrame, TrajectoryReader};
+use crate::units::*;
+use cgmath::prelude::*;
+use std::collections::HashMap;
+use std::io;
+
+// How particles of two frames are paired up.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParticleMatching {
+    // Same index in both frames. Only meaningful as long as both runs order their particles the same way,
+    // which deterministic runs do until they diverge - from then on differences grow quickly.
+    ByIndex,
+    // Every particle with the closest particle of the other frame, in both directions. Doesn't depend on particle order,
+    // but stays around the particle spacing even for runs that diverged completely, i.e. compares the shape of the fluid.
+    Nearest,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TrajectoryDiffOptions {
+    pub matching: ParticleMatching,
+    // Frames are paired by time, frames of either recording without a counterpart within this tolerance are skipped.
+    pub time_tolerance: Real, // in s
+    // Runs count as diverged from the first frame on whose rms distance exceeds this or whose particle counts differ.
+    pub divergence_threshold: Real, // in m
+}
+
+impl Default for TrajectoryDiffOptions {
+    fn default() -> Self {
+        TrajectoryDiffOptions {
+            matching: ParticleMatching::ByIndex,
+            time_tolerance: 1.0e-5,
+            divergence_threshold: 1.0e-3,
+        }
+    }
+}
+
+// Position difference of a pair of frames.
+#[derive(Clone, Copy, Debug)]
+pub struct FrameDifference {
+    pub time: Real, // of the first recording
+    pub num_particles: (usize, usize),
+    pub rms_distance: Real, // in m, L2 norm of all position differences divided by sqrt of the number of pairs
+    pub max_distance: Real, // in m
+}
+
+impl FrameDifference {
+    // Compares the positions of two frames. With ByIndex and different particle counts, only the particles both frames have are compared.
+    pub fn between(a: &TrajectoryFrame, b: &TrajectoryFrame, matching: ParticleMatching) -> FrameDifference {
+        microprofile::scope!("FrameDifference", "between");
+
+        let distances: Vec<Real> = match matching {
+            ParticleMatching::ByIndex => a.positions.iter().zip(b.positions.iter()).map(|(a, b)| a.distance(*b)).collect(),
+            ParticleMatching::Nearest => {
+                let grid_b = NearestGrid::new(&b.positions);
+                let grid_a = NearestGrid::new(&a.positions);
+                let a_to_b = a.positions.iter().filter_map(|p| grid_b.nearest_distance(*p));
+                let b_to_a = b.positions.iter().filter_map(|p| grid_a.nearest_distance(*p));
+                a_to_b.chain(b_to_a).collect()
+            }
+        };
+        let rms_distance = if distances.is_empty() {
+            0.0
+        } else {
+            (distances.iter().map(|d| d * d).sum::<Real>() / distances.len() as Real).sqrt()
+        };
+        FrameDifference {
+            time: a.time,
+            num_particles: (a.positions.len(), b.positions.len()),
+            rms_distance,
+            max_distance: distances.iter().cloned().fold(0.0, Real::max),
+        }
+    }
+
+    pub fn diverged(&self, threshold: Real) -> bool {
+        // NaN distances of blown up runs count as diverged
+        self.num_particles.0 != self.num_particles.1 || self.rms_distance > threshold || self.rms_distance.is_nan()
+    }
+}
+
+// Result of diff_trajectories.
+#[derive(Clone, Debug, Default)]
+pub struct TrajectoryDiff {
+    pub frames: Vec<FrameDifference>,  // one per pair of frames
+    pub divergence_time: Option<Real>, // time of the first diverged frame, None if the runs stayed within the threshold
+}
+
+impl TrajectoryDiff {
+    // One line per compared frame.
+    pub fn write_csv(&self, writer: &mut impl io::Write) -> io::Result<()> {
+        writeln!(writer, "time_s,num_particles_a,num_particles_b,rms_distance_m,max_distance_m")?;
+        for frame in self.frames.iter() {
+            writeln!(
+                writer,
+                "{},{},{},{},{}",
+                frame.time, frame.num_particles.0, frame.num_particles.1, frame.rms_distance, frame.max_distance
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// Compares two recordings of the same scene frame by frame, e.g. to check how much a change to a solver alters its results.
+// Frames are streamed, so recordings don't need to fit into memory.
+pub fn diff_trajectories<A: io::Read, B: io::Read>(
+    mut a: TrajectoryReader<A>,
+    mut b: TrajectoryReader<B>,
+    options: &TrajectoryDiffOptions,
+) -> io::Result<TrajectoryDiff> {
+    let mut diff = TrajectoryDiff::default();
+    let (mut frame_a, mut frame_b) = (a.read_frame()?, b.read_frame()?);
+    while let (Some(current_a), Some(current_b)) = (&frame_a, &frame_b) {
+        // skip frames of whichever recording is behind
+        if current_a.time < current_b.time - options.time_tolerance {
+            frame_a = a.read_frame()?;
+            continue;
+        }
+        if current_b.time < current_a.time - options.time_tolerance {
+            frame_b = b.read_frame()?;
+            continue;
+        }
+
+        let difference = FrameDifference::between(current_a, current_b, options.matching);
+        if diff.divergence_time.is_none() && difference.diverged(options.divergence_threshold) {
+            diff.divergence_time = Some(difference.time);
+        }
+        diff.frames.push(difference);
+        frame_a = a.read_frame()?;
+        frame_b = b.read_frame()?;
+    }
+    Ok(diff)
+}
+
+// Uniform grid over a set of points for nearest point queries, with about one point per cell.
+struct NearestGrid<'a> {
+    positions: &'a [Point],
+    cell_size: Real,
+    cells: HashMap<(i32, i32), Vec<usize>>,
+    min_cell: (i32, i32),
+    max_cell: (i32, i32),
+}
+
+impl<'a> NearestGrid<'a> {
+    fn new(positions: &'a [Point]) -> NearestGrid<'a> {
+        // extent of the bulk of the points, so that a few splashes far off don't put everything else into a single cell
+        let bulk_extent = |coordinate: fn(&Point) -> Real| {
+            let mut values: Vec<Real> = positions.iter().map(coordinate).filter(|v| v.is_finite()).collect();
+            values.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap());
+            match (values.get(values.len() / 20), values.get(values.len() - values.len() / 20 - 1)) {
+                (Some(low), Some(high)) => (high - low).max(1.0e-3),
+                _ => 1.0e-3,
+            }
+        };
+        let area = bulk_extent(|p| p.x) * bulk_extent(|p| p.y);
+        let cell_size = (area / positions.len().max(1) as Real).sqrt();
+
+        let mut cells: HashMap<(i32, i32), Vec<usize>> = HashMap::new();
+        for (i, p) in positions.iter().enumerate() {
+            if p.x.is_finite() && p.y.is_finite() {
+                cells.entry(Self::cell(*p, cell_size)).or_default().push(i);
+            }
+        }
+        NearestGrid {
+            positions,
+            cell_size,
+            min_cell: cells
+                .keys()
+                .fold((i32::MAX, i32::MAX), |min, cell| (min.0.min(cell.0), min.1.min(cell.1))),
+            max_cell: cells
+                .keys()
+                .fold((i32::MIN, i32::MIN), |max, cell| (max.0.max(cell.0), max.1.max(cell.1))),
+            cells,
+        }
+    }
+
+    fn cell(p: Point, cell_size: Real) -> (i32, i32) {
+        ((p.x / cell_size).floor() as i32, (p.y / cell_size).floor() as i32)
+    }
+
+    // None if there are no (finite) points or the query point isn't finite.
+    fn nearest_distance(&self, p: Point) -> Option<Real> {
+        if self.cells.is_empty() || !(p.x.is_finite() && p.y.is_finite()) {
+            return None;
+        }
+        let center = Self::cell(p, self.cell_size);
+        // rings beyond this one don't contain any cells
+        let last_ring = (center.0 - self.min_cell.0)
+            .abs()
+            .max((self.max_cell.0 - center.0).abs())
+            .max((center.1 - self.min_cell.1).abs())
+            .max((self.max_cell.1 - center.1).abs());
+        let mut nearest2 = Real::INFINITY;
+        // rings of cells around the query, any point in ring r+1 is at least r cells away
+        let mut ring: i32 = 0;
+        loop {
+            // top and bottom row of the ring, then the remaining left and right columns
+            let ring_cells = (-ring..=ring)
+                .flat_map(|x| vec![(x, -ring), (x, ring)])
+                .chain((1 - ring..ring).flat_map(|y| vec![(-ring, y), (ring, y)]));
+            for (x, y) in ring_cells {
+                if let Some(indices) = self.cells.get(&(center.0 + x, center.1 + y)) {
+                    for i in indices.iter() {
+                        nearest2 = nearest2.min(self.positions[*i].distance2(p));
+                    }
+                }
+            }
+            let searched_distance = ring as Real * self.cell_size;
+            if nearest2 <= searched_distance * searched_distance || ring >= last_ring {
+                break;
+            }
+            ring += 1;
+        }
+        Some(nearest2.sqrt())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sph::{TrajectoryCompression, TrajectoryEncoding, TrajectoryWriter};
+
+    fn recording(frames: &[(Real, Vec<Point>)]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut writer = TrajectoryWriter::new(&mut bytes, TrajectoryEncoding::Full, TrajectoryCompression::None).unwrap();
+        for (time, positions) in frames.iter() {
+            let velocities = vec![Vector::zero(); positions.len()];
+            writer.write_frame(*time, positions, &velocities).unwrap();
+        }
+        drop(writer);
+        bytes
+    }
+
+    fn lattice(offset: Real) -> Vec<Point> {
+        (0..100)
+            .map(|i| Point::new((i % 10) as Real * 0.01 + offset, (i / 10) as Real * 0.01))
+            .collect()
+    }
+
+    #[test]
+    fn reports_differences_and_divergence_time() {
+        let a = recording(&[(0.0, lattice(0.0)), (0.1, lattice(0.0)), (0.2, lattice(0.0)), (0.3, lattice(0.0))]);
+        // the second run has no frame at 0.1 but an extra one at 0.15, then drifts off
+        let b = recording(&[(0.0, lattice(0.0)), (0.15, lattice(0.0)), (0.2, lattice(0.0005)), (0.3, lattice(0.002))]);
+
+        let options = TrajectoryDiffOptions::default();
+        let diff = diff_trajectories(TrajectoryReader::new(&a[..]).unwrap(), TrajectoryReader::new(&b[..]).unwrap(), &options).unwrap();
+        let times: Vec<Real> = diff.frames.iter().map(|frame| frame.time).collect();
+        assert_eq!(times, vec![0.0, 0.2, 0.3]);
+        assert_eq!(diff.frames[0].rms_distance, 0.0);
+        assert_lt!((diff.frames[1].rms_distance - 0.0005).abs(), 1.0e-6);
+        assert_lt!((diff.frames[2].max_distance - 0.002).abs(), 1.0e-6);
+        assert_eq!(diff.divergence_time, Some(0.3));
+
+        let mut csv = Vec::new();
+        diff.write_csv(&mut csv).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap().lines().count(), 4);
+    }
+
+    #[test]
+    fn nearest_matching_ignores_particle_order() {
+        let a = TrajectoryFrame {
+            time: 0.0,
+            positions: lattice(0.0),
+            velocities: Vec::new(),
+        };
+        let mut b = a.clone();
+        b.positions.reverse();
+        b.positions[0].x += 0.001;
+
+        let by_index = FrameDifference::between(&a, &b, ParticleMatching::ByIndex);
+        assert_gt!(by_index.rms_distance, 0.01);
+        let nearest = FrameDifference::between(&a, &b, ParticleMatching::Nearest);
+        assert_lt!((nearest.max_distance - 0.001).abs(), 1.0e-6);
+        assert!(!nearest.diverged(1.0e-3));
+
+        // far off particle of a single frame
+        b.positions.push(Point::new(5.0, 5.0));
+        let nearest = FrameDifference::between(&a, &b, ParticleMatching::Nearest);
+        assert_lt!(
+            (nearest.max_distance - Point::new(5.0, 5.0).distance(Point::new(0.09, 0.09))).abs(),
+            1.0e-4
+        );
+        assert!(nearest.diverged(1.0e-3));
+    }
+}
@@ -0,0 +1,239 @@
+use super::fluidparticleworld::FluidParticleWorld;
+use super::solver::Solver;
+use super::timemanager::{TimeManager, TimeManagerConfiguration};
+use crate::units::*;
+use cgmath::prelude::*;
+use std::collections::HashMap;
+use std::fmt;
+
+// Ratios of smoothing length to particle spacing outside of this range leave particles with too few neighbors for consistent densities
+// or make every step needlessly expensive. The default smoothing factor of 2 gives about 12 neighbors in 2D.
+const SANE_SMOOTHING_RATIOS: (Real, Real) = (1.5, 3.0);
+
+// Positions whose float precision is coarser than this fraction of the particle spacing make kernel evaluations noisy.
+const MIN_POSITION_PRECISION: Real = 0.01;
+
+// Fluid particles closer than this fraction of the boundary particle spacing to a boundary particle sit on top of or inside a boundary.
+// Within walls of several particle layers, every point is at most about 0.71 spacings away from a boundary particle.
+const BOUNDARY_OVERLAP_DISTANCE: Real = 0.75;
+
+// Problem with a scene that is likely to make a simulation of it blow up or give meaningless results.
+// Positions are examples of affected particles, meant for locating the problem in the scene.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SceneWarning {
+    // Positions the neighborhood grid can't sort into cells, including NaN and infinite ones. Neighbors of these are missed.
+    OutsideGridDomain { num_particles: usize, position: Point },
+    // Positions too far from the origin for float precision to resolve the particle spacing.
+    ImprecisePositions { num_particles: usize, position: Point },
+    // Fluid particles overlapping boundary particles, e.g. fluid initialized inside walls or obstacles.
+    FluidInsideBoundary { num_particles: usize, position: Point },
+    // Smoothing length over particle spacing outside of SANE_SMOOTHING_RATIOS.
+    SmoothingRatio { ratio: Real },
+    // Timestep above what the solver's stiffness allows, see Solver::max_stable_timestep.
+    TimestepTooLarge { timestep: Real, max_stable_timestep: Real },
+    // Fixed timestep that moves the fastest initial particle further than a particle diameter per step.
+    InitialVelocityTooHigh { max_velocity: Real, timestep: Real },
+}
+
+impl fmt::Display for SceneWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneWarning::OutsideGridDomain { num_particles, position } => write!(
+                f,
+                "{} particles outside of the neighborhood grid or not finite, e.g. at ({}, {})",
+                num_particles, position.x, position.y
+            ),
+            SceneWarning::ImprecisePositions { num_particles, position } => write!(
+                f,
+                "{} particles too far from the origin to resolve the particle spacing, e.g. at ({}, {})",
+                num_particles, position.x, position.y
+            ),
+            SceneWarning::FluidInsideBoundary { num_particles, position } => write!(
+                f,
+                "{} fluid particles inside boundaries, e.g. at ({}, {})",
+                num_particles, position.x, position.y
+            ),
+            SceneWarning::SmoothingRatio { ratio } => write!(
+                f,
+                "smoothing length is {:.2} particle spacings, sane are {} to {}",
+                ratio, SANE_SMOOTHING_RATIOS.0, SANE_SMOOTHING_RATIOS.1
+            ),
+            SceneWarning::TimestepTooLarge {
+                timestep,
+                max_stable_timestep,
+            } => write!(
+                f,
+                "timestep of {:.2e}s exceeds the {:.2e}s the solver's stiffness allows",
+                timestep, max_stable_timestep
+            ),
+            SceneWarning::InitialVelocityTooHigh { max_velocity, timestep } => write!(
+                f,
+                "initial velocities up to {:.2}m/s move particles more than their diameter per {:.2e}s step",
+                max_velocity, timestep
+            ),
+        }
+    }
+}
+
+// Counts the positions a predicate applies to, along with the first of them.
+fn find_positions<'a>(positions: impl Iterator<Item = &'a Point>, predicate: impl Fn(&Point) -> bool) -> Option<(usize, Point)> {
+    positions.filter(|p| predicate(p)).fold(None, |found, p| match found {
+        None => Some((1, *p)),
+        Some((num_particles, first)) => Some((num_particles + 1, first)),
+    })
+}
+
+// See FluidParticleWorld::validate.
+pub(super) fn validate_world(fluid_world: &FluidParticleWorld) -> Vec<SceneWarning> {
+    microprofile::scope!("validation", "validate_world");
+    let mut warnings = Vec::new();
+    let particles = &fluid_world.particles;
+    let all_positions = || particles.positions.iter().chain(particles.boundary_particles.iter());
+    let particle_spacing = fluid_world.properties.particle_radius() * 2.0;
+
+    let (domain_min, domain_max) = particles.neighborhood().grid_domain();
+    let outside_domain = |p: &Point| !(p.x >= domain_min.x && p.y >= domain_min.y && p.x < domain_max.x && p.y < domain_max.y);
+    if let Some((num_particles, position)) = find_positions(all_positions(), outside_domain) {
+        warnings.push(SceneWarning::OutsideGridDomain { num_particles, position });
+    }
+
+    let max_coordinate = particle_spacing * MIN_POSITION_PRECISION / Real::EPSILON;
+    let imprecise = |p: &Point| !outside_domain(p) && (p.x.abs() > max_coordinate || p.y.abs() > max_coordinate);
+    if let Some((num_particles, position)) = find_positions(all_positions(), imprecise) {
+        warnings.push(SceneWarning::ImprecisePositions { num_particles, position });
+    }
+
+    // the neighborhood datastructure may not know about the boundaries yet, so they are sorted into a grid of their own
+    let overlap_distance = fluid_world.boundary_particle_spacing() * BOUNDARY_OVERLAP_DISTANCE;
+    let cell = |p: &Point| ((p.x / overlap_distance).floor() as i64, (p.y / overlap_distance).floor() as i64);
+    let mut boundary_cells: HashMap<(i64, i64), Vec<Point>> = HashMap::new();
+    for p in particles.boundary_particles.iter().filter(|p| !outside_domain(p)) {
+        boundary_cells.entry(cell(p)).or_default().push(*p);
+    }
+    let inside_boundary = |p: &Point| {
+        let (x, y) = cell(p);
+        (x - 1..=x + 1).any(|x| {
+            (y - 1..=y + 1).any(|y| match boundary_cells.get(&(x, y)) {
+                Some(boundary_particles) => boundary_particles.iter().any(|b| b.distance2(*p) < overlap_distance * overlap_distance),
+                None => false,
+            })
+        })
+    };
+    if let Some((num_particles, position)) = find_positions(particles.positions.iter(), |p| !outside_domain(p) && inside_boundary(p)) {
+        warnings.push(SceneWarning::FluidInsideBoundary { num_particles, position });
+    }
+
+    let ratio = fluid_world.properties.smoothing_length() / particle_spacing;
+    if ratio < SANE_SMOOTHING_RATIOS.0 || ratio > SANE_SMOOTHING_RATIOS.1 {
+        warnings.push(SceneWarning::SmoothingRatio { ratio });
+    }
+
+    warnings
+}
+
+// Checks the timestep configuration against the solver's stiffness and the initial particle velocities.
+// Adaptive timesteps are checked with their maximum, which they take while the fluid is slow.
+pub fn validate_timestep(fluid_world: &FluidParticleWorld, solver: &dyn Solver, time_manager: &TimeManager) -> Vec<SceneWarning> {
+    let mut warnings = Vec::new();
+    let (timestep, fixed) = match time_manager.config() {
+        TimeManagerConfiguration::FixedTimeStep(timestep) => (*timestep, true),
+        TimeManagerConfiguration::AdaptiveTimeStep { timestep_max, .. } => (*timestep_max, false),
+    };
+
+    if let Some(max_stable_timestep) = solver.max_stable_timestep(fluid_world) {
+        if timestep > max_stable_timestep {
+            warnings.push(SceneWarning::TimestepTooLarge {
+                timestep,
+                max_stable_timestep,
+            });
+        }
+    }
+
+    // adaptive timesteps follow the velocities on their own
+    if fixed {
+        let particle_diameter = fluid_world.properties.particle_radius() * 2.0;
+        let max_velocity = fluid_world.particles.velocities.iter().map(|v| v.magnitude()).fold(0.0, Real::max);
+        if max_velocity * timestep > particle_diameter {
+            warnings.push(SceneWarning::InitialVelocityTooHigh { max_velocity, timestep });
+        }
+    }
+
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sph::{DFSPHSolver, WCSPHSolver, XSPHViscosityModel};
+
+    fn tank() -> FluidParticleWorld {
+        let mut fluid_world = FluidParticleWorld::new(2.0, NumberDensity(10000.0), Density(1000.0));
+        let spacing = fluid_world.properties.particle_radius() * 2.0;
+        fluid_world.add_fluid_rect(&Rect::new(spacing, spacing, 0.5, 0.5), 0.0);
+        // thick lines grow to the right of the line direction, i.e. away from the fluid
+        fluid_world.add_boundary_thick_line(Point::new(0.0, 0.0), Point::new(1.0, 0.0), 2);
+        fluid_world.add_boundary_thick_line(Point::new(0.0, 1.0), Point::new(0.0, 0.0), 2);
+        fluid_world
+    }
+
+    #[test]
+    fn sane_scene_has_no_warnings() {
+        let fluid_world = tank();
+        assert_eq!(fluid_world.validate(), Vec::new());
+
+        let solver = DFSPHSolver::new(
+            XSPHViscosityModel::new(fluid_world.properties.smoothing_length()),
+            fluid_world.properties.smoothing_length(),
+        );
+        let time_manager = TimeManager::new(TimeManagerConfiguration::FixedTimeStep(0.001));
+        assert_eq!(validate_timestep(&fluid_world, &solver, &time_manager), Vec::new());
+    }
+
+    #[test]
+    fn finds_broken_particles() {
+        let mut fluid_world = tank();
+        let spacing = fluid_world.properties.particle_radius() * 2.0;
+        // inside the bottom wall, whose rows are one and two spacings below the line
+        fluid_world.add_fluid_particle(Point::new(0.5, -spacing * 1.5), Vector::zero());
+        fluid_world.add_fluid_particle(Point::new(0.5, -spacing), Vector::zero());
+        fluid_world.add_fluid_particle(Point::new(Real::NAN, 0.0), Vector::zero());
+        fluid_world.add_fluid_particle(Point::new(1.0e5, 0.0), Vector::zero());
+
+        let warnings = fluid_world.validate();
+        assert_eq!(warnings.len(), 3);
+        assert!(matches!(warnings[0], SceneWarning::OutsideGridDomain { num_particles: 1, .. }));
+        assert_eq!(
+            warnings[1],
+            SceneWarning::ImprecisePositions {
+                num_particles: 1,
+                position: Point::new(1.0e5, 0.0)
+            }
+        );
+        assert_eq!(
+            warnings[2],
+            SceneWarning::FluidInsideBoundary {
+                num_particles: 2,
+                position: Point::new(0.5, -spacing * 1.5)
+            }
+        );
+    }
+
+    #[test]
+    fn checks_smoothing_ratio_and_timestep() {
+        let fluid_world = FluidParticleWorld::new(1.0, NumberDensity(10000.0), Density(1000.0));
+        assert_eq!(fluid_world.validate(), vec![SceneWarning::SmoothingRatio { ratio: 1.0 }]);
+
+        let mut fluid_world = tank();
+        fluid_world.particles.velocities[0] = Vector::new(20.0, 0.0);
+        // speed of sound of 10m/s at a spacing of 1cm
+        let solver = WCSPHSolver::new(
+            XSPHViscosityModel::new(fluid_world.properties.smoothing_length()),
+            &fluid_world.properties,
+        );
+        let time_manager = TimeManager::new(TimeManagerConfiguration::FixedTimeStep(0.001));
+        let warnings = validate_timestep(&fluid_world, &solver, &time_manager);
+        assert_eq!(warnings.len(), 2);
+        assert!(matches!(warnings[0], SceneWarning::TimestepTooLarge { max_stable_timestep, .. } if (max_stable_timestep - 0.0004).abs() < 1.0e-6));
+        assert!(matches!(warnings[1], SceneWarning::InitialVelocityTooHigh { .. }));
+    }
+}
@@ -0,0 +1,108 @@
+use crate::units::*;
+use cgmath::prelude::*;
+use rayon::prelude::*;
+
+// Safety net for parameter exploration: limits speed and accelleration of individual particles to what the CFL condition allows
+// for the current timestep, so that a single bad particle can't blow up the entire simulation.
+// Clamping is not physical. If it hits more than a handful of particles, the timestep is too large or the parameters are off.
+pub struct VelocityClamping {
+    // Maximum distance a particle may travel per step relative to the particle diameter, i.e. the maximum CFL number.
+    pub cfl_factor: Real,
+    num_clamped_velocities: usize,
+    num_clamped_accellerations: usize,
+}
+
+impl VelocityClamping {
+    pub fn new(cfl_factor: Real) -> VelocityClamping {
+        VelocityClamping {
+            cfl_factor,
+            num_clamped_velocities: 0,
+            num_clamped_accellerations: 0,
+        }
+    }
+
+    pub fn max_velocity(&self, particle_diameter: Real, dt: Real) -> Real {
+        self.cfl_factor * particle_diameter / dt
+    }
+
+    // Accelleration that changes a particle's velocity by at most max_velocity within a step.
+    pub fn max_accelleration(&self, particle_diameter: Real, dt: Real) -> Real {
+        self.max_velocity(particle_diameter, dt) / dt
+    }
+
+    // Number of particles whose velocity was clamped since the last reset, solvers reset at the start of every step.
+    pub fn num_clamped_velocities(&self) -> usize {
+        self.num_clamped_velocities
+    }
+
+    // Number of particles whose accelleration was clamped since the last reset.
+    pub fn num_clamped_accellerations(&self) -> usize {
+        self.num_clamped_accellerations
+    }
+
+    pub fn reset_counters(&mut self) {
+        self.num_clamped_velocities = 0;
+        self.num_clamped_accellerations = 0;
+    }
+
+    pub fn clamp_velocities(&mut self, velocities: &mut [Vector], particle_diameter: Real, dt: Real) {
+        self.num_clamped_velocities += clamp_magnitudes(velocities, self.max_velocity(particle_diameter, dt));
+    }
+
+    pub fn clamp_accellerations(&mut self, accellerations: &mut [Vector], particle_diameter: Real, dt: Real) {
+        self.num_clamped_accellerations += clamp_magnitudes(accellerations, self.max_accelleration(particle_diameter, dt));
+    }
+}
+
+// Scales all vectors longer than max_magnitude down to it, returns how many were changed.
+// Non-finite vectors are left alone, that's for the watchdog to notice.
+fn clamp_magnitudes(vectors: &mut [Vector], max_magnitude: Real) -> usize {
+    microprofile::scope!("VelocityClamping", "clamp_magnitudes");
+    let max_magnitude_sq = max_magnitude * max_magnitude;
+    vectors
+        .par_iter_mut()
+        .map(|v| {
+            let magnitude_sq = v.magnitude2();
+            if magnitude_sq > max_magnitude_sq && magnitude_sq.is_finite() {
+                *v *= max_magnitude / magnitude_sq.sqrt();
+                1
+            } else {
+                0
+            }
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clamps_to_cfl_limits() {
+        let mut clamping = VelocityClamping::new(0.5);
+        // half a particle diameter of 2cm per step of 1ms
+        assert_lt!((clamping.max_velocity(0.02, 0.001) - 10.0).abs(), 1.0e-4);
+        assert_lt!((clamping.max_accelleration(0.02, 0.001) - 10000.0).abs(), 1.0e-1);
+
+        let mut velocities = vec![
+            Vector::new(1.0, 0.0),
+            Vector::new(30.0, 40.0),
+            Vector::new(0.0, -10.5),
+            Vector::new(Real::NAN, 0.0),
+        ];
+        clamping.clamp_velocities(&mut velocities, 0.02, 0.001);
+        assert_eq!(clamping.num_clamped_velocities(), 2);
+        assert_eq!(velocities[0], Vector::new(1.0, 0.0));
+        assert_lt!((velocities[1] - Vector::new(6.0, 8.0)).magnitude(), 1.0e-4);
+        assert_lt!((velocities[2] - Vector::new(0.0, -10.0)).magnitude(), 1.0e-4);
+        assert!(velocities[3].x.is_nan());
+
+        let mut accellerations = vec![Vector::new(0.0, 20000.0), Vector::new(-9000.0, 0.0)];
+        clamping.clamp_accellerations(&mut accellerations, 0.02, 0.001);
+        assert_eq!(clamping.num_clamped_accellerations(), 1);
+        assert_lt!((accellerations[0].y - 10000.0).abs(), 1.0e-1);
+        clamping.reset_counters();
+        assert_eq!(clamping.num_clamped_velocities(), 0);
+        assert_eq!(clamping.num_clamped_accellerations(), 0);
+    }
+}
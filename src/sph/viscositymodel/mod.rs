@@ -1,11 +1,12 @@
 pub use physical::PhysicalViscosityModel;
-pub use xsph::XSPHViscosityModel;
+pub use xsph::{XSPHSmoothing, XSPHViscosityModel};
 
 mod physical;
 mod xsph;
 
 // ------------------------------------------------------
 
+use super::solver::SolverParameter;
 use crate::units::{Real, Vector};
 
 pub trait ViscosityModel {
@@ -14,5 +15,11 @@ pub trait ViscosityModel {
     // todo. integrating like this seems to be tricky! that's a ton of parameters that might be unused!
     // sphlishsphlash is just reiterating on all particles instead for the viscosity model
     // maybe set some of them and store model specific factor.
-    fn compute_viscous_accelleration(&self, dt: Real, r_sq: Real, r: Real, massj: Real, rhoj: Real, velocitydiff: Vector) -> Vector;
+    #[allow(clippy::too_many_arguments)]
+    fn compute_viscous_accelleration(&self, dt: Real, r_sq: Real, r: Real, massj: Real, rhoi: Real, rhoj: Real, velocitydiff: Vector) -> Vector;
+
+    // Tunables of the model, solvers list them along with their own, see Solver::parameters.
+    fn parameters(&self) -> Vec<SolverParameter>;
+    // Returns false if the model has no tunable with that name.
+    fn set_parameter(&mut self, name: &str, value: Real) -> bool;
 }
@@ -1,6 +1,7 @@
 use super::ViscosityModel;
 
 use super::super::smoothing_kernel::*;
+use super::super::solver::SolverParameter;
 use crate::units::*;
 
 // Laplacian based physical model as in "Particle-Based Fluid Simulation for Interactive Applications", Müller et al.
@@ -18,7 +19,27 @@ impl PhysicalViscosityModel {
 }
 impl ViscosityModel for PhysicalViscosityModel {
     #[inline]
-    fn compute_viscous_accelleration(&self, _dt: Real, r_sq: Real, r: Real, massj: Real, rhoj: Real, velocitydiff: Vector) -> Vector {
-        self.fluid_viscosity * massj * self.kernel.laplacian(r_sq, r) / rhoj * velocitydiff
+    fn compute_viscous_accelleration(&self, _dt: Real, r_sq: Real, r: Real, massj: Real, rhoi: Real, rhoj: Real, velocitydiff: Vector) -> Vector {
+        // μ / ρ_i * m_j / ρ_j * ∇²W_ij * (v_j - v_i)
+        self.fluid_viscosity / rhoi * massj * self.kernel.laplacian(r_sq, r) / rhoj * velocitydiff
+    }
+
+    fn parameters(&self) -> Vec<SolverParameter> {
+        vec![SolverParameter {
+            name: "viscosity",
+            unit: "Pa*s",
+            value: self.fluid_viscosity,
+            min: 1.0e-4,
+            max: 100.0,
+            logarithmic: true,
+        }]
+    }
+
+    fn set_parameter(&mut self, name: &str, value: Real) -> bool {
+        match name {
+            "viscosity" => self.fluid_viscosity = value,
+            _ => return false,
+        }
+        true
     }
 }
@@ -1,7 +1,12 @@
 use super::ViscosityModel;
 
+use super::super::fluidparticleworld::FluidParticleWorld;
+use super::super::neighborhood_search::ParticleIndex;
 use super::super::smoothing_kernel::*;
+use super::super::solver::SolverParameter;
 use crate::units::*;
+use cgmath::prelude::*;
+use rayon::prelude::*;
 
 // XSPH as in "Ghost SPH for Animating Water", Schechter et al. (https://www.cs.ubc.ca/~rbridson/docs/schechter-siggraph2012-ghostsph.pdf)
 pub struct XSPHViscosityModel {
@@ -18,7 +23,72 @@ impl XSPHViscosityModel {
 }
 impl ViscosityModel for XSPHViscosityModel {
     #[inline]
-    fn compute_viscous_accelleration(&self, dt: Real, r_sq: Real, r: Real, massj: Real, rhoj: Real, velocitydiff: Vector) -> Vector {
+    fn compute_viscous_accelleration(&self, dt: Real, r_sq: Real, r: Real, massj: Real, _rhoi: Real, rhoj: Real, velocitydiff: Vector) -> Vector {
         self.epsilon * massj * self.kernel.evaluate(r_sq, r) / (rhoj * dt) * velocitydiff
     }
+
+    fn parameters(&self) -> Vec<SolverParameter> {
+        vec![SolverParameter {
+            name: "xsph_epsilon",
+            unit: "",
+            value: self.epsilon,
+            min: 0.0,
+            max: 0.5,
+            logarithmic: false,
+        }]
+    }
+
+    fn set_parameter(&mut self, name: &str, value: Real) -> bool {
+        match name {
+            "xsph_epsilon" => self.epsilon = value,
+            _ => return false,
+        }
+        true
+    }
+}
+
+// XSPH velocity smoothing (Monaghan 1989) as a filter on the velocities after integration, independent of the solver's viscosity model.
+// Pairing it with the physical viscosity model is a common way to stabilize a simulation without making the fluid itself more viscous.
+pub struct XSPHSmoothing {
+    pub epsilon: Real, // fraction of the velocity difference to the neighborhood that is removed each step, default 0.05
+    kernel: Poly6,
+}
+impl XSPHSmoothing {
+    pub fn new(smoothing_length: Real) -> XSPHSmoothing {
+        XSPHSmoothing {
+            epsilon: 0.05,
+            kernel: Poly6::new(smoothing_length),
+        }
+    }
+
+    // Moves all velocities towards those of their neighbors: v_i += ε Σ_j m_j / ((ρ_i + ρ_j) / 2) W_ij (v_j - v_i)
+    // Uses the neighborhood datastructure and densities as of the last simulation step, particles added since are left as they are.
+    pub fn apply(&self, fluid_world: &mut FluidParticleWorld) {
+        microprofile::scope!("XSPHSmoothing", "apply");
+        let particles = &fluid_world.particles;
+        let num_particles = particles.neighborhood().num_particles().min(particles.densities.len());
+        let mut smoothed_velocities = fluid_world.scratch_buffers.get_buffer_vector(particles.velocities.len());
+        smoothed_velocities
+            .buffer
+            .par_iter_mut()
+            .zip(particles.velocities.par_iter())
+            .enumerate()
+            .for_each(|(i, (smoothed_velocity, vi))| {
+                *smoothed_velocity = *vi;
+                if i >= num_particles {
+                    return;
+                }
+                let ri = particles.positions[i];
+                let rhoi = particles.densities[i];
+                let mut velocity_correction = Vector::zero();
+                particles.foreach_neighbor_particle(i as ParticleIndex, |j| {
+                    let j = j as usize;
+                    let r_sq = ri.distance2(particles.positions[j]);
+                    let weight = particles.masses[j] * self.kernel.evaluate(r_sq, r_sq.sqrt()) / (0.5 * (rhoi + particles.densities[j]));
+                    velocity_correction += weight * (particles.velocities[j] - vi);
+                });
+                *smoothed_velocity += self.epsilon * velocity_correction;
+            });
+        std::mem::swap(&mut fluid_world.particles.velocities, &mut smoothed_velocities.buffer);
+    }
 }
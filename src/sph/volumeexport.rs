@@ -0,0 +1,132 @@
+use super::fluidparticleworld::FluidParticleWorld;
+use super::interpolation::{self, SampleGrid};
+use crate::units::*;
+use std::io;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VolumeImageFormat {
+    // Portable float map, single channel linear 32bit floats. Reads in Houdini, Nuke & co. without losing precision.
+    Pfm,
+    // 16bit portable graymap, fluid fractions from 0 to 1 over the full range, clamped above. Reads pretty much everywhere.
+    Pgm,
+}
+
+impl VolumeImageFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            VolumeImageFormat::Pfm => "pfm",
+            VolumeImageFormat::Pgm => "pgm",
+        }
+    }
+}
+
+// Fluid rasterized into a density volume, one image per frame, for volumetric rendering of the liquid in external renderers.
+// Since the simulation is 2D, the volume is a single slice that is meant to be extruded, e.g. as the density of a Houdini volume.
+// Every pixel holds the fraction of fluid at its center, i.e. the color field c(x) = Σ V_j W(x - x_j), which is about 1 inside the fluid
+// and falls off smoothly to 0 over a smoothing length at the surface. See interpolation.
+//
+//   let mut volume = DensityVolume::new(SampleGrid { origin, spacing: particle_radius * 0.5, size: (1024, 512) });
+//   volume.sample(&fluid_world);
+//   volume.write(&mut File::create(format!("density_{:05}.pfm", frame))?, VolumeImageFormat::Pfm)?;
+pub struct DensityVolume {
+    pub grid: SampleGrid,  // pixel centers, the bottom left sample point is the bottom left pixel
+    pub values: Vec<Real>, // fluid fraction per grid point, see SampleGrid::index
+}
+
+impl DensityVolume {
+    pub fn new(grid: SampleGrid) -> DensityVolume {
+        DensityVolume {
+            grid,
+            values: vec![0.0; grid.num_points()],
+        }
+    }
+
+    pub fn sample(&mut self, fluid_world: &FluidParticleWorld) {
+        microprofile::scope!("DensityVolume", "sample");
+        interpolation::splat_color_field_to_grid(fluid_world, &self.grid, &mut self.values);
+    }
+
+    pub fn write(&self, writer: &mut impl io::Write, format: VolumeImageFormat) -> io::Result<()> {
+        match format {
+            VolumeImageFormat::Pfm => self.write_pfm(writer),
+            VolumeImageFormat::Pgm => self.write_pgm(writer),
+        }
+    }
+
+    pub fn write_pfm(&self, writer: &mut impl io::Write) -> io::Result<()> {
+        // negative scale marks little endian, rows go from the bottom up just like the grid's
+        write!(writer, "Pf\n{} {}\n-1.0\n", self.grid.size.0, self.grid.size.1)?;
+        let mut data = Vec::with_capacity(self.values.len() * 4);
+        for value in self.values.iter() {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        writer.write_all(&data)
+    }
+
+    pub fn write_pgm(&self, writer: &mut impl io::Write) -> io::Result<()> {
+        // 16bit values are big endian, rows go from the top down
+        write!(writer, "P5\n{} {}\n65535\n", self.grid.size.0, self.grid.size.1)?;
+        let mut data = Vec::with_capacity(self.values.len() * 2);
+        for row in self.values.chunks(self.grid.size.0.max(1)).rev() {
+            for value in row.iter() {
+                let value = (value.clamp(0.0, 1.0) * 65535.0).round() as u16;
+                data.extend_from_slice(&value.to_be_bytes());
+            }
+        }
+        writer.write_all(&data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Resting block in the lower left, a grid sticking out to the right and above it.
+    fn sampled_block() -> DensityVolume {
+        let mut fluid_world = FluidParticleWorld::new(2.0, NumberDensity(10000.0), Density(100.0));
+        fluid_world.add_fluid_rect(&Rect::new(0.0, 0.0, 0.5, 0.5), 0.0);
+        let mut volume = DensityVolume::new(SampleGrid {
+            origin: Point::new(0.05, 0.05),
+            spacing: 0.1,
+            size: (8, 6),
+        });
+        volume.sample(&fluid_world);
+        volume
+    }
+
+    #[test]
+    fn samples_fluid_fraction() {
+        let volume = sampled_block();
+        assert_lt!((volume.values[volume.grid.index(2, 2)] - 1.0).abs(), 0.05);
+        assert_eq!(volume.values[volume.grid.index(7, 5)], 0.0);
+    }
+
+    #[test]
+    fn writes_pfm_and_pgm() {
+        let volume = sampled_block();
+        let inside = volume.values[volume.grid.index(2, 0)];
+
+        let mut pfm = Vec::new();
+        volume.write(&mut pfm, VolumeImageFormat::Pfm).unwrap();
+        let header = b"Pf\n8 6\n-1.0\n";
+        assert!(pfm.starts_with(header));
+        assert_eq!(pfm.len(), header.len() + 48 * 4);
+        // bottom row first
+        let offset = header.len() + 2 * 4;
+        assert_eq!(
+            Real::from_le_bytes([pfm[offset], pfm[offset + 1], pfm[offset + 2], pfm[offset + 3]]),
+            inside
+        );
+
+        let mut pgm = Vec::new();
+        volume.write(&mut pgm, VolumeImageFormat::Pgm).unwrap();
+        let header = b"P5\n8 6\n65535\n";
+        assert!(pgm.starts_with(header));
+        assert_eq!(pgm.len(), header.len() + 48 * 2);
+        // bottom row last
+        let offset = header.len() + (5 * 8 + 2) * 2;
+        let expected = (inside.min(1.0) * 65535.0).round() as u16;
+        assert_eq!(u16::from_be_bytes([pgm[offset], pgm[offset + 1]]), expected);
+        assert_eq!(&pgm[header.len()..header.len() + 16], &[0; 16]);
+    }
+}
@@ -0,0 +1,209 @@
+use super::fluidparticleworld::FluidParticleWorld;
+use super::neighborhood_search::ParticleIndex;
+use super::steptimings::SimulationPass;
+use crate::units::*;
+use cgmath::prelude::*;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InstabilityKind {
+    NonFinitePosition,
+    NonFiniteVelocity,
+    ExcessiveVelocity, // faster than Watchdog::max_velocity
+    NonFiniteAccelleration,
+    NonFiniteDensity,
+}
+
+// Description of the first problem a watchdog found.
+#[derive(Clone, Debug)]
+pub struct Instability {
+    pub kind: InstabilityKind,
+    pub particle: ParticleIndex, // index at the time of detection, particles are not reordered afterwards
+    pub pass: SimulationPass,    // pass after which the instability was detected
+    pub time: Real,              // simulation time at the start of the step
+    pub value: String,           // offending value, formatted for display
+}
+
+impl std::fmt::Display for Instability {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{:?} of particle {} after {} pass at {:.4}s: {}",
+            self.kind,
+            self.particle,
+            self.pass.name(),
+            self.time,
+            self.value
+        )
+    }
+}
+
+impl Instability {
+    // Writes the complete particle state as csv for offline debugging, preceded by a comment line describing the instability.
+    pub fn write_snapshot(&self, writer: &mut impl std::io::Write, fluid_world: &FluidParticleWorld) -> std::io::Result<()> {
+        let particles = &fluid_world.particles;
+        writeln!(writer, "# {}", self)?;
+        // registered particle attributes as additional columns, left empty for boundary particles
+        let attributes_header = particles.attributes_csv_header();
+        writeln!(writer, "type,index,x,y,velocity_x,velocity_y,density{}", attributes_header)?;
+        for (i, (p, v)) in particles.positions.iter().zip(particles.velocities.iter()).enumerate() {
+            let density = particles.densities.get(i).map_or(String::new(), |density| density.to_string());
+            let attributes = particles.attributes_csv_row(i);
+            writeln!(writer, "fluid,{},{},{},{},{},{}{}", i, p.x, p.y, v.x, v.y, density, attributes)?;
+        }
+        let empty_attributes = ",".repeat(attributes_header.matches(',').count());
+        for (i, p) in particles.boundary_particles.iter().enumerate() {
+            writeln!(writer, "boundary,{},{},{},0,0,{}", i, p.x, p.y, empty_attributes)?;
+        }
+        Ok(())
+    }
+}
+
+// Checks particle data for signs of a blown up simulation.
+// Solvers that have a watchdog run it after their passes and stop simulating once it raised an alarm,
+// leaving the particle state as it was when the problem was found.
+pub struct Watchdog {
+    pub max_velocity: Real, // in m/s, anything faster is considered an explosion
+    alarm: Option<Instability>,
+}
+
+impl Watchdog {
+    pub fn new(max_velocity: Real) -> Watchdog {
+        Watchdog { max_velocity, alarm: None }
+    }
+
+    pub fn alarm(&self) -> Option<&Instability> {
+        self.alarm.as_ref()
+    }
+
+    pub fn reset(&mut self) {
+        self.alarm = None;
+    }
+
+    fn raise(&mut self, kind: InstabilityKind, pass: SimulationPass, time: Real, particle: Option<(usize, String)>) -> bool {
+        if let Some((particle, value)) = particle {
+            self.alarm = Some(Instability {
+                kind,
+                particle: particle as ParticleIndex,
+                pass,
+                time,
+                value,
+            });
+        }
+        self.alarm.is_some()
+    }
+
+    // All check functions return true if there is an alarm, either a new or a previous one.
+
+    pub fn check_positions(&mut self, pass: SimulationPass, time: Real, positions: &[Point]) -> bool {
+        if self.alarm.is_some() {
+            return true;
+        }
+        let particle = positions
+            .iter()
+            .position(|p| !p.x.is_finite() || !p.y.is_finite())
+            .map(|i| (i, format!("position {:?}", positions[i])));
+        self.raise(InstabilityKind::NonFinitePosition, pass, time, particle)
+    }
+
+    pub fn check_velocities(&mut self, pass: SimulationPass, time: Real, velocities: &[Vector]) -> bool {
+        if self.alarm.is_some() {
+            return true;
+        }
+        let non_finite = velocities
+            .iter()
+            .position(|v| !v.x.is_finite() || !v.y.is_finite())
+            .map(|i| (i, format!("velocity {:?}", velocities[i])));
+        if self.raise(InstabilityKind::NonFiniteVelocity, pass, time, non_finite) {
+            return true;
+        }
+        let max_velocity_sq = self.max_velocity * self.max_velocity;
+        let excessive = velocities
+            .iter()
+            .position(|v| v.magnitude2() > max_velocity_sq)
+            .map(|i| (i, format!("speed {}m/s", velocities[i].magnitude())));
+        self.raise(InstabilityKind::ExcessiveVelocity, pass, time, excessive)
+    }
+
+    pub fn check_accellerations(&mut self, pass: SimulationPass, time: Real, accellerations: &[Vector]) -> bool {
+        if self.alarm.is_some() {
+            return true;
+        }
+        let particle = accellerations
+            .iter()
+            .position(|a| !a.x.is_finite() || !a.y.is_finite())
+            .map(|i| (i, format!("accelleration {:?}", accellerations[i])));
+        self.raise(InstabilityKind::NonFiniteAccelleration, pass, time, particle)
+    }
+
+    pub fn check_densities(&mut self, pass: SimulationPass, time: Real, densities: &[Real]) -> bool {
+        if self.alarm.is_some() {
+            return true;
+        }
+        let particle = densities
+            .iter()
+            .position(|density| !density.is_finite())
+            .map(|i| (i, format!("density {}", densities[i])));
+        self.raise(InstabilityKind::NonFiniteDensity, pass, time, particle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sph::{Solver, TimeManager, TimeManagerConfiguration, WCSPHSolver, XSPHViscosityModel};
+
+    #[test]
+    fn solver_halts_on_alarm() {
+        let mut fluid_world = FluidParticleWorld::new(2.0, NumberDensity(5000.0), Density(100.0));
+        fluid_world.add_fluid_rect(&Rect::new(0.0, 0.0, 0.2, 0.2), 0.0);
+        fluid_world.particles.velocities[3] = Vector::new(Real::NAN, 0.0);
+        let mut solver = WCSPHSolver::new(
+            XSPHViscosityModel::new(fluid_world.properties.smoothing_length()),
+            &fluid_world.properties,
+        );
+        solver.set_watchdog(Some(Watchdog::new(100.0)));
+        let mut time_manager = TimeManager::new(TimeManagerConfiguration::FixedTimeStep(0.001));
+
+        solver.simulation_step(&mut fluid_world, &mut time_manager).unwrap();
+        let alarm = solver.watchdog().unwrap().alarm().unwrap().clone();
+        assert_eq!(alarm.pass, SimulationPass::Integration);
+        assert!(!fluid_world.particles.positions[alarm.particle as usize].x.is_finite());
+
+        // no more progress until reset
+        let time = time_manager.passed_time();
+        solver.simulation_step(&mut fluid_world, &mut time_manager).unwrap();
+        assert_eq!(time_manager.passed_time(), time);
+
+        fluid_world.particles.register_attribute("phase", 1_u32);
+        let mut snapshot = Vec::new();
+        alarm.write_snapshot(&mut snapshot, &fluid_world).unwrap();
+        let snapshot = String::from_utf8(snapshot).unwrap();
+        assert_eq!(snapshot.lines().count(), 2 + fluid_world.particles.positions.len());
+        assert!(snapshot.starts_with("# NonFinitePosition of particle"));
+        assert_eq!(snapshot.lines().nth(1), Some("type,index,x,y,velocity_x,velocity_y,density,phase"));
+        assert!(snapshot.lines().skip(2).all(|line| line.ends_with(",1")));
+    }
+
+    #[test]
+    fn reports_first_problem() {
+        let mut watchdog = Watchdog::new(10.0);
+        let velocities = [Vector::new(1.0, 0.0), Vector::new(20.0, 0.0), Vector::new(Real::NAN, 0.0)];
+        assert!(!watchdog.check_positions(SimulationPass::Integration, 1.0, &[Point::new(0.0, 0.0)]));
+        assert!(!watchdog.check_velocities(SimulationPass::Integration, 1.0, &velocities[..1]));
+
+        // non-finite values take precedence over excessive ones
+        assert!(watchdog.check_velocities(SimulationPass::Pressure, 1.0, &velocities));
+        let alarm = watchdog.alarm().unwrap();
+        assert_eq!(alarm.kind, InstabilityKind::NonFiniteVelocity);
+        assert_eq!(alarm.particle, 2);
+        assert_eq!(alarm.pass, SimulationPass::Pressure);
+
+        // alarm sticks until reset
+        assert!(watchdog.check_densities(SimulationPass::Density, 1.0, &[Real::INFINITY]));
+        assert_eq!(watchdog.alarm().unwrap().kind, InstabilityKind::NonFiniteVelocity);
+        watchdog.reset();
+        assert!(watchdog.check_velocities(SimulationPass::Pressure, 1.0, &velocities[..2]));
+        assert_eq!(watchdog.alarm().unwrap().kind, InstabilityKind::ExcessiveVelocity);
+        assert_eq!(watchdog.alarm().unwrap().particle, 1);
+    }
+}
@@ -0,0 +1,222 @@
+use super::fluidparticleworld::{FluidParticleWorld, MovingBoundary};
+use crate::units::*;
+
+// How a wave maker's paddle oscillates around its rest pose.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WaveMakerMotion {
+    // Paddle moves back and forth horizontally, amplitude in m.
+    Piston { amplitude: Real },
+    // Paddle rotates around a hinge at its bottom, amplitude in radians. Positive angles tilt the paddle towards the fluid.
+    Flap { amplitude: Real },
+}
+
+// Vertical paddle at the left end of a wave tank, driving surface waves that travel to the right.
+// The paddle is a moving boundary that follows a sinusoidal motion, starting at its rest pose with maximum velocity.
+pub struct WaveMaker {
+    pub motion: WaveMakerMotion,
+    pub frequency: Real, // in Hz
+    hinge: Point,        // bottom of the paddle
+    paddle: MovingBoundary,
+}
+
+impl WaveMaker {
+    // Adds the paddle from `bottom` up to `bottom + height`, its boundary particles lie to the left of that line.
+    pub fn new(fluid_world: &mut FluidParticleWorld, bottom: Point, height: Real, motion: WaveMakerMotion, frequency: Real) -> WaveMaker {
+        // thick lines grow to the right of the line direction, so the paddle goes from top to bottom
+        let paddle = fluid_world.add_moving_boundary(|fluid_world| {
+            fluid_world.add_boundary_thick_line(bottom + Vector::new(0.0, height), bottom, 2);
+        });
+        WaveMaker {
+            motion,
+            frequency,
+            hinge: bottom,
+            paddle,
+        }
+    }
+
+    fn angular_frequency(&self) -> Real {
+        2.0 * std::f32::consts::PI * self.frequency
+    }
+
+    fn amplitude(&self) -> Real {
+        match self.motion {
+            WaveMakerMotion::Piston { amplitude } | WaveMakerMotion::Flap { amplitude } => amplitude,
+        }
+    }
+
+    // Paddle displacement in m (piston) or angle in radians (flap) and its rate of change at a given time.
+    pub fn stroke(&self, time: Real) -> (Real, Real) {
+        let omega = self.angular_frequency();
+        let (sin, cos) = (omega * time).sin_cos();
+        (self.amplitude() * sin, self.amplitude() * omega * cos)
+    }
+
+    // Moves the paddle to where it is at the given simulation time. Meant to be called right before every simulation step.
+    pub fn update(&self, fluid_world: &mut FluidParticleWorld, time: Real) {
+        let (stroke, stroke_rate) = self.stroke(time);
+        match self.motion {
+            WaveMakerMotion::Piston { .. } => {
+                fluid_world.move_boundary(self.paddle, |p| p + Vector::new(stroke, 0.0), |_| Vector::new(stroke_rate, 0.0))
+            }
+            WaveMakerMotion::Flap { .. } => {
+                // clockwise rotation around the hinge
+                let hinge = self.hinge;
+                let (sin, cos) = (-stroke).sin_cos();
+                fluid_world.move_boundary(
+                    self.paddle,
+                    |p| {
+                        let r = p - hinge;
+                        hinge + Vector::new(r.x * cos - r.y * sin, r.x * sin + r.y * cos)
+                    },
+                    |p| {
+                        let r = p - hinge;
+                        Vector::new(r.y, -r.x) * stroke_rate
+                    },
+                );
+            }
+        }
+    }
+
+    // Wave number k in 1/m of the generated waves from the linear dispersion relation ω² = g k tanh(k h).
+    pub fn wave_number(&self, water_depth: Real, gravity: Real) -> Real {
+        let omega_sq = self.angular_frequency() * self.angular_frequency();
+        // Newton iteration, starting from the larger one of the deep and shallow water solutions
+        let mut k = (omega_sq / gravity).max(self.angular_frequency() / (gravity * water_depth).sqrt());
+        for _ in 0..32 {
+            let tanh = (k * water_depth).tanh();
+            let residual = gravity * k * tanh - omega_sq;
+            let derivative = gravity * (tanh + k * water_depth * (1.0 - tanh * tanh));
+            k -= residual / derivative;
+        }
+        k
+    }
+
+    pub fn wavelength(&self, water_depth: Real, gravity: Real) -> Real {
+        2.0 * std::f32::consts::PI / self.wave_number(water_depth, gravity)
+    }
+
+    // Crest to trough height in m of the progressive waves far away from the paddle, from linear wave maker theory (Biesel transfer functions).
+    // `water_depth` is the still water depth above the paddle's bottom.
+    pub fn expected_wave_height(&self, water_depth: Real, gravity: Real) -> Real {
+        let kh = self.wave_number(water_depth, gravity) * water_depth;
+        let denominator = (2.0 * kh).sinh() + 2.0 * kh;
+        match self.motion {
+            WaveMakerMotion::Piston { amplitude } => {
+                let stroke = 2.0 * amplitude;
+                stroke * 2.0 * ((2.0 * kh).cosh() - 1.0) / denominator
+            }
+            WaveMakerMotion::Flap { amplitude } => {
+                // stroke at the still water level
+                let stroke = 2.0 * water_depth * amplitude.tan();
+                stroke * 4.0 * kh.sinh() / kh * (kh * kh.sinh() - kh.cosh() + 1.0) / denominator
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fluidparticleworld::BoundaryGeometry;
+    use super::*;
+    use cgmath::prelude::*;
+
+    #[test]
+    fn dispersion_relation() {
+        let wave_maker = |frequency| WaveMaker {
+            motion: WaveMakerMotion::Piston { amplitude: 0.01 },
+            frequency,
+            hinge: Point::new(0.0, 0.0),
+            paddle: MovingBoundary(0),
+        };
+        for &(frequency, depth) in [(0.2, 0.5), (1.0, 0.5), (2.0, 0.5), (1.0, 10.0)].iter() {
+            let wave_maker = wave_maker(frequency);
+            let k = wave_maker.wave_number(depth, 9.81);
+            let omega = 2.0 * std::f32::consts::PI * frequency;
+            assert_lt!((9.81 * k * (k * depth).tanh() / (omega * omega) - 1.0).abs(), 1.0e-4);
+        }
+        // deep water: λ = g T² / 2π
+        let deep = wave_maker(1.0);
+        assert_lt!((deep.wavelength(10.0, 9.81) - 9.81 / (2.0 * std::f32::consts::PI)).abs(), 1.0e-3);
+
+        // shallow water limits: H / S = k h for the piston and k h / 2 for the flap
+        let mut shallow = wave_maker(0.05);
+        let kh = shallow.wave_number(0.5, 9.81) * 0.5;
+        assert_lt!((shallow.expected_wave_height(0.5, 9.81) / 0.02 / kh - 1.0).abs(), 0.01);
+        shallow.motion = WaveMakerMotion::Flap { amplitude: 0.01 };
+        let flap_stroke = 2.0 * 0.5 * (0.01 as Real).tan();
+        assert_lt!((shallow.expected_wave_height(0.5, 9.81) / flap_stroke / (kh * 0.5) - 1.0).abs(), 0.01);
+        // deep water limits: H / S = 2 for the piston and 2 (1 - 1 / kh) for the flap,
+        // waves only feel the paddle's motion close to the surface
+        let mut deep = wave_maker(1.5);
+        let kh = deep.wave_number(2.0, 9.81) * 2.0;
+        assert_lt!((deep.expected_wave_height(2.0, 9.81) / 0.02 - 2.0).abs(), 1.0e-3);
+        deep.motion = WaveMakerMotion::Flap { amplitude: 0.001 };
+        let flap_stroke = 2.0 * 2.0 * (0.001 as Real).tan();
+        assert_lt!(
+            (deep.expected_wave_height(2.0, 9.81) / flap_stroke - 2.0 * (1.0 - 1.0 / kh)).abs(),
+            1.0e-3
+        );
+    }
+
+    #[test]
+    fn paddle_motion() {
+        let mut fluid_world = FluidParticleWorld::new(2.0, NumberDensity(10000.0), Density(100.0));
+        fluid_world.add_boundary_thick_line(Point::new(0.0, 0.0), Point::new(1.0, 0.0), 2);
+        let num_static = fluid_world.particles.boundary_particles.len();
+        let mut wave_maker = WaveMaker::new(
+            &mut fluid_world,
+            Point::new(0.0, 0.0),
+            0.5,
+            WaveMakerMotion::Piston { amplitude: 0.105 },
+            1.0,
+        );
+        let num_paddle = fluid_world.particles.boundary_particles.len() - num_static;
+        // sorts boundary particles, static and paddle particles get mixed up
+        fluid_world.update_neighborhood_datastructure(Vec::new(), Vec::new());
+        let rest_positions = fluid_world.particles.boundary_particles.clone();
+
+        // quarter period, piston is at its maximum displacement
+        wave_maker.update(&mut fluid_world, 0.25);
+        fluid_world.update_neighborhood_datastructure(Vec::new(), Vec::new());
+        let particles = &fluid_world.particles;
+        let moved = particles.boundary_particles.iter().filter(|p| !rest_positions.contains(p)).count();
+        assert_eq!(moved, num_paddle);
+        assert_eq!(particles.boundary_velocities.len(), particles.boundary_particles.len());
+        assert!(particles.boundary_velocities.iter().all(|v| v.magnitude() < 1.0e-5));
+        assert_eq!(fluid_world.boundary_geometry().len(), 2);
+        if let BoundaryGeometry::Line { start, end, .. } = fluid_world.boundary_geometry()[1] {
+            assert_lt!((start.x + end.x) * 0.5, 0.105);
+            assert_gt!((start.x + end.x) * 0.5, 0.0);
+        } else {
+            panic!("expected paddle to be a line");
+        }
+
+        // back at rest pose, moving with maximum speed
+        wave_maker.update(&mut fluid_world, 1.0);
+        let particles = &fluid_world.particles;
+        let mut num_moving = 0;
+        for (p, v) in particles.boundary_particles.iter().zip(particles.boundary_velocities.iter()) {
+            if v.magnitude() > 0.0 {
+                assert_lt!((v - Vector::new(0.21 * std::f32::consts::PI, 0.0)).magnitude(), 1.0e-4);
+                assert!(rest_positions.iter().any(|r| r.distance(*p) < 1.0e-5));
+                num_moving += 1;
+            }
+        }
+        assert_eq!(num_moving, num_paddle);
+
+        // flap rotates around the bottom, the top moves the furthest
+        wave_maker.motion = WaveMakerMotion::Flap { amplitude: 0.1 };
+        wave_maker.update(&mut fluid_world, 0.0);
+        let particles = &fluid_world.particles;
+        let top_speed = particles.boundary_velocities.iter().map(|v| v.magnitude()).fold(0.0, Real::max);
+        let paddle_thickness = fluid_world.properties.particle_radius() * 4.0;
+        let expected_top_speed = 0.2 * std::f32::consts::PI * (0.5 * 0.5 + paddle_thickness * paddle_thickness).sqrt();
+        assert_lt!((top_speed - expected_top_speed).abs(), 0.01);
+        for (p, v) in particles.boundary_particles.iter().zip(particles.boundary_velocities.iter()) {
+            // towards the fluid above the hinge
+            if p.y > 0.0 {
+                assert!(v.x > 0.0 || v.magnitude() == 0.0);
+            }
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use yasph2d::sph;
+
+// Fixed number of the most recent values of some quantity, oldest first. Pushing to a full series drops the oldest value.
+pub struct TimeSeries {
+    values: VecDeque<f32>,
+    capacity: usize,
+}
+
+impl TimeSeries {
+    pub fn new(capacity: usize) -> TimeSeries {
+        TimeSeries {
+            values: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, value: f32) {
+        if self.values.len() == self.capacity {
+            self.values.pop_front();
+        }
+        self.values.push_back(value);
+    }
+
+    pub fn clear(&mut self) {
+        self.values.clear();
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = f32> + '_ {
+        self.values.iter().cloned()
+    }
+
+    pub fn latest(&self) -> Option<f32> {
+        self.values.back().cloned()
+    }
+
+    // Min and max of all finite values, None if there are none.
+    pub fn range(&self) -> Option<(f32, f32)> {
+        self.values.iter().filter(|v| v.is_finite()).fold(None, |range, &v| match range {
+            Some((min, max)) => Some((v.min(min), v.max(max))),
+            None => Some((v, v)),
+        })
+    }
+}
+
+// Number of frames the statistics plots look back, about 5s in real time.
+const STATISTICS_HISTORY_LENGTH: usize = 300;
+
+// Per frame history of simulation statistics, to see trends like slowly rising energy or density error.
+pub struct StatisticsHistory {
+    pub kinetic_energy: TimeSeries,    // in J
+    pub max_density_error: TimeSeries, // relative
+    pub step_time: TimeSeries,         // processing time of all simulation steps of a frame in ms
+}
+
+impl StatisticsHistory {
+    pub fn new() -> StatisticsHistory {
+        StatisticsHistory {
+            kinetic_energy: TimeSeries::new(STATISTICS_HISTORY_LENGTH),
+            max_density_error: TimeSeries::new(STATISTICS_HISTORY_LENGTH),
+            step_time: TimeSeries::new(STATISTICS_HISTORY_LENGTH),
+        }
+    }
+
+    pub fn record(&mut self, statistics: &sph::SimulationStatistics, step_time: Duration) {
+        self.kinetic_energy.push(statistics.kinetic_energy);
+        self.max_density_error.push(statistics.max_density_error);
+        self.step_time.push(step_time.as_secs_f32() * 1000.0);
+    }
+
+    pub fn clear(&mut self) {
+        self.kinetic_energy.clear();
+        self.max_density_error.clear();
+        self.step_time.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn time_series_scrolls() {
+        let mut series = TimeSeries::new(3);
+        assert_eq!(series.latest(), None);
+        assert_eq!(series.range(), None);
+        for value in [4.0, 1.0, 2.0, 3.0].iter() {
+            series.push(*value);
+        }
+        assert_eq!(series.values().collect::<Vec<_>>(), vec![1.0, 2.0, 3.0]);
+        assert_eq!(series.latest(), Some(3.0));
+        assert_eq!(series.range(), Some((1.0, 3.0)));
+
+        series.push(f32::NAN);
+        assert_eq!(series.range(), Some((2.0, 3.0)));
+        series.clear();
+        assert_eq!(series.values().count(), 0);
+        assert_eq!(series.capacity(), 3);
+    }
+}
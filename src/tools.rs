@@ -0,0 +1,424 @@
+use cgmath::prelude::*;
+use yasph2d::sph;
+use yasph2d::units::*;
+
+// Pulls fluid particles towards a point (or pushes them away with negative strength).
+pub struct ForceTool {
+    pub radius: Real,   // in m
+    pub strength: Real, // accelleration in m/s² at the center, linearly falling off to zero at the radius
+}
+
+impl ForceTool {
+    pub fn new() -> ForceTool {
+        ForceTool {
+            radius: 0.2,
+            strength: 100.0,
+        }
+    }
+
+    // Applies the force as a velocity change over a timestep dt.
+    // `direction`: 1.0 for attraction, -1.0 for repulsion
+    pub fn apply(&self, fluid_world: &mut sph::FluidParticleWorld, center: Point, direction: Real, dt: Real) {
+        microprofile::scope!("ForceTool", "apply");
+
+        let mut affected_particles = Vec::new();
+        fluid_world
+            .particles
+            .foreach_fluid_particle_in_radius(center, self.radius, |i| affected_particles.push(i));
+
+        let particles = &mut fluid_world.particles;
+        for i in affected_particles {
+            let i = i as usize;
+            let to_center = center - particles.positions[i];
+            let distance = to_center.magnitude();
+            if distance < 1.0e-6 {
+                continue;
+            }
+            let falloff = 1.0 - distance / self.radius;
+            particles.velocities[i] += to_center / distance * (direction * self.strength * falloff * dt);
+        }
+    }
+}
+
+// Spawns resting fluid at rest spacing under the cursor, not overlapping any existing particles.
+pub struct FluidBrushTool {
+    pub radius: Real, // in m
+}
+
+impl FluidBrushTool {
+    pub fn new() -> FluidBrushTool {
+        FluidBrushTool { radius: 0.05 }
+    }
+
+    pub fn apply(&self, fluid_world: &mut sph::FluidParticleWorld, center: Point) {
+        microprofile::scope!("FluidBrushTool", "apply");
+        fluid_world.add_fluid_circle(center, self.radius);
+    }
+}
+
+// Places boundary polylines vertex by vertex.
+// Committed polylines are remembered so they can be restored after the scene was reset.
+pub struct BoundaryDrawTool {
+    pub active: bool,
+    pub thickness_in_particles: u32,
+    pending_vertices: Vec<Point>,
+    committed_polylines: Vec<Vec<Point>>,
+}
+
+impl BoundaryDrawTool {
+    pub fn new() -> BoundaryDrawTool {
+        BoundaryDrawTool {
+            active: false,
+            thickness_in_particles: 2,
+            pending_vertices: Vec::new(),
+            committed_polylines: Vec::new(),
+        }
+    }
+
+    pub fn pending_vertices(&self) -> &[Point] {
+        &self.pending_vertices
+    }
+
+    pub fn add_vertex(&mut self, position: Point) {
+        self.pending_vertices.push(position);
+    }
+
+    pub fn remove_last_vertex(&mut self) {
+        self.pending_vertices.pop();
+    }
+
+    pub fn discard_pending(&mut self) {
+        self.pending_vertices.clear();
+    }
+
+    // Turns the pending vertices into boundary particles.
+    pub fn commit(&mut self, fluid_world: &mut sph::FluidParticleWorld) {
+        if self.pending_vertices.len() >= 2 {
+            Self::add_polyline(fluid_world, &self.pending_vertices, self.thickness_in_particles);
+            self.committed_polylines.push(std::mem::take(&mut self.pending_vertices));
+        } else {
+            self.pending_vertices.clear();
+        }
+    }
+
+    // Adds all previously committed polylines to a (freshly reset) world.
+    pub fn restore(&self, fluid_world: &mut sph::FluidParticleWorld) {
+        for polyline in self.committed_polylines.iter() {
+            Self::add_polyline(fluid_world, polyline, self.thickness_in_particles);
+        }
+    }
+
+    fn add_polyline(fluid_world: &mut sph::FluidParticleWorld, vertices: &[Point], thickness_in_particles: u32) {
+        for segment in vertices.windows(2) {
+            if segment[0] != segment[1] {
+                fluid_world.add_boundary_thick_line(segment[0], segment[1], thickness_in_particles);
+            }
+        }
+    }
+}
+
+// Obstacle presets selectable in the gui, the first entry deactivates the obstacle tool.
+pub const OBSTACLE_PRESETS: [&str; 5] = ["None", "Cylinder", "Wedge", "Airfoil", "Stairs"];
+
+// Places parametric obstacles by dragging them into the scene with the left mouse button.
+// Placed obstacles are remembered so they can be restored after the scene was reset.
+pub struct ObstacleTool {
+    pub preset: usize, // index into OBSTACLE_PRESETS
+    pub size: Real,    // in m, diameter, length, chord or width of the obstacle depending on the preset
+    dragging: bool,
+    placed_obstacles: Vec<(sph::Obstacle, Point)>,
+}
+
+impl ObstacleTool {
+    pub fn new() -> ObstacleTool {
+        ObstacleTool {
+            preset: 0,
+            size: 0.3,
+            dragging: false,
+            placed_obstacles: Vec::new(),
+        }
+    }
+
+    pub fn active(&self) -> bool {
+        self.preset != 0
+    }
+
+    pub fn dragging(&self) -> bool {
+        self.dragging
+    }
+
+    // Obstacle that would be placed right now.
+    pub fn obstacle(&self) -> Option<sph::Obstacle> {
+        match self.preset {
+            1 => Some(sph::Obstacle::Cylinder { radius: self.size * 0.5 }),
+            2 => Some(sph::Obstacle::Wedge {
+                length: self.size,
+                half_angle: 15.0_f32.to_radians(),
+            }),
+            3 => Some(sph::Obstacle::Airfoil {
+                chord: self.size,
+                thickness: 0.12,
+                angle_of_attack: 5.0_f32.to_radians(),
+            }),
+            4 => Some(sph::Obstacle::Stairs {
+                num_steps: 4,
+                step_width: self.size / 4.0,
+                step_height: self.size / 8.0,
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn start_drag(&mut self) {
+        self.dragging = self.active();
+    }
+
+    // Places the dragged obstacle at the given position.
+    pub fn drop(&mut self, fluid_world: &mut sph::FluidParticleWorld, position: Point) {
+        if !self.dragging {
+            return;
+        }
+        self.dragging = false;
+        if let Some(obstacle) = self.obstacle() {
+            obstacle.add_to(fluid_world, position);
+            self.placed_obstacles.push((obstacle, position));
+        }
+    }
+
+    // Adds all previously placed obstacles to a (freshly reset) world.
+    pub fn restore(&self, fluid_world: &mut sph::FluidParticleWorld) {
+        for (obstacle, position) in self.placed_obstacles.iter() {
+            obstacle.add_to(fluid_world, *position);
+        }
+    }
+}
+
+// Drops floating boxes into the scene, their density relative to the fluid decides how deep they float.
+// Dropped boxes are remembered so they can be dropped again at the same spots after the scene was reset.
+pub struct FloatingBoxTool {
+    pub density_ratio: Real,
+    pub size: Real,                          // in m, width of the box, boxes are wider than high so they float upright
+    dropped_boxes: Vec<(Point, Real, Real)>, // center, size and density ratio
+    bodies: Vec<sph::RigidBody>,
+}
+
+impl FloatingBoxTool {
+    pub fn new() -> FloatingBoxTool {
+        FloatingBoxTool {
+            density_ratio: 0.5,
+            size: 0.3,
+            dropped_boxes: Vec::new(),
+            bodies: Vec::new(),
+        }
+    }
+
+    pub fn drop(&mut self, fluid_world: &mut sph::FluidParticleWorld, center: Point) {
+        self.dropped_boxes.push((center, self.size, self.density_ratio));
+        self.bodies.push(Self::add_box(fluid_world, center, self.size, self.density_ratio));
+    }
+
+    fn add_box(fluid_world: &mut sph::FluidParticleWorld, center: Point, size: Real, density_ratio: Real) -> sph::RigidBody {
+        sph::RigidBody::new_box(fluid_world, center, size, size * 0.4, density_ratio)
+    }
+
+    // Moves all boxes by the fluid forces of the last simulation step.
+    pub fn update(&mut self, fluid_world: &mut sph::FluidParticleWorld, dt: Real) {
+        for body in self.bodies.iter_mut() {
+            body.update(fluid_world, dt);
+        }
+    }
+
+    // Adds all previously dropped boxes to a (freshly reset) world.
+    pub fn restore(&mut self, fluid_world: &mut sph::FluidParticleWorld) {
+        self.bodies = self
+            .dropped_boxes
+            .iter()
+            .map(|&(center, size, density_ratio)| Self::add_box(fluid_world, center, size, density_ratio))
+            .collect();
+    }
+}
+
+// Click-to-inspect mode: the left mouse button selects the fluid particle under the cursor,
+// whose properties are shown in the overlay and whose neighbors are highlighted.
+// Every simulation step reorders the particles, so after each step the selection moves on to the particle closest to where the selected one went.
+pub struct ParticleInspectorTool {
+    pub active: bool,
+    selected: Option<(usize, Point, Vector)>, // index, position and velocity as of the last pick
+}
+
+impl ParticleInspectorTool {
+    pub fn new() -> ParticleInspectorTool {
+        ParticleInspectorTool {
+            active: false,
+            selected: None,
+        }
+    }
+
+    pub fn selected(&self) -> Option<usize> {
+        self.selected.map(|(index, _, _)| index)
+    }
+
+    pub fn deselect(&mut self) {
+        self.selected = None;
+    }
+
+    // Selects the particle under the cursor, or nothing if there is none.
+    pub fn select(&mut self, fluid_world: &sph::FluidParticleWorld, position: Point) {
+        self.selected = Self::pick(fluid_world, position, fluid_world.properties.particle_radius());
+    }
+
+    // Follows the selected particle through a simulation step of length dt.
+    pub fn follow(&mut self, fluid_world: &sph::FluidParticleWorld, dt: Real) {
+        if let Some((_, position, velocity)) = self.selected {
+            // a particle moves less than its diameter per step unless the timestep breaks the CFL condition
+            self.selected = Self::pick(fluid_world, position + velocity * dt, fluid_world.properties.particle_radius() * 2.0);
+        }
+    }
+
+    // Closest fluid particle within a radius, via the neighborhood search.
+    fn pick(fluid_world: &sph::FluidParticleWorld, position: Point, radius: Real) -> Option<(usize, Point, Vector)> {
+        let particles = &fluid_world.particles;
+        let mut closest: Option<(usize, Real)> = None;
+        particles.foreach_fluid_particle_in_radius(position, radius, |j| {
+            let distance_sq = particles.positions[j as usize].distance2(position);
+            let is_closer = match closest {
+                Some((_, closest_distance_sq)) => distance_sq < closest_distance_sq,
+                None => true,
+            };
+            if is_closer {
+                closest = Some((j as usize, distance_sq));
+            }
+        });
+        closest.map(|(i, _)| (i, particles.positions[i], particles.velocities[i]))
+    }
+
+    // Fluid particles within the smoothing length of the selected particle, without the particle itself.
+    pub fn neighbors(&self, fluid_world: &sph::FluidParticleWorld) -> Vec<usize> {
+        let mut neighbors = Vec::new();
+        if let Some(selected) = self.selected() {
+            let position = fluid_world.particles.positions[selected];
+            fluid_world
+                .particles
+                .foreach_fluid_particle_in_radius(position, fluid_world.properties.smoothing_length(), |j| {
+                    if j as usize != selected {
+                        neighbors.push(j as usize);
+                    }
+                });
+        }
+        neighbors
+    }
+
+    // Overlay text for the selected particle.
+    pub fn description(&self, fluid_world: &sph::FluidParticleWorld, solver: &dyn sph::Solver) -> Option<String> {
+        let selected = self.selected()?;
+        let particles = &fluid_world.particles;
+        let index = selected as sph::neighborhood_search::ParticleIndex;
+        let density = particles.densities.get(selected).cloned().unwrap_or(0.0);
+        let velocity = particles.velocities[selected];
+        Some(format!(
+            "Particle {}: position ({:.3}, {:.3})m, velocity ({:.3}, {:.3})m/s ({:.3}m/s)\nDensity {:.2}kg/m² ({:+.2}%), pressure {}, {} neighbors",
+            selected,
+            particles.positions[selected].x,
+            particles.positions[selected].y,
+            velocity.x,
+            velocity.y,
+            velocity.magnitude(),
+            density,
+            (density / fluid_world.properties.fluid_density() - 1.0) * 100.0,
+            solver
+                .particle_pressure(fluid_world, index)
+                .map_or("-".to_string(), |pressure| format!("{:.1}N/m", pressure)),
+            particles.num_neighbors(index),
+        ))
+    }
+}
+
+// Actions of the region select tool selectable in the gui, the first entry deactivates the tool.
+pub const REGION_ACTIONS: [&str; 5] = ["None", "Impulse", "Stop", "Delete", "Color"];
+// Colors the region select tool can tag particles with, index is the tag. Particles with tag 0 use the visualization's color map.
+pub const REGION_COLORS: [&str; 5] = ["Color map", "Red", "Green", "Blue", "Yellow"];
+
+// Bulk edit of fluid particles: dragging a rectangle with the left mouse button applies the selected action
+// to all fluid particles within it once the button is released.
+pub struct RegionSelectTool {
+    pub action: usize,   // index into REGION_ACTIONS
+    pub impulse: Vector, // in m/s, velocity change of the impulse action
+    pub color: usize,    // index into REGION_COLORS, tag set by the color action
+    drag_start: Option<Point>,
+}
+
+impl RegionSelectTool {
+    pub fn new() -> RegionSelectTool {
+        RegionSelectTool {
+            action: 0,
+            impulse: Vector::new(0.0, 2.0),
+            color: 1,
+            drag_start: None,
+        }
+    }
+
+    pub fn active(&self) -> bool {
+        self.action != 0
+    }
+
+    pub fn dragging(&self) -> bool {
+        self.drag_start.is_some()
+    }
+
+    pub fn start_drag(&mut self, position: Point) {
+        if self.active() {
+            self.drag_start = Some(position);
+        }
+    }
+
+    // Region spanned by the ongoing drag as min and max corner.
+    pub fn region(&self, cursor_position: Point) -> Option<(Point, Point)> {
+        self.drag_start.map(|start| {
+            (
+                Point::new(start.x.min(cursor_position.x), start.y.min(cursor_position.y)),
+                Point::new(start.x.max(cursor_position.x), start.y.max(cursor_position.y)),
+            )
+        })
+    }
+
+    // Applies the selected action to the dragged region. Deleting particles changes particle indices, so the solver needs to drop their data as well.
+    pub fn drop(&mut self, fluid_world: &mut sph::FluidParticleWorld, solver: &mut dyn sph::Solver, position: Point) {
+        let (min, max) = match self.region(position) {
+            Some(region) => region,
+            None => return,
+        };
+        self.drag_start = None;
+
+        // linear search instead of the neighborhood search, so particles added since the last step are included
+        let particles = &mut fluid_world.particles;
+        let selected: Vec<bool> = particles
+            .positions
+            .iter()
+            .map(|p| p.x >= min.x && p.x <= max.x && p.y >= min.y && p.y <= max.y)
+            .collect();
+        match REGION_ACTIONS[self.action] {
+            "Impulse" => {
+                for (velocity, _) in particles.velocities.iter_mut().zip(selected.iter()).filter(|(_, selected)| **selected) {
+                    *velocity += self.impulse;
+                }
+            }
+            "Stop" => {
+                for (velocity, _) in particles.velocities.iter_mut().zip(selected.iter()).filter(|(_, selected)| **selected) {
+                    *velocity = Vector::zero();
+                }
+            }
+            "Delete" => {
+                let keep: Vec<bool> = selected.iter().map(|selected| !selected).collect();
+                fluid_world.retain_fluid_particles(&keep);
+                solver.retain_particle_data(&keep);
+            }
+            "Color" => {
+                particles.tags.resize(particles.positions.len(), 0);
+                for (tag, _) in particles.tags.iter_mut().zip(selected.iter()).filter(|(_, selected)| **selected) {
+                    *tag = self.color as u32;
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -1,4 +1,182 @@
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
 // For simulating
 pub type Real = f32;
 pub type Point = cgmath::Point2<Real>;
 pub type Vector = cgmath::Vector2<Real>;
+
+// Axis aligned rectangle, x & y being the corner with the smallest coordinates.
+// The one of ggez, so that the default viewer can pass its own, ggez doesn't build for the web though.
+#[cfg(target_arch = "wasm32")]
+pub use self::web::Rect;
+#[cfg(not(target_arch = "wasm32"))]
+pub use ggez::graphics::Rect;
+
+#[cfg(target_arch = "wasm32")]
+mod web {
+    use super::Real;
+
+    // What the simulation needs of ggez::graphics::Rect.
+    #[derive(Clone, Copy, Debug, Default, PartialEq)]
+    pub struct Rect {
+        pub x: Real,
+        pub y: Real,
+        pub w: Real,
+        pub h: Real,
+    }
+
+    impl Rect {
+        pub const fn new(x: Real, y: Real, w: Real, h: Real) -> Rect {
+            Rect { x, y, w, h }
+        }
+    }
+}
+
+// Typed physical quantities, so that parameters that are easily mixed up are told apart at compile time,
+// e.g. the particle density and the fluid density a fluid world is created with.
+//
+// Deliberately lightweight: Plain newtypes in SI units, convertible to Real with `.0` and only with operations that have a well defined result.
+// The simulation itself works on Real, quantities are meant for the places where values enter or leave it.
+// As everything is 2D, densities are per m² and pressures are forces per m.
+macro_rules! quantity {
+    ($name:ident, $unit:expr) => {
+        #[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+        pub struct $name(pub Real);
+
+        impl $name {
+            pub const UNIT: &'static str = $unit;
+        }
+
+        impl Add for $name {
+            type Output = $name;
+            fn add(self, other: $name) -> $name {
+                $name(self.0 + other.0)
+            }
+        }
+
+        impl Sub for $name {
+            type Output = $name;
+            fn sub(self, other: $name) -> $name {
+                $name(self.0 - other.0)
+            }
+        }
+
+        impl Neg for $name {
+            type Output = $name;
+            fn neg(self) -> $name {
+                $name(-self.0)
+            }
+        }
+
+        impl Mul<Real> for $name {
+            type Output = $name;
+            fn mul(self, factor: Real) -> $name {
+                $name(self.0 * factor)
+            }
+        }
+
+        impl Mul<$name> for Real {
+            type Output = $name;
+            fn mul(self, quantity: $name) -> $name {
+                $name(self * quantity.0)
+            }
+        }
+
+        impl Div<Real> for $name {
+            type Output = $name;
+            fn div(self, divisor: Real) -> $name {
+                $name(self.0 / divisor)
+            }
+        }
+
+        // ratio of two quantities of the same kind
+        impl Div for $name {
+            type Output = Real;
+            fn div(self, other: $name) -> Real {
+                self.0 / other.0
+            }
+        }
+    };
+}
+
+// a * b = c, including the division of c by either factor.
+macro_rules! quantity_product {
+    ($a:ident * $b:ident = $c:ident) => {
+        impl Mul<$b> for $a {
+            type Output = $c;
+            fn mul(self, other: $b) -> $c {
+                $c(self.0 * other.0)
+            }
+        }
+
+        impl Mul<$a> for $b {
+            type Output = $c;
+            fn mul(self, other: $a) -> $c {
+                $c(self.0 * other.0)
+            }
+        }
+
+        impl Div<$a> for $c {
+            type Output = $b;
+            fn div(self, other: $a) -> $b {
+                $b(self.0 / other.0)
+            }
+        }
+
+        impl Div<$b> for $c {
+            type Output = $a;
+            fn div(self, other: $b) -> $a {
+                $a(self.0 / other.0)
+            }
+        }
+    };
+}
+
+quantity!(Length, "m");
+quantity!(Time, "s");
+quantity!(Velocity, "m/s");
+quantity!(Acceleration, "m/s²");
+quantity!(Mass, "kg");
+quantity!(Density, "kg/m²");
+quantity!(NumberDensity, "1/m²"); // e.g. particles per area
+quantity!(Pressure, "N/m");
+
+quantity_product!(Velocity * Time = Length);
+quantity_product!(Acceleration * Time = Velocity);
+quantity_product!(Mass * NumberDensity = Density);
+
+impl Density {
+    // Pressure at a depth below the surface of a fluid at rest, p = ρ g h.
+    pub fn hydrostatic_pressure(self, gravity: Acceleration, depth: Length) -> Pressure {
+        Pressure(self.0 * gravity.0 * depth.0)
+    }
+
+    // Bulk modulus K = ρ c² of a fluid with the given speed of sound.
+    pub fn bulk_modulus(self, speed_of_sound: Velocity) -> Pressure {
+        Pressure(self.0 * speed_of_sound.0 * speed_of_sound.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quantity_arithmetic() {
+        let velocity = Length(3.0) / Time(2.0);
+        assert_eq!(velocity, Velocity(1.5));
+        assert_eq!(velocity / Time(0.5), Acceleration(3.0));
+        assert_eq!(Acceleration(3.0) * Time(2.0) - Velocity(1.0), Velocity(5.0));
+        assert_eq!(Length(1.0) / Length(4.0), 0.25);
+        assert_eq!(2.0 * -Length(1.5), Length(-3.0));
+
+        // particle mass from the fluid's density and the number of particles per area
+        let mass = Density(1000.0) / NumberDensity(10000.0);
+        assert_eq!(mass, Mass(0.1));
+        assert_eq!(mass * NumberDensity(10000.0), Density(1000.0));
+
+        assert_eq!(Density(1000.0).hydrostatic_pressure(Acceleration(10.0), Length(0.5)), Pressure(5000.0));
+        assert_eq!(Density(1000.0).bulk_modulus(Velocity(10.0)), Pressure(100000.0));
+        assert_eq!(Pressure::UNIT, "N/m");
+    }
+}
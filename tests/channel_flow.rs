@@ -0,0 +1,80 @@
+use more_asserts::*;
+use yasph2d::sph::scenes::ChannelFlow;
+use yasph2d::sph::{self, Solver};
+use yasph2d::units::*;
+
+// Channel flow through an inlet and an outlet, checking that the open boundaries maintain a steady flow.
+// Prints particle count and flow rate over time with `cargo test --test channel_flow -- --nocapture`
+
+const INFLOW_SPEED: Real = 0.5;
+const SIMULATION_TIME: Real = 2.0; // long enough for inflowing fluid to make it through the entire channel
+
+const SCENE: ChannelFlow = ChannelFlow {
+    channel_length: 1.0,
+    channel_height: 0.2,
+    inflow: sph::InflowProfile::Uniform(INFLOW_SPEED),
+};
+
+fn check_steady_flow(create_solver: impl Fn(&sph::FluidParticleWorld) -> Box<dyn Solver>, timestep: Real) {
+    let (mut fluid_world, inlet, outlet) = SCENE.create_world(NumberDensity(5000.0), Density(100.0));
+    let mut solver = create_solver(&fluid_world);
+    let mut time_manager = sph::TimeManager::new(sph::TimeManagerConfiguration::FixedTimeStep(timestep));
+    solver.set_watchdog(Some(sph::Watchdog::new(10.0)));
+    let num_particles_initial = fluid_world.particles.positions.len() as Real;
+    let expected_mass_flow_rate = fluid_world.properties.fluid_density() * INFLOW_SPEED * SCENE.channel_height;
+    // vertical line in the middle of the channel, crossing the entire flow
+    let flux_line = sph::FluxLine::new(
+        Point::new(SCENE.channel_length * 0.5, -0.1),
+        Point::new(SCENE.channel_length * 0.5, SCENE.channel_height + 0.1),
+    );
+
+    let mut next_check_time = 0.0;
+    while time_manager.passed_time() < SIMULATION_TIME {
+        outlet.update(&mut fluid_world, solver.as_mut());
+        inlet.update(&mut fluid_world);
+        solver.simulation_step(&mut fluid_world, &mut time_manager).unwrap();
+        if let Some(alarm) = solver.watchdog().unwrap().alarm() {
+            panic!("{}", alarm);
+        }
+
+        let time = time_manager.passed_time();
+        if time < next_check_time {
+            continue;
+        }
+        next_check_time += 0.25;
+        let num_particles = fluid_world.particles.positions.len() as Real;
+        let mass_flow_rate = flux_line.measure(&fluid_world).mass_flow_rate;
+        println!("{:.2}s: {} particles, mass flow {:.3}kg/(m*s)", time, num_particles, mass_flow_rate);
+        // past the initial transient
+        if time > 1.0 {
+            assert_lt!((num_particles / num_particles_initial - 1.0).abs(), 0.05);
+            assert_lt!((mass_flow_rate / expected_mass_flow_rate - 1.0).abs(), 0.05);
+        }
+    }
+}
+
+#[test]
+fn channel_flow_wcsph() {
+    check_steady_flow(
+        |fluid_world| {
+            Box::new(sph::WCSPHSolver::new(
+                sph::XSPHViscosityModel::new(fluid_world.properties.smoothing_length()),
+                &fluid_world.properties,
+            ))
+        },
+        0.0005,
+    );
+}
+
+#[test]
+fn channel_flow_dfsph() {
+    check_steady_flow(
+        |fluid_world| {
+            Box::new(sph::DFSPHSolver::new(
+                sph::XSPHViscosityModel::new(fluid_world.properties.smoothing_length()),
+                fluid_world.properties.smoothing_length(),
+            ))
+        },
+        0.002,
+    );
+}
@@ -0,0 +1,48 @@
+// Fixture of the scene validation tests: a scene's fluid world simulated with DFSPH and the viewer's adaptive timesteps.
+// The tests print their measurements, see them with `cargo test --test <name> -- --nocapture`
+
+// not every test uses everything
+#![allow(dead_code)]
+
+use yasph2d::sph::{self, Solver};
+use yasph2d::units::*;
+
+pub struct SceneRun {
+    pub fluid_world: sph::FluidParticleWorld,
+    pub solver: sph::DFSPHSolver<sph::XSPHViscosityModel>,
+    pub time_manager: sph::TimeManager,
+}
+
+impl SceneRun {
+    pub fn new(fluid_world: sph::FluidParticleWorld) -> SceneRun {
+        let viscosity_model = sph::XSPHViscosityModel::new(fluid_world.properties.smoothing_length());
+        SceneRun::with_viscosity_model(fluid_world, viscosity_model)
+    }
+
+    pub fn with_viscosity_model(fluid_world: sph::FluidParticleWorld, viscosity_model: sph::XSPHViscosityModel) -> SceneRun {
+        let solver = sph::DFSPHSolver::new(viscosity_model, fluid_world.properties.smoothing_length());
+        let time_manager = sph::TimeManager::new(sph::TimeManagerConfiguration::AdaptiveTimeStep {
+            timestep_max: 0.002,
+            timestep_min: 0.00001,
+            timestep_target_frame: sph::AdaptiveTimeStepTarget::None,
+            cfl_factor: 0.5,
+        });
+        SceneRun {
+            fluid_world,
+            solver,
+            time_manager,
+        }
+    }
+
+    pub fn time(&self) -> Real {
+        self.time_manager.passed_time()
+    }
+
+    // Steps until the simulated time reaches end_time, calling after_step after every step.
+    pub fn run_until(&mut self, end_time: Real, mut after_step: impl FnMut(&mut sph::FluidParticleWorld, &sph::TimeManager)) {
+        while self.time_manager.passed_time() < end_time {
+            self.solver.simulation_step(&mut self.fluid_world, &mut self.time_manager).unwrap();
+            after_step(&mut self.fluid_world, &self.time_manager);
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use cgmath::prelude::*;
+use more_asserts::*;
+use rand::prelude::*;
+use yasph2d::sph::{self, Solver};
+use yasph2d::units::*;
+
+// Blob of fluid falling freely through a closed box, checking global conservation laws.
+// The blob starts compressed and with random velocities, so pressure and viscosity forces are strong, but they are internal:
+// If every pair of particles acts with equal and opposite forces on each other, they cancel out and the total momentum only changes by gravity.
+// Catches asymmetric force computations, e.g. a pressure term that uses only one of the two particles' pressures.
+
+const BOX_SIZE: Real = 2.0;
+const BLOB_SIZE: Real = 0.3;
+const INITIAL_SPEED: Real = 0.2;
+const TIMESTEP: Real = 0.0005;
+// Short enough that the blob doesn't reach the walls.
+const SIMULATION_TIME: Real = 0.25;
+
+// compression: Initial particle spacing relative to rest spacing.
+// mass_variation: Particle masses are randomly scaled by a factor within 1 ± mass_variation.
+fn create_world(compression: Real, mass_variation: Real) -> sph::FluidParticleWorld {
+    let mut fluid_world = sph::FluidParticleWorld::new(2.0, NumberDensity(5000.0), Density(100.0));
+
+    // thick lines grow to the right of the line direction, so go around clockwise
+    let corners = [
+        Point::new(0.0, 0.0),
+        Point::new(0.0, BOX_SIZE),
+        Point::new(BOX_SIZE, BOX_SIZE),
+        Point::new(BOX_SIZE, 0.0),
+    ];
+    for i in 0..corners.len() {
+        fluid_world.add_boundary_thick_line(corners[i], corners[(i + 1) % corners.len()], 2);
+    }
+
+    let mut rng: rand::rngs::SmallRng = rand::SeedableRng::seed_from_u64(123456789);
+    let spacing = fluid_world.properties.particle_radius() * 2.0 * compression;
+    let num_particles_per_axis = (BLOB_SIZE / spacing) as usize;
+    let blob_min = Point::new(BOX_SIZE * 0.5 - BLOB_SIZE * 0.5, BOX_SIZE * 0.7 - BLOB_SIZE * 0.5);
+    for x in 0..num_particles_per_axis {
+        for y in 0..num_particles_per_axis {
+            let position = blob_min + Vector::new(x as Real, y as Real) * spacing;
+            let velocity = (rng.gen::<Vector>() - Vector::new(0.5, 0.5)) * 2.0 * INITIAL_SPEED;
+            let mass = fluid_world.properties.particle_mass() * (1.0 + (rng.gen::<Real>() * 2.0 - 1.0) * mass_variation);
+            fluid_world.add_fluid_particle_with_mass(position, velocity, mass);
+        }
+    }
+    fluid_world
+}
+
+fn total_momentum(fluid_world: &sph::FluidParticleWorld) -> Vector {
+    let particles = &fluid_world.particles;
+    particles.velocities.iter().zip(particles.masses.iter()).map(|(v, m)| v * *m).sum()
+}
+
+fn total_mass(fluid_world: &sph::FluidParticleWorld) -> Real {
+    fluid_world.particles.masses.iter().sum()
+}
+
+fn check_conservation(solver: &mut dyn Solver, mut fluid_world: sph::FluidParticleWorld) {
+    let mut time_manager = sph::TimeManager::new(sph::TimeManagerConfiguration::FixedTimeStep(TIMESTEP));
+    let num_particles = fluid_world.particles.positions.len();
+    let total_mass = total_mass(&fluid_world);
+    // Start measuring after the first step: WCSPH's leap frog integration only applies half of the first step's accelleration.
+    solver.simulation_step(&mut fluid_world, &mut time_manager).unwrap();
+    let initial_momentum = total_momentum(&fluid_world);
+    let initial_time = time_manager.passed_time();
+
+    while time_manager.passed_time() < SIMULATION_TIME {
+        solver.simulation_step(&mut fluid_world, &mut time_manager).unwrap();
+
+        // Otherwise walls would add an external force.
+        for i in 0..fluid_world.particles.positions.len() {
+            assert_eq!(
+                fluid_world.particles.neighborhood().num_boundary_neighbors(i as u32),
+                0,
+                "blob hit the walls at {}s",
+                time_manager.passed_time()
+            );
+        }
+    }
+
+    // mass
+    assert_eq!(fluid_world.particles.positions.len(), num_particles);
+    assert_eq!(fluid_world.particles.velocities.len(), num_particles);
+    assert_eq!(fluid_world.particles.masses.len(), num_particles);
+    assert_lt!((self::total_mass(&fluid_world) - total_mass).abs(), total_mass * 1.0e-5);
+    for p in fluid_world.particles.positions.iter() {
+        assert!(
+            p.x > 0.0 && p.y > 0.0 && p.x < BOX_SIZE && p.y < BOX_SIZE,
+            "particle left the box: {:?}",
+            p
+        );
+    }
+
+    // momentum
+    let gravity_impulse = fluid_world.gravity() * total_mass * (time_manager.passed_time() - initial_time);
+    let momentum_change = total_momentum(&fluid_world) - initial_momentum;
+    let error = (momentum_change - gravity_impulse).magnitude() / gravity_impulse.magnitude();
+    println!(
+        "momentum change {:?}, gravity impulse {:?}, relative error {}",
+        momentum_change, gravity_impulse, error
+    );
+    assert_lt!(error, 0.001);
+}
+
+#[test]
+fn conservation_wcsph() {
+    let fluid_world = create_world(0.95, 0.0);
+    let mut solver = sph::WCSPHSolver::new(
+        sph::XSPHViscosityModel::new(fluid_world.properties.smoothing_length()),
+        &fluid_world.properties,
+    );
+    check_conservation(&mut solver, fluid_world);
+}
+
+#[test]
+fn conservation_dfsph() {
+    // DFSPH removes all compression within a single step, shooting particles off the blob's corners.
+    let fluid_world = create_world(1.0, 0.0);
+    let mut solver = sph::DFSPHSolver::new(
+        sph::XSPHViscosityModel::new(fluid_world.properties.smoothing_length()),
+        fluid_world.properties.smoothing_length(),
+    );
+    check_conservation(&mut solver, fluid_world);
+}
+
+#[test]
+fn conservation_mixed_masses() {
+    // Pairwise forces need to be weighted with the respective other particle's mass to stay symmetric.
+    let fluid_world = create_world(0.95, 0.3);
+    let mut solver = sph::WCSPHSolver::new(
+        sph::XSPHViscosityModel::new(fluid_world.properties.smoothing_length()),
+        &fluid_world.properties,
+    );
+    check_conservation(&mut solver, fluid_world);
+
+    // Like initial compression, DFSPH evens out density variations from differing masses within a single step.
+    let fluid_world = create_world(1.0, 0.1);
+    let mut solver = sph::DFSPHSolver::new(
+        sph::XSPHViscosityModel::new(fluid_world.properties.smoothing_length()),
+        fluid_world.properties.smoothing_length(),
+    );
+    check_conservation(&mut solver, fluid_world);
+}
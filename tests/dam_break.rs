@@ -0,0 +1,49 @@
+mod common;
+
+use common::SceneRun;
+use more_asserts::*;
+use yasph2d::sph::{self, scenes::DamBreak};
+use yasph2d::units::*;
+
+// Dam break with the setup of Martin & Moyce 1952, comparing the surge front position against their measurements.
+// Prints the error over time, so solver changes can be evaluated quantitatively.
+
+const COLUMN_WIDTH: Real = 0.2;
+const PARTICLE_DENSITY: Real = 5000.0;
+// Simulate until this dimensionless time. Later measurements are influenced by the tank's size.
+const END_TIME_DIMENSIONLESS: Real = 3.0;
+
+fn surge_front_error(scene: &DamBreak, mut run: SceneRun) -> Real {
+    let gravity = -run.fluid_world.gravity().y;
+    // dimensionless time is proportional to the time
+    let time_scale = scene.dimensionless_time(1.0, gravity);
+
+    // sample at the reference's time points
+    let mut squared_error_sum = 0.0;
+    let mut num_samples = 0;
+    for &(reference_time, reference_front) in sph::scenes::MARTIN_MOYCE_SURGE_FRONT.iter() {
+        if reference_time > END_TIME_DIMENSIONLESS {
+            break;
+        }
+        run.run_until(reference_time / time_scale, |_, _| {});
+        let time = scene.dimensionless_time(run.time(), gravity);
+        // we may have overshot the sample time a bit
+        let reference_front = DamBreak::reference_surge_front(time).unwrap_or(reference_front);
+        let front = scene.surge_front(&run.fluid_world) / scene.column_width;
+        println!("T = {:.2}: Z = {:.2}, reference {:.2}", time, front, reference_front);
+
+        squared_error_sum += (front - reference_front) * (front - reference_front);
+        num_samples += 1;
+    }
+    let rms_error = (squared_error_sum / num_samples as Real).sqrt();
+    println!("RMS error of Z: {:.3}", rms_error);
+    rms_error
+}
+
+#[test]
+fn dam_break_dfsph() {
+    let scene = DamBreak::martin_moyce(COLUMN_WIDTH);
+    let fluid_world = scene.create_world(NumberDensity(PARTICLE_DENSITY), Density(100.0));
+    // Measurements include friction on the tank's floor which we don't model, so we expect to be a bit faster.
+    assert_lt!(surge_front_error(&scene, SceneRun::new(fluid_world)), 0.2);
+}
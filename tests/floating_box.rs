@@ -0,0 +1,61 @@
+mod common;
+
+use common::SceneRun;
+use more_asserts::*;
+use yasph2d::sph::{self, scenes::FloatingBox};
+use yasph2d::units::*;
+
+// Archimedes' principle: boxes of different densities released at the surface settle at a draught proportional to their density.
+// Prints the measured and expected draughts.
+
+const PARTICLE_DENSITY: Real = 5000.0;
+const SETTLING_TIME: Real = 3.0;
+const MEASUREMENT_END: Real = 4.0;
+
+fn check_equilibrium_draught(density_ratio: Real) {
+    let scene = FloatingBox {
+        tank_width: 2.0,
+        tank_height: 0.6,
+        water_depth: 0.3,
+        box_width: 0.4,
+        box_height: 0.16,
+        density_ratio,
+    };
+    let (fluid_world, mut body) = scene.create_world(NumberDensity(PARTICLE_DENSITY), Density(100.0));
+    let spacing = fluid_world.properties.particle_radius() * 2.0;
+    let mut run = SceneRun::new(fluid_world);
+    // away from the box and the walls
+    let gauge = sph::WaveGauge::new(scene.tank_width * 0.2, 0.0, scene.tank_height);
+
+    let mut draughts = Vec::new();
+    run.run_until(MEASUREMENT_END, |fluid_world, time_manager| {
+        body.update(fluid_world, time_manager.timestep());
+        if time_manager.passed_time() > SETTLING_TIME {
+            draughts.push(body.draught(gauge.measure(fluid_world)));
+        }
+    });
+
+    // waves from the box's release slosh around the closed tank for a long time, the box keeps heaving a little
+    let draught = draughts.iter().sum::<Real>() / draughts.len() as Real;
+    println!(
+        "density ratio {}: draught {}m, expected {}m, angle {}",
+        density_ratio,
+        draught,
+        scene.expected_draught(),
+        body.angle
+    );
+    // the fluid keeps some distance to the box, which displaces a bit more than its outline
+    assert_lt!((draught - scene.expected_draught()).abs(), spacing);
+    // floats upright
+    assert_lt!(body.angle.abs(), 0.05);
+}
+
+#[test]
+fn floating_box_light() {
+    check_equilibrium_draught(0.3);
+}
+
+#[test]
+fn floating_box_heavy() {
+    check_equilibrium_draught(0.7);
+}
@@ -0,0 +1,77 @@
+use more_asserts::*;
+use yasph2d::sph::scenes::FlowPastCylinder;
+use yasph2d::sph::{self, Solver};
+use yasph2d::units::*;
+
+// Flow past a cylinder at a low Reynolds number, comparing the drag coefficient against the reference for unbounded flow.
+// Prints drag, lift and the wake probe over time with `cargo test --test flow_past_cylinder -- --nocapture`
+
+const REYNOLDS_NUMBER: Real = 40.0;
+const SIMULATION_TIME: Real = 2.0;
+const AVERAGING_START: Real = 1.0; // past the initial transient of the impulsively started flow
+
+const SCENE: FlowPastCylinder = FlowPastCylinder {
+    channel_length: 1.2,
+    channel_height: 0.4,
+    cylinder_center: Point::new(0.3, 0.2),
+    cylinder_radius: 0.05,
+    inflow_speed: 0.5,
+};
+
+#[test]
+fn flow_past_cylinder_dfsph() {
+    let (mut fluid_world, inlet, outlet) = SCENE.create_world(NumberDensity(5000.0), Density(100.0));
+    let smoothing_length = fluid_world.properties.smoothing_length();
+    let mut viscosity_model = sph::PhysicalViscosityModel::new(smoothing_length);
+    viscosity_model.fluid_viscosity = SCENE.kinematic_viscosity(REYNOLDS_NUMBER) * fluid_world.properties.fluid_density();
+    let mut solver = sph::DFSPHSolver::new(viscosity_model, smoothing_length);
+    let mut time_manager = sph::TimeManager::new(sph::TimeManagerConfiguration::FixedTimeStep(0.002));
+    solver.set_watchdog(Some(sph::Watchdog::new(10.0)));
+    let mut probes = SCENE.wake_probes();
+
+    let mut drag_sum = 0.0;
+    let mut lift_sum = 0.0;
+    let mut num_samples = 0;
+    let mut next_print_time = 0.0;
+    while time_manager.passed_time() < SIMULATION_TIME {
+        outlet.update(&mut fluid_world, &mut solver);
+        inlet.update(&mut fluid_world);
+        solver.simulation_step(&mut fluid_world, &mut time_manager).unwrap();
+        if let Some(alarm) = solver.watchdog().unwrap().alarm() {
+            panic!("{}", alarm);
+        }
+
+        let time = time_manager.passed_time();
+        probes.record(&fluid_world, &solver, time);
+        let coefficients = SCENE.force_coefficients(&fluid_world);
+        if time >= AVERAGING_START {
+            drag_sum += coefficients.drag;
+            lift_sum += coefficients.lift;
+            num_samples += 1;
+        }
+        if time >= next_print_time {
+            next_print_time += 0.1;
+            let wake = probes.probes()[1].last_sample().unwrap();
+            println!(
+                "{:.2}s: drag {:.3}, lift {:.3}, wake density {:.1}kg/m², velocity ({:.3}, {:.3})m/s",
+                time, coefficients.drag, coefficients.lift, wake.density, wake.velocity.x, wake.velocity.y
+            );
+        }
+    }
+
+    let mean_drag = drag_sum / num_samples as Real;
+    let mean_lift = lift_sum / num_samples as Real;
+    let reference_drag = FlowPastCylinder::reference_drag_coefficient(REYNOLDS_NUMBER).unwrap();
+    println!("mean drag {:.3} (reference {:.3}), mean lift {:.3}", mean_drag, reference_drag, mean_lift);
+
+    // Above the reference: DFSPH clamps density loss, i.e. there is no suction that would pull the fluid in behind the cylinder.
+    // Instead, an empty cavity trails the cylinder (the wake probe reads no fluid), which together with the confinement by the channel walls
+    // raises the drag. Bounds are meant to catch regressions until solvers support negative pressures.
+    assert_gt!(mean_drag, reference_drag);
+    assert_lt!(mean_drag, reference_drag * 2.5);
+    // symmetric setup
+    assert_lt!(mean_lift.abs(), mean_drag * 0.2);
+    // the upstream probe sees the undisturbed inflow
+    let upstream_velocity = probes.probes()[0].last_sample().unwrap().velocity;
+    assert_lt!((upstream_velocity.x / SCENE.inflow_speed - 1.0).abs(), 0.3);
+}
@@ -0,0 +1,186 @@
+use cgmath::prelude::*;
+use more_asserts::*;
+use yasph2d::sph::smoothing_kernel::{Kernel, Spiky};
+use yasph2d::sph::{self, Solver};
+use yasph2d::units::*;
+
+// Water column at rest in a box.
+// Once in equilibrium, pressure has to carry the weight of the fluid above, p(y) = ρ0 g (H - y) with H being the height of the surface.
+// Catches sign & scale errors in the pressure computation as well as boundaries that let particles sink in or push them up.
+
+const COLUMN_WIDTH: Real = 0.3;
+const COLUMN_HEIGHT: Real = 0.4;
+const TIMESTEP: Real = 0.0005;
+const SETTLE_TIME: Real = 2.0;
+
+// Speed of sound of the weakly compressible fluid
+const SPEED_OF_SOUND: Velocity = Velocity(10.0);
+const TARGET_DENSITY_VARIATION: Real = 0.01;
+
+fn simulate_water_column(integrator: Box<dyn sph::Integrator>) -> (sph::FluidParticleWorld, sph::WCSPHSolver<sph::XSPHViscosityModel>) {
+    let mut fluid_world = sph::FluidParticleWorld::new(2.0, NumberDensity(5000.0), Density(100.0));
+    let spacing = fluid_world.properties.particle_radius() * 2.0;
+    fluid_world.add_fluid_rect(&Rect::new(spacing, spacing, COLUMN_WIDTH - spacing * 2.0, COLUMN_HEIGHT), 0.0);
+    fluid_world.add_boundary_thick_line(Point::new(0.0, 0.0), Point::new(COLUMN_WIDTH, 0.0), 2);
+    // thick lines grow to the right of the line direction
+    fluid_world.add_boundary_thick_line(Point::new(0.0, COLUMN_HEIGHT * 2.0), Point::new(0.0, 0.0), 2);
+    fluid_world.add_boundary_thick_line(Point::new(COLUMN_WIDTH, 0.0), Point::new(COLUMN_WIDTH, COLUMN_HEIGHT * 2.0), 2);
+
+    let mut solver = sph::WCSPHSolver::new(
+        sph::XSPHViscosityModel::new(fluid_world.properties.smoothing_length()),
+        &fluid_world.properties,
+    )
+    .with_target_compressibility(TARGET_DENSITY_VARIATION, SPEED_OF_SOUND * TARGET_DENSITY_VARIATION.sqrt());
+    assert_lt!((solver.speed_of_sound() - SPEED_OF_SOUND).0.abs(), 1.0e-4);
+    solver.set_integrator(integrator);
+    let mut time_manager = sph::TimeManager::new(sph::TimeManagerConfiguration::FixedTimeStep(TIMESTEP));
+
+    let num_steps = (SETTLE_TIME / TIMESTEP) as usize;
+    for _ in 0..num_steps {
+        solver.simulation_step(&mut fluid_world, &mut time_manager).unwrap();
+    }
+    (fluid_world, solver)
+}
+
+// Checks that the column came to rest within the box and returns the surface height.
+fn assert_column_at_rest(fluid_world: &sph::FluidParticleWorld) -> Real {
+    let particles = &fluid_world.particles;
+    let gravity = -fluid_world.gravity().y;
+    let particle_radius = fluid_world.properties.particle_radius();
+
+    // all particles stay in the box and are (nearly) at rest
+    for p in particles.positions.iter() {
+        assert_gt!(p.y, -particle_radius);
+        assert_gt!(p.x, -particle_radius);
+        assert_lt!(p.x, COLUMN_WIDTH + particle_radius);
+    }
+    let average_speed = particles.velocities.iter().map(|v| v.magnitude()).sum::<Real>() / particles.velocities.len() as Real;
+    // WCSPH keeps jittering a bit, but should be far from the speeds of the initial collapse
+    assert_lt!(average_speed, 0.1 * (gravity * COLUMN_HEIGHT).sqrt());
+
+    let surface_height = particles.positions.iter().map(|p| p.y).fold(0.0, Real::max) + particle_radius;
+    // at rest density, the fluid's volume fills the box up to this height
+    let fluid_volume = particles.positions.len() as Real * fluid_world.properties.particle_mass() / fluid_world.properties.fluid_density();
+    let expected_surface_height = fluid_volume / COLUMN_WIDTH;
+    assert_lt!((surface_height - expected_surface_height).abs(), expected_surface_height * 0.1);
+    println!(
+        "average speed {:.4}m/s, surface height {:.4}m (expected {:.4}m)",
+        average_speed, surface_height, expected_surface_height
+    );
+    surface_height
+}
+
+// Fraction of a vertical pressure gradient that the discrete SPH gradient recovers at a particle, Σj mj/ρj (yj - yi) ∂Wij/∂yi.
+// This is 1 for the continuous kernel, but with a smoothing length of only two particle spacings the neighbor sum falls about 10% short,
+// so the fluid has to build up correspondingly more pressure to carry its own weight.
+fn pressure_gradient_consistency(fluid_world: &sph::FluidParticleWorld, i: usize) -> Real {
+    let particles = &fluid_world.particles;
+    let kernel = Spiky::new(fluid_world.properties.smoothing_length());
+    let ri = particles.positions[i];
+    (0..particles.positions.len())
+        .filter(|&j| j != i)
+        .map(|j| {
+            let ri_to_rj = particles.positions[j] - ri;
+            let r_sq = ri_to_rj.magnitude2();
+            particles.masses[j] / particles.densities[j] * ri_to_rj.y * kernel.gradient(ri_to_rj, r_sq, r_sq.sqrt()).y
+        })
+        .sum()
+}
+
+// Fraction of [min, max] covered by a particle at x, treating each particle as a cell of one particle spacing.
+fn cell_overlap(x: Real, spacing: Real, min: Real, max: Real) -> Real {
+    ((x + spacing * 0.5).min(max) - (x - spacing * 0.5).max(min)).max(0.0) / spacing
+}
+
+// Hydrostatic pressure from the weight of the fluid above y, within the vertical strip from x_min to x_max.
+// Unlike ρ0 g times the depth below the top most particle, this doesn't depend on how much the surface layer got compressed.
+fn weight_above(fluid_world: &sph::FluidParticleWorld, y: Real, x_min: Real, x_max: Real) -> Real {
+    let particles = &fluid_world.particles;
+    let spacing = fluid_world.properties.particle_radius() * 2.0;
+    let gravity = -fluid_world.gravity().y;
+    let mass: Real = (0..particles.positions.len())
+        .map(|j| {
+            let p = particles.positions[j];
+            particles.masses[j] * cell_overlap(p.x, spacing, x_min, x_max) * cell_overlap(p.y, spacing, y, Real::INFINITY)
+        })
+        .sum();
+    gravity * mass / (x_max - x_min)
+}
+
+#[test]
+fn hydrostatic_pressure_column() {
+    let (fluid_world, solver) = simulate_water_column(Box::new(sph::VelocityVerlet));
+    let surface_height = assert_column_at_rest(&fluid_world);
+    let particles = &fluid_world.particles;
+    let fluid_density = fluid_world.properties.fluid_density();
+    let smoothing_length = fluid_world.properties.smoothing_length();
+
+    // Compare horizontal bands, averaging out particle noise.
+    // Particles close to the walls and the surface have particle deficiency, so only look at the interior.
+    const NUM_BANDS: usize = 4;
+    let stiffness = Density(fluid_density).bulk_modulus(SPEED_OF_SOUND).0 / 7.0; // as in WCSPHSolver
+    let band_min = smoothing_length;
+    let band_max = surface_height - smoothing_length;
+    let band_height = (band_max - band_min) / NUM_BANDS as Real;
+    for band in 0..NUM_BANDS {
+        let band_y = band_min + band_height * band as Real;
+        let mut pressure_sum = 0.0;
+        let mut density_sum = 0.0;
+        let mut weight_sum = 0.0;
+        let mut consistency_sum = 0.0;
+        let mut count = 0;
+        for (i, p) in particles.positions.iter().enumerate() {
+            if p.y < band_y || p.y >= band_y + band_height || p.x < smoothing_length || p.x > COLUMN_WIDTH - smoothing_length {
+                continue;
+            }
+            pressure_sum += solver.particle_pressure(&fluid_world, i as u32).unwrap();
+            density_sum += particles.densities[i];
+            weight_sum += weight_above(&fluid_world, p.y, smoothing_length, COLUMN_WIDTH - smoothing_length);
+            consistency_sum += pressure_gradient_consistency(&fluid_world, i);
+            count += 1;
+        }
+        assert_gt!(count, 0);
+        let pressure = pressure_sum / count as Real;
+        let density = density_sum / count as Real;
+        let weight = weight_sum / count as Real;
+        let consistency = consistency_sum / count as Real;
+        println!(
+            "band {}: pressure {:.2}N/m, weight above {:.2}N/m, gradient consistency {:.3}",
+            band, pressure, weight, consistency
+        );
+
+        let expected_pressure = weight / consistency;
+        assert_lt!(
+            (pressure - expected_pressure).abs(),
+            0.05 * expected_pressure,
+            "band {}: pressure {}, expected {} from the weight above {} and gradient consistency {}",
+            band,
+            pressure,
+            expected_pressure,
+            weight,
+            consistency
+        );
+
+        // inverted Tait equation
+        let expected_density = fluid_density * (expected_pressure / stiffness + 1.0).powf(1.0 / 7.0);
+        assert_lt!(
+            (density - expected_density).abs(),
+            TARGET_DENSITY_VARIATION * fluid_density,
+            "band {}: density {}, expected {}",
+            band,
+            density,
+            expected_density
+        );
+    }
+}
+
+// The column settles with the other integrators as well, compare with `cargo test --test hydrostatic -- --nocapture`
+#[test]
+fn hydrostatic_column_settles_with_all_integrators() {
+    let integrators: Vec<Box<dyn sph::Integrator>> = vec![Box::new(sph::SymplecticEuler), Box::new(sph::RungeKutta2::default())];
+    for integrator in integrators {
+        println!("{}:", integrator.name());
+        let (fluid_world, _) = simulate_water_column(integrator);
+        assert_column_at_rest(&fluid_world);
+    }
+}
@@ -0,0 +1,63 @@
+use more_asserts::*;
+use yasph2d::sph::scenes::PoiseuilleFlow;
+use yasph2d::sph::{self, Solver};
+use yasph2d::units::*;
+
+// Body force driven flow between no-slip walls, comparing the steady velocity profile against the analytic parabola.
+// Validates the physical viscosity model together with no-slip ghost particles.
+// Prints the profile with `cargo test --test poiseuille -- --nocapture`
+
+const KINEMATIC_VISCOSITY: Real = 0.02; // in m²/s
+const NUM_LAYERS: usize = 10;
+
+const SCENE: PoiseuilleFlow = PoiseuilleFlow {
+    channel_length: 0.1,
+    channel_height: 0.1,
+    body_force: 0.8, // maximum velocity of g H² / (8 ν) = 5cm/s
+};
+
+#[test]
+fn poiseuille_flow_wcsph() {
+    let (mut fluid_world, domain) = SCENE.create_world(NumberDensity(40000.0), Density(100.0));
+    let smoothing_length = fluid_world.properties.smoothing_length();
+    let mut viscosity_model = sph::PhysicalViscosityModel::new(smoothing_length);
+    viscosity_model.fluid_viscosity = KINEMATIC_VISCOSITY * fluid_world.properties.fluid_density();
+    let max_velocity = SCENE.analytic_velocity(SCENE.channel_height * 0.5, KINEMATIC_VISCOSITY);
+    let mut solver = sph::WCSPHSolver::new(viscosity_model, &fluid_world.properties).with_target_compressibility(0.01, Velocity(max_velocity));
+    solver.set_boundary_handling(sph::WCSPHBoundaryHandling::GhostParticles(sph::WallCondition::NoSlip));
+    // ghosts alone keep the fluid off the walls, boundary particles right behind the wall surfaces would push it away violently
+    assert!(solver.set_parameter("boundary_force_factor", 0.0));
+    solver.set_watchdog(Some(sph::Watchdog::new(10.0)));
+    let mut time_manager = sph::TimeManager::new(sph::TimeManagerConfiguration::FixedTimeStep(0.0005));
+
+    // within 1% of the steady state, see PoiseuilleFlow
+    let simulation_time = 0.5 * SCENE.channel_height * SCENE.channel_height / KINEMATIC_VISCOSITY;
+    while time_manager.passed_time() < simulation_time {
+        domain.update(&mut fluid_world, &mut solver);
+        solver.simulation_step(&mut fluid_world, &mut time_manager).unwrap();
+        if let Some(alarm) = solver.watchdog().unwrap().alarm() {
+            panic!("{}", alarm);
+        }
+    }
+
+    let profile = SCENE.velocity_profile(&fluid_world, NUM_LAYERS);
+    let measured_max_velocity = profile.iter().map(|(_, velocity)| *velocity).fold(0.0, Real::max);
+    let mut max_shape_error: Real = 0.0;
+    for (y, velocity) in profile {
+        let expected = SCENE.analytic_velocity(y, KINEMATIC_VISCOSITY);
+        println!("y = {:.3}m: {:.4}m/s, analytic {:.4}m/s", y, velocity, expected);
+        max_shape_error = max_shape_error.max((velocity / measured_max_velocity - expected / max_velocity).abs());
+    }
+    println!(
+        "maximum velocity {:.4}m/s (analytic {:.4}m/s), max error of the normalized profile {:.2}%",
+        measured_max_velocity,
+        max_velocity,
+        max_shape_error * 100.0
+    );
+
+    // A parabola that is a bit too fast: With a smoothing length of only two particle spacings,
+    // the sum over the few neighbors underestimates the viscous laplacian by about 14% compared to its integral.
+    assert_lt!(max_shape_error, 0.03);
+    assert_gt!(measured_max_velocity, max_velocity);
+    assert_lt!(measured_max_velocity, max_velocity * 1.25);
+}
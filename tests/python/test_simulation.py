@@ -0,0 +1,31 @@
+# Tests of the Python bindings, see src/python.rs. Run after building the module:
+#
+#   maturin develop --release
+#   pytest tests/python
+
+import pytest
+import yasph2d
+
+
+def test_steps_and_reads_particles():
+    simulation = yasph2d.Simulation(particle_density=5000.0, fluid_density=1000.0, solver="wcsph", timestep=0.001)
+    simulation.add_fluid_rect(0.0, 0.0, 0.1, 0.1)
+    simulation.step_until(0.01)
+    assert simulation.positions().shape[1] == 2
+
+
+@pytest.mark.parametrize(
+    "arguments",
+    [
+        dict(particle_density=0.0, fluid_density=1000.0),
+        dict(particle_density=-5000.0, fluid_density=1000.0),
+        dict(particle_density=5000.0, fluid_density=0.0),
+        dict(particle_density=5000.0, fluid_density=1000.0, smoothing_factor=0.0),
+        dict(particle_density=5000.0, fluid_density=1000.0, smoothing_factor=float("nan")),
+        dict(particle_density=5000.0, fluid_density=1000.0, timestep=0.0),
+        dict(particle_density=5000.0, fluid_density=1000.0, timestep=-0.001),
+    ],
+)
+def test_rejects_non_positive_arguments(arguments):
+    with pytest.raises(ValueError):
+        yasph2d.Simulation(**arguments)
@@ -0,0 +1,44 @@
+mod common;
+
+use common::SceneRun;
+use more_asserts::*;
+use yasph2d::sph::scenes::SloshingTank;
+use yasph2d::units::*;
+
+// Sloshing tank shaken at its natural frequency, checking that the time dependent frame accelleration excites the first sloshing mode.
+// Prints the wall elevations over time.
+
+const PARTICLE_DENSITY: Real = 5000.0;
+const NUM_PERIODS: Real = 3.0;
+
+// Largest difference between the fluid height at the left and right wall during the last shaking period.
+fn max_elevation_difference(shaking_amplitude: Real) -> Real {
+    let mut scene = SloshingTank {
+        tank_width: 0.5,
+        tank_height: 0.5,
+        fill_height: 0.2,
+        shaking_amplitude,
+        shaking_frequency: 0.0,
+    };
+    scene.shaking_frequency = scene.natural_frequency(9.81);
+    let mut run = SceneRun::new(scene.create_world(NumberDensity(PARTICLE_DENSITY), Density(100.0)));
+
+    let period = 1.0 / scene.shaking_frequency;
+    let mut max_difference: Real = 0.0;
+    run.run_until(period * NUM_PERIODS, |fluid_world, time_manager| {
+        let (left, right) = scene.wall_elevations(fluid_world);
+        if time_manager.passed_time() > period * (NUM_PERIODS - 1.0) {
+            max_difference = max_difference.max((left - right).abs());
+        }
+    });
+    println!("amplitude {}m: max elevation difference {}m", shaking_amplitude, max_difference);
+    max_difference
+}
+
+#[test]
+fn sloshing_at_natural_frequency() {
+    let at_rest = max_elevation_difference(0.0);
+    let shaken = max_elevation_difference(0.005);
+    assert_lt!(at_rest, 0.02);
+    assert_gt!(shaken, 0.04);
+}
@@ -0,0 +1,64 @@
+use cgmath::prelude::*;
+use more_asserts::*;
+use yasph2d::sph::scenes::TaylorGreenVortex;
+use yasph2d::sph::{self, Solver};
+use yasph2d::units::*;
+
+// Decaying vortices in a fully periodic domain, comparing the decay of the kinetic energy against the analytic exp(-4 ν k² t)
+// at several resolutions. Validates the physical viscosity model away from any boundaries.
+// Prints the decay rates with `cargo test --test taylor_green -- --nocapture`
+
+const KINEMATIC_VISCOSITY: Real = 0.002; // in m²/s
+const SIMULATION_TIME: Real = 0.1; // kinetic energy decays to about 45%
+
+const SCENE: TaylorGreenVortex = TaylorGreenVortex {
+    domain_size: 0.2,
+    max_velocity: 0.05, // Reynolds number U L / ν = 5
+};
+
+// Kinetic energy of the fluid within the domain, i.e. without periodic copies.
+fn kinetic_energy(fluid_world: &sph::FluidParticleWorld) -> Real {
+    let particles = &fluid_world.particles;
+    (0..particles.positions.len())
+        .filter(|&i| particles.tags[i] != sph::PERIODIC_COPY_TAG)
+        .map(|i| 0.5 * particles.masses[i] * particles.velocities[i].magnitude2())
+        .sum()
+}
+
+// Measured decay rate of the kinetic energy, ln(E(0) / E(t)) / t
+fn kinetic_energy_decay_rate(particle_density: NumberDensity) -> Real {
+    let (mut fluid_world, domain) = SCENE.create_world(particle_density, Density(100.0));
+    let smoothing_length = fluid_world.properties.smoothing_length();
+    let mut viscosity_model = sph::PhysicalViscosityModel::new(smoothing_length);
+    viscosity_model.fluid_viscosity = KINEMATIC_VISCOSITY * fluid_world.properties.fluid_density();
+    let mut solver = sph::WCSPHSolver::new(viscosity_model, &fluid_world.properties).with_target_compressibility(0.01, Velocity(SCENE.max_velocity));
+    solver.set_watchdog(Some(sph::Watchdog::new(10.0)));
+    let mut time_manager = sph::TimeManager::new(sph::TimeManagerConfiguration::FixedTimeStep(0.0005));
+
+    domain.update(&mut fluid_world, &mut solver);
+    let initial_kinetic_energy = kinetic_energy(&fluid_world);
+    while time_manager.passed_time() < SIMULATION_TIME {
+        domain.update(&mut fluid_world, &mut solver);
+        solver.simulation_step(&mut fluid_world, &mut time_manager).unwrap();
+        if let Some(alarm) = solver.watchdog().unwrap().alarm() {
+            panic!("{}", alarm);
+        }
+    }
+    (initial_kinetic_energy / kinetic_energy(&fluid_world)).ln() / time_manager.passed_time()
+}
+
+#[test]
+fn taylor_green_kinetic_energy_decay() {
+    // 4 ν k², see TaylorGreenVortex
+    let analytic_decay_rate = -(SCENE.kinetic_energy(1.0, KINEMATIC_VISCOSITY, 1.0) / SCENE.kinetic_energy(1.0, KINEMATIC_VISCOSITY, 0.0)).ln();
+    for &particle_density in [10000.0, 22500.0, 40000.0].iter() {
+        let decay_rate = kinetic_energy_decay_rate(NumberDensity(particle_density));
+        println!(
+            "{} particles/m²: kinetic energy decays with {:.3}/s, analytic {:.3}/s",
+            particle_density, decay_rate, analytic_decay_rate
+        );
+        // Same limitation as in the Poiseuille flow: With a smoothing length of only two particle spacings,
+        // the viscous laplacian is off by about 15% regardless of the resolution.
+        assert_lt!((decay_rate / analytic_decay_rate - 1.0).abs(), 0.3);
+    }
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use common::SceneRun;
+use more_asserts::*;
+use yasph2d::sph::{self, scenes::WaveTank};
+use yasph2d::units::*;
+
+// Regular waves from a piston and a flap wave maker, compared against linear wave maker theory.
+// Prints the measured and expected wave heights.
+
+const PARTICLE_DENSITY: Real = 5000.0;
+const GAUGE_POSITION: Real = 0.6;
+// Waves need a while to reach the gauge, reflections from the far wall arrive at about 5s.
+const MEASUREMENT_START: Real = 2.0;
+const MEASUREMENT_END: Real = 4.0;
+
+fn check_regular_waves(motion: sph::WaveMakerMotion) {
+    let scene = WaveTank {
+        tank_length: 3.0,
+        tank_height: 0.4,
+        water_depth: 0.2,
+        motion,
+        frequency: 1.2,
+    };
+    let (fluid_world, wave_maker) = scene.create_world(NumberDensity(PARTICLE_DENSITY), Density(100.0));
+    // XSPH smoothes velocities in every step, which damps the waves heavily over the many steps per wave period
+    let mut viscosity = sph::XSPHViscosityModel::new(fluid_world.properties.smoothing_length());
+    viscosity.epsilon = 0.0;
+    let mut run = SceneRun::with_viscosity_model(fluid_world, viscosity);
+    let mut gauge = sph::WaveGauge::new(GAUGE_POSITION, 0.0, scene.tank_height);
+
+    // the wave maker moves to where it is at the start of the next step
+    wave_maker.update(&mut run.fluid_world, run.time());
+    run.run_until(MEASUREMENT_END, |fluid_world, time_manager| {
+        gauge.record(fluid_world, time_manager.passed_time());
+        wave_maker.update(fluid_world, time_manager.passed_time());
+    });
+
+    let wave_height = gauge.wave_height(MEASUREMENT_START);
+    let expected_wave_height = wave_maker.expected_wave_height(scene.water_depth, 9.81);
+    println!("{:?}: wave height {}m, expected {}m", motion, wave_height, expected_wave_height);
+    // at this resolution the wave height is only about two particle spacings, waves come out some 20% lower than expected
+    assert_lt!((wave_height / expected_wave_height - 1.0).abs(), 0.3);
+
+    // surface goes up and down with the wave maker's frequency
+    // Upcrossings of the mean elevation only count after the surface went down by a quarter wave height, so noise doesn't add any.
+    let samples: Vec<(Real, Real)> = gauge.samples().iter().filter(|(time, _)| *time >= MEASUREMENT_START).cloned().collect();
+    let mean_elevation = samples.iter().map(|(_, elevation)| elevation).sum::<Real>() / samples.len() as Real;
+    let mut upcrossings = Vec::new();
+    let mut went_down = false;
+    for &(time, elevation) in samples.iter() {
+        if elevation < mean_elevation - wave_height * 0.25 {
+            went_down = true;
+        } else if went_down && elevation >= mean_elevation {
+            upcrossings.push(time);
+            went_down = false;
+        }
+    }
+    assert_ge!(upcrossings.len(), 2);
+    let period = (upcrossings[upcrossings.len() - 1] - upcrossings[0]) / (upcrossings.len() - 1) as Real;
+    println!("{:?}: period {}s", motion, period);
+    assert_lt!((period * wave_maker.frequency - 1.0).abs(), 0.08);
+}
+
+#[test]
+fn wave_tank_piston() {
+    check_regular_waves(sph::WaveMakerMotion::Piston { amplitude: 0.015 });
+}
+
+#[test]
+fn wave_tank_flap() {
+    // about the same stroke at the still water level as the piston
+    check_regular_waves(sph::WaveMakerMotion::Flap { amplitude: 0.075 });
+}